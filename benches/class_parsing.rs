@@ -0,0 +1,105 @@
+// Measures how long parsing every class in a JDK's modules image takes, and how many heap
+// allocations the parsed classes make, first with the system allocator and then with a bump
+// allocator that never frees. The second is as cheap as allocation gets, so the difference is the
+// most that allocating class metadata from an arena could save. Run with
+// `JAVA_HOME=/path/to/jdk cargo bench --bench class_parsing`.
+use joyvm::classloader;
+use joyvm::jimage::JimageSource;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+const SAMPLES: usize = 11;
+const ARENA_SIZE: usize = 4 << 30;
+
+// Counts allocations, and while BUMPING is set, hands out memory from one large block instead of
+// going to the system allocator. Memory from the block is never freed; it's all reused once
+// everything allocated from it has been dropped.
+struct MeasuringAllocator {
+    allocations: AtomicUsize,
+    bumping: AtomicBool,
+    arena: AtomicPtr<u8>,
+    used: AtomicUsize,
+}
+
+impl MeasuringAllocator {
+    fn in_arena(&self, ptr: *mut u8) -> bool {
+        let arena = self.arena.load(Ordering::Relaxed);
+        !arena.is_null() && ptr >= arena && ptr < arena.wrapping_add(ARENA_SIZE)
+    }
+}
+
+unsafe impl GlobalAlloc for MeasuringAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+        if !self.bumping.load(Ordering::Relaxed) {
+            return System.alloc(layout);
+        }
+        let arena = self.arena.load(Ordering::Relaxed);
+        let start = (self.used.load(Ordering::Relaxed) + layout.align() - 1) & !(layout.align() - 1);
+        if start + layout.size() > ARENA_SIZE {
+            return std::ptr::null_mut();
+        }
+        self.used.store(start + layout.size(), Ordering::Relaxed);
+        arena.add(start)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if !self.in_arena(ptr) {
+            System.dealloc(ptr, layout);
+        }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: MeasuringAllocator = MeasuringAllocator {
+    allocations: AtomicUsize::new(0),
+    bumping: AtomicBool::new(false),
+    arena: AtomicPtr::new(std::ptr::null_mut()),
+    used: AtomicUsize::new(0),
+};
+
+fn main() {
+    let java_home = std::env::var("JAVA_HOME").expect("Set JAVA_HOME to a JDK 9 or later");
+    let image = JimageSource::from_java_home(java_home).unwrap();
+    let classes: Vec<Vec<u8>> = image.names().unwrap().iter()
+        .filter(|name| name.ends_with(".class"))
+        .map(|name| image.read(name).unwrap().unwrap())
+        .collect();
+    let bytes: usize = classes.iter().map(Vec::len).sum();
+
+    let before = ALLOCATOR.allocations.load(Ordering::Relaxed);
+    parse_all(&classes);
+    let allocations = ALLOCATOR.allocations.load(Ordering::Relaxed) - before;
+
+    let system = median(|| parse_all(&classes));
+    let arena = unsafe { System.alloc(Layout::from_size_align(ARENA_SIZE, 4096).unwrap()) };
+    assert!(!arena.is_null());
+    ALLOCATOR.arena.store(arena, Ordering::Relaxed);
+    let bump = median(|| {
+        ALLOCATOR.used.store(0, Ordering::Relaxed);
+        ALLOCATOR.bumping.store(true, Ordering::Relaxed);
+        let time = parse_all(&classes);
+        ALLOCATOR.bumping.store(false, Ordering::Relaxed);
+        time
+    });
+
+    println!("{} classes, {} KiB, {} allocations", classes.len(), bytes / 1024, allocations);
+    println!("{:<16} {:>10.2?}", "system allocator", system);
+    println!("{:<16} {:>10.2?}", "bump allocator", bump);
+}
+
+// Parses every class, dropping them only once the time has been taken.
+fn parse_all(classes: &[Vec<u8>]) -> Duration {
+    let start = Instant::now();
+    let parsed: Vec<_> = classes.iter().map(|class| classloader::load_class(class).unwrap()).collect();
+    let time = start.elapsed();
+    drop(std::hint::black_box(parsed));
+    time
+}
+
+fn median<F: FnMut() -> Duration>(mut sample: F) -> Duration {
+    let mut times: Vec<Duration> = (0..SAMPLES).map(|_| sample()).collect();
+    times.sort();
+    times[SAMPLES / 2]
+}
@@ -0,0 +1,84 @@
+// Measures interpreter throughput, and the memory each workload allocates: the estimate the heap
+// accounts for, from each class's field layout, and the bytes the process actually allocates
+// while the workload runs. Objects store their fields by slot, so the two differ. Run with
+// `cargo bench`; any arguments filter the workloads by name, as with `cargo bench sieve`. With
+// `--features jit`, code run by the JIT isn't counted in the instructions per second.
+use joyvm::runtime::Value;
+use joyvm::vm::Vm;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// Counts the bytes allocated, not subtracting any that are freed.
+struct CountingAllocator {
+    allocated: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocated.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator {allocated: AtomicUsize::new(0)};
+
+const BENCHMARKS: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/Benchmarks.class"));
+const PARTICLE: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/Particle.class"));
+const SAMPLES: usize = 5;
+
+struct Workload {
+    name: &'static str,
+    descriptor: &'static str,
+    arg: i32,
+}
+
+const WORKLOADS: &[Workload] = &[
+    Workload {name: "sumOfSquares", descriptor: "(I)I", arg: 3_000_000},
+    Workload {name: "collatzSteps", descriptor: "(I)J", arg: 30_000},
+    Workload {name: "leibnizPi", descriptor: "(I)D", arg: 2_000_000},
+    Workload {name: "sieve", descriptor: "(I)I", arg: 1_000_000},
+    Workload {name: "fib", descriptor: "(I)I", arg: 24},
+    Workload {name: "particles", descriptor: "(I)J", arg: 200_000},
+];
+
+fn main() {
+    let filters: Vec<String> = std::env::args().skip(1).filter(|arg| !arg.starts_with("--")).collect();
+    for workload in WORKLOADS {
+        if !filters.is_empty() && !filters.iter().any(|filter| workload.name.contains(filter.as_str())) {
+            continue;
+        }
+
+        let mut times = vec![];
+        let mut instructions = 0;
+        let mut allocated = 0;
+        let mut actual = 0;
+        for _ in 0..SAMPLES {
+            let mut vm = Vm::new();
+            vm.add_class(BENCHMARKS).unwrap();
+            vm.add_class(PARTICLE).unwrap();
+            let actual_before = ALLOCATOR.allocated.load(Ordering::Relaxed);
+            let start = Instant::now();
+            let result = vm.invoke_static("Benchmarks", workload.name, workload.descriptor, vec![Value::Int(workload.arg)]).unwrap();
+            times.push(start.elapsed());
+            actual = ALLOCATOR.allocated.load(Ordering::Relaxed) - actual_before;
+            instructions = vm.executed_instructions();
+            allocated = vm.heap().allocated_bytes();
+            std::hint::black_box(result);
+        }
+
+        times.sort();
+        let median = times[SAMPLES / 2];
+        println!("{:<14} {:>10.2?} {:>8.1} M instructions/s {:>8} KiB estimated {:>8} KiB actual",
+            workload.name, median, throughput(instructions, median), allocated / 1024, actual / 1024);
+    }
+}
+
+fn throughput(instructions: u64, time: Duration) -> f64 {
+    instructions as f64 / time.as_secs_f64() / 1e6
+}
@@ -0,0 +1,57 @@
+// Measures how long the application loader takes to load every class in testdata, reading and
+// parsing the class files, and again with the classes mapped from a class data archive. Run
+// with `cargo bench --features archives --bench startup`.
+use joyvm::archive::ClassArchive;
+use joyvm::classpath::Classpath;
+use joyvm::vm::Vm;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const SAMPLES: usize = 11;
+
+fn main() {
+    let testdata = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+    let names: Vec<String> = std::fs::read_dir(&testdata).unwrap()
+        .filter_map(|entry| entry.unwrap().file_name().to_str()?.strip_suffix(".class").map(str::to_string))
+        .collect();
+
+    // Some fixtures are deliberately broken, so only the classes that load are measured.
+    let mut vm = vm_with_classpath(&testdata);
+    let names: Vec<String> = names.into_iter().filter(|name| vm.load_class(name).is_ok()).collect();
+    let archive = ClassArchive::dump(&vm).unwrap().to_bytes();
+
+    let from_files = median(|| {
+        let mut vm = vm_with_classpath(&testdata);
+        let start = Instant::now();
+        load_all(&mut vm, &names);
+        start.elapsed()
+    });
+    let from_archive = median(|| {
+        let mut vm = vm_with_classpath(&testdata);
+        let start = Instant::now();
+        ClassArchive::from_bytes(&archive).unwrap().map(&mut vm).unwrap();
+        load_all(&mut vm, &names);
+        start.elapsed()
+    });
+    println!("{} classes, {} archive bytes", names.len(), archive.len());
+    println!("{:<14} {:>10.2?}", "class files", from_files);
+    println!("{:<14} {:>10.2?}", "archive", from_archive);
+}
+
+fn vm_with_classpath(path: &Path) -> Vm {
+    let mut vm = Vm::new();
+    vm.set_classpath(Classpath::parse(path).unwrap());
+    vm
+}
+
+fn load_all(vm: &mut Vm, names: &[String]) {
+    for name in names {
+        std::hint::black_box(vm.load_class(name).unwrap());
+    }
+}
+
+fn median<F: FnMut() -> Duration>(mut sample: F) -> Duration {
+    let mut times: Vec<Duration> = (0..SAMPLES).map(|_| sample()).collect();
+    times.sort();
+    times[SAMPLES / 2]
+}
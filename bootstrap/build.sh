@@ -0,0 +1,6 @@
+#!/bin/sh
+# Rebuilds the bootstrap class files from source. The compiled classes are checked in so that
+# building joyvm doesn't require a JDK.
+set -e
+cd "$(dirname "$0")"
+javac -source 8 -target 8 -Xlint:-options -bootclasspath . -sourcepath . -d . java/io/*.java java/lang/*.java java/lang/invoke/*.java java/lang/reflect/*.java java/nio/*.java java/util/*.java java/util/zip/*.java java/util/concurrent/locks/*.java sun/misc/*.java
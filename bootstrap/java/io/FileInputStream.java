@@ -0,0 +1,37 @@
+package java.io;
+
+public class FileInputStream extends InputStream {
+    private final FileDescriptor fd;
+
+    public FileInputStream(FileDescriptor fd) {
+        this.fd = fd;
+    }
+
+    public FileInputStream(String name) throws FileNotFoundException {
+        this(new FileDescriptor(open(name)));
+    }
+
+    public int read() throws IOException {
+        byte[] b = new byte[1];
+        return readBytes(b, 0, 1) == -1 ? -1 : b[0] & 0xff;
+    }
+
+    public int read(byte[] b, int off, int len) throws IOException {
+        return readBytes(b, off, len);
+    }
+
+    public final FileDescriptor getFD() {
+        return fd;
+    }
+
+    public void close() throws IOException {
+        close0();
+    }
+
+    // Opens the file, returning its file descriptor.
+    private static native int open(String name) throws FileNotFoundException;
+
+    private native void close0() throws IOException;
+
+    private native int readBytes(byte[] b, int off, int len) throws IOException;
+}
@@ -0,0 +1,40 @@
+package java.io;
+
+public class FileOutputStream extends OutputStream {
+    private final FileDescriptor fd;
+
+    public FileOutputStream(FileDescriptor fd) {
+        this.fd = fd;
+    }
+
+    public FileOutputStream(String name) throws FileNotFoundException {
+        this(name, false);
+    }
+
+    public FileOutputStream(String name, boolean append) throws FileNotFoundException {
+        this(new FileDescriptor(open(name, append)));
+    }
+
+    public void write(int b) throws IOException {
+        writeBytes(new byte[] {(byte) b}, 0, 1);
+    }
+
+    public void write(byte[] b, int off, int len) throws IOException {
+        writeBytes(b, off, len);
+    }
+
+    public final FileDescriptor getFD() {
+        return fd;
+    }
+
+    public void close() throws IOException {
+        close0();
+    }
+
+    // Opens the file, creating it if need be, and returns its file descriptor.
+    private static native int open(String name, boolean append) throws FileNotFoundException;
+
+    private native void close0() throws IOException;
+
+    private native void writeBytes(byte[] b, int off, int len) throws IOException;
+}
@@ -0,0 +1,25 @@
+package java.io;
+
+public abstract class InputStream {
+    public abstract int read() throws IOException;
+
+    public int read(byte[] b) throws IOException {
+        return read(b, 0, b.length);
+    }
+
+    public int read(byte[] b, int off, int len) throws IOException {
+        if (off < 0 || len < 0 || len > b.length - off) {
+            throw new IndexOutOfBoundsException();
+        }
+        for (int i = 0; i < len; i++) {
+            int c = read();
+            if (c == -1) {
+                return i == 0 ? -1 : i;
+            }
+            b[off + i] = (byte) c;
+        }
+        return len;
+    }
+
+    public void close() throws IOException {}
+}
@@ -0,0 +1,99 @@
+package java.io;
+
+// Writes text as UTF-8. As in the JDK, IOExceptions aren't thrown but recorded for checkError.
+public class PrintStream extends OutputStream {
+    private final OutputStream out;
+    private boolean trouble;
+
+    public PrintStream(OutputStream out) {
+        this.out = out;
+    }
+
+    public void write(int b) {
+        try {
+            out.write(b);
+        } catch (IOException e) {
+            trouble = true;
+        }
+    }
+
+    public void write(byte[] b, int off, int len) {
+        try {
+            out.write(b, off, len);
+        } catch (IOException e) {
+            trouble = true;
+        }
+    }
+
+    public void flush() {
+        try {
+            out.flush();
+        } catch (IOException e) {
+            trouble = true;
+        }
+    }
+
+    public boolean checkError() {
+        flush();
+        return trouble;
+    }
+
+    public void print(String s) {
+        byte[] bytes = (s == null ? "null" : s).getBytes();
+        write(bytes, 0, bytes.length);
+    }
+
+    public void print(char[] s) {
+        print(new String(s));
+    }
+
+    public void print(char c) {
+        print(String.valueOf(c));
+    }
+
+    public void print(boolean b) {
+        print(String.valueOf(b));
+    }
+
+    public void print(int i) {
+        print(String.valueOf(i));
+    }
+
+    public void print(long l) {
+        print(String.valueOf(l));
+    }
+
+    public void println() {
+        print("\n");
+    }
+
+    public void println(String s) {
+        print(s);
+        println();
+    }
+
+    public void println(char[] s) {
+        print(s);
+        println();
+    }
+
+    public void println(char c) {
+        print(c);
+        println();
+    }
+
+    public void println(boolean b) {
+        print(b);
+        println();
+    }
+
+    public void println(int i) {
+        print(i);
+        println();
+    }
+
+    public void println(long l) {
+        print(l);
+        println();
+    }
+}
@@ -0,0 +1,66 @@
+package java.lang;
+
+// The hooks registered with Runtime.addShutdownHook. The VM takes them when shutdown begins,
+// after main finishes or when the program calls exit, and runs them in the order they were added.
+class ApplicationShutdownHooks {
+    private static Thread[] hooks = new Thread[0];
+    private static boolean shuttingDown;
+
+    private ApplicationShutdownHooks() {}
+
+    static void add(Thread hook) {
+        if (hook == null) {
+            throw new NullPointerException();
+        }
+        checkNotShuttingDown();
+        if (hook.isAlive()) {
+            throw new IllegalArgumentException("Hook already running");
+        }
+        if (indexOf(hook) >= 0) {
+            throw new IllegalArgumentException("Hook previously registered");
+        }
+        Thread[] added = new Thread[hooks.length + 1];
+        System.arraycopy(hooks, 0, added, 0, hooks.length);
+        added[hooks.length] = hook;
+        hooks = added;
+    }
+
+    static boolean remove(Thread hook) {
+        if (hook == null) {
+            throw new NullPointerException();
+        }
+        checkNotShuttingDown();
+        int index = indexOf(hook);
+        if (index < 0) {
+            return false;
+        }
+        Thread[] removed = new Thread[hooks.length - 1];
+        System.arraycopy(hooks, 0, removed, 0, index);
+        System.arraycopy(hooks, index + 1, removed, index, removed.length - index);
+        hooks = removed;
+        return true;
+    }
+
+    // Called by the VM as shutdown begins. From then on, hooks can't be added or removed.
+    static Thread[] take() {
+        shuttingDown = true;
+        Thread[] taken = hooks;
+        hooks = new Thread[0];
+        return taken;
+    }
+
+    private static int indexOf(Thread hook) {
+        for (int i = 0; i < hooks.length; i++) {
+            if (hooks[i] == hook) {
+                return i;
+            }
+        }
+        return -1;
+    }
+
+    private static void checkNotShuttingDown() {
+        if (shuttingDown) {
+            throw new IllegalStateException("Shutdown in progress");
+        }
+    }
+}
@@ -0,0 +1,39 @@
+package java.lang;
+
+// Thrown by failed assert statements, which pass the detail after the colon, if there is one, to
+// the constructor for its type. Object has no toString here, so details other than strings are
+// described by their class.
+public class AssertionError extends Error {
+    public AssertionError() {}
+
+    public AssertionError(Object detail) {
+        super(describe(detail), detail instanceof Throwable ? (Throwable) detail : null);
+    }
+
+    private static String describe(Object detail) {
+        if (detail == null) {
+            return "null";
+        }
+        return detail instanceof String ? (String) detail : detail.getClass().getName();
+    }
+
+    public AssertionError(boolean detail) {
+        super(String.valueOf(detail));
+    }
+
+    public AssertionError(char detail) {
+        super(String.valueOf(detail));
+    }
+
+    public AssertionError(int detail) {
+        super(String.valueOf(detail));
+    }
+
+    public AssertionError(long detail) {
+        super(String.valueOf(detail));
+    }
+
+    public AssertionError(String message, Throwable cause) {
+        super(message, cause);
+    }
+}
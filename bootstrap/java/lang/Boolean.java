@@ -0,0 +1,30 @@
+package java.lang;
+
+public final class Boolean implements java.io.Serializable {
+    public static final Class<Boolean> TYPE = (Class<Boolean>) Class.getPrimitiveClass("boolean");
+    public static final Boolean TRUE = new Boolean(true);
+    public static final Boolean FALSE = new Boolean(false);
+
+    private final boolean value;
+
+    public Boolean(boolean value) {
+        this.value = value;
+    }
+
+    // Autoboxing goes through here, so boxed booleans are always TRUE or FALSE.
+    public static Boolean valueOf(boolean b) {
+        return b ? TRUE : FALSE;
+    }
+
+    public boolean booleanValue() {
+        return value;
+    }
+
+    public boolean equals(Object other) {
+        return other instanceof Boolean && ((Boolean) other).value == value;
+    }
+
+    public int hashCode() {
+        return value ? 1231 : 1237;
+    }
+}
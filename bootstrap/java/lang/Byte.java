@@ -0,0 +1,56 @@
+package java.lang;
+
+public final class Byte extends Number {
+    public static final Class<Byte> TYPE = (Class<Byte>) Class.getPrimitiveClass("byte");
+    public static final byte MIN_VALUE = -128;
+    public static final byte MAX_VALUE = 127;
+
+    private final byte value;
+
+    public Byte(byte value) {
+        this.value = value;
+    }
+
+    // Every byte is boxed to a shared instance, as JLS 5.1.7 requires.
+    private static class ByteCache {
+        static final Byte[] cache = new Byte[256];
+
+        static {
+            for (int i = 0; i < cache.length; i++) {
+                cache[i] = new Byte((byte) (i - 128));
+            }
+        }
+    }
+
+    public static Byte valueOf(byte value) {
+        return ByteCache.cache[value + 128];
+    }
+
+    public byte byteValue() {
+        return value;
+    }
+
+    public int intValue() {
+        return value;
+    }
+
+    public long longValue() {
+        return value;
+    }
+
+    public float floatValue() {
+        return value;
+    }
+
+    public double doubleValue() {
+        return value;
+    }
+
+    public boolean equals(Object other) {
+        return other instanceof Byte && ((Byte) other).value == value;
+    }
+
+    public int hashCode() {
+        return value;
+    }
+}
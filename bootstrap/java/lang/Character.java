@@ -0,0 +1,43 @@
+package java.lang;
+
+public final class Character implements java.io.Serializable {
+    public static final Class<Character> TYPE = (Class<Character>) Class.getPrimitiveClass("char");
+    public static final char MIN_VALUE = '\u0000';
+    public static final char MAX_VALUE = '\uffff';
+
+    private final char value;
+
+    public Character(char value) {
+        this.value = value;
+    }
+
+    // Characters up to 127 are boxed to shared instances, as JLS 5.1.7 requires.
+    private static class CharacterCache {
+        static final Character[] cache = new Character[128];
+
+        static {
+            for (int i = 0; i < cache.length; i++) {
+                cache[i] = new Character((char) i);
+            }
+        }
+    }
+
+    public static Character valueOf(char c) {
+        if (c < CharacterCache.cache.length) {
+            return CharacterCache.cache[c];
+        }
+        return new Character(c);
+    }
+
+    public char charValue() {
+        return value;
+    }
+
+    public boolean equals(Object other) {
+        return other instanceof Character && ((Character) other).value == value;
+    }
+
+    public int hashCode() {
+        return value;
+    }
+}
@@ -0,0 +1,74 @@
+package java.lang;
+
+import java.lang.reflect.Constructor;
+import java.lang.reflect.Field;
+import java.lang.reflect.Method;
+
+public final class Class<T> {
+    private static final int INTERFACE = 0x0200;
+
+    // Filled in by the VM when it creates the mirror.
+    private transient String name;
+    private transient int modifiers;
+    private transient Class<? super T> superclass;
+    private transient Class<?>[] interfaces;
+    private transient boolean primitive;
+
+    private Class() {}
+
+    public String getName() {
+        return name;
+    }
+
+    public int getModifiers() {
+        return modifiers;
+    }
+
+    public boolean isInterface() {
+        return (modifiers & INTERFACE) != 0;
+    }
+
+    public Class<? super T> getSuperclass() {
+        return superclass;
+    }
+
+    public Class<?>[] getInterfaces() {
+        Class<?>[] copy = new Class<?>[interfaces.length];
+        for (int i = 0; i < interfaces.length; i++) {
+            copy[i] = interfaces[i];
+        }
+        return copy;
+    }
+
+    public boolean isPrimitive() {
+        return primitive;
+    }
+
+    public native Package getPackage();
+
+    // Whether the class's assert statements are checked, which javac has it ask when it's
+    // initialized.
+    public native boolean desiredAssertionStatus();
+
+    // Loads the class with the caller's class loader, and initializes it.
+    public static native Class<?> forName(String className) throws ClassNotFoundException;
+
+    public static native Class<?> forName(String name, boolean initialize, ClassLoader loader) throws ClassNotFoundException;
+
+    public native Field[] getDeclaredFields();
+
+    public Field getDeclaredField(String name) throws NoSuchFieldException {
+        for (Field field : getDeclaredFields()) {
+            if (field.getName().equals(name)) {
+                return field;
+            }
+        }
+        throw new NoSuchFieldException(name);
+    }
+
+    public native Method[] getDeclaredMethods();
+
+    public native Constructor<?>[] getDeclaredConstructors();
+
+    static native Class<?> getPrimitiveClass(String name);
+}
@@ -0,0 +1,25 @@
+package java.lang;
+
+public abstract class ClassLoader {
+    // The VM's own loaders, which ClassLoader objects created by Java code delegate to.
+    private static final int BOOTSTRAP_LOADER = 0;
+    private static final int APPLICATION_LOADER = 2;
+
+    // The VM's id for this loader, whose defined classes live in a namespace of their own.
+    private final int loaderId;
+
+    // Delegates to the application class loader, as the JDK's default parent is the system
+    // class loader.
+    protected ClassLoader() {
+        loaderId = register(APPLICATION_LOADER);
+    }
+
+    // A null parent means the bootstrap loader.
+    protected ClassLoader(ClassLoader parent) {
+        loaderId = register(parent == null ? BOOTSTRAP_LOADER : parent.loaderId);
+    }
+
+    private native int register(int parentId);
+
+    protected final native Class<?> defineClass(String name, byte[] b, int off, int len) throws ClassFormatError;
+}
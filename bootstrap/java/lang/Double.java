@@ -0,0 +1,31 @@
+package java.lang;
+
+public final class Double extends Number {
+    public static final Class<Double> TYPE = (Class<Double>) Class.getPrimitiveClass("double");
+
+    private final double value;
+
+    public Double(double value) {
+        this.value = value;
+    }
+
+    public static Double valueOf(double value) {
+        return new Double(value);
+    }
+
+    public int intValue() {
+        return (int) value;
+    }
+
+    public long longValue() {
+        return (long) value;
+    }
+
+    public float floatValue() {
+        return (float) value;
+    }
+
+    public double doubleValue() {
+        return value;
+    }
+}
@@ -0,0 +1,55 @@
+package java.lang;
+
+public final class Integer extends Number {
+    public static final Class<Integer> TYPE = (Class<Integer>) Class.getPrimitiveClass("int");
+    public static final int MIN_VALUE = 0x80000000;
+    public static final int MAX_VALUE = 0x7fffffff;
+
+    private final int value;
+
+    public Integer(int value) {
+        this.value = value;
+    }
+
+    // Values from -128 to 127 are boxed to shared instances, as JLS 5.1.7 requires.
+    private static class IntegerCache {
+        static final Integer[] cache = new Integer[256];
+
+        static {
+            for (int i = 0; i < cache.length; i++) {
+                cache[i] = new Integer(i - 128);
+            }
+        }
+    }
+
+    public static Integer valueOf(int value) {
+        if (value >= -128 && value <= 127) {
+            return IntegerCache.cache[value + 128];
+        }
+        return new Integer(value);
+    }
+
+    public int intValue() {
+        return value;
+    }
+
+    public long longValue() {
+        return value;
+    }
+
+    public float floatValue() {
+        return value;
+    }
+
+    public double doubleValue() {
+        return value;
+    }
+
+    public boolean equals(Object other) {
+        return other instanceof Integer && ((Integer) other).value == value;
+    }
+
+    public int hashCode() {
+        return value;
+    }
+}
@@ -0,0 +1,55 @@
+package java.lang;
+
+public final class Long extends Number {
+    public static final Class<Long> TYPE = (Class<Long>) Class.getPrimitiveClass("long");
+    public static final long MIN_VALUE = 0x8000000000000000L;
+    public static final long MAX_VALUE = 0x7fffffffffffffffL;
+
+    private final long value;
+
+    public Long(long value) {
+        this.value = value;
+    }
+
+    // Values from -128 to 127 are boxed to shared instances, as JLS 5.1.7 requires.
+    private static class LongCache {
+        static final Long[] cache = new Long[256];
+
+        static {
+            for (int i = 0; i < cache.length; i++) {
+                cache[i] = new Long((long) (i - 128));
+            }
+        }
+    }
+
+    public static Long valueOf(long value) {
+        if (value >= -128 && value <= 127) {
+            return LongCache.cache[(int) value + 128];
+        }
+        return new Long(value);
+    }
+
+    public int intValue() {
+        return (int) value;
+    }
+
+    public long longValue() {
+        return value;
+    }
+
+    public float floatValue() {
+        return value;
+    }
+
+    public double doubleValue() {
+        return value;
+    }
+
+    public boolean equals(Object other) {
+        return other instanceof Long && ((Long) other).value == value;
+    }
+
+    public int hashCode() {
+        return (int) (value ^ (value >>> 32));
+    }
+}
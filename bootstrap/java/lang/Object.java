@@ -0,0 +1,25 @@
+package java.lang;
+
+public class Object {
+    public Object() {}
+
+    public boolean equals(Object other) {
+        return this == other;
+    }
+
+    public final native Class<?> getClass();
+
+    public native int hashCode();
+
+    protected native Object clone() throws CloneNotSupportedException;
+
+    public final native void notify();
+
+    public final native void notifyAll();
+
+    public final native void wait(long timeout) throws InterruptedException;
+
+    public final void wait() throws InterruptedException {
+        wait(0);
+    }
+}
@@ -0,0 +1,48 @@
+package java.lang;
+
+public class Package {
+    // Filled in by the VM when it creates the package, from the manifest of the JAR its first
+    // class came from.
+    private transient String name;
+    private transient String specificationTitle;
+    private transient String specificationVersion;
+    private transient String specificationVendor;
+    private transient String implementationTitle;
+    private transient String implementationVersion;
+    private transient String implementationVendor;
+    private transient boolean sealed;
+
+    private Package() {}
+
+    public String getName() {
+        return name;
+    }
+
+    public String getSpecificationTitle() {
+        return specificationTitle;
+    }
+
+    public String getSpecificationVersion() {
+        return specificationVersion;
+    }
+
+    public String getSpecificationVendor() {
+        return specificationVendor;
+    }
+
+    public String getImplementationTitle() {
+        return implementationTitle;
+    }
+
+    public String getImplementationVersion() {
+        return implementationVersion;
+    }
+
+    public String getImplementationVendor() {
+        return implementationVendor;
+    }
+
+    public boolean isSealed() {
+        return sealed;
+    }
+}
@@ -0,0 +1,29 @@
+package java.lang;
+
+public class Runtime {
+    private static final Runtime currentRuntime = new Runtime();
+
+    private Runtime() {}
+
+    public static Runtime getRuntime() {
+        return currentRuntime;
+    }
+
+    // Runs the shutdown hooks, unless shutdown has already begun, and then halts.
+    public native void exit(int status);
+
+    // Stops the program at once, without running the shutdown hooks.
+    public native void halt(int status);
+
+    public void addShutdownHook(Thread hook) {
+        ApplicationShutdownHooks.add(hook);
+    }
+
+    public boolean removeShutdownHook(Thread hook) {
+        return ApplicationShutdownHooks.remove(hook);
+    }
+
+    public void gc() {
+        System.gc();
+    }
+}
@@ -0,0 +1,59 @@
+package java.lang;
+
+public final class Short extends Number {
+    public static final Class<Short> TYPE = (Class<Short>) Class.getPrimitiveClass("short");
+    public static final short MIN_VALUE = -32768;
+    public static final short MAX_VALUE = 32767;
+
+    private final short value;
+
+    public Short(short value) {
+        this.value = value;
+    }
+
+    // Values from -128 to 127 are boxed to shared instances, as JLS 5.1.7 requires.
+    private static class ShortCache {
+        static final Short[] cache = new Short[256];
+
+        static {
+            for (int i = 0; i < cache.length; i++) {
+                cache[i] = new Short((short) (i - 128));
+            }
+        }
+    }
+
+    public static Short valueOf(short value) {
+        if (value >= -128 && value <= 127) {
+            return ShortCache.cache[value + 128];
+        }
+        return new Short(value);
+    }
+
+    public short shortValue() {
+        return value;
+    }
+
+    public int intValue() {
+        return value;
+    }
+
+    public long longValue() {
+        return value;
+    }
+
+    public float floatValue() {
+        return value;
+    }
+
+    public double doubleValue() {
+        return value;
+    }
+
+    public boolean equals(Object other) {
+        return other instanceof Short && ((Short) other).value == value;
+    }
+
+    public int hashCode() {
+        return value;
+    }
+}
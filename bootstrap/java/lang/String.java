@@ -0,0 +1,59 @@
+package java.lang;
+
+public final class String {
+    private final char[] value;
+
+    public String(char[] value) {
+        this.value = value;
+    }
+
+    public native int length();
+
+    public native char charAt(int index);
+
+    public native boolean equals(Object other);
+
+    public native int hashCode();
+
+    public native int indexOf(int c);
+
+    public native int compareTo(String other);
+
+    public native byte[] getBytes();
+
+    public native String intern();
+
+    public static String valueOf(char c) {
+        return new String(new char[] {c});
+    }
+
+    public static String valueOf(boolean b) {
+        return b ? "true" : "false";
+    }
+
+    public static String valueOf(int i) {
+        return valueOf((long) i);
+    }
+
+    public static String valueOf(long l) {
+        if (l == 0x8000000000000000L) {
+            return "-9223372036854775808";
+        }
+        char[] digits = new char[20];
+        int start = digits.length;
+        boolean negative = l < 0;
+        if (negative) {
+            l = -l;
+        }
+        do {
+            digits[--start] = (char) ('0' + l % 10);
+            l /= 10;
+        } while (l != 0);
+        if (negative) {
+            digits[--start] = '-';
+        }
+        char[] value = new char[digits.length - start];
+        System.arraycopy(digits, start, value, 0, value.length);
+        return new String(value);
+    }
+}
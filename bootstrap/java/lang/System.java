@@ -0,0 +1,46 @@
+package java.lang;
+
+import java.io.FileDescriptor;
+import java.io.FileInputStream;
+import java.io.FileOutputStream;
+import java.io.InputStream;
+import java.io.PrintStream;
+
+public final class System {
+    public static final InputStream in = new FileInputStream(FileDescriptor.in);
+    public static final PrintStream out = new PrintStream(new FileOutputStream(FileDescriptor.out));
+    public static final PrintStream err = new PrintStream(new FileOutputStream(FileDescriptor.err));
+
+    private System() {}
+
+    public static void exit(int status) {
+        Runtime.getRuntime().exit(status);
+    }
+
+    public static native void gc();
+
+    public static native long currentTimeMillis();
+
+    public static native long nanoTime();
+
+    public static native String getenv(String name);
+
+    public static native String getProperty(String key);
+
+    public static String getProperty(String key, String def) {
+        String value = getProperty(key);
+        return value != null ? value : def;
+    }
+
+    public static native String setProperty(String key, String value);
+
+    public static native void arraycopy(Object src, int srcPos, Object dest, int destPos, int length);
+
+    public static native int identityHashCode(Object object);
+
+    public static native void load(String filename);
+
+    public static native void loadLibrary(String libname);
+
+    public static native String mapLibraryName(String libname);
+}
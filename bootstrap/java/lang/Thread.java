@@ -0,0 +1,225 @@
+package java.lang;
+
+// A thread of execution. Each started thread runs on a host thread of its own, or as a green
+// thread, though only one runs Java code at a time.
+public class Thread implements Runnable {
+    public static final int MIN_PRIORITY = 1;
+    public static final int NORM_PRIORITY = 5;
+    public static final int MAX_PRIORITY = 10;
+
+    private static int threadNumber;
+    private static volatile UncaughtExceptionHandler defaultUncaughtExceptionHandler;
+
+    private String name;
+    private final Runnable target;
+    private boolean started;
+    private volatile boolean interrupted;
+    private boolean daemon;
+    private int priority;
+    private volatile UncaughtExceptionHandler uncaughtExceptionHandler;
+    volatile Object parkBlocker; // What the thread is parked in LockSupport for, if anything.
+
+    public Thread() {
+        this(null, nextName());
+    }
+
+    public Thread(Runnable target) {
+        this(target, nextName());
+    }
+
+    public Thread(String name) {
+        this(null, name);
+    }
+
+    public Thread(Runnable target, String name) {
+        if (name == null) {
+            throw new NullPointerException("name cannot be null");
+        }
+        this.target = target;
+        this.name = name;
+        // Main is the current thread while it's constructed.
+        Thread parent = currentThread();
+        this.daemon = parent.isDaemon();
+        this.priority = parent == this ? NORM_PRIORITY : parent.getPriority();
+    }
+
+    // Thread-0, Thread-1 and so on.
+    private static String nextName() {
+        String prefix = "Thread-";
+        String number = String.valueOf(threadNumber++);
+        char[] name = new char[prefix.length() + number.length()];
+        for (int i = 0; i < prefix.length(); i++) {
+            name[i] = prefix.charAt(i);
+        }
+        for (int i = 0; i < number.length(); i++) {
+            name[prefix.length() + i] = number.charAt(i);
+        }
+        return new String(name);
+    }
+
+    public static native Thread currentThread();
+
+    public static native void yield();
+
+    public static void sleep(long millis) throws InterruptedException {
+        if (millis < 0) {
+            throw new IllegalArgumentException("timeout value is negative");
+        }
+        sleep0(millis);
+    }
+
+    // Sleeps to the nearest millisecond, as the JDK does.
+    public static void sleep(long millis, int nanos) throws InterruptedException {
+        if (millis < 0) {
+            throw new IllegalArgumentException("timeout value is negative");
+        }
+        if (nanos < 0 || nanos > 999999) {
+            throw new IllegalArgumentException("nanosecond timeout value out of range");
+        }
+        if (nanos >= 500000 || (nanos != 0 && millis == 0)) {
+            millis++;
+        }
+        sleep0(millis);
+    }
+
+    private static native void sleep0(long millis);
+
+    // Sets the thread's interrupt status. If it's sleeping or joining another thread, it wakes
+    // and throws InterruptedException, clearing the status. If it's parked, it just wakes.
+    public void interrupt() {
+        interrupted = true;
+    }
+
+    public boolean isInterrupted() {
+        return interrupted;
+    }
+
+    // Returns the current thread's interrupt status, clearing it.
+    public static boolean interrupted() {
+        Thread current = currentThread();
+        boolean interrupted = current.interrupted;
+        current.interrupted = false;
+        return interrupted;
+    }
+
+    public void start() {
+        if (started) {
+            throw new IllegalThreadStateException();
+        }
+        started = true;
+        start0();
+    }
+
+    private native void start0();
+
+    // Whether the thread has started and not yet finished.
+    public final native boolean isAlive();
+
+    // Waits for the thread to finish, for up to the given number of milliseconds, or without a
+    // limit if it's zero.
+    public final void join(long millis) throws InterruptedException {
+        if (millis < 0) {
+            throw new IllegalArgumentException("timeout value is negative");
+        }
+        join0(millis);
+    }
+
+    public final void join(long millis, int nanos) throws InterruptedException {
+        if (millis < 0) {
+            throw new IllegalArgumentException("timeout value is negative");
+        }
+        if (nanos < 0 || nanos > 999999) {
+            throw new IllegalArgumentException("nanosecond timeout value out of range");
+        }
+        if (nanos >= 500000 || (nanos != 0 && millis == 0)) {
+            millis++;
+        }
+        join0(millis);
+    }
+
+    public final void join() throws InterruptedException {
+        join0(0);
+    }
+
+    private native void join0(long millis);
+
+    public void run() {
+        if (target != null) {
+            target.run();
+        }
+    }
+
+    // Daemon threads don't keep the VM running: it shuts down once the others have finished,
+    // and stops them. A thread starts out as a daemon if the thread that created it is one.
+    public final void setDaemon(boolean on) {
+        if (isAlive()) {
+            throw new IllegalThreadStateException();
+        }
+        daemon = on;
+    }
+
+    public final boolean isDaemon() {
+        return daemon;
+    }
+
+    // How much of the processor the thread should get, which only green threads take any notice
+    // of: each turn they get lasts that many timeslices. Host threads have no portable way to be
+    // prioritized. A thread starts out with the priority of the thread that created it.
+    public final void setPriority(int newPriority) {
+        if (newPriority < MIN_PRIORITY || newPriority > MAX_PRIORITY) {
+            throw new IllegalArgumentException();
+        }
+        priority = newPriority;
+    }
+
+    public final int getPriority() {
+        return priority;
+    }
+
+    // Handles exceptions that escape a thread's run method, in place of printing them.
+    public interface UncaughtExceptionHandler {
+        void uncaughtException(Thread t, Throwable e);
+    }
+
+    public void setUncaughtExceptionHandler(UncaughtExceptionHandler handler) {
+        uncaughtExceptionHandler = handler;
+    }
+
+    public UncaughtExceptionHandler getUncaughtExceptionHandler() {
+        return uncaughtExceptionHandler;
+    }
+
+    // The handler for threads that don't have one of their own.
+    public static void setDefaultUncaughtExceptionHandler(UncaughtExceptionHandler handler) {
+        defaultUncaughtExceptionHandler = handler;
+    }
+
+    public static UncaughtExceptionHandler getDefaultUncaughtExceptionHandler() {
+        return defaultUncaughtExceptionHandler;
+    }
+
+    // Called by the VM when an exception escapes the thread. Returns whether there was a handler
+    // to pass it to.
+    private boolean dispatchUncaughtException(Throwable e) {
+        UncaughtExceptionHandler handler = uncaughtExceptionHandler;
+        if (handler == null) {
+            handler = defaultUncaughtExceptionHandler;
+        }
+        if (handler == null) {
+            return false;
+        }
+        handler.uncaughtException(this, e);
+        return true;
+    }
+
+    public final String getName() {
+        return name;
+    }
+
+    public final void setName(String name) {
+        if (name == null) {
+            throw new NullPointerException("name cannot be null");
+        }
+        this.name = name;
+    }
+}
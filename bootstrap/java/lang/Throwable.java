@@ -0,0 +1,39 @@
+package java.lang;
+
+public class Throwable {
+    private String detailMessage;
+    private Throwable cause = this;
+
+    // Filled in by the VM when the throwable is created.
+    private StackTraceElement[] stackTrace;
+
+    public Throwable() {}
+
+    public Throwable(String message) {
+        detailMessage = message;
+    }
+
+    public Throwable(String message, Throwable cause) {
+        detailMessage = message;
+        this.cause = cause;
+    }
+
+    public Throwable(Throwable cause) {
+        this.cause = cause;
+    }
+
+    public String getMessage() {
+        return detailMessage;
+    }
+
+    public StackTraceElement[] getStackTrace() {
+        return stackTrace;
+    }
+
+    public Throwable getCause() {
+        if (cause == this) {
+            return null;
+        }
+        return cause;
+    }
+}
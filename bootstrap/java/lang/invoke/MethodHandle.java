@@ -0,0 +1,36 @@
+package java.lang.invoke;
+
+// Method handles are created by the VM when it resolves a MethodHandle constant or a Lookup finds
+// a member, so the fields below are filled in directly rather than by a constructor.
+public abstract class MethodHandle {
+    private final int referenceKind;
+    private final Class<?> declaringClass;
+    private final String name;
+    private final MethodType type;
+
+    private MethodHandle() {
+        referenceKind = 0;
+        declaringClass = null;
+        name = null;
+        type = null;
+    }
+
+    public MethodType type() {
+        return type;
+    }
+
+    // invokeExact and invoke are signature polymorphic: calls to them take the descriptor of the
+    // call site rather than this one, per JVM spec 2.9.3. The call site's type must match the
+    // handle's exactly for invokeExact.
+    public final native Object invokeExact(Object... args) throws Throwable;
+
+    // The arguments and result are converted between the call site's type and the handle's, as
+    // asType would.
+    public final native Object invoke(Object... args) throws Throwable;
+
+    public Object invokeWithArguments(Object... arguments) throws Throwable {
+        return invokeWithArguments(this, arguments);
+    }
+
+    private static native Object invokeWithArguments(MethodHandle handle, Object[] arguments) throws Throwable;
+}
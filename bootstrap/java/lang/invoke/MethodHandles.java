@@ -0,0 +1,71 @@
+package java.lang.invoke;
+
+public class MethodHandles {
+    private MethodHandles() {}
+
+    // A lookup with the access of the class that calls this.
+    public static native Lookup lookup();
+
+    public static final class Lookup {
+        // The reference kinds of JVM spec 5.4.3.5.
+        private static final int REF_getField = 1;
+        private static final int REF_getStatic = 2;
+        private static final int REF_putField = 3;
+        private static final int REF_putStatic = 4;
+        private static final int REF_invokeVirtual = 5;
+        private static final int REF_invokeStatic = 6;
+        private static final int REF_invokeSpecial = 7;
+        private static final int REF_newInvokeSpecial = 8;
+        private static final int REF_invokeInterface = 9;
+
+        private final Class<?> lookupClass;
+
+        private Lookup(Class<?> lookupClass) {
+            this.lookupClass = lookupClass;
+        }
+
+        public Class<?> lookupClass() {
+            return lookupClass;
+        }
+
+        public MethodHandle findStatic(Class<?> refc, String name, MethodType type) throws NoSuchMethodException, IllegalAccessException {
+            return findMethod(REF_invokeStatic, refc, name, type);
+        }
+
+        public MethodHandle findVirtual(Class<?> refc, String name, MethodType type) throws NoSuchMethodException, IllegalAccessException {
+            return findMethod(refc.isInterface() ? REF_invokeInterface : REF_invokeVirtual, refc, name, type);
+        }
+
+        public MethodHandle findConstructor(Class<?> refc, MethodType type) throws NoSuchMethodException, IllegalAccessException {
+            return findMethod(REF_newInvokeSpecial, refc, "<init>", type);
+        }
+
+        // Only the lookup class may make invokespecial calls, as only it could in bytecode.
+        public MethodHandle findSpecial(Class<?> refc, String name, MethodType type, Class<?> specialCaller) throws NoSuchMethodException, IllegalAccessException {
+            if (specialCaller != lookupClass) {
+                throw new IllegalAccessException("no private access for invokespecial");
+            }
+            return findMethod(REF_invokeSpecial, refc, name, type);
+        }
+
+        public MethodHandle findGetter(Class<?> refc, String name, Class<?> type) throws NoSuchFieldException, IllegalAccessException {
+            return findField(REF_getField, refc, name, type);
+        }
+
+        public MethodHandle findSetter(Class<?> refc, String name, Class<?> type) throws NoSuchFieldException, IllegalAccessException {
+            return findField(REF_putField, refc, name, type);
+        }
+
+        public MethodHandle findStaticGetter(Class<?> refc, String name, Class<?> type) throws NoSuchFieldException, IllegalAccessException {
+            return findField(REF_getStatic, refc, name, type);
+        }
+
+        public MethodHandle findStaticSetter(Class<?> refc, String name, Class<?> type) throws NoSuchFieldException, IllegalAccessException {
+            return findField(REF_putStatic, refc, name, type);
+        }
+
+        private native MethodHandle findMethod(int kind, Class<?> refc, String name, MethodType type) throws NoSuchMethodException, IllegalAccessException;
+
+        private native MethodHandle findField(int kind, Class<?> refc, String name, Class<?> type) throws NoSuchFieldException, IllegalAccessException;
+    }
+}
@@ -0,0 +1,73 @@
+package java.lang.invoke;
+
+// Method types are created by the VM, so the fields below are filled in directly rather than by
+// a constructor. The descriptor is kept alongside the classes, so that types can be compared
+// without walking them.
+public final class MethodType {
+    private final Class<?> rtype;
+    private final Class<?>[] ptypes;
+    private final String descriptor;
+
+    private MethodType() {
+        rtype = null;
+        ptypes = null;
+        descriptor = null;
+    }
+
+    public static native MethodType methodType(Class<?> rtype, Class<?>[] ptypes);
+
+    public static MethodType methodType(Class<?> rtype) {
+        return methodType(rtype, new Class<?>[0]);
+    }
+
+    public static MethodType methodType(Class<?> rtype, Class<?> ptype0) {
+        return methodType(rtype, new Class<?>[] {ptype0});
+    }
+
+    public static MethodType methodType(Class<?> rtype, Class<?> ptype0, Class<?>... ptypes) {
+        Class<?>[] all = new Class<?>[ptypes.length + 1];
+        all[0] = ptype0;
+        System.arraycopy(ptypes, 0, all, 1, ptypes.length);
+        return methodType(rtype, all);
+    }
+
+    public Class<?> returnType() {
+        return rtype;
+    }
+
+    public Class<?> parameterType(int num) {
+        return ptypes[num];
+    }
+
+    public int parameterCount() {
+        return ptypes.length;
+    }
+
+    public Class<?>[] parameterArray() {
+        return ptypes.clone();
+    }
+
+    public String toMethodDescriptorString() {
+        return descriptor;
+    }
+
+    public boolean equals(Object other) {
+        if (!(other instanceof MethodType)) {
+            return false;
+        }
+        MethodType type = (MethodType) other;
+        if (rtype != type.rtype || ptypes.length != type.ptypes.length) {
+            return false;
+        }
+        for (int i = 0; i < ptypes.length; i++) {
+            if (ptypes[i] != type.ptypes[i]) {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    public int hashCode() {
+        return descriptor.hashCode();
+    }
+}
@@ -0,0 +1,17 @@
+package java.lang.reflect;
+
+// Reflection checks that the caller could access a member as if it used it directly, unless
+// setAccessible(true) was called.
+public class AccessibleObject {
+    boolean override;
+
+    protected AccessibleObject() {}
+
+    public void setAccessible(boolean flag) {
+        override = flag;
+    }
+
+    public boolean isAccessible() {
+        return override;
+    }
+}
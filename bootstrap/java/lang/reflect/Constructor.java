@@ -0,0 +1,39 @@
+package java.lang.reflect;
+
+// Constructors are created by the VM, so the fields below are filled in directly rather than by
+// a constructor. The slot is the constructor's index among its class file's methods.
+public final class Constructor<T> extends AccessibleObject {
+    private final Class<T> clazz;
+    private final int slot;
+    private final Class<?>[] parameterTypes;
+    private final int modifiers;
+
+    private Constructor() {
+        clazz = null;
+        slot = 0;
+        parameterTypes = null;
+        modifiers = 0;
+    }
+
+    public Class<T> getDeclaringClass() {
+        return clazz;
+    }
+
+    public String getName() {
+        return clazz.getName();
+    }
+
+    public Class<?>[] getParameterTypes() {
+        return parameterTypes.clone();
+    }
+
+    public int getParameterCount() {
+        return parameterTypes.length;
+    }
+
+    public int getModifiers() {
+        return modifiers;
+    }
+
+    public native T newInstance(Object... initargs) throws InstantiationException, IllegalAccessException, InvocationTargetException;
+}
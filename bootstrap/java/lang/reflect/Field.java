@@ -0,0 +1,37 @@
+package java.lang.reflect;
+
+// Fields are created by the VM, so the fields below are filled in directly rather than by a
+// constructor.
+public final class Field extends AccessibleObject {
+    private final Class<?> clazz;
+    private final String name;
+    private final Class<?> type;
+    private final int modifiers;
+
+    private Field() {
+        clazz = null;
+        name = null;
+        type = null;
+        modifiers = 0;
+    }
+
+    public Class<?> getDeclaringClass() {
+        return clazz;
+    }
+
+    public String getName() {
+        return name;
+    }
+
+    public Class<?> getType() {
+        return type;
+    }
+
+    public int getModifiers() {
+        return modifiers;
+    }
+
+    public native Object get(Object obj) throws IllegalAccessException;
+
+    public native void set(Object obj, Object value) throws IllegalAccessException;
+}
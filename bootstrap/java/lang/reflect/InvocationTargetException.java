@@ -0,0 +1,15 @@
+package java.lang.reflect;
+
+// Wraps an exception thrown by a method or constructor called through reflection.
+public class InvocationTargetException extends ReflectiveOperationException {
+    private final Throwable target;
+
+    public InvocationTargetException(Throwable target) {
+        super(null, target);
+        this.target = target;
+    }
+
+    public Throwable getTargetException() {
+        return target;
+    }
+}
@@ -0,0 +1,47 @@
+package java.lang.reflect;
+
+// Methods are created by the VM, so the fields below are filled in directly rather than by a
+// constructor. The slot is the method's index in its class file.
+public final class Method extends AccessibleObject {
+    private final Class<?> clazz;
+    private final int slot;
+    private final String name;
+    private final Class<?> returnType;
+    private final Class<?>[] parameterTypes;
+    private final int modifiers;
+
+    private Method() {
+        clazz = null;
+        slot = 0;
+        name = null;
+        returnType = null;
+        parameterTypes = null;
+        modifiers = 0;
+    }
+
+    public Class<?> getDeclaringClass() {
+        return clazz;
+    }
+
+    public String getName() {
+        return name;
+    }
+
+    public Class<?> getReturnType() {
+        return returnType;
+    }
+
+    public Class<?>[] getParameterTypes() {
+        return parameterTypes.clone();
+    }
+
+    public int getParameterCount() {
+        return parameterTypes.length;
+    }
+
+    public int getModifiers() {
+        return modifiers;
+    }
+
+    public native Object invoke(Object obj, Object... args) throws IllegalAccessException, InvocationTargetException;
+}
@@ -0,0 +1,47 @@
+package java.nio;
+
+// Only direct buffers, with absolute gets and puts in big-endian order.
+public abstract class ByteBuffer {
+    final int capacity;
+
+    ByteBuffer(int capacity) {
+        this.capacity = capacity;
+    }
+
+    public static ByteBuffer allocateDirect(int capacity) {
+        if (capacity < 0) {
+            throw new IllegalArgumentException("Negative capacity");
+        }
+        return new DirectByteBuffer(capacity);
+    }
+
+    public final int capacity() {
+        return capacity;
+    }
+
+    public abstract boolean isDirect();
+
+    public abstract byte get(int index);
+
+    public abstract ByteBuffer put(int index, byte value);
+
+    public int getInt(int index) {
+        checkIndex(index, 4);
+        return (get(index) & 0xff) << 24 | (get(index + 1) & 0xff) << 16 | (get(index + 2) & 0xff) << 8 | (get(index + 3) & 0xff);
+    }
+
+    public ByteBuffer putInt(int index, int value) {
+        checkIndex(index, 4);
+        put(index, (byte) (value >> 24));
+        put(index + 1, (byte) (value >> 16));
+        put(index + 2, (byte) (value >> 8));
+        put(index + 3, (byte) value);
+        return this;
+    }
+
+    final void checkIndex(int index, int size) {
+        if (index < 0 || size > capacity - index) {
+            throw new IndexOutOfBoundsException();
+        }
+    }
+}
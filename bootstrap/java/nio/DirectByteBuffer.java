@@ -0,0 +1,32 @@
+package java.nio;
+
+import sun.misc.Unsafe;
+
+// The memory is freed by the VM once the buffer is unreachable.
+class DirectByteBuffer extends ByteBuffer {
+    private static final Unsafe unsafe = Unsafe.getUnsafe();
+
+    private final long address;
+
+    DirectByteBuffer(int capacity) {
+        super(capacity);
+        address = reserve(capacity);
+    }
+
+    private native long reserve(int capacity);
+
+    public boolean isDirect() {
+        return true;
+    }
+
+    public byte get(int index) {
+        checkIndex(index, 1);
+        return unsafe.getByte(address + index);
+    }
+
+    public ByteBuffer put(int index, byte value) {
+        checkIndex(index, 1);
+        unsafe.putByte(address + index, value);
+        return this;
+    }
+}
@@ -0,0 +1,78 @@
+package java.util;
+
+// The JDK's linear congruential generator, so that seeded generators give the same numbers as
+// they would on any other JVM.
+public class Random implements java.io.Serializable {
+    private static final long multiplier = 0x5DEECE66DL;
+    private static final long addend = 0xBL;
+    private static final long mask = (1L << 48) - 1;
+    private static final double DOUBLE_UNIT = 0x1.0p-53;
+
+    private long seed;
+
+    public Random() {
+        this(newSeed());
+    }
+
+    public Random(long seed) {
+        this.seed = initialScramble(seed);
+    }
+
+    // A seed from the VM's entropy source.
+    private static native long newSeed();
+
+    private static long initialScramble(long seed) {
+        return (seed ^ multiplier) & mask;
+    }
+
+    public void setSeed(long seed) {
+        this.seed = initialScramble(seed);
+    }
+
+    protected int next(int bits) {
+        seed = (seed * multiplier + addend) & mask;
+        return (int) (seed >>> (48 - bits));
+    }
+
+    public void nextBytes(byte[] bytes) {
+        for (int i = 0; i < bytes.length; ) {
+            for (int rnd = nextInt(), n = bytes.length - i < 4 ? bytes.length - i : 4; n-- > 0; rnd >>= 8) {
+                bytes[i++] = (byte) rnd;
+            }
+        }
+    }
+
+    public int nextInt() {
+        return next(32);
+    }
+
+    public int nextInt(int bound) {
+        if (bound <= 0) {
+            throw new IllegalArgumentException("bound must be positive");
+        }
+        int r = next(31);
+        int m = bound - 1;
+        if ((bound & m) == 0) {
+            r = (int) ((bound * (long) r) >> 31);
+        } else {
+            for (int u = r; u - (r = u % bound) + m < 0; u = next(31)) {}
+        }
+        return r;
+    }
+
+    public long nextLong() {
+        return ((long) next(32) << 32) + next(32);
+    }
+
+    public boolean nextBoolean() {
+        return next(1) != 0;
+    }
+
+    public float nextFloat() {
+        return next(24) / ((float) (1 << 24));
+    }
+
+    public double nextDouble() {
+        return (((long) next(26) << 27) + next(27)) * DOUBLE_UNIT;
+    }
+}
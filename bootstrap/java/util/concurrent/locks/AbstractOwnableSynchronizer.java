@@ -0,0 +1,16 @@
+package java.util.concurrent.locks;
+
+// A synchronizer that a single thread may hold, and that records which one.
+public abstract class AbstractOwnableSynchronizer {
+    private transient Thread exclusiveOwnerThread;
+
+    protected AbstractOwnableSynchronizer() {}
+
+    protected final void setExclusiveOwnerThread(Thread thread) {
+        exclusiveOwnerThread = thread;
+    }
+
+    protected final Thread getExclusiveOwnerThread() {
+        return exclusiveOwnerThread;
+    }
+}
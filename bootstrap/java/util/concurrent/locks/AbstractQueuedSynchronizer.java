@@ -0,0 +1,119 @@
+package java.util.concurrent.locks;
+
+import sun.misc.Unsafe;
+
+// The framework that ReentrantLock is built on, in exclusive mode only: subclasses say how to
+// acquire and release the state, and threads that can't acquire it queue up and park until the
+// thread ahead of them releases it. The state, and the head and tail of the queue, change only by
+// compare-and-set, as in the JDK.
+public abstract class AbstractQueuedSynchronizer extends AbstractOwnableSynchronizer {
+    private static final Unsafe UNSAFE = Unsafe.getUnsafe();
+    private static final long STATE_OFFSET;
+    private static final long HEAD_OFFSET;
+    private static final long TAIL_OFFSET;
+
+    static {
+        try {
+            STATE_OFFSET = UNSAFE.objectFieldOffset(AbstractQueuedSynchronizer.class.getDeclaredField("state"));
+            HEAD_OFFSET = UNSAFE.objectFieldOffset(AbstractQueuedSynchronizer.class.getDeclaredField("head"));
+            TAIL_OFFSET = UNSAFE.objectFieldOffset(AbstractQueuedSynchronizer.class.getDeclaredField("tail"));
+        } catch (NoSuchFieldException e) {
+            throw new Error(e);
+        }
+    }
+
+    // A queued thread. The head is a placeholder for the thread that last acquired the state.
+    static final class Node {
+        volatile Thread thread;
+        volatile Node next;
+
+        Node(Thread thread) {
+            this.thread = thread;
+        }
+    }
+
+    private volatile int state;
+    private transient volatile Node head;
+    private transient volatile Node tail;
+
+    protected AbstractQueuedSynchronizer() {}
+
+    protected final int getState() {
+        return state;
+    }
+
+    protected final void setState(int newState) {
+        state = newState;
+    }
+
+    protected final boolean compareAndSetState(int expect, int update) {
+        return UNSAFE.compareAndSetInt(this, STATE_OFFSET, expect, update);
+    }
+
+    protected boolean tryAcquire(int arg) {
+        throw new UnsupportedOperationException();
+    }
+
+    protected boolean tryRelease(int arg) {
+        throw new UnsupportedOperationException();
+    }
+
+    protected boolean isHeldExclusively() {
+        throw new UnsupportedOperationException();
+    }
+
+    // Acquires the state, queueing and parking until it can. An interrupt doesn't stop the wait,
+    // but the thread is interrupted again once it has acquired.
+    public final void acquire(int arg) {
+        if (tryAcquire(arg)) {
+            return;
+        }
+        Node node = enqueue(new Node(Thread.currentThread()));
+        boolean interrupted = false;
+        while (true) {
+            if (head.next == node && tryAcquire(arg)) {
+                head = node;
+                node.thread = null;
+                if (interrupted) {
+                    Thread.currentThread().interrupt();
+                }
+                return;
+            }
+            LockSupport.park(this);
+            if (Thread.interrupted()) {
+                interrupted = true;
+            }
+        }
+    }
+
+    // Releases the state, unparking the next queued thread if it's free.
+    public final boolean release(int arg) {
+        if (!tryRelease(arg)) {
+            return false;
+        }
+        Node first = head == null ? null : head.next;
+        if (first != null) {
+            LockSupport.unpark(first.thread);
+        }
+        return true;
+    }
+
+    public final boolean hasQueuedThreads() {
+        return head != tail;
+    }
+
+    private Node enqueue(Node node) {
+        while (true) {
+            Node last = tail;
+            if (last == null) {
+                Node placeholder = new Node(null);
+                if (UNSAFE.compareAndSetReference(this, HEAD_OFFSET, null, placeholder)) {
+                    tail = placeholder;
+                }
+            } else if (UNSAFE.compareAndSetReference(this, TAIL_OFFSET, last, node)) {
+                last.next = node;
+                return node;
+            }
+        }
+    }
+}
@@ -0,0 +1,10 @@
+package java.util.concurrent.locks;
+
+// Only the methods that don't take timeouts or conditions.
+public interface Lock {
+    void lock();
+
+    boolean tryLock();
+
+    void unlock();
+}
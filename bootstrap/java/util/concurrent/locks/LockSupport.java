@@ -0,0 +1,62 @@
+package java.util.concurrent.locks;
+
+import sun.misc.Unsafe;
+
+// Blocks and wakes threads, for building locks and other synchronizers on. Each thread has a
+// permit, which unpark makes available and park consumes, waiting for it if need be. park also
+// returns if the thread is interrupted, without clearing its status, and may return for no
+// reason at all, so callers check what they're waiting for again.
+public class LockSupport {
+    private static final Unsafe UNSAFE = Unsafe.getUnsafe();
+
+    private LockSupport() {}
+
+    public static void unpark(Thread thread) {
+        if (thread != null) {
+            UNSAFE.unpark(thread);
+        }
+    }
+
+    public static void park() {
+        UNSAFE.park(false, 0L);
+    }
+
+    // The blocker is what the thread is parked for, for getBlocker to report.
+    public static void park(Object blocker) {
+        Thread thread = Thread.currentThread();
+        setBlocker(thread, blocker);
+        UNSAFE.park(false, 0L);
+        setBlocker(thread, null);
+    }
+
+    public static void parkNanos(long nanos) {
+        if (nanos > 0) {
+            UNSAFE.park(false, nanos);
+        }
+    }
+
+    public static void parkNanos(Object blocker, long nanos) {
+        if (nanos > 0) {
+            Thread thread = Thread.currentThread();
+            setBlocker(thread, blocker);
+            UNSAFE.park(false, nanos);
+            setBlocker(thread, null);
+        }
+    }
+
+    // The deadline is in milliseconds since the epoch.
+    public static void parkUntil(long deadline) {
+        UNSAFE.park(true, deadline);
+    }
+
+    public static void parkUntil(Object blocker, long deadline) {
+        Thread thread = Thread.currentThread();
+        setBlocker(thread, blocker);
+        UNSAFE.park(true, deadline);
+        setBlocker(thread, null);
+    }
+
+    public static native Object getBlocker(Thread thread);
+
+    private static native void setBlocker(Thread thread, Object blocker);
+}
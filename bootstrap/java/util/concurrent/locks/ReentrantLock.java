@@ -0,0 +1,74 @@
+package java.util.concurrent.locks;
+
+// A non-fair lock that the thread holding it can take again, as many times as it then unlocks it.
+public class ReentrantLock implements Lock {
+    private final Sync sync = new Sync();
+
+    // The state counts how many times the owner holds the lock.
+    static final class Sync extends AbstractQueuedSynchronizer {
+        protected boolean tryAcquire(int acquires) {
+            Thread current = Thread.currentThread();
+            int count = getState();
+            if (count == 0) {
+                if (compareAndSetState(0, acquires)) {
+                    setExclusiveOwnerThread(current);
+                    return true;
+                }
+            } else if (current == getExclusiveOwnerThread()) {
+                setState(count + acquires);
+                return true;
+            }
+            return false;
+        }
+
+        protected boolean tryRelease(int releases) {
+            if (Thread.currentThread() != getExclusiveOwnerThread()) {
+                throw new IllegalMonitorStateException();
+            }
+            int count = getState() - releases;
+            if (count == 0) {
+                setExclusiveOwnerThread(null);
+            }
+            setState(count);
+            return count == 0;
+        }
+
+        protected boolean isHeldExclusively() {
+            return getExclusiveOwnerThread() == Thread.currentThread();
+        }
+
+        int getHoldCount() {
+            return isHeldExclusively() ? getState() : 0;
+        }
+    }
+
+    public ReentrantLock() {}
+
+    public void lock() {
+        sync.acquire(1);
+    }
+
+    public boolean tryLock() {
+        return sync.tryAcquire(1);
+    }
+
+    public void unlock() {
+        sync.release(1);
+    }
+
+    public boolean isLocked() {
+        return sync.getState() != 0;
+    }
+
+    public boolean isHeldByCurrentThread() {
+        return sync.isHeldExclusively();
+    }
+
+    public int getHoldCount() {
+        return sync.getHoldCount();
+    }
+
+    public final boolean hasQueuedThreads() {
+        return sync.hasQueuedThreads();
+    }
+}
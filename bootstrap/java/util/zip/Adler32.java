@@ -0,0 +1,31 @@
+package java.util.zip;
+
+public class Adler32 implements Checksum {
+    private int adler = 1;
+
+    public Adler32() {}
+
+    public void update(int b) {
+        adler = update(adler, b);
+    }
+
+    public void update(byte[] b, int off, int len) {
+        adler = updateBytes(adler, b, off, len);
+    }
+
+    public void update(byte[] b) {
+        adler = updateBytes(adler, b, 0, b.length);
+    }
+
+    public void reset() {
+        adler = 1;
+    }
+
+    public long getValue() {
+        return adler & 0xffffffffL;
+    }
+
+    private static native int update(int adler, int b);
+
+    private static native int updateBytes(int adler, byte[] b, int off, int len);
+}
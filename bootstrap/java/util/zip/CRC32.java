@@ -0,0 +1,31 @@
+package java.util.zip;
+
+public class CRC32 implements Checksum {
+    private int crc;
+
+    public CRC32() {}
+
+    public void update(int b) {
+        crc = update(crc, b);
+    }
+
+    public void update(byte[] b, int off, int len) {
+        crc = updateBytes(crc, b, off, len);
+    }
+
+    public void update(byte[] b) {
+        crc = updateBytes(crc, b, 0, b.length);
+    }
+
+    public void reset() {
+        crc = 0;
+    }
+
+    public long getValue() {
+        return crc & 0xffffffffL;
+    }
+
+    private static native int update(int crc, int b);
+
+    private static native int updateBytes(int crc, byte[] b, int off, int len);
+}
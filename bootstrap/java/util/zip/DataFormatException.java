@@ -0,0 +1,9 @@
+package java.util.zip;
+
+public class DataFormatException extends Exception {
+    public DataFormatException() {}
+
+    public DataFormatException(String message) {
+        super(message);
+    }
+}
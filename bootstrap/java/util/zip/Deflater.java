@@ -0,0 +1,153 @@
+package java.util.zip;
+
+// Input is buffered by the VM until there's 64KB of it, or the stream is flushed or finished, so
+// needsInput is always true. Strategies are accepted but make no difference, and preset
+// dictionaries aren't supported.
+public class Deflater {
+    public static final int DEFLATED = 8;
+    public static final int NO_COMPRESSION = 0;
+    public static final int BEST_SPEED = 1;
+    public static final int BEST_COMPRESSION = 9;
+    public static final int DEFAULT_COMPRESSION = -1;
+    public static final int FILTERED = 1;
+    public static final int HUFFMAN_ONLY = 2;
+    public static final int DEFAULT_STRATEGY = 0;
+    public static final int NO_FLUSH = 0;
+    public static final int SYNC_FLUSH = 2;
+    public static final int FULL_FLUSH = 3;
+
+    private long address;
+    private int level;
+    private int strategy = DEFAULT_STRATEGY;
+    private final boolean nowrap;
+
+    public Deflater(int level, boolean nowrap) {
+        checkLevel(level);
+        this.level = level;
+        this.nowrap = nowrap;
+        address = init(level, nowrap);
+    }
+
+    public Deflater(int level) {
+        this(level, false);
+    }
+
+    public Deflater() {
+        this(DEFAULT_COMPRESSION, false);
+    }
+
+    public void setInput(byte[] b, int off, int len) {
+        setInput(ensureOpen(), b, off, len);
+    }
+
+    public void setInput(byte[] b) {
+        setInput(b, 0, b.length);
+    }
+
+    public void setLevel(int level) {
+        checkLevel(level);
+        this.level = level;
+        setLevel(ensureOpen(), level);
+    }
+
+    public void setStrategy(int strategy) {
+        if (strategy != DEFAULT_STRATEGY && strategy != FILTERED && strategy != HUFFMAN_ONLY) {
+            throw new IllegalArgumentException();
+        }
+        this.strategy = strategy;
+    }
+
+    public boolean needsInput() {
+        ensureOpen();
+        return true;
+    }
+
+    public void finish() {
+        finish(ensureOpen());
+    }
+
+    public boolean finished() {
+        return finished(ensureOpen());
+    }
+
+    public int deflate(byte[] b, int off, int len, int flush) {
+        if (flush != NO_FLUSH && flush != SYNC_FLUSH && flush != FULL_FLUSH) {
+            throw new IllegalArgumentException();
+        }
+        return deflateBytes(ensureOpen(), b, off, len, flush);
+    }
+
+    public int deflate(byte[] b, int off, int len) {
+        return deflate(b, off, len, NO_FLUSH);
+    }
+
+    public int deflate(byte[] b) {
+        return deflate(b, 0, b.length, NO_FLUSH);
+    }
+
+    public int getAdler() {
+        return getAdler(ensureOpen());
+    }
+
+    public int getTotalIn() {
+        return (int) getBytesRead();
+    }
+
+    public long getBytesRead() {
+        return getBytesRead(ensureOpen());
+    }
+
+    public int getTotalOut() {
+        return (int) getBytesWritten();
+    }
+
+    public long getBytesWritten() {
+        return getBytesWritten(ensureOpen());
+    }
+
+    public void reset() {
+        reset(ensureOpen(), level, nowrap);
+    }
+
+    public void end() {
+        if (address != 0) {
+            end(address);
+            address = 0;
+        }
+    }
+
+    private static void checkLevel(int level) {
+        if (level < DEFAULT_COMPRESSION || level > BEST_COMPRESSION) {
+            throw new IllegalArgumentException("invalid compression level");
+        }
+    }
+
+    private long ensureOpen() {
+        if (address == 0) {
+            throw new NullPointerException("Deflater has been closed");
+        }
+        return address;
+    }
+
+    private native long init(int level, boolean nowrap);
+
+    private static native void setInput(long address, byte[] b, int off, int len);
+
+    private static native void setLevel(long address, int level);
+
+    private static native void finish(long address);
+
+    private static native boolean finished(long address);
+
+    private static native int deflateBytes(long address, byte[] b, int off, int len, int flush);
+
+    private static native int getAdler(long address);
+
+    private static native long getBytesRead(long address);
+
+    private static native long getBytesWritten(long address);
+
+    private static native void reset(long address, int level, boolean nowrap);
+
+    private static native void end(long address);
+}
@@ -0,0 +1,110 @@
+package java.util.zip;
+
+// The VM decodes whole blocks as soon as their input arrives, so setInput takes all the input it's
+// given and getRemaining only counts what's left after the end of the stream. Preset dictionaries
+// aren't supported: streams that need one fail with DataFormatException.
+public class Inflater {
+    private long address;
+    private final boolean nowrap;
+
+    public Inflater(boolean nowrap) {
+        this.nowrap = nowrap;
+        address = init(nowrap);
+    }
+
+    public Inflater() {
+        this(false);
+    }
+
+    public void setInput(byte[] b, int off, int len) {
+        setInput(ensureOpen(), b, off, len);
+    }
+
+    public void setInput(byte[] b) {
+        setInput(b, 0, b.length);
+    }
+
+    public int getRemaining() {
+        return getRemaining(ensureOpen());
+    }
+
+    public boolean needsInput() {
+        return needsInput(ensureOpen());
+    }
+
+    public boolean needsDictionary() {
+        return false;
+    }
+
+    public boolean finished() {
+        return finished(ensureOpen());
+    }
+
+    public int inflate(byte[] b, int off, int len) throws DataFormatException {
+        return inflateBytes(ensureOpen(), b, off, len);
+    }
+
+    public int inflate(byte[] b) throws DataFormatException {
+        return inflate(b, 0, b.length);
+    }
+
+    public int getAdler() {
+        return getAdler(ensureOpen());
+    }
+
+    public int getTotalIn() {
+        return (int) getBytesRead();
+    }
+
+    public long getBytesRead() {
+        return getBytesRead(ensureOpen());
+    }
+
+    public int getTotalOut() {
+        return (int) getBytesWritten();
+    }
+
+    public long getBytesWritten() {
+        return getBytesWritten(ensureOpen());
+    }
+
+    public void reset() {
+        reset(ensureOpen(), nowrap);
+    }
+
+    public void end() {
+        if (address != 0) {
+            end(address);
+            address = 0;
+        }
+    }
+
+    private long ensureOpen() {
+        if (address == 0) {
+            throw new NullPointerException("Inflater has been closed");
+        }
+        return address;
+    }
+
+    private native long init(boolean nowrap);
+
+    private static native void setInput(long address, byte[] b, int off, int len);
+
+    private static native int getRemaining(long address);
+
+    private static native boolean needsInput(long address);
+
+    private static native boolean finished(long address);
+
+    private static native int inflateBytes(long address, byte[] b, int off, int len) throws DataFormatException;
+
+    private static native int getAdler(long address);
+
+    private static native long getBytesRead(long address);
+
+    private static native long getBytesWritten(long address);
+
+    private static native void reset(long address, boolean nowrap);
+
+    private static native void end(long address);
+}
@@ -0,0 +1,72 @@
+package sun.misc;
+
+import java.lang.reflect.Field;
+
+// Only the operations on direct memory addresses, compare-and-set on instance fields, and parking
+// threads.
+public final class Unsafe {
+    private static final Unsafe theUnsafe = new Unsafe();
+
+    private Unsafe() {}
+
+    public static Unsafe getUnsafe() {
+        return theUnsafe;
+    }
+
+    public native long allocateMemory(long bytes);
+
+    public native long reallocateMemory(long address, long bytes);
+
+    public native void freeMemory(long address);
+
+    public native void setMemory(long address, long bytes, byte value);
+
+    public native void copyMemory(long srcAddress, long destAddress, long bytes);
+
+    public native byte getByte(long address);
+
+    public native short getShort(long address);
+
+    public native char getChar(long address);
+
+    public native int getInt(long address);
+
+    public native long getLong(long address);
+
+    public native float getFloat(long address);
+
+    public native double getDouble(long address);
+
+    public native void putByte(long address, byte value);
+
+    public native void putShort(long address, short value);
+
+    public native void putChar(long address, char value);
+
+    public native void putInt(long address, int value);
+
+    public native void putLong(long address, long value);
+
+    public native void putFloat(long address, float value);
+
+    public native void putDouble(long address, double value);
+
+    // The offset of an instance field from the start of its objects, for the methods below.
+    public native long objectFieldOffset(Field field);
+
+    // Sets the field at the offset to the new value if it holds the expected one, returning
+    // whether it did. References are compared by identity.
+    public native boolean compareAndSetInt(Object object, long offset, int expected, int value);
+
+    public native boolean compareAndSetLong(Object object, long offset, long expected, long value);
+
+    public native boolean compareAndSetReference(Object object, long offset, Object expected, Object value);
+
+    // Waits for the thread's permit, and consumes it, unless it's interrupted or the time passes
+    // first. The time is a deadline in milliseconds since the epoch if it's absolute, and
+    // otherwise a timeout in nanoseconds, or none if it's zero.
+    public native void park(boolean isAbsolute, long time);
+
+    // Makes the thread's permit available, if it has started and not finished.
+    public native void unpark(Object thread);
+}
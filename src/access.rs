@@ -0,0 +1,167 @@
+use crate::classes::*;
+use crate::loaders;
+use crate::runtime::RuntimeClass;
+use crate::vm::{Vm, VmError};
+use std::fmt;
+use std::rc::Rc;
+
+// Access control, per JVM spec 5.4.4: whether a class may refer to another class, or to a field
+// or method that resolution found. Which modules may access which is checked separately, by
+// Vm::check_access.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Public,
+    Protected,
+    Package, // No modifier: only classes in the same runtime package.
+    Private,
+}
+
+impl Access {
+    pub fn of_field(flags: FieldFlags) -> Access {
+        Access::from_flags(flags.contains(FieldFlags::PUBLIC), flags.contains(FieldFlags::PROTECTED), flags.contains(FieldFlags::PRIVATE))
+    }
+
+    pub fn of_method(flags: MethodFlags) -> Access {
+        Access::from_flags(flags.contains(MethodFlags::PUBLIC), flags.contains(MethodFlags::PROTECTED), flags.contains(MethodFlags::PRIVATE))
+    }
+
+    fn from_flags(public: bool, protected: bool, private: bool) -> Access {
+        match (public, protected, private) {
+            (true, _, _) => Access::Public,
+            (_, true, _) => Access::Protected,
+            (_, _, true) => Access::Private,
+            _ => Access::Package,
+        }
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Access::Public => write!(f, "public"),
+            Access::Protected => write!(f, "protected"),
+            Access::Package => write!(f, "package-private"),
+            Access::Private => write!(f, "private"),
+        }
+    }
+}
+
+// Classes are in the same runtime package if they have the same package name and defining
+// loader.
+pub fn same_package(left: &RuntimeClass, right: &RuntimeClass) -> bool {
+    left.loader == right.loader && loaders::package_name(&left.name) == loaders::package_name(&right.name)
+}
+
+// A class may refer to public classes, and to the other classes of its runtime package. Array
+// classes are as accessible as their element classes.
+pub fn check_class(from: &RuntimeClass, to: &RuntimeClass) -> Result<(), VmError> {
+    if to.is_array() {
+        return match to.component_class {
+            Some(ref component) => check_class(from, component),
+            None => Ok(()),
+        };
+    }
+    if to.class.flags.contains(ClassFlags::PUBLIC) || same_package(from, to) {
+        return Ok(());
+    }
+    Err(VmError::IllegalAccess(format!("failed to access class {} from class {}", to.java_name(), from.java_name())))
+}
+
+// Checks that a class may use the field that resolving a reference to it through the owner
+// found, if any, on an instance of the receiver's class. Without a receiver, only the checks
+// that hold for every object are made.
+pub fn check_field(vm: &mut Vm, from: &Rc<RuntimeClass>, owner: &Rc<RuntimeClass>, name: &str, receiver: Option<&RuntimeClass>) -> Result<(), VmError> {
+    if let Some((declaring_class, flags)) = find_field(owner, name)? {
+        let describe = || format!("field {}.{}", declaring_class.java_name(), name);
+        let receiver = receiver.filter(|_| !flags.contains(FieldFlags::STATIC));
+        check_member(vm, from, &declaring_class, Access::of_field(flags), receiver, describe)?;
+    }
+    Ok(())
+}
+
+// Checks that a class may call the method that resolution found on an instance of the
+// receiver's class, as check_field does. Methods of array classes are public, including the
+// clone method that they override.
+pub fn check_method(vm: &mut Vm, from: &Rc<RuntimeClass>, owner: &RuntimeClass, declaring_class: &Rc<RuntimeClass>, method_index: usize, receiver: Option<&RuntimeClass>) -> Result<(), VmError> {
+    if owner.is_array() {
+        return Ok(());
+    }
+    let method = &declaring_class.class.methods[method_index];
+    let (name, descriptor) = (declaring_class.class.utf8(&method.name)?, declaring_class.class.utf8(&method.descriptor)?);
+    let describe = || format!("method {}.{}{}", declaring_class.java_name(), name, descriptor);
+    let receiver = receiver.filter(|_| !method.flags.contains(MethodFlags::STATIC) && name != "<init>");
+    check_member(vm, from, declaring_class, Access::of_method(method.flags), receiver, describe)
+}
+
+// Whether each use of an instance field or method must check the class of the object it's used
+// on. A subclass may only use the protected members of a class in another runtime package on
+// instances of itself and its subclasses, per JVM spec 5.4.4, so that it can't reach them through
+// instances of other subclasses. That depends on the object, so resolution can't settle it once.
+// Constructors are exempt.
+pub fn checks_receiver(from: &RuntimeClass, declaring_class: &RuntimeClass, access: Access) -> bool {
+    access == Access::Protected && !same_package(from, declaring_class)
+}
+
+// Public members are accessible to every class; protected ones to subclasses of the declaring
+// class, on instances of the subclass, and to its runtime package; package-private ones to its
+// runtime package; and private ones to the declaring class and its nestmates.
+fn check_member(vm: &mut Vm, from: &Rc<RuntimeClass>, declaring_class: &Rc<RuntimeClass>, access: Access, receiver: Option<&RuntimeClass>, describe: impl FnOnce() -> String) -> Result<(), VmError> {
+    let allowed = match access {
+        Access::Public => true,
+        Access::Protected => same_package(from, declaring_class)
+            || (from.is_subclass_of(declaring_class) && receiver.is_none_or(|receiver| receiver.is_subclass_of(from))),
+        Access::Package => same_package(from, declaring_class),
+        Access::Private => from.is_same_class(declaring_class) || is_nestmate(vm, from, declaring_class),
+    };
+    if allowed {
+        return Ok(());
+    }
+    Err(VmError::IllegalAccess(format!("class {} tried to access {} {}", from.java_name(), access, describe())))
+}
+
+// Nestmates are the classes of a nest, which may use each other's private members. That's how
+// javac compiles nested classes from Java 11 on; before, it generated accessor methods instead.
+pub fn is_nestmate(vm: &mut Vm, left: &Rc<RuntimeClass>, right: &Rc<RuntimeClass>) -> bool {
+    same_package(left, right) && nest_host(vm, left).is_same_class(&nest_host(vm, right))
+}
+
+// The class at the top of a class's nest, per JVM spec 5.4.4. A class that names no nest host is
+// its own host, as is one whose host can't be loaded, is in another runtime package or doesn't
+// list the class among its members.
+pub fn nest_host(vm: &mut Vm, class: &Rc<RuntimeClass>) -> Rc<RuntimeClass> {
+    let host_name = class.class.attributes.iter().find_map(|attribute| match *attribute {
+        Attribute::NestHost{ref host_class, ..} => class.class.class_name(host_class).ok(),
+        _ => None,
+    });
+    let host = match host_name.map(|name| vm.load_class_with(class.loader, name)) {
+        Some(Ok(host)) => host,
+        _ => return Rc::clone(class),
+    };
+    let listed = host.class.attributes.iter().any(|attribute| match *attribute {
+        Attribute::NestMembers{ref classes, ..} => classes.iter().any(|member| host.class.class_name(member).is_ok_and(|name| name == class.name)),
+        _ => false,
+    });
+    if listed && same_package(class, &host) {
+        host
+    } else {
+        Rc::clone(class)
+    }
+}
+
+// The class that declares the named field and the field's flags, looked up as field resolution
+// does (JVM spec 5.4.3.2): in the class, then its superinterfaces, then its superclass.
+pub fn find_field(class: &Rc<RuntimeClass>, name: &str) -> Result<Option<(Rc<RuntimeClass>, FieldFlags)>, VmError> {
+    if let Some(index) = class.members().field_named(name) {
+        return Ok(Some((Rc::clone(class), class.class.fields[index].flags)));
+    }
+    for interface in &class.interfaces {
+        if let Some(found) = find_field(interface, name)? {
+            return Ok(Some(found));
+        }
+    }
+    match class.super_class {
+        Some(ref super_class) => find_field(super_class, name),
+        None => Ok(None),
+    }
+}
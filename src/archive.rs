@@ -0,0 +1,461 @@
+use crate::classes::Class;
+use crate::classloader::{self, ClassLoaderError};
+use crate::classpath::{self, ClasspathError};
+use crate::loaders::LoaderId;
+use crate::vm::Vm;
+use std::convert::TryInto;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use std::{error, fmt, fs, io};
+
+const MAGIC: &[u8; 8] = b"JOYVMCDS";
+const VERSION: u32 = 2;
+
+// The loaders whose classes are archived. Other loaders are created by the program as it runs,
+// so there's no telling in advance what they'd load.
+const LOADERS: [LoaderId; 3] = [LoaderId::BOOTSTRAP, LoaderId::PLATFORM, LoaderId::APPLICATION];
+
+// A class data archive, in the spirit of HotSpot's CDS: the classes that the built-in loaders
+// read from their classpaths during one run, already parsed, gathered into a single file. Mapping
+// it in a later run hands each loader its classes as they were parsed, so they're found without
+// searching directories or decompressing JAR entries, and aren't parsed again. Classes are
+// stored with bincode; benches/startup.rs compares loading with and without an archive.
+//
+// The archive records each loader's classpath and the size and modification time of the files
+// its classes came from. It's only mapped if they're unchanged, since otherwise it could supply
+// stale classes. Classes that didn't come from a file, like joyvm's own bootstrap classes and
+// those added with Vm::add_class, aren't archived.
+pub struct ClassArchive {
+    sections: Vec<Section>,
+}
+
+// The classes one loader read, and the classpath it read them from.
+struct Section {
+    loader: LoaderId,
+    classpath: Vec<Option<Origin>>, // Sources without an origin are recorded as None.
+    classes: Vec<ArchivedClass>,
+}
+
+struct Origin {
+    path: PathBuf,
+    stamp: Option<Stamp>, // For files. The stamps of directories say nothing about their classes.
+}
+
+struct ArchivedClass {
+    name: String,
+    source: usize, // The index of the classpath entry it came from.
+    stamp: Option<Stamp>, // For classes in directories, the class file's own.
+    class: Class,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Stamp {
+    size: u64,
+    modified: (u64, u32), // Seconds and nanoseconds since the Unix epoch.
+}
+
+impl Stamp {
+    fn of(path: &Path) -> Result<Stamp, ArchiveError> {
+        let io_error = |err: io::Error| ArchiveError::Io{path: path.to_path_buf(), kind: err.kind()};
+        let metadata = fs::metadata(path).map_err(io_error)?;
+        let modified = metadata.modified().map_err(io_error)?.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Ok(Stamp {size: metadata.len(), modified: (modified.as_secs(), modified.subsec_nanos())})
+    }
+}
+
+impl ClassArchive {
+    // Gathers the classes that the built-in loaders have loaded from their classpaths so far.
+    pub fn dump(vm: &Vm) -> Result<ClassArchive, ArchiveError> {
+        let mut sections = vec![];
+        for &loader in &LOADERS {
+            let sources = match vm.class_loaders().get(loader) {
+                Some(loader) => loader.classpath(),
+                None => continue,
+            };
+            let mut classpath = vec![];
+            for origin in sources.origins() {
+                classpath.push(match origin {
+                    Some(path) if path.is_dir() => Some(Origin {path: path.to_path_buf(), stamp: None}),
+                    Some(path) => Some(Origin {path: path.to_path_buf(), stamp: Some(Stamp::of(path)?)}),
+                    None => None,
+                });
+            }
+
+            let mut names: Vec<&str> = vm.loaded_classes()
+                .filter(|class| class.loader == loader && !class.name.starts_with('['))
+                .map(|class| class.name.as_str())
+                .collect();
+            names.sort_unstable();
+            let mut classes = vec![];
+            for name in names {
+                let (source, bytes) = match sources.find_class_in_source(name)? {
+                    Some(found) => found,
+                    None => continue,
+                };
+                let stamp = match classpath[source] {
+                    Some(Origin {ref path, stamp: None}) => Some(Stamp::of(&path.join(classpath::class_file_name(name)?))?),
+                    Some(_) => None,
+                    None => continue,
+                };
+                // The class file is parsed again, rather than taking the loaded class, which
+                // transformers may have changed.
+                let class = classloader::load_class(&bytes)?;
+                classes.push(ArchivedClass {name: name.to_string(), source, stamp, class});
+            }
+            if !classes.is_empty() {
+                sections.push(Section {loader, classpath, classes});
+            }
+        }
+        Ok(ClassArchive {sections})
+    }
+
+    // The number of classes in the archive.
+    pub fn len(&self) -> usize {
+        self.sections.iter().map(|section| section.classes.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Checks that the VM's loaders would read the same classes from their classpaths as the
+    // archive holds, returning why not if they wouldn't.
+    pub fn validate(&self, vm: &Vm) -> Result<(), ArchiveError> {
+        for section in &self.sections {
+            let loader = vm.class_loaders().get(section.loader).ok_or_else(|| ArchiveError::Stale(format!("loader {} doesn't exist", section.loader.index())))?;
+            let origins = loader.classpath().origins();
+            let same_classpath = origins.len() == section.classpath.len()
+                && origins.iter().zip(&section.classpath).all(|(origin, recorded)| *origin == recorded.as_ref().map(|recorded| recorded.path.as_path()));
+            if !same_classpath {
+                return Err(ArchiveError::Stale(format!("the classpath of the {} loader has changed", loader.name())));
+            }
+            for origin in section.classpath.iter().flatten() {
+                if let Some(stamp) = origin.stamp {
+                    check_stamp(&origin.path, stamp)?;
+                }
+            }
+            for class in &section.classes {
+                if let (Some(stamp), Some(origin)) = (class.stamp, &section.classpath[class.source]) {
+                    check_stamp(&origin.path.join(classpath::class_file_name(&class.name)?), stamp)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Validates the archive and gives each loader its archived classes, returning the number of
+    // classes mapped. They're used in place of the class files on the loader's classpath, unless
+    // class file transformers are installed when they're loaded.
+    pub fn map(self, vm: &mut Vm) -> Result<usize, ArchiveError> {
+        self.validate(vm)?;
+        let count = self.len();
+        for section in self.sections {
+            let classes = section.classes.into_iter().map(|class| (class.name, class.class, class.source)).collect();
+            vm.class_loader_mut(section.loader).expect("Validated loaders exist").map_archived(classes);
+        }
+        Ok(count)
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<ClassArchive, ArchiveError> {
+        let path = path.as_ref();
+        let data = fs::read(path).map_err(|err| ArchiveError::Io{path: path.to_path_buf(), kind: err.kind()})?;
+        ClassArchive::from_bytes(&data)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), ArchiveError> {
+        let path = path.as_ref();
+        fs::write(path, self.to_bytes()).map_err(|err| ArchiveError::Io{path: path.to_path_buf(), kind: err.kind()})
+    }
+
+    // The archive is a header followed by the sections, with all numbers little-endian and
+    // strings and byte arrays preceded by their lengths. Each class is a byte array holding the
+    // class serialized with bincode.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Writer(MAGIC.to_vec());
+        out.u32(VERSION);
+        out.u32(self.sections.len() as u32);
+        for section in &self.sections {
+            out.u32(section.loader.index() as u32);
+            out.u32(section.classpath.len() as u32);
+            for origin in &section.classpath {
+                match *origin {
+                    Some(ref origin) => {
+                        out.u8(1);
+                        out.bytes(origin.path.to_string_lossy().as_bytes());
+                        out.stamp(origin.stamp);
+                    },
+                    None => out.u8(0),
+                }
+            }
+            out.u32(section.classes.len() as u32);
+            for class in &section.classes {
+                out.bytes(class.name.as_bytes());
+                out.u32(class.source as u32);
+                out.stamp(class.stamp);
+                out.bytes(&bincode::serialize(&class.class).expect("Classes can be serialized"));
+            }
+        }
+        out.0
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<ClassArchive, ArchiveError> {
+        if !data.starts_with(MAGIC) {
+            return Err(ArchiveError::NotAnArchive);
+        }
+        let mut reader = Reader {data, position: MAGIC.len()};
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(ArchiveError::UnsupportedVersion(version));
+        }
+        let mut sections = vec![];
+        for _ in 0..reader.u32()? {
+            let loader = *LOADERS.get(reader.u32()? as usize).ok_or(ArchiveError::Corrupt)?;
+            let mut classpath = vec![];
+            for _ in 0..reader.u32()? {
+                classpath.push(match reader.u8()? {
+                    0 => None,
+                    _ => Some(Origin {path: PathBuf::from(reader.string()?), stamp: reader.stamp()?}),
+                });
+            }
+            let mut classes = vec![];
+            for _ in 0..reader.u32()? {
+                let name = reader.string()?;
+                let source = reader.u32()? as usize;
+                if source >= classpath.len() {
+                    return Err(ArchiveError::Corrupt);
+                }
+                let stamp = reader.stamp()?;
+                let class = bincode::deserialize(reader.bytes()?).map_err(|_| ArchiveError::Corrupt)?;
+                classes.push(ArchivedClass {name, source, stamp, class});
+            }
+            sections.push(Section {loader, classpath, classes});
+        }
+        Ok(ClassArchive {sections})
+    }
+}
+
+fn check_stamp(path: &Path, stamp: Stamp) -> Result<(), ArchiveError> {
+    match Stamp::of(path) {
+        Ok(current) if current == stamp => Ok(()),
+        _ => Err(ArchiveError::Stale(format!("{} has changed", path.display()))),
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn stamp(&mut self, stamp: Option<Stamp>) {
+        match stamp {
+            Some(stamp) => {
+                self.u8(1);
+                self.u64(stamp.size);
+                self.u64(stamp.modified.0);
+                self.u32(stamp.modified.1);
+            },
+            None => self.u8(0),
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], ArchiveError> {
+        let bytes = self.data.get(self.position..self.position + length).ok_or(ArchiveError::Corrupt)?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, ArchiveError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, ArchiveError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().expect("Slice has length 4")))
+    }
+
+    fn u64(&mut self) -> Result<u64, ArchiveError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().expect("Slice has length 8")))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], ArchiveError> {
+        let length = self.u32()? as usize;
+        self.take(length)
+    }
+
+    fn string(&mut self) -> Result<String, ArchiveError> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| ArchiveError::Corrupt)
+    }
+
+    fn stamp(&mut self) -> Result<Option<Stamp>, ArchiveError> {
+        Ok(match self.u8()? {
+            0 => None,
+            _ => Some(Stamp {size: self.u64()?, modified: (self.u64()?, self.u32()?)}),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ArchiveError {
+    Class(ClassLoaderError),
+    Classpath(ClasspathError),
+    Corrupt,
+    Io{path: PathBuf, kind: io::ErrorKind},
+    NotAnArchive,
+    Stale(String), // Why the archive doesn't match the classpath.
+    UnsupportedVersion(u32),
+}
+
+impl std::convert::From<ClassLoaderError> for ArchiveError {
+    fn from(err: ClassLoaderError) -> ArchiveError {
+        ArchiveError::Class(err)
+    }
+}
+
+impl std::convert::From<ClasspathError> for ArchiveError {
+    fn from(err: ClasspathError) -> ArchiveError {
+        ArchiveError::Classpath(err)
+    }
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ArchiveError::Class(ref err) => write!(f, "{}", err),
+            ArchiveError::Classpath(ref err) => write!(f, "{}", err),
+            ArchiveError::Corrupt => write!(f, "Class data archive is corrupt"),
+            ArchiveError::Io{ref path, ref kind} => write!(f, "Failed to access {}: {:?}", path.display(), kind),
+            ArchiveError::NotAnArchive => write!(f, "Not a class data archive"),
+            ArchiveError::Stale(ref reason) => write!(f, "Class data archive is out of date: {}", reason),
+            ArchiveError::UnsupportedVersion(version) => write!(f, "Unsupported class data archive version {}", version),
+        }
+    }
+}
+
+impl error::Error for ArchiveError {
+    fn description(&self) -> &str {
+        match *self {
+            ArchiveError::Class(_) => "Failed to parse a class",
+            ArchiveError::Classpath(_) => "Failed to read from the classpath",
+            ArchiveError::Corrupt => "Class data archive is corrupt",
+            ArchiveError::Io{..} => "Failed to access a file",
+            ArchiveError::NotAnArchive => "Not a class data archive",
+            ArchiveError::Stale(_) => "Class data archive is out of date",
+            ArchiveError::UnsupportedVersion(_) => "Unsupported class data archive version",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            ArchiveError::Class(ref err) => Some(err),
+            ArchiveError::Classpath(ref err) => Some(err),
+            ArchiveError::Corrupt => None,
+            ArchiveError::Io{..} => None,
+            ArchiveError::NotAnArchive => None,
+            ArchiveError::Stale(_) => None,
+            ArchiveError::UnsupportedVersion(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classpath::Classpath;
+    use std::time::{Duration, SystemTime};
+
+    // A fresh directory holding copies of the given fixtures.
+    fn scratch(name: &str, fixtures: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("joyvm-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for fixture in fixtures {
+            fs::copy(Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join(fixture), dir.join(Path::new(fixture).file_name().unwrap())).unwrap();
+        }
+        dir
+    }
+
+    fn vm_with_classpath(path: &Path) -> Vm {
+        let mut vm = Vm::new();
+        vm.set_classpath(Classpath::parse(path).unwrap());
+        vm
+    }
+
+    fn touch(path: &Path) {
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60)).unwrap();
+    }
+
+    #[test]
+    fn test_archived_jar_classes_are_mapped() {
+        let dir = scratch("archive-jar", &["jars/app.jar", "jars/lib.jar"]);
+        let mut vm = vm_with_classpath(&dir.join("app.jar"));
+        vm.load_class("com/example/App").unwrap();
+        vm.load_class("com/example/lib/Lib").unwrap();
+        let archive = ClassArchive::dump(&vm).unwrap();
+        assert_eq!(2, archive.len()); // Object and the like are built in.
+        archive.write(dir.join("classes.jsa")).unwrap();
+
+        let mut vm = vm_with_classpath(&dir.join("app.jar"));
+        assert_eq!(Ok(2), ClassArchive::read(dir.join("classes.jsa")).unwrap().map(&mut vm));
+        assert_eq!(2, vm.classpath().len());
+        assert_eq!("com/example/lib/Lib", vm.load_class("com/example/lib/Lib").unwrap().name);
+        // Loading a class takes it from the archive rather than the classpath.
+        let loader = vm.class_loader_mut(LoaderId::APPLICATION).unwrap();
+        assert!(loader.take_archived("com/example/lib/Lib").is_none());
+        assert!(loader.take_archived("com/example/App").is_some());
+
+        // The archive no longer matches once a JAR it was dumped from changes.
+        touch(&dir.join("lib.jar"));
+        let mut vm = vm_with_classpath(&dir.join("app.jar"));
+        match ClassArchive::read(dir.join("classes.jsa")).unwrap().map(&mut vm) {
+            Err(ArchiveError::Stale(ref reason)) if reason.contains("lib.jar") => (),
+            other => panic!("Expected a stale archive; got {:?}", other),
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_archived_directory_classes_are_validated() {
+        let dir = scratch("archive-directory", &["Completes.class", "Throws.class"]);
+        let mut vm = vm_with_classpath(&dir);
+        vm.load_class("Completes").unwrap();
+        let archive = ClassArchive::from_bytes(&ClassArchive::dump(&vm).unwrap().to_bytes()).unwrap();
+        assert_eq!(1, archive.len());
+
+        let other = vm_with_classpath(&Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata"));
+        assert_eq!(Err(ArchiveError::Stale("the classpath of the app loader has changed".to_string())), archive.validate(&other));
+        touch(&dir.join("Throws.class")); // Not archived, so it doesn't matter.
+        assert_eq!(Ok(()), archive.validate(&vm_with_classpath(&dir)));
+        touch(&dir.join("Completes.class"));
+        assert!(matches!(archive.validate(&vm_with_classpath(&dir)), Err(ArchiveError::Stale(_))));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_archives() {
+        assert!(matches!(ClassArchive::from_bytes(b"not an archive"), Err(ArchiveError::NotAnArchive)));
+        assert!(matches!(ClassArchive::from_bytes(b"JOYVMCDS\x03\x00\x00\x00"), Err(ArchiveError::UnsupportedVersion(3))));
+        assert!(matches!(ClassArchive::from_bytes(b"JOYVMCDS\x02\x00\x00\x00\x01\x00"), Err(ArchiveError::Corrupt)));
+    }
+}
@@ -0,0 +1,124 @@
+use crate::call;
+use crate::vm::{Vm, VmError};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// How many times joyvm bench runs a method: some to warm up, so that classes are initialized,
+// caches filled and hot code compiled, and then some to measure.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BenchOptions {
+    pub warmup: u32,
+    pub iterations: u32,
+}
+
+impl Default for BenchOptions {
+    fn default() -> BenchOptions {
+        BenchOptions {warmup: 10, iterations: 100}
+    }
+}
+
+// Each measured call's time, and the instructions the interpreter ran for them all. Compiled code
+// doesn't count instructions, so with the JIT they only cover what was interpreted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BenchResult {
+    pub method: String, // As given, as in Benchmarks.fib(I)I.
+    pub warmup: u32,
+    pub times: Vec<Duration>, // In the order they ran.
+    pub instructions: u64,
+}
+
+impl BenchResult {
+    pub fn total(&self) -> Duration {
+        self.times.iter().sum()
+    }
+
+    pub fn mean(&self) -> Duration {
+        self.total() / self.times.len().max(1) as u32
+    }
+
+    pub fn median(&self) -> Duration {
+        let mut times = self.times.clone();
+        times.sort_unstable();
+        times.get(times.len() / 2).copied().unwrap_or_default()
+    }
+
+    pub fn min(&self) -> Duration {
+        self.times.iter().min().copied().unwrap_or_default()
+    }
+
+    pub fn ops_per_second(&self) -> f64 {
+        self.times.len() as f64 / self.total().as_secs_f64()
+    }
+
+    pub fn instructions_per_op(&self) -> u64 {
+        self.instructions / self.times.len().max(1) as u64
+    }
+}
+
+//     Benchmarks.fib(I)I: 100 iterations after 10 to warm up
+//       1234.5 ops/s, 810.04µs/op (median 805.11µs, min 798.2µs)
+//       150050 instructions/op, 185.2M instructions/s
+impl fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}: {} iterations after {} to warm up", self.method, self.times.len(), self.warmup)?;
+        writeln!(f, "  {:.1} ops/s, {:.2?}/op (median {:.2?}, min {:.2?})", self.ops_per_second(), self.mean(), self.median(), self.min())?;
+        let throughput = self.instructions as f64 / self.total().as_secs_f64() / 1e6;
+        write!(f, "  {} instructions/op, {:.1}M instructions/s", self.instructions_per_op(), throughput)
+    }
+}
+
+// Calls the static method, named with its descriptor as for Vm::call_static, over and over with
+// the same arguments, timing each call once it's warmed up. Arguments are only parsed once, so
+// strings are the same objects each time.
+pub fn run(vm: &mut Vm, class_name: &str, method: &str, args: &[&str], options: BenchOptions) -> Result<BenchResult, VmError> {
+    let (name, descriptor, args) = call::parse_call(vm, method, args)?;
+    for _ in 0..options.warmup {
+        vm.invoke_static(class_name, name, descriptor, args.clone())?;
+    }
+    let mut times = Vec::with_capacity(options.iterations as usize);
+    let executed = vm.executed_instructions();
+    for _ in 0..options.iterations {
+        let start = Instant::now();
+        let result = vm.invoke_static(class_name, name, descriptor, args.clone())?;
+        times.push(start.elapsed());
+        std::hint::black_box(result);
+    }
+    Ok(BenchResult {
+        method: format!("{}.{}", class_name.replace('/', "."), method),
+        warmup: options.warmup,
+        times,
+        instructions: vm.executed_instructions() - executed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+
+    #[test]
+    fn test_run() {
+        let mut vm = vm_with(&[fixture!("Debuggee")]);
+        let options = BenchOptions {warmup: 2, iterations: 5};
+        let result = run(&mut vm, "Debuggee", "square(I)I", &["3"], options).unwrap();
+        assert_eq!("Debuggee.square(I)I", result.method);
+        assert_eq!(5, result.times.len());
+        // iload_0, iload_0, imul, istore_1, iload_1, ireturn.
+        assert_eq!(6, result.instructions_per_op());
+        assert_eq!(2 * 6 + 5 * 6, vm.executed_instructions());
+        assert!(result.min() <= result.median() && result.mean() <= result.total());
+
+        let text = result.to_string();
+        assert!(text.starts_with("Debuggee.square(I)I: 5 iterations after 2 to warm up\n  "), "{}", text);
+        assert!(text.contains(" ops/s, "), "{}", text);
+        assert!(text.contains("\n  6 instructions/op, "), "{}", text);
+    }
+
+    #[test]
+    fn test_errors_are_returned() {
+        let mut vm = vm_with(&[fixture!("Arithmetic")]);
+        let result = run(&mut vm, "Arithmetic", "divide(II)I", &["1", "0"], BenchOptions::default());
+        assert!(matches!(result, Err(VmError::UncaughtException(_))));
+        assert!(matches!(run(&mut vm, "Arithmetic", "divide(II)I", &["1"], BenchOptions::default()), Err(VmError::InvalidArgument(_))));
+    }
+}
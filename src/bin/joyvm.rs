@@ -0,0 +1,841 @@
+use joyvm::call;
+use joyvm::bench::{self, BenchOptions};
+use joyvm::classes::Class;
+use joyvm::classloader;
+use joyvm::counters;
+use joyvm::crash::{self, Crash};
+use joyvm::depgraph::DependencyGraph;
+use joyvm::descriptor::MethodDescriptor;
+use joyvm::diff;
+use joyvm::gc::GcCause;
+use joyvm::gclog::{self, GcLogOptions};
+use joyvm::histogram::Histogram;
+use joyvm::disasm::{self, DisasmOptions};
+use joyvm::jar::JarSource;
+use joyvm::limits::Limits;
+use joyvm::options::{parse_size, VmOptions};
+use joyvm::outcome::{Outcome, UncaughtThrowable};
+use joyvm::profiler;
+use joyvm::recorder::{FlightRecorder, Recording, RecordingReader};
+use joyvm::threaddump;
+use joyvm::trace::{TraceFilter, Tracer};
+use joyvm::verify;
+use joyvm::vm::{Vm, VmError};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::process;
+use std::str::FromStr;
+use std::time::Duration;
+
+const USAGE: &str = "Usage: joyvm [options] <main class> [args...]
+       joyvm [options] -jar <jar> [args...]
+       joyvm verify <jar>...
+       joyvm disasm [-v] [-p] <class file>...
+       joyvm dump [--json] <class file>...
+       joyvm diff <old class file> <new class file>
+       joyvm deps [--referrers <class> | --missing | --unreachable <root class>...] <classpath>
+       joyvm recording <file>
+       joyvm histo [--live] [options] <main class> [args...]
+       joyvm call [-cp <path>] <class file or class> <method> [args...]
+       joyvm bench [-cp <path>] [--warmup <count>] [--iterations <count>] <class file or class> <method> [args...]
+
+Options:
+  -cp, -classpath <path>          Directories and JARs to load classes from, separated as in PATH
+  -D<key>=<value>                 Sets a system property
+  -Xmx<size>                      Limits the heap to this many bytes, or kilobytes with k, and so on
+  -Xss<size>                      Sets the size of the Java stack, which bounds how deeply calls nest
+  -ea, -da                        Enables or disables assertions
+  -verbose:class                  Prints each class as it's loaded
+  -verbose:gc                     Prints each garbage collection
+  -Xlog:gc[*][:<file>]            Logs each garbage collection as HotSpot does, to the file if one is
+                                  given, with the details of each with gc*
+  -Xrs                            Leaves SIGQUIT alone, rather than printing a thread dump and a summary
+                                  of the heap on stdout when it comes, as on Ctrl-\\
+  -XX:+PrintClassHistogram        Also prints a heap histogram on SIGQUIT
+  --max-instructions <count>      Stops the program once it has run this many instructions
+  --max-duration <milliseconds>   Stops the program once it has run for this long
+  --max-allocated-bytes <bytes>   Stops the program once it has allocated this much
+  --max-loaded-classes <count>    Stops the program once it has loaded this many classes
+  --trace <pattern>               Prints each instruction run in methods matching the pattern, such as
+                                  com.example.* or com.example.Main::run, on stderr. May be repeated
+  --trace-file <file>             Writes the trace to the file instead, tracing everything unless
+                                  --trace is also given
+  --profile <file>                Samples where the program spends its time, writing folded stacks for
+                                  flame graphs to the file when it finishes
+  --profile-interval <milliseconds>
+                                  How often to sample, rather than every 10ms
+  --record <file>                 Records class loads, collections and samples of allocations and of
+                                  where the program spends its time to the file, for joyvm recording
+  --hot-methods <count>           Prints the methods invoked and looped in most on stderr when the
+                                  program finishes
+
+Disassembly options, as for javap -c:
+  -v                              Also prints the constant pool and attributes, like javap -v
+  -p                              Includes private members
+
+Dump options:
+  --json                          Prints each class as JSON on one line, rather than in Rust's debug format
+
+Heap histogram options, for a program run as above whose heap is summarised as jmap -histo does
+once it finishes:
+  --live                          Collects garbage first, so that only reachable objects are counted
+
+Calling a single static method, named with its descriptor as in 'max(II)I', prints what it returns.
+Arguments are written as in Java, without suffixes, and strings as they are; references can be null.
+A class file's directory is the classpath unless one is given.
+
+Benchmark options, for timing a method called as above and counting the instructions it runs:
+  --warmup <count>                Calls it this many times before measuring, rather than 10
+  --iterations <count>            Measures this many calls, rather than 100
+
+Dependency options, which otherwise prints each class on the classpath and the classes it uses:
+  --referrers <class>             Prints the classes that use this one
+  --missing                       Prints the classes that are used but aren't on the classpath
+  --unreachable <root class>...   Prints the classes that nothing the roots use could load";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let code = match args.split_first() {
+        Some((command, jars)) if command == "verify" && !jars.is_empty() => verify_jars(jars),
+        Some((command, args)) if command == "disasm" => disassemble(args),
+        Some((command, args)) if command == "dump" => dump(args),
+        Some((command, args)) if command == "diff" => diff_classes(args),
+        Some((command, args)) if command == "deps" => dependencies(args),
+        Some((command, args)) if command == "recording" => print_recording(args),
+        Some((command, args)) if command == "histo" => histogram(args),
+        Some((command, args)) if command == "call" => call_method(args),
+        Some((command, args)) if command == "bench" => benchmark(args),
+        _ => match Launch::parse(&args) {
+            Ok(launch) => run(launch),
+            Err(err) => {
+                eprintln!("{}\n\n{}", err, USAGE);
+                2
+            },
+        },
+    };
+    process::exit(code);
+}
+
+// Verifies each JAR in turn, printing a report for each. Fails if any class doesn't verify.
+fn verify_jars(jars: &[String]) -> i32 {
+    let mut code = 0;
+    for jar in jars {
+        match verify::verify_jar(jar) {
+            Ok(report) => {
+                println!("{}", report);
+                if !report.is_success() {
+                    code = 1;
+                }
+            },
+            Err(err) => {
+                eprintln!("{}: {}", jar, err);
+                code = 1;
+            },
+        }
+    }
+    code
+}
+
+// Prints each class file as javap would. Fails if any can't be read or disassembled.
+fn disassemble(args: &[String]) -> i32 {
+    let mut options = DisasmOptions::default();
+    let mut files = vec![];
+    for arg in args {
+        match arg.as_str() {
+            "-v" | "-verbose" => options.verbose = true,
+            "-p" | "-private" => options.private = true,
+            "-c" => (),
+            _ if arg.starts_with('-') => {
+                eprintln!("Unknown option {}\n\n{}", arg, USAGE);
+                return 2;
+            },
+            _ => files.push(arg),
+        }
+    }
+    if files.is_empty() {
+        eprintln!("No class files given\n\n{}", USAGE);
+        return 2;
+    }
+
+    let mut code = 0;
+    for file in files {
+        match read_class(file).and_then(|class| disasm::disassemble(&class, options).map_err(|err| err.to_string())) {
+            Ok(disassembly) => {
+                if options.verbose {
+                    let path = fs::canonicalize(file).unwrap_or_else(|_| Path::new(file).to_path_buf());
+                    println!("Classfile {}", path.display());
+                }
+                print!("{}", disassembly);
+            },
+            Err(err) => {
+                eprintln!("{}: {}", file, err);
+                code = 1;
+            },
+        }
+    }
+    code
+}
+
+// Prints each class file as parsed, for tools to consume with --json. Fails if any can't be read.
+fn dump(args: &[String]) -> i32 {
+    let json = args.iter().any(|arg| arg == "--json");
+    let files: Vec<&String> = args.iter().filter(|arg| *arg != "--json").collect();
+    if let Some(option) = files.iter().find(|file| file.starts_with('-')) {
+        eprintln!("Unknown option {}\n\n{}", option, USAGE);
+        return 2;
+    }
+    if files.is_empty() {
+        eprintln!("No class files given\n\n{}", USAGE);
+        return 2;
+    }
+
+    let mut code = 0;
+    for file in files {
+        match read_class(file) {
+            Ok(class) if json => println!("{}", class.to_json()),
+            Ok(class) => println!("{:#?}", class),
+            Err(err) => {
+                eprintln!("{}: {}", file, err);
+                code = 1;
+            },
+        }
+    }
+    code
+}
+
+// Prints how two versions of a class differ. As with diff, the status says whether they do.
+fn diff_classes(args: &[String]) -> i32 {
+    let (old, new) = match *args {
+        [ref old, ref new] => (old, new),
+        _ => {
+            eprintln!("diff needs two class files\n\n{}", USAGE);
+            return 2;
+        },
+    };
+    match (read_class(old), read_class(new)) {
+        (Ok(old), Ok(new)) => {
+            let diff = diff::diff(&old, &new);
+            print!("{}", diff);
+            if diff.is_empty() { 0 } else { 1 }
+        },
+        (Err(err), _) => {
+            eprintln!("{}: {}", old, err);
+            2
+        },
+        (_, Err(err)) => {
+            eprintln!("{}: {}", new, err);
+            2
+        },
+    }
+}
+
+// Prints which classes on a classpath use which. Class names may be given with dots or slashes.
+fn dependencies(args: &[String]) -> i32 {
+    let (query, classpath) = match args.split_last() {
+        Some((classpath, query)) if !classpath.starts_with('-') => (query, classpath),
+        _ => {
+            eprintln!("deps needs a classpath\n\n{}", USAGE);
+            return 2;
+        },
+    };
+    let graph = match DependencyGraph::from_classpath(classpath) {
+        Ok(graph) => graph,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 2;
+        },
+    };
+    for file in graph.unreadable() {
+        eprintln!("Skipped {}, which isn't a valid class file", file);
+    }
+
+    let names: Vec<String> = query.iter().skip(1).map(|name| name.replace('.', "/")).collect();
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+    match (query.first().map(String::as_str), &names[..]) {
+        (None, _) => for class in graph.classes() {
+            println!("{}", class);
+            for reference in graph.references(class).into_iter().flatten() {
+                println!("  -> {}", reference);
+            }
+        },
+        (Some("--referrers"), &[class]) => graph.referrers(class).for_each(|class| println!("{}", class)),
+        (Some("--missing"), &[]) => graph.missing().into_iter().for_each(|class| println!("{}", class)),
+        (Some("--unreachable"), roots) if !roots.is_empty() => {
+            if let Some(root) = roots.iter().find(|root| !graph.contains(root)) {
+                eprintln!("{} isn't on the classpath", root);
+                return 2;
+            }
+            graph.unreachable(roots).into_iter().for_each(|class| println!("{}", class));
+        },
+        _ => {
+            eprintln!("Unknown options {}\n\n{}", query.join(" "), USAGE);
+            return 2;
+        },
+    }
+    0
+}
+
+// Runs the program and then prints how many objects of each class are on the heap, and how much
+// room they take. There's no attaching to a running VM, so this is the heap as the program left
+// it; with --live, once the garbage has been collected.
+fn histogram(args: &[String]) -> i32 {
+    let (live, args) = match args.split_first() {
+        Some((option, rest)) if option == "--live" => (true, rest),
+        _ => (false, args),
+    };
+    match Launch::parse(args) {
+        Ok(launch) => run(Launch {histogram: Some(live), ..launch}),
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            2
+        },
+    }
+}
+
+// Invokes one static method with the arguments, printing what it returns, for trying out a
+// method, or the interpreter, without writing a main.
+fn call_method(args: &[String]) -> i32 {
+    let (classpath, args) = match args {
+        [option, classpath, rest @ ..] if option == "-cp" || option == "-classpath" => (Some(classpath.clone()), rest),
+        _ => (None, args),
+    };
+    let (class, method, args) = match args {
+        [class, method, rest @ ..] => (class, method, rest),
+        _ => {
+            eprintln!("call needs a class and a method\n\n{}", USAGE);
+            return 2;
+        },
+    };
+    let (mut vm, class_name) = match vm_with_class(classpath, class) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 1;
+        },
+    };
+
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match vm.call_static(&class_name, method, &args) {
+        Ok(None) => 0,
+        Ok(Some(value)) => {
+            // The method was found, so its descriptor is valid.
+            let descriptor = method.find('(').and_then(|paren| MethodDescriptor::parse(&method[paren..]).ok());
+            match descriptor.and_then(|descriptor| descriptor.return_type) {
+                Some(return_type) => match call::format_result(&mut vm, &return_type, &value) {
+                    Ok(result) => println!("{}", result),
+                    Err(err) => {
+                        eprintln!("Error: {}", err);
+                        return 1;
+                    },
+                },
+                None => println!("{:?}", value),
+            }
+            0
+        },
+        Err(err) => report_call_error(&vm, err),
+    }
+}
+
+// Runs a static method over and over, reporting how fast it goes.
+fn benchmark(args: &[String]) -> i32 {
+    let mut options = BenchOptions::default();
+    let mut classpath = None;
+    let mut args = args.iter();
+    let class = loop {
+        let arg = match args.next() {
+            Some(arg) => arg,
+            None => {
+                eprintln!("bench needs a class and a method\n\n{}", USAGE);
+                return 2;
+            },
+        };
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        let parsed = match arg.as_str() {
+            "-cp" | "-classpath" => value().map(|value| classpath = Some(value.clone())),
+            "--warmup" => value().and_then(|value| number(arg, value)).map(|warmup| options.warmup = warmup),
+            "--iterations" => value().and_then(|value| number(arg, value)).map(|iterations| options.iterations = iterations),
+            _ if arg.starts_with('-') => Err(format!("Unknown option {}", arg)),
+            _ => break arg,
+        };
+        if let Err(err) = parsed {
+            eprintln!("{}\n\n{}", err, USAGE);
+            return 2;
+        }
+    };
+    let method = match args.next() {
+        Some(method) => method,
+        None => {
+            eprintln!("bench needs a class and a method\n\n{}", USAGE);
+            return 2;
+        },
+    };
+    let (mut vm, class_name) = match vm_with_class(classpath, class) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 1;
+        },
+    };
+
+    let args: Vec<&str> = args.map(String::as_str).collect();
+    match bench::run(&mut vm, &class_name, method, &args, options) {
+        Ok(result) => {
+            println!("{}", result);
+            0
+        },
+        Err(err) => report_call_error(&vm, err),
+    }
+}
+
+// Makes a VM for running a class's methods, returning the name of the class. A class file is
+// defined as it is, and the classes it uses are looked for beside it unless a classpath is given;
+// otherwise the class is looked for on the classpath.
+fn vm_with_class(classpath: Option<String>, class: &str) -> Result<(Vm, String), String> {
+    let file = Path::new(class);
+    let is_file = class.ends_with(".class");
+    let classpath = classpath.unwrap_or_else(|| match file.parent() {
+        Some(dir) if is_file && !dir.as_os_str().is_empty() => dir.display().to_string(),
+        _ => ".".to_string(),
+    });
+    let mut vm = VmOptions::new().classpath(&classpath).build().map_err(|err| format!("Error: invalid classpath {}: {}", classpath, err))?;
+    let class_name = if is_file {
+        let bytes = fs::read(file).map_err(|err| format!("{}: {}", class, err))?;
+        vm.add_class(&bytes).map_err(|err| format!("{}: {}", class, err))?
+    } else {
+        class.replace('.', "/")
+    };
+    Ok((vm, class_name))
+}
+
+// Reports a call that failed, returning the exit status, as the java launcher would for main.
+fn report_call_error(vm: &Vm, err: VmError) -> i32 {
+    match err {
+        VmError::UncaughtException(throwable) => {
+            match UncaughtThrowable::from_object(vm, &throwable) {
+                Ok(throwable) => eprintln!("Exception in thread \"main\" {}", throwable),
+                Err(err) => eprintln!("Error: {}", err),
+            }
+            1
+        },
+        VmError::SystemExit(status) => status,
+        err => {
+            eprintln!("Error: {}", err);
+            1
+        },
+    }
+}
+
+// Prints each record in a recording made with --record, one per line.
+fn print_recording(args: &[String]) -> i32 {
+    let file = match *args {
+        [ref file] => file,
+        _ => {
+            eprintln!("recording needs a file\n\n{}", USAGE);
+            return 2;
+        },
+    };
+    let reader = match RecordingReader::open(Path::new(file)) {
+        Ok(reader) => reader,
+        Err(err) => {
+            eprintln!("{}: {}", file, err);
+            return 1;
+        },
+    };
+    for record in reader {
+        match record {
+            Ok(record) => println!("{}", record),
+            Err(err) => {
+                eprintln!("{}: {}", file, err);
+                return 1;
+            },
+        }
+    }
+    0
+}
+
+fn read_class(file: &str) -> Result<Class, String> {
+    let bytes = fs::read(file).map_err(|err| err.to_string())?;
+    classloader::load_class(&bytes).map_err(|err| err.to_string())
+}
+
+// A program to run, as described on the command line.
+#[derive(Debug, PartialEq)]
+struct Launch {
+    options: VmOptions,
+    classpath: Option<String>, // The VM's classpath is only settled once we know what's run.
+    trace: Option<TraceFilter>,
+    trace_file: Option<String>,
+    profile: Option<String>,
+    profile_interval: Duration,
+    record: Option<String>,
+    hot_methods: Option<usize>,
+    gc_log: Option<GcLogOptions>,
+    histogram: Option<bool>, // Whether to collect garbage before taking it, if there is one.
+    dump_on_quit: bool,
+    class_histogram_on_quit: bool,
+    main: Main,
+    args: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Main {
+    Class(String), // As given, with dots between the package names.
+    Jar(String), // Whose manifest names the main class, with -jar.
+}
+
+impl Launch {
+    // Options come first; the first argument that isn't one names the main class, and the rest
+    // are passed to main. Options follow the java launcher's, with a few of joyvm's own.
+    fn parse(args: &[String]) -> Result<Launch, String> {
+        let (mut options, mut classpath, mut limits) = (VmOptions::new(), None, Limits::default());
+        let (mut trace, mut trace_file): (Option<TraceFilter>, _) = (None, None);
+        let (mut profile, mut profile_interval) = (None, profiler::DEFAULT_INTERVAL);
+        let (mut record, mut hot_methods, mut gc_log) = (None, None, None);
+        let (mut dump_on_quit, mut class_histogram_on_quit) = (true, false);
+        let mut args = args.iter();
+        let main = loop {
+            let arg = args.next().ok_or("No main class given")?;
+            let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "-cp" | "-classpath" | "--class-path" | "--cp" => classpath = Some(value()?.clone()),
+                "-D" | "--property" => options = property(options, value()?)?,
+                "-ea" | "-enableassertions" => options = options.enable_assertions(true),
+                "-da" | "-disableassertions" => options = options.enable_assertions(false),
+                "-verbose" | "-verbose:class" => options = options.verbose_class(true),
+                "-verbose:gc" => options = options.verbose_gc(true),
+                "-Xrs" => dump_on_quit = false,
+                "-XX:+PrintClassHistogram" => class_histogram_on_quit = true,
+                "-jar" => break Main::Jar(value()?.clone()),
+                "--max-instructions" => limits.max_instructions = Some(number(arg, value()?)?),
+                "--max-duration" => limits.max_duration = Some(Duration::from_millis(number(arg, value()?)?)),
+                "--max-allocated-bytes" => limits.max_allocated_bytes = Some(number(arg, value()?)?),
+                "--max-loaded-classes" => limits.max_loaded_classes = Some(number(arg, value()?)?),
+                "--trace" => trace = Some(trace.unwrap_or_default().pattern(value()?)),
+                "--trace-file" => trace_file = Some(value()?.clone()),
+                "--profile" => profile = Some(value()?.clone()),
+                "--profile-interval" => profile_interval = Duration::from_millis(number(arg, value()?)?),
+                "--record" => record = Some(value()?.clone()),
+                "--hot-methods" => hot_methods = Some(number(arg, value()?)?),
+                _ if arg.starts_with("-D") => options = property(options, &arg[2..])?,
+                _ if arg.starts_with("-Xlog:") => {
+                    gc_log = Some(GcLogOptions::parse(&arg[6..]).ok_or_else(|| format!("Unsupported logging option {}", arg))?);
+                },
+                _ if arg.starts_with("-Xmx") => {
+                    options = options.max_heap(parse_size(&arg[4..]).ok_or_else(|| format!("Invalid maximum heap size: {}", arg))?);
+                },
+                _ if arg.starts_with("-Xss") => {
+                    options = options.stack_size(parse_size(&arg[4..]).ok_or_else(|| format!("Invalid thread stack size: {}", arg))?);
+                },
+                _ if arg.starts_with('-') => return Err(format!("Unknown option {}", arg)),
+                _ => break Main::Class(arg.clone()),
+            }
+        };
+        if trace_file.is_some() && trace.is_none() {
+            trace = Some(TraceFilter::new());
+        }
+        Ok(Launch {
+            options: options.limits(limits),
+            classpath,
+            trace,
+            trace_file,
+            profile,
+            profile_interval,
+            record,
+            hot_methods,
+            gc_log,
+            histogram: None,
+            dump_on_quit,
+            class_histogram_on_quit,
+            main,
+            args: args.cloned().collect(),
+        })
+    }
+}
+
+fn property(options: VmOptions, property: &str) -> Result<VmOptions, String> {
+    match property.find('=') {
+        Some(equals) => Ok(options.property(&property[..equals], &property[equals + 1..])),
+        None => Err(format!("Property {} has no value", property)),
+    }
+}
+
+fn number<T: FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("{} needs a number; got {}", option, value))
+}
+
+// Runs the program, returning the exit status the java launcher would. Exceptions escaping main
+// are printed on stderr, as are problems that stopped the program from running at all.
+fn run(launch: Launch) -> i32 {
+    let (classpath, main_class) = match launch.main {
+        // As with java, the JAR is the whole classpath, along with those its manifest names.
+        Main::Jar(ref jar) => match JarSource::open(jar) {
+            Ok(source) => match source.main_class() {
+                Some(main_class) => (jar.clone(), main_class),
+                None => {
+                    eprintln!("no main manifest attribute, in {}", jar);
+                    return 1;
+                },
+            },
+            Err(err) => {
+                eprintln!("Error: Unable to access jarfile {}: {}", jar, err);
+                return 1;
+            },
+        },
+        Main::Class(ref main_class) => {
+            let classpath = launch.classpath.clone()
+                .or_else(|| env::var("CLASSPATH").ok())
+                .unwrap_or_else(|| ".".to_string());
+            (classpath, main_class.replace('.', "/"))
+        },
+    };
+    let mut vm = match launch.options.classpath(&classpath).build() {
+        Ok(vm) => vm,
+        Err(err) => {
+            eprintln!("Error: invalid classpath {}: {}", classpath, err);
+            return 1;
+        },
+    };
+
+    // The tracer's output is flushed when the VM is dropped.
+    if let Some(filter) = launch.trace {
+        let tracer = match launch.trace_file {
+            Some(ref file) => match Tracer::to_file(Path::new(file), filter) {
+                Ok(tracer) => tracer,
+                Err(err) => {
+                    eprintln!("Error: Unable to write the trace to {}: {}", file, err);
+                    return 1;
+                },
+            },
+            None => Tracer::to_stderr(filter),
+        };
+        vm.set_hooks(Box::new(tracer));
+    }
+
+    if let Some(ref options) = launch.gc_log {
+        match options.open() {
+            Ok(log) => vm.subscribe(gclog::EVENTS, Box::new(log)),
+            Err(err) => {
+                eprintln!("Error: Unable to write the GC log to {}: {}", options.file.as_ref().map_or_else(|| "stdout".to_string(), |file| file.display().to_string()), err);
+                return 1;
+            },
+        };
+    }
+
+    if launch.profile.is_some() {
+        vm.start_profiler(launch.profile_interval);
+    }
+    let recording = match launch.record {
+        Some(ref file) => match FlightRecorder::to_file(Path::new(file)) {
+            Ok(recorder) => Some(Recording::start(&mut vm, recorder, launch.profile_interval)),
+            Err(err) => {
+                eprintln!("Error: Unable to write the recording to {}: {}", file, err);
+                return 1;
+            },
+        },
+        None => None,
+    };
+
+    // As with java, Ctrl-\ prints what every thread is doing without stopping the program.
+    if launch.dump_on_quit {
+        let class_histogram = launch.class_histogram_on_quit;
+        vm.set_quit_handler(Box::new(move |vm: &mut Vm| {
+            let mut dump = format!("{}\n{}", threaddump::thread_dump(vm), threaddump::heap_summary(vm));
+            if class_histogram {
+                dump = format!("{}\n{}", dump, Histogram::of_heap(vm));
+            }
+            let stdout = &mut vm.console_mut().stdout;
+            let _ = stdout.write_all(dump.as_bytes()).and_then(|()| stdout.flush());
+        }));
+    }
+
+    // Panics and internal errors get a crash report instead of the usual message.
+    crash::install_panic_hook(&vm);
+    let args: Vec<&str> = launch.args.iter().map(String::as_str).collect();
+    let result = match panic::catch_unwind(AssertUnwindSafe(|| vm.run_main(&main_class, &args))) {
+        Ok(Err(ref err)) if err.is_internal() => return report_crash(&mut vm, &Crash::from_error(err)),
+        Ok(result) => result,
+        Err(_) => {
+            let crash = vm.take_panic().unwrap_or_else(|| Crash {description: "Rust panic".to_string(), backtrace: String::new()});
+            return report_crash(&mut vm, &crash);
+        },
+    };
+    let code = match result {
+        Ok(Outcome::Threw(throwable)) => {
+            eprintln!("Exception in thread \"main\" {}", throwable);
+            1
+        },
+        Ok(Outcome::LimitExceeded(violation)) => {
+            eprintln!("Error: {}", violation);
+            1
+        },
+        Ok(outcome) => outcome.exit_code(),
+        Err(VmError::ClassNotFound(ref name)) if *name == main_class => {
+            eprintln!("Error: Could not find or load main class {}", main_class.replace('/', "."));
+            1
+        },
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            1
+        },
+    };
+
+    if let Some(live) = launch.histogram {
+        if live {
+            vm.collect_garbage(GcCause::HeapInspection);
+        }
+        print!("{}", Histogram::of_heap(&vm));
+    }
+    if let Some(count) = launch.hot_methods {
+        eprint!("{}", counters::report(&counters::hottest_methods(&vm, count)));
+    }
+    if let (Some(file), Some(recording)) = (launch.record, recording) {
+        if let Err(err) = recording.stop(&mut vm) {
+            eprintln!("Error: Unable to write the recording to {}: {}", file, err);
+            return 1;
+        }
+    }
+    if let (Some(file), Some(profile)) = (launch.profile, vm.stop_profiler()) {
+        if let Err(err) = fs::write(&file, profile.to_string()) {
+            eprintln!("Error: Unable to write the profile to {}: {}", file, err);
+            return 1;
+        }
+    }
+    code
+}
+
+// Writes a crash report and says where it went, as HotSpot does when it fails, returning the
+// status of an abort.
+fn report_crash(vm: &mut Vm, crash: &Crash) -> i32 {
+    eprintln!("#\n# A fatal error has been detected by joyvm:\n#\n#  {}\n#", crash.description);
+    match crash::write_report(vm, crash) {
+        Ok(path) => eprintln!("# An error report file with more information is saved as:\n# {}\n#", path.display()),
+        Err(err) => eprintln!("# Unable to write an error report file: {}\n#", err),
+    }
+    134
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Launch, String> {
+        Launch::parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse_main_class_and_args() {
+        let launch = parse(&["com.example.Main", "one", "-cp"]).unwrap();
+        assert_eq!(Main::Class("com.example.Main".to_string()), launch.main);
+        assert_eq!(vec!["one", "-cp"], launch.args);
+        assert_eq!(None, launch.classpath);
+        assert_eq!(VmOptions::new(), launch.options);
+    }
+
+    #[test]
+    fn test_parse_java_options() {
+        let launch = parse(&[
+            "-classpath", "app.jar:lib", "-Da=b=c", "-D", "empty=", "-Xmx64m", "-Xss512k", "-ea", "-verbose:class", "-verbose:gc",
+            "Main",
+        ]).unwrap();
+        assert_eq!(Some("app.jar:lib".to_string()), launch.classpath);
+        let options = VmOptions::new()
+            .property("a", "b=c")
+            .property("empty", "")
+            .max_heap(64 << 20)
+            .stack_size(512 << 10)
+            .enable_assertions(true)
+            .verbose_class(true)
+            .verbose_gc(true);
+        assert_eq!(options, launch.options);
+        assert_eq!(Main::Class("Main".to_string()), launch.main);
+
+        assert_eq!(Some("a".to_string()), parse(&["-cp", "a", "Main"]).unwrap().classpath);
+        assert_eq!(VmOptions::new(), parse(&["-ea", "-da", "Main"]).unwrap().options);
+    }
+
+    #[test]
+    fn test_parse_jar() {
+        let launch = parse(&["-Xmx1g", "-jar", "app.jar", "-ea"]).unwrap();
+        assert_eq!(Main::Jar("app.jar".to_string()), launch.main);
+        assert_eq!(vec!["-ea"], launch.args);
+        assert_eq!(VmOptions::new().max_heap(1 << 30), launch.options);
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let launch = parse(&[
+            "--max-instructions", "1000", "--max-duration", "250", "--max-allocated-bytes", "4096", "--max-loaded-classes", "50",
+            "Main",
+        ]).unwrap();
+        assert_eq!(VmOptions::new().limits(Limits {
+            max_instructions: Some(1000),
+            max_duration: Some(Duration::from_millis(250)),
+            max_allocated_bytes: Some(4096),
+            max_loaded_classes: Some(50),
+        }), launch.options);
+    }
+
+    #[test]
+    fn test_parse_trace() {
+        let launch = parse(&["--trace", "com.example.*", "--trace", "Main::main", "Main"]).unwrap();
+        assert_eq!(Some(TraceFilter::new().pattern("com.example.*").pattern("Main::main")), launch.trace);
+        assert_eq!(None, launch.trace_file);
+
+        let launch = parse(&["--trace-file", "trace.txt", "Main"]).unwrap();
+        assert_eq!(Some(TraceFilter::new()), launch.trace);
+        assert_eq!(Some("trace.txt".to_string()), launch.trace_file);
+        assert_eq!(None, parse(&["Main"]).unwrap().trace);
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        let launch = parse(&["--profile", "out.folded", "--profile-interval", "5", "Main"]).unwrap();
+        assert_eq!(Some("out.folded".to_string()), launch.profile);
+        assert_eq!(Duration::from_millis(5), launch.profile_interval);
+        assert_eq!(profiler::DEFAULT_INTERVAL, parse(&["Main"]).unwrap().profile_interval);
+    }
+
+    #[test]
+    fn test_parse_gc_log() {
+        let launch = parse(&["-Xlog:gc*:file=gc.log", "Main"]).unwrap();
+        assert_eq!(Some(GcLogOptions {detailed: true, file: Some("gc.log".into())}), launch.gc_log);
+        assert_eq!(Some(GcLogOptions::default()), parse(&["-Xlog:gc", "Main"]).unwrap().gc_log);
+        assert_eq!(Err("Unsupported logging option -Xlog:safepoint".to_string()), parse(&["-Xlog:safepoint", "Main"]).map(|_| ()));
+    }
+
+    #[test]
+    fn test_parse_record() {
+        assert_eq!(Some("run.rec".to_string()), parse(&["--record", "run.rec", "Main"]).unwrap().record);
+        assert_eq!(None, parse(&["Main"]).unwrap().record);
+    }
+
+    #[test]
+    fn test_parse_hot_methods() {
+        assert_eq!(Some(20), parse(&["--hot-methods", "20", "Main"]).unwrap().hot_methods);
+        assert_eq!(None, parse(&["Main"]).unwrap().hot_methods);
+        assert_eq!(Err("--hot-methods needs a number; got all".to_string()), parse(&["--hot-methods", "all", "Main"]).map(|_| ()));
+    }
+
+    #[test]
+    fn test_parse_quit_options() {
+        let launch = parse(&["Main"]).unwrap();
+        assert!(launch.dump_on_quit && !launch.class_histogram_on_quit);
+        let launch = parse(&["-Xrs", "-XX:+PrintClassHistogram", "Main"]).unwrap();
+        assert!(!launch.dump_on_quit && launch.class_histogram_on_quit);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Err("No main class given".to_string()), parse(&[]).map(|_| ()));
+        assert_eq!(Err("-cp needs a value".to_string()), parse(&["-cp"]).map(|_| ()));
+        assert_eq!(Err("-jar needs a value".to_string()), parse(&["-jar"]).map(|_| ()));
+        assert_eq!(Err("Unknown option -fast".to_string()), parse(&["-fast", "Main"]).map(|_| ()));
+        assert_eq!(Err("Property flag has no value".to_string()), parse(&["-Dflag", "Main"]).map(|_| ()));
+        assert_eq!(Err("Invalid maximum heap size: -Xmx12x".to_string()), parse(&["-Xmx12x", "Main"]).map(|_| ()));
+        assert_eq!(Err("Invalid thread stack size: -Xss".to_string()), parse(&["-Xss", "Main"]).map(|_| ()));
+        assert_eq!(Err("--max-instructions needs a number; got lots".to_string()), parse(&["--max-instructions", "lots", "Main"]).map(|_| ()));
+    }
+}
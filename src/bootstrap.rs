@@ -0,0 +1,145 @@
+// Minimal implementations of the platform classes that joyvm needs in order to run anything at
+// all. The Java sources live alongside the compiled classes under /bootstrap; after changing
+// them, rebuild with bootstrap/build.sh.
+use crate::classpath::{ClassSource, ClasspathError};
+
+macro_rules! bootstrap_classes {
+    ($($name:literal),*) => {
+        pub const CLASSES: &[(&str, &[u8])] = &[
+            $(($name, include_bytes!(concat!("../bootstrap/", $name, ".class")))),*
+        ];
+    };
+}
+
+bootstrap_classes!(
+    "java/lang/Object",
+    "java/lang/String",
+    "java/lang/Class",
+    "java/lang/Package",
+    "java/lang/Cloneable",
+    "java/lang/System",
+    "java/lang/Runtime",
+    "java/lang/ApplicationShutdownHooks",
+    "java/lang/Runnable",
+    "java/lang/Thread",
+    "java/lang/Thread$UncaughtExceptionHandler",
+    "java/lang/Number",
+    "java/lang/Boolean",
+    "java/lang/Character",
+    "java/lang/Character$CharacterCache",
+    "java/lang/Byte",
+    "java/lang/Byte$ByteCache",
+    "java/lang/Short",
+    "java/lang/Short$ShortCache",
+    "java/lang/Integer",
+    "java/lang/Integer$IntegerCache",
+    "java/lang/Long",
+    "java/lang/Long$LongCache",
+    "java/lang/Float",
+    "java/lang/Double",
+    "java/lang/Void",
+    "java/lang/Throwable",
+    "java/lang/StackTraceElement",
+    "java/lang/Exception",
+    "java/lang/CloneNotSupportedException",
+    "java/lang/InterruptedException",
+    "java/lang/RuntimeException",
+    "java/lang/Error",
+    "java/lang/AssertionError",
+    "java/lang/NullPointerException",
+    "java/lang/ClassCastException",
+    "java/lang/ArithmeticException",
+    "java/lang/IndexOutOfBoundsException",
+    "java/lang/ArrayIndexOutOfBoundsException",
+    "java/lang/StringIndexOutOfBoundsException",
+    "java/lang/NegativeArraySizeException",
+    "java/lang/ArrayStoreException",
+    "java/lang/IllegalArgumentException",
+    "java/lang/IllegalStateException",
+    "java/lang/UnsupportedOperationException",
+    "java/lang/IllegalMonitorStateException",
+    "java/lang/IllegalThreadStateException",
+    "java/lang/SecurityException",
+    "java/lang/VirtualMachineError",
+    "java/lang/StackOverflowError",
+    "java/lang/OutOfMemoryError",
+    "java/lang/LinkageError",
+    "java/lang/ClassFormatError",
+    "java/lang/NoClassDefFoundError",
+    "java/lang/IncompatibleClassChangeError",
+    "java/lang/IllegalAccessError",
+    "java/lang/NoSuchFieldError",
+    "java/lang/NoSuchMethodError",
+    "java/lang/AbstractMethodError",
+    "java/lang/ClassCircularityError",
+    "java/lang/VerifyError",
+    "java/lang/UnsatisfiedLinkError",
+    "java/lang/ReflectiveOperationException",
+    "java/lang/ClassNotFoundException",
+    "java/lang/IllegalAccessException",
+    "java/lang/InstantiationException",
+    "java/lang/NoSuchFieldException",
+    "java/lang/NoSuchMethodException",
+    "java/lang/ClassLoader",
+    "java/lang/invoke/MethodHandle",
+    "java/lang/invoke/MethodHandles",
+    "java/lang/invoke/MethodHandles$Lookup",
+    "java/lang/invoke/MethodType",
+    "java/lang/invoke/WrongMethodTypeException",
+    "java/lang/reflect/AccessibleObject",
+    "java/lang/reflect/Field",
+    "java/lang/reflect/Method",
+    "java/lang/reflect/Constructor",
+    "java/lang/reflect/InvocationTargetException",
+    "java/io/Serializable",
+    "java/io/IOException",
+    "java/io/FileNotFoundException",
+    "java/io/FileDescriptor",
+    "java/io/InputStream",
+    "java/io/OutputStream",
+    "java/io/FileInputStream",
+    "java/io/FileOutputStream",
+    "java/io/PrintStream",
+    "java/nio/ByteBuffer",
+    "java/nio/DirectByteBuffer",
+    "java/util/Random",
+    "java/util/zip/Checksum",
+    "java/util/zip/CRC32",
+    "java/util/zip/Adler32",
+    "java/util/zip/DataFormatException",
+    "java/util/zip/Inflater",
+    "java/util/zip/Deflater",
+    "java/util/concurrent/locks/LockSupport",
+    "java/util/concurrent/locks/Lock",
+    "java/util/concurrent/locks/AbstractOwnableSynchronizer",
+    "java/util/concurrent/locks/AbstractQueuedSynchronizer",
+    "java/util/concurrent/locks/AbstractQueuedSynchronizer$Node",
+    "java/util/concurrent/locks/ReentrantLock",
+    "java/util/concurrent/locks/ReentrantLock$Sync",
+    "sun/misc/Unsafe"
+);
+
+// The bootstrap loader's classpath: the classes above, which are only parsed when the bootstrap
+// loader is first asked for them.
+pub struct BootstrapSource;
+
+impl ClassSource for BootstrapSource {
+    fn find_resource(&self, path: &str) -> Result<Option<Vec<u8>>, ClasspathError> {
+        Ok(path.strip_suffix(".class")
+            .and_then(|name| CLASSES.iter().find(|&&(class_name, _)| class_name == name))
+            .map(|&(_, bytes)| bytes.to_vec()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classloader;
+
+    #[test]
+    fn test_bootstrap_classes_have_their_listed_names() {
+        for &(name, bytes) in CLASSES {
+            assert_eq!(name, classloader::load_class(bytes).unwrap().name().unwrap());
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use crate::opcodes::*;
+use std::{error, fmt};
+
+// An instruction decoded from a method's bytecode, for analyses that need to know the shape of a
+// method rather than run it. The interpreter decodes methods separately, into handlers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub pc: usize,
+    pub opcode: u8, // For wide instructions, this is the opcode they modify.
+    pub length: usize,
+    pub operand: Operand,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operand {
+    None,
+    Local(u16),
+    Increment{local: u16, delta: i16},
+    Int(i32), // The value pushed by bipush and sipush, or the array type of newarray.
+    Constant(u16), // A constant pool index.
+    Branch(usize), // An absolute branch target.
+    Switch{default: usize, targets: Vec<(i32, usize)>},
+    MultiArray{class: u16, dimensions: u8},
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BytecodeError {
+    Truncated(usize), // The instruction at this pc runs off the end of the code.
+    InvalidOpcode{pc: usize, opcode: u8},
+    InvalidBranch{pc: usize, target: i64},
+}
+
+impl fmt::Display for BytecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BytecodeError::Truncated(pc) => write!(f, "Instruction at pc {} runs off the end of the code", pc),
+            BytecodeError::InvalidOpcode{pc, opcode} => write!(f, "Invalid opcode 0x{:02x} at pc {}", opcode, pc),
+            BytecodeError::InvalidBranch{pc, target} => write!(f, "Branch from pc {} to {} leaves the method", pc, target),
+        }
+    }
+}
+
+impl error::Error for BytecodeError {
+    fn description(&self) -> &str {
+        match *self {
+            BytecodeError::Truncated(_) => "Truncated instruction",
+            BytecodeError::InvalidOpcode{..} => "Invalid opcode",
+            BytecodeError::InvalidBranch{..} => "Branch out of method",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        None
+    }
+}
+
+// Decodes every instruction in the code, in order.
+pub fn decode(code: &[u8]) -> Result<Vec<Instruction>, BytecodeError> {
+    let mut instructions = vec![];
+    let mut pc = 0;
+    while pc < code.len() {
+        let instruction = decode_at(code, pc)?;
+        pc += instruction.length;
+        instructions.push(instruction);
+    }
+    Ok(instructions)
+}
+
+pub fn decode_at(code: &[u8], pc: usize) -> Result<Instruction, BytecodeError> {
+    let truncated = || BytecodeError::Truncated(pc);
+    let u8_at = |offset: usize| code.get(pc + offset).copied().ok_or_else(truncated);
+    let u16_at = |offset: usize| Ok(u16::from_be_bytes([u8_at(offset)?, u8_at(offset + 1)?]));
+    let i32_at = |offset: usize| Ok(i32::from_be_bytes([u8_at(offset)?, u8_at(offset + 1)?, u8_at(offset + 2)?, u8_at(offset + 3)?]));
+    let target = |offset: i32| {
+        let target = pc as i64 + offset as i64;
+        if target < 0 || target >= code.len() as i64 {
+            return Err(BytecodeError::InvalidBranch{pc, target});
+        }
+        Ok(target as usize)
+    };
+
+    let opcode = u8_at(0)?;
+    let (opcode, operand, length) = match opcode {
+        BIPUSH => (opcode, Operand::Int(u8_at(1)? as i8 as i32), 2),
+        SIPUSH => (opcode, Operand::Int(u16_at(1)? as i16 as i32), 3),
+        NEWARRAY => (opcode, Operand::Int(u8_at(1)? as i32), 2),
+        LDC => (opcode, Operand::Constant(u8_at(1)? as u16), 2),
+        ILOAD..=ALOAD | ISTORE..=ASTORE | RET => (opcode, Operand::Local(u8_at(1)? as u16), 2),
+        ILOAD_0..=ALOAD_3 => (ILOAD + (opcode - ILOAD_0) / 4, Operand::Local(((opcode - ILOAD_0) % 4) as u16), 1),
+        ISTORE_0..=ASTORE_3 => (ISTORE + (opcode - ISTORE_0) / 4, Operand::Local(((opcode - ISTORE_0) % 4) as u16), 1),
+        IINC => (opcode, Operand::Increment{local: u8_at(1)? as u16, delta: u8_at(2)? as i8 as i16}, 3),
+        LDC_W | LDC2_W | GETSTATIC..=INVOKESTATIC | NEW | ANEWARRAY | CHECKCAST | INSTANCEOF => (opcode, Operand::Constant(u16_at(1)?), 3),
+        INVOKEINTERFACE | INVOKEDYNAMIC => {
+            // The count and the zero bytes after the index aren't needed, but must be there.
+            u8_at(3)?;
+            u8_at(4)?;
+            (opcode, Operand::Constant(u16_at(1)?), 5)
+        },
+        MULTIANEWARRAY => (opcode, Operand::MultiArray{class: u16_at(1)?, dimensions: u8_at(3)?}, 4),
+        IFEQ..=JSR | IFNULL | IFNONNULL => (opcode, Operand::Branch(target(u16_at(1)? as i16 as i32)?), 3),
+        GOTO_W | JSR_W => (opcode, Operand::Branch(target(i32_at(1)?)?), 5),
+        WIDE => match u8_at(1)? {
+            modified @ (ILOAD..=ALOAD | ISTORE..=ASTORE | RET) => (modified, Operand::Local(u16_at(2)?), 4),
+            IINC => (IINC, Operand::Increment{local: u16_at(2)?, delta: u16_at(4)? as i16}, 6),
+            modified => return Err(BytecodeError::InvalidOpcode{pc: pc + 1, opcode: modified}),
+        },
+        TABLESWITCH | LOOKUPSWITCH => {
+            let start = ((pc + 4) & !3) - pc;
+            let default = target(i32_at(start)?)?;
+            let mut targets = vec![];
+            let end = if opcode == TABLESWITCH {
+                let low = i32_at(start + 4)?;
+                let high = i32_at(start + 8)?;
+                let count = (high as i64 - low as i64 + 1).max(0) as usize;
+                if pc + start + 12 + 4 * count > code.len() {
+                    return Err(truncated());
+                }
+                for i in 0..count {
+                    targets.push((low.wrapping_add(i as i32), target(i32_at(start + 12 + 4 * i)?)?));
+                }
+                start + 12 + 4 * count
+            } else {
+                let count = i32_at(start + 4)?.max(0) as usize;
+                if pc + start + 8 + 8 * count > code.len() {
+                    return Err(truncated());
+                }
+                for i in 0..count {
+                    targets.push((i32_at(start + 8 + 8 * i)?, target(i32_at(start + 12 + 8 * i)?)?));
+                }
+                start + 8 + 8 * count
+            };
+            (opcode, Operand::Switch{default, targets}, end)
+        },
+        _ if mnemonic(opcode).is_none() => return Err(BytecodeError::InvalidOpcode{pc, opcode}),
+        _ => (opcode, Operand::None, 1),
+    };
+
+    Ok(Instruction {pc, opcode, length, operand})
+}
+
+impl Instruction {
+    // The pcs that execution can continue at within the method, not counting exception handlers
+    // or returns from subroutines, which depend on the rest of the method.
+    pub fn successors(&self) -> Vec<usize> {
+        let next = self.pc + self.length;
+        match (self.opcode, &self.operand) {
+            (GOTO | GOTO_W, &Operand::Branch(target)) => vec![target],
+            // A subroutine returns to the instruction after the jsr.
+            (JSR | JSR_W, &Operand::Branch(target)) => vec![target, next],
+            (_, &Operand::Branch(target)) => vec![target, next],
+            (_, &Operand::Switch{default, ref targets}) => {
+                let mut successors = vec![default];
+                successors.extend(targets.iter().map(|&(_, target)| target));
+                successors
+            },
+            (IRETURN..=RETURN | ATHROW | RET, _) => vec![],
+            _ => vec![next],
+        }
+    }
+
+    // The local variable slots the instruction reads.
+    pub fn reads_locals(&self) -> Vec<usize> {
+        match (self.opcode, &self.operand) {
+            (LLOAD | DLOAD, &Operand::Local(index)) => vec![index as usize, index as usize + 1],
+            (ILOAD..=ALOAD | RET, &Operand::Local(index)) => vec![index as usize],
+            (IINC, &Operand::Increment{local, ..}) => vec![local as usize],
+            _ => vec![],
+        }
+    }
+
+    // The local variable slots the instruction overwrites.
+    pub fn writes_locals(&self) -> Vec<usize> {
+        match (self.opcode, &self.operand) {
+            (LSTORE | DSTORE, &Operand::Local(index)) => vec![index as usize, index as usize + 1],
+            (ISTORE..=ASTORE, &Operand::Local(index)) => vec![index as usize],
+            (IINC, &Operand::Increment{local, ..}) => vec![local as usize],
+            _ => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_short_forms() {
+        let instructions = decode(&[ALOAD_2, LSTORE_1, RETURN]).unwrap();
+        assert_eq!(Instruction {pc: 0, opcode: ALOAD, length: 1, operand: Operand::Local(2)}, instructions[0]);
+        assert_eq!(Instruction {pc: 1, opcode: LSTORE, length: 1, operand: Operand::Local(1)}, instructions[1]);
+        assert_eq!(vec![1, 2], instructions[1].writes_locals());
+    }
+
+    #[test]
+    fn test_decode_wide() {
+        let instructions = decode(&[WIDE, IINC, 0x01, 0x00, 0xff, 0xfe, WIDE, ALOAD, 0x01, 0x02]).unwrap();
+        assert_eq!(Operand::Increment{local: 256, delta: -2}, instructions[0].operand);
+        assert_eq!(Instruction {pc: 6, opcode: ALOAD, length: 4, operand: Operand::Local(258)}, instructions[1]);
+    }
+
+    #[test]
+    fn test_decode_branches() {
+        let instructions = decode(&[IFEQ, 0x00, 0x04, NOP, GOTO, 0xff, 0xfc]).unwrap();
+        assert_eq!(vec![4, 3], instructions[0].successors());
+        assert_eq!(vec![0], instructions[2].successors());
+        assert_eq!(Err(BytecodeError::InvalidBranch{pc: 0, target: 16}), decode(&[GOTO, 0x00, 0x10]));
+    }
+
+    #[test]
+    fn test_decode_tableswitch() {
+        let code = [NOP, TABLESWITCH, 0, 0, 0, 0, 0, 16, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 15, 0, 0, 0, 15, RETURN];
+        let instructions = decode(&code).unwrap();
+        assert_eq!(23, instructions[1].length);
+        assert_eq!(Operand::Switch{default: 17, targets: vec![(1, 16), (2, 16)]}, instructions[1].operand);
+    }
+
+    #[test]
+    fn test_decode_lookupswitch() {
+        let code = [LOOKUPSWITCH, 0, 0, 0, 0, 0, 0, 20, 0, 0, 0, 1, 0, 0, 0, 5, 0, 0, 0, 20, RETURN];
+        let instructions = decode(&code).unwrap();
+        assert_eq!(Operand::Switch{default: 20, targets: vec![(5, 20)]}, instructions[0].operand);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(Err(BytecodeError::Truncated(0)), decode(&[SIPUSH, 0x00]));
+        assert_eq!(Err(BytecodeError::Truncated(0)), decode(&[INVOKEINTERFACE, 0x00, 0x01, 0x01]));
+        assert_eq!(Err(BytecodeError::Truncated(0)), decode(&[INVOKEDYNAMIC, 0x00, 0x01]));
+        assert_eq!(Err(BytecodeError::InvalidOpcode{pc: 0, opcode: 0xca}), decode(&[0xca]));
+    }
+}
@@ -0,0 +1,152 @@
+use crate::descriptor::{DescriptorError, FieldType};
+use crate::runtime::Value;
+use crate::vm::{Vm, VmError};
+
+// Splits a method named with its descriptor, as in "max(II)I", into the name and descriptor, and
+// parses the arguments for it.
+pub fn parse_call<'a>(vm: &mut Vm, method: &'a str, args: &[&str]) -> Result<(&'a str, &'a str, Vec<Value>), VmError> {
+    let (name, descriptor) = method.find('(').map(|paren| method.split_at(paren))
+        .ok_or_else(|| DescriptorError::MissingParameterList(method.to_string()))?;
+    let parsed = vm.method_descriptor(descriptor)?;
+    if parsed.parameters.len() != args.len() {
+        return Err(VmError::InvalidArgument(format!("{} takes {} arguments, not {}", method, parsed.parameters.len(), args.len())));
+    }
+    let args = parsed.parameters.iter().zip(args)
+        .map(|(parameter, arg)| parse_argument(vm, parameter, arg))
+        .collect::<Result<Vec<Value>, VmError>>()?;
+    Ok((name, descriptor, args))
+}
+
+// Makes a value of the type from an argument as written on a command line: numbers as Java
+// literals are written, without suffixes; booleans as true or false; chars as the character
+// itself; strings as they are. Any reference can be null. Other references can't be given.
+pub fn parse_argument(vm: &mut Vm, field_type: &FieldType, argument: &str) -> Result<Value, VmError> {
+    let invalid = || VmError::InvalidArgument(format!("{} isn't a valid {}", argument, type_name(field_type)));
+    let value = match *field_type {
+        FieldType::Boolean => match argument {
+            "true" => Value::Int(1),
+            "false" => Value::Int(0),
+            _ => return Err(invalid()),
+        },
+        FieldType::Byte => Value::Int(argument.parse::<i8>().map_err(|_| invalid())? as i32),
+        FieldType::Short => Value::Int(argument.parse::<i16>().map_err(|_| invalid())? as i32),
+        FieldType::Char => {
+            let mut units = argument.encode_utf16();
+            match (units.next(), units.next()) {
+                (Some(unit), None) => Value::Int(unit as i32),
+                _ => return Err(invalid()),
+            }
+        },
+        FieldType::Int => Value::Int(argument.parse().map_err(|_| invalid())?),
+        FieldType::Long => Value::Long(argument.parse().map_err(|_| invalid())?),
+        FieldType::Float => Value::Float(argument.parse().map_err(|_| invalid())?),
+        FieldType::Double => Value::Double(argument.parse().map_err(|_| invalid())?),
+        _ if argument == "null" => Value::null(),
+        FieldType::Object(ref class) if class == "java/lang/String" => Value::Reference(Some(vm.new_string(argument)?)),
+        FieldType::Object(_) | FieldType::Array(_) => return Err(invalid()),
+    };
+    Ok(value)
+}
+
+// The inverse of parse_argument, for printing what a method returned. Objects other than strings
+// are shown as Object.toString shows them, by class and identity hash.
+pub fn format_result(vm: &mut Vm, return_type: &FieldType, value: &Value) -> Result<String, VmError> {
+    let formatted = match (return_type, value) {
+        (&FieldType::Boolean, &Value::Int(value)) => (value != 0).to_string(),
+        (&FieldType::Char, &Value::Int(value)) => char::decode_utf16([value as u16]).map(|c| c.unwrap_or('\u{fffd}')).collect(),
+        (_, &Value::Int(value)) => value.to_string(),
+        (_, &Value::Long(value)) => value.to_string(),
+        (_, &Value::Float(value)) => format_floating(value as f64, format!("{:?}", value)),
+        (_, &Value::Double(value)) => format_floating(value, format!("{:?}", value)),
+        (_, &Value::Reference(None)) => "null".to_string(),
+        (_, &Value::Reference(Some(ref object))) if object.class().name == "java/lang/String" => vm.read_string(object)?,
+        (_, &Value::Reference(Some(ref object))) => format!("{}@{:x}", object.class().java_name(), vm.identity_hash(object)),
+        (_, &Value::ReturnAddress(_)) => return Err(VmError::InvalidBytecode("A method returned a return address".to_string())),
+    };
+    Ok(formatted)
+}
+
+// Rust's debug format matches Java's for ordinary values, as in 1.0 and 0.1, but not the rest.
+fn format_floating(value: f64, formatted: String) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else {
+        formatted
+    }
+}
+
+fn type_name(field_type: &FieldType) -> String {
+    match *field_type {
+        FieldType::Boolean => "boolean".to_string(),
+        FieldType::Byte => "byte".to_string(),
+        FieldType::Char => "char".to_string(),
+        FieldType::Short => "short".to_string(),
+        FieldType::Int => "int".to_string(),
+        FieldType::Long => "long".to_string(),
+        FieldType::Float => "float".to_string(),
+        FieldType::Double => "double".to_string(),
+        FieldType::Object(ref class) => class.replace('/', "."),
+        FieldType::Array(ref component) => format!("{}[]", type_name(component)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+
+    #[test]
+    fn test_parse_arguments() {
+        let mut vm = Vm::new();
+        let mut parse = |field_type: &str, argument: &str| parse_argument(&mut vm, &FieldType::parse(field_type).unwrap(), argument);
+        assert_eq!(Value::Int(-3), parse("I", "-3").unwrap());
+        assert_eq!(Value::Long(1 << 40), parse("J", "1099511627776").unwrap());
+        assert_eq!(Value::Int(1), parse("Z", "true").unwrap());
+        assert_eq!(Value::Int('é' as i32), parse("C", "é").unwrap());
+        assert_eq!(Value::Double(0.5), parse("D", "0.5").unwrap());
+        assert_eq!(Value::null(), parse("[I", "null").unwrap());
+        assert_eq!("byte", match parse("B", "128") {
+            Err(VmError::InvalidArgument(msg)) => msg.rsplit(' ').next().unwrap().to_string(),
+            other => panic!("{:?}", other.map(|_| ())),
+        });
+        assert!(parse("C", "ab").is_err());
+        assert!(parse("Z", "1").is_err());
+        assert!(parse("Ljava/util/List;", "[]").is_err());
+
+        let string = parse("Ljava/lang/String;", "hello").unwrap();
+        match string {
+            Value::Reference(Some(ref string)) => assert_eq!("hello", vm.read_string(string).unwrap()),
+            _ => panic!("Not a string: {:?}", string),
+        }
+    }
+
+    #[test]
+    fn test_call_static() {
+        let mut vm = vm_with(&[fixture!("Debuggee")]);
+        assert_eq!(Ok(Some(Value::Int(49))), vm.call_static("Debuggee", "square(I)I", &["7"]));
+        assert_eq!(Ok(Some(Value::Int(14))), vm.call_static("Debuggee", "sumOfSquares(I)I", &["3"]));
+        match vm.call_static("Debuggee", "square(I)I", &[]) {
+            Err(VmError::InvalidArgument(msg)) => assert_eq!("square(I)I takes 1 arguments, not 0", msg),
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(vm.call_static("Debuggee", "square", &["7"]), Err(VmError::Descriptor(_))));
+        assert!(matches!(vm.call_static("Debuggee", "square(J)I", &["7"]), Err(VmError::MethodNotFound{..})));
+    }
+
+    #[test]
+    fn test_format_results() {
+        let mut vm = Vm::new();
+        let mut format = |return_type: &str, value: Value| format_result(&mut vm, &FieldType::parse(return_type).unwrap(), &value).unwrap();
+        assert_eq!("true", format("Z", Value::Int(1)));
+        assert_eq!("x", format("C", Value::Int('x' as i32)));
+        assert_eq!("-7", format("S", Value::Int(-7)));
+        assert_eq!("1.0", format("D", Value::Double(1.0)));
+        assert_eq!("-Infinity", format("F", Value::Float(f32::NEG_INFINITY)));
+        assert_eq!("NaN", format("D", Value::Double(f64::NAN)));
+        assert_eq!("null", format("Ljava/lang/Object;", Value::null()));
+        let string = vm.new_string("text").unwrap();
+        assert_eq!("text", format_result(&mut vm, &FieldType::Object("java/lang/String".to_string()), &Value::Reference(Some(string))).unwrap());
+    }
+}
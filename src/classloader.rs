@@ -3,20 +3,35 @@ extern crate bytes;
 use crate::classes::*;
 use std::{error, fmt, str};
 
-// Bytes.into_buf() is used later, but Rust wrongly claims this import is unused
-#[allow(unused_imports)]
-use bytes::IntoBuf;
+use bytes::{Buf, IntoBuf};
+
+// Parses a complete class file, as laid out in chapter 4 of the JVM spec.
+pub fn load_class(bytes: &[u8]) -> Result<Class, ClassLoaderError> {
+    let mut data = bytes::Bytes::from(bytes).into_buf();
+    let class = Class::deserialize(&mut data)?;
+    if data.has_remaining() {
+        return Err(ClassLoaderError::TrailingData(data.remaining()));
+    }
+
+    Ok(class)
+}
 
 // Trait for entities that can be unambiguously deserialized without reference to
 // other sibling or parent entities.
 trait Deserialize: Sized {
-    fn deserialize(data: &mut bytes::Buf) -> Result<Self, ClassLoaderError>;
+    // The fewest bytes that any encoding of the entity takes up, which bounds how many of them the
+    // rest of a class file could hold.
+    const MIN_SIZE: usize = 1;
+
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<Self, ClassLoaderError>;
 }
 
 // Trait for entities that require information about the ConstantPool to be
 // deserialized.
 trait DeserializeWithConstants: Sized {
-    fn deserialize(data: &mut bytes::Buf, constants: &Vec<Constant>) -> Result<Self, ClassLoaderError>;
+    const MIN_SIZE: usize = 1;
+
+    fn deserialize(data: &mut dyn bytes::Buf, constants: &[Constant]) -> Result<Self, ClassLoaderError>;
 }
 
 macro_rules! require {
@@ -33,8 +48,228 @@ macro_rules! require {
     }};
 }
 
+impl Deserialize for Class {
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<Class, ClassLoaderError> {
+        require!(data has 4 bytes for "magic number");
+        let magic = data.get_u32_be();
+        if magic != 0xcafe_babe {
+            return Err(ClassLoaderError::InvalidMagic(magic));
+        }
+
+        require!(data has 4 bytes for "class file version");
+        let minor_version = data.get_u16_be();
+        let major_version = data.get_u16_be();
+
+        require!(data has 2 bytes for "constant pool count");
+        let constant_pool_count = data.get_u16_be();
+        let constants = deserialize_constant_pool(constant_pool_count, data)?;
+
+        let flags = ClassFlags::deserialize(data)?;
+        let this_class = ConstantIndex::deserialize(data)?;
+        let super_class = ConstantIndex::deserialize(data)?;
+
+        require!(data has 2 bytes for "interface count");
+        let interface_count = data.get_u16_be() as usize;
+        let interfaces = deserialize_multiple(interface_count, data)?;
+
+        require!(data has 2 bytes for "field count");
+        let field_count = data.get_u16_be() as usize;
+        let fields = deserialize_multiple_with_constants(field_count, data, &constants)?;
+
+        require!(data has 2 bytes for "method count");
+        let method_count = data.get_u16_be() as usize;
+        let methods = deserialize_multiple_with_constants(method_count, data, &constants)?;
+
+        require!(data has 2 bytes for "class attribute count");
+        let attribute_count = data.get_u16_be() as usize;
+        let attributes = deserialize_attributes(attribute_count, data, &constants)?;
+
+        Ok(Class {
+            minor_version,
+            major_version,
+            constants,
+            flags,
+            this_class,
+            super_class,
+            interfaces,
+            fields,
+            methods,
+            attributes,
+        })
+    }
+}
+
+// The constant pool count is one greater than the number of entries, and Long and Double
+// constants take up two entries each. We pad the latter with Dummy constants so that the
+// indices of our Vec line up with those used in the rest of the class file.
+fn deserialize_constant_pool(constant_pool_count: u16, data: &mut dyn bytes::Buf) -> Result<Vec<Constant>, ClassLoaderError> {
+    let mut constants = presized((constant_pool_count as usize).saturating_sub(1), Constant::MIN_SIZE, data);
+    while constants.len() + 1 < constant_pool_count as usize {
+        let constant = Constant::deserialize(data)?;
+        let is_double_width = matches!(constant, Constant::Long(_) | Constant::Double(_));
+        constants.push(constant);
+        if is_double_width {
+            constants.push(Constant::Dummy);
+        }
+    }
+
+    if constants.len() + 1 > constant_pool_count as usize {
+        return Err(ClassLoaderError::Misc("Double-width constant overruns the end of the constant pool".to_string()));
+    }
+
+    Ok(constants)
+}
+
+// Parses just the start of a class file: its version, flags, name, superclass and interfaces.
+// The constant pool is stepped over rather than parsed, apart from the names those refer to, and
+// the members and attributes that follow aren't read at all, so this is much quicker than
+// load_class for scanning the classes of large JARs. It's also more forgiving, since nothing
+// after the interfaces is checked.
+pub fn load_class_header(bytes: &[u8]) -> Result<ClassHeader, ClassLoaderError> {
+    let mut data = bytes::Bytes::from(bytes).into_buf();
+    require!(data has 4 bytes for "magic number");
+    let magic = data.get_u32_be();
+    if magic != 0xcafe_babe {
+        return Err(ClassLoaderError::InvalidMagic(magic));
+    }
+
+    require!(data has 4 bytes for "class file version");
+    let minor_version = data.get_u16_be();
+    let major_version = data.get_u16_be();
+
+    require!(data has 2 bytes for "constant pool count");
+    let constant_pool_count = data.get_u16_be();
+    let constants = skim_constant_pool(constant_pool_count, bytes, &mut data)?;
+
+    let flags = ClassFlags::deserialize(&mut data)?;
+    let this_class = ConstantIndex::deserialize(&mut data)?;
+    let super_class = ConstantIndex::deserialize(&mut data)?;
+
+    require!(data has 2 bytes for "interface count");
+    let interface_count = data.get_u16_be() as usize;
+    let interfaces: Vec<ConstantIndex> = deserialize_multiple(interface_count, &mut data)?;
+
+    let class_name = |index: &ConstantIndex| -> Result<String, ClassLoaderError> {
+        let name = match *skimmed(&constants, index)? {
+            SkimmedConstant::ClassRef(ref name) => name,
+            _ => return Err(ConstantLookupError::UnexpectedType(index.0).into()),
+        };
+        match *skimmed(&constants, name)? {
+            SkimmedConstant::Utf8(start, length) => decode_modified_utf8(bytes[start..start + length].to_vec()),
+            _ => Err(ConstantLookupError::UnexpectedType(name.0).into()),
+        }
+    };
+    Ok(ClassHeader {
+        minor_version,
+        major_version,
+        flags,
+        name: class_name(&this_class)?,
+        super_class: if super_class.0 == 0 { None } else { Some(class_name(&super_class)?) },
+        interfaces: interfaces.iter().map(class_name).collect::<Result<_, _>>()?,
+    })
+}
+
+// A constant as load_class_header sees it: Utf8 constants by where their contents are in the
+// class file, class references, and padding after longs and doubles. The rest aren't needed.
+enum SkimmedConstant {
+    Utf8(usize, usize), // The offset and length of the contents.
+    ClassRef(ConstantIndex),
+    Dummy,
+    Other,
+}
+
+// Steps over the constant pool, laid out as deserialize_constant_pool expects, noting only what
+// load_class_header needs. The bytes are those of the whole class file, which the data reads.
+fn skim_constant_pool(constant_pool_count: u16, bytes: &[u8], data: &mut dyn bytes::Buf) -> Result<Vec<SkimmedConstant>, ClassLoaderError> {
+    let mut constants = presized((constant_pool_count as usize).saturating_sub(1), Constant::MIN_SIZE, data);
+    while constants.len() + 1 < constant_pool_count as usize {
+        require!(data has 1 byte for "constant tag");
+        let tag = data.get_u8();
+        let length = match tag {
+            1 => {
+                require!(data has 2 bytes for "length field of Utf8 constant");
+                let length = data.get_u16_be() as usize;
+                require!(data has length bytes for "Utf8 constant");
+                constants.push(SkimmedConstant::Utf8(bytes.len() - data.remaining(), length));
+                data.advance(length);
+                continue;
+            },
+            7 => {
+                constants.push(SkimmedConstant::ClassRef(ConstantIndex::deserialize(data)?));
+                continue;
+            },
+            5 | 6 => {
+                require!(data has 8 bytes for "double-width constant");
+                data.advance(8);
+                constants.push(SkimmedConstant::Other);
+                constants.push(SkimmedConstant::Dummy);
+                continue;
+            },
+            8 | 16 | 19 | 20 => 2,
+            15 => 3,
+            3 | 4 | 9..=12 | 17 | 18 => 4,
+            _ => return Err(ClassLoaderError::InvalidConstantType(tag)),
+        };
+        require!(data has length bytes for "constant");
+        data.advance(length);
+        constants.push(SkimmedConstant::Other);
+    }
+
+    if constants.len() + 1 > constant_pool_count as usize {
+        return Err(ClassLoaderError::Misc("Double-width constant overruns the end of the constant pool".to_string()));
+    }
+
+    Ok(constants)
+}
+
+// Looks up a skimmed constant as ConstantIndex::lookup does a parsed one.
+fn skimmed<'a>(constants: &'a [SkimmedConstant], index: &ConstantIndex) -> Result<&'a SkimmedConstant, ConstantLookupError> {
+    if index.0 == 0 {
+        return Err(ConstantLookupError::ZeroIndex);
+    }
+    match constants.get(index.0 as usize - 1) {
+        Some(SkimmedConstant::Dummy) => Err(ConstantLookupError::IndexInsideDoubleWidthConstant(index.0)),
+        Some(constant) => Ok(constant),
+        None => Err(ConstantLookupError::OutOfRange(index.0)),
+    }
+}
+
+impl DeserializeWithConstants for Field {
+    const MIN_SIZE: usize = 8;
+
+    fn deserialize(data: &mut dyn bytes::Buf, constants: &[Constant]) -> Result<Field, ClassLoaderError> {
+        let flags = FieldFlags::deserialize(data)?;
+        let name = ConstantIndex::deserialize(data)?;
+        let descriptor = ConstantIndex::deserialize(data)?;
+
+        require!(data has 2 bytes for "field attribute count");
+        let attribute_count = data.get_u16_be() as usize;
+        let attributes = deserialize_attributes(attribute_count, data, constants)?;
+
+        Ok(Field {flags, name, descriptor, attributes})
+    }
+}
+
+impl DeserializeWithConstants for Method {
+    const MIN_SIZE: usize = 8;
+
+    fn deserialize(data: &mut dyn bytes::Buf, constants: &[Constant]) -> Result<Method, ClassLoaderError> {
+        let flags = MethodFlags::deserialize(data)?;
+        let name = ConstantIndex::deserialize(data)?;
+        let descriptor = ConstantIndex::deserialize(data)?;
+
+        require!(data has 2 bytes for "method attribute count");
+        let attribute_count = data.get_u16_be() as usize;
+        let attributes = deserialize_attributes(attribute_count, data, constants)?;
+
+        Ok(Method {flags, name, descriptor, attributes})
+    }
+}
+
 impl Deserialize for Constant {
-    fn deserialize(data: &mut bytes::Buf) -> Result<Constant, ClassLoaderError> {
+    const MIN_SIZE: usize = 3;
+
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<Constant, ClassLoaderError> {
         require!(data has 1 byte for "constant tag");
         let tag = data.get_u8();
         match tag {
@@ -48,74 +283,111 @@ impl Deserialize for Constant {
             9 => deserialize_fieldref(data),
             10 => deserialize_methodref(data),
             11 => deserialize_interface_method_ref(data),
+            12 => deserialize_name_and_type(data),
             15 => deserialize_method_handle_ref(data),
             16 => deserialize_method_type(data),
+            17 => deserialize_dynamic(data),
             18 => deserialize_invoke_dynamic_info(data),
+            19 => ConstantIndex::deserialize(data).map(Constant::Module),
+            20 => ConstantIndex::deserialize(data).map(Constant::Package),
             _ => Err(ClassLoaderError::InvalidConstantType(tag)),
         }
     }
 }
 
-fn deserialize_utf8(data: &mut bytes::Buf) -> Result<Constant, ClassLoaderError> {
+fn deserialize_utf8(data: &mut dyn bytes::Buf) -> Result<Constant, ClassLoaderError> {
     require!(data has 2 bytes for "length field of Utf8 constant");
     let length = data.get_u16_be() as usize;
 
     require!(data has length bytes for "Utf8 constant");
-    let mut contents = vec![0; length as usize];
+    let mut contents = vec![0; length];
     data.copy_to_slice(&mut contents);
 
-    str::from_utf8(&contents)
-        .map(|slice| Constant::Utf8(slice.to_string()))
-        .map_err(|err| ClassLoaderError::Utf8(err))
+    decode_modified_utf8(contents).map(Constant::Utf8)
 }
 
-fn deserialize_integer(data: &mut bytes::Buf) -> Result<Constant, ClassLoaderError> {
+// Decodes the modified UTF-8 of Utf8 constants, per JVM spec 4.4.7. Unlike standard UTF-8, NUL is
+// encoded as C0 80, and characters outside the BMP as the two three-byte sequences of their
+// surrogate pair; there are no four-byte sequences. Nearly every constant is ASCII, which is the
+// same in both and is checked a word at a time, so that's taken as it is. A lone surrogate, which
+// a Java string may hold but a Rust one can't, becomes U+FFFD.
+fn decode_modified_utf8(bytes: Vec<u8>) -> Result<String, ClassLoaderError> {
+    if bytes.is_ascii() && !bytes.contains(&0) {
+        return Ok(String::from_utf8(bytes).expect("ASCII is valid UTF-8"));
+    }
+
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let continuation = |offset: usize| match bytes.get(i + offset) {
+            Some(&byte) if byte & 0xc0 == 0x80 => Ok((byte & 0x3f) as u16),
+            _ => Err(ClassLoaderError::Utf8(i)),
+        };
+        let (unit, length) = match bytes[i] {
+            byte @ 0x01..=0x7f => (byte as u16, 1),
+            byte @ 0xc0..=0xdf => (((byte & 0x1f) as u16) << 6 | continuation(1)?, 2),
+            byte @ 0xe0..=0xef => (((byte & 0x0f) as u16) << 12 | continuation(1)? << 6 | continuation(2)?, 3),
+            _ => return Err(ClassLoaderError::Utf8(i)),
+        };
+        units.push(unit);
+        i += length;
+    }
+    Ok(char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect())
+}
+
+fn deserialize_integer(data: &mut dyn bytes::Buf) -> Result<Constant, ClassLoaderError> {
     require!(data has 4 bytes for "Integer constant");
     Ok(Constant::Integer(data.get_u32_be()))
 }
 
-fn deserialize_float(data: &mut bytes::Buf) -> Result<Constant, ClassLoaderError> {
+fn deserialize_float(data: &mut dyn bytes::Buf) -> Result<Constant, ClassLoaderError> {
     require!(data has 4 bytes for "Float constant");
     Ok(Constant::Float(data.get_f32_be()))
 }
 
-fn deserialize_long(data: &mut bytes::Buf) -> Result<Constant, ClassLoaderError> {
+fn deserialize_long(data: &mut dyn bytes::Buf) -> Result<Constant, ClassLoaderError> {
     require!(data has 8 bytes for "Long constant");
     Ok(Constant::Long(data.get_u64_be()))
 }
 
-fn deserialize_double(data: &mut bytes::Buf) -> Result<Constant, ClassLoaderError> {
+fn deserialize_double(data: &mut dyn bytes::Buf) -> Result<Constant, ClassLoaderError> {
     require!(data has 8 bytes for "Double constant");
     Ok(Constant::Double(data.get_f64_be()))
 }
 
-fn deserialize_classref(data: &mut bytes::Buf) -> Result<Constant, ClassLoaderError> {
+fn deserialize_classref(data: &mut dyn bytes::Buf) -> Result<Constant, ClassLoaderError> {
     ConstantIndex::deserialize(data).map(Constant::ClassRef)
 }
 
-fn deserialize_string(data: &mut bytes::Buf) -> Result<Constant, ClassLoaderError> {
+fn deserialize_string(data: &mut dyn bytes::Buf) -> Result<Constant, ClassLoaderError> {
     ConstantIndex::deserialize(data).map(Constant::StringRef)
 }
 
-fn deserialize_fieldref(data: &mut bytes::Buf) -> Result<Constant, ClassLoaderError> {
+fn deserialize_fieldref(data: &mut dyn bytes::Buf) -> Result<Constant, ClassLoaderError> {
     let class = ConstantIndex::deserialize(data)?;
     let name_and_type = ConstantIndex::deserialize(data)?;
-    Ok(Constant::FieldRef {class: class, name_and_type: name_and_type})
+    Ok(Constant::FieldRef {class, name_and_type})
 }
 
-fn deserialize_methodref(data: &mut bytes::Buf) -> Result<Constant, ClassLoaderError> {
+fn deserialize_methodref(data: &mut dyn bytes::Buf) -> Result<Constant, ClassLoaderError> {
     let class = ConstantIndex::deserialize(data)?;
     let name_and_type = ConstantIndex::deserialize(data)?;
-    Ok(Constant::MethodRef {class: class, name_and_type: name_and_type})
+    Ok(Constant::MethodRef {class, name_and_type})
 }
 
-fn deserialize_interface_method_ref(data: &mut bytes::Buf) -> Result<Constant, ClassLoaderError> {
+fn deserialize_interface_method_ref(data: &mut dyn bytes::Buf) -> Result<Constant, ClassLoaderError> {
     let class = ConstantIndex::deserialize(data)?;
     let name_and_type = ConstantIndex::deserialize(data)?;
-    Ok(Constant::InterfaceMethodRef {class: class, name_and_type: name_and_type})
+    Ok(Constant::InterfaceMethodRef {class, name_and_type})
+}
+
+fn deserialize_name_and_type(data: &mut dyn bytes::Buf) -> Result<Constant, ClassLoaderError> {
+    let name = ConstantIndex::deserialize(data)?;
+    let descriptor = ConstantIndex::deserialize(data)?;
+    Ok(Constant::NameAndTypeRef {name, descriptor})
 }
 
-fn deserialize_method_handle_ref(data: &mut bytes::Buf) -> Result<Constant, ClassLoaderError> {
+fn deserialize_method_handle_ref(data: &mut dyn bytes::Buf) -> Result<Constant, ClassLoaderError> {
     require!(data has 1 byte for "method handle ref kind");
     let kind = data.get_u8();
     let index = ConstantIndex::deserialize(data)?;
@@ -132,34 +404,45 @@ fn deserialize_method_handle_ref(data: &mut bytes::Buf) -> Result<Constant, Clas
         _ => Err(ClassLoaderError::InvalidMethodHandleKind(kind)),
     };
 
-    handle.map(|h| Constant::MethodHandleRef(h))
+    handle.map(Constant::MethodHandleRef)
 }
 
-fn deserialize_method_type(data: &mut bytes::Buf) -> Result<Constant, ClassLoaderError> {
+fn deserialize_method_type(data: &mut dyn bytes::Buf) -> Result<Constant, ClassLoaderError> {
     Ok(Constant::MethodType(ConstantIndex::deserialize(data)?))
 }
 
-fn deserialize_invoke_dynamic_info(data: &mut bytes::Buf) -> Result<Constant, ClassLoaderError> {
+fn deserialize_dynamic(data: &mut dyn bytes::Buf) -> Result<Constant, ClassLoaderError> {
+    Ok(Constant::Dynamic{
+        bootstrap_method_attr: deserialize_method_index(data)?,
+        name_and_type: ConstantIndex::deserialize(data)?,
+    })
+}
+
+fn deserialize_invoke_dynamic_info(data: &mut dyn bytes::Buf) -> Result<Constant, ClassLoaderError> {
     Ok(Constant::InvokeDynamicInfo{
         bootstrap_method_attr: deserialize_method_index(data)?,
         name_and_type: ConstantIndex::deserialize(data)?,
     })
 }
 
-fn deserialize_method_index(data: &mut bytes::Buf) -> Result<MethodIndex, ClassLoaderError> {
+fn deserialize_method_index(data: &mut dyn bytes::Buf) -> Result<MethodIndex, ClassLoaderError> {
     require!(data has 2 bytes for "method index");
     Ok(MethodIndex(data.get_u16_be()))
 }
 
 impl Deserialize for ConstantIndex {
-    fn deserialize(data: &mut bytes::Buf) -> Result<ConstantIndex, ClassLoaderError> {
+    const MIN_SIZE: usize = 2;
+
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<ConstantIndex, ClassLoaderError> {
         require!(data has 2 bytes for "constant index");
         Ok(ConstantIndex(data.get_u16_be()))
     }
 }
 
 impl DeserializeWithConstants for Attribute {
-    fn deserialize(data: &mut bytes::Buf, constants: &Vec<Constant>) -> Result<Attribute, ClassLoaderError> {
+    const MIN_SIZE: usize = 6;
+
+    fn deserialize(data: &mut dyn bytes::Buf, constants: &[Constant]) -> Result<Attribute, ClassLoaderError> {
         let attribute_type_index = ConstantIndex::deserialize(data)?;
         let attribute_type_ref = attribute_type_index.lookup(constants)?;
         let attribute_type = match *attribute_type_ref {
@@ -176,7 +459,42 @@ impl DeserializeWithConstants for Attribute {
             "Code" => deserialize_code(attribute_type_index, constants,  data),
             "StackMapTable" => deserialize_stack_map_table(attribute_type_index, data),
             "Exceptions" => deserialize_exceptions(attribute_type_index, data),
-            _ => Err(ClassLoaderError::UnknownAttributeType(attribute_type.to_string()))
+            "InnerClasses" => deserialize_inner_classes(attribute_type_index, data),
+            "EnclosingMethod" => deserialize_enclosing_method(attribute_type_index, data),
+            "Synthetic" => Ok(Attribute::Synthetic {attribute_name: attribute_type_index}),
+            "Signature" => deserialize_signature(attribute_type_index, data),
+            "SourceFile" => deserialize_source_file(attribute_type_index, data),
+            "SourceDebugExtension" => deserialize_source_debug_extension(attribute_type_index, declared_length, data),
+            "LineNumberTable" => deserialize_line_number_table(attribute_type_index, data),
+            "LocalVariableTable" => deserialize_local_variable_table(attribute_type_index, data),
+            "LocalVariableTypeTable" => deserialize_local_variable_type_table(attribute_type_index, data),
+            "Deprecated" => Ok(Attribute::Deprecated {attribute_name: attribute_type_index}),
+            "RuntimeVisibleAnnotations" => deserialize_annotations(data).map(|annotations|
+                Attribute::RuntimeVisibleAnnotations {attribute_name: attribute_type_index, annotations}),
+            "RuntimeInvisibleAnnotations" => deserialize_annotations(data).map(|annotations|
+                Attribute::RuntimeInvisibleAnnotations {attribute_name: attribute_type_index, annotations}),
+            "RuntimeVisibleParameterAnnotations" => deserialize_parameter_annotations(data).map(|annotations_by_param_index|
+                Attribute::RuntimeVisibleParameterAnnotations {attribute_name: attribute_type_index, annotations_by_param_index}),
+            "RuntimeInvisibleParameterAnnotations" => deserialize_parameter_annotations(data).map(|annotations_by_param_index|
+                Attribute::RuntimeInvisibleParameterAnnotations {attribute_name: attribute_type_index, annotations_by_param_index}),
+            "AnnotationDefault" => ElementValue::deserialize(data).map(|value|
+                Attribute::AnnotationDefault {attribute_name: attribute_type_index, value: Box::new(value)}),
+            "BootstrapMethods" => deserialize_bootstrap_methods(attribute_type_index, data),
+            "Module" => deserialize_module(attribute_type_index, data),
+            "ModulePackages" => deserialize_module_packages(attribute_type_index, data),
+            "ModuleMainClass" => ConstantIndex::deserialize(data).map(|main_class|
+                Attribute::ModuleMainClass {attribute_name: attribute_type_index, main_class}),
+            "NestHost" => ConstantIndex::deserialize(data).map(|host_class|
+                Attribute::NestHost {attribute_name: attribute_type_index, host_class}),
+            "NestMembers" => deserialize_nest_members(attribute_type_index, data),
+            _ => {
+                // Callers skip attributes of unknown types, so the body is passed over to leave the
+                // data at the next attribute.
+                let length = declared_length as usize;
+                require!(data has length bytes for "unknown attribute");
+                data.advance(length);
+                Err(ClassLoaderError::UnknownAttributeType(attribute_type.to_string()))
+            },
         };
         let actual_length = (bytes_remaining_before_parsing_body - data.remaining()) as u32;
 
@@ -194,14 +512,14 @@ impl DeserializeWithConstants for Attribute {
     }
 }
 
-fn deserialize_constant_value(attribute_name: ConstantIndex, data: &mut bytes::Buf) -> Result<Attribute, ClassLoaderError> {
+fn deserialize_constant_value(attribute_name: ConstantIndex, data: &mut dyn bytes::Buf) -> Result<Attribute, ClassLoaderError> {
     Ok(Attribute::ConstantValue {
-        attribute_name: attribute_name,
+        attribute_name,
         constant_value: ConstantIndex::deserialize(data)?,
     })
 }
 
-fn deserialize_code(attribute_name: ConstantIndex, constants: &Vec<Constant>, data: &mut bytes::Buf) -> Result<Attribute, ClassLoaderError> {
+fn deserialize_code(attribute_name: ConstantIndex, constants: &[Constant], data: &mut dyn bytes::Buf) -> Result<Attribute, ClassLoaderError> {
     require!(data has 2 bytes for "Code attribute max stack size");
     let max_stack = data.get_u16_be();
 
@@ -213,9 +531,7 @@ fn deserialize_code(attribute_name: ConstantIndex, constants: &Vec<Constant>, da
 
     require!(data has code_length bytes for "Code attribute code body");
     let mut code = vec![0; code_length];
-    for idx in 0..code_length {
-        code[idx] = data.get_u8();
-    }
+    data.copy_to_slice(&mut code);
 
     require!(data has 2 bytes for "Code attribute exception table length");
     let exception_row_count = data.get_u16_be() as usize;
@@ -223,42 +539,218 @@ fn deserialize_code(attribute_name: ConstantIndex, constants: &Vec<Constant>, da
 
     require!(data has 2 bytes for "Code attribute subattribute count");
     let attributes_count = data.get_u16_be() as usize;
-    let attributes = deserialize_multiple_with_constants(attributes_count, data, constants)?;
-
-    Ok(Attribute::Code {
-        attribute_name: attribute_name,
-        max_stack: max_stack,
-        max_locals: max_locals,
-        code: code,
-        exception_table: exception_table,
-        attributes: attributes,
-    })
+    let attributes = deserialize_attributes(attributes_count, data, constants)?;
+
+    Ok(Attribute::Code(Box::new(CodeAttribute {
+        attribute_name,
+        max_stack,
+        max_locals,
+        code,
+        exception_table,
+        attributes,
+    })))
 }
 
-fn deserialize_stack_map_table(attribute_name: ConstantIndex, data: &mut bytes::Buf) -> Result<Attribute, ClassLoaderError> {
+fn deserialize_stack_map_table(attribute_name: ConstantIndex, data: &mut dyn bytes::Buf) -> Result<Attribute, ClassLoaderError> {
     require!(data has 2 bytes for "stack map table entry count");
     let num_entries = data.get_u16_be() as usize;
     let entries = deserialize_multiple(num_entries, data)?;
 
     Ok(Attribute::StackMapTable {
-        attribute_name: attribute_name,
-        entries: entries,
+        attribute_name,
+        entries,
     })
 }
 
-fn deserialize_exceptions(attribute_name: ConstantIndex, data: &mut bytes::Buf) -> Result<Attribute, ClassLoaderError> {
+fn deserialize_exceptions(attribute_name: ConstantIndex, data: &mut dyn bytes::Buf) -> Result<Attribute, ClassLoaderError> {
     require!(data has 2 bytes for "exception attribute table size");
     let num_exceptions = data.get_u16_be() as usize;
     let exception_indices = deserialize_multiple(num_exceptions, data)?;
 
     Ok(Attribute::Exceptions {
-        attribute_name: attribute_name,
+        attribute_name,
         index_table: exception_indices,
     })
 }
 
+fn deserialize_inner_classes(attribute_name: ConstantIndex, data: &mut dyn bytes::Buf) -> Result<Attribute, ClassLoaderError> {
+    require!(data has 2 bytes for "inner class count");
+    let num_classes = data.get_u16_be() as usize;
+    let classes = deserialize_multiple(num_classes, data)?;
+
+    Ok(Attribute::InnerClasses {attribute_name, classes})
+}
+
+fn deserialize_enclosing_method(attribute_name: ConstantIndex, data: &mut dyn bytes::Buf) -> Result<Attribute, ClassLoaderError> {
+    Ok(Attribute::EnclosingMethod {
+        attribute_name,
+        class: ConstantIndex::deserialize(data)?,
+        method: ConstantIndex::deserialize(data)?,
+    })
+}
+
+fn deserialize_signature(attribute_name: ConstantIndex, data: &mut dyn bytes::Buf) -> Result<Attribute, ClassLoaderError> {
+    Ok(Attribute::Signature {
+        attribute_name,
+        signature: ConstantIndex::deserialize(data)?,
+    })
+}
+
+fn deserialize_source_file(attribute_name: ConstantIndex, data: &mut dyn bytes::Buf) -> Result<Attribute, ClassLoaderError> {
+    Ok(Attribute::SourceFile {
+        attribute_name,
+        source_file: ConstantIndex::deserialize(data)?,
+    })
+}
+
+// SourceDebugExtension is the only attribute whose body has no internal structure, so we have
+// to rely on the declared length to know how much to read.
+fn deserialize_source_debug_extension(attribute_name: ConstantIndex, length: u32, data: &mut dyn bytes::Buf) -> Result<Attribute, ClassLoaderError> {
+    let length = length as usize;
+    require!(data has length bytes for "source debug extension");
+    let mut debug_extension = vec![0; length];
+    data.copy_to_slice(&mut debug_extension);
+
+    Ok(Attribute::SourceDebug {attribute_name, debug_extension})
+}
+
+fn deserialize_line_number_table(attribute_name: ConstantIndex, data: &mut dyn bytes::Buf) -> Result<Attribute, ClassLoaderError> {
+    require!(data has 2 bytes for "line number table length");
+    let num_lines = data.get_u16_be() as usize;
+
+    let mut table = presized(num_lines, 4, data);
+    for _ in 0..num_lines {
+        require!(data has 4 bytes for "line number table entry");
+        table.push((data.get_u16_be(), data.get_u16_be()));
+    }
+
+    Ok(Attribute::LineNumberTable {attribute_name, table})
+}
+
+fn deserialize_local_variable_table(attribute_name: ConstantIndex, data: &mut dyn bytes::Buf) -> Result<Attribute, ClassLoaderError> {
+    require!(data has 2 bytes for "local variable table length");
+    let num_variables = data.get_u16_be() as usize;
+    let variables = deserialize_multiple(num_variables, data)?;
+
+    Ok(Attribute::LocalVariableTable {attribute_name, variables})
+}
+
+fn deserialize_local_variable_type_table(attribute_name: ConstantIndex, data: &mut dyn bytes::Buf) -> Result<Attribute, ClassLoaderError> {
+    require!(data has 2 bytes for "local variable type table length");
+    let num_variable_types = data.get_u16_be() as usize;
+    let variable_types = deserialize_multiple(num_variable_types, data)?;
+
+    Ok(Attribute::LocalVariableTypeTable {attribute_name, variable_types})
+}
+
+fn deserialize_annotations(data: &mut dyn bytes::Buf) -> Result<Vec<Annotation>, ClassLoaderError> {
+    require!(data has 2 bytes for "annotation count");
+    let num_annotations = data.get_u16_be() as usize;
+    deserialize_multiple(num_annotations, data)
+}
+
+fn deserialize_parameter_annotations(data: &mut dyn bytes::Buf) -> Result<Vec<ParameterAnnotations>, ClassLoaderError> {
+    require!(data has 1 byte for "parameter count");
+    let num_parameters = data.get_u8();
+
+    let mut annotations_by_param_index = presized(num_parameters as usize, 2, data);
+    for _ in 0..num_parameters {
+        annotations_by_param_index.push(ParameterAnnotations(deserialize_annotations(data)?));
+    }
+
+    Ok(annotations_by_param_index)
+}
+
+fn deserialize_bootstrap_methods(attribute_name: ConstantIndex, data: &mut dyn bytes::Buf) -> Result<Attribute, ClassLoaderError> {
+    require!(data has 2 bytes for "bootstrap method count");
+    let num_methods = data.get_u16_be() as usize;
+    let methods = deserialize_multiple(num_methods, data)?;
+
+    Ok(Attribute::BootstrapMethods {attribute_name, methods})
+}
+
+fn deserialize_module(attribute_name: ConstantIndex, data: &mut dyn bytes::Buf) -> Result<Attribute, ClassLoaderError> {
+    let name = ConstantIndex::deserialize(data)?;
+    require!(data has 2 bytes for "module flags");
+    let flags = ModuleFlags::from_bits_truncate(data.get_u16_be());
+    let version = ConstantIndex::deserialize(data)?;
+
+    require!(data has 2 bytes for "module requires count");
+    let num_requires = data.get_u16_be() as usize;
+    let requires = deserialize_multiple(num_requires, data)?;
+    require!(data has 2 bytes for "module exports count");
+    let num_exports = data.get_u16_be() as usize;
+    let exports = deserialize_multiple(num_exports, data)?;
+    require!(data has 2 bytes for "module opens count");
+    let num_opens = data.get_u16_be() as usize;
+    let opens = deserialize_multiple(num_opens, data)?;
+    require!(data has 2 bytes for "module uses count");
+    let num_uses = data.get_u16_be() as usize;
+    let uses = deserialize_multiple(num_uses, data)?;
+    require!(data has 2 bytes for "module provides count");
+    let num_provides = data.get_u16_be() as usize;
+    let provides = deserialize_multiple(num_provides, data)?;
+
+    Ok(Attribute::Module(Box::new(ModuleAttribute {attribute_name, name, flags, version, requires, exports, opens, uses, provides})))
+}
+
+fn deserialize_module_packages(attribute_name: ConstantIndex, data: &mut dyn bytes::Buf) -> Result<Attribute, ClassLoaderError> {
+    require!(data has 2 bytes for "module package count");
+    let num_packages = data.get_u16_be() as usize;
+    let packages = deserialize_multiple(num_packages, data)?;
+
+    Ok(Attribute::ModulePackages {attribute_name, packages})
+}
+
+fn deserialize_nest_members(attribute_name: ConstantIndex, data: &mut dyn bytes::Buf) -> Result<Attribute, ClassLoaderError> {
+    require!(data has 2 bytes for "nest member count");
+    let num_classes = data.get_u16_be() as usize;
+    let classes = deserialize_multiple(num_classes, data)?;
+
+    Ok(Attribute::NestMembers {attribute_name, classes})
+}
+
+impl Deserialize for ModuleRequires {
+    const MIN_SIZE: usize = 6;
+
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<ModuleRequires, ClassLoaderError> {
+        let module = ConstantIndex::deserialize(data)?;
+        require!(data has 2 bytes for "module requires flags");
+        let flags = RequiresFlags::from_bits_truncate(data.get_u16_be());
+        let version = ConstantIndex::deserialize(data)?;
+        Ok(ModuleRequires {module, flags, version})
+    }
+}
+
+impl Deserialize for ModulePackage {
+    const MIN_SIZE: usize = 6;
+
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<ModulePackage, ClassLoaderError> {
+        let package = ConstantIndex::deserialize(data)?;
+        require!(data has 4 bytes for "module package flags and target count");
+        let flags = data.get_u16_be();
+        let num_targets = data.get_u16_be() as usize;
+        let targets = deserialize_multiple(num_targets, data)?;
+        Ok(ModulePackage {package, flags, targets})
+    }
+}
+
+impl Deserialize for ModuleProvides {
+    const MIN_SIZE: usize = 4;
+
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<ModuleProvides, ClassLoaderError> {
+        let service = ConstantIndex::deserialize(data)?;
+        require!(data has 2 bytes for "module provides count");
+        let num_implementations = data.get_u16_be() as usize;
+        let implementations = deserialize_multiple(num_implementations, data)?;
+        Ok(ModuleProvides {service, implementations})
+    }
+}
+
 impl Deserialize for ExceptionTableRow {
-    fn deserialize(data: &mut bytes::Buf) -> Result<ExceptionTableRow, ClassLoaderError> {
+    const MIN_SIZE: usize = 8;
+
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<ExceptionTableRow, ClassLoaderError> {
         require!(data has 8 bytes for "exception table row");
         Ok(ExceptionTableRow {
             start_pc: data.get_u16_be(),
@@ -270,12 +762,12 @@ impl Deserialize for ExceptionTableRow {
 }
 
 impl Deserialize for StackMapFrame {
-    fn deserialize(data: &mut bytes::Buf) -> Result<StackMapFrame, ClassLoaderError> {
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<StackMapFrame, ClassLoaderError> {
         require!(data has 1 byte for "stack map frame type");
         let frame_type = data.get_u8();
         match frame_type {
-            0...63 => Ok(StackMapFrame::SameFrame{offset_delta: frame_type}),
-            64...127 => Ok(StackMapFrame::SameLocalsOneStackItemFrame {
+            0..=63 => Ok(StackMapFrame::SameFrame{offset_delta: frame_type}),
+            64..=127 => Ok(StackMapFrame::SameLocalsOneStackItemFrame {
                 offset_delta: frame_type - 64,
                 stack_item: VerificationType::deserialize(data)?,
             }),
@@ -286,7 +778,7 @@ impl Deserialize for StackMapFrame {
                     stack_item: VerificationType::deserialize(data)?,
                 })
             },
-            248...250 => {
+            248..=250 => {
                 require!(data has 2 bytes for "chop frame offset");
                 Ok(StackMapFrame::ChopFrame {
                     offset_delta: data.get_u16_be(),
@@ -299,7 +791,7 @@ impl Deserialize for StackMapFrame {
                     offset_delta: data.get_u16_be(),
                 })
             },
-            252...254 => {
+            252..=254 => {
                 require!(data has 2 bytes for "append frame offset");
                 let offset_delta = data.get_u16_be();
 
@@ -307,7 +799,7 @@ impl Deserialize for StackMapFrame {
                 let locals = deserialize_multiple(num_locals, data)?;
 
                 Ok(StackMapFrame::AppendFrame {
-                    offset_delta: offset_delta,
+                    offset_delta,
                     new_locals: locals,
                 })
             },
@@ -324,9 +816,9 @@ impl Deserialize for StackMapFrame {
                 let stack_items = deserialize_multiple(num_stack_items, data)?;
 
                 Ok(StackMapFrame::FullFrame {
-                    offset_delta: offset_delta,
-                    locals: locals,
-                    stack_items: stack_items,
+                    offset_delta,
+                    locals,
+                    stack_items,
                 })
             },
             _ => Err(ClassLoaderError::InvalidStackFrameType(frame_type)),
@@ -335,7 +827,7 @@ impl Deserialize for StackMapFrame {
 }
 
 impl Deserialize for VerificationType {
-    fn deserialize(data: &mut bytes::Buf) -> Result<VerificationType, ClassLoaderError> {
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<VerificationType, ClassLoaderError> {
         require!(data has 1 byte for "verification type identifier");
         let type_id = data.get_u8();
         match type_id {
@@ -357,7 +849,7 @@ impl Deserialize for VerificationType {
 }
 
 impl Deserialize for InnerClassFlags {
-    fn deserialize(data: &mut bytes::Buf) -> Result<InnerClassFlags, ClassLoaderError> {
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<InnerClassFlags, ClassLoaderError> {
         require!(data has 2 bytes for "inner class access flags");
         let flag_bytes = data.get_u16_be();
         let masked_flag_bytes = flag_bytes & InnerClassFlags::all().bits(); // Ignore unused bits per spec 4.7.6.
@@ -365,8 +857,136 @@ impl Deserialize for InnerClassFlags {
     }
 }
 
-fn deserialize_multiple<D: Deserialize>(count: usize, data: &mut bytes::Buf) -> Result<Vec<D>, ClassLoaderError> {
-    let mut res = vec![];
+impl Deserialize for InnerClassInfo {
+    const MIN_SIZE: usize = 8;
+
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<InnerClassInfo, ClassLoaderError> {
+        Ok(InnerClassInfo {
+            inner_class: ConstantIndex::deserialize(data)?,
+            outer_class: ConstantIndex::deserialize(data)?,
+            inner_class_name: ConstantIndex::deserialize(data)?,
+            flags: InnerClassFlags::deserialize(data)?,
+        })
+    }
+}
+
+impl Deserialize for LocalVariable {
+    const MIN_SIZE: usize = 10;
+
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<LocalVariable, ClassLoaderError> {
+        require!(data has 4 bytes for "local variable range");
+        let start_pc = data.get_u16_be();
+        let length = data.get_u16_be();
+        let name = ConstantIndex::deserialize(data)?;
+        let descriptor = ConstantIndex::deserialize(data)?;
+
+        require!(data has 2 bytes for "local variable index");
+        Ok(LocalVariable {start_pc, length, name, descriptor, index: data.get_u16_be()})
+    }
+}
+
+impl Deserialize for LocalVariableType {
+    const MIN_SIZE: usize = 10;
+
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<LocalVariableType, ClassLoaderError> {
+        require!(data has 4 bytes for "local variable type range");
+        let start_pc = data.get_u16_be();
+        let length = data.get_u16_be();
+        let name = ConstantIndex::deserialize(data)?;
+        let signature = ConstantIndex::deserialize(data)?;
+
+        require!(data has 2 bytes for "local variable type index");
+        Ok(LocalVariableType {start_pc, length, name, signature, index: data.get_u16_be()})
+    }
+}
+
+impl Deserialize for Annotation {
+    const MIN_SIZE: usize = 4;
+
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<Annotation, ClassLoaderError> {
+        let type_index = ConstantIndex::deserialize(data)?;
+
+        require!(data has 2 bytes for "annotation element count");
+        let num_pairs = data.get_u16_be();
+        let mut indexes_with_values = presized(num_pairs as usize, 2 + ElementValue::MIN_SIZE, data);
+        for _ in 0..num_pairs {
+            let name = ConstantIndex::deserialize(data)?;
+            indexes_with_values.push((name, ElementValue::deserialize(data)?));
+        }
+
+        Ok(Annotation {type_index, indexes_with_values})
+    }
+}
+
+impl Deserialize for ElementValue {
+    const MIN_SIZE: usize = 3;
+
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<ElementValue, ClassLoaderError> {
+        require!(data has 1 byte for "element value tag");
+        let tag = data.get_u8();
+        match tag {
+            b'B' => Ok(ElementValue::Byte(ConstantIndex::deserialize(data)?)),
+            b'C' => Ok(ElementValue::Char(ConstantIndex::deserialize(data)?)),
+            b'D' => Ok(ElementValue::Double(ConstantIndex::deserialize(data)?)),
+            b'F' => Ok(ElementValue::Float(ConstantIndex::deserialize(data)?)),
+            b'I' => Ok(ElementValue::Integer(ConstantIndex::deserialize(data)?)),
+            b'J' => Ok(ElementValue::Long(ConstantIndex::deserialize(data)?)),
+            b'S' => Ok(ElementValue::Short(ConstantIndex::deserialize(data)?)),
+            b'Z' => Ok(ElementValue::Boolean(ConstantIndex::deserialize(data)?)),
+            b's' => Ok(ElementValue::String(ConstantIndex::deserialize(data)?)),
+            b'e' => Ok(ElementValue::Enum {
+                enum_type: ConstantIndex::deserialize(data)?,
+                enum_value: ConstantIndex::deserialize(data)?,
+            }),
+            b'c' => Ok(ElementValue::Class(ConstantIndex::deserialize(data)?)),
+            b'@' => Ok(ElementValue::Annotation(Annotation::deserialize(data)?)),
+            b'[' => {
+                require!(data has 2 bytes for "element value array length");
+                let num_values = data.get_u16_be() as usize;
+                Ok(ElementValue::Array(deserialize_multiple(num_values, data)?))
+            },
+            _ => Err(ClassLoaderError::InvalidElementValueTag(tag)),
+        }
+    }
+}
+
+impl Deserialize for BootstrapMethod {
+    const MIN_SIZE: usize = 4;
+
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<BootstrapMethod, ClassLoaderError> {
+        let method = ConstantIndex::deserialize(data)?;
+
+        require!(data has 2 bytes for "bootstrap method argument count");
+        let num_arguments = data.get_u16_be() as usize;
+        let arguments = deserialize_multiple(num_arguments, data)?;
+
+        Ok(BootstrapMethod {method, arguments})
+    }
+}
+
+impl Deserialize for ClassFlags {
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<ClassFlags, ClassLoaderError> {
+        require!(data has 2 bytes for "class access flags");
+        Ok(ClassFlags::from_bits_truncate(data.get_u16_be())) // Ignore unused bits per spec 4.1.
+    }
+}
+
+impl Deserialize for FieldFlags {
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<FieldFlags, ClassLoaderError> {
+        require!(data has 2 bytes for "field access flags");
+        Ok(FieldFlags::from_bits_truncate(data.get_u16_be())) // Ignore unused bits per spec 4.5.
+    }
+}
+
+impl Deserialize for MethodFlags {
+    fn deserialize(data: &mut dyn bytes::Buf) -> Result<MethodFlags, ClassLoaderError> {
+        require!(data has 2 bytes for "method access flags");
+        Ok(MethodFlags::from_bits_truncate(data.get_u16_be())) // Ignore unused bits per spec 4.6.
+    }
+}
+
+fn deserialize_multiple<D: Deserialize>(count: usize, data: &mut dyn bytes::Buf) -> Result<Vec<D>, ClassLoaderError> {
+    let mut res = presized(count, D::MIN_SIZE, data);
     for _ in 0..count {
         res.push(D::deserialize(data)?);
     }
@@ -374,8 +994,8 @@ fn deserialize_multiple<D: Deserialize>(count: usize, data: &mut bytes::Buf) ->
     Ok(res)
 }
 
-fn deserialize_multiple_with_constants<D: DeserializeWithConstants>(count: usize, data: &mut bytes::Buf, constants: &Vec<Constant>) -> Result<Vec<D>, ClassLoaderError> {
-    let mut res = vec![];
+fn deserialize_multiple_with_constants<D: DeserializeWithConstants>(count: usize, data: &mut dyn bytes::Buf, constants: &[Constant]) -> Result<Vec<D>, ClassLoaderError> {
+    let mut res = presized(count, D::MIN_SIZE, data);
     for _ in 0..count {
         res.push(D::deserialize(data, constants)?);
     }
@@ -383,18 +1003,42 @@ fn deserialize_multiple_with_constants<D: DeserializeWithConstants>(count: usize
     Ok(res)
 }
 
+// Reads the attributes of a class, field, method or Code attribute. Attributes of types we don't
+// know are skipped, as JVMS 4.7.1 requires, so that newer class files still load.
+fn deserialize_attributes(count: usize, data: &mut dyn bytes::Buf, constants: &[Constant]) -> Result<Vec<Attribute>, ClassLoaderError> {
+    let mut res = presized(count, Attribute::MIN_SIZE, data);
+    for _ in 0..count {
+        match Attribute::deserialize(data, constants) {
+            Ok(attribute) => res.push(attribute),
+            Err(ClassLoaderError::UnknownAttributeType(_)) => (),
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(res)
+}
+
+// Makes room for as many items as the class file says there are, but no more than the rest of the
+// data could hold, so a hostile count can't make us allocate far beyond the size of the file.
+fn presized<T>(count: usize, min_size: usize, data: &dyn bytes::Buf) -> Vec<T> {
+    Vec::with_capacity(count.min(data.remaining() / min_size))
+}
+
 #[derive(Debug, PartialEq)]
 pub enum ClassLoaderError {
-    Utf8(str::Utf8Error),
+    Utf8(usize), // The offset of an invalid byte within a Utf8 constant.
     Eof(String),
     InvalidConstantRef(ConstantLookupError),
     InvalidConstantType(u8),
+    InvalidElementValueTag(u8),
+    InvalidMagic(u32),
     InvalidMethodHandleKind(u8),
     InvalidAttributeType(Constant),
     InvalidStackFrameType(u8),
     InvalidVerificationType(u8),
     LengthMismatch{context: String, stated_length: u32, inferred_length: u32},
     Misc(String),
+    TrailingData(usize),
     UnknownAttributeType(String),
 }
 
@@ -407,10 +1051,12 @@ impl std::convert::From<ConstantLookupError> for ClassLoaderError {
 impl fmt::Display for ClassLoaderError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            ClassLoaderError::Utf8(ref cause) => write!(f, "Failed to decode UTF-8: {}", cause),
+            ClassLoaderError::Utf8(offset) => write!(f, "Failed to decode modified UTF-8 at byte {}", offset),
             ClassLoaderError::Eof(ref msg) => write!(f, "Unexpected EOF: {}", msg),
             ClassLoaderError::InvalidConstantRef(ref cause) => write!(f, "Invalid constant reference: {}", cause),
             ClassLoaderError::InvalidConstantType(ref tag) => write!(f, "Unsupported constant type {}", tag),
+            ClassLoaderError::InvalidElementValueTag(ref tag) => write!(f, "Invalid element value tag {:#?}", tag),
+            ClassLoaderError::InvalidMagic(ref magic) => write!(f, "Invalid magic number {:#010x}", magic),
             ClassLoaderError::InvalidMethodHandleKind(ref kind) => write!(f, "Unsupported method handle kind {}", kind),
             ClassLoaderError::InvalidAttributeType(ref attribute_type) => write!(f, "Invalid attribute type {:#?}", attribute_type),
             ClassLoaderError::InvalidVerificationType(ref verification_type_tag) => write!(f, "Invalid verification type tag {:#?}", verification_type_tag),
@@ -418,6 +1064,7 @@ impl fmt::Display for ClassLoaderError {
             ClassLoaderError::LengthMismatch{ref context, ref stated_length, ref inferred_length} =>
                 write!(f, "Stated length of {} disagrees with inferred length. Inferred length: {}; stated length: {}", context, inferred_length, stated_length),
             ClassLoaderError::Misc(ref msg) => write!(f, "Unexpected error during class load: {}", msg),
+            ClassLoaderError::TrailingData(ref count) => write!(f, "Found {} unexpected bytes after the end of the class", count),
             ClassLoaderError::UnknownAttributeType(ref type_name) => write!(f, "Unknown attribute type '{}'", type_name),
         }
     }
@@ -430,28 +1077,34 @@ impl error::Error for ClassLoaderError {
             ClassLoaderError::Eof(ref msg) => msg,
             ClassLoaderError::InvalidConstantRef(_) => "Invalid constant reference",
             ClassLoaderError::InvalidConstantType(..) => "Unsupported constant type",
+            ClassLoaderError::InvalidElementValueTag(..) => "Invalid element value tag",
+            ClassLoaderError::InvalidMagic(..) => "Invalid magic number",
             ClassLoaderError::InvalidMethodHandleKind(..) => "Unsupported method handle kind",
             ClassLoaderError::InvalidAttributeType(..) => "Invalid attribute type",
             ClassLoaderError::InvalidVerificationType(..) => "Invalid verification type",
             ClassLoaderError::InvalidStackFrameType(..) => "Invalid stack frame type",
             ClassLoaderError::LengthMismatch{..} => "Stated length of entity disagrees with inferred length",
             ClassLoaderError::Misc(ref msg) => msg,
+            ClassLoaderError::TrailingData(..) => "Unexpected data after the end of the class",
             ClassLoaderError::UnknownAttributeType(..) => "Unknown attribute type",
         }
     }
 
-    fn cause(&self) -> Option<&error::Error> {
+    fn cause(&self) -> Option<&dyn error::Error> {
         match *self {
-            ClassLoaderError::Utf8(ref cause) => Some(cause),
+            ClassLoaderError::Utf8(..) => None,
             ClassLoaderError::InvalidConstantRef(ref cause) => Some(cause),
             ClassLoaderError::Eof(..) => None,
             ClassLoaderError::InvalidConstantType(..) => None,
+            ClassLoaderError::InvalidElementValueTag(..) => None,
+            ClassLoaderError::InvalidMagic(..) => None,
             ClassLoaderError::InvalidMethodHandleKind(..) => None,
             ClassLoaderError::InvalidAttributeType(..) => None,
             ClassLoaderError::InvalidVerificationType(..) => None,
             ClassLoaderError::InvalidStackFrameType(..) => None,
             ClassLoaderError::LengthMismatch{..} => None,
             ClassLoaderError::Misc(..) => None,
+            ClassLoaderError::TrailingData(..) => None,
             ClassLoaderError::UnknownAttributeType(..) => None,
         }
     }
@@ -492,6 +1145,38 @@ mod tests {
         assert_eof(Constant::deserialize, b"");
     }
 
+    #[test]
+    fn test_deserialize_utf8_nul() {
+        assert_deserialize(Constant::Utf8("a\0b".to_string()), b"\x01\x00\x04a\xc0\x80b");
+    }
+
+    #[test]
+    fn test_deserialize_utf8_two_and_three_octet_sequences() {
+        assert_deserialize(Constant::Utf8("\u{e9}\u{20ac}".to_string()), b"\x01\x00\x05\xc3\xa9\xe2\x82\xac");
+    }
+
+    #[test]
+    fn test_deserialize_utf8_supplementary_character() {
+        // U+1F600 as the surrogate pair D83D DE00.
+        assert_deserialize(Constant::Utf8("\u{1f600}!".to_string()), b"\x01\x00\x07\xed\xa0\xbd\xed\xb8\x80!");
+    }
+
+    #[test]
+    fn test_deserialize_utf8_lone_surrogate() {
+        assert_deserialize(Constant::Utf8("\u{fffd}".to_string()), b"\x01\x00\x03\xed\xa0\xbd");
+    }
+
+    #[test]
+    fn test_deserialize_utf8_standard_supplementary_character() {
+        // Four-byte sequences are standard UTF-8, but not modified UTF-8.
+        assert_invalid_utf8(b"\x01\x00\x04\xf0\x9f\x98\x80");
+    }
+
+    #[test]
+    fn test_deserialize_utf8_raw_nul() {
+        assert_invalid_utf8(b"\x01\x00\x01\x00");
+    }
+
     #[test]
     fn test_deserialize_utf8_premature_termination_after_tag() {
         assert_eof(Constant::deserialize, b"\x01");
@@ -1049,6 +1734,19 @@ mod tests {
         assert_eof(Constant::deserialize, b"\x0b\x00\x00\x00");
     }
 
+    #[test]
+    fn test_deserialize_name_and_type_with_abcd_and_1234() {
+        assert_deserialize(Constant::NameAndTypeRef {
+            name: ConstantIndex(0xabcd),
+            descriptor: ConstantIndex(0x1234),
+        }, b"\x0c\xab\xcd\x12\x34");
+    }
+
+    #[test]
+    fn test_deserialize_name_and_type_premature_termination() {
+        assert_eof(Constant::deserialize, b"\x0c\x00\x00\x00");
+    }
+
     #[test]
     fn test_deserialize_method_handle_of_type_get_field() {
         assert_method_handle(MethodHandle::GetField(ConstantIndex(0x1234)), b"\x0f\x01\x12\x34");
@@ -1159,6 +1857,19 @@ mod tests {
         assert_eof(Constant::deserialize, b"\x10\x5b");
     }
 
+    #[test]
+    fn test_deserialize_dynamic_with_indexes_abcd_and_1234() {
+        assert_deserialize(Constant::Dynamic {
+            bootstrap_method_attr: MethodIndex(0xabcd),
+            name_and_type: ConstantIndex(0x1234),
+        }, b"\x11\xab\xcd\x12\x34");
+    }
+
+    #[test]
+    fn test_deserialize_dynamic_premature_termination() {
+        assert_eof(Constant::deserialize, b"\x11\x12\x34\x56");
+    }
+
     #[test]
     fn test_deserialize_invoke_dynamic_info_with_indexes_0000_and_0000() {
         assert_deserialize(Constant::InvokeDynamicInfo {
@@ -1312,17 +2023,17 @@ mod tests {
         expect!(ClassLoaderError::InvalidConstantRef(_) in deserialize_with_constants(
                 Attribute::deserialize,
                 b"\x00\x01\x00\x00\x00\x00",
-                &vec![Constant::Dummy]));
+                &[Constant::Dummy]));
     }
 
     #[test]
     fn test_deserialize_attribute_ending_before_type_ref() {
-        assert_eof_with_constants(Attribute::deserialize, b"", &vec![]);
+        assert_eof_with_constants(Attribute::deserialize, b"", &[]);
     }
 
     #[test]
     fn test_deserialize_attribute_ending_during_type_ref() {
-        assert_eof_with_constants(Attribute::deserialize, b"\x00", &vec![]);
+        assert_eof_with_constants(Attribute::deserialize, b"\x00", &[]);
     }
 
     #[test]
@@ -1359,7 +2070,7 @@ mod tests {
         assert_eof_with_constants(
             Attribute::deserialize,
             b"\x00\x01",
-            &vec![Constant::Utf8("ConstantValue".to_string())]
+            &[Constant::Utf8("ConstantValue".to_string())]
         );
     }
 
@@ -1368,7 +2079,7 @@ mod tests {
         assert_eof_with_constants(
             Attribute::deserialize,
             b"\x00\x01\x00",
-            &vec![Constant::Utf8("ConstantValue".to_string())]
+            &[Constant::Utf8("ConstantValue".to_string())]
         );
     }
 
@@ -1377,7 +2088,7 @@ mod tests {
         assert_eof_with_constants(
             Attribute::deserialize,
             b"\x00\x01\x00\x00",
-            &vec![Constant::Utf8("ConstantValue".to_string())]
+            &[Constant::Utf8("ConstantValue".to_string())]
         );
     }
 
@@ -1386,7 +2097,7 @@ mod tests {
         assert_eof_with_constants(
             Attribute::deserialize,
             b"\x00\x01\x00\x00\x00",
-            &vec![Constant::Utf8("ConstantValue".to_string())]
+            &[Constant::Utf8("ConstantValue".to_string())]
         );
     }
 
@@ -1395,7 +2106,7 @@ mod tests {
         assert_eof_with_constants(
             Attribute::deserialize,
             b"\x00\x01\x00\x00\x00\x02",
-            &vec![Constant::Utf8("ConstantValue".to_string())]
+            &[Constant::Utf8("ConstantValue".to_string())]
         );
     }
 
@@ -1404,7 +2115,7 @@ mod tests {
         assert_eof_with_constants(
             Attribute::deserialize,
             b"\x00\x01\x00\x00\x00\x02\xff",
-            &vec![Constant::Utf8("ConstantValue".to_string())]
+            &[Constant::Utf8("ConstantValue".to_string())]
         );
     }
 
@@ -1497,14 +2208,14 @@ mod tests {
 
     #[test]
     fn test_deserialize_trivial_code_block() {
-        let expected = Attribute::Code {
+        let expected = Attribute::Code(Box::new(CodeAttribute {
             attribute_name: ConstantIndex(1),
             max_stack: 0,
             max_locals: 0,
             code: vec![],
             exception_table: vec![],
             attributes: vec![]
-        };
+        }));
 
         let bytes = b"\x00\x01\x00\x00\x00\x0c\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00";
         let constants = utf8_constant_pool(vec!["Code"]);
@@ -1514,14 +2225,14 @@ mod tests {
 
     #[test]
     fn test_deserialize_code_with_attribute_name_at_index_2() {
-        let expected = Attribute::Code {
+        let expected = Attribute::Code(Box::new(CodeAttribute {
             attribute_name: ConstantIndex(2),
             max_stack: 0,
             max_locals: 0,
             code: vec![],
             exception_table: vec![],
             attributes: vec![]
-        };
+        }));
 
         let bytes = b"\x00\x02\x00\x00\x00\x0c\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00";
         let constants = utf8_constant_pool(vec!["Constant", "Code"]);
@@ -1531,14 +2242,14 @@ mod tests {
 
     #[test]
     fn test_deserialize_code_with_max_stack_1() {
-        let expected = Attribute::Code {
+        let expected = Attribute::Code(Box::new(CodeAttribute {
             attribute_name: ConstantIndex(1),
             max_stack: 1,
             max_locals: 0,
             code: vec![],
             exception_table: vec![],
             attributes: vec![]
-        };
+        }));
 
         let bytes = b"\x00\x01\x00\x00\x00\x0c\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00";
         let constants = utf8_constant_pool(vec!["Code"]);
@@ -1548,14 +2259,14 @@ mod tests {
 
     #[test]
     fn test_deserialize_code_with_max_stack_ffff() {
-        let expected = Attribute::Code {
+        let expected = Attribute::Code(Box::new(CodeAttribute {
             attribute_name: ConstantIndex(1),
             max_stack: 0xffff,
             max_locals: 0,
             code: vec![],
             exception_table: vec![],
             attributes: vec![]
-        };
+        }));
 
         let bytes = b"\x00\x01\x00\x00\x00\x0c\xff\xff\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00";
         let constants = utf8_constant_pool(vec!["Code"]);
@@ -1565,14 +2276,14 @@ mod tests {
 
     #[test]
     fn test_deserialize_code_with_max_locals_1() {
-        let expected = Attribute::Code {
+        let expected = Attribute::Code(Box::new(CodeAttribute {
             attribute_name: ConstantIndex(1),
             max_stack: 0,
             max_locals: 1,
             code: vec![],
             exception_table: vec![],
             attributes: vec![]
-        };
+        }));
 
         let bytes = b"\x00\x01\x00\x00\x00\x0c\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00";
         let constants = utf8_constant_pool(vec!["Code"]);
@@ -1582,14 +2293,14 @@ mod tests {
 
     #[test]
     fn test_deserialize_code_with_max_locals_ffff() {
-        let expected = Attribute::Code {
+        let expected = Attribute::Code(Box::new(CodeAttribute {
             attribute_name: ConstantIndex(1),
             max_stack: 0,
             max_locals: 0xffff,
             code: vec![],
             exception_table: vec![],
             attributes: vec![]
-        };
+        }));
 
         let bytes = b"\x00\x01\x00\x00\x00\x0c\x00\x00\xff\xff\x00\x00\x00\x00\x00\x00\x00\x00";
         let constants = utf8_constant_pool(vec!["Code"]);
@@ -1599,14 +2310,14 @@ mod tests {
 
     #[test]
     fn test_deserialize_code_with_one_byte_of_code() {
-        let expected = Attribute::Code {
+        let expected = Attribute::Code(Box::new(CodeAttribute {
             attribute_name: ConstantIndex(1),
             max_stack: 0,
             max_locals: 0,
             code: vec![0xcd],
             exception_table: vec![],
             attributes: vec![]
-        };
+        }));
 
         let bytes = b"\x00\x01\x00\x00\x00\x0d\x00\x00\x00\x00\x00\x00\x00\x01\xcd\x00\x00\x00\x00";
         let constants = utf8_constant_pool(vec!["Code"]);
@@ -1616,14 +2327,14 @@ mod tests {
 
     #[test]
     fn test_deserialize_code_with_ten_bytes_of_code() {
-        let expected = Attribute::Code {
+        let expected = Attribute::Code(Box::new(CodeAttribute {
             attribute_name: ConstantIndex(1),
             max_stack: 0,
             max_locals: 0,
             code: vec![0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa],
             exception_table: vec![],
             attributes: vec![]
-        };
+        }));
 
         let bytes = b"\x00\x01\x00\x00\x00\x16\x00\x00\x00\x00\x00\x00\x00\x0a\x11\x22\x33\x44\x55\x66\x77\x88\x99\xaa\x00\x00\x00\x00";
         let constants = utf8_constant_pool(vec!["Code"]);
@@ -1637,19 +2348,19 @@ mod tests {
         // Testing the maximum possible code body would take 4GB of memory, so we will settle for
         // testing a body that requires four bytes to hold the size.
         let mut code : Vec<u8> = vec![0; 0x01fffff3];
-        for idx in 0..0x01fffff3 {
+        for (idx, byte) in code.iter_mut().enumerate() {
             // Arbitrary choice of bytes to fill up the vector
-            code[idx] = ((idx as u16) % 256) as u8
+            *byte = ((idx as u16) % 256) as u8
         }
 
-        let expected = Attribute::Code {
+        let expected = Attribute::Code(Box::new(CodeAttribute {
             attribute_name: ConstantIndex(1),
             max_stack: 0,
             max_locals: 0,
             code: code.to_vec(),
             exception_table: vec![],
             attributes: vec![]
-        };
+        }));
 
         let mut bytes  = vec![];
         bytes.append(&mut b"\x00\x01\x01\xff\xff\xff\x00\x00\x00\x00\x01\xff\xff\xf3".to_vec());
@@ -1662,7 +2373,7 @@ mod tests {
 
     #[test]
     fn test_deserialize_code_with_one_exception_table_row() {
-        let expected = Attribute::Code {
+        let expected = Attribute::Code(Box::new(CodeAttribute {
             attribute_name: ConstantIndex(1),
             max_stack: 0,
             max_locals: 0,
@@ -1671,7 +2382,7 @@ mod tests {
                 ExceptionTableRow{start_pc: 0, end_pc:0, handler_pc: 0, catch_type: ConstantIndex(0)},
             ],
             attributes: vec![]
-        };
+        }));
 
         let bytes = b"\x00\x01\x00\x00\x00\x14\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00";
         let constants = utf8_constant_pool(vec!["Code"]);
@@ -1681,7 +2392,7 @@ mod tests {
 
     #[test]
     fn test_deserialize_code_with_nontrivial_exception_table_row() {
-        let expected = Attribute::Code {
+        let expected = Attribute::Code(Box::new(CodeAttribute {
             attribute_name: ConstantIndex(1),
             max_stack: 0,
             max_locals: 0,
@@ -1690,7 +2401,7 @@ mod tests {
                 ExceptionTableRow{start_pc: 0xabcd, end_pc:0xcdef, handler_pc: 0xef12, catch_type: ConstantIndex(0x1234)},
             ],
             attributes: vec![]
-        };
+        }));
 
         let bytes = b"\x00\x01\x00\x00\x00\x14\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\xab\xcd\xcd\xef\xef\x12\x12\x34\x00\x00";
         let constants = utf8_constant_pool(vec!["Code"]);
@@ -1700,27 +2411,27 @@ mod tests {
     #[test]
     fn test_deserialize_code_with_65536_exception_table_rows() {
         let mut exception_table = vec![];
-        for row in 0..0xffff as u16 {
+        for row in 0..0xffff_u16 {
             exception_table.push(ExceptionTableRow {
                 // Values here are chosen arbitrarily to make rows distinct.
-                start_pc: row as u16,
-                end_pc: row.wrapping_add(1) as u16,
-                handler_pc: row.wrapping_add(2) as u16,
-                catch_type: ConstantIndex(row.wrapping_add(3) as u16),
+                start_pc: row,
+                end_pc: row.wrapping_add(1),
+                handler_pc: row.wrapping_add(2),
+                catch_type: ConstantIndex(row.wrapping_add(3)),
             });
         }
 
-        let expected = Attribute::Code {
+        let expected = Attribute::Code(Box::new(CodeAttribute {
             attribute_name: ConstantIndex(1),
             max_stack: 0,
             max_locals: 0,
             code: vec![],
-            exception_table: exception_table,
+            exception_table,
             attributes: vec![]
-        };
+        }));
 
         let mut bytes = b"\x00\x01\x00\x08\x00\x04\x00\x00\x00\x00\x00\x00\x00\x00\xff\xff".to_vec();
-        for row in 0..0xffff as u16 {
+        for row in 0..0xffff_u16 {
             // Bit wrangling to produce ExceptionTableRow data that matches the contents of
             // exception_table as defined at the start of this function.
             bytes.push((row >> 8) as u8);
@@ -1741,14 +2452,14 @@ mod tests {
 
     #[test]
     fn test_deserialize_code_with_one_attribute() {
-        let expected = Attribute::Code {
+        let expected = Attribute::Code(Box::new(CodeAttribute {
             attribute_name: ConstantIndex(1),
             max_stack: 0,
             max_locals: 0,
             code: vec![],
             exception_table: vec![],
             attributes: vec![Attribute::ConstantValue{attribute_name: ConstantIndex(2), constant_value: ConstantIndex(0)}],
-        };
+        }));
 
         let bytes = b"\x00\x01\x00\x00\x00\x14\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x02\x00\x00\x00\x02\x00\x00";
         let constants = utf8_constant_pool(vec!["Code", "ConstantValue"]);
@@ -1766,17 +2477,17 @@ mod tests {
             });
         }
 
-        let expected = Attribute::Code {
+        let expected = Attribute::Code(Box::new(CodeAttribute {
             attribute_name: ConstantIndex(1),
             max_stack: 0,
             max_locals: 0,
             code: vec![],
             exception_table: vec![],
-            attributes: attributes,
-        };
+            attributes,
+        }));
 
         let mut bytes = b"\x00\x01\x00\x08\x00\x04\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\xff\xff".to_vec();
-        for idx in 0..0xffff as u32 {
+        for idx in 0..0xffff_u32 {
             let value_index = idx % 0x10000;
             bytes.append(&mut b"\x00\x02\x00\x00\x00\x02".to_vec());
             bytes.push((value_index >> 8) as u8);
@@ -2953,6 +3664,474 @@ mod tests {
         assert_deserialize(InnerClassFlags::all(), b"\xff\xff");
     }
 
+    #[test]
+    fn test_deserialize_source_file_attribute() {
+        let expected = Attribute::SourceFile {
+            attribute_name: ConstantIndex(1),
+            source_file: ConstantIndex(0x1234),
+        };
+
+        let constants = utf8_constant_pool(vec!["SourceFile"]);
+        let bytes = b"\x00\x01\x00\x00\x00\x02\x12\x34";
+
+        assert_deserialize_with_constants(expected, bytes, &constants);
+    }
+
+    #[test]
+    fn test_deserialize_signature_attribute() {
+        let expected = Attribute::Signature {
+            attribute_name: ConstantIndex(1),
+            signature: ConstantIndex(0xcafe),
+        };
+
+        let constants = utf8_constant_pool(vec!["Signature"]);
+        let bytes = b"\x00\x01\x00\x00\x00\x02\xca\xfe";
+
+        assert_deserialize_with_constants(expected, bytes, &constants);
+    }
+
+    #[test]
+    fn test_deserialize_synthetic_attribute() {
+        let expected = Attribute::Synthetic {attribute_name: ConstantIndex(1)};
+        let constants = utf8_constant_pool(vec!["Synthetic"]);
+        assert_deserialize_with_constants(expected, b"\x00\x01\x00\x00\x00\x00", &constants);
+    }
+
+    #[test]
+    fn test_deserialize_deprecated_attribute() {
+        let expected = Attribute::Deprecated {attribute_name: ConstantIndex(1)};
+        let constants = utf8_constant_pool(vec!["Deprecated"]);
+        assert_deserialize_with_constants(expected, b"\x00\x01\x00\x00\x00\x00", &constants);
+    }
+
+    #[test]
+    fn test_deserialize_deprecated_attribute_with_nonzero_length_throws_error() {
+        let constants = utf8_constant_pool(vec!["Deprecated"]);
+        expect!(ClassLoaderError::LengthMismatch{..} in
+            deserialize_with_constants(Attribute::deserialize, b"\x00\x01\x00\x00\x00\x01", &constants));
+    }
+
+    #[test]
+    fn test_deserialize_enclosing_method_attribute() {
+        let expected = Attribute::EnclosingMethod {
+            attribute_name: ConstantIndex(1),
+            class: ConstantIndex(0x0002),
+            method: ConstantIndex(0x0000),
+        };
+
+        let constants = utf8_constant_pool(vec!["EnclosingMethod"]);
+        let bytes = b"\x00\x01\x00\x00\x00\x04\x00\x02\x00\x00";
+
+        assert_deserialize_with_constants(expected, bytes, &constants);
+    }
+
+    #[test]
+    fn test_deserialize_source_debug_extension_attribute() {
+        let expected = Attribute::SourceDebug {
+            attribute_name: ConstantIndex(1),
+            debug_extension: b"SMAP".to_vec(),
+        };
+
+        let constants = utf8_constant_pool(vec!["SourceDebugExtension"]);
+        let bytes = b"\x00\x01\x00\x00\x00\x04SMAP";
+
+        assert_deserialize_with_constants(expected, bytes, &constants);
+    }
+
+    #[test]
+    fn test_deserialize_source_debug_extension_premature_termination() {
+        let constants = utf8_constant_pool(vec!["SourceDebugExtension"]);
+        assert_eof_with_constants(Attribute::deserialize, b"\x00\x01\x00\x00\x00\x04SMA", &constants);
+    }
+
+    #[test]
+    fn test_deserialize_empty_line_number_table() {
+        let expected = Attribute::LineNumberTable {
+            attribute_name: ConstantIndex(1),
+            table: vec![],
+        };
+
+        let constants = utf8_constant_pool(vec!["LineNumberTable"]);
+        let bytes = b"\x00\x01\x00\x00\x00\x02\x00\x00";
+
+        assert_deserialize_with_constants(expected, bytes, &constants);
+    }
+
+    #[test]
+    fn test_deserialize_line_number_table_with_two_entries() {
+        let expected = Attribute::LineNumberTable {
+            attribute_name: ConstantIndex(1),
+            table: vec![(0x0000, 0x0003), (0x0004, 0x1234)],
+        };
+
+        let constants = utf8_constant_pool(vec!["LineNumberTable"]);
+        let bytes = b"\x00\x01\x00\x00\x00\x0a\x00\x02\x00\x00\x00\x03\x00\x04\x12\x34";
+
+        assert_deserialize_with_constants(expected, bytes, &constants);
+    }
+
+    #[test]
+    fn test_deserialize_line_number_table_premature_termination_during_entry() {
+        let constants = utf8_constant_pool(vec!["LineNumberTable"]);
+        assert_eof_with_constants(Attribute::deserialize, b"\x00\x01\x00\x00\x00\x06\x00\x01\x00\x00\x00", &constants);
+    }
+
+    #[test]
+    fn test_deserialize_inner_classes_attribute() {
+        let expected = Attribute::InnerClasses {
+            attribute_name: ConstantIndex(1),
+            classes: vec![InnerClassInfo {
+                inner_class: ConstantIndex(2),
+                outer_class: ConstantIndex(3),
+                inner_class_name: ConstantIndex(4),
+                flags: InnerClassFlags::PRIVATE | InnerClassFlags::STATIC,
+            }],
+        };
+
+        let constants = utf8_constant_pool(vec!["InnerClasses"]);
+        let bytes = b"\x00\x01\x00\x00\x00\x0a\x00\x01\x00\x02\x00\x03\x00\x04\x00\x0a";
+
+        assert_deserialize_with_constants(expected, bytes, &constants);
+    }
+
+    #[test]
+    fn test_deserialize_local_variable_table() {
+        let expected = Attribute::LocalVariableTable {
+            attribute_name: ConstantIndex(1),
+            variables: vec![LocalVariable {
+                start_pc: 0,
+                length: 0x10,
+                name: ConstantIndex(2),
+                descriptor: ConstantIndex(3),
+                index: 1,
+            }],
+        };
+
+        let constants = utf8_constant_pool(vec!["LocalVariableTable"]);
+        let bytes = b"\x00\x01\x00\x00\x00\x0c\x00\x01\x00\x00\x00\x10\x00\x02\x00\x03\x00\x01";
+
+        assert_deserialize_with_constants(expected, bytes, &constants);
+    }
+
+    #[test]
+    fn test_deserialize_local_variable_type_table() {
+        let expected = Attribute::LocalVariableTypeTable {
+            attribute_name: ConstantIndex(1),
+            variable_types: vec![LocalVariableType {
+                start_pc: 2,
+                length: 3,
+                name: ConstantIndex(4),
+                signature: ConstantIndex(5),
+                index: 6,
+            }],
+        };
+
+        let constants = utf8_constant_pool(vec!["LocalVariableTypeTable"]);
+        let bytes = b"\x00\x01\x00\x00\x00\x0c\x00\x01\x00\x02\x00\x03\x00\x04\x00\x05\x00\x06";
+
+        assert_deserialize_with_constants(expected, bytes, &constants);
+    }
+
+    #[test]
+    fn test_deserialize_runtime_visible_annotations_with_nested_values() {
+        let expected = Attribute::RuntimeVisibleAnnotations {
+            attribute_name: ConstantIndex(1),
+            annotations: vec![Annotation {
+                type_index: ConstantIndex(2),
+                indexes_with_values: vec![
+                    (ConstantIndex(3), ElementValue::Integer(ConstantIndex(4))),
+                    (ConstantIndex(5), ElementValue::Array(vec![
+                        ElementValue::Enum {enum_type: ConstantIndex(6), enum_value: ConstantIndex(7)},
+                        ElementValue::Class(ConstantIndex(8)),
+                    ])),
+                ],
+            }],
+        };
+
+        let constants = utf8_constant_pool(vec!["RuntimeVisibleAnnotations"]);
+        let bytes = b"\x00\x01\x00\x00\x00\x18\x00\x01\x00\x02\x00\x02\x00\x03I\x00\x04\x00\x05[\x00\x02e\x00\x06\x00\x07c\x00\x08";
+
+        assert_deserialize_with_constants(expected, bytes, &constants);
+    }
+
+    #[test]
+    fn test_deserialize_runtime_invisible_parameter_annotations() {
+        let expected = Attribute::RuntimeInvisibleParameterAnnotations {
+            attribute_name: ConstantIndex(1),
+            annotations_by_param_index: vec![
+                ParameterAnnotations(vec![]),
+                ParameterAnnotations(vec![Annotation {type_index: ConstantIndex(2), indexes_with_values: vec![]}]),
+            ],
+        };
+
+        let constants = utf8_constant_pool(vec!["RuntimeInvisibleParameterAnnotations"]);
+        let bytes = b"\x00\x01\x00\x00\x00\x09\x02\x00\x00\x00\x01\x00\x02\x00\x00";
+
+        assert_deserialize_with_constants(expected, bytes, &constants);
+    }
+
+    #[test]
+    fn test_deserialize_annotation_default_with_nested_annotation() {
+        let expected = Attribute::AnnotationDefault {
+            attribute_name: ConstantIndex(1),
+            value: Box::new(ElementValue::Annotation(Annotation {
+                type_index: ConstantIndex(2),
+                indexes_with_values: vec![(ConstantIndex(3), ElementValue::String(ConstantIndex(4)))],
+            })),
+        };
+
+        let constants = utf8_constant_pool(vec!["AnnotationDefault"]);
+        let bytes = b"\x00\x01\x00\x00\x00\x0a@\x00\x02\x00\x01\x00\x03s\x00\x04";
+
+        assert_deserialize_with_constants(expected, bytes, &constants);
+    }
+
+    #[test]
+    fn test_deserialize_annotation_default_with_invalid_tag() {
+        let constants = utf8_constant_pool(vec!["AnnotationDefault"]);
+        deserialize_with_constants_expecting_error(Attribute::deserialize, b"\x00\x01\x00\x00\x00\x03X\x00\x01", &constants, |err| match *err {
+            ClassLoaderError::InvalidElementValueTag(b'X') => (),
+            _ => panic!("Expected invalid element value tag error; got {:#?}", err),
+        });
+    }
+
+    #[test]
+    fn test_deserialize_bootstrap_methods_attribute() {
+        let expected = Attribute::BootstrapMethods {
+            attribute_name: ConstantIndex(1),
+            methods: vec![
+                BootstrapMethod {method: ConstantIndex(2), arguments: vec![]},
+                BootstrapMethod {method: ConstantIndex(3), arguments: vec![ConstantIndex(4), ConstantIndex(5)]},
+            ],
+        };
+
+        let constants = utf8_constant_pool(vec!["BootstrapMethods"]);
+        let bytes = b"\x00\x01\x00\x00\x00\x0e\x00\x02\x00\x02\x00\x00\x00\x03\x00\x02\x00\x04\x00\x05";
+
+        assert_deserialize_with_constants(expected, bytes, &constants);
+    }
+
+    #[test]
+    fn test_deserialize_module_attribute() {
+        let expected = Attribute::Module(Box::new(ModuleAttribute {
+            attribute_name: ConstantIndex(1),
+            name: ConstantIndex(2),
+            flags: ModuleFlags::OPEN,
+            version: ConstantIndex(0),
+            requires: vec![ModuleRequires {module: ConstantIndex(3), flags: RequiresFlags::TRANSITIVE, version: ConstantIndex(4)}],
+            exports: vec![ModulePackage {package: ConstantIndex(5), flags: 0, targets: vec![ConstantIndex(6)]}],
+            opens: vec![],
+            uses: vec![ConstantIndex(7)],
+            provides: vec![ModuleProvides {service: ConstantIndex(7), implementations: vec![ConstantIndex(8)]}],
+        }));
+
+        let constants = utf8_constant_pool(vec!["Module"]);
+        let bytes = b"\x00\x01\x00\x00\x00\x26\x00\x02\x00\x20\x00\x00\
+            \x00\x01\x00\x03\x00\x20\x00\x04\
+            \x00\x01\x00\x05\x00\x00\x00\x01\x00\x06\
+            \x00\x00\
+            \x00\x01\x00\x07\
+            \x00\x01\x00\x07\x00\x01\x00\x08";
+
+        assert_deserialize_with_constants(expected, bytes, &constants);
+    }
+
+    #[test]
+    fn test_deserialize_module_packages_attribute() {
+        let expected = Attribute::ModulePackages {attribute_name: ConstantIndex(1), packages: vec![ConstantIndex(2), ConstantIndex(3)]};
+        let constants = utf8_constant_pool(vec!["ModulePackages"]);
+        assert_deserialize_with_constants(expected, b"\x00\x01\x00\x00\x00\x06\x00\x02\x00\x02\x00\x03", &constants);
+    }
+
+    #[test]
+    fn test_deserialize_nest_host_attribute() {
+        let expected = Attribute::NestHost {attribute_name: ConstantIndex(1), host_class: ConstantIndex(0x1234)};
+        let constants = utf8_constant_pool(vec!["NestHost"]);
+        assert_deserialize_with_constants(expected, b"\x00\x01\x00\x00\x00\x02\x12\x34", &constants);
+    }
+
+    #[test]
+    fn test_deserialize_nest_members_attribute() {
+        let expected = Attribute::NestMembers {attribute_name: ConstantIndex(1), classes: vec![ConstantIndex(2), ConstantIndex(3)]};
+        let constants = utf8_constant_pool(vec!["NestMembers"]);
+        assert_deserialize_with_constants(expected, b"\x00\x01\x00\x00\x00\x06\x00\x02\x00\x02\x00\x03", &constants);
+    }
+
+    #[test]
+    fn test_deserialize_module_and_package_constants() {
+        assert_deserialize(Constant::Module(ConstantIndex(0x1234)), b"\x13\x12\x34");
+        assert_deserialize(Constant::Package(ConstantIndex(0x5678)), b"\x14\x56\x78");
+    }
+
+    #[test]
+    fn test_deserialize_class_flags_ignores_unused_bits() {
+        assert_deserialize(ClassFlags::all(), b"\xff\xff");
+    }
+
+    #[test]
+    fn test_deserialize_method_flags_public_static() {
+        assert_deserialize(MethodFlags::PUBLIC | MethodFlags::STATIC, b"\x00\x09");
+    }
+
+    #[test]
+    fn test_deserialize_field_flags_private_final() {
+        assert_deserialize(FieldFlags::PRIVATE | FieldFlags::FINAL, b"\x00\x12");
+    }
+
+    #[test]
+    fn test_deserialize_field() {
+        let expected = Field {
+            flags: FieldFlags::PRIVATE,
+            name: ConstantIndex(2),
+            descriptor: ConstantIndex(3),
+            attributes: vec![Attribute::Synthetic {attribute_name: ConstantIndex(1)}],
+        };
+
+        let constants = utf8_constant_pool(vec!["Synthetic"]);
+        let bytes = b"\x00\x02\x00\x02\x00\x03\x00\x01\x00\x01\x00\x00\x00\x00";
+
+        assert_deserialize_with_constants(expected, bytes, &constants);
+    }
+
+    #[test]
+    fn test_deserialize_field_skips_unknown_attributes() {
+        let expected = Field {
+            flags: FieldFlags::PRIVATE,
+            name: ConstantIndex(2),
+            descriptor: ConstantIndex(3),
+            attributes: vec![Attribute::Synthetic {attribute_name: ConstantIndex(1)}],
+        };
+
+        // A PermittedSubclasses attribute listing one class, then Synthetic.
+        let constants = utf8_constant_pool(vec!["Synthetic", "PermittedSubclasses"]);
+        let bytes = b"\x00\x02\x00\x02\x00\x03\x00\x02\x00\x02\x00\x00\x00\x04\x00\x01\x00\x05\x00\x01\x00\x00\x00\x00";
+
+        assert_deserialize_with_constants(expected, bytes, &constants);
+    }
+
+    #[test]
+    fn test_deserialize_unknown_attribute_premature_termination() {
+        let constants = utf8_constant_pool(vec!["PermittedSubclasses"]);
+        assert_eof_with_constants(Field::deserialize, b"\x00\x02\x00\x02\x00\x03\x00\x01\x00\x01\x00\x00\x00\x04\x00\x01", &constants);
+    }
+
+    #[test]
+    fn test_deserialize_method_premature_termination_during_attribute_count() {
+        assert_eof_with_constants(Method::deserialize, b"\x00\x01\x00\x02\x00\x03\x00", &[]);
+    }
+
+    #[test]
+    fn test_load_minimal_class() {
+        let expected = Class {
+            minor_version: 0,
+            major_version: 52,
+            constants: vec![
+                Constant::ClassRef(ConstantIndex(2)),
+                Constant::Utf8("Foo".to_string()),
+            ],
+            flags: ClassFlags::PUBLIC | ClassFlags::SUPER,
+            this_class: ConstantIndex(1),
+            super_class: ConstantIndex(0),
+            interfaces: vec![],
+            fields: vec![],
+            methods: vec![],
+            attributes: vec![],
+        };
+
+        let bytes = b"\xca\xfe\xba\xbe\x00\x00\x00\x34\x00\x03\x07\x00\x02\x01\x00\x03Foo\x00\x21\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+        assert_eq!(Ok(expected), load_class(bytes));
+    }
+
+    #[test]
+    fn test_load_class_pads_double_width_constants() {
+        let bytes = b"\xca\xfe\xba\xbe\x00\x00\x00\x34\x00\x04\x05\x00\x00\x00\x00\x00\x00\x00\x2a\x03\x00\x00\x00\x07\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+        let class = load_class(bytes).unwrap();
+        assert_eq!(vec![Constant::Long(42), Constant::Dummy, Constant::Integer(7)], class.constants);
+    }
+
+    #[test]
+    fn test_load_class_with_double_width_constant_overrunning_pool() {
+        let bytes = b"\xca\xfe\xba\xbe\x00\x00\x00\x34\x00\x02\x05\x00\x00\x00\x00\x00\x00\x00\x2a\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+        expect!(ClassLoaderError::Misc(_) in load_class(bytes));
+    }
+
+    #[test]
+    fn test_load_class_with_methods_and_fields() {
+        let bytes = b"\xca\xfe\xba\xbe\x00\x00\x00\x34\x00\x04\x07\x00\x02\x01\x00\x03Foo\x01\x00\x01I\x00\x21\x00\x01\x00\x00\x00\x00\
+            \x00\x01\x00\x08\x00\x03\x00\x03\x00\x00\
+            \x00\x01\x00\x09\x00\x03\x00\x03\x00\x00\
+            \x00\x00";
+        let class = load_class(bytes).unwrap();
+        assert_eq!(vec![Field {flags: FieldFlags::STATIC, name: ConstantIndex(3), descriptor: ConstantIndex(3), attributes: vec![]}], class.fields);
+        assert_eq!(vec![Method {flags: MethodFlags::PUBLIC | MethodFlags::STATIC, name: ConstantIndex(3), descriptor: ConstantIndex(3), attributes: vec![]}], class.methods);
+    }
+
+    #[test]
+    fn test_load_class_with_hostile_counts() {
+        // The constant pool and interface counts are both 0xffff, with nothing after them.
+        expect!(ClassLoaderError::Eof(_) in load_class(b"\xca\xfe\xba\xbe\x00\x00\x00\x34\xff\xff"));
+        expect!(ClassLoaderError::Eof(_) in load_class(b"\xca\xfe\xba\xbe\x00\x00\x00\x34\x00\x01\x00\x21\x00\x00\x00\x00\xff\xff"));
+    }
+
+    #[test]
+    fn test_presized_is_bounded_by_remaining_data() {
+        let data = bytes::Bytes::from(&[0u8; 20][..]).into_buf();
+        assert_eq!(2, presized::<Field>(0xffff, Field::MIN_SIZE, &data).capacity());
+        assert_eq!(3, presized::<Field>(3, 1, &data).capacity());
+    }
+
+    #[test]
+    fn test_load_class_header() {
+        // Nothing follows the interfaces, since load_class_header doesn't read that far.
+        let bytes = b"\xca\xfe\xba\xbe\x00\x03\x00\x34\x00\x09\x05\x00\x00\x00\x00\x00\x00\x00\x2a\x07\x00\x04\x01\x00\x03Foo\
+            \x07\x00\x06\x01\x00\x10java/lang/Object\x07\x00\x08\x01\x00\x03Bar\x06\x21\x00\x03\x00\x05\x00\x01\x00\x07";
+        let expected = ClassHeader {
+            minor_version: 3,
+            major_version: 52,
+            flags: ClassFlags::PUBLIC | ClassFlags::SUPER | ClassFlags::INTERFACE | ClassFlags::ABSTRACT,
+            name: "Foo".to_string(),
+            super_class: Some("java/lang/Object".to_string()),
+            interfaces: vec!["Bar".to_string()],
+        };
+        assert_eq!(Ok(expected), load_class_header(bytes));
+
+        let minimal = b"\xca\xfe\xba\xbe\x00\x00\x00\x34\x00\x03\x07\x00\x02\x01\x00\x03Foo\x00\x21\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+        let class = load_class(minimal).unwrap();
+        let header = load_class_header(minimal).unwrap();
+        assert_eq!((class.name().unwrap(), None), (header.name.as_str(), header.super_class));
+    }
+
+    #[test]
+    fn test_load_class_header_with_bad_references() {
+        let header = |this_class: u8| {
+            let mut bytes = b"\xca\xfe\xba\xbe\x00\x00\x00\x34\x00\x05\x06\x00\x00\x00\x00\x00\x00\x00\x00\x07\x00\x04\x01\x00\x01A\x00\x21\x00".to_vec();
+            bytes.extend_from_slice(&[this_class, 0, 0, 0, 0]);
+            load_class_header(&bytes)
+        };
+        assert_eq!("A", header(3).unwrap().name);
+        expect!(ClassLoaderError::InvalidConstantRef(ConstantLookupError::IndexInsideDoubleWidthConstant(2)) in header(2));
+        expect!(ClassLoaderError::InvalidConstantRef(ConstantLookupError::UnexpectedType(4)) in header(4));
+        expect!(ClassLoaderError::InvalidConstantRef(ConstantLookupError::OutOfRange(9)) in header(9));
+        expect!(ClassLoaderError::InvalidConstantType(2) in load_class_header(b"\xca\xfe\xba\xbe\x00\x00\x00\x34\x00\x02\x02"));
+        expect!(ClassLoaderError::Eof(_) in load_class_header(b"\xca\xfe\xba\xbe\x00\x00\x00\x34\xff\xff\x01\x00\x05ab"));
+    }
+
+    #[test]
+    fn test_load_class_with_invalid_magic() {
+        expect!(ClassLoaderError::InvalidMagic(0xcafed00d) in load_class(b"\xca\xfe\xd0\x0d\x00\x00\x00\x34"));
+    }
+
+    #[test]
+    fn test_load_class_premature_termination_during_version() {
+        expect!(ClassLoaderError::Eof(_) in load_class(b"\xca\xfe\xba\xbe\x00\x00\x00"));
+    }
+
+    #[test]
+    fn test_load_class_with_trailing_data() {
+        let bytes = b"\xca\xfe\xba\xbe\x00\x00\x00\x34\x00\x01\x00\x21\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\xff";
+        expect!(ClassLoaderError::TrailingData(1) in load_class(bytes));
+    }
+
     fn do_float_test(float_bits: u32, input: &[u8]) {
         assert_deserialize(Constant::Float(f32::from_bits(float_bits)), input);
     }
@@ -2969,21 +4148,21 @@ mod tests {
         assert_eq!(Ok(expected), D::deserialize(&mut bytes::Bytes::from(input).into_buf()));
     }
 
-    fn assert_deserialize_with_constants<D: DeserializeWithConstants+Debug+PartialEq>(expected: D, input: &[u8], constants: &Vec<Constant>) {
+    fn assert_deserialize_with_constants<D: DeserializeWithConstants+Debug+PartialEq>(expected: D, input: &[u8], constants: &[Constant]) {
         assert_eq!(Ok(expected), D::deserialize(&mut bytes::Bytes::from(input).into_buf(), constants));
     }
 
     fn assert_eof<D: Deserialize+Debug, F> (deserializer: F, input: &[u8])
-        where F: Fn(&mut bytes::Buf) -> Result<D, ClassLoaderError> {
+        where F: Fn(&mut dyn bytes::Buf) -> Result<D, ClassLoaderError> {
             expect!(ClassLoaderError::Eof(_) in deserialize(deserializer, input));
     }
 
-    fn assert_eof_with_constants<D: DeserializeWithConstants+Debug, F> (deserializer: F, input: &[u8], constants: &Vec<Constant>)
-        where F: Fn(&mut bytes::Buf, &Vec<Constant>) -> Result<D, ClassLoaderError> {
+    fn assert_eof_with_constants<D: DeserializeWithConstants+Debug, F> (deserializer: F, input: &[u8], constants: &[Constant])
+        where F: Fn(&mut dyn bytes::Buf, &[Constant]) -> Result<D, ClassLoaderError> {
             expect!(ClassLoaderError::Eof(_) in deserialize_with_constants(deserializer, input, constants));
     }
 
-    fn assert_invalid_attribute_type(input: &[u8], constants: &Vec<Constant>) {
+    fn assert_invalid_attribute_type(input: &[u8], constants: &[Constant]) {
         expect!(ClassLoaderError::InvalidAttributeType(_) in deserialize_with_constants(Attribute::deserialize, input, constants));
     }
 
@@ -2992,38 +4171,38 @@ mod tests {
     }
 
     fn deserialize_expecting_error<D: Deserialize+fmt::Debug, F, G>(deserializer: F, input: &[u8], handler: G) where
-        F: Fn(&mut bytes::Buf) -> Result<D, ClassLoaderError>,
+        F: Fn(&mut dyn bytes::Buf) -> Result<D, ClassLoaderError>,
         G: Fn(&ClassLoaderError) {
             let res = deserializer(&mut bytes::Bytes::from(input).into_buf());
             match res {
                 Ok(ref res) => panic!("Expected error, but got result {:#?}", res),
-                Err(ref err) => handler(&err),
+                Err(ref err) => handler(err),
             }
     }
 
-    fn deserialize_with_constants_expecting_error<D: DeserializeWithConstants+Debug, F, G>(deserializer: F, input: &[u8], constants: &Vec<Constant>, handler: G) where
-        F: Fn(&mut bytes::Buf, &Vec<Constant>) -> Result<D, ClassLoaderError>,
+    fn deserialize_with_constants_expecting_error<D: DeserializeWithConstants+Debug, F, G>(deserializer: F, input: &[u8], constants: &[Constant], handler: G) where
+        F: Fn(&mut dyn bytes::Buf, &[Constant]) -> Result<D, ClassLoaderError>,
         G: Fn(&ClassLoaderError) {
             let res = deserializer(&mut bytes::Bytes::from(input).into_buf(), constants);
             match res{
                 Ok(ref res) => panic!("Expected error, but got result {:#?}", res),
-                Err(ref err) => handler(&err),
+                Err(ref err) => handler(err),
             }
     }
 
     fn deserialize<D: Deserialize, F>(deserializer: F, input: &[u8]) -> Result<D, ClassLoaderError> where
-        F: Fn(&mut bytes::Buf) -> Result<D, ClassLoaderError>
+        F: Fn(&mut dyn bytes::Buf) -> Result<D, ClassLoaderError>
     {
-        return deserializer(&mut bytes::Bytes::from(input).into_buf());
+        deserializer(&mut bytes::Bytes::from(input).into_buf())
     }
 
-    fn deserialize_with_constants<D: DeserializeWithConstants, F>(deserializer: F, input: &[u8], constants: &Vec<Constant>) -> Result<D, ClassLoaderError> where
-        F: Fn(&mut bytes::Buf, &Vec<Constant>) -> Result<D, ClassLoaderError>
+    fn deserialize_with_constants<D: DeserializeWithConstants, F>(deserializer: F, input: &[u8], constants: &[Constant]) -> Result<D, ClassLoaderError> where
+        F: Fn(&mut dyn bytes::Buf, &[Constant]) -> Result<D, ClassLoaderError>
     {
-        return deserializer(&mut bytes::Bytes::from(input).into_buf(), constants);
+        deserializer(&mut bytes::Bytes::from(input).into_buf(), constants)
     }
 
     fn utf8_constant_pool(strings: Vec<&str>) -> Vec<Constant> {
-        return strings.iter().map(|s| Constant::Utf8(s.to_string())).collect();
+        strings.iter().map(|s| Constant::Utf8(s.to_string())).collect()
     }
 }
@@ -0,0 +1,294 @@
+use crate::jar::{JarSource, Manifest};
+use crate::jimage::JimageError;
+use crate::zip::ZipError;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::{error, fmt, fs, io};
+
+// Somewhere class files and other resources can be found, by binary name for classes (e.g.
+// java/lang/String) and by slash-separated path for resources (e.g. META-INF/MANIFEST.MF).
+pub trait ClassSource {
+    // Returns the contents of the resource, or None if this source doesn't have it.
+    fn find_resource(&self, path: &str) -> Result<Option<Vec<u8>>, ClasspathError>;
+
+    // Returns the bytes of the named class, or None if this source doesn't have it.
+    fn find_class(&self, name: &str) -> Result<Option<Vec<u8>>, ClasspathError> {
+        self.find_resource(&class_file_name(name)?)
+    }
+
+    // Sets the Java version to find classes for, for sources such as multi-release JARs that
+    // provide different classes to different versions.
+    fn set_runtime_version(&mut self, _version: u32) {}
+
+    // The file or directory this source reads, if it reads one. Class data archives record it,
+    // to tell later whether the classes they hold have changed.
+    fn origin(&self) -> Option<&Path> {
+        None
+    }
+
+    // The manifest of the JAR this source reads, which describes the packages in it.
+    fn manifest(&self) -> Option<&Manifest> {
+        None
+    }
+}
+
+// A directory laid out by package, as javac -d writes it: com/example/Foo is found at
+// <root>/com/example/Foo.class.
+pub struct DirectorySource {
+    root: PathBuf,
+}
+
+impl DirectorySource {
+    pub fn new<P: Into<PathBuf>>(root: P) -> DirectorySource {
+        DirectorySource {root: root.into()}
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl ClassSource for DirectorySource {
+    fn origin(&self) -> Option<&Path> {
+        Some(&self.root)
+    }
+
+    fn find_resource(&self, path: &str) -> Result<Option<Vec<u8>>, ClasspathError> {
+        check_resource_path(path)?;
+        let path = self.root.join(path);
+        match fs::read(&path) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(ClasspathError::Io{path, kind: err.kind()}),
+        }
+    }
+}
+
+// Checks that a resource path can't refer to anything outside the directory or archive it's
+// looked up in.
+pub fn check_resource_path(path: &str) -> Result<(), ClasspathError> {
+    let valid = !path.is_empty() && path.split('/').all(|part| !part.is_empty() && part != "." && part != "..")
+        && !path.contains(['\\', '\0']);
+    if !valid {
+        return Err(ClasspathError::InvalidName(path.to_string()));
+    }
+    Ok(())
+}
+
+// The path of a class's file, relative to the root of the directory or archive.
+pub fn class_file_name(name: &str) -> Result<String, ClasspathError> {
+    if name.contains(['[', ';']) {
+        return Err(ClasspathError::InvalidName(name.to_string()));
+    }
+    check_resource_path(name)?;
+    Ok(format!("{}.class", name))
+}
+
+// An ordered list of sources, searched first to last, like the JVM's -classpath.
+#[derive(Default)]
+pub struct Classpath {
+    sources: Vec<Box<dyn ClassSource>>,
+    runtime_version: Option<u32>, // Passed on to each source, if it's been set.
+    jars: HashSet<PathBuf>, // Those added by push_path, to avoid following Class-Path cycles.
+}
+
+impl Classpath {
+    pub fn new() -> Classpath {
+        Classpath::default()
+    }
+
+    // Parses a classpath string, with entries separated as in the platform's PATH variable. Each
+    // entry is a directory or a JAR file.
+    pub fn parse<S: AsRef<OsStr> + ?Sized>(classpath: &S) -> Result<Classpath, ClasspathError> {
+        let mut result = Classpath::new();
+        for entry in std::env::split_paths(classpath).filter(|entry| !entry.as_os_str().is_empty()) {
+            result.push_path(entry)?;
+        }
+        Ok(result)
+    }
+
+    // Adds a directory or a JAR file. A JAR is followed by the entries named by the Class-Path
+    // attribute of its manifest, and theirs in turn; as in the JDK, those that don't exist are
+    // skipped, and each JAR is only added once.
+    pub fn push_path<P: Into<PathBuf>>(&mut self, path: P) -> Result<(), ClasspathError> {
+        let path = path.into();
+        if path.is_dir() {
+            self.push(DirectorySource::new(path));
+            return Ok(());
+        }
+
+        let mut pending = vec![path];
+        while let Some(path) = pending.pop() {
+            let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+            if !self.jars.insert(canonical) {
+                continue;
+            }
+            let jar = JarSource::open(&path)?;
+            let referenced = jar.class_path();
+            self.push(jar);
+            for entry in referenced.into_iter().rev() {
+                if entry.is_dir() {
+                    self.push(DirectorySource::new(entry));
+                } else if entry.is_file() {
+                    pending.push(entry);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn push<S: ClassSource + 'static>(&mut self, source: S) {
+        let source = self.prepare(source);
+        self.sources.push(source);
+    }
+
+    fn prepare<S: ClassSource + 'static>(&self, source: S) -> Box<dyn ClassSource> {
+        let mut source = Box::new(source);
+        if let Some(version) = self.runtime_version {
+            source.set_runtime_version(version);
+        }
+        source
+    }
+
+    pub fn source(&self, index: usize) -> Option<&dyn ClassSource> {
+        self.sources.get(index).map(|source| source.as_ref())
+    }
+
+    // The origin of each source, in order.
+    pub fn origins(&self) -> Vec<Option<&Path>> {
+        self.sources.iter().map(|source| source.origin()).collect()
+    }
+
+    // Finds a class as find_class does, also returning the index of the source it came from.
+    pub fn find_class_in_source(&self, name: &str) -> Result<Option<(usize, Vec<u8>)>, ClasspathError> {
+        for (index, source) in self.sources.iter().enumerate() {
+            if let Some(bytes) = source.find_class(name)? {
+                return Ok(Some((index, bytes)));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+}
+
+impl ClassSource for Classpath {
+    // Applies to the sources already added and to those added later.
+    fn set_runtime_version(&mut self, version: u32) {
+        self.runtime_version = Some(version);
+        for source in &mut self.sources {
+            source.set_runtime_version(version);
+        }
+    }
+
+    fn find_resource(&self, path: &str) -> Result<Option<Vec<u8>>, ClasspathError> {
+        for source in &self.sources {
+            if let Some(bytes) = source.find_resource(path)? {
+                return Ok(Some(bytes));
+            }
+        }
+        Ok(None)
+    }
+
+    fn find_class(&self, name: &str) -> Result<Option<Vec<u8>>, ClasspathError> {
+        for source in &self.sources {
+            if let Some(bytes) = source.find_class(name)? {
+                return Ok(Some(bytes));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ClasspathError {
+    Archive{path: PathBuf, cause: ZipError},
+    Image{path: PathBuf, cause: JimageError},
+    InvalidName(String),
+    Io{path: PathBuf, kind: io::ErrorKind},
+    #[cfg(feature = "remote")]
+    Remote{url: String, message: String}, // Fetching a URL failed.
+    WrongName{expected: String, found: String}, // The class file found for a name declares a different one.
+}
+
+impl fmt::Display for ClasspathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ClasspathError::Archive{ref path, ref cause} => write!(f, "Failed to read archive {}: {}", path.display(), cause),
+            ClasspathError::Image{ref path, ref cause} => write!(f, "Failed to read image {}: {}", path.display(), cause),
+            ClasspathError::InvalidName(ref name) => write!(f, "Invalid class or resource name '{}'", name),
+            ClasspathError::Io{ref path, ref kind} => write!(f, "Failed to read {}: {:?}", path.display(), kind),
+            #[cfg(feature = "remote")]
+            ClasspathError::Remote{ref url, ref message} => write!(f, "Failed to fetch {}: {}", url, message),
+            ClasspathError::WrongName{ref expected, ref found} => write!(f, "{} (wrong name: {})", expected, found),
+        }
+    }
+}
+
+impl error::Error for ClasspathError {
+    fn description(&self) -> &str {
+        match *self {
+            ClasspathError::Archive{..} => "Failed to read archive",
+            ClasspathError::Image{..} => "Failed to read image",
+            ClasspathError::InvalidName(_) => "Invalid class or resource name",
+            ClasspathError::Io{..} => "Failed to read from the classpath",
+            #[cfg(feature = "remote")]
+            ClasspathError::Remote{..} => "Failed to fetch from the classpath",
+            ClasspathError::WrongName{..} => "Class file has the wrong name",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            ClasspathError::Archive{ref cause, ..} => Some(cause),
+            ClasspathError::Image{ref cause, ..} => Some(cause),
+            ClasspathError::InvalidName(_) => None,
+            ClasspathError::Io{..} => None,
+            #[cfg(feature = "remote")]
+            ClasspathError::Remote{..} => None,
+            ClasspathError::WrongName{..} => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bootstrap_dir() -> DirectorySource {
+        DirectorySource::new(concat!(env!("CARGO_MANIFEST_DIR"), "/bootstrap"))
+    }
+
+    #[test]
+    fn test_directory_source_finds_classes_by_package() {
+        let bytes = bootstrap_dir().find_class("java/lang/Object").unwrap().unwrap();
+        assert_eq!(&[0xca, 0xfe, 0xba, 0xbe], &bytes[..4]);
+        assert_eq!(Ok(None), bootstrap_dir().find_class("java/lang/Missing"));
+        assert_eq!(Ok(None), bootstrap_dir().find_class("Object"));
+    }
+
+    #[test]
+    fn test_names_cannot_escape_the_root() {
+        for name in &["../Cargo", "java/../../Cargo", "/etc/passwd", "", "java//lang", "[Ljava/lang/Object;"] {
+            assert_eq!(Err(ClasspathError::InvalidName(name.to_string())), bootstrap_dir().find_class(name));
+        }
+    }
+
+    #[test]
+    fn test_classpath_searches_in_order() {
+        let testdata = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata");
+        let bootstrap = concat!(env!("CARGO_MANIFEST_DIR"), "/bootstrap");
+        let classpath = Classpath::parse(&std::env::join_paths([testdata, "", bootstrap]).unwrap()).unwrap();
+        assert_eq!(2, classpath.len());
+        assert!(classpath.find_class("Hashing").unwrap().is_some());
+        assert!(classpath.find_class("java/lang/String").unwrap().is_some());
+        assert_eq!(Ok(None), classpath.find_class("com/example/Missing"));
+    }
+}
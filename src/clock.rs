@@ -0,0 +1,136 @@
+use crate::runtime::*;
+use crate::vm::{Vm, VmError};
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Where System.currentTimeMillis and System.nanoTime get the time. The default is the host's
+// clock; embedders can install another with Vm::set_clock, say to run a simulation against time
+// that they control.
+pub trait Clock {
+    // Milliseconds since the Unix epoch.
+    fn current_time_millis(&mut self) -> i64;
+
+    // Nanoseconds since a fixed but arbitrary origin, for measuring elapsed time.
+    fn nano_time(&mut self) -> i64;
+
+    // Lets the duration pass, for the cooperative scheduler when every thread is sleeping.
+    fn sleep(&mut self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+pub struct HostClock {
+    origin: Instant,
+}
+
+impl HostClock {
+    pub fn new() -> HostClock {
+        HostClock {origin: Instant::now()}
+    }
+}
+
+impl Default for HostClock {
+    fn default() -> HostClock {
+        HostClock::new()
+    }
+}
+
+impl Clock for HostClock {
+    fn current_time_millis(&mut self) -> i64 {
+        match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_millis() as i64,
+            Err(err) => -(err.duration().as_millis() as i64), // The host's clock is set before 1970.
+        }
+    }
+
+    fn nano_time(&mut self) -> i64 {
+        self.origin.elapsed().as_nanos() as i64
+    }
+}
+
+// A clock that only moves when it's told to. Clones share the same time, so one can be installed
+// in the VM and another kept to move it along.
+#[derive(Clone, Default)]
+pub struct ManualClock(Rc<Cell<i64>>); // Nanoseconds since the Unix epoch.
+
+impl ManualClock {
+    pub fn new(millis: i64) -> ManualClock {
+        let clock = ManualClock::default();
+        clock.set_millis(millis);
+        clock
+    }
+
+    pub fn set_millis(&self, millis: i64) {
+        self.0.set(millis * 1_000_000);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.0.set(self.0.get() + by.as_nanos() as i64);
+    }
+}
+
+impl Clock for ManualClock {
+    fn current_time_millis(&mut self) -> i64 {
+        self.0.get().div_euclid(1_000_000)
+    }
+
+    fn nano_time(&mut self) -> i64 {
+        self.0.get()
+    }
+
+    // Sleeping threads wake at once, with the clock moved on as though they had slept.
+    fn sleep(&mut self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+pub fn invoke_native(vm: &mut Vm, class: &str, name: &str, descriptor: &str, args: &[Value]) -> Result<Option<Value>, VmError> {
+    match (class, name, descriptor, args) {
+        ("java/lang/System", "currentTimeMillis", "()J", &[]) => Ok(Some(Value::Long(vm.clock_mut().current_time_millis()))),
+        ("java/lang/System", "nanoTime", "()J", &[]) => Ok(Some(Value::Long(vm.clock_mut().nano_time()))),
+        _ => Err(VmError::Unsupported(format!("native method {}.{}{}", class, name, descriptor))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+
+
+    fn read(vm: &mut Vm, method: &str) -> i64 {
+        match vm.invoke_static("Randomness", method, "()J", vec![]) {
+            Ok(Some(Value::Long(time))) => time,
+            other => panic!("Expected a time; got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_manual_clock() {
+        let mut vm = vm_with(&[fixture!("Randomness")]);
+        let clock = ManualClock::new(1_600_000_000_000);
+        vm.set_clock(Box::new(clock.clone()));
+        assert_eq!(1_600_000_000_000, read(&mut vm, "currentTimeMillis"));
+        let start = read(&mut vm, "nanoTime");
+
+        clock.advance(Duration::from_micros(2500));
+        assert_eq!(1_600_000_000_002, read(&mut vm, "currentTimeMillis"));
+        assert_eq!(start + 2_500_000, read(&mut vm, "nanoTime"));
+
+        clock.set_millis(-1);
+        assert_eq!(-1, read(&mut vm, "currentTimeMillis"));
+    }
+
+    #[test]
+    fn test_host_clock() {
+        let mut vm = vm_with(&[fixture!("Randomness")]);
+        let before = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+        let millis = read(&mut vm, "currentTimeMillis");
+        let after = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+        assert!(before <= millis && millis <= after);
+
+        let first = read(&mut vm, "nanoTime");
+        assert!(first <= read(&mut vm, "nanoTime"));
+    }
+}
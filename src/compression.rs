@@ -0,0 +1,284 @@
+use crate::console;
+use crate::deflate::{DeflateStream, Flush};
+use crate::heap::{ObjectRef, WeakObjectRef};
+use crate::inflate::{self, InflateStream};
+use crate::runtime::*;
+use crate::vm::{Vm, VmError};
+use crate::zip;
+use std::collections::HashMap;
+
+// The natives of java.util.zip: the CRC32 and Adler32 checksums, and the streams behind Inflater
+// and Deflater. As in the JDK, where they're zlib's z_streams, Java code refers to a stream by an
+// address that the VM hands out. A stream is freed by end(), or else along with its owner.
+#[derive(Default)]
+pub struct ZipStreams {
+    streams: HashMap<i64, (WeakObjectRef, ZipStream)>,
+    next_address: i64,
+}
+
+pub enum ZipStream {
+    Inflate(InflateStream),
+    Deflate(DeflateStream),
+}
+
+impl ZipStreams {
+    pub fn new() -> ZipStreams {
+        ZipStreams::default()
+    }
+
+    pub fn add(&mut self, owner: &ObjectRef, stream: ZipStream) -> i64 {
+        self.next_address += 1;
+        self.streams.insert(self.next_address, (owner.downgrade(), stream));
+        self.next_address
+    }
+
+    pub fn get_mut(&mut self, address: i64) -> Option<&mut ZipStream> {
+        self.streams.get_mut(&address).map(|(_, stream)| stream)
+    }
+
+    pub fn remove(&mut self, address: i64) -> Option<ZipStream> {
+        self.streams.remove(&address).map(|(_, stream)| stream)
+    }
+
+    pub fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+
+    // Frees the streams whose Inflater or Deflater has been freed without calling end(). Returns
+    // how many were freed.
+    pub fn free_unowned(&mut self) -> usize {
+        let before = self.streams.len();
+        self.streams.retain(|_, (owner, _)| owner.upgrade().is_some());
+        before - self.streams.len()
+    }
+}
+
+pub fn invoke_native(vm: &mut Vm, class: &str, name: &str, descriptor: &str, args: &[Value]) -> Result<Option<Value>, VmError> {
+    match (class, name, descriptor, args) {
+        ("java/util/zip/CRC32", "update", "(II)I", &[Value::Int(crc), Value::Int(byte)]) => {
+            Ok(Some(Value::Int(zip::update_crc32(crc as u32, &[byte as u8]) as i32)))
+        },
+        ("java/util/zip/CRC32", "updateBytes", "(I[BII)I", &[Value::Int(crc), Value::Reference(ref bytes), Value::Int(offset), Value::Int(length)]) => {
+            let data = read_bytes(vm, bytes, offset, length)?;
+            Ok(Some(Value::Int(zip::update_crc32(crc as u32, &data) as i32)))
+        },
+        ("java/util/zip/Adler32", "update", "(II)I", &[Value::Int(adler), Value::Int(byte)]) => {
+            Ok(Some(Value::Int(inflate::adler32(adler as u32, &[byte as u8]) as i32)))
+        },
+        ("java/util/zip/Adler32", "updateBytes", "(I[BII)I", &[Value::Int(adler), Value::Reference(ref bytes), Value::Int(offset), Value::Int(length)]) => {
+            let data = read_bytes(vm, bytes, offset, length)?;
+            Ok(Some(Value::Int(inflate::adler32(adler as u32, &data) as i32)))
+        },
+
+        ("java/util/zip/Inflater", "init", "(Z)J", &[Value::Reference(Some(ref this)), Value::Int(nowrap)]) => {
+            let address = vm.zip_streams_mut().add(this, ZipStream::Inflate(InflateStream::new(nowrap == 0)));
+            Ok(Some(Value::Long(address)))
+        },
+        ("java/util/zip/Deflater", "init", "(IZ)J", &[Value::Reference(Some(ref this)), Value::Int(level), Value::Int(nowrap)]) => {
+            let address = vm.zip_streams_mut().add(this, ZipStream::Deflate(DeflateStream::new(level, nowrap == 0)));
+            Ok(Some(Value::Long(address)))
+        },
+        ("java/util/zip/Inflater" | "java/util/zip/Deflater", "setInput", "(J[BII)V", &[Value::Long(address), Value::Reference(ref bytes), Value::Int(offset), Value::Int(length)]) => {
+            let data = read_bytes(vm, bytes, offset, length)?;
+            match stream(vm, address)? {
+                ZipStream::Inflate(stream) => stream.add_input(&data),
+                ZipStream::Deflate(stream) => stream.add_input(&data),
+            }
+            Ok(None)
+        },
+        ("java/util/zip/Inflater", "inflateBytes", "(J[BII)I", &[Value::Long(address), Value::Reference(ref bytes), Value::Int(offset), Value::Int(length)]) => {
+            let (array, range) = console::region(vm, bytes, offset, length)?;
+            let mut buffer = vec![0; range.len()];
+            let result = match stream(vm, address)? {
+                ZipStream::Inflate(stream) => stream.take(&mut buffer),
+                ZipStream::Deflate(_) => return Err(closed(vm)),
+            };
+            let count = match result {
+                Ok(count) => count,
+                Err(err) => return Err(vm.throw_new_with_message("java/util/zip/DataFormatException", &err.to_string())),
+            };
+            write_bytes(&array, range.start, &buffer[..count]);
+            Ok(Some(Value::Int(count as i32)))
+        },
+        ("java/util/zip/Deflater", "deflateBytes", "(J[BIII)I", &[Value::Long(address), Value::Reference(ref bytes), Value::Int(offset), Value::Int(length), Value::Int(flush)]) => {
+            let (array, range) = console::region(vm, bytes, offset, length)?;
+            let mut buffer = vec![0; range.len()];
+            // Deflater.SYNC_FLUSH and FULL_FLUSH. Blocks never refer back to earlier ones, so the
+            // two are the same here.
+            let flush = if flush == 2 || flush == 3 { Flush::Sync } else { Flush::None };
+            let count = match stream(vm, address)? {
+                ZipStream::Deflate(stream) => stream.deflate(&mut buffer, flush),
+                ZipStream::Inflate(_) => return Err(closed(vm)),
+            };
+            write_bytes(&array, range.start, &buffer[..count]);
+            Ok(Some(Value::Int(count as i32)))
+        },
+        ("java/util/zip/Deflater", "setLevel", "(JI)V", &[Value::Long(address), Value::Int(level)]) => {
+            if let ZipStream::Deflate(stream) = stream(vm, address)? {
+                stream.set_level(level);
+            }
+            Ok(None)
+        },
+        ("java/util/zip/Deflater", "finish", "(J)V", &[Value::Long(address)]) => {
+            if let ZipStream::Deflate(stream) = stream(vm, address)? {
+                stream.finish();
+            }
+            Ok(None)
+        },
+        ("java/util/zip/Inflater", "getRemaining", "(J)I", &[Value::Long(address)]) => {
+            let remaining = match stream(vm, address)? {
+                ZipStream::Inflate(stream) => stream.remaining(),
+                ZipStream::Deflate(_) => 0,
+            };
+            Ok(Some(Value::Int(remaining as i32)))
+        },
+        ("java/util/zip/Inflater", "needsInput", "(J)Z", &[Value::Long(address)]) => {
+            let needs_input = match stream(vm, address)? {
+                ZipStream::Inflate(stream) => stream.needs_input(),
+                ZipStream::Deflate(_) => true, // Deflaters take all their input straight away.
+            };
+            Ok(Some(Value::Int(needs_input as i32)))
+        },
+        ("java/util/zip/Inflater" | "java/util/zip/Deflater", "finished", "(J)Z", &[Value::Long(address)]) => {
+            let finished = match stream(vm, address)? {
+                ZipStream::Inflate(stream) => stream.finished(),
+                ZipStream::Deflate(stream) => stream.finished(),
+            };
+            Ok(Some(Value::Int(finished as i32)))
+        },
+        ("java/util/zip/Inflater" | "java/util/zip/Deflater", "getAdler", "(J)I", &[Value::Long(address)]) => {
+            let adler = match stream(vm, address)? {
+                ZipStream::Inflate(stream) => stream.adler(),
+                ZipStream::Deflate(stream) => stream.adler(),
+            };
+            Ok(Some(Value::Int(adler as i32)))
+        },
+        ("java/util/zip/Inflater" | "java/util/zip/Deflater", "getBytesRead" | "getBytesWritten", "(J)J", &[Value::Long(address)]) => {
+            let (read, written) = match stream(vm, address)? {
+                ZipStream::Inflate(stream) => stream.totals(),
+                ZipStream::Deflate(stream) => stream.totals(),
+            };
+            Ok(Some(Value::Long(if name == "getBytesRead" { read } else { written } as i64)))
+        },
+        ("java/util/zip/Inflater", "reset", "(JZ)V", &[Value::Long(address), Value::Int(nowrap)]) => {
+            *stream(vm, address)? = ZipStream::Inflate(InflateStream::new(nowrap == 0));
+            Ok(None)
+        },
+        ("java/util/zip/Deflater", "reset", "(JIZ)V", &[Value::Long(address), Value::Int(level), Value::Int(nowrap)]) => {
+            *stream(vm, address)? = ZipStream::Deflate(DeflateStream::new(level, nowrap == 0));
+            Ok(None)
+        },
+        ("java/util/zip/Inflater" | "java/util/zip/Deflater", "end", "(J)V", &[Value::Long(address)]) => {
+            vm.zip_streams_mut().remove(address);
+            Ok(None)
+        },
+        _ => Err(VmError::Unsupported(format!("native method {}.{}{}", class, name, descriptor))),
+    }
+}
+
+fn stream(vm: &mut Vm, address: i64) -> Result<&mut ZipStream, VmError> {
+    if vm.zip_streams_mut().get_mut(address).is_none() {
+        return Err(closed(vm));
+    }
+    Ok(vm.zip_streams_mut().get_mut(address).unwrap())
+}
+
+// The Java side checks that a stream is open before using it, so this only happens if the
+// address is bad.
+fn closed(vm: &mut Vm) -> VmError {
+    vm.throw_new_with_message("java/lang/NullPointerException", "Stream has been closed")
+}
+
+fn read_bytes(vm: &mut Vm, bytes: &Option<ObjectRef>, offset: i32, length: i32) -> Result<Vec<u8>, VmError> {
+    let (array, range) = console::region(vm, bytes, offset, length)?;
+    let data = array.fields.borrow().bytes().map_or_else(Vec::new, |bytes| bytes[range].iter().map(|&b| b as u8).collect());
+    Ok(data)
+}
+
+fn write_bytes(array: &ObjectRef, offset: usize, data: &[u8]) {
+    if let Some(elements) = array.fields.borrow_mut().bytes_mut() {
+        for (element, &byte) in elements[offset..].iter_mut().zip(data) {
+            *element = byte as i8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use crate::gc::GcCause;
+
+
+    fn byte_array(vm: &mut Vm, bytes: &[u8]) -> ObjectRef {
+        let class = vm.load_class("[B").unwrap();
+        let array = vm.new_array(&class, bytes.len()).unwrap();
+        write_bytes(&array, 0, bytes);
+        array
+    }
+
+    fn contents(array: &ObjectRef) -> Vec<u8> {
+        array.fields.borrow().bytes().unwrap().iter().map(|&b| b as u8).collect()
+    }
+
+    #[test]
+    fn test_checksums() {
+        let mut vm = vm_with(&[fixture!("Compression")]);
+        let hello = Value::Reference(Some(byte_array(&mut vm, b"hello")));
+        assert_eq!(Ok(Some(Value::Long(0x3610a686))), vm.invoke_static("Compression", "crc32", "([B)J", vec![hello]));
+        let wikipedia = Value::Reference(Some(byte_array(&mut vm, b"Wikipedia")));
+        assert_eq!(Ok(Some(Value::Long(0x11e60398))), vm.invoke_static("Compression", "adler32", "([B)J", vec![wikipedia]));
+    }
+
+    #[test]
+    fn test_deflate_and_inflate_round_trip() {
+        let mut vm = vm_with(&[fixture!("Compression")]);
+        let data: Vec<u8> = (0..300).map(|i| format!("line {}\n", i % 40)).collect::<String>().into_bytes();
+        for &(level, nowrap) in &[(-1, false), (0, false), (1, true), (9, false)] {
+            let input = Value::Reference(Some(byte_array(&mut vm, &data)));
+            match vm.invoke_static("Compression", "roundTrip", "([BIZ)[B", vec![input, Value::Int(level), Value::Int(nowrap as i32)]) {
+                Ok(Some(Value::Reference(Some(output)))) => assert_eq!(data, contents(&output), "level {}", level),
+                other => panic!("Expected the data back at level {}; got {:?}", level, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_inflating_zlib_output_leaves_trailing_input() {
+        let mut vm = vm_with(&[fixture!("Compression")]);
+        // "inflated by the guest", as compressed by zlib, followed by three more bytes.
+        let compressed = [
+            0x78, 0x9c, 0xcb, 0xcc, 0x4b, 0xcb, 0x49, 0x2c, 0x49, 0x4d, 0x51, 0x48, 0xaa, 0x54, 0x28, 0xc9,
+            0x48, 0x55, 0x48, 0x2f, 0x4d, 0x2d, 0x2e, 0x01, 0x00, 0x57, 0x6a, 0x07, 0xec, 1, 2, 3,
+        ];
+        let compressed = Value::Reference(Some(byte_array(&mut vm, &compressed)));
+        let output = byte_array(&mut vm, &[0; 21]);
+        let args = vec![compressed, Value::Reference(Some(output.clone()))];
+        assert_eq!(Ok(Some(Value::Int(3))), vm.invoke_static("Compression", "remaining", "([B[B)I", args));
+        assert_eq!(b"inflated by the guest".to_vec(), contents(&output));
+    }
+
+    #[test]
+    fn test_inflating_bad_data_throws() {
+        let mut vm = vm_with(&[fixture!("Compression")]);
+        let compressed = Value::Reference(Some(byte_array(&mut vm, b"not compressed")));
+        let output = Value::Reference(Some(byte_array(&mut vm, &[0; 10])));
+        match vm.invoke_static("Compression", "remaining", "([B[B)I", vec![compressed, output]) {
+            Err(VmError::UncaughtException(exception)) => assert_eq!("java/util/zip/DataFormatException", exception.class().name),
+            other => panic!("Expected DataFormatException; got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_abandoned_streams_are_freed_by_gc() {
+        let mut vm = vm_with(&[fixture!("Compression")]);
+        assert_eq!(Ok(None), vm.invoke_static("Compression", "abandon", "()V", vec![]));
+        assert_eq!(2, vm.zip_streams().len());
+        vm.collect_garbage(GcCause::Requested);
+        assert!(vm.zip_streams().is_empty());
+    }
+}
@@ -0,0 +1,307 @@
+use crate::heap::ObjectRef;
+use crate::runtime::*;
+use crate::sandbox::{self, Decision};
+use crate::vm::{self, Vm, VmError};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+
+// The streams behind System.in, System.out and System.err. The bootstrap classes wrap file
+// descriptors 0, 1 and 2 in a FileInputStream and FileOutputStreams, whose natives read and
+// write these. By default they're the host's stdin, stdout and stderr; embedders can swap them
+// with Vm::set_stdin and friends, say to capture what a program prints. Files opened by name get
+// the file descriptors after those, once the sandbox policy allows them.
+pub struct Console {
+    pub stdin: Box<dyn Read>,
+    pub stdout: Box<dyn Write>,
+    pub stderr: Box<dyn Write>,
+    files: HashMap<i32, OpenFile>,
+    next_fd: i32,
+}
+
+// A file opened by a FileInputStream or FileOutputStream, or whatever the sandbox policy gave in
+// its place.
+pub enum OpenFile {
+    Input(Box<dyn Read>),
+    Output(Box<dyn Write>),
+}
+
+impl Default for Console {
+    fn default() -> Console {
+        Console {
+            stdin: Box::new(io::stdin()),
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+            files: HashMap::new(),
+            next_fd: 3,
+        }
+    }
+}
+
+impl Console {
+    // Adds a file, returning its file descriptor.
+    pub fn open(&mut self, file: OpenFile) -> i32 {
+        let fd = self.next_fd;
+        self.next_fd += 1;
+        self.files.insert(fd, file);
+        fd
+    }
+
+    pub fn close(&mut self, fd: i32) -> Option<OpenFile> {
+        self.files.remove(&fd)
+    }
+
+    pub fn open_file_count(&self) -> usize {
+        self.files.len()
+    }
+
+    fn reader(&mut self, fd: i32) -> Option<&mut dyn Read> {
+        match (fd, self.files.get_mut(&fd)) {
+            (0, _) => Some(&mut *self.stdin),
+            (_, Some(OpenFile::Input(file))) => Some(&mut **file),
+            _ => None,
+        }
+    }
+
+    fn writer(&mut self, fd: i32) -> Option<&mut dyn Write> {
+        match (fd, self.files.get_mut(&fd)) {
+            (1, _) => Some(&mut *self.stdout),
+            (2, _) => Some(&mut *self.stderr),
+            (_, Some(OpenFile::Output(file))) => Some(&mut **file),
+            _ => None,
+        }
+    }
+}
+
+// A Write that keeps what's written to it, for capturing output. Clones share the same buffer,
+// so one can be handed to the VM and another kept to read the output.
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn new() -> SharedBuffer {
+        SharedBuffer::default()
+    }
+
+    pub fn contents(&self) -> Vec<u8> {
+        self.0.borrow().clone()
+    }
+
+    // The contents, with invalid UTF-8 replaced.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn invoke_native(vm: &mut Vm, class: &str, name: &str, descriptor: &str, args: &[Value]) -> Result<Option<Value>, VmError> {
+    match (class, name, descriptor, args) {
+        ("java/io/FileOutputStream", "writeBytes", "([BII)V", &[Value::Reference(Some(ref this)), Value::Reference(ref bytes), Value::Int(offset), Value::Int(length)]) => {
+            let (fd, (array, range)) = (file_descriptor(this)?, region(vm, bytes, offset, length)?);
+            let bytes: Vec<u8> = array.fields.borrow().bytes().map_or_else(Vec::new, |bytes| bytes[range].iter().map(|&b| b as u8).collect());
+            let stream = match vm.console_mut().writer(fd) {
+                Some(stream) => stream,
+                None => return Err(bad_file_descriptor(vm)),
+            };
+            // Java's streams buffer for themselves, so each write is passed straight on.
+            match stream.write_all(&bytes).and_then(|()| stream.flush()) {
+                Ok(()) => Ok(None),
+                Err(err) => Err(vm.throw_new_with_message("java/io/IOException", &err.to_string())),
+            }
+        },
+        ("java/io/FileInputStream", "readBytes", "([BII)I", &[Value::Reference(Some(ref this)), Value::Reference(ref bytes), Value::Int(offset), Value::Int(length)]) => {
+            let (fd, (array, range)) = (file_descriptor(this)?, region(vm, bytes, offset, length)?);
+            let stream = match vm.console_mut().reader(fd) {
+                Some(stream) => stream,
+                None => return Err(bad_file_descriptor(vm)),
+            };
+            if range.is_empty() {
+                return Ok(Some(Value::Int(0)));
+            }
+            let mut buffer = vec![0; range.len()];
+            let read = match stream.read(&mut buffer) {
+                Ok(0) => return Ok(Some(Value::Int(-1))), // The end of the stream.
+                Ok(read) => read,
+                Err(err) => return Err(vm.throw_new_with_message("java/io/IOException", &err.to_string())),
+            };
+            if let Some(elements) = array.fields.borrow_mut().bytes_mut() {
+                for (element, &byte) in elements[range].iter_mut().zip(&buffer[..read]) {
+                    *element = byte as i8;
+                }
+            }
+            Ok(Some(Value::Int(read as i32)))
+        },
+        ("java/io/FileInputStream", "open", "(Ljava/lang/String;)I", &[Value::Reference(ref name)]) => {
+            let name = file_name(vm, name)?;
+            let file = match vm.sandbox_mut().read_file(Path::new(&name)) {
+                Decision::Allow => File::open(&name).map(|file| Box::new(file) as Box<dyn Read>),
+                Decision::Deny => return Err(sandbox::denied(vm, &format!("read {}", name))),
+                Decision::Virtualize(file) => Ok(file),
+            };
+            match file {
+                Ok(file) => Ok(Some(Value::Int(vm.console_mut().open(OpenFile::Input(file))))),
+                Err(err) => Err(file_not_found(vm, &name, &err)),
+            }
+        },
+        ("java/io/FileOutputStream", "open", "(Ljava/lang/String;Z)I", &[Value::Reference(ref name), Value::Int(append)]) => {
+            let name = file_name(vm, name)?;
+            let file = match vm.sandbox_mut().write_file(Path::new(&name), append != 0) {
+                Decision::Allow => {
+                    OpenOptions::new().write(true).create(true).append(append != 0).truncate(append == 0).open(&name)
+                        .map(|file| Box::new(file) as Box<dyn Write>)
+                },
+                Decision::Deny => return Err(sandbox::denied(vm, &format!("write {}", name))),
+                Decision::Virtualize(file) => Ok(file),
+            };
+            match file {
+                Ok(file) => Ok(Some(Value::Int(vm.console_mut().open(OpenFile::Output(file))))),
+                Err(err) => Err(file_not_found(vm, &name, &err)),
+            }
+        },
+        // Closing System.in, System.out or System.err has no effect, since they're the embedder's.
+        ("java/io/FileInputStream" | "java/io/FileOutputStream", "close0", "()V", &[Value::Reference(Some(ref this))]) => {
+            let fd = file_descriptor(this)?;
+            if let Some(file) = vm.console_mut().close(fd) {
+                if let Value::Reference(Some(fd)) = vm::get_field(this, "fd")? {
+                    vm::set_field(&fd, "fd", Value::Int(-1))?;
+                }
+                if let OpenFile::Output(mut file) = file {
+                    if let Err(err) = file.flush() {
+                        return Err(vm.throw_new_with_message("java/io/IOException", &err.to_string()));
+                    }
+                }
+            }
+            Ok(None)
+        },
+        _ => Err(VmError::Unsupported(format!("native method {}.{}{}", class, name, descriptor))),
+    }
+}
+
+fn file_name(vm: &mut Vm, name: &Option<ObjectRef>) -> Result<String, VmError> {
+    match *name {
+        Some(ref name) => vm.read_string(name),
+        None => Err(vm.throw_new("java/lang/NullPointerException")),
+    }
+}
+
+// Like the JDK's message, such as "missing.txt (No such file or directory)".
+fn file_not_found(vm: &mut Vm, name: &str, err: &io::Error) -> VmError {
+    let reason = err.to_string();
+    let reason = reason.split(" (os error").next().unwrap_or(&reason);
+    vm.throw_new_with_message("java/io/FileNotFoundException", &format!("{} ({})", name, reason))
+}
+
+// The number of the file descriptor that a FileInputStream or FileOutputStream wraps.
+fn file_descriptor(stream: &ObjectRef) -> Result<i32, VmError> {
+    match vm::get_field(stream, "fd")? {
+        Value::Reference(Some(fd)) => match vm::get_field(&fd, "fd")? {
+            Value::Int(fd) => Ok(fd),
+            other => Err(VmError::InvalidBytecode(format!("FileDescriptor has invalid fd {:?}", other))),
+        },
+        other => Err(VmError::InvalidBytecode(format!("Stream has invalid fd {:?}", other))),
+    }
+}
+
+fn bad_file_descriptor(vm: &mut Vm) -> VmError {
+    vm.throw_new_with_message("java/io/IOException", "Bad file descriptor")
+}
+
+// The byte array that a read or write uses, and the part of it that it covers, throwing
+// NullPointerException or IndexOutOfBoundsException as Java's streams do if there isn't one.
+pub fn region(vm: &mut Vm, bytes: &Option<ObjectRef>, offset: i32, length: i32) -> Result<(ObjectRef, Range<usize>), VmError> {
+    let bytes = bytes.as_ref().ok_or_else(|| vm.throw_new("java/lang/NullPointerException"))?;
+    let array_length = match bytes.fields.borrow().bytes() {
+        Some(bytes) => bytes.len(),
+        None => return Err(VmError::InvalidBytecode(format!("Expected a byte array; got {}", bytes.class().name))),
+    };
+    if offset < 0 || length < 0 || offset as usize + length as usize > array_length {
+        let message = format!("Range [{}, {} + {}) out of bounds for length {}", offset, offset, length, array_length);
+        return Err(vm.throw_new_with_message("java/lang/IndexOutOfBoundsException", &message));
+    }
+    Ok((bytes.clone(), offset as usize..(offset + length) as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use crate::env::Env;
+
+    // Captures the VM's stdout and stderr, and has its stdin read the given input.
+    fn redirect(vm: &mut Vm, input: &[u8]) -> (SharedBuffer, SharedBuffer) {
+        let (stdout, stderr) = (SharedBuffer::new(), SharedBuffer::new());
+        vm.set_stdout(stdout.clone());
+        vm.set_stderr(stderr.clone());
+        vm.set_stdin(io::Cursor::new(input.to_vec()));
+        (stdout, stderr)
+    }
+
+    #[test]
+    fn test_hello_world() {
+        let mut vm = vm_with(&[fixture!("ConsoleIo")]);
+        let (stdout, stderr) = redirect(&mut vm, b"");
+        assert_eq!(Ok(None), vm.invoke_static("ConsoleIo", "main", "([Ljava/lang/String;)V", vec![Value::null()]));
+        assert_eq!("Hello, World!\n", stdout.text());
+        assert_eq!("", stderr.text());
+    }
+
+    #[test]
+    fn test_printing() {
+        let mut vm = vm_with(&[fixture!("ConsoleIo")]);
+        let (stdout, stderr) = redirect(&mut vm, b"");
+        assert_eq!(Ok(None), vm.invoke_static("ConsoleIo", "printAll", "()V", vec![]));
+        assert_eq!("a1-42truecde\nnull\n-2147483648\ncaf\u{e9}\n", stdout.text());
+        assert_eq!("to stderr\n", stderr.text());
+    }
+
+    #[test]
+    fn test_reading_stdin() {
+        let mut vm = vm_with(&[fixture!("ConsoleIo")]);
+        let (stdout, _) = redirect(&mut vm, b"echoed input");
+        assert_eq!(Ok(Some(Value::Int(12))), vm.invoke_static("ConsoleIo", "echo", "()I", vec![]));
+        assert_eq!(b"echoed input".to_vec(), stdout.contents());
+
+        let mut vm = vm_with(&[fixture!("ConsoleIo")]);
+        redirect(&mut vm, &[0xff]);
+        assert_eq!(Ok(Some(Value::Int(0xff))), vm.invoke_static("ConsoleIo", "readByte", "()I", vec![]));
+        assert_eq!(Ok(Some(Value::Int(-1))), vm.invoke_static("ConsoleIo", "readByte", "()I", vec![]));
+    }
+
+    #[test]
+    fn test_write_failures_set_the_error_flag() {
+        struct Broken;
+        impl Write for Broken {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk full"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut vm = vm_with(&[fixture!("ConsoleIo")]);
+        redirect(&mut vm, b"");
+        vm.set_stdout(Broken);
+        assert_eq!(Ok(None), vm.invoke_static("ConsoleIo", "main", "([Ljava/lang/String;)V", vec![Value::null()]));
+        let system = vm.load_class("java/lang/System").unwrap();
+        let out = match Env::new(&mut vm, system).get_static_field("java/lang/System", "out") {
+            Ok(Value::Reference(Some(out))) => out,
+            other => panic!("Expected System.out; got {:?}", other),
+        };
+        assert_eq!(Ok(Value::Int(1)), vm::get_field(&out, "trouble"));
+    }
+}
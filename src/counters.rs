@@ -0,0 +1,86 @@
+use crate::runtime::RuntimeClass;
+use crate::vm::Vm;
+use std::fmt::Write;
+use std::rc::Rc;
+
+// How often the interpreter has entered a method and taken a backward branch in it, which is how
+// hot the method is. The JIT compiles a method once the two add up to its threshold. Counts are
+// kept on each method's RuntimeClass, and don't include loops run by compiled code.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MethodCounters {
+    pub invocations: u64,
+    pub backedges: u64,
+}
+
+impl MethodCounters {
+    pub fn total(&self) -> u64 {
+        self.invocations + self.backedges
+    }
+}
+
+// A method along with its counts, as hottest_methods finds them.
+#[derive(Clone)]
+pub struct HotMethod {
+    pub class: Rc<RuntimeClass>,
+    pub method_index: usize,
+    pub counters: MethodCounters,
+}
+
+impl HotMethod {
+    // The method's class, name and descriptor, as in Main.run(I)V.
+    pub fn name(&self) -> String {
+        let method = &self.class.class.methods[self.method_index];
+        let name = self.class.class.utf8(&method.name).unwrap_or("<unknown>");
+        let descriptor = self.class.class.utf8(&method.descriptor).unwrap_or("");
+        format!("{}.{}{}", self.class.name, name, descriptor)
+    }
+}
+
+// The methods of every loaded class that have run at all, hottest first, up to the limit.
+pub fn hottest_methods(vm: &Vm, limit: usize) -> Vec<HotMethod> {
+    let mut methods: Vec<HotMethod> = vm.loaded_classes()
+        .flat_map(|class| (0..class.class.methods.len()).map(move |method_index| {
+            HotMethod {class: Rc::clone(class), method_index, counters: class.counters(method_index)}
+        }))
+        .filter(|method| method.counters.total() > 0)
+        .collect();
+    methods.sort_by_cached_key(|method| (std::cmp::Reverse(method.counters.total()), method.name()));
+    methods.truncate(limit);
+    methods
+}
+
+// A table of the methods and their counts, for printing.
+pub fn report(methods: &[HotMethod]) -> String {
+    let mut report = format!("{:>12} {:>12}  Method\n", "Invocations", "Backedges");
+    for method in methods {
+        let _ = writeln!(report, "{:>12} {:>12}  {}", method.counters.invocations, method.counters.backedges, method.name());
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use crate::runtime::Value;
+
+    #[test]
+    fn test_counts_invocations_and_backedges() {
+        let mut vm = vm_with(&[fixture!("Debuggee")]);
+        vm.invoke_static("Debuggee", "sumOfSquares", "(I)I", vec![Value::Int(5)]).unwrap();
+        vm.invoke_static("Debuggee", "sumOfSquares", "(I)I", vec![Value::Int(3)]).unwrap();
+
+        let hottest = hottest_methods(&vm, 10);
+        let names: Vec<String> = hottest.iter().map(HotMethod::name).collect();
+        assert_eq!(vec!["Debuggee.sumOfSquares(I)I", "Debuggee.square(I)I"], names);
+        assert_eq!(MethodCounters {invocations: 2, backedges: 8}, hottest[0].counters);
+        assert_eq!(MethodCounters {invocations: 8, backedges: 0}, hottest[1].counters);
+        assert_eq!(1, hottest_methods(&vm, 1).len());
+
+        assert_eq!(concat!(
+            " Invocations    Backedges  Method\n",
+            "           2            8  Debuggee.sumOfSquares(I)I\n",
+            "           8            0  Debuggee.square(I)I\n",
+        ), report(&hottest));
+    }
+}
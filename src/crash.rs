@@ -0,0 +1,207 @@
+use crate::threaddump;
+use crate::threads::ThreadId;
+use crate::vm::{ActiveFrame, Vm, VmError};
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::panic::{self, PanicHookInfo};
+use std::path::PathBuf;
+use std::process;
+use std::sync::PoisonError;
+
+// What brought the VM down: a panic in the VM itself, or an internal error (see
+// VmError::is_internal) that reached the embedder.
+#[derive(Debug)]
+pub struct Crash {
+    pub description: String,
+    pub backtrace: String, // The Rust backtrace where the crash was noticed.
+}
+
+impl Crash {
+    pub fn from_error(error: &VmError) -> Crash {
+        Crash {description: format!("Internal error: {}", error), backtrace: Backtrace::force_capture().to_string()}
+    }
+
+    fn from_panic(info: &PanicHookInfo) -> Crash {
+        let message = panic_message(info.payload());
+        let description = match info.location() {
+            Some(location) => format!("Rust panic at {}: {}", location, message),
+            None => format!("Rust panic: {}", message),
+        };
+        Crash {description, backtrace: Backtrace::force_capture().to_string()}
+    }
+}
+
+// The message a panic was raised with, from its payload, as the default hook prints it.
+pub fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload.downcast_ref::<&str>().copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("Box<dyn Any>")
+}
+
+// Adds a panic hook that records each panic, with its backtrace, for Vm::take_panic, and then
+// passes it on to the hook that was there before. The backtrace is only available from the hook,
+// since the stack has unwound by the time the panic is caught. Panics are recorded wherever they
+// happen, so that one on a thread the VM started is reported as well as one on the thread that
+// catches it.
+pub fn install_panic_hook(vm: &Vm) {
+    let record = vm.panic_record();
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let crash = Crash::from_panic(info);
+        record.lock().unwrap_or_else(PoisonError::into_inner).get_or_insert(crash);
+        previous(info);
+    }));
+}
+
+// A report on the crash and the state of the VM when it happened, in the manner of HotSpot's
+// hs_err files: what went wrong, the current frame's method and pc as its registers, the Java and
+// Rust stacks, the other threads, the heap and the loaded classes. The Java stack is where an
+// internal error was raised, if the VM recorded it, or otherwise the current one, which for a
+// panic is where it happened, since frames aren't popped as a panic unwinds.
+pub fn report(vm: &mut Vm, crash: &Crash) -> String {
+    let current = vm.threads().current();
+    let mut stacks = vm.thread_stacks();
+    if let Some(error_stack) = vm.take_error_stack() {
+        stacks[0].1 = error_stack;
+    }
+    let frames = &stacks[0].1;
+
+    let mut report = String::new();
+    let _ = writeln!(report, "#\n# A fatal error has been detected by joyvm:\n#\n#  {}\n#", crash.description);
+    let _ = writeln!(report, "# Java VM: joyvm {} ({:?} threading)\n#\n", env!("CARGO_PKG_VERSION"), vm.threads().threading());
+
+    let _ = writeln!(report, "---------------  T H R E A D  ---------------\n");
+    let _ = writeln!(report, "Current thread: {}\n", current);
+    let _ = writeln!(report, "Registers:");
+    match frames.first() {
+        Some(frame) => {
+            let _ = writeln!(report, "  method: {}", method_name(frame));
+            let _ = writeln!(report, "  pc: {}", frame.pc);
+            if let Some(line) = line_number(frame) {
+                let _ = writeln!(report, "  line: {}", line);
+            }
+            let _ = writeln!(report, "  depth: {} of at most {}", frames.len(), vm.max_stack_depth());
+        },
+        None => {
+            let _ = writeln!(report, "  none; no Java code was running");
+        },
+    }
+    let _ = writeln!(report, "\nJava frames (innermost first):");
+    write_frames(&mut report, frames);
+    let _ = writeln!(report, "\nRust backtrace:\n{}", crash.backtrace.trim_end());
+
+    let _ = writeln!(report, "\n---------------  P R O C E S S  ---------------");
+    for (thread, frames) in stacks.iter().skip(1) {
+        write_thread(&mut report, *thread, frames);
+    }
+
+    let _ = write!(report, "\n{}", threaddump::heap_summary(vm));
+
+    let mut classes: Vec<&str> = vm.loaded_classes().map(|class| class.name.as_str()).collect();
+    classes.sort_unstable();
+    let _ = writeln!(report, "\nLoaded classes ({}):", classes.len());
+    for class in classes {
+        let _ = writeln!(report, "  {}", class);
+    }
+    report
+}
+
+fn write_thread(report: &mut String, thread: ThreadId, frames: &[ActiveFrame]) {
+    let _ = writeln!(report, "\nThread {}:", thread);
+    write_frames(report, frames);
+}
+
+// As HotSpot lists interpreted frames: the method and pc, with the source line where known.
+fn write_frames(report: &mut String, frames: &[ActiveFrame]) {
+    if frames.is_empty() {
+        let _ = writeln!(report, "  none");
+    }
+    for frame in frames {
+        let source = frame.class.class.source_file().unwrap_or("Unknown Source");
+        let _ = match line_number(frame) {
+            Some(line) => writeln!(report, "j  {}+{} ({}:{})", method_name(frame), frame.pc, source, line),
+            None => writeln!(report, "j  {}+{}", method_name(frame), frame.pc),
+        };
+    }
+}
+
+fn method_name(frame: &ActiveFrame) -> String {
+    let method = &frame.class.class.methods[frame.method_index];
+    let name = frame.class.class.utf8(&method.name).unwrap_or("<unknown>");
+    let descriptor = frame.class.class.utf8(&method.descriptor).unwrap_or("");
+    format!("{}.{}{}", frame.class.java_name(), name, descriptor)
+}
+
+fn line_number(frame: &ActiveFrame) -> Option<u16> {
+    frame.class.class.methods[frame.method_index].code()?.line_number(frame.pc)
+}
+
+// Writes the report to hs_err_pid<pid>.log in the current directory, or in the temporary
+// directory if that fails, as HotSpot does, returning where it went.
+pub fn write_report(vm: &mut Vm, crash: &Crash) -> io::Result<PathBuf> {
+    let report = report(vm, crash);
+    let name = format!("hs_err_pid{}.log", process::id());
+    let path = env::current_dir().map(|dir| dir.join(&name)).unwrap_or_else(|_| PathBuf::from(&name));
+    match fs::write(&path, &report) {
+        Ok(()) => Ok(path),
+        Err(_) => {
+            let path = env::temp_dir().join(&name);
+            fs::write(&path, &report).map(|()| path)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use crate::events::{Event, EventKinds};
+    use std::panic::AssertUnwindSafe;
+
+
+    #[test]
+    fn test_report_on_panic() {
+        let mut vm = vm_with(&[fixture!("Crashes")]);
+        vm.subscribe(EventKinds::METHOD_ENTRY, Box::new(|event: &Event| {
+            if let Event::MethodEntry{class, method} = *event {
+                if class.class.utf8(&method.name).unwrap() == "locked" {
+                    panic!("Boom");
+                }
+            }
+        }));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| vm.invoke_static("Crashes", "callsLocked", "()I", vec![])));
+        assert!(result.is_err());
+
+        let crash = Crash {description: "Rust panic: Boom".to_string(), backtrace: "0: somewhere".to_string()};
+        let report = report(&mut vm, &crash);
+        assert!(report.contains("#  Rust panic: Boom\n"), "{}", report);
+        assert!(report.contains("Rust backtrace:\n0: somewhere\n"), "{}", report);
+        assert!(report.contains(concat!(
+            "Java frames (innermost first):\n",
+            "j  Crashes.locked(Ljava/lang/Object;)I+0 (Crashes.java:3)\n",
+            "j  Crashes.callsLocked()I+7 (Crashes.java:9)\n",
+        )), "{}", report);
+        assert!(report.contains("  method: Crashes.locked(Ljava/lang/Object;)I\n  pc: 0\n  line: 3\n  depth: 2 of at most "), "{}", report);
+        assert!(report.contains("\n  Crashes\n"), "{}", report);
+        assert!(report.contains("\n  java/lang/Object\n"), "{}", report);
+    }
+
+    #[test]
+    fn test_report_on_internal_error() {
+        let mut vm = vm_with(&[fixture!("Crashes")]);
+        let error = vm.invoke_static("Crashes", "callsMissing", "()I", vec![]).unwrap_err();
+        assert!(error.is_internal());
+        let report = report(&mut vm, &Crash::from_error(&error));
+        assert!(report.contains("#  Internal error: "), "{}", report);
+        assert!(report.contains("\nj  Crashes.missing()I+0\nj  Crashes.callsMissing()I+0 (Crashes.java:16)\n"), "{}", report);
+        assert!(vm.take_error_stack().is_none());
+
+        let report = super::report(&mut vm, &Crash::from_error(&error));
+        assert!(report.contains("Java frames (innermost first):\n  none\n"), "{}", report);
+        assert!(report.contains("  none; no Java code was running\n"), "{}", report);
+    }
+}
@@ -0,0 +1,352 @@
+use crate::classes::{Attribute, Code, Method};
+use crate::runtime::*;
+use crate::threads::ThreadId;
+use crate::vm::Vm;
+use std::rc::Rc;
+
+// Lets embedders stop the interpreter at breakpoints and step through code a line at a time, to
+// build debugging UIs of their own without going through JDWP. Install a debugger with
+// Vm::set_debugger and add breakpoints with Vm::add_breakpoint. When a thread stops, the debugger
+// is called with the stopped frame, whose locals it can read and change, and says how to carry
+// on. It's also passed the VM, to look at objects or add and remove breakpoints; code it runs
+// there doesn't stop.
+//
+// Like the execution hooks, this runs in the interpreter's hooked dispatch loop, so compiled code
+// isn't stopped in, and nothing is checked while no debugger is installed.
+pub trait Debugger {
+    fn on_stop(&mut self, vm: &mut Vm, stop: Stop) -> Resume;
+}
+
+impl<F: FnMut(&mut Vm, Stop) -> Resume> Debugger for F {
+    fn on_stop(&mut self, vm: &mut Vm, stop: Stop) -> Resume {
+        self(vm, stop)
+    }
+}
+
+// A frame that has stopped before executing the instruction at its pc.
+pub struct Stop<'a> {
+    pub reason: StopReason,
+    pub thread: ThreadId,
+    pub class: &'a Rc<RuntimeClass>,
+    pub method: &'a Method,
+    pub pc: usize,
+    pub line: Option<u16>,
+    pub depth: usize, // The number of Java frames on the thread's stack, this one included.
+    pub locals: &'a mut [Value], // Longs and doubles take two slots, the second unused.
+    pub stack: &'a [Value], // The operand stack, with the top last.
+}
+
+impl<'a> Stop<'a> {
+    pub fn method_name(&self) -> &str {
+        self.class.class.utf8(&self.method.name).unwrap_or("<unknown>")
+    }
+
+    // The slot of the local variable with the given name in scope here, if the class was compiled
+    // with a LocalVariableTable, as javac -g does.
+    pub fn local_slot(&self, name: &str) -> Option<usize> {
+        self.method.code()?.attributes.iter()
+            .filter_map(|attribute| match *attribute {
+                Attribute::LocalVariableTable{ref variables, ..} => Some(variables),
+                _ => None,
+            })
+            .flatten()
+            .find(|variable| {
+                let start = variable.start_pc as usize;
+                (start..start + variable.length as usize).contains(&self.pc) && self.class.class.utf8(&variable.name) == Ok(name)
+            })
+            .map(|variable| variable.index as usize)
+    }
+
+    pub fn local(&self, name: &str) -> Option<&Value> {
+        self.local_slot(name).and_then(|slot| self.locals.get(slot))
+    }
+
+    // Changes the value of a local variable, returning false if there isn't one of that name. The
+    // value should be of the variable's type, as the interpreter trusts the verifier on that.
+    pub fn set_local(&mut self, name: &str, value: Value) -> bool {
+        match self.local_slot(name).and_then(|slot| self.locals.get_mut(slot)) {
+            Some(local) => {
+                *local = value;
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    Breakpoint(BreakpointId),
+    Step,
+}
+
+// How a stopped thread carries on. Steps go a line at a time, or an instruction at a time in
+// methods without line numbers, and stop early if the frame returns or throws to its caller.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resume {
+    Continue, // Until the next breakpoint.
+    StepInto, // Stops at the next line, or at the start of a method it calls.
+    StepOver, // Stops at the next line of this method, running any calls to completion.
+    StepOut, // Stops in the caller, once this method has returned.
+}
+
+// Where to stop: before the instruction at a pc, or before each run of instructions that the line
+// number table says starts a line. A method may be given by name alone, to stop in each overload.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Breakpoint {
+    pub class: String, // In internal form.
+    pub method: String,
+    pub descriptor: Option<String>,
+    pub location: Location,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Location {
+    Pc(usize),
+    Line(u16),
+}
+
+impl Breakpoint {
+    pub fn at_line(class: &str, method: &str, line: u16) -> Breakpoint {
+        Breakpoint {class: class.to_string(), method: method.to_string(), descriptor: None, location: Location::Line(line)}
+    }
+
+    pub fn at_pc(class: &str, method: &str, pc: usize) -> Breakpoint {
+        Breakpoint {class: class.to_string(), method: method.to_string(), descriptor: None, location: Location::Pc(pc)}
+    }
+
+    pub fn descriptor(mut self, descriptor: &str) -> Breakpoint {
+        self.descriptor = Some(descriptor.to_string());
+        self
+    }
+
+    fn matches(&self, class: &RuntimeClass, method: &Method, code: &Code, pc: usize) -> bool {
+        let at = match self.location {
+            Location::Pc(at) => at == pc,
+            Location::Line(line) => starts_line(code, pc, line),
+        };
+        at && class.name == self.class
+            && class.class.utf8(&method.name) == Ok(self.method.as_str())
+            && self.descriptor.as_ref().is_none_or(|descriptor| class.class.utf8(&method.descriptor) == Ok(descriptor.as_str()))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BreakpointId(u32);
+
+// The debugger and its breakpoints, as the VM holds them.
+#[derive(Default)]
+pub struct Debugging {
+    debugger: Option<Box<dyn Debugger>>,
+    breakpoints: Vec<(BreakpointId, Breakpoint)>,
+    next_id: u32,
+    step: Option<Step>,
+}
+
+// A step in progress, and where it started from.
+struct Step {
+    resume: Resume,
+    thread: ThreadId,
+    depth: usize,
+    pc: usize,
+    line: Option<u16>,
+}
+
+impl Debugging {
+    pub fn set_debugger(&mut self, debugger: Box<dyn Debugger>) {
+        self.debugger = Some(debugger);
+    }
+
+    pub fn take_debugger(&mut self) -> Option<Box<dyn Debugger>> {
+        self.step = None;
+        self.debugger.take()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.debugger.is_some()
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> BreakpointId {
+        let id = BreakpointId(self.next_id);
+        self.next_id += 1;
+        self.breakpoints.push((id, breakpoint));
+        id
+    }
+
+    pub fn remove_breakpoint(&mut self, id: BreakpointId) -> Option<Breakpoint> {
+        let index = self.breakpoints.iter().position(|&(existing, _)| existing == id)?;
+        Some(self.breakpoints.remove(index).1)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = (BreakpointId, &Breakpoint)> {
+        self.breakpoints.iter().map(|(id, breakpoint)| (*id, breakpoint))
+    }
+
+    fn step_finished(&self, thread: ThreadId, depth: usize, code: &Code, pc: usize) -> bool {
+        let step = match self.step {
+            Some(ref step) if step.thread == thread => step,
+            _ => return false,
+        };
+        // A line ends when another starts, or when a loop jumps back to the start of the same one.
+        let next_line = || match step.line {
+            Some(line) => code.line_number(pc) != Some(line) || (pc <= step.pc && starts_line(code, pc, line)),
+            None => true,
+        };
+        match step.resume {
+            _ if depth < step.depth => true,
+            Resume::StepInto => depth > step.depth || next_line(),
+            Resume::StepOver => depth == step.depth && next_line(),
+            Resume::StepOut | Resume::Continue => false,
+        }
+    }
+}
+
+// Whether the instruction at the pc is the first of the line's, according to the line number table.
+fn starts_line(code: &Code, pc: usize, line: u16) -> bool {
+    code.attributes.iter()
+        .filter_map(|attribute| match *attribute {
+            Attribute::LineNumberTable{ref table, ..} => Some(table),
+            _ => None,
+        })
+        .flatten()
+        .any(|&(start_pc, line_number)| start_pc as usize == pc && line_number == line)
+}
+
+// Called by the interpreter before each instruction while a debugger is installed, to stop there
+// if a breakpoint or step says to.
+pub fn check(vm: &mut Vm, class: &Rc<RuntimeClass>, method: &Method, code: &Code, pc: usize, locals: &mut [Value], stack: &[Value]) {
+    let (thread, depth) = (vm.threads().current(), vm.stack_depth());
+    let debugging = vm.debugging();
+    let reason = match debugging.breakpoints.iter().find(|(_, breakpoint)| breakpoint.matches(class, method, code, pc)) {
+        Some(&(id, _)) => StopReason::Breakpoint(id),
+        None if debugging.step_finished(thread, depth, code, pc) => StopReason::Step,
+        None => return,
+    };
+    let mut debugger = match debugging.debugger.take() {
+        Some(debugger) => debugger,
+        None => return,
+    };
+    debugging.step = None;
+
+    let line = code.line_number(pc);
+    let resume = debugger.on_stop(vm, Stop {reason, thread, class, method, pc, line, depth, locals, stack});
+
+    // The debugger may have been replaced or removed while it was running.
+    let debugging = vm.debugging();
+    if debugging.debugger.is_none() {
+        debugging.debugger = Some(debugger);
+        if resume != Resume::Continue {
+            debugging.step = Some(Step {resume, thread, depth, pc, line});
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use std::cell::RefCell;
+
+
+    fn sum_of_squares(vm: &mut Vm, n: i32) -> Option<Value> {
+        vm.invoke_static("Debuggee", "sumOfSquares", "(I)I", vec![Value::Int(n)]).unwrap()
+    }
+
+    // Installs a debugger that records where it stops and resumes as each of the script's steps
+    // says, then continues once the script runs out.
+    fn script(vm: &mut Vm, steps: Vec<Resume>) -> Rc<RefCell<Vec<String>>> {
+        let stops = Rc::new(RefCell::new(vec![]));
+        let recorded = Rc::clone(&stops);
+        let mut steps = steps.into_iter();
+        vm.set_debugger(Box::new(move |_: &mut Vm, stop: Stop| {
+            let line = stop.line.map_or("?".to_string(), |line| line.to_string());
+            recorded.borrow_mut().push(format!("{}:{}", stop.method_name(), line));
+            steps.next().unwrap_or(Resume::Continue)
+        }));
+        stops
+    }
+
+    #[test]
+    fn test_breakpoint_at_line_stops_each_time() {
+        let mut vm = vm_with(&[fixture!("Debuggee")]);
+        let stops = script(&mut vm, vec![]);
+        let id = vm.add_breakpoint(Breakpoint::at_line("Debuggee", "square", 3));
+        assert_eq!(Some(Value::Int(14)), sum_of_squares(&mut vm, 3));
+        assert_eq!(vec!["square:3"; 3], *stops.borrow());
+
+        assert_eq!(Some(Breakpoint::at_line("Debuggee", "square", 3)), vm.remove_breakpoint(id));
+        sum_of_squares(&mut vm, 3);
+        assert_eq!(3, stops.borrow().len());
+    }
+
+    #[test]
+    fn test_breakpoint_at_pc_with_descriptor() {
+        let mut vm = vm_with(&[fixture!("Debuggee")]);
+        let stops = script(&mut vm, vec![]);
+        vm.add_breakpoint(Breakpoint::at_pc("Debuggee", "sumOfSquares", 22).descriptor("(I)I"));
+        vm.add_breakpoint(Breakpoint::at_pc("Debuggee", "sumOfSquares", 0).descriptor("(J)J"));
+        sum_of_squares(&mut vm, 2);
+        assert_eq!(vec!["sumOfSquares:12"], *stops.borrow());
+    }
+
+    #[test]
+    fn test_step_over_and_into() {
+        let mut vm = vm_with(&[fixture!("Debuggee")]);
+        let stops = script(&mut vm, vec![Resume::StepOver, Resume::StepOver, Resume::StepOver, Resume::StepInto, Resume::StepOut]);
+        let start = vm.add_breakpoint(Breakpoint::at_line("Debuggee", "sumOfSquares", 8));
+        sum_of_squares(&mut vm, 1);
+        assert_eq!(vec![
+            "sumOfSquares:8",
+            "sumOfSquares:9",
+            "sumOfSquares:10", // The call to square is run without stopping.
+            "sumOfSquares:9", // The loop comes back round.
+            "sumOfSquares:12",
+        ], *stops.borrow());
+
+        // Stepping into square, and then out, stops in the middle of line 10.
+        vm.remove_breakpoint(start);
+        let stops = script(&mut vm, vec![Resume::StepInto, Resume::StepOut, Resume::StepOver]);
+        vm.add_breakpoint(Breakpoint::at_line("Debuggee", "sumOfSquares", 10));
+        sum_of_squares(&mut vm, 1);
+        assert_eq!(vec!["sumOfSquares:10", "square:3", "sumOfSquares:10", "sumOfSquares:9"], *stops.borrow());
+    }
+
+    #[test]
+    fn test_step_out_of_outermost_frame_runs_to_completion() {
+        let mut vm = vm_with(&[fixture!("Debuggee")]);
+        let stops = script(&mut vm, vec![Resume::StepOut]);
+        vm.add_breakpoint(Breakpoint::at_line("Debuggee", "sumOfSquares", 8));
+        assert_eq!(Some(Value::Int(5)), sum_of_squares(&mut vm, 2));
+        assert_eq!(1, stops.borrow().len());
+    }
+
+    #[test]
+    fn test_inspect_and_modify_locals() {
+        let mut vm = vm_with(&[fixture!("Debuggee")]);
+        let seen = Rc::new(RefCell::new(vec![]));
+        let recorded = Rc::clone(&seen);
+        vm.set_debugger(Box::new(move |_: &mut Vm, mut stop: Stop| {
+            recorded.borrow_mut().push((stop.local("i").cloned(), stop.local("total").cloned(), stop.depth));
+            // Skips the rest of the loop after the first time round.
+            assert!(stop.set_local("n", Value::Int(1)));
+            assert!(!stop.set_local("result", Value::Int(0)));
+            Resume::Continue
+        }));
+        vm.add_breakpoint(Breakpoint::at_line("Debuggee", "sumOfSquares", 10));
+        assert_eq!(Some(Value::Int(1)), sum_of_squares(&mut vm, 100));
+        assert_eq!(vec![(Some(Value::Int(1)), Some(Value::Int(0)), 1)], *seen.borrow());
+    }
+
+    #[test]
+    fn test_take_debugger_stops_debugging() {
+        let mut vm = vm_with(&[fixture!("Debuggee")]);
+        let stops = script(&mut vm, vec![]);
+        vm.add_breakpoint(Breakpoint::at_line("Debuggee", "square", 3));
+        assert!(vm.is_debugging());
+        assert!(vm.take_debugger().is_some());
+        assert!(!vm.is_debugging());
+        sum_of_squares(&mut vm, 3);
+        assert!(stops.borrow().is_empty());
+        assert_eq!(1, vm.breakpoints().count());
+    }
+}
@@ -0,0 +1,360 @@
+use crate::inflate::{adler32, DISTANCE_BASE, DISTANCE_EXTRA, LENGTH_BASE, LENGTH_EXTRA};
+
+// Compresses data as DEFLATE (RFC 1951), for java.util.zip.Deflater. Like inflate.rs this favours
+// simplicity: repeats are found with hash chains, searched further at higher levels, and every
+// block uses the fixed Huffman code. Level 0 stores the data as it is. Unless the stream is raw,
+// it's wrapped as a zlib stream (RFC 1950) with a header and an Adler-32 trailer.
+pub struct DeflateStream {
+    level: i32,
+    wrapped: bool,
+    pending: Vec<u8>, // Input that hasn't been compressed yet.
+    output: BitWriter,
+    taken: usize,
+    started: bool, // The zlib header has been written.
+    finishing: bool, // No more input will be given.
+    ended: bool, // The last block has been written.
+    adler: u32,
+    total_in: u64,
+    total_out: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Flush {
+    None,
+    Sync, // Ends the output on a byte boundary, so that everything given so far can be inflated.
+}
+
+// Pending input is compressed into a block once there's this much of it.
+const BLOCK_SIZE: usize = 65536;
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: usize = 15;
+
+impl DeflateStream {
+    pub fn new(level: i32, wrapped: bool) -> DeflateStream {
+        DeflateStream {
+            level,
+            wrapped,
+            pending: vec![],
+            output: BitWriter::default(),
+            taken: 0,
+            started: false,
+            finishing: false,
+            ended: false,
+            adler: 1,
+            total_in: 0,
+            total_out: 0,
+        }
+    }
+
+    // Changes the level for input that hasn't been compressed yet.
+    pub fn set_level(&mut self, level: i32) {
+        self.level = level;
+    }
+
+    pub fn add_input(&mut self, data: &[u8]) {
+        self.pending.extend_from_slice(data);
+        self.adler = adler32(self.adler, data);
+        self.total_in += data.len() as u64;
+        while self.pending.len() >= BLOCK_SIZE {
+            let rest = self.pending.split_off(BLOCK_SIZE);
+            let block = std::mem::replace(&mut self.pending, rest);
+            self.compress(&block, false);
+        }
+    }
+
+    // Marks the end of the input, so that the next call to deflate ends the stream.
+    pub fn finish(&mut self) {
+        self.finishing = true;
+    }
+
+    // Compresses the pending input if finishing or flushing, then copies out as much compressed
+    // output as fits.
+    pub fn deflate(&mut self, buffer: &mut [u8], flush: Flush) -> usize {
+        if self.finishing && !self.ended {
+            let block = std::mem::take(&mut self.pending);
+            self.compress(&block, true);
+            self.output.align_to_byte();
+            if self.wrapped {
+                self.output.bytes.extend_from_slice(&self.adler.to_be_bytes());
+            }
+            self.ended = true;
+        } else if flush == Flush::Sync && !self.ended {
+            let block = std::mem::take(&mut self.pending);
+            if !block.is_empty() {
+                self.compress(&block, false);
+            }
+            // An empty stored block, which brings the output to a byte boundary.
+            self.stored(&[], false);
+        }
+
+        let available = &self.output.bytes[self.taken..];
+        let count = available.len().min(buffer.len());
+        buffer[..count].copy_from_slice(&available[..count]);
+        self.taken += count;
+        self.total_out += count as u64;
+        if self.taken == self.output.bytes.len() {
+            self.output.bytes.clear();
+            self.taken = 0;
+        }
+        count
+    }
+
+    // Whether the stream has ended and all its output has been taken.
+    pub fn finished(&self) -> bool {
+        self.ended && self.output.bytes.is_empty()
+    }
+
+    // The Adler-32 checksum of the input so far.
+    pub fn adler(&self) -> u32 {
+        self.adler
+    }
+
+    // The number of input bytes given, and of output bytes taken.
+    pub fn totals(&self) -> (u64, u64) {
+        (self.total_in, self.total_out)
+    }
+
+    fn compress(&mut self, data: &[u8], last: bool) {
+        if self.wrapped && !self.started {
+            // The second byte records the level, and makes the pair a multiple of 31.
+            let flags = match self.level {
+                0 | 1 => 0x01,
+                2..=5 => 0x5e,
+                7..=9 => 0xda,
+                _ => 0x9c,
+            };
+            self.output.bytes.extend_from_slice(&[0x78, flags]);
+        }
+        self.started = true;
+
+        match self.level {
+            0 => self.stored(data, last),
+            level => fixed_block(&mut self.output, data, max_chain(level), last),
+        }
+    }
+
+    // Writes the data as stored blocks, which hold up to 65535 bytes each.
+    fn stored(&mut self, data: &[u8], last: bool) {
+        let mut chunks: Vec<&[u8]> = data.chunks(65535).collect();
+        if chunks.is_empty() {
+            chunks.push(&[]);
+        }
+        let count = chunks.len();
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            self.output.bits((last && index == count - 1) as u32, 1);
+            self.output.bits(0, 2);
+            self.output.align_to_byte();
+            let length = chunk.len() as u16;
+            self.output.bytes.extend_from_slice(&length.to_le_bytes());
+            self.output.bytes.extend_from_slice(&(!length).to_le_bytes());
+            self.output.bytes.extend_from_slice(chunk);
+        }
+    }
+}
+
+// How many earlier occurrences of a string to try matching at each level. -1 is the default,
+// which is level 6.
+fn max_chain(level: i32) -> usize {
+    match level {
+        1 => 4,
+        2..=3 => 16,
+        7..=8 => 512,
+        9 => 4096,
+        _ => 128,
+    }
+}
+
+// Writes a block coded with the fixed Huffman code, replacing repeated strings with back
+// references to where they last appeared within the block.
+fn fixed_block(output: &mut BitWriter, data: &[u8], max_chain: usize, last: bool) {
+    output.bits(last as u32, 1);
+    output.bits(1, 2);
+
+    // Each position starts a chain through the earlier positions whose next three bytes hash
+    // the same.
+    let hash = |position: usize| {
+        (((data[position] as usize) << 10) ^ ((data[position + 1] as usize) << 5) ^ data[position + 2] as usize) & ((1 << HASH_BITS) - 1)
+    };
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut previous = vec![usize::MAX; data.len()];
+    let insert = |position: usize, head: &mut [usize], previous: &mut [usize]| {
+        if position + MIN_MATCH <= data.len() {
+            let hash = hash(position);
+            previous[position] = head[hash];
+            head[hash] = position;
+        }
+    };
+
+    let mut position = 0;
+    while position < data.len() {
+        let (mut length, mut distance) = (0, 0);
+        if position + MIN_MATCH <= data.len() {
+            let (mut candidate, mut chain) = (head[hash(position)], max_chain);
+            while candidate != usize::MAX && position - candidate <= WINDOW_SIZE && chain > 0 {
+                let matched = data[candidate..].iter().zip(&data[position..]).take(MAX_MATCH).take_while(|(a, b)| a == b).count();
+                if matched > length {
+                    length = matched;
+                    distance = position - candidate;
+                    if length == MAX_MATCH {
+                        break;
+                    }
+                }
+                candidate = previous[candidate];
+                chain -= 1;
+            }
+        }
+
+        if length >= MIN_MATCH {
+            let code = LENGTH_BASE.iter().rposition(|&base| base as usize <= length).unwrap_or(0);
+            literal(output, 257 + code);
+            output.bits((length - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code] as u32);
+            let code = DISTANCE_BASE.iter().rposition(|&base| base as usize <= distance).unwrap_or(0);
+            output.code(code as u32, 5);
+            output.bits((distance - DISTANCE_BASE[code] as usize) as u32, DISTANCE_EXTRA[code] as u32);
+            for skipped in position..position + length {
+                insert(skipped, &mut head, &mut previous);
+            }
+            position += length;
+        } else {
+            literal(output, data[position] as usize);
+            insert(position, &mut head, &mut previous);
+            position += 1;
+        }
+    }
+    literal(output, 256);
+}
+
+// Writes a literal/length symbol with the fixed Huffman code of RFC 1951 section 3.2.6.
+fn literal(output: &mut BitWriter, symbol: usize) {
+    let symbol = symbol as u32;
+    match symbol {
+        0..=143 => output.code(0x30 + symbol, 8),
+        144..=255 => output.code(0x190 + symbol - 144, 9),
+        256..=279 => output.code(symbol - 256, 7),
+        _ => output.code(0xc0 + symbol - 280, 8),
+    }
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    // Writes bits least significant first, as DEFLATE packs them.
+    fn bits(&mut self, value: u32, count: u32) {
+        self.bit_buffer |= value << self.bit_count;
+        self.bit_count += count;
+        while self.bit_count >= 8 {
+            self.bytes.push(self.bit_buffer as u8);
+            self.bit_buffer >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    // Writes a Huffman code, which is packed most significant bit first.
+    fn code(&mut self, code: u32, length: u32) {
+        self.bits(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn align_to_byte(&mut self) {
+        if self.bit_count > 0 {
+            self.bytes.push(self.bit_buffer as u8);
+            self.bit_buffer = 0;
+            self.bit_count = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inflate::{inflate, InflateStream};
+
+    fn deflate_all(data: &[u8], level: i32, wrapped: bool) -> Vec<u8> {
+        let mut stream = DeflateStream::new(level, wrapped);
+        stream.add_input(data);
+        stream.finish();
+        let mut output = vec![];
+        let mut buffer = [0; 100];
+        while !stream.finished() {
+            let count = stream.deflate(&mut buffer, Flush::None);
+            output.extend_from_slice(&buffer[..count]);
+        }
+        output
+    }
+
+    fn sample() -> Vec<u8> {
+        (0..5000).map(|i| format!("{} squared is {}; ", i % 300, (i % 300) * (i % 300))).collect::<String>().into_bytes()
+    }
+
+    #[test]
+    fn test_round_trip_at_every_level() {
+        let data = sample();
+        for level in -1..=9 {
+            let compressed = deflate_all(&data, level, false);
+            assert_eq!(Ok(data.clone()), inflate(&compressed, data.len()), "level {}", level);
+            if level != 0 {
+                assert!(compressed.len() < data.len() / 4, "level {} gave {} bytes", level, compressed.len());
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_input() {
+        assert_eq!(Ok(vec![]), inflate(&deflate_all(b"", 6, false), 0));
+        assert_eq!(Ok(vec![]), inflate(&deflate_all(b"", 0, false), 0));
+    }
+
+    #[test]
+    fn test_zlib_stream() {
+        // zlib inflates this back to the same text. zlib's own output is a byte longer, as it starts
+        // the match one character later.
+        assert_eq!(vec![0x78, 0x9c, 0x4b, 0x4c, 0x4a, 0x86, 0x23, 0x00, 0x1d, 0xe0, 0x04, 0x99], deflate_all(b"abcabcabcabc", -1, true));
+    }
+
+    #[test]
+    fn test_sync_flush_makes_input_so_far_inflatable() {
+        let mut deflater = DeflateStream::new(6, true);
+        let mut inflater = InflateStream::new(true);
+        let mut buffer = [0; 1000];
+        for text in [&b"hello hello hello "[..], b"world world world"] {
+            deflater.add_input(text);
+            let count = deflater.deflate(&mut buffer, Flush::Sync);
+            inflater.add_input(&buffer[..count]);
+            let mut inflated = [0; 100];
+            let count = inflater.take(&mut inflated).unwrap();
+            assert_eq!(text, &inflated[..count]);
+        }
+        assert!(!inflater.finished());
+    }
+
+    #[test]
+    fn test_large_input_spans_blocks() {
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8 % 7).collect();
+        let compressed = deflate_all(&data, 1, true);
+        let mut inflater = InflateStream::new(true);
+        let mut output = vec![];
+        let mut buffer = [0; 4096];
+        // Given a little at a time, so that blocks are cut off partway.
+        for chunk in compressed.chunks(777) {
+            inflater.add_input(chunk);
+            loop {
+                let count = inflater.take(&mut buffer).unwrap();
+                if count == 0 {
+                    break;
+                }
+                output.extend_from_slice(&buffer[..count]);
+            }
+        }
+        assert!(inflater.finished());
+        assert_eq!(data, output);
+        assert_eq!(adler32(1, &data), inflater.adler());
+        assert_eq!((compressed.len() as u64, data.len() as u64), inflater.totals());
+    }
+}
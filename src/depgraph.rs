@@ -0,0 +1,203 @@
+use crate::classes::Class;
+use crate::classloader;
+use crate::classpath::ClasspathError;
+use crate::jar::{EntryError, JarSource};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Which classes refer to which, across JARs and directories of classes, for questions such as
+// what uses a class and what nothing uses. References are as Class::referenced_classes finds them.
+// Classes are named with slashes, as in class files. As on a classpath, the first class added with
+// a name hides any later ones.
+#[derive(Debug, Default, PartialEq)]
+pub struct DependencyGraph {
+    classes: BTreeMap<String, ClassNode>,
+    unreadable: Vec<String>, // Class files that didn't parse, as jar!entry or file paths.
+}
+
+#[derive(Debug, PartialEq)]
+struct ClassNode {
+    origin: PathBuf, // The JAR or class file it was read from.
+    references: BTreeSet<String>,
+}
+
+impl DependencyGraph {
+    pub fn new() -> DependencyGraph {
+        DependencyGraph::default()
+    }
+
+    // Builds the graph of the classes on a classpath, separated as in the platform's PATH.
+    pub fn from_classpath<S: AsRef<OsStr> + ?Sized>(classpath: &S) -> Result<DependencyGraph, ClasspathError> {
+        let mut graph = DependencyGraph::new();
+        for path in env::split_paths(classpath) {
+            match path.is_dir() {
+                true => graph.add_directory(&path)?,
+                false => graph.add_jar(&path)?,
+            }
+        }
+        Ok(graph)
+    }
+
+    pub fn add_class(&mut self, class: &Class, origin: &Path) {
+        if let Ok(name) = class.name() {
+            self.classes.entry(name.to_string()).or_insert_with(|| ClassNode {
+                origin: origin.to_path_buf(),
+                references: class.referenced_classes().into_iter().collect(),
+            });
+        }
+    }
+
+    // Adds every class in the JAR, other than versioned entries and module descriptors, which are
+    // parsed in parallel.
+    pub fn add_jar(&mut self, path: &Path) -> Result<(), ClasspathError> {
+        let jar = JarSource::open(path)?;
+        for (entry, class) in jar.load_classes() {
+            match class {
+                Ok(class) => self.add_class(&class, path),
+                Err(EntryError::Archive(cause)) => return Err(ClasspathError::Archive{path: path.to_path_buf(), cause}),
+                Err(EntryError::ClassLoad(_)) | Err(EntryError::Panicked(_)) => self.unreadable.push(format!("{}!{}", path.display(), entry)),
+            }
+        }
+        Ok(())
+    }
+
+    // Adds every class file under the directory.
+    pub fn add_directory(&mut self, root: &Path) -> Result<(), ClasspathError> {
+        let mut directories = vec![root.to_path_buf()];
+        while let Some(directory) = directories.pop() {
+            let mut entries: Vec<PathBuf> = fs::read_dir(&directory).map_err(io_error(&directory))?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<_, _>>()
+                .map_err(io_error(&directory))?;
+            // Read in a fixed order, so the first of two classes with the same name is well defined.
+            entries.sort();
+            for path in entries.into_iter().rev() {
+                if path.is_dir() {
+                    directories.push(path);
+                } else if path.extension() == Some(OsStr::new("class")) && path.file_name() != Some(OsStr::new("module-info.class")) {
+                    let bytes = fs::read(&path).map_err(io_error(&path))?;
+                    match classloader::load_class(&bytes) {
+                        Ok(class) => self.add_class(&class, &path),
+                        Err(_) => self.unreadable.push(path.display().to_string()),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn classes(&self) -> impl Iterator<Item = &str> {
+        self.classes.keys().map(String::as_str)
+    }
+
+    pub fn contains(&self, class: &str) -> bool {
+        self.classes.contains_key(class)
+    }
+
+    pub fn origin(&self, class: &str) -> Option<&Path> {
+        self.classes.get(class).map(|node| node.origin.as_path())
+    }
+
+    // The classes the class refers to, whether or not they're in the graph.
+    pub fn references(&self, class: &str) -> Option<impl Iterator<Item = &str>> {
+        self.classes.get(class).map(|node| node.references.iter().map(String::as_str))
+    }
+
+    // The classes in the graph that refer to the class.
+    pub fn referrers<'a>(&'a self, class: &'a str) -> impl Iterator<Item = &'a str> {
+        self.classes.iter().filter(move |(_, node)| node.references.contains(class)).map(|(name, _)| name.as_str())
+    }
+
+    // Classes that are referred to but aren't in the graph, such as those of the platform or of
+    // missing libraries.
+    pub fn missing(&self) -> BTreeSet<&str> {
+        self.classes.values()
+            .flat_map(|node| node.references.iter())
+            .filter(|class| !self.classes.contains_key(*class))
+            .map(String::as_str)
+            .collect()
+    }
+
+    // The classes in the graph that can be reached from the roots, including the roots.
+    pub fn reachable(&self, roots: &[&str]) -> BTreeSet<&str> {
+        let mut reached = BTreeSet::new();
+        let mut pending: Vec<&str> = roots.to_vec();
+        while let Some(class) = pending.pop() {
+            let node = match self.classes.get_key_value(class) {
+                Some((name, node)) if reached.insert(name.as_str()) => node,
+                _ => continue,
+            };
+            pending.extend(node.references.iter().map(String::as_str));
+        }
+        reached
+    }
+
+    // The classes in the graph that can't be reached from the roots, which nothing the roots use
+    // could load by name, short of reflection.
+    pub fn unreachable(&self, roots: &[&str]) -> Vec<&str> {
+        let reached = self.reachable(roots);
+        self.classes().filter(|class| !reached.contains(class)).collect()
+    }
+
+    pub fn unreadable(&self) -> &[String] {
+        &self.unreadable
+    }
+}
+
+fn io_error(path: &Path) -> impl Fn(io::Error) -> ClasspathError + '_ {
+    move |err| ClasspathError::Io{path: path.to_path_buf(), kind: err.kind()}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testdata(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join(path)
+    }
+
+    #[test]
+    fn test_directory_graph() {
+        let graph = DependencyGraph::from_classpath(&testdata("linkage")).unwrap();
+        assert!(graph.contains("Linkage"));
+        assert!(graph.contains("other/Exposed"));
+        // Classes under linkage/changed have the same names as those at the top, which hide them.
+        assert_eq!(Some(testdata("linkage/Changed.class").as_path()), graph.origin("Changed"));
+        assert!(graph.references("Linkage").unwrap().any(|class| class == "Changed"));
+        assert_eq!(None, graph.references("java/lang/Object").map(|references| references.count()));
+
+        let referrers: Vec<&str> = graph.referrers("Circle2").collect();
+        assert!(referrers.contains(&"Circle1"));
+        assert!(!referrers.contains(&"Circle2"));
+        assert!(graph.missing().contains("java/lang/Object"));
+        assert!(!graph.missing().contains("Linkage"));
+        assert!(graph.unreadable().is_empty());
+    }
+
+    #[test]
+    fn test_reachability() {
+        let graph = DependencyGraph::from_classpath(&testdata("linkage")).unwrap();
+        let reachable = graph.reachable(&["Circle1"]);
+        assert_eq!(vec!["Circle1", "Circle2"], reachable.into_iter().collect::<Vec<_>>());
+
+        let unreachable = graph.unreachable(&["Linkage"]);
+        assert!(!unreachable.contains(&"Linkage"));
+        assert!(!unreachable.contains(&"Changed"));
+        assert_eq!(graph.classes().count(), graph.reachable(&["Linkage"]).len() + unreachable.len());
+        assert_eq!(graph.classes().count(), graph.unreachable(&["NotAClass"]).len());
+    }
+
+    #[test]
+    fn test_jar_graph() {
+        let graph = DependencyGraph::from_classpath(&testdata("jars/app.jar")).unwrap();
+        assert!(graph.classes().count() > 0);
+        for class in graph.classes() {
+            assert_eq!(Some(testdata("jars/app.jar").as_path()), graph.origin(class));
+        }
+        assert!(matches!(DependencyGraph::from_classpath(&testdata("jars/missing.jar")), Err(ClasspathError::Io{..})));
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::{error, fmt};
+use std::str::Chars;
+use std::iter::Peekable;
+use std::rc::Rc;
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum FieldType {
+    Byte,
+    Char,
+    Double,
+    Float,
+    Int,
+    Long,
+    Short,
+    Boolean,
+    Object(String),
+    Array(Box<FieldType>),
+}
+
+impl FieldType {
+    pub fn parse(descriptor: &str) -> Result<FieldType, DescriptorError> {
+        let mut chars = descriptor.chars().peekable();
+        let field_type = parse_field_type(&mut chars, descriptor)?;
+        match chars.next() {
+            None => Ok(field_type),
+            Some(_) => Err(DescriptorError::TrailingCharacters(descriptor.to_string())),
+        }
+    }
+
+    // Longs and doubles take up two local variable slots, and count as a single category 2
+    // value on the operand stack.
+    pub fn is_category_2(&self) -> bool {
+        matches!(*self, FieldType::Long | FieldType::Double)
+    }
+
+    pub fn is_reference(&self) -> bool {
+        matches!(*self, FieldType::Object(_) | FieldType::Array(_))
+    }
+
+    // The inverse of parse.
+    pub fn descriptor(&self) -> String {
+        match *self {
+            FieldType::Byte => "B".to_string(),
+            FieldType::Char => "C".to_string(),
+            FieldType::Double => "D".to_string(),
+            FieldType::Float => "F".to_string(),
+            FieldType::Int => "I".to_string(),
+            FieldType::Long => "J".to_string(),
+            FieldType::Short => "S".to_string(),
+            FieldType::Boolean => "Z".to_string(),
+            FieldType::Object(ref name) => format!("L{};", name),
+            FieldType::Array(ref component) => format!("[{}", component.descriptor()),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct MethodDescriptor {
+    pub parameters: Vec<FieldType>,
+    pub return_type: Option<FieldType>, // None for void methods
+}
+
+impl MethodDescriptor {
+    pub fn parse(descriptor: &str) -> Result<MethodDescriptor, DescriptorError> {
+        let mut chars = descriptor.chars().peekable();
+        if chars.next() != Some('(') {
+            return Err(DescriptorError::MissingParameterList(descriptor.to_string()));
+        }
+
+        let mut parameters = vec![];
+        loop {
+            match chars.peek() {
+                Some(')') => {
+                    chars.next();
+                    break;
+                },
+                Some(_) => parameters.push(parse_field_type(&mut chars, descriptor)?),
+                None => return Err(DescriptorError::UnexpectedEnd(descriptor.to_string())),
+            }
+        }
+
+        let return_type = match chars.peek() {
+            Some('V') => {
+                chars.next();
+                None
+            },
+            _ => Some(parse_field_type(&mut chars, descriptor)?),
+        };
+
+        match chars.next() {
+            None => Ok(MethodDescriptor {parameters, return_type}),
+            Some(_) => Err(DescriptorError::TrailingCharacters(descriptor.to_string())),
+        }
+    }
+
+    // The number of local variable slots taken up by the parameters, not counting `this`.
+    pub fn parameter_slots(&self) -> usize {
+        self.parameters.iter().map(|param| if param.is_category_2() { 2 } else { 1 }).sum()
+    }
+}
+
+// Descriptors already parsed by the VM, since linking, verification and invocation see the same
+// ones over and over. Handles to parsed descriptors are shared, so are cheap to clone. Descriptors
+// that fail to parse aren't remembered, as the failure is an error anyway.
+#[derive(Default)]
+pub struct DescriptorCache {
+    fields: HashMap<String, Rc<FieldType>>,
+    methods: HashMap<String, Rc<MethodDescriptor>>,
+}
+
+impl DescriptorCache {
+    pub fn new() -> DescriptorCache {
+        DescriptorCache::default()
+    }
+
+    pub fn field_type(&mut self, descriptor: &str) -> Result<Rc<FieldType>, DescriptorError> {
+        if let Some(field_type) = self.fields.get(descriptor) {
+            return Ok(Rc::clone(field_type));
+        }
+        let field_type = Rc::new(FieldType::parse(descriptor)?);
+        self.fields.insert(descriptor.to_string(), Rc::clone(&field_type));
+        Ok(field_type)
+    }
+
+    pub fn method(&mut self, descriptor: &str) -> Result<Rc<MethodDescriptor>, DescriptorError> {
+        if let Some(method) = self.methods.get(descriptor) {
+            return Ok(Rc::clone(method));
+        }
+        let method = Rc::new(MethodDescriptor::parse(descriptor)?);
+        self.methods.insert(descriptor.to_string(), Rc::clone(&method));
+        Ok(method)
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len() + self.methods.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn parse_field_type(chars: &mut Peekable<Chars>, descriptor: &str) -> Result<FieldType, DescriptorError> {
+    match chars.next() {
+        Some('B') => Ok(FieldType::Byte),
+        Some('C') => Ok(FieldType::Char),
+        Some('D') => Ok(FieldType::Double),
+        Some('F') => Ok(FieldType::Float),
+        Some('I') => Ok(FieldType::Int),
+        Some('J') => Ok(FieldType::Long),
+        Some('S') => Ok(FieldType::Short),
+        Some('Z') => Ok(FieldType::Boolean),
+        Some('L') => {
+            let mut class_name = String::new();
+            loop {
+                match chars.next() {
+                    Some(';') if !class_name.is_empty() => return Ok(FieldType::Object(class_name)),
+                    Some(';') => return Err(DescriptorError::EmptyClassName(descriptor.to_string())),
+                    Some(c) => class_name.push(c),
+                    None => return Err(DescriptorError::UnexpectedEnd(descriptor.to_string())),
+                }
+            }
+        },
+        Some('[') => Ok(FieldType::Array(Box::new(parse_field_type(chars, descriptor)?))),
+        Some(c) => Err(DescriptorError::InvalidTypeCharacter(c, descriptor.to_string())),
+        None => Err(DescriptorError::UnexpectedEnd(descriptor.to_string())),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DescriptorError {
+    EmptyClassName(String),
+    InvalidTypeCharacter(char, String),
+    MissingParameterList(String),
+    TrailingCharacters(String),
+    UnexpectedEnd(String),
+}
+
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DescriptorError::EmptyClassName(ref descriptor) => write!(f, "Empty class name in descriptor '{}'", descriptor),
+            DescriptorError::InvalidTypeCharacter(ref c, ref descriptor) => write!(f, "Invalid type character '{}' in descriptor '{}'", c, descriptor),
+            DescriptorError::MissingParameterList(ref descriptor) => write!(f, "Method descriptor '{}' has no parameter list", descriptor),
+            DescriptorError::TrailingCharacters(ref descriptor) => write!(f, "Unexpected trailing characters in descriptor '{}'", descriptor),
+            DescriptorError::UnexpectedEnd(ref descriptor) => write!(f, "Descriptor '{}' ended unexpectedly", descriptor),
+        }
+    }
+}
+
+impl error::Error for DescriptorError {
+    fn description(&self) -> &str {
+        match *self {
+            DescriptorError::EmptyClassName(..) => "Empty class name in descriptor",
+            DescriptorError::InvalidTypeCharacter(..) => "Invalid type character in descriptor",
+            DescriptorError::MissingParameterList(..) => "Method descriptor has no parameter list",
+            DescriptorError::TrailingCharacters(..) => "Unexpected trailing characters in descriptor",
+            DescriptorError::UnexpectedEnd(..) => "Descriptor ended unexpectedly",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_primitive_field_types() {
+        assert_eq!(Ok(FieldType::Byte), FieldType::parse("B"));
+        assert_eq!(Ok(FieldType::Char), FieldType::parse("C"));
+        assert_eq!(Ok(FieldType::Double), FieldType::parse("D"));
+        assert_eq!(Ok(FieldType::Float), FieldType::parse("F"));
+        assert_eq!(Ok(FieldType::Int), FieldType::parse("I"));
+        assert_eq!(Ok(FieldType::Long), FieldType::parse("J"));
+        assert_eq!(Ok(FieldType::Short), FieldType::parse("S"));
+        assert_eq!(Ok(FieldType::Boolean), FieldType::parse("Z"));
+    }
+
+    #[test]
+    fn test_parse_object_field_type() {
+        assert_eq!(Ok(FieldType::Object("java/lang/String".to_string())), FieldType::parse("Ljava/lang/String;"));
+    }
+
+    #[test]
+    fn test_parse_nested_array_field_type() {
+        let expected = FieldType::Array(Box::new(FieldType::Array(Box::new(FieldType::Object("Foo".to_string())))));
+        assert_eq!(Ok(expected), FieldType::parse("[[LFoo;"));
+    }
+
+    #[test]
+    fn test_field_type_descriptor_round_trips() {
+        for descriptor in ["B", "C", "D", "F", "I", "J", "S", "Z", "Ljava/lang/String;", "[[I", "[LFoo;"].iter() {
+            assert_eq!(*descriptor, FieldType::parse(descriptor).unwrap().descriptor());
+        }
+    }
+
+    #[test]
+    fn test_parse_field_type_with_empty_class_name() {
+        assert_eq!(Err(DescriptorError::EmptyClassName("L;".to_string())), FieldType::parse("L;"));
+    }
+
+    #[test]
+    fn test_parse_field_type_with_unterminated_class_name() {
+        assert_eq!(Err(DescriptorError::UnexpectedEnd("LFoo".to_string())), FieldType::parse("LFoo"));
+    }
+
+    #[test]
+    fn test_parse_field_type_with_trailing_characters() {
+        assert_eq!(Err(DescriptorError::TrailingCharacters("II".to_string())), FieldType::parse("II"));
+    }
+
+    #[test]
+    fn test_parse_void_is_not_a_field_type() {
+        assert_eq!(Err(DescriptorError::InvalidTypeCharacter('V', "V".to_string())), FieldType::parse("V"));
+    }
+
+    #[test]
+    fn test_parse_empty_field_type() {
+        assert_eq!(Err(DescriptorError::UnexpectedEnd("".to_string())), FieldType::parse(""));
+    }
+
+    #[test]
+    fn test_parse_method_descriptor_with_no_parameters_returning_void() {
+        let expected = MethodDescriptor {parameters: vec![], return_type: None};
+        assert_eq!(Ok(expected), MethodDescriptor::parse("()V"));
+    }
+
+    #[test]
+    fn test_parse_method_descriptor_with_mixed_parameters() {
+        let expected = MethodDescriptor {
+            parameters: vec![
+                FieldType::Int,
+                FieldType::Double,
+                FieldType::Object("java/lang/Thread".to_string()),
+            ],
+            return_type: Some(FieldType::Object("java/lang/Object".to_string())),
+        };
+        assert_eq!(Ok(expected), MethodDescriptor::parse("(IDLjava/lang/Thread;)Ljava/lang/Object;"));
+    }
+
+    #[test]
+    fn test_parameter_slots_counts_category_2_types_twice() {
+        let descriptor = MethodDescriptor::parse("(IJ[JDLFoo;)V").unwrap();
+        assert_eq!(7, descriptor.parameter_slots());
+    }
+
+    #[test]
+    fn test_descriptor_cache_shares_parsed_descriptors() {
+        let mut cache = DescriptorCache::new();
+        let first = cache.method("(IJ)V").unwrap();
+        assert!(Rc::ptr_eq(&first, &cache.method("(IJ)V").unwrap()));
+        assert_eq!(MethodDescriptor::parse("(IJ)V").unwrap(), *first);
+        assert!(Rc::ptr_eq(&cache.field_type("[I").unwrap(), &cache.field_type("[I").unwrap()));
+        assert_eq!(2, cache.len());
+    }
+
+    #[test]
+    fn test_descriptor_cache_does_not_keep_failures() {
+        let mut cache = DescriptorCache::new();
+        assert_eq!(Err(DescriptorError::UnexpectedEnd("(I)".to_string())), cache.method("(I)"));
+        assert_eq!(Err(DescriptorError::InvalidTypeCharacter('V', "V".to_string())), cache.field_type("V"));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_parse_method_descriptor_without_parameter_list() {
+        assert_eq!(Err(DescriptorError::MissingParameterList("I".to_string())), MethodDescriptor::parse("I"));
+    }
+
+    #[test]
+    fn test_parse_method_descriptor_without_return_type() {
+        assert_eq!(Err(DescriptorError::UnexpectedEnd("(I)".to_string())), MethodDescriptor::parse("(I)"));
+    }
+
+    #[test]
+    fn test_parse_method_descriptor_with_unterminated_parameter_list() {
+        assert_eq!(Err(DescriptorError::UnexpectedEnd("(II".to_string())), MethodDescriptor::parse("(II"));
+    }
+
+    #[test]
+    fn test_parse_method_descriptor_with_trailing_characters() {
+        assert_eq!(Err(DescriptorError::TrailingCharacters("()VV".to_string())), MethodDescriptor::parse("()VV"));
+    }
+}
@@ -0,0 +1,573 @@
+use crate::bytecode::{self, Operand};
+use crate::classes::*;
+use crate::opcodes::*;
+use std::fmt;
+
+// Compares two versions of a class by what they mean rather than by their bytes: constants are
+// resolved before they're compared, so reordering the constant pool changes nothing, and code is
+// compared instruction by instruction. Debug information and stack maps are left out, since they
+// follow from the source's layout and from the code. Constants that don't resolve are given by
+// their indexes, so malformed classes can still be compared.
+pub fn diff(old: &Class, new: &Class) -> ClassDiff {
+    let mut changes = vec![];
+    if (old.major_version, old.minor_version) != (new.major_version, new.minor_version) {
+        changes.push(Change::Version {
+            old: (old.major_version, old.minor_version),
+            new: (new.major_version, new.minor_version),
+        });
+    }
+    if old.flags != new.flags {
+        changes.push(Change::Flags{member: None, old: old.flags.bits(), new: new.flags.bits()});
+    }
+    let (old_super, new_super) = (class_text(old, &old.super_class), class_text(new, &new.super_class));
+    if old_super != new_super {
+        changes.push(Change::SuperClass{old: old_super, new: new_super});
+    }
+    let old_interfaces: Vec<String> = old.interfaces.iter().map(|interface| class_text(old, interface)).collect();
+    let new_interfaces: Vec<String> = new.interfaces.iter().map(|interface| class_text(new, interface)).collect();
+    for interface in old_interfaces.iter().filter(|interface| !new_interfaces.contains(interface)) {
+        changes.push(Change::InterfaceRemoved(interface.clone()));
+    }
+    for interface in new_interfaces.iter().filter(|interface| !old_interfaces.contains(interface)) {
+        changes.push(Change::InterfaceAdded(interface.clone()));
+    }
+    diff_attributes(None, old, &old.attributes, new, &new.attributes, &mut changes);
+
+    let old_members = members(old);
+    let new_members = members(new);
+    for (member, _) in old_members.iter().filter(|(member, _)| !new_members.iter().any(|(other, _)| other == member)) {
+        changes.push(Change::MemberRemoved(member.clone()));
+    }
+    for (member, new_member) in &new_members {
+        let old_member = match old_members.iter().find(|(other, _)| other == member) {
+            Some((_, old_member)) => old_member,
+            None => {
+                changes.push(Change::MemberAdded(member.clone()));
+                continue;
+            },
+        };
+        if old_member.0 != new_member.0 {
+            changes.push(Change::Flags{member: Some(member.clone()), old: old_member.0, new: new_member.0});
+        }
+        diff_attributes(Some(member), old, old_member.1, new, new_member.1, &mut changes);
+        let old_code = code_lines(old, old_member.1);
+        let new_code = code_lines(new, new_member.1);
+        if old_code != new_code {
+            changes.push(Change::CodeChanged{method: member.clone(), lines: diff_lines(&old_code, &new_code)});
+        }
+    }
+    ClassDiff {changes}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClassDiff {
+    pub changes: Vec<Change>, // Those to the class come first, then those to its members.
+}
+
+impl ClassDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    Version{old: (u16, u16), new: (u16, u16)}, // Major and minor versions.
+    Flags{member: Option<Member>, old: u16, new: u16}, // The class's flags when there's no member.
+    SuperClass{old: String, new: String},
+    InterfaceAdded(String),
+    InterfaceRemoved(String),
+    MemberAdded(Member),
+    MemberRemoved(Member),
+    // An attribute of the class, or of a member, that was added, removed or changed, described
+    // with its constants resolved.
+    Attribute{member: Option<Member>, name: String, old: Option<String>, new: Option<String>},
+    CodeChanged{method: Member, lines: Vec<DiffLine>},
+}
+
+// A field or method, which is the same member across versions if its name and descriptor are.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Member {
+    pub kind: MemberKind,
+    pub name: String,
+    pub descriptor: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemberKind {
+    Field,
+    Method,
+}
+
+// A line of a method's code, as in a unified diff. Branches give their offsets, rather than
+// their targets, so that code added early in a method doesn't change every branch after it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiffLine {
+    Same(String),
+    Removed(String),
+    Added(String),
+}
+
+// How many unchanged lines to show around changed code.
+const CONTEXT_LINES: usize = 2;
+
+impl fmt::Display for ClassDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Change::Version{old, new} => write!(f, "~ version {}.{} -> {}.{}", old.0, old.1, new.0, new.1),
+            Change::Flags{ref member, old, new} => {
+                let names = match *member {
+                    None => CLASS_FLAG_NAMES,
+                    Some(Member {kind: MemberKind::Field, ..}) => FIELD_FLAG_NAMES,
+                    Some(Member {kind: MemberKind::Method, ..}) => METHOD_FLAG_NAMES,
+                };
+                write!(f, "~ {}flags {} -> {}", owner(member), flags_text(old, names), flags_text(new, names))
+            },
+            Change::SuperClass{ref old, ref new} => write!(f, "~ super class {} -> {}", old, new),
+            Change::InterfaceAdded(ref interface) => write!(f, "+ interface {}", interface),
+            Change::InterfaceRemoved(ref interface) => write!(f, "- interface {}", interface),
+            Change::MemberAdded(ref member) => write!(f, "+ {}", member),
+            Change::MemberRemoved(ref member) => write!(f, "- {}", member),
+            Change::Attribute{ref member, ref name, ref old, ref new} => {
+                let (old, new) = (old.as_deref().unwrap_or("(none)"), new.as_deref().unwrap_or("(none)"));
+                write!(f, "~ {}{}: {} -> {}", owner(member), name, old, new)
+            },
+            Change::CodeChanged{ref method, ref lines} => {
+                write!(f, "~ {} code", method)?;
+                let near_change = |index: usize| {
+                    let start = index.saturating_sub(CONTEXT_LINES);
+                    lines[start..lines.len().min(index + CONTEXT_LINES + 1)].iter().any(|line| !matches!(*line, DiffLine::Same(_)))
+                };
+                let mut skipped = false;
+                for (index, line) in lines.iter().enumerate() {
+                    if !near_change(index) {
+                        skipped = true;
+                        continue;
+                    }
+                    if skipped {
+                        write!(f, "\n    ...")?;
+                        skipped = false;
+                    }
+                    match *line {
+                        DiffLine::Same(ref text) => write!(f, "\n    {}", text)?,
+                        DiffLine::Removed(ref text) => write!(f, "\n  - {}", text)?,
+                        DiffLine::Added(ref text) => write!(f, "\n  + {}", text)?,
+                    }
+                }
+                if skipped {
+                    write!(f, "\n    ...")?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl fmt::Display for Member {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            MemberKind::Field => write!(f, "field {}:{}", self.name, self.descriptor),
+            MemberKind::Method => write!(f, "method {}{}", self.name, self.descriptor),
+        }
+    }
+}
+
+fn owner(member: &Option<Member>) -> String {
+    member.as_ref().map_or(String::new(), |member| format!("{} ", member))
+}
+
+fn flags_text(bits: u16, names: &[(u16, &str)]) -> String {
+    let names: Vec<&str> = names.iter().filter(|&&(flag, _)| bits & flag != 0).map(|&(_, name)| name).collect();
+    format!("(0x{:04x}) {}", bits, names.join(", ")).trim_end().to_string()
+}
+
+// Each member, with its flags and attributes, in the order the class gives them.
+fn members(class: &Class) -> Vec<(Member, (u16, &[Attribute]))> {
+    let member = |kind, name, descriptor| Member {kind, name: utf8_text(class, name), descriptor: utf8_text(class, descriptor)};
+    let fields = class.fields.iter()
+        .map(|field| (member(MemberKind::Field, &field.name, &field.descriptor), (field.flags.bits(), &field.attributes[..])));
+    let methods = class.methods.iter()
+        .map(|method| (member(MemberKind::Method, &method.name, &method.descriptor), (method.flags.bits(), &method.attributes[..])));
+    fields.chain(methods).collect()
+}
+
+fn diff_attributes(member: Option<&Member>, old: &Class, old_attributes: &[Attribute], new: &Class, new_attributes: &[Attribute], changes: &mut Vec<Change>) {
+    let old_texts = attribute_texts(old, old_attributes);
+    let new_texts = attribute_texts(new, new_attributes);
+    let mut seen = vec![];
+    for (name, _) in old_texts.iter().chain(&new_texts) {
+        if seen.contains(&name) {
+            continue;
+        }
+        seen.push(name);
+        let find = |texts: &[(String, String)]| texts.iter().find(|(other, _)| other == name).map(|(_, text)| text.clone());
+        let (old_text, new_text) = (find(&old_texts), find(&new_texts));
+        if old_text != new_text {
+            changes.push(Change::Attribute{member: member.cloned(), name: name.clone(), old: old_text, new: new_text});
+        }
+    }
+}
+
+// The attributes that are compared, by name, described with their constants resolved. Any that
+// appear more than once are described together.
+fn attribute_texts(class: &Class, attributes: &[Attribute]) -> Vec<(String, String)> {
+    let mut texts: Vec<(String, String)> = vec![];
+    for attribute in attributes {
+        let (name, text) = match *attribute {
+            Attribute::ConstantValue{ref constant_value, ..} => ("ConstantValue", constant_text(class, constant_value)),
+            Attribute::Exceptions{ref index_table, ..} => ("Exceptions", classes_text(class, index_table)),
+            Attribute::InnerClasses{ref classes, ..} => {
+                let classes: Vec<String> = classes.iter().map(|inner| format!("{} {} of {} (0x{:04x})",
+                    utf8_or_none(class, &inner.inner_class_name), class_text(class, &inner.inner_class),
+                    class_text(class, &inner.outer_class), inner.flags.bits())).collect();
+                ("InnerClasses", classes.join(", "))
+            },
+            Attribute::EnclosingMethod{class: ref enclosing, ref method, ..} => {
+                let method = match method.0 {
+                    0 => String::new(),
+                    _ => format!(".{}", constant_text(class, method)),
+                };
+                ("EnclosingMethod", format!("{}{}", class_text(class, enclosing), method))
+            },
+            Attribute::Synthetic{..} => ("Synthetic", String::new()),
+            Attribute::Signature{ref signature, ..} => ("Signature", utf8_text(class, signature)),
+            Attribute::SourceFile{ref source_file, ..} => ("SourceFile", utf8_text(class, source_file)),
+            Attribute::SourceDebug{ref debug_extension, ..} => ("SourceDebugExtension", String::from_utf8_lossy(debug_extension).into_owned()),
+            Attribute::Deprecated{..} => ("Deprecated", String::new()),
+            Attribute::RuntimeVisibleAnnotations{ref annotations, ..} => ("RuntimeVisibleAnnotations", annotations_text(class, annotations)),
+            Attribute::RuntimeInvisibleAnnotations{ref annotations, ..} => ("RuntimeInvisibleAnnotations", annotations_text(class, annotations)),
+            Attribute::RuntimeVisibleParameterAnnotations{ref annotations_by_param_index, ..} => {
+                let parameters: Vec<String> = annotations_by_param_index.iter().map(|annotations| format!("({})", annotations_text(class, &annotations.0))).collect();
+                ("RuntimeVisibleParameterAnnotations", parameters.join(" "))
+            },
+            Attribute::RuntimeInvisibleParameterAnnotations{ref annotations_by_param_index, ..} => {
+                let parameters: Vec<String> = annotations_by_param_index.iter().map(|annotations| format!("({})", annotations_text(class, &annotations.0))).collect();
+                ("RuntimeInvisibleParameterAnnotations", parameters.join(" "))
+            },
+            Attribute::AnnotationDefault{ref value, ..} => ("AnnotationDefault", element_value_text(class, value)),
+            Attribute::Module(ref module) => {
+                let ModuleAttribute {ref name, flags, ref version, ref requires, ref exports, ref opens, ref uses, ref provides, ..} = **module;
+                let mut text = format!("{} {} (0x{:04x})", constant_text(class, name), utf8_or_none(class, version), flags.bits());
+                for requires in requires {
+                    text.push_str(&format!("; requires {} {} (0x{:04x})", constant_text(class, &requires.module), utf8_or_none(class, &requires.version), requires.flags.bits()));
+                }
+                for (kind, packages) in &[("exports", exports), ("opens", opens)] {
+                    for package in packages.iter() {
+                        text.push_str(&format!("; {} {} to [{}]", kind, constant_text(class, &package.package), constants_text(class, &package.targets)));
+                    }
+                }
+                text.push_str(&format!("; uses [{}]", classes_text(class, uses)));
+                for provides in provides {
+                    text.push_str(&format!("; provides {} with [{}]", class_text(class, &provides.service), classes_text(class, &provides.implementations)));
+                }
+                ("Module", text)
+            },
+            Attribute::ModulePackages{ref packages, ..} => ("ModulePackages", constants_text(class, packages)),
+            Attribute::ModuleMainClass{ref main_class, ..} => ("ModuleMainClass", class_text(class, main_class)),
+            Attribute::NestHost{ref host_class, ..} => ("NestHost", class_text(class, host_class)),
+            Attribute::NestMembers{ref classes, ..} => ("NestMembers", classes_text(class, classes)),
+            // Code is compared on its own, and the bootstrap methods where invokedynamic uses them.
+            // The rest follow from the code and the source's layout.
+            Attribute::Code(_) | Attribute::BootstrapMethods{..} | Attribute::StackMapTable{..} |
+            Attribute::LineNumberTable{..} | Attribute::LocalVariableTable{..} | Attribute::LocalVariableTypeTable{..} => continue,
+        };
+        match texts.iter_mut().find(|(other, _)| other == name) {
+            Some((_, existing)) => {
+                existing.push_str("; ");
+                existing.push_str(&text);
+            },
+            None => texts.push((name.to_string(), text)),
+        }
+    }
+    texts
+}
+
+// A method's code as lines to diff: its instructions, then its exception handlers.
+fn code_lines(class: &Class, attributes: &[Attribute]) -> Vec<String> {
+    let (code, exception_table) = match attributes.iter().find_map(|attribute| match *attribute {
+        Attribute::Code(ref body) => Some((&body.code, &body.exception_table)),
+        _ => None,
+    }) {
+        Some(code) => code,
+        None => return vec![],
+    };
+
+    let mut lines = vec![];
+    let mut pc = 0;
+    while pc < code.len() {
+        let instruction = match bytecode::decode_at(code, pc) {
+            Ok(instruction) => instruction,
+            Err(err) => {
+                lines.push(format!("<{}>", err));
+                break;
+            },
+        };
+        let offset = |target: usize| format!("{:+}", target as i64 - pc as i64);
+        let name = match code[pc] {
+            WIDE => format!("wide {}", mnemonic(instruction.opcode).unwrap_or("???")),
+            opcode => mnemonic(opcode).unwrap_or("???").to_string(),
+        };
+        let operands = match instruction.operand {
+            Operand::None => String::new(),
+            Operand::Local(_) if instruction.length == 1 => String::new(),
+            Operand::Local(local) => local.to_string(),
+            Operand::Increment{local, delta} => format!("{} {}", local, delta),
+            Operand::Int(value) => value.to_string(),
+            Operand::Constant(index) => constant_text(class, &ConstantIndex(index)),
+            Operand::Branch(target) => offset(target),
+            Operand::Switch{default, ref targets} => {
+                let mut cases: Vec<String> = targets.iter().map(|&(key, target)| format!("{}: {}", key, offset(target))).collect();
+                cases.push(format!("default: {}", offset(default)));
+                cases.join(", ")
+            },
+            Operand::MultiArray{class: index, dimensions} => format!("{} {}", class_text(class, &ConstantIndex(index)), dimensions),
+        };
+        lines.push(format!("{} {}", name, operands).trim_end().to_string());
+        pc += instruction.length;
+    }
+    for row in exception_table {
+        let catch_type = match row.catch_type.0 {
+            0 => "any".to_string(),
+            _ => class_text(class, &row.catch_type),
+        };
+        lines.push(format!("catch {} from {} to {} using {}", catch_type, row.start_pc, row.end_pc, row.handler_pc));
+    }
+    lines
+}
+
+// The shortest edit from one sequence of lines to the other, by longest common subsequence.
+fn diff_lines(old: &[String], new: &[String]) -> Vec<DiffLine> {
+    let prefix = old.iter().zip(new).take_while(|(old, new)| old == new).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(old, new)| old == new).count();
+    let (old_middle, new_middle) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+
+    // common[i][j] is the length of the longest common subsequence of old_middle[i..] and new_middle[j..].
+    let mut common = vec![vec![0; new_middle.len() + 1]; old_middle.len() + 1];
+    for i in (0..old_middle.len()).rev() {
+        for j in (0..new_middle.len()).rev() {
+            common[i][j] = match old_middle[i] == new_middle[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+
+    let mut lines: Vec<DiffLine> = old[..prefix].iter().cloned().map(DiffLine::Same).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < old_middle.len() || j < new_middle.len() {
+        if i < old_middle.len() && j < new_middle.len() && old_middle[i] == new_middle[j] {
+            lines.push(DiffLine::Same(old_middle[i].clone()));
+            i += 1;
+            j += 1;
+        } else if j == new_middle.len() || (i < old_middle.len() && common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(DiffLine::Removed(old_middle[i].clone()));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(new_middle[j].clone()));
+            j += 1;
+        }
+    }
+    lines.extend(old[old.len() - suffix..].iter().cloned().map(DiffLine::Same));
+    lines
+}
+
+fn utf8_text(class: &Class, index: &ConstantIndex) -> String {
+    class.utf8(index).map_or_else(|_| format!("#{}", index.0), str::to_string)
+}
+
+fn utf8_or_none(class: &Class, index: &ConstantIndex) -> String {
+    match index.0 {
+        0 => "-".to_string(),
+        _ => utf8_text(class, index),
+    }
+}
+
+fn class_text(class: &Class, index: &ConstantIndex) -> String {
+    match index.0 {
+        0 => "-".to_string(),
+        _ => class.class_name(index).map_or_else(|_| format!("#{}", index.0), str::to_string),
+    }
+}
+
+fn classes_text(class: &Class, indexes: &[ConstantIndex]) -> String {
+    indexes.iter().map(|index| class_text(class, index)).collect::<Vec<_>>().join(", ")
+}
+
+fn constants_text(class: &Class, indexes: &[ConstantIndex]) -> String {
+    indexes.iter().map(|index| constant_text(class, index)).collect::<Vec<_>>().join(", ")
+}
+
+// A constant, resolved into what it refers to. Strings are quoted, to tell them from names.
+fn constant_text(class: &Class, index: &ConstantIndex) -> String {
+    let constant = match index.lookup(&class.constants) {
+        Ok(constant) => constant,
+        Err(_) => return format!("#{}", index.0),
+    };
+    match *constant {
+        Constant::Utf8(ref value) => value.clone(),
+        Constant::Integer(value) => (value as i32).to_string(),
+        Constant::Float(value) => format!("{:?}f", value),
+        Constant::Long(value) => format!("{}L", value as i64),
+        Constant::Double(value) => format!("{:?}d", value),
+        Constant::ClassRef(ref name) => utf8_text(class, name),
+        Constant::StringRef(ref string) => format!("{:?}", utf8_text(class, string)),
+        Constant::FieldRef{class: ref owner, ref name_and_type} |
+        Constant::MethodRef{class: ref owner, ref name_and_type} |
+        Constant::InterfaceMethodRef{class: ref owner, ref name_and_type} => {
+            format!("{}.{}", class_text(class, owner), constant_text(class, name_and_type))
+        },
+        Constant::NameAndTypeRef{ref name, ref descriptor} => format!("{}:{}", utf8_text(class, name), utf8_text(class, descriptor)),
+        Constant::MethodHandleRef(ref handle) => {
+            let (kind, reference) = match *handle {
+                MethodHandle::GetField(ref index) => ("getField", index),
+                MethodHandle::GetStatic(ref index) => ("getStatic", index),
+                MethodHandle::PutField(ref index) => ("putField", index),
+                MethodHandle::PutStatic(ref index) => ("putStatic", index),
+                MethodHandle::InvokeVirtual(ref index) => ("invokeVirtual", index),
+                MethodHandle::InvokeStatic(ref index) => ("invokeStatic", index),
+                MethodHandle::InvokeSpecial(ref index) => ("invokeSpecial", index),
+                MethodHandle::NewInvokeSpecial(ref index) => ("newInvokeSpecial", index),
+                MethodHandle::InvokeInterface(ref index) => ("invokeInterface", index),
+            };
+            format!("{} {}", kind, constant_text(class, reference))
+        },
+        Constant::MethodType(ref descriptor) => utf8_text(class, descriptor),
+        Constant::Dynamic{ref bootstrap_method_attr, ref name_and_type} |
+        Constant::InvokeDynamicInfo{ref bootstrap_method_attr, ref name_and_type} => {
+            let bootstrap_method = match class.bootstrap_method(bootstrap_method_attr) {
+                Some(method) => format!("{}({})", constant_text(class, &method.method), constants_text(class, &method.arguments)),
+                None => format!("bootstrap method {}", bootstrap_method_attr.0),
+            };
+            format!("{} via {}", constant_text(class, name_and_type), bootstrap_method)
+        },
+        Constant::Module(ref name) | Constant::Package(ref name) => utf8_text(class, name),
+        Constant::Dummy => format!("#{}", index.0),
+    }
+}
+
+fn annotations_text(class: &Class, annotations: &[Annotation]) -> String {
+    annotations.iter().map(|annotation| annotation_text(class, annotation)).collect::<Vec<_>>().join(", ")
+}
+
+fn annotation_text(class: &Class, annotation: &Annotation) -> String {
+    let elements: Vec<String> = annotation.indexes_with_values.iter()
+        .map(|(name, value)| format!("{}={}", utf8_text(class, name), element_value_text(class, value)))
+        .collect();
+    format!("@{}({})", utf8_text(class, &annotation.type_index), elements.join(", "))
+}
+
+fn element_value_text(class: &Class, value: &ElementValue) -> String {
+    match *value {
+        ElementValue::Byte(ref index) | ElementValue::Char(ref index) | ElementValue::Double(ref index) |
+        ElementValue::Float(ref index) | ElementValue::Integer(ref index) | ElementValue::Long(ref index) |
+        ElementValue::Short(ref index) | ElementValue::Boolean(ref index) => constant_text(class, index),
+        ElementValue::String(ref index) => format!("{:?}", utf8_text(class, index)),
+        ElementValue::Enum{ref enum_type, ref enum_value} => format!("{}.{}", utf8_text(class, enum_type), utf8_text(class, enum_value)),
+        ElementValue::Class(ref index) => format!("{}.class", utf8_text(class, index)),
+        ElementValue::Annotation(ref annotation) => annotation_text(class, annotation),
+        ElementValue::Array(ref values) => {
+            format!("{{{}}}", values.iter().map(|value| element_value_text(class, value)).collect::<Vec<_>>().join(", "))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::parsed_fixture;
+
+    fn method(name: &str, descriptor: &str) -> Member {
+        Member {kind: MemberKind::Method, name: name.to_string(), descriptor: descriptor.to_string()}
+    }
+
+    #[test]
+    fn test_same_class_has_no_changes() {
+        let class = parsed_fixture!("Threads");
+        assert!(diff(&class, &class).is_empty());
+    }
+
+    #[test]
+    fn test_constant_pool_order_is_ignored() {
+        let (old, new) = (parsed_fixture!("diff/Reordered"), parsed_fixture!("diff/changed/Reordered"));
+        assert_ne!(old.constants, new.constants);
+        assert_eq!(ClassDiff {changes: vec![]}, diff(&old, &new));
+    }
+
+    #[test]
+    fn test_members_and_attributes() {
+        let changes = diff(&parsed_fixture!("diff/Edited"), &parsed_fixture!("diff/changed/Edited")).changes;
+        assert_eq!(Change::InterfaceAdded("java/lang/Runnable".to_string()), changes[0]);
+        assert_eq!(Change::Attribute {
+            member: Some(Member {kind: MemberKind::Field, name: "LIMIT".to_string(), descriptor: "I".to_string()}),
+            name: "ConstantValue".to_string(),
+            old: Some("10".to_string()),
+            new: Some("20".to_string()),
+        }, changes[1]);
+        assert_eq!(Change::Attribute {
+            member: Some(method("check", "()V")),
+            name: "Exceptions".to_string(),
+            old: None,
+            new: Some("java/io/IOException".to_string()),
+        }, changes[3]);
+        assert_eq!(Change::MemberAdded(method("run", "()V")), changes[4]);
+        assert_eq!(5, changes.len());
+
+        let changes = diff(&parsed_fixture!("linkage/Changed"), &parsed_fixture!("linkage/changed/Changed")).changes;
+        assert!(changes.contains(&Change::MemberRemoved(method("removedMethod", "()I"))));
+        assert!(changes.contains(&Change::Flags {member: Some(method("instanceMethod", "()I")), old: 0x0001, new: 0x0009}));
+    }
+
+    #[test]
+    fn test_code_changes() {
+        let changes = diff(&parsed_fixture!("diff/Edited"), &parsed_fixture!("diff/changed/Edited")).changes;
+        let lines = match changes[2] {
+            Change::CodeChanged{method: ref changed, ref lines} if *changed == method("clamp", "(I)I") => lines,
+            ref other => panic!("Expected a code change; got {:?}", other),
+        };
+        let added: Vec<&str> = lines.iter().filter_map(|line| match *line {
+            DiffLine::Added(ref text) => Some(text.as_str()),
+            _ => None,
+        }).collect();
+        assert_eq!(vec!["ifge +5", "iconst_0", "ireturn", "iload_1", "bipush 20", "bipush 20"], added);
+        // The branch past the clamp is as far as it was, so it's unchanged.
+        assert!(lines.contains(&DiffLine::Same("if_icmple +6".to_string())));
+
+        assert_eq!("\
+~ method clamp(I)I code
+    iload_1
+  - bipush 10
+  + ifge +5
+  + iconst_0
+  + ireturn
+  + iload_1
+  + bipush 20
+    if_icmple +6
+  - bipush 10
+  + bipush 20
+    ireturn
+    iload_1
+    ...", changes[2].to_string());
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let lines = |lines: &[&str]| lines.iter().map(|line| line.to_string()).collect::<Vec<_>>();
+        assert_eq!(vec![
+            DiffLine::Same("a".to_string()),
+            DiffLine::Removed("b".to_string()),
+            DiffLine::Same("c".to_string()),
+            DiffLine::Added("d".to_string()),
+            DiffLine::Same("e".to_string()),
+        ], diff_lines(&lines(&["a", "b", "c", "e"]), &lines(&["a", "c", "d", "e"])));
+        assert_eq!(vec![DiffLine::Added("x".to_string())], diff_lines(&[], &lines(&["x"])));
+    }
+}
@@ -0,0 +1,340 @@
+use crate::heap::{ObjectRef, WeakObjectRef};
+use crate::runtime::Value;
+use crate::vm::{Vm, VmError};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::{error, fmt};
+
+// Blocks are spaced apart so that running off the end of one is caught rather than landing in
+// the next, and so that the first address isn't mistaken for null.
+const GUARD_SIZE: u64 = 64;
+
+// Memory outside the Java heap, as allocated by Unsafe.allocateMemory and by direct byte buffers.
+// Addresses are handed out by the VM rather than being real pointers, so a bad address from Java
+// code is reported instead of crashing the process. Values are read and written in the native
+// byte order, as with Unsafe.
+pub struct DirectMemory {
+    blocks: BTreeMap<u64, Vec<u8>>, // By start address.
+    owners: Vec<(WeakObjectRef, u64)>, // Blocks to free once the object that owns them is freed.
+    next_address: u64,
+    reserved: u64,
+    limit: Option<u64>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DirectMemoryError {
+    OutOfMemory{requested: u64, reserved: u64, limit: u64},
+    InvalidAddress{address: u64, length: u64}, // The range isn't inside an allocated block.
+    Unavailable{requested: u64}, // The host couldn't allocate the block, or address it.
+}
+
+impl fmt::Display for DirectMemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DirectMemoryError::OutOfMemory{requested, reserved, limit} =>
+                write!(f, "Cannot reserve {} bytes of direct buffer memory (allocated: {}, limit: {})", requested, reserved, limit),
+            DirectMemoryError::InvalidAddress{address, length} =>
+                write!(f, "Access to {} bytes at address {:#x} is outside any allocated block", length, address),
+            DirectMemoryError::Unavailable{requested} => write!(f, "Cannot allocate {} bytes of direct memory", requested),
+        }
+    }
+}
+
+impl error::Error for DirectMemoryError {
+    fn description(&self) -> &str {
+        match *self {
+            DirectMemoryError::OutOfMemory{..} => "Out of direct memory",
+            DirectMemoryError::InvalidAddress{..} => "Invalid direct memory address",
+            DirectMemoryError::Unavailable{..} => "Direct memory unavailable",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        None
+    }
+}
+
+impl Default for DirectMemory {
+    fn default() -> DirectMemory {
+        DirectMemory {blocks: BTreeMap::new(), owners: vec![], next_address: GUARD_SIZE, reserved: 0, limit: None}
+    }
+}
+
+impl DirectMemory {
+    pub fn new() -> DirectMemory {
+        DirectMemory::default()
+    }
+
+    // Sets the most that may be allocated at once, like -XX:MaxDirectMemorySize, or removes the
+    // limit if it's None. Memory that's already allocated stays allocated.
+    pub fn set_limit(&mut self, limit: Option<u64>) {
+        self.limit = limit;
+    }
+
+    pub fn limit(&self) -> Option<u64> {
+        self.limit
+    }
+
+    // The total size of the blocks currently allocated.
+    pub fn reserved(&self) -> u64 {
+        self.reserved
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    // Allocates a zeroed block, returning its address. The sizes come from Java code, so a block
+    // too large for the host fails with Unavailable rather than aborting the process.
+    pub fn allocate(&mut self, size: u64) -> Result<u64, DirectMemoryError> {
+        let unavailable = || DirectMemoryError::Unavailable{requested: size};
+        let reserved = self.reserved.checked_add(size).ok_or_else(unavailable)?;
+        if let Some(limit) = self.limit.filter(|&limit| reserved > limit) {
+            return Err(DirectMemoryError::OutOfMemory{requested: size, reserved: self.reserved, limit});
+        }
+        let next_address = size.div_ceil(GUARD_SIZE).checked_mul(GUARD_SIZE)
+            .and_then(|span| span.checked_add(GUARD_SIZE))
+            .and_then(|span| self.next_address.checked_add(span))
+            .ok_or_else(unavailable)?;
+        let length = usize::try_from(size).map_err(|_| unavailable())?;
+        let mut block = vec![];
+        block.try_reserve_exact(length).map_err(|_| unavailable())?;
+        block.resize(length, 0);
+
+        let address = self.next_address;
+        self.next_address = next_address;
+        self.blocks.insert(address, block);
+        self.reserved = reserved;
+        Ok(address)
+    }
+
+    // Allocates a block that's freed by free_unowned once the owner has been freed, as the JDK
+    // does for direct byte buffers with a Cleaner.
+    pub fn allocate_owned(&mut self, owner: &ObjectRef, size: u64) -> Result<u64, DirectMemoryError> {
+        let address = self.allocate(size)?;
+        self.owners.push((owner.downgrade(), address));
+        Ok(address)
+    }
+
+    // Frees the block starting at the address. Freeing address 0 does nothing, as with free().
+    pub fn free(&mut self, address: u64) -> Result<(), DirectMemoryError> {
+        if address == 0 {
+            return Ok(());
+        }
+        let block = self.blocks.remove(&address).ok_or(DirectMemoryError::InvalidAddress{address, length: 0})?;
+        self.reserved -= block.len() as u64;
+        Ok(())
+    }
+
+    // Moves the block to a new one of the given size, keeping its contents up to that size. A
+    // zero address allocates a new block, as with realloc().
+    pub fn reallocate(&mut self, address: u64, size: u64) -> Result<u64, DirectMemoryError> {
+        if address == 0 {
+            return self.allocate(size);
+        }
+        let old_size = self.blocks.get(&address).ok_or(DirectMemoryError::InvalidAddress{address, length: 0})?.len() as u64;
+
+        // The old block doesn't count against the limit, but stays put if the new one fails.
+        self.reserved -= old_size;
+        let new_address = self.allocate(size);
+        self.reserved += old_size;
+        let new_address = new_address?;
+        let contents = self.blocks.remove(&address).unwrap_or_default();
+        let kept = contents.len().min(size as usize);
+        self.blocks.get_mut(&new_address).unwrap()[..kept].copy_from_slice(&contents[..kept]);
+        self.reserved -= old_size;
+        Ok(new_address)
+    }
+
+    // Frees the blocks whose owners have been freed, returning the number of bytes released.
+    pub fn free_unowned(&mut self) -> u64 {
+        let before = self.reserved;
+        let (dead, live) = std::mem::take(&mut self.owners).into_iter().partition(|(owner, _)| owner.upgrade().is_none());
+        self.owners = live;
+        for (_, address) in dead {
+            let _ = self.free(address);
+        }
+        before - self.reserved
+    }
+
+    // Finds the block containing the range, and the offset of the range within it.
+    fn locate(&self, address: u64, length: u64) -> Result<(u64, usize), DirectMemoryError> {
+        let invalid = DirectMemoryError::InvalidAddress{address, length};
+        let (&start, block) = self.blocks.range(..=address).next_back().ok_or(invalid)?;
+        let offset = address - start;
+        if offset.checked_add(length).is_none_or(|end| end > block.len() as u64) {
+            return Err(DirectMemoryError::InvalidAddress{address, length});
+        }
+        Ok((start, offset as usize))
+    }
+
+    pub fn read(&self, address: u64, bytes: &mut [u8]) -> Result<(), DirectMemoryError> {
+        let (start, offset) = self.locate(address, bytes.len() as u64)?;
+        bytes.copy_from_slice(&self.blocks[&start][offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    pub fn write(&mut self, address: u64, bytes: &[u8]) -> Result<(), DirectMemoryError> {
+        let (start, offset) = self.locate(address, bytes.len() as u64)?;
+        let block = self.blocks.get_mut(&start).unwrap();
+        block[offset..offset + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    pub fn fill(&mut self, address: u64, length: u64, value: u8) -> Result<(), DirectMemoryError> {
+        let (start, offset) = self.locate(address, length)?;
+        let block = self.blocks.get_mut(&start).unwrap();
+        block[offset..offset + length as usize].fill(value);
+        Ok(())
+    }
+
+    // Copies between two ranges, which may overlap, as with memmove(). Both ranges are checked
+    // first, so the length is no more than a block already holds.
+    pub fn copy(&mut self, from: u64, to: u64, length: u64) -> Result<(), DirectMemoryError> {
+        self.locate(from, length)?;
+        self.locate(to, length)?;
+        let mut bytes = vec![0; length as usize];
+        self.read(from, &mut bytes)?;
+        self.write(to, &bytes)
+    }
+}
+
+// The native methods of sun.misc.Unsafe that work on addresses, and of java.nio.DirectByteBuffer.
+pub fn invoke_native(vm: &mut Vm, class: &str, name: &str, descriptor: &str, args: &[Value]) -> Result<Option<Value>, VmError> {
+    let unsupported = || VmError::Unsupported(format!("native method {}.{}{}", class, name, descriptor));
+    let memory_args = args.get(1..).unwrap_or_default(); // Skips the receiver.
+    let address = match memory_args.first() {
+        Some(&Value::Long(address)) => address as u64,
+        _ => 0,
+    };
+
+    macro_rules! get {
+        ($type:ty, $value:path, $cast:ty) => {{
+            let mut bytes = [0; std::mem::size_of::<$type>()];
+            vm.direct_memory().read(address, &mut bytes)?;
+            Ok(Some($value(<$type>::from_ne_bytes(bytes) as $cast)))
+        }};
+    }
+    macro_rules! put {
+        ($type:ty, $value:expr) => {{
+            vm.direct_memory_mut().write(address, &($value as $type).to_ne_bytes())?;
+            Ok(None)
+        }};
+    }
+
+    match (class, name, descriptor, memory_args) {
+        ("sun/misc/Unsafe", "allocateMemory", "(J)J", &[Value::Long(size)]) => {
+            if size < 0 {
+                return Err(vm.throw_new_with_message("java/lang/IllegalArgumentException", &size.to_string()));
+            }
+            Ok(Some(Value::Long(vm.allocate_direct(None, size as u64)? as i64)))
+        },
+        ("sun/misc/Unsafe", "reallocateMemory", "(JJ)J", &[_, Value::Long(size)]) => {
+            if size < 0 {
+                return Err(vm.throw_new_with_message("java/lang/IllegalArgumentException", &size.to_string()));
+            }
+            Ok(Some(Value::Long(vm.reallocate_direct(address, size as u64)? as i64)))
+        },
+        ("sun/misc/Unsafe", "freeMemory", "(J)V", _) => {
+            vm.free_direct(address)?;
+            Ok(None)
+        },
+        ("sun/misc/Unsafe", "setMemory", "(JJB)V", &[_, Value::Long(length), Value::Int(value)]) => {
+            vm.direct_memory_mut().fill(address, length as u64, value as u8)?;
+            Ok(None)
+        },
+        ("sun/misc/Unsafe", "copyMemory", "(JJJ)V", &[_, Value::Long(to), Value::Long(length)]) => {
+            vm.direct_memory_mut().copy(address, to as u64, length as u64)?;
+            Ok(None)
+        },
+        ("sun/misc/Unsafe", "getByte", "(J)B", _) => get!(i8, Value::Int, i32),
+        ("sun/misc/Unsafe", "getShort", "(J)S", _) => get!(i16, Value::Int, i32),
+        ("sun/misc/Unsafe", "getChar", "(J)C", _) => get!(u16, Value::Int, i32),
+        ("sun/misc/Unsafe", "getInt", "(J)I", _) => get!(i32, Value::Int, i32),
+        ("sun/misc/Unsafe", "getLong", "(J)J", _) => get!(i64, Value::Long, i64),
+        ("sun/misc/Unsafe", "getFloat", "(J)F", _) => get!(f32, Value::Float, f32),
+        ("sun/misc/Unsafe", "getDouble", "(J)D", _) => get!(f64, Value::Double, f64),
+        ("sun/misc/Unsafe", "putByte", "(JB)V", &[_, Value::Int(value)]) => put!(i8, value),
+        ("sun/misc/Unsafe", "putShort", "(JS)V", &[_, Value::Int(value)]) => put!(i16, value),
+        ("sun/misc/Unsafe", "putChar", "(JC)V", &[_, Value::Int(value)]) => put!(u16, value),
+        ("sun/misc/Unsafe", "putInt", "(JI)V", &[_, Value::Int(value)]) => put!(i32, value),
+        ("sun/misc/Unsafe", "putLong", "(JJ)V", &[_, Value::Long(value)]) => put!(i64, value),
+        ("sun/misc/Unsafe", "putFloat", "(JF)V", &[_, Value::Float(value)]) => put!(f32, value),
+        ("sun/misc/Unsafe", "putDouble", "(JD)V", &[_, Value::Double(value)]) => put!(f64, value),
+        // The buffer's memory is freed once the buffer itself is.
+        ("java/nio/DirectByteBuffer", "reserve", "(I)J", &[Value::Int(capacity)]) => match args.first() {
+            Some(&Value::Reference(Some(ref buffer))) => Ok(Some(Value::Long(vm.allocate_direct(Some(buffer), capacity as u64)? as i64))),
+            _ => Err(unsupported()),
+        },
+        _ => Err(unsupported()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_and_write() {
+        let mut memory = DirectMemory::new();
+        let address = memory.allocate(8).unwrap();
+        memory.write(address + 2, &[1, 2, 3]).unwrap();
+        let mut bytes = [0; 5];
+        memory.read(address + 1, &mut bytes).unwrap();
+        assert_eq!([0, 1, 2, 3, 0], bytes);
+        memory.copy(address + 2, address + 3, 3).unwrap();
+        memory.fill(address, 2, 9).unwrap();
+        let mut bytes = [0; 8];
+        memory.read(address, &mut bytes).unwrap();
+        assert_eq!([9, 9, 1, 1, 2, 3, 0, 0], bytes);
+    }
+
+    #[test]
+    fn test_accesses_outside_blocks_fail() {
+        let mut memory = DirectMemory::new();
+        let address = memory.allocate(8).unwrap();
+        assert_eq!(Err(DirectMemoryError::InvalidAddress{address: address + 6, length: 4}), memory.write(address + 6, &[0; 4]));
+        assert!(memory.read(address - 1, &mut [0]).is_err());
+        assert!(memory.read(0, &mut [0]).is_err());
+        memory.free(address).unwrap();
+        assert!(memory.read(address, &mut [0]).is_err());
+        assert!(memory.free(address).is_err());
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut memory = DirectMemory::new();
+        memory.set_limit(Some(100));
+        let address = memory.allocate(60).unwrap();
+        assert_eq!(Err(DirectMemoryError::OutOfMemory{requested: 50, reserved: 60, limit: 100}), memory.allocate(50));
+        let address = memory.reallocate(address, 90).unwrap();
+        assert_eq!(90, memory.reserved());
+        memory.free(address).unwrap();
+        assert_eq!(0, memory.reserved());
+        assert!(memory.allocate(100).is_ok());
+    }
+
+    #[test]
+    fn test_reallocate_keeps_contents() {
+        let mut memory = DirectMemory::new();
+        let address = memory.allocate(2).unwrap();
+        memory.write(address, &[7, 8]).unwrap();
+        let address = memory.reallocate(address, 4).unwrap();
+        let mut bytes = [0; 4];
+        memory.read(address, &mut bytes).unwrap();
+        assert_eq!([7, 8, 0, 0], bytes);
+        assert_eq!(1, memory.block_count());
+    }
+
+    #[test]
+    fn test_blocks_too_large_for_the_host() {
+        let mut memory = DirectMemory::new();
+        assert_eq!(Err(DirectMemoryError::Unavailable{requested: 1 << 50}), memory.allocate(1 << 50));
+        assert_eq!(Err(DirectMemoryError::Unavailable{requested: u64::MAX}), memory.allocate(u64::MAX));
+        let address = memory.allocate(8).unwrap();
+        assert_eq!(Err(DirectMemoryError::Unavailable{requested: u64::MAX}), memory.reallocate(address, u64::MAX));
+        assert_eq!((1, 8), (memory.block_count(), memory.reserved()));
+        assert!(memory.read(address, &mut [0; 8]).is_ok());
+        assert_eq!(Err(DirectMemoryError::InvalidAddress{address, length: u64::MAX}), memory.copy(address, address, u64::MAX));
+    }
+}
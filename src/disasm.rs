@@ -0,0 +1,1125 @@
+use crate::bytecode::{self, BytecodeError, Operand};
+use crate::classes::*;
+use crate::opcodes::*;
+use std::iter::Peekable;
+use std::str::Chars;
+use std::{error, fmt};
+
+// Prints a class file the way javap does, so that the output can be diffed against javap's.
+// Plain output matches javap -c: the class's declaration, and each member's with its code.
+// Verbose output matches javap -v, adding the version, flags, constant pool and the attributes
+// we parse. As with javap, private members are left out unless asked for.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DisasmOptions {
+    pub verbose: bool,
+    pub private: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DisasmError {
+    ConstantLookup(ConstantLookupError),
+    Bytecode(BytecodeError),
+}
+
+impl fmt::Display for DisasmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DisasmError::ConstantLookup(ref err) => write!(f, "Invalid constant: {}", err),
+            DisasmError::Bytecode(ref err) => write!(f, "Invalid bytecode: {}", err),
+        }
+    }
+}
+
+impl error::Error for DisasmError {
+    fn description(&self) -> &str {
+        match *self {
+            DisasmError::ConstantLookup(_) => "Invalid constant",
+            DisasmError::Bytecode(_) => "Invalid bytecode",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            DisasmError::ConstantLookup(ref err) => Some(err),
+            DisasmError::Bytecode(ref err) => Some(err),
+        }
+    }
+}
+
+impl From<ConstantLookupError> for DisasmError {
+    fn from(err: ConstantLookupError) -> DisasmError {
+        DisasmError::ConstantLookup(err)
+    }
+}
+
+impl From<BytecodeError> for DisasmError {
+    fn from(err: BytecodeError) -> DisasmError {
+        DisasmError::Bytecode(err)
+    }
+}
+
+pub fn disassemble(class: &Class, options: DisasmOptions) -> Result<String, DisasmError> {
+    let mut disassembler = Disassembler {class, options, out: String::new()};
+    disassembler.class()?;
+    Ok(disassembler.out)
+}
+
+// javap lines up the comments after constant references in this column.
+const COMMENT_COLUMN: usize = 40;
+
+// The Java modifiers for access flags, in the order javap gives them.
+const MODIFIERS: &[(u16, &str)] = &[
+    (0x0001, "public"), (0x0002, "private"), (0x0004, "protected"), (0x0008, "static"), (0x0010, "final"),
+];
+
+struct Disassembler<'a> {
+    class: &'a Class,
+    options: DisasmOptions,
+    out: String,
+}
+
+impl<'a> Disassembler<'a> {
+    // Like javap, this drops trailing whitespace, as an empty string constant would leave.
+    fn line(&mut self, indent: usize, text: &str) {
+        self.out.push_str(&" ".repeat(indent));
+        self.out.push_str(text.trim_end());
+        self.out.push('\n');
+    }
+
+    // A line with a comment lined up after it, as javap prints constant references.
+    fn commented(&mut self, indent: usize, text: &str, comment: &str) {
+        self.line(indent, &format!("{:<width$}// {}", text, comment, width = COMMENT_COLUMN.max(text.len() + 1)));
+    }
+
+    fn class(&mut self) -> Result<(), DisasmError> {
+        let class = self.class;
+        let verbose = self.options.verbose;
+        if let Some(source_file) = class.source_file() {
+            self.line(if verbose { 2 } else { 0 }, &format!("Compiled from \"{}\"", source_file));
+        }
+        let declaration = self.class_declaration()?;
+        if !verbose {
+            self.line(0, &format!("{} {{", declaration));
+        } else {
+            self.line(0, &declaration);
+            self.line(2, &format!("minor version: {}", class.minor_version));
+            self.line(2, &format!("major version: {}", class.major_version));
+            self.line(2, &format!("flags: {}", flags(class.flags.bits(), CLASS_FLAG_NAMES)));
+            self.commented(2, &format!("this_class: #{}", class.this_class.0), &class_comment(class.class_name(&class.this_class)?));
+            match class.super_class.0 {
+                0 => self.line(2, "super_class: #0"),
+                index => self.commented(2, &format!("super_class: #{}", index), &class_comment(class.class_name(&class.super_class)?)),
+            }
+            self.line(2, &format!("interfaces: {}, fields: {}, methods: {}, attributes: {}",
+                class.interfaces.len(), class.fields.len(), class.methods.len(), class.attributes.len()));
+            self.constant_pool()?;
+            self.line(0, "{");
+        }
+
+        let private = self.options.private;
+        let mut first = true;
+        for field in class.fields.iter().filter(|field| shows(private, field.flags.bits())) {
+            if !first {
+                self.out.push('\n');
+            }
+            first = false;
+            self.field(field)?;
+        }
+        for method in class.methods.iter().filter(|method| shows(private, method.flags.bits())) {
+            if !first {
+                self.out.push('\n');
+            }
+            first = false;
+            self.method(method)?;
+        }
+        self.line(0, "}");
+
+        if verbose {
+            for attribute in &class.attributes {
+                self.attribute(0, attribute)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn class_declaration(&self) -> Result<String, DisasmError> {
+        let class = self.class;
+        let is_interface = class.flags.contains(ClassFlags::INTERFACE);
+        let mut words = vec![];
+        if class.flags.contains(ClassFlags::PUBLIC) {
+            words.push("public");
+        }
+        if class.flags.contains(ClassFlags::FINAL) {
+            words.push("final");
+        }
+        if class.flags.contains(ClassFlags::ABSTRACT) && !is_interface {
+            words.push("abstract");
+        }
+        words.push(if is_interface { "interface" } else { "class" });
+        let mut declaration = format!("{} {}", words.join(" "), java_name(class.name()?));
+
+        let signature = signature_attribute(class, &class.attributes)?.and_then(|signature| class_signature(signature, self.options.verbose));
+        let (type_parameters, super_class, interfaces) = match signature {
+            Some(signature) => signature,
+            None => (
+                String::new(),
+                class.super_class_name()?.map(java_name),
+                class.interfaces.iter().map(|interface| Ok(java_name(class.class_name(interface)?))).collect::<Result<_, DisasmError>>()?,
+            ),
+        };
+        declaration.push_str(&type_parameters);
+        // javap leaves out Object unless it's listing a generic signature in full.
+        let shows_object = self.options.verbose && signature_attribute(class, &class.attributes)?.is_some();
+        if let Some(super_class) = super_class.filter(|super_class| shows_object || super_class != "java.lang.Object") {
+            if !is_interface {
+                declaration.push_str(&format!(" extends {}", super_class));
+            }
+        }
+        if !interfaces.is_empty() {
+            declaration.push_str(if is_interface { " extends " } else { " implements " });
+            declaration.push_str(&interfaces.join(", "));
+        }
+        Ok(declaration)
+    }
+
+    fn constant_pool(&mut self) -> Result<(), DisasmError> {
+        let class = self.class;
+        self.line(0, "Constant pool:");
+        let width = format!("#{}", class.constants.len()).len() + 2;
+        for (index, constant) in class.constants.iter().enumerate() {
+            let (kind, value, comment) = match *constant {
+                Constant::Dummy => continue,
+                Constant::Utf8(ref value) => ("Utf8", escape(value), None),
+                Constant::Integer(value) => ("Integer", (value as i32).to_string(), None),
+                Constant::Float(value) => ("Float", format!("{}f", java_float(value as f64, true)), None),
+                Constant::Long(value) => ("Long", format!("{}l", value as i64), None),
+                Constant::Double(value) => ("Double", format!("{}d", java_float(value, false)), None),
+                Constant::ClassRef(ref name) => ("Class", format!("#{}", name.0), Some(class_comment(class.utf8(name)?))),
+                Constant::StringRef(ref string) => ("String", format!("#{}", string.0), Some(escape(class.utf8(string)?))),
+                Constant::FieldRef{class: ref owner, ref name_and_type} |
+                Constant::MethodRef{class: ref owner, ref name_and_type} |
+                Constant::InterfaceMethodRef{class: ref owner, ref name_and_type} => {
+                    let kind = match *constant {
+                        Constant::FieldRef{..} => "Fieldref",
+                        Constant::MethodRef{..} => "Methodref",
+                        _ => "InterfaceMethodref",
+                    };
+                    (kind, format!("#{}.#{}", owner.0, name_and_type.0), Some(self.member(&ConstantIndex(index as u16 + 1), true)?))
+                },
+                Constant::NameAndTypeRef{ref name, ref descriptor} => {
+                    ("NameAndType", format!("#{}:#{}", name.0, descriptor.0), Some(name_and_type(class.utf8(name)?, class.utf8(descriptor)?)))
+                },
+                Constant::MethodHandleRef(ref handle) => {
+                    let (kind, reference) = method_handle(handle);
+                    ("MethodHandle", format!("{}:#{}", kind, reference.0), Some(self.method_handle(handle)?))
+                },
+                Constant::MethodType(ref descriptor) => ("MethodType", format!("#{}", descriptor.0), Some(format!(" {}", class.utf8(descriptor)?))),
+                Constant::Dynamic{ref bootstrap_method_attr, ref name_and_type} => {
+                    ("Dynamic", format!("#{}:#{}", bootstrap_method_attr.0, name_and_type.0), Some(self.dynamic(bootstrap_method_attr, name_and_type)?))
+                },
+                Constant::InvokeDynamicInfo{ref bootstrap_method_attr, ref name_and_type} => {
+                    ("InvokeDynamic", format!("#{}:#{}", bootstrap_method_attr.0, name_and_type.0), Some(self.dynamic(bootstrap_method_attr, name_and_type)?))
+                },
+                Constant::Module(ref name) => ("Module", format!("#{}", name.0), Some(class.utf8(name)?.to_string())),
+                Constant::Package(ref name) => ("Package", format!("#{}", name.0), Some(class.utf8(name)?.to_string())),
+            };
+            let text = format!("{:>width$} = {:<18} {}", format!("#{}", index + 1), kind, value, width = width);
+            match comment {
+                Some(comment) => self.line(0, &format!("{:<width$}// {}", text, comment, width = (COMMENT_COLUMN + 2).max(text.len() + 1))),
+                None => self.line(0, &text),
+            }
+        }
+        Ok(())
+    }
+
+    fn field(&mut self, field: &Field) -> Result<(), DisasmError> {
+        let class = self.class;
+        let descriptor = class.utf8(&field.descriptor)?;
+        let signature = signature_attribute(class, &field.attributes)?;
+        let field_type = signature.and_then(|signature| parse_type(&mut signature.chars().peekable()))
+            .or_else(|| parse_type(&mut descriptor.chars().peekable()))
+            .unwrap_or_else(|| descriptor.to_string());
+        let mut words = modifiers(field.flags.bits());
+        if field.flags.contains(FieldFlags::VOLATILE) {
+            words.push("volatile");
+        }
+        if field.flags.contains(FieldFlags::TRANSIENT) {
+            words.push("transient");
+        }
+        words.push(&field_type);
+        self.line(2, &format!("{} {};", words.join(" "), class.utf8(&field.name)?));
+
+        if self.options.verbose {
+            self.line(4, &format!("descriptor: {}", descriptor));
+            self.line(4, &format!("flags: {}", flags(field.flags.bits(), FIELD_FLAG_NAMES)));
+            for attribute in &field.attributes {
+                self.attribute(4, attribute)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn method(&mut self, method: &Method) -> Result<(), DisasmError> {
+        let class = self.class;
+        let name = class.utf8(&method.name)?;
+        let descriptor = class.utf8(&method.descriptor)?;
+        self.line(2, &format!("{};", self.method_declaration(method, name, descriptor)?));
+
+        let verbose = self.options.verbose;
+        if verbose {
+            self.line(4, &format!("descriptor: {}", descriptor));
+            self.line(4, &format!("flags: {}", flags(method.flags.bits(), METHOD_FLAG_NAMES)));
+        }
+        for attribute in &method.attributes {
+            match *attribute {
+                Attribute::Code(_) => self.code(method, descriptor)?,
+                _ if verbose => self.attribute(4, attribute)?,
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn method_declaration(&self, method: &Method, name: &str, descriptor: &str) -> Result<String, DisasmError> {
+        let class = self.class;
+        let mut words = modifiers(method.flags.bits());
+        for &(flag, word) in &[(0x0020, "synchronized"), (0x0100, "native"), (0x0400, "abstract"), (0x0800, "strictfp")] {
+            if method.flags.bits() & flag != 0 {
+                words.push(word);
+            }
+        }
+        if name == "<clinit>" {
+            return Ok("static {}".to_string());
+        }
+
+        let signature = signature_attribute(class, &method.attributes)?;
+        let verbose = self.options.verbose;
+        let parsed = signature.and_then(|signature| method_signature(signature, verbose)).or_else(|| method_signature(descriptor, verbose));
+        let (type_parameters, mut parameters, return_type, mut throws) = parsed.unwrap_or_default();
+        if method.flags.contains(MethodFlags::VARARGS) {
+            if let Some(last) = parameters.last_mut().filter(|last| last.ends_with("[]")) {
+                last.truncate(last.len() - 2);
+                last.push_str("...");
+            }
+        }
+        if throws.is_empty() {
+            for attribute in &method.attributes {
+                if let Attribute::Exceptions{ref index_table, ..} = *attribute {
+                    for exception in index_table {
+                        throws.push(java_name(class.class_name(exception)?));
+                    }
+                }
+            }
+        }
+
+        let mut declaration = words.join(" ");
+        if !declaration.is_empty() {
+            declaration.push(' ');
+        }
+        if !type_parameters.is_empty() {
+            declaration.push_str(&type_parameters);
+            declaration.push(' ');
+        }
+        if name == "<init>" {
+            declaration.push_str(&java_name(class.name()?));
+        } else {
+            declaration.push_str(&format!("{} {}", return_type, name));
+        }
+        declaration.push_str(&format!("({})", parameters.join(", ")));
+        if !throws.is_empty() {
+            declaration.push_str(&format!(" throws {}", throws.join(", ")));
+        }
+        Ok(declaration)
+    }
+
+    fn code(&mut self, method: &Method, descriptor: &str) -> Result<(), DisasmError> {
+        let code = match method.code() {
+            Some(code) => code,
+            None => return Ok(()),
+        };
+        let verbose = self.options.verbose;
+        let indent = if verbose { 6 } else { 4 };
+        self.line(4, "Code:");
+        if verbose {
+            // javap counts parameters here, rather than the local variable slots they take.
+            let parameters = method_signature(descriptor, false).map_or(0, |(_, parameters, _, _)| parameters.len());
+            let args_size = parameters + if method.flags.contains(MethodFlags::STATIC) { 0 } else { 1 };
+            self.line(6, &format!("stack={}, locals={}, args_size={}", code.max_stack, code.max_locals, args_size));
+        }
+
+        let mut pc = 0;
+        while pc < code.code.len() {
+            let instruction = bytecode::decode_at(code.code, pc)?;
+            self.instruction(indent, code.code, &instruction)?;
+            pc += instruction.length;
+        }
+
+        if !code.exception_table.is_empty() {
+            self.line(indent, "Exception table:");
+            self.line(indent, "   from    to  target type");
+            for row in code.exception_table {
+                let catch_type = match row.catch_type.0 {
+                    0 => "any".to_string(),
+                    _ => format!("Class {}", self.class.class_name(&row.catch_type)?),
+                };
+                self.line(indent, &format!("   {:>5} {:>5} {:>5}   {}", row.start_pc, row.end_pc, row.handler_pc, catch_type));
+            }
+        }
+        if verbose {
+            for attribute in code.attributes {
+                self.attribute(6, attribute)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn instruction(&mut self, indent: usize, code: &[u8], instruction: &bytecode::Instruction) -> Result<(), DisasmError> {
+        let class = self.class;
+        let pc = instruction.pc;
+        // The decoder folds short forms like iload_0 into the general instruction, and javap
+        // names wide instructions after what they modify, so the name comes from the code.
+        let wide = code[pc] == WIDE;
+        let mnemonic = match wide {
+            true => format!("{}_w", mnemonic(instruction.opcode).unwrap_or("wide")),
+            false => mnemonic(code[pc]).unwrap_or("???").to_string(),
+        };
+        let short_form = instruction.length == 1;
+
+        let (operands, comment) = match instruction.operand {
+            Operand::None => (String::new(), None),
+            Operand::Local(_) if short_form => (String::new(), None),
+            Operand::Local(local) => (local.to_string(), None),
+            Operand::Increment{local, delta} => (format!("{}, {}", local, delta), None),
+            Operand::Int(value) if instruction.opcode == NEWARRAY => (format!(" {}", array_type(value)), None),
+            Operand::Int(value) => (value.to_string(), None),
+            Operand::Branch(target) => (target.to_string(), None),
+            Operand::Constant(index) => {
+                let index = ConstantIndex(index);
+                let operands = match instruction.opcode {
+                    INVOKEINTERFACE => {
+                        let count = code.get(pc + 3).ok_or(BytecodeError::Truncated(pc))?;
+                        format!("#{},  {}", index.0, count)
+                    },
+                    INVOKEDYNAMIC => format!("#{},  0", index.0),
+                    _ => format!("#{}", index.0),
+                };
+                (operands, Some(self.constant_comment(&index)?))
+            },
+            Operand::MultiArray{class: index, dimensions} => {
+                (format!("#{},  {}", index, dimensions), Some(format!("class {}", class_comment(class.class_name(&ConstantIndex(index))?))))
+            },
+            Operand::Switch{default, ref targets} => {
+                let header = match instruction.opcode {
+                    TABLESWITCH => format!("{{ // {} to {}", targets.first().map_or(0, |target| target.0), targets.last().map_or(-1, |target| target.0)),
+                    _ => format!("{{ // {}", targets.len()),
+                };
+                self.line(indent, &format!("{:>4}: {:<13} {}", pc, mnemonic, header));
+                for &(key, target) in targets {
+                    self.line(indent, &format!("{:>18}: {}", key, target));
+                }
+                self.line(indent, &format!("{:>18}: {}", "default", default));
+                self.line(indent, "      }");
+                return Ok(());
+            },
+        };
+
+        let text = match operands.is_empty() {
+            true => format!("{:>4}: {}", pc, mnemonic),
+            false => format!("{:>4}: {:<13} {}", pc, mnemonic, operands),
+        };
+        match comment {
+            Some(comment) => self.line(indent, &format!("{:<width$}// {}", text, comment, width = 40.max(text.len() + 1))),
+            None => self.line(indent, &text),
+        }
+        Ok(())
+    }
+
+    // What an instruction's constant is, as javap describes it.
+    fn constant_comment(&self, index: &ConstantIndex) -> Result<String, DisasmError> {
+        let class = self.class;
+        Ok(match *index.lookup(&class.constants)? {
+            Constant::Integer(value) => format!("int {}", value as i32),
+            Constant::Float(value) => format!("float {}f", java_float(value as f64, true)),
+            Constant::Long(value) => format!("long {}l", value as i64),
+            Constant::Double(value) => format!("double {}d", java_float(value, false)),
+            Constant::ClassRef(ref name) => format!("class {}", class_comment(class.utf8(name)?)),
+            Constant::StringRef(ref string) => format!("String {}", escape(class.utf8(string)?)),
+            Constant::FieldRef{..} => format!("Field {}", self.member(index, false)?),
+            Constant::MethodRef{..} => format!("Method {}", self.member(index, false)?),
+            Constant::InterfaceMethodRef{..} => format!("InterfaceMethod {}", self.member(index, false)?),
+            Constant::MethodHandleRef(ref handle) => format!("MethodHandle {}", self.method_handle(handle)?),
+            Constant::MethodType(ref descriptor) => format!("MethodType {}", class.utf8(descriptor)?),
+            Constant::Dynamic{ref bootstrap_method_attr, ref name_and_type} => {
+                format!("Dynamic {}", self.dynamic(bootstrap_method_attr, name_and_type)?)
+            },
+            Constant::InvokeDynamicInfo{ref bootstrap_method_attr, ref name_and_type} => {
+                format!("InvokeDynamic {}", self.dynamic(bootstrap_method_attr, name_and_type)?)
+            },
+            ref other => format!("{:?}", other),
+        })
+    }
+
+    // A field or method reference, with its class left out if it's this one and qualified is
+    // false, as in javap's comments on instructions.
+    fn member(&self, index: &ConstantIndex, qualified: bool) -> Result<String, DisasmError> {
+        let class = self.class;
+        let member = class.member_ref(index)?;
+        let name = name_and_type(member.name, member.descriptor);
+        if !qualified && member.class == class.name()? {
+            return Ok(name);
+        }
+        Ok(format!("{}.{}", class_comment(member.class), name))
+    }
+
+    fn method_handle(&self, handle: &MethodHandle) -> Result<String, DisasmError> {
+        let (kind, reference) = method_handle(handle);
+        Ok(format!("{} {}", REFERENCE_KINDS[kind as usize - 1], self.member(reference, true)?))
+    }
+
+    fn dynamic(&self, bootstrap_method: &MethodIndex, index: &ConstantIndex) -> Result<String, DisasmError> {
+        let (name, descriptor) = self.class.name_and_type(index)?;
+        Ok(format!("#{}:{}", bootstrap_method.0, name_and_type(name, descriptor)))
+    }
+
+    // Prints an attribute in verbose output. Those of a Code attribute are printed by code.
+    fn attribute(&mut self, indent: usize, attribute: &Attribute) -> Result<(), DisasmError> {
+        let class = self.class;
+        match *attribute {
+            Attribute::ConstantValue{ref constant_value, ..} => {
+                let value = match *constant_value.lookup(&class.constants)? {
+                    Constant::StringRef(ref string) => format!("String {}", escape(class.utf8(string)?)),
+                    _ => self.constant_comment(constant_value)?,
+                };
+                self.line(indent, &format!("ConstantValue: {}", value));
+            },
+            Attribute::Code(_) => (),
+            Attribute::StackMapTable{ref entries, ..} => {
+                self.line(indent, &format!("StackMapTable: number_of_entries = {}", entries.len()));
+                for entry in entries {
+                    self.stack_map_frame(indent + 2, entry)?;
+                }
+            },
+            Attribute::Exceptions{ref index_table, ..} => {
+                self.line(indent, "Exceptions:");
+                let exceptions = index_table.iter().map(|exception| Ok(java_name(class.class_name(exception)?))).collect::<Result<Vec<_>, DisasmError>>()?;
+                self.line(indent + 2, &format!("throws {}", exceptions.join(", ")));
+            },
+            Attribute::InnerClasses{ref classes, ..} => {
+                self.line(indent, "InnerClasses:");
+                for inner in classes {
+                    self.inner_class(indent + 2, inner)?;
+                }
+            },
+            Attribute::EnclosingMethod{class: ref enclosing, ref method, ..} => {
+                let mut comment = class.class_name(enclosing)?.to_string();
+                if method.0 != 0 {
+                    comment.push('.');
+                    comment.push_str(class.name_and_type(method)?.0);
+                }
+                self.commented(indent, &format!("EnclosingMethod: #{}.#{}", enclosing.0, method.0), &comment);
+            },
+            Attribute::Synthetic{..} => self.line(indent, "Synthetic: true"),
+            Attribute::Signature{ref signature, ..} => self.commented(indent, &format!("Signature: #{}", signature.0), class.utf8(signature)?),
+            Attribute::SourceFile{ref source_file, ..} => self.line(indent, &format!("SourceFile: \"{}\"", class.utf8(source_file)?)),
+            Attribute::SourceDebug{ref debug_extension, ..} => {
+                self.line(indent, "SourceDebugExtension:");
+                self.line(indent + 2, &String::from_utf8_lossy(debug_extension));
+            },
+            Attribute::LineNumberTable{ref table, ..} => {
+                self.line(indent, "LineNumberTable:");
+                for &(start_pc, line_number) in table {
+                    self.line(indent + 2, &format!("line {}: {}", line_number, start_pc));
+                }
+            },
+            Attribute::LocalVariableTable{ref variables, ..} => {
+                self.line(indent, "LocalVariableTable:");
+                self.line(indent + 2, "Start  Length  Slot  Name   Signature");
+                for variable in variables {
+                    self.line(indent + 2, &format!("{:>5}  {:>6}  {:>4}  {:>4}   {}",
+                        variable.start_pc, variable.length, variable.index, class.utf8(&variable.name)?, class.utf8(&variable.descriptor)?));
+                }
+            },
+            Attribute::LocalVariableTypeTable{ref variable_types, ..} => {
+                self.line(indent, "LocalVariableTypeTable:");
+                self.line(indent + 2, "Start  Length  Slot  Name   Signature");
+                for variable in variable_types {
+                    self.line(indent + 2, &format!("{:>5}  {:>6}  {:>4}  {:>4}   {}",
+                        variable.start_pc, variable.length, variable.index, class.utf8(&variable.name)?, class.utf8(&variable.signature)?));
+                }
+            },
+            Attribute::Deprecated{..} => self.line(indent, "Deprecated: true"),
+            Attribute::RuntimeVisibleAnnotations{ref annotations, ..} => self.annotations(indent, "RuntimeVisibleAnnotations", annotations)?,
+            Attribute::RuntimeInvisibleAnnotations{ref annotations, ..} => self.annotations(indent, "RuntimeInvisibleAnnotations", annotations)?,
+            Attribute::RuntimeVisibleParameterAnnotations{ref annotations_by_param_index, ..} => {
+                self.parameter_annotations(indent, "RuntimeVisibleParameterAnnotations", annotations_by_param_index)?;
+            },
+            Attribute::RuntimeInvisibleParameterAnnotations{ref annotations_by_param_index, ..} => {
+                self.parameter_annotations(indent, "RuntimeInvisibleParameterAnnotations", annotations_by_param_index)?;
+            },
+            Attribute::AnnotationDefault{ref value, ..} => {
+                self.line(indent, "AnnotationDefault:");
+                self.line(indent + 2, &format!("default_value: {}", self.element_value(value)?));
+            },
+            Attribute::BootstrapMethods{ref methods, ..} => {
+                self.line(indent, "BootstrapMethods:");
+                for (index, method) in methods.iter().enumerate() {
+                    let handle = match *method.method.lookup(&class.constants)? {
+                        Constant::MethodHandleRef(ref handle) => self.method_handle(handle)?,
+                        _ => String::new(),
+                    };
+                    self.line(indent + 2, &format!("{}: #{} {}", index, method.method.0, handle));
+                    self.line(indent + 4, "Method arguments:");
+                    for argument in &method.arguments {
+                        self.line(indent + 6, &format!("#{} {}", argument.0, self.bootstrap_argument(argument)?));
+                    }
+                }
+            },
+            Attribute::Module(ref module) => self.commented(indent, &format!("Module: #{}", module.name.0), class.utf8(&module.name)?),
+            Attribute::ModulePackages{ref packages, ..} => {
+                self.line(indent, "ModulePackages:");
+                for package in packages {
+                    self.commented(indent + 2, &format!("#{}", package.0), class.package_name(package)?);
+                }
+            },
+            Attribute::ModuleMainClass{ref main_class, ..} => {
+                self.commented(indent, &format!("ModuleMainClass: #{}", main_class.0), class.class_name(main_class)?);
+            },
+            Attribute::NestHost{ref host_class, ..} => self.line(indent, &format!("NestHost: class {}", class.class_name(host_class)?)),
+            Attribute::NestMembers{ref classes, ..} => {
+                self.line(indent, "NestMembers:");
+                for member in classes {
+                    self.line(indent + 2, class.class_name(member)?);
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn stack_map_frame(&mut self, indent: usize, frame: &StackMapFrame) -> Result<(), DisasmError> {
+        match *frame {
+            StackMapFrame::SameFrame{offset_delta} => self.line(indent, &format!("frame_type = {} /* same */", offset_delta)),
+            StackMapFrame::SameLocalsOneStackItemFrame{offset_delta, ref stack_item} => {
+                self.line(indent, &format!("frame_type = {} /* same_locals_1_stack_item */", 64 + offset_delta as u16));
+                self.line(indent + 2, &format!("stack = [ {} ]", self.verification_type(stack_item)?));
+            },
+            StackMapFrame::SameLocalsOneStackItemFrameExtended{offset_delta, ref stack_item} => {
+                self.line(indent, "frame_type = 247 /* same_locals_1_stack_item_frame_extended */");
+                self.line(indent + 2, &format!("offset_delta = {}", offset_delta));
+                self.line(indent + 2, &format!("stack = [ {} ]", self.verification_type(stack_item)?));
+            },
+            StackMapFrame::ChopFrame{offset_delta, num_absent_locals} => {
+                self.line(indent, &format!("frame_type = {} /* chop */", 251 - num_absent_locals as u16));
+                self.line(indent + 2, &format!("offset_delta = {}", offset_delta));
+            },
+            StackMapFrame::SameFrameExtended{offset_delta} => {
+                self.line(indent, "frame_type = 251 /* same_frame_extended */");
+                self.line(indent + 2, &format!("offset_delta = {}", offset_delta));
+            },
+            StackMapFrame::AppendFrame{offset_delta, ref new_locals} => {
+                self.line(indent, &format!("frame_type = {} /* append */", 251 + new_locals.len()));
+                self.line(indent + 2, &format!("offset_delta = {}", offset_delta));
+                self.line(indent + 2, &format!("locals = {}", self.verification_types(new_locals)?));
+            },
+            StackMapFrame::FullFrame{offset_delta, ref locals, ref stack_items} => {
+                self.line(indent, "frame_type = 255 /* full_frame */");
+                self.line(indent + 2, &format!("offset_delta = {}", offset_delta));
+                self.line(indent + 2, &format!("locals = {}", self.verification_types(locals)?));
+                self.line(indent + 2, &format!("stack = {}", self.verification_types(stack_items)?));
+            },
+        }
+        Ok(())
+    }
+
+    fn verification_types(&self, types: &[VerificationType]) -> Result<String, DisasmError> {
+        if types.is_empty() {
+            return Ok("[]".to_string());
+        }
+        let types = types.iter().map(|verification_type| self.verification_type(verification_type)).collect::<Result<Vec<_>, _>>()?;
+        Ok(format!("[ {} ]", types.join(", ")))
+    }
+
+    fn verification_type(&self, verification_type: &VerificationType) -> Result<String, DisasmError> {
+        Ok(match *verification_type {
+            VerificationType::Top => "top".to_string(),
+            VerificationType::Integer => "int".to_string(),
+            VerificationType::Float => "float".to_string(),
+            VerificationType::Long => "long".to_string(),
+            VerificationType::Double => "double".to_string(),
+            VerificationType::Null => "null".to_string(),
+            VerificationType::UninitializedThis => "this".to_string(),
+            VerificationType::Object(ref class) => format!("class {}", class_comment(self.class.class_name(class)?)),
+            VerificationType::Uninitialized(offset) => format!("uninitialized {}", offset),
+        })
+    }
+
+    fn inner_class(&mut self, indent: usize, inner: &InnerClassInfo) -> Result<(), DisasmError> {
+        let class = self.class;
+        let mut words = modifiers(inner.flags.bits());
+        if inner.flags.contains(InnerClassFlags::ABSTRACT) && !inner.flags.contains(InnerClassFlags::INTERFACE) {
+            words.push("abstract");
+        }
+        let mut text = words.join(" ");
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        let mut comment = String::new();
+        if inner.inner_class_name.0 != 0 {
+            text.push_str(&format!("#{}= ", inner.inner_class_name.0));
+            comment.push_str(&format!("{}=", class.utf8(&inner.inner_class_name)?));
+        }
+        text.push_str(&format!("#{}", inner.inner_class.0));
+        comment.push_str(&format!("class {}", class.class_name(&inner.inner_class)?));
+        if inner.outer_class.0 != 0 {
+            text.push_str(&format!(" of #{}", inner.outer_class.0));
+            comment.push_str(&format!(" of class {}", class.class_name(&inner.outer_class)?));
+        }
+        text.push(';');
+        self.commented(indent, &text, &comment);
+        Ok(())
+    }
+
+    fn annotations(&mut self, indent: usize, name: &str, annotations: &[Annotation]) -> Result<(), DisasmError> {
+        self.line(indent, &format!("{}:", name));
+        for (index, annotation) in annotations.iter().enumerate() {
+            self.line(indent + 2, &format!("{}: {}", index, self.annotation(annotation)?));
+        }
+        Ok(())
+    }
+
+    fn parameter_annotations(&mut self, indent: usize, name: &str, parameters: &[ParameterAnnotations]) -> Result<(), DisasmError> {
+        self.line(indent, &format!("{}:", name));
+        for (parameter, annotations) in parameters.iter().enumerate() {
+            self.line(indent + 2, &format!("parameter {}:", parameter));
+            for (index, annotation) in annotations.0.iter().enumerate() {
+                self.line(indent + 4, &format!("{}: {}", index, self.annotation(annotation)?));
+            }
+        }
+        Ok(())
+    }
+
+    // An annotation in javap's compact form, by constant pool index.
+    fn annotation(&self, annotation: &Annotation) -> Result<String, DisasmError> {
+        let values = annotation.indexes_with_values.iter()
+            .map(|(name, value)| Ok(format!("#{}={}", name.0, self.element_value(value)?)))
+            .collect::<Result<Vec<_>, DisasmError>>()?;
+        Ok(format!("#{}({})", annotation.type_index.0, values.join(",")))
+    }
+
+    fn element_value(&self, value: &ElementValue) -> Result<String, DisasmError> {
+        Ok(match *value {
+            ElementValue::Byte(ref index) => format!("B#{}", index.0),
+            ElementValue::Char(ref index) => format!("C#{}", index.0),
+            ElementValue::Double(ref index) => format!("D#{}", index.0),
+            ElementValue::Float(ref index) => format!("F#{}", index.0),
+            ElementValue::Integer(ref index) => format!("I#{}", index.0),
+            ElementValue::Long(ref index) => format!("J#{}", index.0),
+            ElementValue::Short(ref index) => format!("S#{}", index.0),
+            ElementValue::Boolean(ref index) => format!("Z#{}", index.0),
+            ElementValue::String(ref index) => format!("s#{}", index.0),
+            ElementValue::Enum{ref enum_type, ref enum_value} => format!("e#{}.#{}", enum_type.0, enum_value.0),
+            ElementValue::Class(ref index) => format!("c#{}", index.0),
+            ElementValue::Annotation(ref annotation) => format!("@{}", self.annotation(annotation)?),
+            ElementValue::Array(ref values) => {
+                let values = values.iter().map(|value| self.element_value(value)).collect::<Result<Vec<_>, _>>()?;
+                format!("[{}]", values.join(","))
+            },
+        })
+    }
+
+    fn bootstrap_argument(&self, argument: &ConstantIndex) -> Result<String, DisasmError> {
+        let class = self.class;
+        Ok(match *argument.lookup(&class.constants)? {
+            Constant::MethodType(ref descriptor) => class.utf8(descriptor)?.to_string(),
+            Constant::MethodHandleRef(ref handle) => self.method_handle(handle)?,
+            Constant::StringRef(ref string) => escape(class.utf8(string)?),
+            Constant::ClassRef(ref name) => class_comment(class.utf8(name)?),
+            Constant::Integer(value) => (value as i32).to_string(),
+            Constant::Float(value) => format!("{}f", java_float(value as f64, true)),
+            Constant::Long(value) => format!("{}l", value as i64),
+            Constant::Double(value) => format!("{}d", java_float(value, false)),
+            _ => self.constant_comment(argument)?,
+        })
+    }
+}
+
+const REFERENCE_KINDS: [&str; 9] = [
+    "REF_getField", "REF_getStatic", "REF_putField", "REF_putStatic", "REF_invokeVirtual", "REF_invokeStatic",
+    "REF_invokeSpecial", "REF_newInvokeSpecial", "REF_invokeInterface",
+];
+
+// The reference kind of a method handle, numbered as in the class file, and what it refers to.
+fn method_handle(handle: &MethodHandle) -> (u8, &ConstantIndex) {
+    match *handle {
+        MethodHandle::GetField(ref index) => (1, index),
+        MethodHandle::GetStatic(ref index) => (2, index),
+        MethodHandle::PutField(ref index) => (3, index),
+        MethodHandle::PutStatic(ref index) => (4, index),
+        MethodHandle::InvokeVirtual(ref index) => (5, index),
+        MethodHandle::InvokeStatic(ref index) => (6, index),
+        MethodHandle::InvokeSpecial(ref index) => (7, index),
+        MethodHandle::NewInvokeSpecial(ref index) => (8, index),
+        MethodHandle::InvokeInterface(ref index) => (9, index),
+    }
+}
+
+fn array_type(code: i32) -> &'static str {
+    match code {
+        4 => "boolean",
+        5 => "char",
+        6 => "float",
+        7 => "double",
+        8 => "byte",
+        9 => "short",
+        10 => "int",
+        11 => "long",
+        _ => "???",
+    }
+}
+
+// Whether a member with these flags is listed: private ones are only if asked for.
+fn shows(private: bool, flags: u16) -> bool {
+    private || flags & 0x0002 == 0
+}
+
+fn flags(bits: u16, names: &[(u16, &str)]) -> String {
+    let names: Vec<&str> = names.iter().filter(|&&(flag, _)| bits & flag != 0).map(|&(_, name)| name).collect();
+    match names.is_empty() {
+        true => format!("(0x{:04x})", bits),
+        false => format!("(0x{:04x}) {}", bits, names.join(", ")),
+    }
+}
+
+fn modifiers(bits: u16) -> Vec<&'static str> {
+    MODIFIERS.iter().filter(|&&(flag, _)| bits & flag != 0).map(|&(_, word)| word).collect()
+}
+
+fn signature_attribute<'a>(class: &'a Class, attributes: &[Attribute]) -> Result<Option<&'a str>, DisasmError> {
+    for attribute in attributes {
+        if let Attribute::Signature{ref signature, ..} = *attribute {
+            return Ok(Some(class.utf8(signature)?));
+        }
+    }
+    Ok(None)
+}
+
+fn java_name(name: &str) -> String {
+    name.replace('/', ".")
+}
+
+// A class name as javap gives it in comments, where array classes are quoted.
+fn class_comment(name: &str) -> String {
+    match name.starts_with('[') {
+        true => format!("\"{}\"", name),
+        false => name.to_string(),
+    }
+}
+
+fn name_and_type(name: &str, descriptor: &str) -> String {
+    match name.starts_with('<') {
+        true => format!("\"{}\":{}", name, descriptor),
+        false => format!("{}:{}", name, descriptor),
+    }
+}
+
+// Escapes a string as javap prints string constants.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\u{8}' => escaped.push_str("\\b"),
+            '\u{c}' => escaped.push_str("\\f"),
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Formats a float or double as Java's toString does: plainly between 10^-3 and 10^7, and in
+// scientific notation otherwise, with the fewest digits that identify the value.
+fn java_float(value: f64, single: bool) -> String {
+    if value.is_nan() {
+        return "NaN".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+    let magnitude = value.abs();
+    if magnitude == 0.0 || (1e-3..1e7).contains(&magnitude) {
+        let plain = if single { (value as f32).to_string() } else { value.to_string() };
+        return if plain.contains('.') { plain } else { format!("{}.0", plain) };
+    }
+    let scientific = if single { format!("{:e}", value as f32) } else { format!("{:e}", value) };
+    let (mantissa, exponent) = scientific.split_at(scientific.find('e').unwrap_or(scientific.len()));
+    let mantissa = if mantissa.contains('.') { mantissa.to_string() } else { format!("{}.0", mantissa) };
+    format!("{}E{}", mantissa, &exponent[1..])
+}
+
+// Generic signatures, per JVM spec 4.7.9.1, turned into Java source form as javap gives them.
+// Descriptors are signatures too. Bounds of plain Object are left out, as javap does unless
+// verbose. These give None for anything malformed.
+fn class_signature(signature: &str, verbose: bool) -> Option<(String, Option<String>, Vec<String>)> {
+    let mut chars = signature.chars().peekable();
+    let type_parameters = parse_type_parameters(&mut chars, verbose)?;
+    let super_class = parse_type(&mut chars)?;
+    let mut interfaces = vec![];
+    while chars.peek().is_some() {
+        interfaces.push(parse_type(&mut chars)?);
+    }
+    Some((type_parameters, Some(super_class), interfaces))
+}
+
+fn method_signature(signature: &str, verbose: bool) -> Option<(String, Vec<String>, String, Vec<String>)> {
+    let mut chars = signature.chars().peekable();
+    let type_parameters = parse_type_parameters(&mut chars, verbose)?;
+    if chars.next()? != '(' {
+        return None;
+    }
+    let mut parameters = vec![];
+    while *chars.peek()? != ')' {
+        parameters.push(parse_type(&mut chars)?);
+    }
+    chars.next();
+    let return_type = parse_type(&mut chars)?;
+    let mut throws = vec![];
+    while chars.next() == Some('^') {
+        throws.push(parse_type(&mut chars)?);
+    }
+    Some((type_parameters, parameters, return_type, throws))
+}
+
+fn parse_type_parameters(chars: &mut Peekable<Chars>, verbose: bool) -> Option<String> {
+    if chars.peek() != Some(&'<') {
+        return Some(String::new());
+    }
+    chars.next();
+    let mut parameters = vec![];
+    while *chars.peek()? != '>' {
+        let mut name = String::new();
+        while *chars.peek()? != ':' {
+            name.push(chars.next()?);
+        }
+        let mut bounds = vec![];
+        while chars.peek() == Some(&':') {
+            chars.next();
+            // The class bound may be missing, when there are interface bounds.
+            if !matches!(chars.peek(), Some(':') | Some('>')) {
+                bounds.push(parse_type(chars)?);
+            }
+        }
+        // javap leaves out bounds that say nothing, unless it's being verbose.
+        if !verbose && bounds == ["java.lang.Object"] {
+            bounds.clear();
+        }
+        match bounds.is_empty() {
+            true => parameters.push(name),
+            false => parameters.push(format!("{} extends {}", name, bounds.join(" & "))),
+        }
+    }
+    chars.next();
+    Some(format!("<{}>", parameters.join(", ")))
+}
+
+fn parse_type(chars: &mut Peekable<Chars>) -> Option<String> {
+    Some(match chars.next()? {
+        'B' => "byte".to_string(),
+        'C' => "char".to_string(),
+        'D' => "double".to_string(),
+        'F' => "float".to_string(),
+        'I' => "int".to_string(),
+        'J' => "long".to_string(),
+        'S' => "short".to_string(),
+        'Z' => "boolean".to_string(),
+        'V' => "void".to_string(),
+        '[' => format!("{}[]", parse_type(chars)?),
+        'T' => {
+            let mut name = String::new();
+            loop {
+                match chars.next()? {
+                    ';' => break name,
+                    c => name.push(c),
+                }
+            }
+        },
+        'L' => {
+            let mut name = String::new();
+            loop {
+                match chars.next()? {
+                    ';' => break name,
+                    '/' => name.push('.'),
+                    '<' => name.push_str(&parse_type_arguments(chars)?),
+                    c => name.push(c),
+                }
+            }
+        },
+        _ => return None,
+    })
+}
+
+// The arguments after the opening <, up to and including the closing >.
+fn parse_type_arguments(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut arguments = vec![];
+    loop {
+        let argument = match *chars.peek()? {
+            '>' => break,
+            '*' => {
+                chars.next();
+                "?".to_string()
+            },
+            '+' => {
+                chars.next();
+                format!("? extends {}", parse_type(chars)?)
+            },
+            '-' => {
+                chars.next();
+                format!("? super {}", parse_type(chars)?)
+            },
+            _ => parse_type(chars)?,
+        };
+        arguments.push(argument);
+    }
+    chars.next();
+    Some(format!("<{}>", arguments.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, parsed_fixture};
+    use crate::classloader;
+
+    fn disassemble_fixture(bytes: &[u8], options: DisasmOptions) -> String {
+        disassemble(&classloader::load_class(bytes).unwrap(), options).unwrap()
+    }
+
+    // These excerpts are javap's output for the same classes.
+    #[test]
+    fn test_plain_matches_javap() {
+        let disassembly = disassemble_fixture(fixture!("Switches"), DisasmOptions::default());
+        assert!(disassembly.starts_with("Compiled from \"Switches.java\"\npublic class Switches {\n  static int total;\n\n"), "{}", disassembly);
+        assert!(disassembly.contains("
+  static int dense(int);
+    Code:
+       0: iload_0
+       1: tableswitch   { // 1 to 5
+                     1: 36
+                     2: 39
+                     3: 42
+                     4: 48
+                     5: 45
+               default: 48
+          }
+      36: bipush        10
+"), "{}", disassembly);
+        assert!(disassembly.ends_with("\n}\n"));
+    }
+
+    #[test]
+    fn test_verbose_matches_javap() {
+        let options = DisasmOptions {verbose: true, private: false};
+        let disassembly = disassemble_fixture(fixture!("Arithmetic"), options);
+        assert!(disassembly.starts_with("  Compiled from \"Arithmetic.java\"
+public class Arithmetic
+  minor version: 0
+  major version: 52
+  flags: (0x0021) ACC_PUBLIC, ACC_SUPER
+  this_class: #8                          // Arithmetic
+  super_class: #2                         // java/lang/Object
+  interfaces: 0, fields: 0, methods: 9, attributes: 1
+Constant pool:
+   #1 = Methodref          #2.#3          // java/lang/Object.\"<init>\":()V
+   #2 = Class              #4             // java/lang/Object
+   #3 = NameAndType        #5:#6          // \"<init>\":()V
+   #4 = Utf8               java/lang/Object
+"), "{}", disassembly);
+        assert!(disassembly.contains("
+  static java.lang.String catchDivideByZero();
+    descriptor: ()Ljava/lang/String;
+    flags: (0x0008) ACC_STATIC
+    Code:
+      stack=2, locals=1, args_size=0
+         0: iconst_1
+         1: iconst_0
+         2: invokestatic  #7                  // Method divide:(II)I
+         5: pop
+         6: goto          15
+         9: astore_0
+        10: aload_0
+        11: invokevirtual #15                 // Method java/lang/ArithmeticException.getMessage:()Ljava/lang/String;
+        14: areturn
+        15: aconst_null
+        16: areturn
+      Exception table:
+         from    to  target type
+             0     6     9   Class java/lang/ArithmeticException
+      LineNumberTable:
+        line 24: 0
+        line 27: 6
+        line 25: 9
+        line 26: 10
+        line 28: 15
+      StackMapTable: number_of_entries = 2
+        frame_type = 73 /* same_locals_1_stack_item */
+          stack = [ class java/lang/ArithmeticException ]
+        frame_type = 5 /* same */
+"), "{}", disassembly);
+        assert!(disassembly.ends_with("}\nSourceFile: \"Arithmetic.java\"\n"));
+    }
+
+    #[test]
+    fn test_private_members() {
+        let bytes = fixture!("Reflection");
+        let disassembly = disassemble_fixture(bytes, DisasmOptions::default());
+        assert!(!disassembly.contains("private"));
+        assert!(disassembly.contains("\n  public long total;\n"));
+
+        let disassembly = disassemble_fixture(bytes, DisasmOptions {verbose: false, private: true});
+        assert!(disassembly.contains("\n  private int count;\n"));
+        assert!(disassembly.contains("\n  private Reflection(int);\n"));
+    }
+
+    #[test]
+    fn test_signatures() {
+        let class = class_signature("<T::Ljava/lang/Comparable<TT;>;>Ljava/lang/Object;Ljava/lang/Runnable;", false).unwrap();
+        assert_eq!(("<T extends java.lang.Comparable<T>>".to_string(), Some("java.lang.Object".to_string()), vec!["java.lang.Runnable".to_string()]), class);
+        assert_eq!("<T>", class_signature("<T:Ljava/lang/Object;>Ljava/lang/Object;", false).unwrap().0);
+        assert_eq!("<T extends java.lang.Object>", class_signature("<T:Ljava/lang/Object;>Ljava/lang/Object;", true).unwrap().0);
+
+        let method = method_signature("<E:Ljava/lang/Exception;>(Ljava/util/Map<-TE;*>;[[IJ)Ljava/util/List<+Ljava/lang/Number;>;^TE;", false).unwrap();
+        assert_eq!("<E extends java.lang.Exception>", method.0);
+        assert_eq!(vec!["java.util.Map<? super E, ?>", "int[][]", "long"], method.1);
+        assert_eq!("java.util.List<? extends java.lang.Number>", method.2);
+        assert_eq!(vec!["E"], method.3);
+
+        assert_eq!(None, method_signature("(I", false));
+        assert_eq!(None, class_signature("Ljava/lang/Object", false));
+    }
+
+    #[test]
+    fn test_truncated_invokeinterface() {
+        let class = parsed_fixture!("Switches");
+        let mut disassembler = Disassembler {class: &class, options: DisasmOptions::default(), out: String::new()};
+        let instruction = bytecode::Instruction {pc: 0, opcode: INVOKEINTERFACE, length: 5, operand: Operand::Constant(1)};
+        let result = disassembler.instruction(4, &[INVOKEINTERFACE, 0, 1], &instruction);
+        assert_eq!(Err(DisasmError::Bytecode(BytecodeError::Truncated(0))), result);
+    }
+
+    #[test]
+    fn test_constants_format_as_java() {
+        assert_eq!("0.1", java_float(0.1, false));
+        assert_eq!("100.0", java_float(100.0, false));
+        assert_eq!("1.0E7", java_float(1e7, false));
+        assert_eq!("1.0E-4", java_float(1e-4, false));
+        assert_eq!("1.5", java_float(1.5, true));
+        assert_eq!("-0.0", java_float(-0.0, false));
+        assert_eq!("-Infinity", java_float(f64::NEG_INFINITY, true));
+        assert_eq!("NaN", java_float(f64::NAN, false));
+
+        assert_eq!("tab\\there \\\"q\\\" \\\\ \\u0001 é", escape("tab\there \"q\" \\ \u{1} é"));
+    }
+}
@@ -0,0 +1,120 @@
+use crate::runtime::*;
+use crate::vm::{Vm, VmError};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Where java.util.Random gets the seeds of generators that aren't given one. The default draws on
+// the host's randomness; embedders can install another with Vm::set_entropy_source, say to make
+// a program's random choices the same on every run.
+pub trait EntropySource {
+    fn next_seed(&mut self) -> i64;
+}
+
+// Seeds from std's per-process random hash keys, mixed with the time and a counter so that no
+// two are alike.
+#[derive(Default)]
+pub struct HostEntropy {
+    count: u64,
+}
+
+impl EntropySource for HostEntropy {
+    fn next_seed(&mut self) -> i64 {
+        self.count += 1;
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(self.count);
+        hasher.write_u128(SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_nanos()));
+        hasher.finish() as i64
+    }
+}
+
+// The same sequence of seeds for the same starting seed, from the SplitMix64 generator.
+pub struct SeededEntropy {
+    state: u64,
+}
+
+impl SeededEntropy {
+    pub fn new(seed: u64) -> SeededEntropy {
+        SeededEntropy {state: seed}
+    }
+}
+
+impl EntropySource for SeededEntropy {
+    fn next_seed(&mut self) -> i64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        (z ^ (z >> 31)) as i64
+    }
+}
+
+pub fn invoke_native(vm: &mut Vm, class: &str, name: &str, descriptor: &str, args: &[Value]) -> Result<Option<Value>, VmError> {
+    match (class, name, descriptor, args) {
+        ("java/util/Random", "newSeed", "()J", &[]) => Ok(Some(Value::Long(vm.entropy_source_mut().next_seed()))),
+        _ => Err(VmError::Unsupported(format!("native method {}.{}{}", class, name, descriptor))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+
+
+    fn next(vm: &mut Vm, method: &str, descriptor: &str, args: Vec<Value>) -> Value {
+        vm.invoke_static("Randomness", method, descriptor, args).unwrap().unwrap()
+    }
+
+    fn next_ints(vm: &mut Vm, count: usize) -> Vec<Value> {
+        (0..count).map(|_| next(vm, "nextInt", "()I", vec![])).collect()
+    }
+
+    #[test]
+    fn test_seeded_random_matches_the_jdk() {
+        let mut vm = vm_with(&[fixture!("Randomness")]);
+        assert_eq!(Ok(None), vm.invoke_static("Randomness", "seed", "(J)V", vec![Value::Long(42)]));
+        assert_eq!(Value::Int(-1170105035), next(&mut vm, "nextInt", "()I", vec![]));
+        assert_eq!(Value::Int(3), next(&mut vm, "nextIntBelow", "(I)I", vec![Value::Int(10)]));
+        assert_eq!(Value::Int(10), next(&mut vm, "nextIntBelow", "(I)I", vec![Value::Int(16)]));
+        assert_eq!(Value::Long(884324181205335268), next(&mut vm, "nextLong", "()J", vec![]));
+        assert_eq!(Value::Int(1), next(&mut vm, "nextBoolean", "()Z", vec![]));
+        assert_eq!(Value::Double(0.27707849007413665), next(&mut vm, "nextDouble", "()D", vec![]));
+        assert_eq!(Value::Float(0.6655489), next(&mut vm, "nextFloat", "()F", vec![]));
+
+        assert_eq!(Ok(None), vm.invoke_static("Randomness", "seed", "(J)V", vec![Value::Long(7)]));
+        let bytes = match next(&mut vm, "nextBytes", "(I)[B", vec![Value::Int(6)]) {
+            Value::Reference(Some(bytes)) => bytes.fields.borrow().bytes().unwrap().to_vec(),
+            other => panic!("Expected a byte array; got {:?}", other),
+        };
+        assert_eq!(vec![-103, 23, 15, -69, 24, 52], bytes);
+    }
+
+    #[test]
+    fn test_seeded_entropy_makes_unseeded_randoms_reproducible() {
+        let mut runs = vec![];
+        for _ in 0..2 {
+            let mut vm = vm_with(&[fixture!("Randomness")]);
+            vm.set_entropy_source(Box::new(SeededEntropy::new(1234)));
+            let mut run = vec![];
+            for _ in 0..2 {
+                assert_eq!(Ok(None), vm.invoke_static("Randomness", "unseeded", "()V", vec![]));
+                run.push(next_ints(&mut vm, 3));
+            }
+            assert_ne!(run[0], run[1]);
+            runs.push(run);
+        }
+        assert_eq!(runs[0], runs[1]);
+    }
+
+    #[test]
+    fn test_host_entropy_differs_between_randoms() {
+        let mut vm = vm_with(&[fixture!("Randomness")]);
+        let mut runs = vec![];
+        for _ in 0..2 {
+            assert_eq!(Ok(None), vm.invoke_static("Randomness", "unseeded", "()V", vec![]));
+            runs.push(next_ints(&mut vm, 3));
+        }
+        assert_ne!(runs[0], runs[1]);
+    }
+}
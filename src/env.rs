@@ -0,0 +1,382 @@
+use crate::classes::ClassFlags;
+use crate::handles::{GlobalRef, LocalRef};
+use crate::heap::ObjectRef;
+use crate::interpreter;
+use crate::runtime::*;
+use crate::vm::{self, Vm, VmError};
+use std::rc::Rc;
+
+// What a registered native can do to the VM, modelled on JNI's JNIEnv: make strings, objects and
+// arrays, read and write fields, call Java methods, throw exceptions and hold references. Natives
+// are given an Env rather than the Vm itself, so that they only use the VM through operations
+// that keep its state consistent.
+//
+// As in JNI, classes are named in internal form, and found through the loader of the class that
+// declares the native. Errors, including exceptions thrown by Java code the native calls, are
+// returned as VmErrors, which the native can propagate with ? to rethrow them.
+pub struct Env<'a> {
+    vm: &'a mut Vm,
+    class: Rc<RuntimeClass>, // The class declaring the native.
+}
+
+impl<'a> Env<'a> {
+    pub fn new(vm: &'a mut Vm, class: Rc<RuntimeClass>) -> Env<'a> {
+        Env {vm, class}
+    }
+
+    // The class that declares the native being run.
+    pub fn native_class(&self) -> &Rc<RuntimeClass> {
+        &self.class
+    }
+
+    pub fn find_class(&mut self, name: &str) -> Result<Rc<RuntimeClass>, VmError> {
+        self.vm.load_class_with(self.class.loader, name)
+    }
+
+    // Constructs an object by running the constructor with the given descriptor.
+    pub fn new_object(&mut self, class_name: &str, descriptor: &str, args: Vec<Value>) -> Result<ObjectRef, VmError> {
+        let class = self.find_class(class_name)?;
+        if class.is_interface() || class.class.flags.contains(ClassFlags::ABSTRACT) {
+            return Err(VmError::InvalidBytecode(format!("Cannot instantiate abstract class or interface {}", class.name)));
+        }
+        self.vm.initialize(&class)?;
+        let init = class.find_declared_method("<init>", descriptor)?.ok_or_else(|| method_not_found(&class, "<init>", descriptor))?;
+        let object = self.vm.new_object(&class);
+        if class.is_throwable() {
+            self.vm.fill_in_stack_trace(&object)?;
+        }
+        let mut all_args = vec![Value::Reference(Some(object.clone()))];
+        all_args.extend(args);
+        interpreter::invoke(self.vm, &class, init, all_args)?;
+        Ok(object)
+    }
+
+    pub fn is_instance_of(&mut self, object: &ObjectRef, class_name: &str) -> Result<bool, VmError> {
+        let class = self.find_class(class_name)?;
+        Ok(object.class().is_assignable_to(&class))
+    }
+
+    pub fn new_string(&mut self, text: &str) -> Result<ObjectRef, VmError> {
+        self.vm.new_string(text)
+    }
+
+    pub fn read_string(&self, string: &ObjectRef) -> Result<String, VmError> {
+        self.vm.read_string(string)
+    }
+
+    // Reads an instance field by name, which may be inherited.
+    pub fn get_field(&self, object: &ObjectRef, name: &str) -> Result<Value, VmError> {
+        vm::get_field(object, name)
+    }
+
+    pub fn set_field(&mut self, object: &ObjectRef, name: &str, value: Value) -> Result<(), VmError> {
+        vm::set_field(object, name, value)
+    }
+
+    // Reads a static field, initializing the class that declares it first.
+    pub fn get_static_field(&mut self, class_name: &str, name: &str) -> Result<Value, VmError> {
+        let (class, slot) = self.static_field(class_name, name)?;
+        Ok(class.get_static(slot))
+    }
+
+    pub fn set_static_field(&mut self, class_name: &str, name: &str, value: Value) -> Result<(), VmError> {
+        let (class, slot) = self.static_field(class_name, name)?;
+        class.put_static(slot, value);
+        Ok(())
+    }
+
+    fn static_field(&mut self, class_name: &str, name: &str) -> Result<(Rc<RuntimeClass>, usize), VmError> {
+        let class = self.find_class(class_name)?;
+        let (declaring_class, slot) = resolve_static_field(&class, name).ok_or_else(|| VmError::FieldNotFound {
+            class: class.name.to_string(),
+            name: name.to_string(),
+        })?;
+        self.vm.initialize(&declaring_class)?;
+        Ok((declaring_class, slot))
+    }
+
+    // Calls a static method, initializing its class first if necessary.
+    pub fn call_static(&mut self, class_name: &str, name: &str, descriptor: &str, args: Vec<Value>) -> Result<Option<Value>, VmError> {
+        let class = self.find_class(class_name)?;
+        self.vm.initialize(&class)?;
+        let (declaring_class, method) = resolve_method(&class, name, descriptor)?.ok_or_else(|| method_not_found(&class, name, descriptor))?;
+        interpreter::invoke(self.vm, &declaring_class, method, args)
+    }
+
+    // Calls an instance method, selecting the implementation from the receiver's class as
+    // invokevirtual does. The receiver isn't included in the arguments.
+    pub fn call_method(&mut self, object: &ObjectRef, name: &str, descriptor: &str, args: Vec<Value>) -> Result<Option<Value>, VmError> {
+        let receiver_class = Rc::clone(object.class());
+        let (class, method) = resolve_method(&receiver_class, name, descriptor)?.ok_or_else(|| method_not_found(&receiver_class, name, descriptor))?;
+        interpreter::check_implemented(self.vm, &receiver_class, &class, method)?;
+        let mut all_args = vec![Value::Reference(Some(object.clone()))];
+        all_args.extend(args);
+        interpreter::invoke(self.vm, &class, method, all_args)
+    }
+
+    // Returns an error that throws the exception when the native returns it.
+    pub fn throw(&mut self, exception: ObjectRef) -> VmError {
+        VmError::UncaughtException(exception)
+    }
+
+    pub fn throw_new(&mut self, class_name: &str) -> VmError {
+        self.vm.throw_new(class_name)
+    }
+
+    pub fn throw_new_with_message(&mut self, class_name: &str, message: &str) -> VmError {
+        self.vm.throw_new_with_message(class_name, message)
+    }
+
+    // Creates an array of the named array class, such as [I or [Ljava/lang/String;, with every
+    // element set to the default value.
+    pub fn new_array(&mut self, class_name: &str, length: usize) -> Result<ObjectRef, VmError> {
+        let class = self.find_class(class_name)?;
+        self.vm.new_array(&class, length)
+    }
+
+    pub fn array_length(&self, array: &ObjectRef) -> Result<usize, VmError> {
+        check_array(array)?;
+        Ok(array.fields.borrow().len())
+    }
+
+    // Reads an element, throwing ArrayIndexOutOfBoundsException if there's no such element.
+    pub fn get_array_element(&mut self, array: &ObjectRef, index: usize) -> Result<Value, VmError> {
+        self.check_index(array, index)?;
+        Ok(array.fields.borrow().get(index))
+    }
+
+    // Writes an element, throwing ArrayStoreException if the value doesn't fit the array.
+    pub fn set_array_element(&mut self, array: &ObjectRef, index: usize, value: Value) -> Result<(), VmError> {
+        self.check_index(array, index)?;
+        if let Value::Reference(Some(ref element)) = value {
+            let component = array.class().component_class.clone();
+            if component.is_some_and(|component| !element.class().is_assignable_to(&component)) {
+                return Err(self.vm.throw_new_with_message("java/lang/ArrayStoreException", &element.class().java_name()));
+            }
+        }
+        // Arrays of references would hold any value, so that case is checked here.
+        let is_reference_array = array.fields.borrow().values().is_some();
+        if is_reference_array != matches!(value, Value::Reference(_)) || !array.fields.borrow_mut().set(index, value) {
+            return Err(VmError::NativeSignature(format!("value doesn't fit an element of {}", array.class().name)));
+        }
+        Ok(())
+    }
+
+    fn check_index(&mut self, array: &ObjectRef, index: usize) -> Result<(), VmError> {
+        let length = self.array_length(array)?;
+        if index >= length {
+            let message = format!("Index {} out of bounds for length {}", index, length);
+            return Err(self.vm.throw_new_with_message("java/lang/ArrayIndexOutOfBoundsException", &message));
+        }
+        Ok(())
+    }
+
+    // Local references last until the native returns, unless deleted first or created within a
+    // local frame; global ones last until they're deleted.
+    pub fn new_local_ref(&mut self, object: ObjectRef) -> LocalRef {
+        self.vm.new_local_ref(object)
+    }
+
+    pub fn local_ref(&self, local: LocalRef) -> Option<ObjectRef> {
+        self.vm.local_ref(local).cloned()
+    }
+
+    pub fn delete_local_ref(&mut self, local: LocalRef) {
+        self.vm.delete_local_ref(local);
+    }
+
+    pub fn new_global_ref(&mut self, object: ObjectRef) -> GlobalRef {
+        self.vm.new_global_ref(object)
+    }
+
+    pub fn global_ref(&self, global: GlobalRef) -> Option<ObjectRef> {
+        self.vm.global_ref(global).cloned()
+    }
+
+    pub fn delete_global_ref(&mut self, global: GlobalRef) {
+        self.vm.delete_global_ref(global);
+    }
+
+    // Runs the function in a local frame of its own, so that the local references it creates
+    // are deleted when it returns, as with JNI's PushLocalFrame and PopLocalFrame.
+    pub fn with_local_frame<T>(&mut self, f: impl FnOnce(&mut Env) -> T) -> T {
+        self.vm.push_local_frame();
+        let result = f(self);
+        self.vm.pop_local_frame(None);
+        result
+    }
+}
+
+fn check_array(array: &ObjectRef) -> Result<(), VmError> {
+    if !array.is_array() {
+        return Err(VmError::NativeSignature(format!("{} is not an array class", array.class().name)));
+    }
+    Ok(())
+}
+
+fn method_not_found(class: &RuntimeClass, name: &str, descriptor: &str) -> VmError {
+    VmError::MethodNotFound {
+        class: class.name.to_string(),
+        name: name.to_string(),
+        descriptor: descriptor.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use std::cell::Cell;
+
+
+    // Runs the function with an Env for a native of Natives.
+    fn with_env<T>(vm: &mut Vm, f: impl FnOnce(&mut Env) -> T) -> T {
+        let class = vm.load_class("Natives").unwrap();
+        f(&mut Env::new(vm, class))
+    }
+
+    fn expect_thrown<T: std::fmt::Debug>(result: Result<T, VmError>, exception_class: &str) {
+        match result {
+            Err(VmError::UncaughtException(ref exception)) if exception.class().name == exception_class => (),
+            other => panic!("Expected {}; got {:?}", exception_class, other),
+        }
+    }
+
+    #[test]
+    fn test_fields() {
+        let mut vm = vm_with(&[fixture!("Natives"), fixture!("LoudNatives")]);
+        vm.register_native("Natives", "bump", "(I)I", |env: &mut Env, this: ObjectRef, by: i32| -> Result<i32, VmError> {
+            let count = match env.get_field(&this, "count")? {
+                Value::Int(count) => count + by,
+                other => panic!("count is {:?}", other),
+            };
+            env.set_field(&this, "count", Value::Int(count))?;
+            Ok(count)
+        });
+        assert_eq!(Ok(Some(Value::Int(5))), vm.invoke_static("Natives", "bumpTwice", "()I", vec![]));
+        assert!(matches!(with_env(&mut vm, |env| env.get_static_field("Natives", "missing")), Err(VmError::FieldNotFound{..})));
+    }
+
+    #[test]
+    fn test_strings_and_static_fields() {
+        let mut vm = vm_with(&[fixture!("Natives"), fixture!("LoudNatives")]);
+        vm.register_native("Natives", "greet", "(Ljava/lang/String;)Ljava/lang/String;", |env: &mut Env, name: ObjectRef| -> Result<ObjectRef, VmError> {
+            let greeting = match env.get_static_field("Natives", "greeting")? {
+                Value::Reference(Some(greeting)) => env.read_string(&greeting)?,
+                other => panic!("greeting is {:?}", other),
+            };
+            let name = env.read_string(&name)?;
+            env.set_static_field("Natives", "greeting", Value::null())?;
+            env.new_string(&format!("{}, {}", greeting, name))
+        });
+        let name = vm.new_string("world").unwrap();
+        let greeting = match vm.invoke_static("Natives", "greet", "(Ljava/lang/String;)Ljava/lang/String;", vec![Value::Reference(Some(name))]) {
+            Ok(Some(Value::Reference(Some(greeting)))) => greeting,
+            other => panic!("Expected a string; got {:?}", other),
+        };
+        assert_eq!("hello, world", vm.read_string(&greeting).unwrap());
+        assert_eq!(Some(Value::null()), vm.load_class("Natives").unwrap().static_value("greeting"));
+    }
+
+    #[test]
+    fn test_arrays() {
+        let mut vm = vm_with(&[fixture!("Natives"), fixture!("LoudNatives")]);
+        vm.register_native("Natives", "range", "(I)[I", |env: &mut Env, length: i32| -> Result<ObjectRef, VmError> {
+            let array = env.new_array("[I", length as usize)?;
+            for index in 0..length as usize {
+                env.set_array_element(&array, index, Value::Int(index as i32))?;
+            }
+            Ok(array)
+        });
+        assert_eq!(Ok(Some(Value::Int(10))), vm.invoke_static("Natives", "sumRange", "(I)I", vec![Value::Int(5)]));
+
+        with_env(&mut vm, |env| {
+            let strings = env.new_array("[Ljava/lang/String;", 2).unwrap();
+            assert_eq!(Ok(2), env.array_length(&strings));
+            let string = env.new_string("element").unwrap();
+            env.set_array_element(&strings, 1, Value::Reference(Some(string.clone()))).unwrap();
+            assert_eq!(Ok(Value::Reference(Some(string))), env.get_array_element(&strings, 1));
+            expect_thrown(env.get_array_element(&strings, 2), "java/lang/ArrayIndexOutOfBoundsException");
+            let natives = env.new_object("Natives", "()V", vec![]).unwrap();
+            expect_thrown(env.set_array_element(&strings, 0, Value::Reference(Some(natives))), "java/lang/ArrayStoreException");
+            assert!(matches!(env.set_array_element(&strings, 0, Value::Int(1)), Err(VmError::NativeSignature(_))));
+        });
+    }
+
+    #[test]
+    fn test_calls() {
+        let mut vm = vm_with(&[fixture!("Natives"), fixture!("LoudNatives")]);
+        vm.register_native("Natives", "callBack", "(LNatives;I)I", |env: &mut Env, natives: ObjectRef, value: i32| -> Result<i32, VmError> {
+            let twice = env.call_method(&natives, "twice", "(I)I", vec![Value::Int(value)])?;
+            let thrice = env.call_static("Natives", "thrice", "(I)I", vec![Value::Int(value)])?;
+            match (twice, thrice) {
+                (Some(Value::Int(twice)), Some(Value::Int(thrice))) => Ok(twice + thrice),
+                other => panic!("Expected ints; got {:?}", other),
+            }
+        });
+        let (natives, loud) = with_env(&mut vm, |env| (env.new_object("Natives", "()V", vec![]).unwrap(), env.new_object("LoudNatives", "()V", vec![]).unwrap()));
+        assert_eq!(Ok(Some(Value::Int(5))), vm.invoke_static("Natives", "callBack", "(LNatives;I)I", vec![Value::Reference(Some(natives)), Value::Int(1)]));
+        assert_eq!(Ok(Some(Value::Int(23))), vm.invoke_static("Natives", "callBack", "(LNatives;I)I", vec![Value::Reference(Some(loud)), Value::Int(1)]));
+    }
+
+    #[test]
+    fn test_new_objects_are_constructed() {
+        let mut vm = vm_with(&[fixture!("Natives"), fixture!("LoudNatives")]);
+        vm.register_native("Natives", "make", "(I)Ljava/lang/Object;", |env: &mut Env, count: i32| env.new_object("Natives", "(I)V", vec![Value::Int(count)]));
+        let made = match vm.invoke_static("Natives", "make", "(I)Ljava/lang/Object;", vec![Value::Int(7)]) {
+            Ok(Some(Value::Reference(Some(made)))) => made,
+            other => panic!("Expected an object; got {:?}", other),
+        };
+        assert_eq!(Some(Value::Int(7)), made.field("count"));
+        with_env(&mut vm, |env| {
+            assert_eq!(Ok(true), env.is_instance_of(&made, "java/lang/Object"));
+            assert_eq!(Ok(false), env.is_instance_of(&made, "LoudNatives"));
+        });
+    }
+
+    #[test]
+    fn test_exceptions_reach_java_handlers() {
+        let mut vm = vm_with(&[fixture!("Natives"), fixture!("LoudNatives")]);
+        vm.register_native("Natives", "fail", "(Ljava/lang/String;)V", |env: &mut Env, message: ObjectRef| -> Result<(), VmError> {
+            let message = env.read_string(&message)?;
+            Err(env.throw_new_with_message("java/lang/IllegalArgumentException", &message))
+        });
+        let message = match vm.invoke_static("Natives", "catchFailure", "()Ljava/lang/String;", vec![]) {
+            Ok(Some(Value::Reference(Some(message)))) => message,
+            other => panic!("Expected a string; got {:?}", other),
+        };
+        assert_eq!("native failure", vm.read_string(&message).unwrap());
+    }
+
+    #[test]
+    fn test_references() {
+        let mut vm = vm_with(&[fixture!("Natives"), fixture!("LoudNatives")]);
+        let (global, local, framed) = with_env(&mut vm, |env| {
+            let string = env.new_string("held").unwrap();
+            let global = env.new_global_ref(string.clone());
+            let local = env.new_local_ref(string.clone());
+            let framed = env.with_local_frame(|env| env.new_local_ref(string));
+            (global, local, framed)
+        });
+        assert!(vm.global_ref(global).is_some());
+        assert!(vm.local_ref(local).is_some());
+        assert!(vm.local_ref(framed).is_none());
+        with_env(&mut vm, |env| {
+            env.delete_global_ref(global);
+            env.delete_local_ref(local);
+            assert_eq!(None, env.global_ref(global));
+            assert_eq!(None, env.local_ref(local));
+        });
+    }
+
+    #[test]
+    fn test_locals_are_deleted_when_natives_return() {
+        let mut vm = vm_with(&[fixture!("Natives"), fixture!("LoudNatives")]);
+        let held: Rc<Cell<Option<LocalRef>>> = Rc::new(Cell::new(None));
+        let sink = Rc::clone(&held);
+        vm.register_native("Natives", "record", "(Ljava/lang/Object;)V", move |env: &mut Env, value: ObjectRef| sink.set(Some(env.new_local_ref(value))));
+        let string = vm.new_string("recorded").unwrap();
+        vm.invoke_static("Natives", "record", "(Ljava/lang/Object;)V", vec![Value::Reference(Some(string))]).unwrap();
+        assert!(vm.local_ref(held.get().unwrap()).is_none());
+    }
+}
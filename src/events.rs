@@ -0,0 +1,230 @@
+use crate::classes::Method;
+use crate::gc::{GcCause, GcStats};
+use crate::heap::ObjectRef;
+use crate::runtime::*;
+use crate::threads::ThreadId;
+use crate::vm::{ActiveFrame, VmError};
+use std::path::Path;
+
+// Allocation samples are rare enough by default to cost nothing noticeable.
+pub const DEFAULT_ALLOCATION_SAMPLE_INTERVAL: u64 = 512 * 1024;
+
+// Things that happen as the VM runs, which tools can subscribe to with Vm::subscribe, in the
+// manner of JVMTI's events. Each subscriber says which kinds of event it wants, and is only told
+// about those. Kinds that nobody subscribes to cost a check of a mask where they'd be posted.
+//
+// The method events come from the interpreter's hooked dispatch loop, like the execution hooks,
+// which are given the same events. Compiled code doesn't post them.
+pub enum Event<'a> {
+    // A class has been loaded or defined, with where its class file came from if that's known.
+    // Array classes aren't reported.
+    ClassLoad{class: &'a RuntimeClass, origin: Option<&'a Path>},
+    // A class's static fields have been allocated, as it's linked, before any of its code runs.
+    ClassPrepare{class: &'a RuntimeClass},
+    MethodEntry{class: &'a RuntimeClass, method: &'a Method},
+    // The method has returned or thrown.
+    MethodExit{class: &'a RuntimeClass, method: &'a Method, result: &'a Result<Option<Value>, VmError>},
+    // An instruction has thrown, before a handler is looked for. Exceptions propagating out of a
+    // callee aren't posted again.
+    Exception{class: &'a RuntimeClass, method: &'a Method, pc: usize, exception: &'a ObjectRef},
+    GcStart{id: u64, cause: GcCause}, // Numbered as in GcStats.
+    GcFinish{stats: &'a GcStats},
+    // A thread started with Thread.start is about to run, or has finished running. The main
+    // thread isn't reported.
+    ThreadStart{thread: ThreadId},
+    ThreadEnd{thread: ThreadId},
+    // One allocation in every so many bytes allocated, as set with
+    // Vm::set_allocation_sample_interval, with the object's estimated size.
+    AllocationSample{thread: ThreadId, object: &'a ObjectRef, size: u64},
+    // A thread's stack, innermost frame first, as the profiler samples it. Only posted while the
+    // profiler is running.
+    MethodSample{thread: ThreadId, frames: &'a [ActiveFrame]},
+}
+
+impl<'a> Event<'a> {
+    pub fn kind(&self) -> EventKinds {
+        match *self {
+            Event::ClassLoad{..} => EventKinds::CLASS_LOAD,
+            Event::ClassPrepare{..} => EventKinds::CLASS_PREPARE,
+            Event::MethodEntry{..} => EventKinds::METHOD_ENTRY,
+            Event::MethodExit{..} => EventKinds::METHOD_EXIT,
+            Event::Exception{..} => EventKinds::EXCEPTION,
+            Event::GcStart{..} => EventKinds::GC_START,
+            Event::GcFinish{..} => EventKinds::GC_FINISH,
+            Event::ThreadStart{..} => EventKinds::THREAD_START,
+            Event::ThreadEnd{..} => EventKinds::THREAD_END,
+            Event::AllocationSample{..} => EventKinds::ALLOCATION_SAMPLE,
+            Event::MethodSample{..} => EventKinds::METHOD_SAMPLE,
+        }
+    }
+}
+
+bitflags! {
+    #[derive(Default)]
+    pub struct EventKinds: u16 {
+        const CLASS_LOAD        = 0x0001;
+        const CLASS_PREPARE     = 0x0002;
+        const METHOD_ENTRY      = 0x0004;
+        const METHOD_EXIT       = 0x0008;
+        const EXCEPTION         = 0x0010;
+        const GC_START          = 0x0020;
+        const GC_FINISH         = 0x0040;
+        const THREAD_START      = 0x0080;
+        const THREAD_END        = 0x0100;
+        const ALLOCATION_SAMPLE = 0x0200;
+        const METHOD_SAMPLE     = 0x0400;
+
+        // Those posted by the interpreter's hooked dispatch loop.
+        const INTERPRETER = Self::METHOD_ENTRY.bits | Self::METHOD_EXIT.bits | Self::EXCEPTION.bits;
+    }
+}
+
+pub trait EventListener {
+    fn on_event(&mut self, event: &Event);
+}
+
+impl<F: FnMut(&Event)> EventListener for F {
+    fn on_event(&mut self, event: &Event) {
+        self(event)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u32);
+
+// The subscribers, in the order they subscribed, which is the order they're told about events.
+#[derive(Default)]
+pub struct EventBus {
+    subscriptions: Vec<(SubscriptionId, EventKinds, Box<dyn EventListener>)>,
+    enabled: EventKinds, // Those that anyone has subscribed to.
+    next_id: u32,
+}
+
+impl EventBus {
+    pub fn new() -> EventBus {
+        EventBus::default()
+    }
+
+    pub fn subscribe(&mut self, kinds: EventKinds, listener: Box<dyn EventListener>) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        self.subscriptions.push((id, kinds, listener));
+        self.enabled |= kinds;
+        id
+    }
+
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> Option<Box<dyn EventListener>> {
+        let index = self.subscriptions.iter().position(|&(existing, _, _)| existing == id)?;
+        let (_, _, listener) = self.subscriptions.remove(index);
+        self.enabled = self.subscriptions.iter().fold(EventKinds::empty(), |enabled, &(_, kinds, _)| enabled | kinds);
+        Some(listener)
+    }
+
+    // Whether anyone has subscribed to any of these kinds of event.
+    #[inline]
+    pub fn is_enabled(&self, kinds: EventKinds) -> bool {
+        self.enabled.intersects(kinds)
+    }
+
+    pub fn post(&mut self, event: &Event) {
+        let kind = event.kind();
+        if !self.is_enabled(kind) {
+            return;
+        }
+        for (_, kinds, listener) in &mut self.subscriptions {
+            if kinds.contains(kind) {
+                listener.on_event(event);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use crate::vm::Vm;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn describe(event: &Event) -> String {
+        let method_name = |class: &RuntimeClass, method: &Method| class.class.utf8(&method.name).unwrap().to_string();
+        match *event {
+            Event::ClassLoad{class, ..} => format!("load {}", class.name),
+            Event::ClassPrepare{class} => format!("prepare {}", class.name),
+            Event::MethodEntry{class, method} => format!("enter {}", method_name(class, method)),
+            Event::MethodExit{class, method, result} => format!("exit {} {}", method_name(class, method), result.is_ok()),
+            Event::Exception{class, method, pc, exception} => format!("throw {} {} {}", method_name(class, method), pc, exception.class().name),
+            Event::GcStart{id, cause} => format!("gc start {} {:?}", id, cause),
+            Event::GcFinish{stats} => format!("gc finish {}", stats.id),
+            Event::ThreadStart{thread} => format!("thread start {}", thread),
+            Event::ThreadEnd{thread} => format!("thread end {}", thread),
+            Event::AllocationSample{object, ..} => format!("allocated {}", object.class().name),
+            Event::MethodSample{frames, ..} => format!("sampled {} frames", frames.len()),
+        }
+    }
+
+    fn record(vm: &mut Vm, kinds: EventKinds) -> (SubscriptionId, Rc<RefCell<Vec<String>>>) {
+        let events = Rc::new(RefCell::new(vec![]));
+        let recorded = Rc::clone(&events);
+        let id = vm.subscribe(kinds, Box::new(move |event: &Event| recorded.borrow_mut().push(describe(event))));
+        (id, events)
+    }
+
+
+    #[test]
+    fn test_method_events() {
+        let mut vm = vm_with(&[fixture!("Debuggee")]);
+        let (_, events) = record(&mut vm, EventKinds::METHOD_ENTRY | EventKinds::METHOD_EXIT);
+        vm.invoke_static("Debuggee", "sumOfSquares", "(I)I", vec![Value::Int(1)]).unwrap();
+        assert_eq!(vec!["enter sumOfSquares", "enter square", "exit square true", "exit sumOfSquares true"], *events.borrow());
+    }
+
+    #[test]
+    fn test_exception_events() {
+        let mut vm = vm_with(&[fixture!("Arithmetic")]);
+        let (_, events) = record(&mut vm, EventKinds::EXCEPTION);
+        let _ = vm.invoke_static("Arithmetic", "divide", "(II)I", vec![Value::Int(1), Value::Int(0)]);
+        assert_eq!(vec!["throw divide 2 java/lang/ArithmeticException"], *events.borrow());
+    }
+
+    #[test]
+    fn test_class_and_gc_events() {
+        let mut vm = vm_with(&[fixture!("Debuggee")]);
+        let (_, events) = record(&mut vm, EventKinds::CLASS_PREPARE | EventKinds::GC_START | EventKinds::GC_FINISH);
+        vm.invoke_static("Debuggee", "square", "(I)I", vec![Value::Int(2)]).unwrap();
+        vm.collect_garbage(GcCause::Requested);
+        let events = events.borrow();
+        assert!(events.contains(&"prepare Debuggee".to_string()));
+        assert_eq!(["gc start 0 Requested", "gc finish 0"], events[events.len() - 2..]);
+    }
+
+    #[test]
+    fn test_subscribers_only_see_their_kinds() {
+        let mut vm = vm_with(&[fixture!("Debuggee")]);
+        let (entries, entered) = record(&mut vm, EventKinds::METHOD_ENTRY);
+        let (_, exited) = record(&mut vm, EventKinds::METHOD_EXIT);
+        vm.invoke_static("Debuggee", "square", "(I)I", vec![Value::Int(2)]).unwrap();
+        assert_eq!(vec!["enter square"], *entered.borrow());
+        assert_eq!(vec!["exit square true"], *exited.borrow());
+
+        assert!(vm.unsubscribe(entries).is_some());
+        assert!(vm.unsubscribe(entries).is_none());
+        vm.invoke_static("Debuggee", "square", "(I)I", vec![Value::Int(2)]).unwrap();
+        assert_eq!(1, entered.borrow().len());
+        assert_eq!(2, exited.borrow().len());
+    }
+
+    #[test]
+    fn test_enabled_kinds_follow_subscriptions() {
+        let mut bus = EventBus::new();
+        assert!(!bus.is_enabled(EventKinds::all()));
+        let first = bus.subscribe(EventKinds::GC_START, Box::new(|_: &Event| ()));
+        let second = bus.subscribe(EventKinds::GC_START | EventKinds::THREAD_END, Box::new(|_: &Event| ()));
+        bus.unsubscribe(second);
+        assert!(bus.is_enabled(EventKinds::GC_START));
+        assert!(!bus.is_enabled(EventKinds::THREAD_END));
+        bus.unsubscribe(first);
+        assert!(!bus.is_enabled(EventKinds::all()));
+    }
+}
@@ -0,0 +1,186 @@
+use crate::heap::{Heap, ObjectRef};
+use crate::walker::HeapWalker;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::time::Duration;
+
+// Objects are reference counted, so most are freed the moment they become unreachable. What
+// reference counting can't free is garbage that refers to itself, like a doubly linked list that
+// the program has dropped. The collector finds those cycles by trial deletion: any object with
+// more references than the heap itself accounts for is held from outside the heap, by a static
+// field, a frame, or the embedder, and everything reachable from such an object is live. The
+// rest is garbage. Since this never needs to find the roots, it's safe to run at any time.
+pub fn find_garbage(heap: &Heap) -> Vec<ObjectRef> {
+    let objects: Vec<ObjectRef> = heap.live_objects().collect();
+
+    // An object whose fields are borrowed is being worked on, so it's treated as held outside.
+    let mut borrowed = HashSet::new();
+    let mut internal: HashMap<ObjectRef, usize> = HashMap::new();
+    for object in &objects {
+        if object.fields.try_borrow_mut().is_err() {
+            borrowed.insert(object.clone());
+            continue;
+        }
+        for reference in object.references() {
+            *internal.entry(reference).or_insert(0) += 1;
+        }
+    }
+
+    // Each object is also referred to once by the objects list, and once more by its key in the
+    // map if it has one.
+    let held_outside: Vec<bool> = objects.iter().map(|object| {
+        let accounted = 1 + internal.get(object).map_or(0, |count| count + 1);
+        object.strong_count() > accounted || borrowed.contains(object)
+    }).collect();
+    let roots: Vec<ObjectRef> = objects.iter().zip(held_outside)
+        .filter(|&(_, held_outside)| held_outside)
+        .map(|(object, _)| object.clone())
+        .collect();
+
+    drop(internal);
+    let live: HashSet<ObjectRef> = HeapWalker::new(roots).collect();
+    objects.into_iter().filter(|object| !live.contains(object)).collect()
+}
+
+// What happened in one garbage collection, as reported by Vm::last_gc and to the listener
+// installed with Vm::set_gc_listener. Sizes are estimates, as for the allocation limit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GcStats {
+    pub id: u64, // Collections are numbered from 0.
+    pub cause: GcCause,
+    pub pause: Duration,
+    pub objects_before: usize,
+    pub bytes_before: u64,
+    pub objects_after: usize,
+    pub bytes_after: u64,
+    pub capacity: u64, // The heap limit, or if there's none, as much as the heap has needed.
+
+    // Live objects are in the young generation until they survive their first collection, and
+    // in the old generation after that. These are the sizes after the collection.
+    pub young_bytes: u64,
+    pub old_bytes: u64,
+
+    pub unloaded_classes: usize,
+}
+
+impl GcStats {
+    pub fn reclaimed_bytes(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+// One line per collection, as HotSpot's -Xlog:gc writes them after its decorations, so that GC
+// log analysis tools can read it:
+//
+//     GC(3) Pause Full (System.gc()) 1843K->212K(65536K) 1.204ms
+impl fmt::Display for GcStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "GC({}) Pause Full ({}) {}->{}({}) {:.3}ms", self.id, self.cause, ByteSize(self.bytes_before),
+            ByteSize(self.bytes_after), ByteSize(self.capacity), self.pause.as_secs_f64() * 1000.0)
+    }
+}
+
+// A size in the unit HotSpot's logs would give it in: the largest in which it's at least 100.
+pub struct ByteSize(pub u64);
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const K: u64 = 1 << 10;
+        const M: u64 = 1 << 20;
+        const G: u64 = 1 << 30;
+        match self.0 {
+            bytes if bytes >= 100 * G => write!(f, "{}G", bytes / G),
+            bytes if bytes >= 100 * M => write!(f, "{}M", bytes / M),
+            bytes if bytes >= 100 * K => write!(f, "{}K", bytes / K),
+            bytes => write!(f, "{}B", bytes),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GcCause {
+    Requested, // By the embedder, through Vm::collect_garbage.
+    SystemGc,
+    DirectMemory, // Allocating direct memory hit the limit; unreachable buffers may free some.
+    AllocationFailure, // The heap went over the limit set with Vm::set_max_heap.
+    HeapInspection, // So that a heap histogram only counts reachable objects.
+}
+
+impl fmt::Display for GcCause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GcCause::Requested => write!(f, "Requested"),
+            GcCause::SystemGc => write!(f, "System.gc()"),
+            GcCause::DirectMemory => write!(f, "Direct Memory"),
+            GcCause::AllocationFailure => write!(f, "Allocation Failure"),
+            GcCause::HeapInspection => write!(f, "Heap Inspection Initiated GC"),
+        }
+    }
+}
+
+// A callback for Vm::set_gc_listener.
+pub type GcListener = Box<dyn FnMut(&GcStats)>;
+
+// Running totals over every collection, for polling.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GcTotals {
+    pub collections: u64,
+    pub pause: Duration,
+    pub reclaimed_bytes: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heap::Storage;
+    use crate::runtime::Value;
+    use crate::vm::Vm;
+
+    fn pair(vm: &mut Vm, heap: &mut Heap) -> (ObjectRef, ObjectRef) {
+        let class = vm.load_class("java/lang/Object").unwrap();
+        let first = heap.allocate(&class, Storage::Values(vec![Value::null()]));
+        let second = heap.allocate(&class, Storage::Values(vec![Value::Reference(Some(first.clone()))]));
+        first.fields.borrow_mut().set(0, Value::Reference(Some(second.clone())));
+        (first, second)
+    }
+
+    #[test]
+    fn test_dropped_cycles_are_garbage() {
+        let (mut vm, mut heap) = (Vm::new(), Heap::new());
+        drop(pair(&mut vm, &mut heap));
+        assert_eq!(2, find_garbage(&heap).len());
+    }
+
+    #[test]
+    fn test_cycles_held_outside_the_heap_are_live() {
+        let (mut vm, mut heap) = (Vm::new(), Heap::new());
+        let (first, second) = pair(&mut vm, &mut heap);
+        drop(second);
+        assert!(find_garbage(&heap).is_empty());
+        drop(first);
+        assert_eq!(2, find_garbage(&heap).len());
+    }
+
+    #[test]
+    fn test_stats_format() {
+        let stats = GcStats {
+            id: 3,
+            cause: GcCause::SystemGc,
+            pause: Duration::from_micros(1204),
+            objects_before: 100,
+            bytes_before: 1843 << 10,
+            objects_after: 10,
+            bytes_after: 212 << 10,
+            capacity: 64 << 20,
+            young_bytes: 0,
+            old_bytes: 212 << 10,
+            unloaded_classes: 0,
+        };
+        assert_eq!("GC(3) Pause Full (System.gc()) 1843K->212K(65536K) 1.204ms", stats.to_string());
+        assert_eq!("99B", ByteSize(99).to_string());
+        assert_eq!("100K", ByteSize(100 << 10).to_string());
+        assert_eq!("102399B", ByteSize((100 << 10) - 1).to_string());
+        assert_eq!("150M", ByteSize(150 << 20).to_string());
+        assert_eq!("2048G", ByteSize(2 << 40).to_string());
+    }
+}
@@ -0,0 +1,166 @@
+use crate::events::{Event, EventKinds, EventListener};
+use crate::gc::ByteSize;
+use std::fs::File;
+use std::io::{self, BufWriter, LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+// Logs each collection as HotSpot's unified logging does, decorated with the uptime, level and
+// tags, so that existing GC log analysis tools can read it:
+//
+//     [0.052s][info][gc] GC(0) Pause Full (System.gc()) 1843K->212K(65536K) 1.204ms
+//
+// A detailed log, like -Xlog:gc*, also has a line as each collection starts and lines for the
+// generations' sizes after it. Subscribe it to EVENTS; uptimes count from when it's created.
+pub struct GcLog {
+    output: Box<dyn Write>,
+    detailed: bool,
+    start: Instant,
+    failed: bool, // Logging stops at the first write that fails.
+}
+
+pub const EVENTS: EventKinds = EventKinds::from_bits_truncate(EventKinds::GC_START.bits() | EventKinds::GC_FINISH.bits());
+
+impl GcLog {
+    pub fn new(output: Box<dyn Write>, detailed: bool) -> GcLog {
+        GcLog {output, detailed, start: Instant::now(), failed: false}
+    }
+
+    pub fn to_file(path: &Path, detailed: bool) -> io::Result<GcLog> {
+        Ok(GcLog::new(Box::new(BufWriter::new(File::create(path)?)), detailed))
+    }
+
+    pub fn to_stdout(detailed: bool) -> GcLog {
+        GcLog::new(Box::new(LineWriter::new(io::stdout())), detailed)
+    }
+
+    fn log(&mut self, tags: &str, message: &str) {
+        if self.failed {
+            return;
+        }
+        let uptime = self.start.elapsed().as_secs_f64();
+        if let Err(err) = writeln!(self.output, "[{:.3}s][info][{}] {}", uptime, tags, message) {
+            eprintln!("Stopped logging collections: {}", err);
+            self.failed = true;
+        }
+    }
+}
+
+impl EventListener for GcLog {
+    fn on_event(&mut self, event: &Event) {
+        match *event {
+            Event::GcStart{id, cause} if self.detailed => self.log("gc,start", &format!("GC({}) Pause Full ({})", id, cause)),
+            Event::GcFinish{stats} => {
+                if self.detailed {
+                    self.log("gc,heap", &format!("GC({}) Young: {}", stats.id, ByteSize(stats.young_bytes)));
+                    self.log("gc,heap", &format!("GC({}) Old: {}", stats.id, ByteSize(stats.old_bytes)));
+                    self.log("gc,classes", &format!("GC({}) Unloaded {} classes", stats.id, stats.unloaded_classes));
+                }
+                self.log("gc", &stats.to_string());
+            },
+            _ => (),
+        }
+    }
+}
+
+// Where to log collections and in how much detail, as given by -Xlog:gc, -Xlog:gc* or -verbose:gc.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GcLogOptions {
+    pub detailed: bool,
+    pub file: Option<PathBuf>, // Otherwise the log goes to stdout.
+}
+
+impl GcLogOptions {
+    // Parses what follows -Xlog: when it selects GC logging, as in gc, gc*, gc:gc.log,
+    // gc*:file=gc.log or gc:stdout. Decorators and output options after a further colon are
+    // accepted but ignored, since the output always has the default decorations.
+    pub fn parse(option: &str) -> Option<GcLogOptions> {
+        let mut parts = option.splitn(3, ':');
+        let detailed = match parts.next()? {
+            "gc" | "gc=info" => false,
+            "gc*" | "gc*=info" => true,
+            _ => return None,
+        };
+        let file = match parts.next() {
+            None | Some("") | Some("stdout") => None,
+            Some(output) => Some(PathBuf::from(output.strip_prefix("file=").unwrap_or(output))),
+        };
+        Some(GcLogOptions {detailed, file})
+    }
+
+    pub fn open(&self) -> io::Result<GcLog> {
+        match self.file {
+            Some(ref file) => GcLog::to_file(file, self.detailed),
+            None => Ok(GcLog::to_stdout(self.detailed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gc::GcCause;
+    use crate::vm::Vm;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(bytes)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn log(detailed: bool) -> Vec<String> {
+        let mut vm = Vm::new();
+        let output = SharedOutput::default();
+        vm.subscribe(EVENTS, Box::new(GcLog::new(Box::new(output.clone()), detailed)));
+        vm.collect_garbage(GcCause::SystemGc);
+        vm.collect_garbage(GcCause::Requested);
+        let text = String::from_utf8(output.0.borrow().clone()).unwrap();
+        text.lines().map(str::to_string).collect()
+    }
+
+    // Strips the uptime, which varies.
+    fn undecorated(line: &str) -> &str {
+        &line[line.find("][").unwrap() + 1..]
+    }
+
+    #[test]
+    fn test_log_collections() {
+        let lines = log(false);
+        assert_eq!(2, lines.len());
+        assert!(lines[0].starts_with('[') && lines[0].contains("s][info][gc] "), "{}", lines[0]);
+        assert!(undecorated(&lines[0]).starts_with("[info][gc] GC(0) Pause Full (System.gc()) "), "{}", lines[0]);
+        assert!(undecorated(&lines[1]).starts_with("[info][gc] GC(1) Pause Full (Requested) "), "{}", lines[1]);
+        assert!(lines[1].ends_with("ms"));
+    }
+
+    #[test]
+    fn test_detailed_log() {
+        let lines = log(true);
+        let lines: Vec<&str> = lines.iter().map(|line| undecorated(line)).collect();
+        assert_eq!("[info][gc,start] GC(0) Pause Full (System.gc())", lines[0]);
+        assert!(lines[1].starts_with("[info][gc,heap] GC(0) Young: "));
+        assert!(lines[2].starts_with("[info][gc,heap] GC(0) Old: "));
+        assert_eq!("[info][gc,classes] GC(0) Unloaded 0 classes", lines[3]);
+        assert!(lines[4].starts_with("[info][gc] GC(0) Pause Full"));
+        assert_eq!(10, lines.len());
+    }
+
+    #[test]
+    fn test_parse_options() {
+        assert_eq!(Some(GcLogOptions::default()), GcLogOptions::parse("gc"));
+        assert_eq!(Some(GcLogOptions {detailed: true, file: None}), GcLogOptions::parse("gc*:stdout"));
+        assert_eq!(Some(GcLogOptions {detailed: false, file: Some(PathBuf::from("gc.log"))}), GcLogOptions::parse("gc:gc.log"));
+        assert_eq!(Some(GcLogOptions {detailed: true, file: Some(PathBuf::from("gc.log"))}), GcLogOptions::parse("gc*:file=gc.log:uptime"));
+        assert_eq!(None, GcLogOptions::parse("class+load"));
+        assert_eq!(None, GcLogOptions::parse(""));
+    }
+}
@@ -0,0 +1,183 @@
+use crate::heap::ObjectRef;
+
+// Native code holds heap objects through handles rather than directly, so that the VM knows
+// about every reference it holds: handles are roots, and a collector that moved objects would
+// only need to update the handle table. As in JNI, there are two kinds. A GlobalRef lasts until
+// it's deleted. A LocalRef belongs to the innermost local frame and is deleted along with it;
+// every native method runs in a frame of its own, so locals it creates are cleaned up when it
+// returns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GlobalRef(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LocalRef {
+    slot: usize,
+    serial: u64, // Distinguishes locals that have used the same slot, so stale refs resolve to None.
+}
+
+impl GlobalRef {
+    pub fn index(self) -> usize {
+        self.0
+    }
+
+    // The global at an index, as given by index; for natives that pass globals around as integers.
+    pub fn from_index(index: usize) -> GlobalRef {
+        GlobalRef(index)
+    }
+}
+
+impl LocalRef {
+    pub fn index(self) -> usize {
+        self.slot
+    }
+}
+
+#[derive(Default)]
+pub struct Handles {
+    globals: Vec<Option<ObjectRef>>,
+    locals: Locals,
+    next_serial: u64,
+}
+
+// The locals and local frames of one thread. Each thread has its own, swapped in while it runs.
+#[derive(Default)]
+pub struct Locals {
+    entries: Vec<(u64, Option<ObjectRef>)>,
+    frames: Vec<usize>, // The number of locals when each frame was pushed.
+}
+
+impl Locals {
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &ObjectRef)> {
+        self.entries.iter().enumerate().filter_map(|(index, (_, object))| object.as_ref().map(|object| (index, object)))
+    }
+}
+
+impl Handles {
+    pub fn new() -> Handles {
+        Handles::default()
+    }
+
+    pub fn new_global(&mut self, object: ObjectRef) -> GlobalRef {
+        match self.globals.iter().position(Option::is_none) {
+            Some(index) => {
+                self.globals[index] = Some(object);
+                GlobalRef(index)
+            },
+            None => {
+                self.globals.push(Some(object));
+                GlobalRef(self.globals.len() - 1)
+            },
+        }
+    }
+
+    pub fn global(&self, global: GlobalRef) -> Option<&ObjectRef> {
+        self.globals.get(global.0).and_then(Option::as_ref)
+    }
+
+    pub fn delete_global(&mut self, global: GlobalRef) -> Option<ObjectRef> {
+        self.globals.get_mut(global.0).and_then(Option::take)
+    }
+
+    // Creates a local in the innermost frame. Locals created outside any frame last until
+    // they're deleted.
+    pub fn new_local(&mut self, object: ObjectRef) -> LocalRef {
+        let serial = self.next_serial;
+        self.next_serial += 1;
+        self.locals.entries.push((serial, Some(object)));
+        LocalRef {slot: self.locals.entries.len() - 1, serial}
+    }
+
+    // Returns None if the local has been deleted, or its frame popped.
+    pub fn local(&self, local: LocalRef) -> Option<&ObjectRef> {
+        match self.locals.entries.get(local.slot) {
+            Some(&(serial, ref object)) if serial == local.serial => object.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn delete_local(&mut self, local: LocalRef) -> Option<ObjectRef> {
+        match self.locals.entries.get_mut(local.slot) {
+            Some(&mut (serial, ref mut object)) if serial == local.serial => object.take(),
+            _ => None,
+        }
+    }
+
+    pub fn push_frame(&mut self) {
+        self.locals.frames.push(self.locals.entries.len());
+    }
+
+    // Deletes every local in the innermost frame. As with JNI's PopLocalFrame, the object the
+    // result refers to, if any, survives as a new local in the enclosing frame.
+    pub fn pop_frame(&mut self, result: Option<LocalRef>) -> Option<LocalRef> {
+        let result = result.and_then(|result| self.local(result).cloned());
+        let start = self.locals.frames.pop().unwrap_or(0);
+        self.locals.entries.truncate(start);
+        result.map(|object| self.new_local(object))
+    }
+
+    // The globals and locals that are in use, with their indexes.
+    pub fn globals(&self) -> impl Iterator<Item = (usize, &ObjectRef)> {
+        self.globals.iter().enumerate().filter_map(|(index, object)| object.as_ref().map(|object| (index, object)))
+    }
+
+    pub fn locals(&self) -> impl Iterator<Item = (usize, &ObjectRef)> {
+        self.locals.iter()
+    }
+
+    // Exchanges the running thread's locals for another's, when switching threads.
+    pub fn swap_locals(&mut self, locals: &mut Locals) {
+        std::mem::swap(&mut self.locals, locals);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::Vm;
+
+    fn objects(count: usize) -> Vec<ObjectRef> {
+        let mut vm = Vm::new();
+        (0..count).map(|i| vm.new_string(&i.to_string()).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_globals_are_reused_after_deletion() {
+        let objects = objects(2);
+        let mut handles = Handles::new();
+        let global = handles.new_global(objects[0].clone());
+        assert_eq!(Some(&objects[0]), handles.global(global));
+        assert_eq!(Some(objects[0].clone()), handles.delete_global(global));
+        assert_eq!(None, handles.global(global));
+        assert_eq!(global, handles.new_global(objects[1].clone()));
+    }
+
+    #[test]
+    fn test_popping_a_frame_deletes_its_locals() {
+        let objects = objects(3);
+        let mut handles = Handles::new();
+        let outer = handles.new_local(objects[0].clone());
+        handles.push_frame();
+        let inner = handles.new_local(objects[1].clone());
+        let result = handles.new_local(objects[2].clone());
+        let result = handles.pop_frame(Some(result)).unwrap();
+
+        assert_eq!(Some(&objects[0]), handles.local(outer));
+        assert_eq!(None, handles.local(inner));
+        assert_eq!(Some(&objects[2]), handles.local(result));
+        assert_eq!(2, handles.locals().count());
+    }
+
+    #[test]
+    fn test_stale_locals_resolve_to_none() {
+        let objects = objects(2);
+        let mut handles = Handles::new();
+        handles.push_frame();
+        let stale = handles.new_local(objects[0].clone());
+        handles.pop_frame(None);
+        let fresh = handles.new_local(objects[1].clone());
+        assert_eq!(stale.index(), fresh.index());
+        assert_eq!(None, handles.local(stale));
+        assert_eq!(None, handles.delete_local(stale));
+        assert_eq!(Some(&objects[1]), handles.local(fresh));
+    }
+}
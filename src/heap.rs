@@ -0,0 +1,555 @@
+use crate::descriptor::FieldType;
+use crate::limits;
+use crate::runtime::*;
+use std::cell::{Cell, RefCell};
+use std::hash::{Hash, Hasher};
+use std::rc::{Rc, Weak};
+use std::{fmt, ops};
+
+// A reference to a heap object, and the payload of Value::Reference. Equality is identity, as
+// with Java's == operator.
+#[derive(Clone)]
+pub struct ObjectRef(Rc<Object>);
+
+impl ObjectRef {
+    // A reference that doesn't keep the object alive.
+    pub fn downgrade(&self) -> WeakObjectRef {
+        WeakObjectRef(Rc::downgrade(&self.0))
+    }
+
+    // The number of references to the object, from anywhere.
+    pub fn strong_count(&self) -> usize {
+        Rc::strong_count(&self.0)
+    }
+}
+
+impl PartialEq for ObjectRef {
+    fn eq(&self, other: &ObjectRef) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for ObjectRef {}
+
+impl Hash for ObjectRef {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Rc::as_ptr(&self.0).hash(state)
+    }
+}
+
+impl ops::Deref for ObjectRef {
+    type Target = Object;
+
+    fn deref(&self) -> &Object {
+        &self.0
+    }
+}
+
+impl fmt::Debug for ObjectRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}@{:p}", self.class().name, Rc::as_ptr(&self.0))
+    }
+}
+
+#[derive(Clone)]
+pub struct WeakObjectRef(Weak<Object>);
+
+impl WeakObjectRef {
+    // Returns None if the object has been freed.
+    pub fn upgrade(&self) -> Option<ObjectRef> {
+        self.0.upgrade().map(ObjectRef)
+    }
+}
+
+pub struct Object {
+    pub header: Header,
+    pub fields: RefCell<Storage>, // For arrays, these are the elements.
+}
+
+impl Object {
+    pub fn class(&self) -> &Rc<RuntimeClass> {
+        &self.header.class
+    }
+
+    pub fn is_array(&self) -> bool {
+        self.header.class.is_array()
+    }
+
+    // The value of the named instance field, which may be inherited, or None if there's no such
+    // field. Where a field shadows one in a superclass, this finds the subclass's.
+    pub fn field(&self, name: &str) -> Option<Value> {
+        let slot = self.class().field_slot(name)?;
+        Some(self.fields.borrow().get(slot))
+    }
+
+    // Every instance field of an object, inherited ones first, by name. Empty for arrays.
+    pub fn named_fields(&self) -> Vec<(String, Value)> {
+        let fields = self.fields.borrow();
+        self.class().field_names().into_iter().enumerate()
+            .map(|(slot, name)| (name.to_string(), fields.get(slot)))
+            .collect()
+    }
+
+    // Sets every reference field or element to null. The collector uses this to break cycles.
+    pub fn clear_references(&self) {
+        let mut references = vec![];
+        take_references(&mut self.fields.borrow_mut(), &mut references);
+    }
+
+    // The objects that this object's fields or elements refer to, in order, with repeats.
+    pub fn references(&self) -> Vec<ObjectRef> {
+        match *self.fields.borrow() {
+            Storage::Values(ref values) => values.iter().filter_map(|value| match *value {
+                Value::Reference(Some(ref object)) => Some(object.clone()),
+                _ => None,
+            }).collect(),
+            _ => vec![],
+        }
+    }
+}
+
+// Freeing an object frees everything only it refers to, which would recurse once per link of a
+// long linked list and overflow the stack. Instead, the objects it frees are emptied one at a
+// time here, so that none of them recurses.
+impl Drop for Object {
+    fn drop(&mut self) {
+        let mut pending = vec![];
+        take_references(self.fields.get_mut(), &mut pending);
+        while let Some(ObjectRef(object)) = pending.pop() {
+            if let Ok(mut object) = Rc::try_unwrap(object) {
+                take_references(object.fields.get_mut(), &mut pending);
+            }
+        }
+    }
+}
+
+fn take_references(fields: &mut Storage, into: &mut Vec<ObjectRef>) {
+    if let Storage::Values(ref mut values) = *fields {
+        for value in values {
+            if let Value::Reference(ref mut reference) = *value {
+                into.extend(reference.take());
+            }
+        }
+    }
+}
+
+// An object's fields or an array's elements. Arrays of primitives are kept unboxed, in buffers
+// of their element type, so that natives can work on them in bulk.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Storage {
+    Values(Vec<Value>), // An object's fields, or the elements of an array of references.
+    Bytes(Vec<i8>), // Also used for boolean arrays, as in HotSpot.
+    Chars(Vec<u16>),
+    Shorts(Vec<i16>),
+    Ints(Vec<i32>),
+    Longs(Vec<i64>),
+    Floats(Vec<f32>),
+    Doubles(Vec<f64>),
+}
+
+// Generates the accessors for one of the typed buffers, which return None if the storage holds
+// a different type.
+macro_rules! typed_buffer {
+    ($variant:ident, $element:ty, $get:ident, $get_mut:ident) => {
+        pub fn $get(&self) -> Option<&[$element]> {
+            match *self {
+                Storage::$variant(ref elements) => Some(elements),
+                _ => None,
+            }
+        }
+
+        pub fn $get_mut(&mut self) -> Option<&mut [$element]> {
+            match *self {
+                Storage::$variant(ref mut elements) => Some(elements),
+                _ => None,
+            }
+        }
+    };
+}
+
+impl Storage {
+    // The storage for an array with every element set to the default value for its type.
+    pub fn for_array(component: &FieldType, length: usize) -> Storage {
+        match *component {
+            FieldType::Boolean | FieldType::Byte => Storage::Bytes(vec![0; length]),
+            FieldType::Char => Storage::Chars(vec![0; length]),
+            FieldType::Short => Storage::Shorts(vec![0; length]),
+            FieldType::Int => Storage::Ints(vec![0; length]),
+            FieldType::Long => Storage::Longs(vec![0; length]),
+            FieldType::Float => Storage::Floats(vec![0.0; length]),
+            FieldType::Double => Storage::Doubles(vec![0.0; length]),
+            FieldType::Object(_) | FieldType::Array(_) => Storage::Values(vec![Value::null(); length]),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match *self {
+            Storage::Values(ref values) => values.len(),
+            Storage::Bytes(ref elements) => elements.len(),
+            Storage::Chars(ref elements) => elements.len(),
+            Storage::Shorts(ref elements) => elements.len(),
+            Storage::Ints(ref elements) => elements.len(),
+            Storage::Longs(ref elements) => elements.len(),
+            Storage::Floats(ref elements) => elements.len(),
+            Storage::Doubles(ref elements) => elements.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The size of each element in bytes, with references taking 8.
+    pub fn element_size(&self) -> u64 {
+        match *self {
+            Storage::Bytes(_) => 1,
+            Storage::Chars(_) | Storage::Shorts(_) => 2,
+            Storage::Ints(_) | Storage::Floats(_) => 4,
+            Storage::Values(_) | Storage::Longs(_) | Storage::Doubles(_) => 8,
+        }
+    }
+
+    // Reads a field or element as it would appear on the operand stack. Panics if the index is
+    // out of bounds, like indexing a Vec.
+    pub fn get(&self, index: usize) -> Value {
+        match *self {
+            Storage::Values(ref values) => values[index].clone(),
+            Storage::Bytes(ref elements) => Value::Int(elements[index] as i32),
+            Storage::Chars(ref elements) => Value::Int(elements[index] as i32),
+            Storage::Shorts(ref elements) => Value::Int(elements[index] as i32),
+            Storage::Ints(ref elements) => Value::Int(elements[index]),
+            Storage::Longs(ref elements) => Value::Long(elements[index]),
+            Storage::Floats(ref elements) => Value::Float(elements[index]),
+            Storage::Doubles(ref elements) => Value::Double(elements[index]),
+        }
+    }
+
+    // Reads a field or element, or returns None if the index is out of bounds. For code that
+    // hasn't been verified, where the index may not belong to the object.
+    pub fn try_get(&self, index: usize) -> Option<Value> {
+        if index < self.len() {
+            Some(self.get(index))
+        } else {
+            None
+        }
+    }
+
+    // Writes a field or element, truncating ints stored into narrower arrays. Returns false,
+    // leaving the storage unchanged, if the index is out of bounds or the value is of the wrong
+    // type for the storage.
+    pub fn set(&mut self, index: usize, value: Value) -> bool {
+        if index >= self.len() {
+            return false;
+        }
+        match (self, value) {
+            (Storage::Values(ref mut values), value) => values[index] = value,
+            (Storage::Bytes(ref mut elements), Value::Int(value)) => elements[index] = value as i8,
+            (Storage::Chars(ref mut elements), Value::Int(value)) => elements[index] = value as u16,
+            (Storage::Shorts(ref mut elements), Value::Int(value)) => elements[index] = value as i16,
+            (Storage::Ints(ref mut elements), Value::Int(value)) => elements[index] = value,
+            (Storage::Longs(ref mut elements), Value::Long(value)) => elements[index] = value,
+            (Storage::Floats(ref mut elements), Value::Float(value)) => elements[index] = value,
+            (Storage::Doubles(ref mut elements), Value::Double(value)) => elements[index] = value,
+            _ => return false,
+        }
+        true
+    }
+
+    // Copies elements from another storage of the same type, returning false, leaving this
+    // storage unchanged, if the types differ. Panics if either range is out of bounds.
+    pub fn copy_from(&mut self, dest_pos: usize, src: &Storage, src_pos: usize, length: usize) -> bool {
+        let (dest_range, src_range) = (dest_pos..dest_pos + length, src_pos..src_pos + length);
+        match (self, src) {
+            (Storage::Values(ref mut dest), Storage::Values(ref src)) => dest[dest_range].clone_from_slice(&src[src_range]),
+            (Storage::Bytes(ref mut dest), Storage::Bytes(ref src)) => dest[dest_range].copy_from_slice(&src[src_range]),
+            (Storage::Chars(ref mut dest), Storage::Chars(ref src)) => dest[dest_range].copy_from_slice(&src[src_range]),
+            (Storage::Shorts(ref mut dest), Storage::Shorts(ref src)) => dest[dest_range].copy_from_slice(&src[src_range]),
+            (Storage::Ints(ref mut dest), Storage::Ints(ref src)) => dest[dest_range].copy_from_slice(&src[src_range]),
+            (Storage::Longs(ref mut dest), Storage::Longs(ref src)) => dest[dest_range].copy_from_slice(&src[src_range]),
+            (Storage::Floats(ref mut dest), Storage::Floats(ref src)) => dest[dest_range].copy_from_slice(&src[src_range]),
+            (Storage::Doubles(ref mut dest), Storage::Doubles(ref src)) => dest[dest_range].copy_from_slice(&src[src_range]),
+            _ => return false,
+        }
+        true
+    }
+
+    // Copies elements from one part of the storage to another. Where the parts overlap, this
+    // acts as though the elements were copied to a temporary buffer first. Panics if either
+    // range is out of bounds.
+    pub fn copy_within(&mut self, src_pos: usize, dest_pos: usize, length: usize) {
+        let src_range = src_pos..src_pos + length;
+        match *self {
+            Storage::Values(ref mut values) => {
+                // Values aren't Copy, so they're cloned one at a time, in the order that doesn't
+                // overwrite any before they're read.
+                if dest_pos < src_pos {
+                    (0..length).for_each(|offset| values[dest_pos + offset] = values[src_pos + offset].clone());
+                } else {
+                    (0..length).rev().for_each(|offset| values[dest_pos + offset] = values[src_pos + offset].clone());
+                }
+            },
+            Storage::Bytes(ref mut elements) => elements.copy_within(src_range, dest_pos),
+            Storage::Chars(ref mut elements) => elements.copy_within(src_range, dest_pos),
+            Storage::Shorts(ref mut elements) => elements.copy_within(src_range, dest_pos),
+            Storage::Ints(ref mut elements) => elements.copy_within(src_range, dest_pos),
+            Storage::Longs(ref mut elements) => elements.copy_within(src_range, dest_pos),
+            Storage::Floats(ref mut elements) => elements.copy_within(src_range, dest_pos),
+            Storage::Doubles(ref mut elements) => elements.copy_within(src_range, dest_pos),
+        }
+    }
+
+    typed_buffer!(Values, Value, values, values_mut);
+    typed_buffer!(Bytes, i8, bytes, bytes_mut);
+    typed_buffer!(Chars, u16, chars, chars_mut);
+    typed_buffer!(Shorts, i16, shorts, shorts_mut);
+    typed_buffer!(Ints, i32, ints, ints_mut);
+    typed_buffer!(Longs, i64, longs, longs_mut);
+    typed_buffer!(Floats, f32, floats, floats_mut);
+    typed_buffer!(Doubles, f64, doubles, doubles_mut);
+}
+
+// The VM's bookkeeping for an object, kept alongside its fields as in HotSpot's object header.
+pub struct Header {
+    pub class: Rc<RuntimeClass>,
+    // The identity hash code, or zero if it hasn't been asked for yet. It's assigned on first
+    // use and stored here rather than derived from the object's address, so it stays the same
+    // even if a collector moves the object.
+    hash: Cell<i32>,
+}
+
+// Every object the VM allocates. Objects are reference counted, so they're freed as soon as
+// they're unreachable unless they're part of a cycle; the heap only keeps weak references to
+// them, so that it can report on what's live.
+pub struct Heap {
+    objects: Vec<Weak<Object>>,
+    tenured: usize, // The number of entries in objects that survived the last collection.
+    live_after_last_prune: usize,
+    allocated_objects: u64,
+    allocated_bytes: u64,
+    hash_state: [u32; 4],
+}
+
+impl Default for Heap {
+    fn default() -> Heap {
+        Heap {
+            objects: vec![],
+            tenured: 0,
+            live_after_last_prune: 0,
+            allocated_objects: 0,
+            allocated_bytes: 0,
+            // HotSpot's seeds for its identity hash generator, apart from the first, which it
+            // picks at random. A fixed seed keeps runs reproducible.
+            hash_state: [0x1234_5678, 842502087, 0x8767, 273326509],
+        }
+    }
+}
+
+impl Heap {
+    pub fn new() -> Heap {
+        Heap::default()
+    }
+
+    // Allocates an object of the given class, with a slot for each of its fields.
+    pub fn allocate_object(&mut self, class: &Rc<RuntimeClass>) -> ObjectRef {
+        self.allocate(class, Storage::Values(class.new_instance_fields()))
+    }
+
+    // Allocates an array with every element set to the default value for the component type.
+    // Returns None if the class isn't an array class.
+    pub fn allocate_array(&mut self, class: &Rc<RuntimeClass>, length: usize) -> Option<ObjectRef> {
+        let component = class.component_type()?;
+        Some(self.allocate(class, Storage::for_array(&component, length)))
+    }
+
+    // Allocates an object with the given fields, or an array with the given elements.
+    pub fn allocate(&mut self, class: &Rc<RuntimeClass>, fields: Storage) -> ObjectRef {
+        let object = Rc::new(Object {
+            header: Header {class: Rc::clone(class), hash: Cell::new(0)},
+            fields: RefCell::new(fields),
+        });
+        self.allocated_objects += 1;
+        self.allocated_bytes += limits::estimated_size(&object);
+
+        // Dead entries are dropped whenever the list has doubled in size since the last time,
+        // which keeps the cost of tracking objects constant per allocation.
+        if self.objects.len() >= 2 * self.live_after_last_prune.max(1024) {
+            self.prune();
+        }
+        self.objects.push(Rc::downgrade(&object));
+        ObjectRef(object)
+    }
+
+    // Drops the entries for freed objects.
+    pub fn prune(&mut self) {
+        let (mut index, mut tenured, old) = (0, 0, self.tenured);
+        self.objects.retain(|object| {
+            let live = object.strong_count() > 0;
+            if live && index < old {
+                tenured += 1;
+            }
+            index += 1;
+            live
+        });
+        self.tenured = tenured;
+        self.live_after_last_prune = self.objects.len();
+    }
+
+    // Moves every live object into the old generation, at the end of a collection.
+    pub fn tenure_survivors(&mut self) {
+        self.prune();
+        self.tenured = self.objects.len();
+    }
+
+    // The estimated sizes of the live objects in the young and old generations.
+    pub fn generation_sizes(&self) -> (u64, u64) {
+        let size = |objects: &[Weak<Object>]| objects.iter()
+            .filter_map(Weak::upgrade)
+            .map(|object| limits::estimated_size(&object))
+            .sum::<u64>();
+        let tenured = self.tenured.min(self.objects.len());
+        (size(&self.objects[tenured..]), size(&self.objects[..tenured]))
+    }
+
+    // The object's identity hash code, as returned by Object.hashCode and
+    // System.identityHashCode. It's a positive 31-bit number, as in HotSpot.
+    pub fn identity_hash(&mut self, object: &Object) -> i32 {
+        if object.header.hash.get() == 0 {
+            let mut hash = 0;
+            while hash == 0 {
+                hash = (self.next_random() & 0x7fff_ffff) as i32;
+            }
+            object.header.hash.set(hash);
+        }
+        object.header.hash.get()
+    }
+
+    // Marsaglia's xorshift generator, which is what HotSpot uses for identity hashes.
+    fn next_random(&mut self) -> u32 {
+        let [x, y, z, w] = self.hash_state;
+        let t = x ^ (x << 11);
+        let next = (w ^ (w >> 19)) ^ (t ^ (t >> 8));
+        self.hash_state = [y, z, w, next];
+        next
+    }
+
+    // Every object that's still reachable from somewhere, in the order they were allocated.
+    pub fn live_objects(&self) -> impl Iterator<Item = ObjectRef> + '_ {
+        self.objects.iter().filter_map(Weak::upgrade).map(ObjectRef)
+    }
+
+    pub fn live_object_count(&self) -> usize {
+        self.objects.iter().filter(|object| object.strong_count() > 0).count()
+    }
+
+    // The number of objects allocated since the VM started, including those since freed.
+    pub fn allocated_objects(&self) -> u64 {
+        self.allocated_objects
+    }
+
+    // The estimated size of every object allocated since the VM started.
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocated_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::Vm;
+
+    #[test]
+    fn test_allocated_objects_record_their_class() {
+        let mut vm = Vm::new();
+        let class = vm.load_class("java/lang/Object").unwrap();
+        let mut heap = Heap::new();
+        let object = heap.allocate_object(&class);
+        assert!(Rc::ptr_eq(&class, object.class()));
+        assert!(!object.is_array());
+        assert_eq!(1, heap.allocated_objects());
+    }
+
+    #[test]
+    fn test_storage_copies() {
+        let mut ints = Storage::Ints(vec![1, 2, 3, 4]);
+        assert!(ints.copy_from(1, &Storage::Ints(vec![7, 8, 9]), 1, 2));
+        assert_eq!(Storage::Ints(vec![1, 8, 9, 4]), ints);
+        assert!(!ints.copy_from(0, &Storage::Longs(vec![1]), 0, 1));
+
+        let mut values = Storage::Values(vec![Value::Int(1), Value::Int(2), Value::Int(3)]);
+        values.copy_within(0, 1, 2);
+        assert_eq!(Storage::Values(vec![Value::Int(1), Value::Int(1), Value::Int(2)]), values);
+        values.copy_within(1, 0, 2);
+        assert_eq!(Storage::Values(vec![Value::Int(1), Value::Int(2), Value::Int(2)]), values);
+    }
+
+    #[test]
+    fn test_identity_hashes_are_stable_and_distinct() {
+        let mut vm = Vm::new();
+        let class = vm.load_class("java/lang/Object").unwrap();
+        let mut heap = Heap::new();
+        let (first, second) = (heap.allocate_object(&class), heap.allocate_object(&class));
+        let hash = heap.identity_hash(&first);
+        assert!(hash > 0);
+        assert_eq!(hash, heap.identity_hash(&first));
+        assert_ne!(hash, heap.identity_hash(&second));
+    }
+
+    #[test]
+    fn test_allocate_array() {
+        let mut vm = Vm::new();
+        let mut heap = Heap::new();
+        let array = heap.allocate_array(&vm.load_class("[J").unwrap(), 3).unwrap();
+        assert!(array.is_array());
+        assert_eq!(Some(&[0i64; 3][..]), array.fields.borrow().longs());
+        assert!(heap.allocate_array(&vm.load_class("java/lang/Object").unwrap(), 3).is_none());
+    }
+
+    #[test]
+    fn test_typed_storage() {
+        let mut storage = Storage::for_array(&FieldType::Byte, 2);
+        assert!(storage.set(0, Value::Int(0x1ff)));
+        assert_eq!(Value::Int(-1), storage.get(0));
+        assert!(!storage.set(1, Value::Long(1)));
+        assert_eq!(Some(&[-1i8, 0][..]), storage.bytes());
+        assert_eq!(None, storage.chars());
+        assert!(!storage.set(2, Value::Int(1)));
+        assert_eq!(Some(Value::Int(0)), storage.try_get(1));
+        assert_eq!(None, storage.try_get(2));
+
+        let mut storage = Storage::for_array(&FieldType::Char, 1);
+        assert!(storage.set(0, Value::Int(-1)));
+        assert_eq!(Value::Int(0xffff), storage.get(0));
+        assert_eq!(2, storage.element_size());
+
+        let storage = Storage::for_array(&FieldType::Object("java/lang/Object".to_string()), 1);
+        assert_eq!(Value::null(), storage.get(0));
+    }
+
+    #[test]
+    fn test_freeing_long_chains() {
+        let mut vm = Vm::new();
+        let class = vm.load_class("java/lang/Object").unwrap();
+        let mut heap = Heap::new();
+        let mut head = Value::null();
+        for _ in 0..100_000 {
+            head = Value::Reference(Some(heap.allocate(&class, Storage::Values(vec![head]))));
+        }
+        drop(head);
+        assert_eq!(0, heap.live_object_count());
+    }
+
+    #[test]
+    fn test_freed_objects_are_not_live() {
+        let mut vm = Vm::new();
+        let class = vm.load_class("java/lang/Object").unwrap();
+        let mut heap = Heap::new();
+        let kept = heap.allocate_object(&class);
+        for _ in 0..5000 {
+            heap.allocate_object(&class);
+        }
+        assert_eq!(1, heap.live_object_count());
+        assert_eq!(vec![kept], heap.live_objects().collect::<Vec<_>>());
+        assert!(heap.objects.len() < 5001);
+        assert_eq!(5001, heap.allocated_objects());
+    }
+}
@@ -0,0 +1,128 @@
+use crate::limits;
+use crate::runtime::RuntimeClass;
+use crate::vm::Vm;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+// How many instances of each class are on the heap and how much room they take, as jmap -histo
+// reports it. Sizes are shallow, i.e. an object's own fields or elements and not the objects they
+// refer to, and estimated as for the allocation limit. Classes are listed by their total size,
+// largest first.
+//
+// This covers every object that's still allocated, including garbage that hasn't been collected
+// yet; collect garbage first, with GcCause::HeapInspection, to see only live objects, as
+// jmap -histo:live does.
+#[derive(Clone, Default)]
+pub struct Histogram {
+    entries: Vec<HistogramEntry>,
+}
+
+#[derive(Clone)]
+pub struct HistogramEntry {
+    pub class: Rc<RuntimeClass>,
+    pub instances: u64,
+    pub bytes: u64,
+}
+
+impl Histogram {
+    pub fn of_heap(vm: &Vm) -> Histogram {
+        // Classes are told apart by identity, since different loaders may define the same name.
+        let mut by_class: HashMap<*const RuntimeClass, HistogramEntry> = HashMap::new();
+        for object in vm.heap().live_objects() {
+            let entry = by_class.entry(Rc::as_ptr(object.class())).or_insert_with(|| {
+                HistogramEntry {class: Rc::clone(object.class()), instances: 0, bytes: 0}
+            });
+            entry.instances += 1;
+            entry.bytes += limits::estimated_size(&object);
+        }
+        let mut entries: Vec<HistogramEntry> = by_class.into_values().collect();
+        entries.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.class.name.cmp(&b.class.name)));
+        Histogram {entries}
+    }
+
+    pub fn entries(&self) -> &[HistogramEntry] {
+        &self.entries
+    }
+
+    // The entry for the class of that name, or the first of them if several loaders define one.
+    pub fn entry(&self, class: &str) -> Option<&HistogramEntry> {
+        self.entries.iter().find(|entry| entry.class.name == class)
+    }
+
+    pub fn total_instances(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.instances).sum()
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.bytes).sum()
+    }
+}
+
+// In jmap -histo's format:
+//
+//      num     #instances         #bytes  class name
+//     ----------------------------------------------
+//        1:           120           4800  [C
+//        2:            95           1520  java.lang.String
+//     Total           215           6320
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, " num     #instances         #bytes  class name")?;
+        writeln!(f, "----------------------------------------------")?;
+        for (index, entry) in self.entries.iter().enumerate() {
+            writeln!(f, "{:>4}: {:>13} {:>14}  {}", index + 1, entry.instances, entry.bytes, entry.class.java_name())?;
+        }
+        writeln!(f, "Total {:>13} {:>14}", self.total_instances(), self.total_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use crate::gc::GcCause;
+
+    #[test]
+    fn test_counts_instances_per_class() {
+        let mut vm = Vm::new();
+        let before = Histogram::of_heap(&vm);
+        let _strings: Vec<_> = (0..3).map(|n| vm.new_string(&"x".repeat(n)).unwrap()).collect();
+        let int_array_class = vm.load_class("[I").unwrap();
+        let array = vm.new_array(&int_array_class, 10).unwrap();
+
+        let histogram = Histogram::of_heap(&vm);
+        let count = |histogram: &Histogram, class: &str| histogram.entry(class).map_or(0, |entry| entry.instances);
+        assert_eq!(3, count(&histogram, "java/lang/String") - count(&before, "java/lang/String"));
+        assert_eq!(3, count(&histogram, "[C") - count(&before, "[C"));
+        let ints = histogram.entry("[I").unwrap();
+        assert_eq!(1, ints.instances);
+        assert_eq!(limits::estimated_size(&array), ints.bytes);
+        assert_eq!(histogram.total_instances(), histogram.entries().iter().map(|entry| entry.instances).sum::<u64>());
+        assert!(histogram.entries().windows(2).all(|pair| pair[0].bytes >= pair[1].bytes));
+        assert_eq!(vm.heap().live_object_count() as u64, histogram.total_instances());
+    }
+
+    #[test]
+    fn test_garbage_is_counted_until_collected() {
+        let mut vm = vm_with(&[fixture!("Graph")]);
+        vm.invoke_static("Graph", "leakCycle", "()V", vec![]).unwrap();
+        let leaked = Histogram::of_heap(&vm).entry("Graph").map_or(0, |entry| entry.instances);
+        assert!(leaked > 0);
+        vm.collect_garbage(GcCause::Requested);
+        assert!(Histogram::of_heap(&vm).entry("Graph").is_none());
+    }
+
+    #[test]
+    fn test_format() {
+        let mut vm = Vm::new();
+        let int_array_class = vm.load_class("[I").unwrap();
+        let _array = vm.new_array(&int_array_class, 100).unwrap();
+        let histogram = Histogram::of_heap(&vm);
+        let text = histogram.to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(" num     #instances         #bytes  class name", lines[0]);
+        assert!(lines.contains(&"   1:             1            416  [I"), "{}", text);
+        assert_eq!(format!("Total {:>13} {:>14}", histogram.total_instances(), histogram.total_bytes()), *lines.last().unwrap());
+    }
+}
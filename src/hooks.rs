@@ -0,0 +1,28 @@
+use crate::classes::Method;
+use crate::heap::ObjectRef;
+use crate::runtime::*;
+use crate::vm::VmError;
+
+// Callbacks made by the interpreter as it runs, for building tracers, coverage tools and
+// debuggers. Install them with Vm::set_hooks. Every callback does nothing by default, so
+// implementations only need to override the events they care about. Tools that don't need each
+// instruction can subscribe to the same method and exception events with Vm::subscribe instead.
+//
+// The interpreter only checks for hooks when a method is entered, and runs a separate copy of
+// the dispatch loop when they're installed, so they cost nothing while they're not.
+pub trait ExecutionHooks {
+    // Called when a frame is pushed for the method, before its first instruction runs.
+    fn on_method_enter(&mut self, _class: &RuntimeClass, _method: &Method) {}
+
+    // Called when the method's frame is popped, whether it returned or threw.
+    fn on_method_exit(&mut self, _class: &RuntimeClass, _method: &Method, _result: &Result<Option<Value>, VmError>) {}
+
+    // Called before each instruction is executed, with the operand stack as the instruction will
+    // find it. Longs and doubles take one entry each.
+    fn on_instruction(&mut self, _class: &RuntimeClass, _method: &Method, _pc: usize, _opcode: u8, _stack: &[Value]) {}
+
+    // Called when an instruction throws an exception, before looking for a handler. This covers
+    // athrow and exceptions raised by the VM, but not exceptions propagating out of a callee,
+    // since those were reported when the callee threw them.
+    fn on_exception_thrown(&mut self, _class: &RuntimeClass, _method: &Method, _pc: usize, _exception: &ObjectRef) {}
+}
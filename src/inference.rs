@@ -0,0 +1,1313 @@
+use crate::bytecode::{self, BytecodeError, Instruction, Operand};
+use crate::classes::*;
+use crate::descriptor::{DescriptorError, FieldType, MethodDescriptor};
+use crate::opcodes::*;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::{error, fmt};
+
+// The verifier that the JVM uses for class files older than version 50, which have no
+// StackMapTables to check against (JVM spec 4.10.2). Each method's code is run over the types of
+// values rather than the values themselves, merging the types wherever control flow joins, until
+// the type of every local and stack slot at every instruction is known. Any instruction applied
+// to values of the wrong type rejects the class.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Type {
+    Top, // Unusable, as is the second local slot holding a long or double.
+    Int, // Also booleans, bytes, chars and shorts.
+    Float,
+    Long,
+    Double,
+    Null,
+    Reference(String), // A class name, or the descriptor of an array class.
+    Uninitialized(usize), // Created by the new instruction at this pc, but not yet constructed.
+    UninitializedThis,
+    ReturnAddress(usize), // Pushed by a jsr to the subroutine at this pc.
+}
+
+impl Type {
+    pub fn from_field_type(field_type: &FieldType) -> Type {
+        match *field_type {
+            FieldType::Boolean | FieldType::Byte | FieldType::Char | FieldType::Short | FieldType::Int => Type::Int,
+            FieldType::Float => Type::Float,
+            FieldType::Long => Type::Long,
+            FieldType::Double => Type::Double,
+            FieldType::Object(ref name) => Type::Reference(name.clone()),
+            FieldType::Array(_) => Type::Reference(field_type.descriptor()),
+        }
+    }
+
+    pub fn is_category_2(&self) -> bool {
+        matches!(*self, Type::Long | Type::Double)
+    }
+
+    // Whether this is a constructed object, or null.
+    pub fn is_reference(&self) -> bool {
+        matches!(*self, Type::Null | Type::Reference(_))
+    }
+
+    fn is_array(&self) -> bool {
+        matches!(*self, Type::Reference(ref name) if name.starts_with('['))
+    }
+
+    // The type of an array's elements. The elements of null are null.
+    fn component(&self) -> Option<Type> {
+        match *self {
+            Type::Null => Some(Type::Null),
+            Type::Reference(ref name) if name.starts_with('[') => FieldType::parse(&name[1..]).ok().map(|component| Type::from_field_type(&component)),
+            _ => None,
+        }
+    }
+}
+
+// The class name of an array of the given class or array class.
+fn array_of(name: &str) -> String {
+    if name.starts_with('[') {
+        format!("[{}", name)
+    } else {
+        format!("[L{};", name)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Type::Top => write!(f, "top"),
+            Type::Int => write!(f, "integer"),
+            Type::Float => write!(f, "float"),
+            Type::Long => write!(f, "long"),
+            Type::Double => write!(f, "double"),
+            Type::Null => write!(f, "null"),
+            Type::Reference(ref name) => write!(f, "'{}'", name),
+            Type::Uninitialized(pc) => write!(f, "uninitialized({})", pc),
+            Type::UninitializedThis => write!(f, "uninitializedThis"),
+            Type::ReturnAddress(pc) => write!(f, "returnAddress({})", pc),
+        }
+    }
+}
+
+// The types of the locals and operand stack on entry to an instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub locals: Vec<Type>,
+    pub stack: Vec<Type>, // Longs and doubles are one entry here, but count as two towards max_stack.
+    pub this_uninitialized: bool, // In a constructor that hasn't yet called another.
+}
+
+impl Frame {
+    fn depth(&self) -> usize {
+        self.stack.iter().map(|value| if value.is_category_2() { 2 } else { 1 }).sum()
+    }
+
+    fn push(&mut self, value: Type, max_stack: usize) -> Result<(), Problem> {
+        self.stack.push(value);
+        if self.depth() > max_stack {
+            return Err(Problem::StackOverflow);
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Type, Problem> {
+        self.stack.pop().ok_or(Problem::StackUnderflow)
+    }
+
+    // Pops values totalling the given number of stack words, as the pop2, dup and swap families
+    // do, returning them bottom first. Those instructions can't split a long or double in two.
+    fn pop_words(&mut self, words: usize) -> Result<Vec<Type>, Problem> {
+        let mut values = vec![];
+        let mut popped = 0;
+        while popped < words {
+            let value = self.pop()?;
+            popped += if value.is_category_2() { 2 } else { 1 };
+            values.insert(0, value);
+        }
+        if popped > words {
+            return Err(Problem::SplitValue);
+        }
+        Ok(values)
+    }
+
+    fn local(&self, index: usize) -> Result<&Type, Problem> {
+        self.locals.get(index).ok_or(Problem::InvalidLocal(index))
+    }
+
+    // Longs and doubles take up two locals, so storing over either half of one destroys it.
+    fn set_local(&mut self, index: usize, value: Type) -> Result<(), Problem> {
+        let last = if value.is_category_2() { index + 1 } else { index };
+        if last >= self.locals.len() {
+            return Err(Problem::InvalidLocal(last));
+        }
+        if index > 0 && self.locals[index - 1].is_category_2() {
+            self.locals[index - 1] = Type::Top;
+        }
+        if value.is_category_2() {
+            self.locals[index + 1] = Type::Top;
+        }
+        self.locals[index] = value;
+        Ok(())
+    }
+
+    // Replaces every occurrence of a type, as constructing an object does for all its copies.
+    fn replace(&mut self, from: &Type, to: &Type) {
+        for value in self.locals.iter_mut().chain(self.stack.iter_mut()) {
+            if value == from {
+                *value = to.clone();
+            }
+        }
+    }
+}
+
+// Where a value is in a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Slot {
+    Local(usize),
+    Stack(usize), // Counting entries up from the bottom of the stack.
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Slot::Local(index) => write!(f, "locals[{}]", index),
+            Slot::Stack(index) => write!(f, "stack[{}]", index),
+        }
+    }
+}
+
+// What the verifier needs to know about the classes a method refers to, in order to check that
+// values are assignable and to merge references where control flow joins.
+pub trait Hierarchy {
+    // None if there's no such class.
+    fn lookup(&mut self, name: &str) -> Option<ClassInfo>;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClassInfo {
+    pub super_class: Option<String>,
+    pub is_interface: bool,
+}
+
+impl ClassInfo {
+    pub fn of(class: &Class) -> Result<ClassInfo, ConstantLookupError> {
+        Ok(ClassInfo {super_class: class.super_class_name()?.map(str::to_string), is_interface: class.flags.contains(ClassFlags::INTERFACE)})
+    }
+
+    pub fn of_header(header: &ClassHeader) -> ClassInfo {
+        ClassInfo {super_class: header.super_class.clone(), is_interface: header.flags.contains(ClassFlags::INTERFACE)}
+    }
+}
+
+impl<F: FnMut(&str) -> Option<ClassInfo>> Hierarchy for F {
+    fn lookup(&mut self, name: &str) -> Option<ClassInfo> {
+        self(name)
+    }
+}
+
+// Verifies the code of every method in the class.
+pub fn verify_class(class: &Class, hierarchy: &mut dyn Hierarchy) -> Result<(), InferenceError> {
+    for method in &class.methods {
+        infer_frames(class, method, hierarchy)?;
+    }
+    Ok(())
+}
+
+// Verifies the method's code, returning the frame on entry to each reachable instruction. Methods
+// without code have no frames.
+pub fn infer_frames(class: &Class, method: &Method, hierarchy: &mut dyn Hierarchy) -> Result<BTreeMap<usize, Frame>, InferenceError> {
+    let code = match method.code() {
+        Some(code) => code,
+        None => return Ok(BTreeMap::new()),
+    };
+    let (name, descriptor) = (class.utf8(&method.name)?, class.utf8(&method.descriptor)?);
+    let signature = format!("{}{}", name, descriptor);
+    let instructions = bytecode::decode(code.code).map_err(|cause| InferenceError::Bytecode{method: signature.clone(), cause})?;
+    let length = code.code.len();
+    let mut at = vec![None; length];
+    for (index, instruction) in instructions.iter().enumerate() {
+        at[instruction.pc] = Some(index);
+    }
+
+    let mut verifier = Verifier {
+        class,
+        class_name: class.name()?,
+        method_name: name,
+        signature,
+        descriptor: MethodDescriptor::parse(descriptor)?,
+        is_static: method.flags.contains(MethodFlags::STATIC),
+        code,
+        instructions,
+        at,
+        subroutines: HashMap::new(),
+        frames: vec![None; length],
+        exits: HashMap::new(),
+        pending: BTreeSet::new(),
+        hierarchy,
+    };
+    verifier.run()
+}
+
+// A subroutine, and the jsr instructions that call it.
+struct Subroutine {
+    callers: Vec<usize>,
+    modified: HashSet<usize>, // The locals that the subroutine, or any it calls, may store to.
+}
+
+struct Verifier<'a> {
+    class: &'a Class,
+    class_name: &'a str,
+    method_name: &'a str,
+    signature: String,
+    descriptor: MethodDescriptor,
+    is_static: bool,
+    code: Code<'a>,
+    instructions: Vec<Instruction>,
+    at: Vec<Option<usize>>, // The index of the instruction starting at each pc.
+    subroutines: HashMap<usize, Subroutine>,
+    frames: Vec<Option<Frame>>,
+    exits: HashMap<usize, Frame>, // The merged frames of each subroutine's ret instructions.
+    pending: BTreeSet<usize>, // The instructions whose frames have changed since they were checked.
+    hierarchy: &'a mut dyn Hierarchy,
+}
+
+impl<'a> Verifier<'a> {
+    fn run(&mut self) -> Result<BTreeMap<usize, Frame>, InferenceError> {
+        if self.instructions.is_empty() {
+            return Err(self.rejected(0, Problem::FallsOffEnd));
+        }
+        self.find_subroutines()?;
+        let entry = self.entry_frame().map_err(|problem| self.rejected(0, problem))?;
+        self.frames[0] = Some(entry);
+        self.pending.insert(0);
+        while let Some(pc) = self.pending.pop_first() {
+            self.execute(pc).map_err(|problem| self.rejected(pc, problem))?;
+        }
+        Ok(self.frames.iter().enumerate().filter_map(|(pc, frame)| Some((pc, frame.clone()?))).collect())
+    }
+
+    // Records the frame the instruction started with, if it got that far.
+    fn rejected(&self, pc: usize, problem: Problem) -> InferenceError {
+        InferenceError::Rejected(Box::new(Rejection {
+            class: self.class_name.to_string(),
+            method: self.signature.clone(),
+            pc,
+            instruction: self.at.get(pc).copied().flatten().and_then(|_| mnemonic(self.code.code[pc])),
+            problem,
+            frame: self.frames.get(pc).cloned().flatten(),
+        }))
+    }
+
+    // The receiver, which in a constructor is uninitialized until another constructor is called
+    // on it, and then the parameters.
+    fn entry_frame(&self) -> Result<Frame, Problem> {
+        let mut frame = Frame {locals: vec![Type::Top; self.code.max_locals as usize], stack: vec![], this_uninitialized: false};
+        let mut index = 0;
+        if !self.is_static {
+            let this = if self.method_name == "<init>" && self.class_name != "java/lang/Object" {
+                frame.this_uninitialized = true;
+                Type::UninitializedThis
+            } else {
+                Type::Reference(self.class_name.to_string())
+            };
+            frame.set_local(0, this)?;
+            index += 1;
+        }
+        for parameter in &self.descriptor.parameters {
+            frame.set_local(index, Type::from_field_type(parameter))?;
+            index += if parameter.is_category_2() { 2 } else { 1 };
+        }
+        Ok(frame)
+    }
+
+    // Finds each subroutine's callers and the locals it modifies. When a subroutine returns, the
+    // locals it didn't modify have the types they had at the jsr that called it, rather than the
+    // types merged across all its callers.
+    fn find_subroutines(&mut self) -> Result<(), InferenceError> {
+        let mut callers: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for instruction in &self.instructions {
+            if let (JSR | JSR_W, &Operand::Branch(target)) = (instruction.opcode, &instruction.operand) {
+                callers.entry(target).or_default().push(instruction.pc);
+            }
+        }
+        let mut modified = HashMap::new();
+        for &entry in callers.keys() {
+            self.modified_locals(entry, &mut vec![], &mut modified)?;
+        }
+        self.subroutines = callers.into_iter()
+            .map(|(entry, callers)| (entry, Subroutine {callers, modified: modified.remove(&entry).unwrap_or_default()}))
+            .collect();
+        Ok(())
+    }
+
+    // The locals stored to by the instructions reachable from a subroutine's entry without
+    // returning from it, including those of the subroutines it calls. Subroutines may not call
+    // themselves, directly or otherwise.
+    fn modified_locals(&self, entry: usize, active: &mut Vec<usize>, done: &mut HashMap<usize, HashSet<usize>>) -> Result<HashSet<usize>, InferenceError> {
+        if let Some(modified) = done.get(&entry) {
+            return Ok(modified.clone());
+        }
+        active.push(entry);
+        let mut modified = HashSet::new();
+        let mut visited = HashSet::new();
+        let mut unvisited = vec![entry];
+        while let Some(pc) = unvisited.pop() {
+            let instruction = match self.at.get(pc).copied().flatten() {
+                Some(index) if visited.insert(pc) => &self.instructions[index],
+                _ => continue,
+            };
+            modified.extend(instruction.writes_locals());
+            match (instruction.opcode, &instruction.operand) {
+                (JSR | JSR_W, &Operand::Branch(target)) => {
+                    if active.contains(&target) {
+                        return Err(self.rejected(pc, Problem::RecursiveSubroutine(target)));
+                    }
+                    modified.extend(self.modified_locals(target, active, done)?);
+                    unvisited.push(pc + instruction.length);
+                },
+                (RET, _) => (),
+                _ => unvisited.extend(instruction.successors()),
+            }
+            for row in self.handlers(pc) {
+                unvisited.push(row.handler_pc as usize);
+            }
+        }
+        active.pop();
+        done.insert(entry, modified.clone());
+        Ok(modified)
+    }
+
+    fn handlers(&self, pc: usize) -> impl Iterator<Item = &'a ExceptionTableRow> {
+        self.code.exception_table.iter().filter(move |row| row.start_pc as usize <= pc && pc < row.end_pc as usize)
+    }
+
+    // Checks the instruction against its frame, and passes the frame it leaves on to everywhere
+    // that execution can continue.
+    fn execute(&mut self, pc: usize) -> Result<(), Problem> {
+        let instruction = self.instructions[self.at[pc].expect("Frames are only recorded for instructions")].clone();
+        let mut frame = self.frames[pc].clone().expect("Only instructions with frames are pending");
+
+        // Any instruction in a try block may throw, leaving its locals as they were.
+        for row in self.handlers(pc) {
+            let catch_type = match row.catch_type.0 {
+                0 => "java/lang/Throwable",
+                _ => self.class.class_name(&row.catch_type)?,
+            };
+            let handler = Frame {locals: frame.locals.clone(), stack: vec![Type::Reference(catch_type.to_string())], this_uninitialized: frame.this_uninitialized};
+            self.merge_into(row.handler_pc as usize, handler)?;
+        }
+
+        match (instruction.opcode, &instruction.operand) {
+            (JSR | JSR_W, &Operand::Branch(target)) => {
+                let mut entry = frame.clone();
+                entry.push(Type::ReturnAddress(target), self.code.max_stack as usize)?;
+                self.merge_into(target, entry)?;
+                if let Some(exit) = self.exits.get(&target).cloned() {
+                    self.return_to(pc, &frame, &exit, target)?;
+                }
+            },
+            (RET, &Operand::Local(index)) => {
+                let subroutine = match *frame.local(index as usize)? {
+                    Type::ReturnAddress(subroutine) => subroutine,
+                    ref other => return Err(Problem::NotReturnAddress{actual: other.clone(), slot: Slot::Local(index as usize)}),
+                };
+                let exit = match self.exits.get(&subroutine).cloned() {
+                    Some(old) => {
+                        let merged = self.merge(pc, &old, &frame)?;
+                        if merged == old {
+                            return Ok(());
+                        }
+                        merged
+                    },
+                    None => frame,
+                };
+                self.exits.insert(subroutine, exit.clone());
+                let callers = self.subroutines.get(&subroutine).map_or_else(Vec::new, |subroutine| subroutine.callers.clone());
+                for caller in callers {
+                    if let Some(call) = self.frames[caller].clone() {
+                        self.return_to(caller, &call, &exit, subroutine)?;
+                    }
+                }
+            },
+            _ => {
+                self.step(&instruction, &mut frame)?;
+                for successor in instruction.successors() {
+                    if successor >= self.code.code.len() {
+                        return Err(Problem::FallsOffEnd);
+                    }
+                    self.merge_into(successor, frame.clone())?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    // Continues after a jsr once its subroutine returns, with the locals that the subroutine
+    // modified as they were at the ret, and the rest as they were at the jsr. A long or double
+    // only half of which the subroutine modified is no longer usable.
+    fn return_to(&mut self, jsr: usize, call: &Frame, exit: &Frame, subroutine: usize) -> Result<(), Problem> {
+        let next = jsr + self.instructions[self.at[jsr].expect("Callers are instructions")].length;
+        if next >= self.code.code.len() {
+            return Err(Problem::FallsOffEnd);
+        }
+        let modified = &self.subroutines[&subroutine].modified;
+        let mut locals: Vec<Type> = (0..call.locals.len())
+            .map(|index| if modified.contains(&index) { exit.locals[index].clone() } else { call.locals[index].clone() })
+            .collect();
+        for index in 0..locals.len().saturating_sub(1) {
+            if locals[index].is_category_2() && modified.contains(&index) != modified.contains(&(index + 1)) {
+                locals[index] = Type::Top;
+            }
+        }
+        let frame = Frame {locals, stack: exit.stack.clone(), this_uninitialized: exit.this_uninitialized};
+        self.merge_into(next, frame)
+    }
+
+    fn merge_into(&mut self, pc: usize, frame: Frame) -> Result<(), Problem> {
+        if self.at.get(pc).copied().flatten().is_none() {
+            return Err(Problem::InvalidTarget(pc));
+        }
+        let merged = match self.frames[pc].clone() {
+            Some(old) => {
+                let merged = self.merge(pc, &old, &frame)?;
+                if merged == old {
+                    return Ok(());
+                }
+                merged
+            },
+            None => frame,
+        };
+        self.frames[pc] = Some(merged);
+        self.pending.insert(pc);
+        Ok(())
+    }
+
+    // Locals whose types disagree become unusable, but the stacks must agree, apart from
+    // references, which merge into their closest common superclass. The target is the pc that
+    // the old frame belongs to, or a subroutine's ret.
+    fn merge(&mut self, target: usize, old: &Frame, new: &Frame) -> Result<Frame, Problem> {
+        if old.stack.len() != new.stack.len() {
+            return Err(Problem::StackHeight{target, expected: old.stack.len(), actual: new.stack.len()});
+        }
+        let mut locals = vec![];
+        for (left, right) in old.locals.iter().zip(&new.locals) {
+            locals.push(match (left, right) {
+                _ if left == right => left.clone(),
+                _ if left.is_reference() && right.is_reference() => self.common_superclass(left, right)?,
+                _ => Type::Top,
+            });
+        }
+        let mut stack = vec![];
+        for (index, (left, right)) in old.stack.iter().zip(&new.stack).enumerate() {
+            stack.push(match (left, right) {
+                _ if left == right => left.clone(),
+                _ if left.is_reference() && right.is_reference() => self.common_superclass(left, right)?,
+                _ => return Err(Problem::IncompatibleStack{target, index, expected: left.clone(), actual: right.clone()}),
+            });
+        }
+        Ok(Frame {locals, stack, this_uninitialized: old.this_uninitialized || new.this_uninitialized})
+    }
+
+    // Interfaces count as java/lang/Object here, as they do for assignability, and so do arrays
+    // of primitives that differ.
+    fn common_superclass(&mut self, left: &Type, right: &Type) -> Result<Type, Problem> {
+        let object = || Type::Reference("java/lang/Object".to_string());
+        match (left, right) {
+            (&Type::Null, _) => Ok(right.clone()),
+            (_, &Type::Null) => Ok(left.clone()),
+            _ if left == right => Ok(left.clone()),
+            (&Type::Reference(_), &Type::Reference(_)) if left.is_array() && right.is_array() => {
+                match (left.component(), right.component()) {
+                    (Some(left), Some(right)) if left.is_reference() && right.is_reference() => match self.common_superclass(&left, &right)? {
+                        Type::Reference(name) => Ok(Type::Reference(array_of(&name))),
+                        other => Ok(other),
+                    },
+                    _ => Ok(object()),
+                }
+            },
+            (Type::Reference(left_name), Type::Reference(right_name)) if !left.is_array() && !right.is_array() => {
+                let left_chain = self.superclasses(left_name)?;
+                for name in self.superclasses(right_name)? {
+                    if left_chain.contains(&name) {
+                        return Ok(Type::Reference(name));
+                    }
+                }
+                Ok(object())
+            },
+            _ => Ok(object()),
+        }
+    }
+
+    fn superclasses(&mut self, name: &str) -> Result<Vec<String>, Problem> {
+        superclasses(name, self.hierarchy)
+    }
+
+    fn is_assignable(&mut self, value: &Type, to: &Type) -> Result<bool, Problem> {
+        is_assignable(value, to, self.hierarchy)
+    }
+
+    fn pop_expecting(&mut self, frame: &mut Frame, expected: &Type) -> Result<Type, Problem> {
+        let value = frame.pop()?;
+        if !self.is_assignable(&value, expected)? {
+            return Err(Problem::Mismatch{expected: expected.clone(), actual: value, slot: Slot::Stack(frame.stack.len())});
+        }
+        Ok(value)
+    }
+
+    fn pop_reference(&mut self, frame: &mut Frame) -> Result<Type, Problem> {
+        let value = frame.pop()?;
+        if !value.is_reference() {
+            return Err(Problem::NotReference{actual: value, slot: Slot::Stack(frame.stack.len())});
+        }
+        Ok(value)
+    }
+
+    // Pops an array of one of the given types, or null.
+    fn pop_array(&mut self, frame: &mut Frame, accepted: &[&str]) -> Result<Type, Problem> {
+        let array = frame.pop()?;
+        match array {
+            Type::Null => Ok(array),
+            Type::Reference(ref name) if accepted.contains(&name.as_str()) => Ok(array),
+            _ => Err(Problem::Mismatch{expected: Type::Reference(accepted[0].to_string()), actual: array, slot: Slot::Stack(frame.stack.len())}),
+        }
+    }
+
+    // Pops an array of references, or null.
+    fn pop_reference_array(&mut self, frame: &mut Frame) -> Result<Type, Problem> {
+        let array = frame.pop()?;
+        match array.component() {
+            Some(component) if component.is_reference() => Ok(array),
+            _ => Err(Problem::NotArray{actual: array, slot: Slot::Stack(frame.stack.len())}),
+        }
+    }
+
+    fn load(&mut self, frame: &mut Frame, index: u16, expected: Type) -> Result<(), Problem> {
+        let value = frame.local(index as usize)?;
+        if *value != expected {
+            return Err(Problem::Mismatch{expected, actual: value.clone(), slot: Slot::Local(index as usize)});
+        }
+        frame.push(expected, self.code.max_stack as usize)
+    }
+
+    fn store(&mut self, frame: &mut Frame, index: u16, expected: Type) -> Result<(), Problem> {
+        let value = self.pop_expecting(frame, &expected)?;
+        frame.set_local(index as usize, value)
+    }
+
+    fn unary(&mut self, frame: &mut Frame, operand: Type, result: Type) -> Result<(), Problem> {
+        self.pop_expecting(frame, &operand)?;
+        frame.push(result, self.code.max_stack as usize)
+    }
+
+    fn binary(&mut self, frame: &mut Frame, left: Type, right: Type, result: Type) -> Result<(), Problem> {
+        self.pop_expecting(frame, &right)?;
+        self.pop_expecting(frame, &left)?;
+        frame.push(result, self.code.max_stack as usize)
+    }
+
+    // Applies an instruction other than jsr or ret to the frame.
+    fn step(&mut self, instruction: &Instruction, frame: &mut Frame) -> Result<(), Problem> {
+        let max_stack = self.code.max_stack as usize;
+        let class = self.class;
+        let numeric = |index: u8| [Type::Int, Type::Long, Type::Float, Type::Double][index as usize % 4].clone();
+        match (instruction.opcode, &instruction.operand) {
+            (NOP | GOTO | GOTO_W, _) => (),
+            (ACONST_NULL, _) => frame.push(Type::Null, max_stack)?,
+            (ICONST_M1..=ICONST_5 | BIPUSH | SIPUSH, _) => frame.push(Type::Int, max_stack)?,
+            (LCONST_0 | LCONST_1, _) => frame.push(Type::Long, max_stack)?,
+            (FCONST_0..=FCONST_2, _) => frame.push(Type::Float, max_stack)?,
+            (DCONST_0 | DCONST_1, _) => frame.push(Type::Double, max_stack)?,
+            (opcode @ (LDC | LDC_W | LDC2_W), &Operand::Constant(index)) => {
+                let value = self.constant_type(index)?;
+                if value.is_category_2() != (opcode == LDC2_W) {
+                    return Err(Problem::BadConstant(index));
+                }
+                frame.push(value, max_stack)?;
+            },
+            (opcode @ ILOAD..=DLOAD, &Operand::Local(index)) => self.load(frame, index, numeric(opcode - ILOAD))?,
+            (ALOAD, &Operand::Local(index)) => {
+                let value = frame.local(index as usize)?.clone();
+                if !value.is_reference() && !matches!(value, Type::Uninitialized(_) | Type::UninitializedThis) {
+                    return Err(Problem::NotReference{actual: value, slot: Slot::Local(index as usize)});
+                }
+                frame.push(value, max_stack)?;
+            },
+            (opcode @ (IALOAD | LALOAD | FALOAD | DALOAD), _) => {
+                self.pop_expecting(frame, &Type::Int)?;
+                self.pop_array(frame, &[["[I", "[J", "[F", "[D"][(opcode - IALOAD) as usize]])?;
+                frame.push(numeric(opcode - IALOAD), max_stack)?;
+            },
+            (AALOAD, _) => {
+                self.pop_expecting(frame, &Type::Int)?;
+                let array = self.pop_reference_array(frame)?;
+                frame.push(array.component().expect("Arrays have components"), max_stack)?;
+            },
+            (opcode @ (BALOAD | CALOAD | SALOAD), _) => {
+                self.pop_expecting(frame, &Type::Int)?;
+                self.pop_array(frame, [&["[B", "[Z"][..], &["[C"], &["[S"]][(opcode - BALOAD) as usize])?;
+                frame.push(Type::Int, max_stack)?;
+            },
+            (opcode @ ISTORE..=DSTORE, &Operand::Local(index)) => self.store(frame, index, numeric(opcode - ISTORE))?,
+            (ASTORE, &Operand::Local(index)) => {
+                let value = frame.pop()?;
+                if value.is_category_2() || matches!(value, Type::Int | Type::Float | Type::Top) {
+                    return Err(Problem::NotReference{actual: value, slot: Slot::Stack(frame.stack.len())});
+                }
+                frame.set_local(index as usize, value)?;
+            },
+            (opcode @ (IASTORE | LASTORE | FASTORE | DASTORE), _) => {
+                self.pop_expecting(frame, &numeric(opcode - IASTORE))?;
+                self.pop_expecting(frame, &Type::Int)?;
+                self.pop_array(frame, &[["[I", "[J", "[F", "[D"][(opcode - IASTORE) as usize]])?;
+            },
+            (AASTORE, _) => {
+                // Whether the value suits the array is checked at run time.
+                self.pop_reference(frame)?;
+                self.pop_expecting(frame, &Type::Int)?;
+                self.pop_reference_array(frame)?;
+            },
+            (opcode @ (BASTORE | CASTORE | SASTORE), _) => {
+                self.pop_expecting(frame, &Type::Int)?;
+                self.pop_expecting(frame, &Type::Int)?;
+                self.pop_array(frame, [&["[B", "[Z"][..], &["[C"], &["[S"]][(opcode - BASTORE) as usize])?;
+            },
+            (POP, _) => {
+                frame.pop_words(1)?;
+            },
+            (POP2, _) => {
+                frame.pop_words(2)?;
+            },
+            (opcode @ (DUP | DUP_X1 | DUP_X2 | DUP2 | DUP2_X1 | DUP2_X2 | SWAP), _) => {
+                // The words duplicated or swapped, and the words they're inserted beneath.
+                let (top, beneath) = match opcode {
+                    DUP => (1, 0),
+                    DUP_X1 | SWAP => (1, 1),
+                    DUP_X2 => (1, 2),
+                    DUP2 => (2, 0),
+                    DUP2_X1 => (2, 1),
+                    _ => (2, 2),
+                };
+                let top = frame.pop_words(top)?;
+                let beneath = frame.pop_words(beneath)?;
+                let pushed = match opcode {
+                    SWAP => [top, beneath].concat(),
+                    _ => [top.clone(), beneath, top].concat(),
+                };
+                for value in pushed {
+                    frame.push(value, max_stack)?;
+                }
+            },
+            (opcode @ IADD..=DREM, _) => {
+                let operand = numeric(opcode - IADD);
+                self.binary(frame, operand.clone(), operand.clone(), operand)?;
+            },
+            (opcode @ INEG..=DNEG, _) => self.unary(frame, numeric(opcode - INEG), numeric(opcode - INEG))?,
+            (opcode @ ISHL..=LXOR, _) => {
+                let operand = if (opcode - ISHL).is_multiple_of(2) { Type::Int } else { Type::Long };
+                let right = if opcode <= LUSHR { Type::Int } else { operand.clone() };
+                self.binary(frame, operand.clone(), right, operand)?;
+            },
+            (IINC, &Operand::Increment{local, ..}) => {
+                let value = frame.local(local as usize)?;
+                if *value != Type::Int {
+                    return Err(Problem::Mismatch{expected: Type::Int, actual: value.clone(), slot: Slot::Local(local as usize)});
+                }
+            },
+            (opcode @ I2L..=D2F, _) => {
+                // Each type converts to the other three in turn.
+                let from = (opcode - I2L) / 3;
+                let to = [1, 2, 3, 0, 2, 3, 0, 1, 3, 0, 1, 2][(opcode - I2L) as usize];
+                self.unary(frame, numeric(from), numeric(to))?;
+            },
+            (I2B | I2C | I2S, _) => self.unary(frame, Type::Int, Type::Int)?,
+            (LCMP, _) => self.binary(frame, Type::Long, Type::Long, Type::Int)?,
+            (FCMPL | FCMPG, _) => self.binary(frame, Type::Float, Type::Float, Type::Int)?,
+            (DCMPL | DCMPG, _) => self.binary(frame, Type::Double, Type::Double, Type::Int)?,
+            (IFEQ..=IFLE | TABLESWITCH | LOOKUPSWITCH, _) => {
+                self.pop_expecting(frame, &Type::Int)?;
+            },
+            (IF_ICMPEQ..=IF_ICMPLE, _) => {
+                self.pop_expecting(frame, &Type::Int)?;
+                self.pop_expecting(frame, &Type::Int)?;
+            },
+            (IF_ACMPEQ | IF_ACMPNE, _) => {
+                self.pop_reference(frame)?;
+                self.pop_reference(frame)?;
+            },
+            (IFNULL | IFNONNULL | MONITORENTER | MONITOREXIT, _) => {
+                self.pop_reference(frame)?;
+            },
+            (opcode @ IRETURN..=RETURN, _) => {
+                let return_type = self.descriptor.return_type.as_ref().map(Type::from_field_type);
+                let matches = match return_type {
+                    None => opcode == RETURN,
+                    Some(ref value) if value.is_reference() => opcode == ARETURN,
+                    Some(ref value) => opcode != ARETURN && opcode != RETURN && *value == numeric(opcode - IRETURN),
+                };
+                if !matches {
+                    return Err(Problem::WrongReturn(return_type));
+                }
+                if let Some(ref value) = return_type {
+                    self.pop_expecting(frame, value)?;
+                }
+                if frame.this_uninitialized {
+                    return Err(Problem::ThisUninitialized);
+                }
+            },
+            (opcode @ GETSTATIC..=PUTFIELD, &Operand::Constant(index)) => {
+                let field = class.member_ref(&ConstantIndex(index))?;
+                let value = Type::from_field_type(&FieldType::parse(field.descriptor)?);
+                let owner = Type::Reference(field.class.to_string());
+                match opcode {
+                    GETSTATIC => frame.push(value, max_stack)?,
+                    PUTSTATIC => {
+                        self.pop_expecting(frame, &value)?;
+                    },
+                    GETFIELD => {
+                        self.pop_expecting(frame, &owner)?;
+                        frame.push(value, max_stack)?;
+                    },
+                    _ => {
+                        self.pop_expecting(frame, &value)?;
+                        // A constructor may set its own class's fields before calling another
+                        // constructor, as inner classes do to store their outer instance.
+                        let object = frame.pop()?;
+                        let own_field = object == Type::UninitializedThis && field.class == self.class_name;
+                        if !own_field && !self.is_assignable(&object, &owner)? {
+                            return Err(Problem::Mismatch{expected: owner, actual: object, slot: Slot::Stack(frame.stack.len())});
+                        }
+                    },
+                }
+            },
+            (opcode @ INVOKEVIRTUAL..=INVOKEINTERFACE, &Operand::Constant(index)) => self.invoke(frame, opcode, index)?,
+            (INVOKEDYNAMIC, &Operand::Constant(index)) => {
+                let name_and_type = match *ConstantIndex(index).lookup(&class.constants)? {
+                    Constant::InvokeDynamicInfo{ref name_and_type, ..} => name_and_type,
+                    _ => return Err(ConstantLookupError::UnexpectedType(index).into()),
+                };
+                let descriptor = MethodDescriptor::parse(class.name_and_type(name_and_type)?.1)?;
+                self.pop_arguments(frame, &descriptor)?;
+                if let Some(ref return_type) = descriptor.return_type {
+                    frame.push(Type::from_field_type(return_type), max_stack)?;
+                }
+            },
+            (NEW, &Operand::Constant(index)) => {
+                let name = class.class_name(&ConstantIndex(index))?;
+                if name.starts_with('[') {
+                    return Err(Problem::NewArray(name.to_string()));
+                }
+                // An object left over from the last time round a loop can't be constructed now.
+                let created = Type::Uninitialized(instruction.pc);
+                for local in &mut frame.locals {
+                    if *local == created {
+                        *local = Type::Top;
+                    }
+                }
+                frame.push(created, max_stack)?;
+            },
+            (NEWARRAY, &Operand::Int(array_type)) => {
+                let descriptor = match array_type {
+                    4 => "[Z",
+                    5 => "[C",
+                    6 => "[F",
+                    7 => "[D",
+                    8 => "[B",
+                    9 => "[S",
+                    10 => "[I",
+                    11 => "[J",
+                    _ => return Err(Problem::BadArrayType(array_type)),
+                };
+                self.unary(frame, Type::Int, Type::Reference(descriptor.to_string()))?;
+            },
+            (ANEWARRAY, &Operand::Constant(index)) => {
+                let name = class.class_name(&ConstantIndex(index))?;
+                self.unary(frame, Type::Int, Type::Reference(array_of(name)))?;
+            },
+            (ARRAYLENGTH, _) => {
+                let array = frame.pop()?;
+                if array != Type::Null && !array.is_array() {
+                    return Err(Problem::NotArray{actual: array, slot: Slot::Stack(frame.stack.len())});
+                }
+                frame.push(Type::Int, max_stack)?;
+            },
+            (ATHROW, _) => {
+                self.pop_expecting(frame, &Type::Reference("java/lang/Throwable".to_string()))?;
+            },
+            (CHECKCAST, &Operand::Constant(index)) => {
+                self.pop_reference(frame)?;
+                frame.push(Type::Reference(class.class_name(&ConstantIndex(index))?.to_string()), max_stack)?;
+            },
+            (INSTANCEOF, _) => {
+                self.pop_reference(frame)?;
+                frame.push(Type::Int, max_stack)?;
+            },
+            (MULTIANEWARRAY, &Operand::MultiArray{class: index, dimensions}) => {
+                let name = class.class_name(&ConstantIndex(index))?;
+                if dimensions == 0 || name.chars().take_while(|&c| c == '[').count() < dimensions as usize {
+                    return Err(Problem::BadDimensions(dimensions));
+                }
+                for _ in 0..dimensions {
+                    self.pop_expecting(frame, &Type::Int)?;
+                }
+                frame.push(Type::Reference(name.to_string()), max_stack)?;
+            },
+            (opcode, _) => unreachable!("Decoded instruction {:?} with unexpected operand {:?}", mnemonic(opcode), instruction.operand),
+        }
+        Ok(())
+    }
+
+    fn constant_type(&self, index: u16) -> Result<Type, Problem> {
+        let reference = |name: &str| Ok(Type::Reference(name.to_string()));
+        match *ConstantIndex(index).lookup(&self.class.constants)? {
+            Constant::Integer(_) => Ok(Type::Int),
+            Constant::Float(_) => Ok(Type::Float),
+            Constant::Long(_) => Ok(Type::Long),
+            Constant::Double(_) => Ok(Type::Double),
+            Constant::StringRef(_) => reference("java/lang/String"),
+            Constant::ClassRef(_) => reference("java/lang/Class"),
+            Constant::MethodType(_) => reference("java/lang/invoke/MethodType"),
+            Constant::MethodHandleRef(_) => reference("java/lang/invoke/MethodHandle"),
+            Constant::Dynamic{ref name_and_type, ..} => Ok(Type::from_field_type(&FieldType::parse(self.class.name_and_type(name_and_type)?.1)?)),
+            _ => Err(Problem::BadConstant(index)),
+        }
+    }
+
+    fn pop_arguments(&mut self, frame: &mut Frame, descriptor: &MethodDescriptor) -> Result<(), Problem> {
+        for parameter in descriptor.parameters.iter().rev() {
+            self.pop_expecting(frame, &Type::from_field_type(parameter))?;
+        }
+        Ok(())
+    }
+
+    // Only invokespecial may call constructors, and nothing may call static initializers. The
+    // receiver of an invokespecial that isn't a constructor call must be of the current class.
+    fn invoke(&mut self, frame: &mut Frame, opcode: u8, index: u16) -> Result<(), Problem> {
+        let class = self.class;
+        let method = class.member_ref(&ConstantIndex(index))?;
+        let descriptor = MethodDescriptor::parse(method.descriptor)?;
+        let is_constructor = method.name == "<init>";
+        if method.name.starts_with('<') && !(is_constructor && opcode == INVOKESPECIAL) {
+            return Err(Problem::IllegalCall(method.name.to_string()));
+        }
+        self.pop_arguments(frame, &descriptor)?;
+        if is_constructor {
+            let object = frame.pop()?;
+            self.construct(frame, object, method.class)?;
+        } else if opcode != INVOKESTATIC {
+            let receiver = if opcode == INVOKESPECIAL { self.class_name } else { method.class };
+            self.pop_expecting(frame, &Type::Reference(receiver.to_string()))?;
+        }
+        if let Some(ref return_type) = descriptor.return_type {
+            frame.push(Type::from_field_type(return_type), self.code.max_stack as usize)?;
+        }
+        Ok(())
+    }
+
+    // Calling a constructor initializes every copy of the object. An object created by new must
+    // be constructed by its own class, and a constructor's receiver by its own class or its
+    // superclass.
+    fn construct(&mut self, frame: &mut Frame, object: Type, owner: &str) -> Result<(), Problem> {
+        let valid = match object {
+            Type::Uninitialized(pc) => match bytecode::decode_at(self.code.code, pc) {
+                Ok(Instruction {opcode: NEW, operand: Operand::Constant(index), ..}) => self.class.class_name(&ConstantIndex(index))? == owner,
+                _ => false,
+            },
+            Type::UninitializedThis => owner == self.class_name || self.class.super_class_name()? == Some(owner),
+            _ => false,
+        };
+        if !valid {
+            return Err(Problem::BadConstructorCall{class: owner.to_string(), object});
+        }
+        let constructed = match object {
+            Type::UninitializedThis => {
+                frame.this_uninitialized = false;
+                Type::Reference(self.class_name.to_string())
+            },
+            _ => Type::Reference(owner.to_string()),
+        };
+        frame.replace(&object, &constructed);
+        Ok(())
+    }
+}
+
+// The class followed by its superclasses, or just java/lang/Object for an interface.
+fn superclasses(name: &str, hierarchy: &mut dyn Hierarchy) -> Result<Vec<String>, Problem> {
+    let mut chain = vec![];
+    let mut current = Some(name.to_string());
+    while let Some(name) = current {
+        let info = hierarchy.lookup(&name).ok_or_else(|| Problem::UnknownClass(name.clone()))?;
+        if info.is_interface {
+            return Ok(vec!["java/lang/Object".to_string()]);
+        }
+        current = info.super_class;
+        chain.push(name);
+    }
+    Ok(chain)
+}
+
+// Whether a value of one type may be used where the other is expected. Anything may be used as
+// top, and any reference may be assigned to an interface type, which is checked at run time
+// instead.
+pub fn is_assignable(value: &Type, to: &Type, hierarchy: &mut dyn Hierarchy) -> Result<bool, Problem> {
+    match (value, to) {
+        _ if value == to => Ok(true),
+        (_, &Type::Top) => Ok(true),
+        (&Type::Null, &Type::Reference(_)) => Ok(true),
+        (Type::Reference(from), Type::Reference(to_name)) => {
+            if to_name == "java/lang/Object" {
+                return Ok(true);
+            }
+            match (value.component(), to.component()) {
+                (Some(from), Some(to)) => return Ok(from.is_reference() && to.is_reference() && is_assignable(&from, &to, hierarchy)?),
+                (None, Some(_)) => return Ok(false),
+                _ => (),
+            }
+            if hierarchy.lookup(to_name).ok_or_else(|| Problem::UnknownClass(to_name.clone()))?.is_interface {
+                return Ok(true);
+            }
+            Ok(!value.is_array() && superclasses(from, hierarchy)?.contains(to_name))
+        },
+        _ => Ok(false),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum InferenceError {
+    Bytecode{method: String, cause: BytecodeError},
+    ConstantLookup(ConstantLookupError),
+    Descriptor(DescriptorError),
+    Rejected(Box<Rejection>),
+}
+
+// Where verification failed and why, with the types the verifier had inferred by then.
+#[derive(Debug, PartialEq)]
+pub struct Rejection {
+    pub class: String,
+    pub method: String, // The name and descriptor.
+    pub pc: usize,
+    pub instruction: Option<&'static str>, // The mnemonic, unless pc isn't an instruction start.
+    pub problem: Problem,
+    pub frame: Option<Frame>, // On entry to the instruction, if it was reached.
+}
+
+// What's wrong with the instruction at which verification failed.
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+    BadArrayType(i32),
+    BadConstant(u16),
+    BadConstructorCall{class: String, object: Type},
+    BadDimensions(u8),
+    ConstantLookup(ConstantLookupError),
+    Descriptor(DescriptorError),
+    FallsOffEnd,
+    IllegalCall(String),
+    IncompatibleStack{target: usize, index: usize, expected: Type, actual: Type},
+    InvalidLocal(usize),
+    InvalidTarget(usize),
+    Mismatch{expected: Type, actual: Type, slot: Slot},
+    NewArray(String),
+    NotArray{actual: Type, slot: Slot},
+    NotReference{actual: Type, slot: Slot},
+    NotReturnAddress{actual: Type, slot: Slot},
+    RecursiveSubroutine(usize),
+    SplitValue,
+    StackHeight{target: usize, expected: usize, actual: usize},
+    StackOverflow,
+    StackUnderflow,
+    ThisUninitialized,
+    UnknownClass(String),
+    WrongReturn(Option<Type>),
+}
+
+impl std::convert::From<ConstantLookupError> for InferenceError {
+    fn from(err: ConstantLookupError) -> InferenceError {
+        InferenceError::ConstantLookup(err)
+    }
+}
+
+impl std::convert::From<DescriptorError> for InferenceError {
+    fn from(err: DescriptorError) -> InferenceError {
+        InferenceError::Descriptor(err)
+    }
+}
+
+impl std::convert::From<ConstantLookupError> for Problem {
+    fn from(err: ConstantLookupError) -> Problem {
+        Problem::ConstantLookup(err)
+    }
+}
+
+impl std::convert::From<DescriptorError> for Problem {
+    fn from(err: DescriptorError) -> Problem {
+        Problem::Descriptor(err)
+    }
+}
+
+impl fmt::Display for InferenceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InferenceError::Bytecode{ref method, ref cause} => write!(f, "Invalid code in method {}: {}", method, cause),
+            InferenceError::ConstantLookup(ref cause) => write!(f, "Invalid constant pool: {}", cause),
+            InferenceError::Descriptor(ref cause) => write!(f, "Invalid descriptor: {}", cause),
+            InferenceError::Rejected(ref rejection) => write!(f, "{}", rejection),
+        }
+    }
+}
+
+impl error::Error for InferenceError {
+    fn description(&self) -> &str {
+        match *self {
+            InferenceError::Bytecode{..} => "Invalid code",
+            InferenceError::ConstantLookup(_) => "Invalid constant pool",
+            InferenceError::Descriptor(_) => "Invalid descriptor",
+            InferenceError::Rejected(ref rejection) => rejection.problem.summary(),
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            InferenceError::Bytecode{ref cause, ..} => Some(cause),
+            InferenceError::ConstantLookup(ref cause) => Some(cause),
+            InferenceError::Descriptor(ref cause) => Some(cause),
+            InferenceError::Rejected(ref rejection) => Some(&rejection.problem),
+        }
+    }
+}
+
+impl Problem {
+    // A headline for the problem, in the same terms HotSpot uses for a VerifyError.
+    pub fn summary(&self) -> &'static str {
+        match *self {
+            Problem::BadArrayType(_) => "Illegal newarray type",
+            Problem::BadConstant(_) => "Illegal constant",
+            Problem::BadConstructorCall{..} => "Bad constructor call",
+            Problem::BadDimensions(_) => "Illegal dimension count",
+            Problem::ConstantLookup(_) => "Invalid constant pool",
+            Problem::Descriptor(_) => "Invalid descriptor",
+            Problem::FallsOffEnd => "Falling off the end of the code",
+            Problem::IllegalCall(_) => "Illegal call",
+            Problem::IncompatibleStack{..} => "Inconsistent stackmap frames at branch target",
+            Problem::InvalidLocal(_) => "Illegal local variable number",
+            Problem::InvalidTarget(_) => "Illegal target of jump or branch",
+            Problem::Mismatch{slot: Slot::Local(_), ..} => "Bad local variable type",
+            Problem::Mismatch{slot: Slot::Stack(_), ..} => "Bad type on operand stack",
+            Problem::NewArray(_) => "Illegal use of new",
+            Problem::NotArray{..} => "Bad type on operand stack",
+            Problem::NotReference{slot: Slot::Local(_), ..} => "Bad local variable type",
+            Problem::NotReference{slot: Slot::Stack(_), ..} => "Bad type on operand stack",
+            Problem::NotReturnAddress{..} => "Bad local variable type",
+            Problem::RecursiveSubroutine(_) => "Recursive subroutine call",
+            Problem::SplitValue => "Attempt to split a long or double",
+            Problem::StackHeight{..} => "Inconsistent stack height",
+            Problem::StackOverflow => "Operand stack overflow",
+            Problem::StackUnderflow => "Operand stack underflow",
+            Problem::ThisUninitialized => "Constructor must call super() or this() before return",
+            Problem::UnknownClass(_) => "Unknown class",
+            Problem::WrongReturn(_) => "Bad return type",
+        }
+    }
+}
+
+// Laid out like HotSpot's detailed VerifyError messages.
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.problem.summary())?;
+        writeln!(f, "Exception Details:")?;
+        writeln!(f, "  Location:")?;
+        match self.instruction {
+            Some(mnemonic) => writeln!(f, "    {}.{} @{}: {}", self.class, self.method, self.pc, mnemonic)?,
+            None => writeln!(f, "    {}.{} @{}", self.class, self.method, self.pc)?,
+        }
+        writeln!(f, "  Reason:")?;
+        write!(f, "    {}", self.problem)?;
+        if let Some(ref frame) = self.frame {
+            let list = |types: &[Type]| types.iter().map(|value| format!(" {}", value)).collect::<Vec<_>>().join(",");
+            writeln!(f)?;
+            writeln!(f, "  Current Frame:")?;
+            writeln!(f, "    bci: @{}", self.pc)?;
+            writeln!(f, "    flags: {{ {}}}", if frame.this_uninitialized { "flagThisUninit " } else { "" })?;
+            writeln!(f, "    locals: {{{} }}", list(&frame.locals))?;
+            write!(f, "    stack: {{{} }}", list(&frame.stack))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Problem::BadArrayType(array_type) => write!(f, "Illegal newarray type {}", array_type),
+            Problem::BadConstant(index) => write!(f, "Constant {} can't be loaded by this instruction", index),
+            Problem::BadConstructorCall{ref class, ref object} => write!(f, "Bad constructor call: {} can't construct {}", class, object),
+            Problem::BadDimensions(dimensions) => write!(f, "Illegal dimension count {} for multianewarray", dimensions),
+            Problem::ConstantLookup(ref cause) => write!(f, "Invalid constant pool: {}", cause),
+            Problem::Descriptor(ref cause) => write!(f, "Invalid descriptor: {}", cause),
+            Problem::FallsOffEnd => write!(f, "Falling off the end of the code"),
+            Problem::IllegalCall(ref name) => write!(f, "Illegal call to {}", name),
+            Problem::IncompatibleStack{target, index, ref expected, ref actual} => write!(f, "Type {} (stack[{}]) is not compatible with {} in the frame at {}", actual, index, expected, target),
+            Problem::InvalidLocal(index) => write!(f, "Illegal local variable number {}", index),
+            Problem::InvalidTarget(pc) => write!(f, "Illegal target of jump or branch {}", pc),
+            Problem::Mismatch{ref expected, ref actual, slot} => write!(f, "Type {} ({}) is not assignable to {}", actual, slot, expected),
+            Problem::NewArray(ref name) => write!(f, "Illegal use of new with array class {}", name),
+            Problem::NotArray{ref actual, slot} => write!(f, "Type {} ({}) is not an array", actual, slot),
+            Problem::NotReference{ref actual, slot} => write!(f, "Type {} ({}) is not a reference", actual, slot),
+            Problem::NotReturnAddress{ref actual, slot} => write!(f, "Type {} ({}) is not a return address", actual, slot),
+            Problem::RecursiveSubroutine(pc) => write!(f, "Recursive call to subroutine at pc {}", pc),
+            Problem::SplitValue => write!(f, "Attempt to split a long or double"),
+            Problem::StackHeight{target, expected, actual} => write!(f, "Stack height {} doesn't match {} in the frame at {}", actual, expected, target),
+            Problem::StackOverflow => write!(f, "Exceeded max stack size"),
+            Problem::StackUnderflow => write!(f, "Attempt to pop empty stack"),
+            Problem::ThisUninitialized => write!(f, "Constructor must call super() or this() before return"),
+            Problem::UnknownClass(ref name) => write!(f, "Unknown class {}", name),
+            Problem::WrongReturn(None) => write!(f, "Method returning void returns a value"),
+            Problem::WrongReturn(Some(ref expected)) => write!(f, "Wrong return instruction for a method returning {}", expected),
+        }
+    }
+}
+
+impl error::Error for Problem {
+    fn description(&self) -> &str {
+        self.summary()
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            Problem::BadArrayType(_) => None,
+            Problem::BadConstant(_) => None,
+            Problem::BadConstructorCall{..} => None,
+            Problem::BadDimensions(_) => None,
+            Problem::ConstantLookup(ref cause) => Some(cause),
+            Problem::Descriptor(ref cause) => Some(cause),
+            Problem::FallsOffEnd => None,
+            Problem::IllegalCall(_) => None,
+            Problem::IncompatibleStack{..} => None,
+            Problem::InvalidLocal(_) => None,
+            Problem::InvalidTarget(_) => None,
+            Problem::Mismatch{..} => None,
+            Problem::NewArray(_) => None,
+            Problem::NotArray{..} => None,
+            Problem::NotReference{..} => None,
+            Problem::NotReturnAddress{..} => None,
+            Problem::RecursiveSubroutine(_) => None,
+            Problem::SplitValue => None,
+            Problem::StackHeight{..} => None,
+            Problem::StackOverflow => None,
+            Problem::StackUnderflow => None,
+            Problem::ThisUninitialized => None,
+            Problem::UnknownClass(_) => None,
+            Problem::WrongReturn(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::parsed_fixture;
+    use crate::classloader;
+
+    // Answers from the bootstrap classes and the given classes.
+    fn hierarchy(classes: &[&Class]) -> impl Hierarchy {
+        let bootstrap: Vec<Class> = crate::bootstrap::CLASSES.iter().map(|&(_, bytes)| classloader::load_class(bytes).unwrap()).collect();
+        let known: HashMap<String, ClassInfo> = bootstrap.iter().chain(classes.iter().copied())
+            .map(|class| (class.name().unwrap().to_string(), ClassInfo::of(class).unwrap()))
+            .collect();
+        move |name: &str| known.get(name).cloned()
+    }
+
+    fn method<'a>(class: &'a Class, name: &str) -> &'a Method {
+        class.methods.iter().find(|method| class.utf8(&method.name).unwrap() == name).unwrap()
+    }
+
+    fn frames(class: &Class, name: &str) -> BTreeMap<usize, Frame> {
+        infer_frames(class, method(class, name), &mut hierarchy(&[class])).unwrap()
+    }
+
+    #[test]
+    fn test_compiled_classes_verify() {
+        let classes = [parsed_fixture!("Exceptions"), parsed_fixture!("Boom"), parsed_fixture!("Bang"), parsed_fixture!("Dispatch"), parsed_fixture!("Arrays"), parsed_fixture!("Switches"), parsed_fixture!("Subroutines"), parsed_fixture!("Legacy")];
+        let mut hierarchy = hierarchy(&classes.iter().collect::<Vec<_>>());
+        for class in &classes {
+            assert_eq!(Ok(()), verify_class(class, &mut hierarchy));
+        }
+        for &(_, bytes) in crate::bootstrap::CLASSES {
+            assert_eq!(Ok(()), verify_class(&classloader::load_class(bytes).unwrap(), &mut hierarchy));
+        }
+    }
+
+    #[test]
+    fn test_subroutines_keep_their_callers_locals() {
+        let frames = frames(&parsed_fixture!("Legacy"), "twoCallers");
+        // The subroutine is called with an int in local 1 and then with a float there.
+        assert_eq!(vec![Type::ReturnAddress(17), Type::Top, Type::Top], frames[&18].locals);
+        assert_eq!(vec![Type::ReturnAddress(17), Type::Int, Type::Top], frames[&5].locals);
+        assert_eq!(vec![Type::ReturnAddress(17), Type::Float, Type::Int], frames[&12].locals);
+    }
+
+    #[test]
+    fn test_handlers_start_with_the_exception() {
+        let frames = frames(&parsed_fixture!("Legacy"), "finallyIncrements");
+        assert_eq!(vec![Type::Reference("java/lang/Throwable".to_string())], frames[&9].stack);
+        assert_eq!(vec![Type::Int, Type::Top, Type::Reference("java/lang/Throwable".to_string()), Type::Top], frames[&10].locals);
+        assert_eq!(Type::ReturnAddress(15), frames[&24].locals[3]);
+    }
+
+    #[test]
+    fn test_invalid_code_is_rejected() {
+        let class = parsed_fixture!("Unverifiable");
+        let rejected = |name: &str, pc: usize, problem: Problem| {
+            let signature = format!("{}{}", name, class.utf8(&method(&class, name).descriptor).unwrap());
+            match infer_frames(&class, method(&class, name), &mut hierarchy(&[&class])) {
+                Err(InferenceError::Rejected(rejection)) => assert_eq!((signature, pc, problem), (rejection.method, rejection.pc, rejection.problem)),
+                other => panic!("Expected {} to be rejected, got {:?}", name, other),
+            }
+        };
+        rejected("loadsFloatAsInt", 2, Problem::Mismatch{expected: Type::Int, actual: Type::Float, slot: Slot::Local(0)});
+        rejected("retWithoutJsr", 2, Problem::NotReturnAddress{actual: Type::Int, slot: Slot::Local(0)});
+        rejected("recursiveSubroutine", 5, Problem::RecursiveSubroutine(4));
+        rejected("mergesIntAndFloat", 8, Problem::IncompatibleStack{target: 9, index: 0, expected: Type::Int, actual: Type::Float});
+        rejected("fallsOffEnd", 0, Problem::FallsOffEnd);
+        rejected("splitsLong", 1, Problem::SplitValue);
+        rejected("usesUninitialized", 3, Problem::Mismatch{expected: Type::Reference("java/lang/Object".to_string()), actual: Type::Uninitialized(0), slot: Slot::Stack(0)});
+        rejected("overflowsStack", 0, Problem::StackOverflow);
+    }
+
+    #[test]
+    fn test_constructors_must_call_another() {
+        let mut class = parsed_fixture!("Boom"); // Its only method is its constructor.
+        for attribute in &mut class.methods[0].attributes {
+            if let Attribute::Code(ref mut body) = *attribute {
+                body.code = vec![RETURN];
+            }
+        }
+        let error = verify_class(&class, &mut hierarchy(&[]));
+        assert!(matches!(error, Err(InferenceError::Rejected(ref rejection)) if rejection.pc == 0 && rejection.problem == Problem::ThisUninitialized), "{:?}", error);
+    }
+
+    #[test]
+    fn test_rejections_show_the_frame() {
+        let class = parsed_fixture!("Unverifiable");
+        let error = infer_frames(&class, method(&class, "loadsFloatAsInt"), &mut hierarchy(&[&class])).unwrap_err();
+        assert_eq!(concat!(
+            "Bad local variable type\n",
+            "Exception Details:\n",
+            "  Location:\n",
+            "    Unverifiable.loadsFloatAsInt()I @2: iload_0\n",
+            "  Reason:\n",
+            "    Type float (locals[0]) is not assignable to integer\n",
+            "  Current Frame:\n",
+            "    bci: @2\n",
+            "    flags: { }\n",
+            "    locals: { float }\n",
+            "    stack: { }",
+        ), error.to_string());
+
+        // Subroutines are checked before any frames are inferred.
+        let error = infer_frames(&class, method(&class, "recursiveSubroutine"), &mut hierarchy(&[&class])).unwrap_err();
+        assert_eq!(concat!(
+            "Recursive subroutine call\n",
+            "Exception Details:\n",
+            "  Location:\n",
+            "    Unverifiable.recursiveSubroutine()V @5: jsr\n",
+            "  Reason:\n",
+            "    Recursive call to subroutine at pc 4",
+        ), error.to_string());
+    }
+}
@@ -0,0 +1,536 @@
+use std::{error, fmt};
+
+// The most that DEFLATE can expand its input: a 258-byte match takes as little as two bits.
+const MAX_EXPANSION: usize = 1032;
+
+// Decompresses raw DEFLATE data (RFC 1951), as stored in ZIP and JAR entries. This favours
+// simplicity over speed: Huffman codes are decoded a bit at a time, in the style of zlib's puff.
+// The expected size is only a hint, since it comes from the archive: room is made for no more
+// than the data could inflate to.
+pub fn inflate(data: &[u8], expected_size: usize) -> Result<Vec<u8>, InflateError> {
+    let capacity = expected_size.min(data.len().saturating_mul(MAX_EXPANSION));
+    let mut inflater = Inflater {input: BitReader {data, position: 0, bit_buffer: 0, bit_count: 0}, output: Vec::with_capacity(capacity)};
+    while !inflater.block()? {}
+    Ok(inflater.output)
+}
+
+// Inflation that continues as more input arrives, for java.util.zip.Inflater. Whole blocks are
+// decoded at a time, as soon as their input is all there: a block cut off by the end of the input
+// is decoded again from its start once more arrives. Unless the stream is raw, it's wrapped as a
+// zlib stream (RFC 1950), whose header and Adler-32 trailer are checked.
+pub struct InflateStream {
+    wrapped: bool,
+    state: StreamState,
+    input: Vec<u8>, // From the start of the next block, or the input left over after the stream.
+    position: usize,
+    bit_buffer: u32,
+    bit_count: u32,
+    output: Vec<u8>, // What hasn't been taken yet, after the history that back references may use.
+    taken: usize,
+    error: Option<InflateError>,
+    adler: u32,
+    total_in: u64,
+    total_out: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum StreamState {
+    Header,
+    Blocks,
+    Trailer,
+    Done,
+}
+
+// How far back a back reference may reach, and so how much taken output must be kept.
+const WINDOW_SIZE: usize = 32768;
+
+impl InflateStream {
+    pub fn new(wrapped: bool) -> InflateStream {
+        InflateStream {
+            wrapped,
+            state: if wrapped { StreamState::Header } else { StreamState::Blocks },
+            input: vec![],
+            position: 0,
+            bit_buffer: 0,
+            bit_count: 0,
+            output: vec![],
+            taken: 0,
+            error: None,
+            adler: 1,
+            total_in: 0,
+            total_out: 0,
+        }
+    }
+
+    // Adds input and decodes as much of it as possible. Input given once the stream has ended
+    // replaces what was left over, as Inflater.setInput does.
+    pub fn add_input(&mut self, data: &[u8]) {
+        if self.state == StreamState::Done {
+            self.total_in -= self.remaining() as u64;
+            self.input.clear();
+            self.position = 0;
+        } else {
+            self.input.drain(..self.position);
+            self.position = 0;
+        }
+        self.input.extend_from_slice(data);
+        self.total_in += data.len() as u64;
+        if self.error.is_none() {
+            if let Err(err) = self.decode() {
+                self.error = Some(err);
+            }
+        }
+    }
+
+    fn decode(&mut self) -> Result<(), InflateError> {
+        loop {
+            let input = BitReader {data: &self.input, position: self.position, bit_buffer: self.bit_buffer, bit_count: self.bit_count};
+            let start = self.output.len();
+            let mut inflater = Inflater {input, output: std::mem::take(&mut self.output)};
+            let result = match self.state {
+                StreamState::Header => inflater.zlib_header().map(|()| StreamState::Blocks),
+                StreamState::Blocks => match inflater.block() {
+                    Ok(false) => Ok(StreamState::Blocks),
+                    Ok(true) if self.wrapped => Ok(StreamState::Trailer),
+                    Ok(true) => {
+                        inflater.input.align_to_byte();
+                        Ok(StreamState::Done)
+                    },
+                    Err(err) => Err(err),
+                },
+                StreamState::Trailer => {
+                    inflater.input.align_to_byte();
+                    inflater.input.bytes(4).and_then(|trailer| match u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]) {
+                        adler if adler == self.adler => Ok(StreamState::Done),
+                        _ => Err(InflateError::ChecksumMismatch),
+                    })
+                },
+                StreamState::Done => Ok(StreamState::Done),
+            };
+
+            let Inflater {input, mut output} = inflater;
+            match result {
+                Ok(state) => {
+                    self.adler = adler32(self.adler, &output[start..]);
+                    self.position = input.position;
+                    self.bit_buffer = input.bit_buffer;
+                    self.bit_count = input.bit_count;
+                    self.output = output;
+                    if self.state == StreamState::Done {
+                        return Ok(());
+                    }
+                    self.state = state;
+                },
+                Err(err) => {
+                    output.truncate(start);
+                    self.output = output;
+                    return if err == InflateError::Truncated { Ok(()) } else { Err(err) };
+                },
+            }
+        }
+    }
+
+    // Copies out as much decoded output as fits, or fails once all the output before a problem
+    // with the input has been taken.
+    pub fn take(&mut self, buffer: &mut [u8]) -> Result<usize, InflateError> {
+        let available = &self.output[self.taken..];
+        let count = available.len().min(buffer.len());
+        if count == 0 && !buffer.is_empty() {
+            if let Some(err) = self.error.clone() {
+                return Err(err);
+            }
+        }
+        buffer[..count].copy_from_slice(&available[..count]);
+        self.taken += count;
+        self.total_out += count as u64;
+        if self.taken > 2 * WINDOW_SIZE {
+            let excess = self.taken - WINDOW_SIZE;
+            self.output.drain(..excess);
+            self.taken -= excess;
+        }
+        Ok(count)
+    }
+
+    // Whether the end of the stream has been reached and all the output taken.
+    pub fn finished(&self) -> bool {
+        self.state == StreamState::Done && self.taken == self.output.len()
+    }
+
+    // Whether everything that can be decoded so far has been taken, so more input is needed.
+    pub fn needs_input(&self) -> bool {
+        self.state != StreamState::Done && self.error.is_none() && self.taken == self.output.len()
+    }
+
+    // The number of input bytes after the end of the stream.
+    pub fn remaining(&self) -> usize {
+        match self.state {
+            StreamState::Done => self.input.len() - self.position,
+            _ => 0,
+        }
+    }
+
+    // The Adler-32 checksum of the output so far.
+    pub fn adler(&self) -> u32 {
+        self.adler
+    }
+
+    // The number of input bytes used, and of output bytes taken.
+    pub fn totals(&self) -> (u64, u64) {
+        (self.total_in - self.remaining() as u64, self.total_out)
+    }
+}
+
+// Updates an Adler-32 checksum (RFC 1950) with more data. A new checksum starts at 1.
+pub fn adler32(adler: u32, data: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    let (mut a, mut b) = (adler & 0xffff, adler >> 16);
+    // Sums are only reduced every few thousand bytes, as far as they can go without overflowing.
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MODULUS;
+        b %= MODULUS;
+    }
+    (b << 16) | a
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum InflateError {
+    Truncated,
+    InvalidHeader, // Not a zlib stream, or one that needs a preset dictionary.
+    ChecksumMismatch,
+    InvalidBlockType,
+    InvalidStoredLength,
+    InvalidCode,
+    InvalidCodeLengths,
+    InvalidDistance(usize), // A back reference to before the start of the output.
+}
+
+impl fmt::Display for InflateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InflateError::Truncated => write!(f, "Compressed data ends unexpectedly"),
+            InflateError::InvalidHeader => write!(f, "Incorrect header check"),
+            InflateError::ChecksumMismatch => write!(f, "Incorrect data check"),
+            InflateError::InvalidBlockType => write!(f, "Invalid block type"),
+            InflateError::InvalidStoredLength => write!(f, "Stored block length doesn't match its complement"),
+            InflateError::InvalidCode => write!(f, "Invalid Huffman code"),
+            InflateError::InvalidCodeLengths => write!(f, "Invalid Huffman code lengths"),
+            InflateError::InvalidDistance(distance) => write!(f, "Distance {} reaches before the start of the data", distance),
+        }
+    }
+}
+
+impl error::Error for InflateError {
+    fn description(&self) -> &str {
+        match *self {
+            InflateError::Truncated => "Compressed data ends unexpectedly",
+            InflateError::InvalidHeader => "Incorrect header check",
+            InflateError::ChecksumMismatch => "Incorrect data check",
+            InflateError::InvalidBlockType => "Invalid block type",
+            InflateError::InvalidStoredLength => "Invalid stored block length",
+            InflateError::InvalidCode => "Invalid Huffman code",
+            InflateError::InvalidCodeLengths => "Invalid Huffman code lengths",
+            InflateError::InvalidDistance(_) => "Invalid distance",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        None
+    }
+}
+
+const MAX_BITS: usize = 15;
+
+// Base values and extra bits for length codes 257..285 and distance codes 0..29.
+pub const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+pub const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+pub const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+pub const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+// The order in which a dynamic block lists the lengths of the code length code.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+impl BitReader<'_> {
+    // Reads bits least significant first, as DEFLATE packs them.
+    fn bits(&mut self, count: u32) -> Result<u32, InflateError> {
+        while self.bit_count < count {
+            let byte = *self.data.get(self.position).ok_or(InflateError::Truncated)?;
+            self.position += 1;
+            self.bit_buffer |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buffer & ((1u64 << count) - 1) as u32;
+        self.bit_buffer >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&[u8], InflateError> {
+        let bytes = self.data.get(self.position..self.position + count).ok_or(InflateError::Truncated)?;
+        self.position += count;
+        Ok(bytes)
+    }
+}
+
+// A canonical Huffman code, as the number of codes of each length and the symbols in code order.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, InflateError> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+
+        // Reject codes with more codes of some length than there's room for. Incomplete codes are
+        // allowed, since a block may use only one distance code.
+        let mut left: i32 = 1;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(InflateError::InvalidCodeLengths);
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for length in 1..=MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate().filter(|&(_, &length)| length != 0) {
+            symbols[offsets[length as usize] as usize] = symbol as u16;
+            offsets[length as usize] += 1;
+        }
+        Ok(Huffman {counts, symbols})
+    }
+
+    fn decode(&self, input: &mut BitReader) -> Result<u16, InflateError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= input.bits(1)? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(InflateError::InvalidCode)
+    }
+}
+
+struct Inflater<'a> {
+    input: BitReader<'a>,
+    output: Vec<u8>,
+}
+
+impl Inflater<'_> {
+    // Decodes a block, returning whether it was the last.
+    fn block(&mut self) -> Result<bool, InflateError> {
+        let last = self.input.bits(1)? == 1;
+        match self.input.bits(2)? {
+            0 => self.stored_block()?,
+            1 => self.fixed_block()?,
+            2 => self.dynamic_block()?,
+            _ => return Err(InflateError::InvalidBlockType),
+        }
+        Ok(last)
+    }
+
+    // Checks a zlib header: the compression method must be DEFLATE, and there must be no preset
+    // dictionary, which isn't supported.
+    fn zlib_header(&mut self) -> Result<(), InflateError> {
+        let header = self.input.bytes(2)?;
+        let (method, flags) = (header[0], header[1]);
+        if method & 0x0f != 8 || method >> 4 > 7 || !(method as u16 * 256 + flags as u16).is_multiple_of(31) || flags & 0x20 != 0 {
+            return Err(InflateError::InvalidHeader);
+        }
+        Ok(())
+    }
+
+    fn stored_block(&mut self) -> Result<(), InflateError> {
+        self.input.align_to_byte();
+        let header = self.input.bytes(4)?;
+        let length = u16::from_le_bytes([header[0], header[1]]);
+        if length != !u16::from_le_bytes([header[2], header[3]]) {
+            return Err(InflateError::InvalidStoredLength);
+        }
+        let bytes = self.input.bytes(length as usize)?;
+        self.output.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn fixed_block(&mut self) -> Result<(), InflateError> {
+        let mut lengths = [0u8; 288];
+        lengths[..144].fill(8);
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        lengths[280..].fill(8);
+        let literals = Huffman::new(&lengths)?;
+        let distances = Huffman::new(&[5; 30])?;
+        self.codes(&literals, &distances)
+    }
+
+    fn dynamic_block(&mut self) -> Result<(), InflateError> {
+        let literal_count = self.input.bits(5)? as usize + 257;
+        let distance_count = self.input.bits(5)? as usize + 1;
+        let code_length_count = self.input.bits(4)? as usize + 4;
+        if literal_count > 286 || distance_count > 30 {
+            return Err(InflateError::InvalidCodeLengths);
+        }
+
+        let mut code_lengths = [0u8; 19];
+        for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+            code_lengths[index] = self.input.bits(3)? as u8;
+        }
+        let code_length_code = Huffman::new(&code_lengths)?;
+
+        // The literal/length and distance code lengths are run-length encoded as one sequence.
+        let mut lengths = vec![0u8; literal_count + distance_count];
+        let mut index = 0;
+        while index < lengths.len() {
+            let symbol = code_length_code.decode(&mut self.input)?;
+            let (value, repeat) = match symbol {
+                0..=15 => (symbol as u8, 1),
+                16 => (*lengths[..index].last().ok_or(InflateError::InvalidCodeLengths)?, 3 + self.input.bits(2)? as usize),
+                17 => (0, 3 + self.input.bits(3)? as usize),
+                _ => (0, 11 + self.input.bits(7)? as usize),
+            };
+            let run = lengths.get_mut(index..index + repeat).ok_or(InflateError::InvalidCodeLengths)?;
+            run.fill(value);
+            index += repeat;
+        }
+        if lengths[256] == 0 {
+            return Err(InflateError::InvalidCodeLengths); // There must be a code for the end of the block.
+        }
+
+        let literals = Huffman::new(&lengths[..literal_count])?;
+        let distances = Huffman::new(&lengths[literal_count..])?;
+        self.codes(&literals, &distances)
+    }
+
+    fn codes(&mut self, literals: &Huffman, distances: &Huffman) -> Result<(), InflateError> {
+        loop {
+            let symbol = literals.decode(&mut self.input)? as usize;
+            match symbol {
+                0..=255 => self.output.push(symbol as u8),
+                256 => return Ok(()),
+                257..=285 => {
+                    let code = symbol - 257;
+                    let length = LENGTH_BASE[code] as usize + self.input.bits(LENGTH_EXTRA[code] as u32)? as usize;
+                    let code = distances.decode(&mut self.input)? as usize;
+                    if code >= DISTANCE_BASE.len() {
+                        return Err(InflateError::InvalidCode);
+                    }
+                    let distance = DISTANCE_BASE[code] as usize + self.input.bits(DISTANCE_EXTRA[code] as u32)? as usize;
+                    if distance > self.output.len() {
+                        return Err(InflateError::InvalidDistance(distance));
+                    }
+                    // The copy may overlap what it's producing, so it goes a byte at a time.
+                    let start = self.output.len() - distance;
+                    for i in 0..length {
+                        self.output.push(self.output[start + i]);
+                    }
+                },
+                _ => return Err(InflateError::InvalidCode),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stored_block() {
+        assert_eq!(Ok(b"abc".to_vec()), inflate(&[0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c'], 3));
+        assert_eq!(Err(InflateError::InvalidStoredLength), inflate(&[0x01, 0x03, 0x00, 0xfc, 0xfe, b'a', b'b', b'c'], 3));
+    }
+
+    #[test]
+    fn test_fixed_block_with_back_reference() {
+        // "abcabcabcabc", as compressed by zlib.
+        let data = [0x4b, 0x4c, 0x4a, 0x4e, 0x84, 0x21, 0x00];
+        assert_eq!(Ok(b"abcabcabcabc".to_vec()), inflate(&data, 12));
+    }
+
+    #[test]
+    fn test_dynamic_block() {
+        // As compressed by zlib.
+        let data = [
+            0x5d, 0x92, 0x31, 0x0e, 0x43, 0x31, 0x08, 0x43, 0xaf, 0xc2, 0x11, 0x02, 0x24, 0x24, 0x51, 0x4e,
+            0x53, 0xa9, 0x1d, 0x3a, 0xb6, 0x55, 0xef, 0x5f, 0xfc, 0x27, 0xdc, 0x31, 0x08, 0xd9, 0xcf, 0x26,
+            0x4d, 0x3e, 0xaf, 0xef, 0xed, 0xfd, 0xb8, 0xcb, 0xf3, 0x23, 0xed, 0x88, 0xd6, 0xb7, 0x1e, 0xb1,
+            0xfa, 0xee, 0x47, 0xbc, 0xbe, 0xf7, 0x91, 0x4e, 0xfb, 0x71, 0x64, 0xd4, 0x81, 0x8d, 0x23, 0x51,
+            0x07, 0x9e, 0x1b, 0x93, 0x24, 0x53, 0x63, 0xd5, 0x41, 0xa4, 0xc9, 0xae, 0x83, 0x95, 0x14, 0xda,
+            0xc8, 0xa6, 0x01, 0x94, 0x49, 0x0d, 0x5b, 0x04, 0xab, 0x3d, 0x95, 0xd4, 0x99, 0x2f, 0xdd, 0x94,
+            0x91, 0x77, 0x12, 0x29, 0x43, 0x83, 0x5a, 0x83, 0x73, 0x60, 0x8b, 0xc0, 0x6d, 0x41, 0x8b, 0xd0,
+            0xdd, 0xe0, 0xb8, 0x39, 0x2f, 0x3a, 0x24, 0xfa, 0x0e, 0x7a, 0x23, 0xfa, 0xde, 0xb1, 0xc5, 0x55,
+            0xaf, 0xd4, 0x32, 0xa2, 0x1f, 0x96, 0x8e, 0x46, 0xf4, 0x63, 0x26, 0x97, 0x11, 0x7d, 0x80, 0xde,
+            0x88, 0x3e, 0xae, 0x2d, 0xa2, 0x9f, 0x97, 0x16, 0xd1, 0xcf, 0xcb, 0x91, 0xab, 0x07, 0x97, 0x13,
+            0xfd, 0x06, 0xbd, 0x13, 0xfd, 0x46, 0x46, 0xe7, 0xee, 0x1b, 0xaa, 0x70, 0x2e, 0xbf, 0xa1, 0x31,
+            0xe7, 0xf6, 0x15, 0xc5, 0xfa, 0xe0, 0x53, 0x22, 0x81, 0x07, 0xcf, 0x70, 0x26, 0xa7, 0x08, 0xea,
+            0xb8, 0xa6, 0xaf, 0xbf, 0x9b, 0xc3, 0x97, 0x42, 0xe8, 0xc0, 0xdf, 0xf8, 0x01,
+        ];
+        let expected: String = (0..40).map(|i| format!("{} squared is {}; ", i, i * i)).collect();
+        assert_eq!(Ok(expected.into_bytes()), inflate(&data, 744));
+    }
+
+    #[test]
+    fn test_truncated_data() {
+        assert_eq!(Err(InflateError::Truncated), inflate(&[0x4b, 0x4c, 0x4a], 12));
+        assert_eq!(Err(InflateError::Truncated), inflate(&[], 0));
+        assert_eq!(Err(InflateError::Truncated), inflate(&[], usize::MAX));
+    }
+
+    #[test]
+    fn test_stream_checks_zlib_wrapping() {
+        // "abcabcabcabc", as compressed by zlib, with its header and trailer.
+        let data = [0x78, 0x9c, 0x4b, 0x4c, 0x4a, 0x4e, 0x84, 0x21, 0x00, 0x1d, 0xe0, 0x04, 0x99];
+        let mut stream = InflateStream::new(true);
+        stream.add_input(&data[..5]);
+        assert!(stream.needs_input());
+        stream.add_input(&data[5..]);
+        let mut output = [0; 20];
+        assert_eq!(Ok(12), stream.take(&mut output));
+        assert!(stream.finished());
+        assert_eq!(0x1de00499, stream.adler());
+
+        let mut corrupted = data;
+        corrupted[12] ^= 1;
+        let mut stream = InflateStream::new(true);
+        stream.add_input(&corrupted);
+        assert_eq!(Ok(12), stream.take(&mut output));
+        assert_eq!(Err(InflateError::ChecksumMismatch), stream.take(&mut output));
+
+        let mut stream = InflateStream::new(true);
+        stream.add_input(&data[2..]);
+        assert_eq!(Err(InflateError::InvalidHeader), stream.take(&mut output));
+    }
+
+    #[test]
+    fn test_distance_before_start() {
+        // A fixed block whose first symbol is a length, with nothing to copy from.
+        assert_eq!(Err(InflateError::InvalidDistance(1)), inflate(&[0x03, 0x02, 0x00], 0));
+    }
+}
@@ -0,0 +1,103 @@
+use crate::runtime::RuntimeClass;
+use std::rc::Rc;
+
+// The number of receiver classes a call site remembers before it gives up and is treated as
+// megamorphic, as HotSpot does.
+pub const POLYMORPHIC_LIMIT: usize = 4;
+
+// Remembers which method a virtual or interface call site selected for each receiver class it has
+// seen, so that later calls on the same classes skip the search through the class hierarchy.
+// Entries are keyed by the identity of the receiver's class, so they stay valid until the methods
+// of a loaded class change, at which point Vm::invalidate_inline_caches must be called.
+#[derive(Clone, Default)]
+pub enum InlineCache {
+    #[default]
+    Empty,
+    Monomorphic(CacheEntry),
+    Polymorphic(Vec<CacheEntry>),
+    Megamorphic, // Too many receiver classes have been seen, so every call does a full lookup.
+}
+
+#[derive(Clone)]
+pub struct CacheEntry {
+    pub receiver: Rc<RuntimeClass>,
+    pub class: Rc<RuntimeClass>, // The class declaring the selected method.
+    pub method_index: usize,
+}
+
+impl InlineCache {
+    // The method selected for receivers of the given class, if it's been cached.
+    #[inline]
+    pub fn lookup(&self, receiver: &Rc<RuntimeClass>) -> Option<(Rc<RuntimeClass>, usize)> {
+        let hit = |entry: &CacheEntry| Rc::ptr_eq(&entry.receiver, receiver);
+        match *self {
+            InlineCache::Monomorphic(ref entry) if hit(entry) => Some((Rc::clone(&entry.class), entry.method_index)),
+            InlineCache::Polymorphic(ref entries) => entries.iter()
+                .find(|entry| hit(entry))
+                .map(|entry| (Rc::clone(&entry.class), entry.method_index)),
+            _ => None,
+        }
+    }
+
+    // Records the method selected for a receiver class that missed the cache.
+    pub fn record(&mut self, receiver: &Rc<RuntimeClass>, class: &Rc<RuntimeClass>, method_index: usize) {
+        let entry = CacheEntry {receiver: Rc::clone(receiver), class: Rc::clone(class), method_index};
+        *self = match std::mem::take(self) {
+            InlineCache::Empty => InlineCache::Monomorphic(entry),
+            InlineCache::Monomorphic(first) => InlineCache::Polymorphic(vec![first, entry]),
+            InlineCache::Polymorphic(ref entries) if entries.len() >= POLYMORPHIC_LIMIT => InlineCache::Megamorphic,
+            InlineCache::Polymorphic(mut entries) => {
+                entries.push(entry);
+                InlineCache::Polymorphic(entries)
+            },
+            InlineCache::Megamorphic => InlineCache::Megamorphic,
+        };
+    }
+
+    // The number of receiver classes cached, for tests and diagnostics.
+    pub fn len(&self) -> usize {
+        match *self {
+            InlineCache::Monomorphic(_) => 1,
+            InlineCache::Polymorphic(ref entries) => entries.len(),
+            InlineCache::Empty | InlineCache::Megamorphic => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::Vm;
+
+    fn classes(vm: &mut Vm, names: &[&str]) -> Vec<Rc<RuntimeClass>> {
+        names.iter().map(|name| vm.load_class(name).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_cache_grows_from_monomorphic_to_megamorphic() {
+        let mut vm = Vm::new();
+        let receivers = classes(&mut vm, &["java/lang/Object", "java/lang/String", "java/lang/Throwable",
+            "java/lang/Exception", "java/lang/RuntimeException"]);
+        let declaring = Rc::clone(&receivers[0]);
+        let mut cache = InlineCache::default();
+
+        cache.record(&receivers[0], &declaring, 0);
+        assert!(matches!(cache, InlineCache::Monomorphic(_)));
+        for receiver in &receivers[1..POLYMORPHIC_LIMIT] {
+            cache.record(receiver, &declaring, 1);
+        }
+        assert!(matches!(cache, InlineCache::Polymorphic(_)));
+        assert_eq!(POLYMORPHIC_LIMIT, cache.len());
+        assert_eq!(Some(0), cache.lookup(&receivers[0]).map(|(_, index)| index));
+        assert_eq!(Some(1), cache.lookup(&receivers[1]).map(|(_, index)| index));
+        assert!(cache.lookup(&receivers[POLYMORPHIC_LIMIT]).is_none());
+
+        cache.record(&receivers[POLYMORPHIC_LIMIT], &declaring, 1);
+        assert!(matches!(cache, InlineCache::Megamorphic));
+        assert!(cache.lookup(&receivers[0]).is_none());
+    }
+}
@@ -0,0 +1,94 @@
+use crate::loaders::LoaderId;
+
+// Rewrites class files before their classes are defined, in the manner of java.lang.instrument's
+// ClassFileTransformer, so that agents such as tracers and mocking tools can change classes
+// before any of their code runs. Install them with Vm::add_transformer.
+//
+// Transformers see every class a loader defines, whether it was found on a classpath, added by
+// the embedder or defined at runtime through ClassLoader.defineClass, but not array classes,
+// which have no class files. Classes that are already loaded aren't retransformed.
+pub trait ClassFileTransformer {
+    // Returns the new class file, or None to leave the class as it is. Transformers run in the
+    // order they were added, each seeing the previous one's output. The class file returned must
+    // still be for the named class.
+    fn transform(&mut self, loader: LoaderId, name: &str, bytes: &[u8]) -> Option<Vec<u8>>;
+}
+
+impl<F: FnMut(LoaderId, &str, &[u8]) -> Option<Vec<u8>>> ClassFileTransformer for F {
+    fn transform(&mut self, loader: LoaderId, name: &str, bytes: &[u8]) -> Option<Vec<u8>> {
+        self(loader, name, bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use crate::classpath::ClasspathError;
+    use crate::runtime::Value;
+    use crate::vm::{Vm, VmError};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Replaces the first occurrence of one byte string with another of the same length.
+    fn replace(bytes: &[u8], from: &[u8], to: &[u8]) -> Option<Vec<u8>> {
+        let start = bytes.windows(from.len()).position(|window| window == from)?;
+        let mut replaced = bytes.to_vec();
+        replaced[start..start + to.len()].copy_from_slice(to);
+        Some(replaced)
+    }
+
+    fn message(vm: &mut Vm) -> String {
+        match vm.invoke_static("Transformed", "message", "()Ljava/lang/String;", vec![]) {
+            Ok(Some(Value::Reference(Some(message)))) => vm.read_string(&message).unwrap(),
+            other => panic!("Expected a message; got {:#?}", other),
+        }
+    }
+
+    #[test]
+    fn test_transformers_run_in_order_before_definition() {
+        let mut vm = vm_with(&[fixture!("Transformed")]);
+        let seen = Rc::new(RefCell::new(vec![]));
+        let recorded = Rc::clone(&seen);
+        vm.add_transformer(Box::new(move |loader: LoaderId, name: &str, bytes: &[u8]| {
+            recorded.borrow_mut().push((loader, name.to_string()));
+            replace(bytes, b"before", b"during")
+        }));
+        vm.add_transformer(Box::new(|_: LoaderId, _: &str, bytes: &[u8]| replace(bytes, b"during", b"after!")));
+
+        assert_eq!("after!", message(&mut vm));
+        assert!(seen.borrow().contains(&(LoaderId::APPLICATION, "Transformed".to_string())));
+        assert!(seen.borrow().contains(&(LoaderId::BOOTSTRAP, "java/lang/Object".to_string())));
+
+        // Classes that are already loaded aren't transformed again.
+        assert_eq!(2, vm.take_transformers().len());
+        assert_eq!("after!", message(&mut vm));
+    }
+
+    #[test]
+    fn test_transformers_see_defined_classes() {
+        let mut vm = Vm::new();
+        let seen = Rc::new(RefCell::new(vec![]));
+        let recorded = Rc::clone(&seen);
+        vm.add_transformer(Box::new(move |loader: LoaderId, name: &str, _: &[u8]| {
+            recorded.borrow_mut().push((loader, name.to_string()));
+            None
+        }));
+        let loader = vm.new_class_loader("custom", LoaderId::APPLICATION);
+        vm.define_class(loader, None, fixture!("Completes")).unwrap();
+        assert_eq!(Some(&(loader, "Completes".to_string())), seen.borrow().first());
+    }
+
+    #[test]
+    fn test_transformers_cannot_rename_classes() {
+        let mut vm = Vm::new();
+        vm.add_transformer(Box::new(|_: LoaderId, name: &str, _: &[u8]| match name {
+            "Completes" => Some(fixture!("Transformed").to_vec()),
+            _ => None,
+        }));
+        let loader = vm.new_class_loader("custom", LoaderId::APPLICATION);
+        assert_eq!(
+            Err(VmError::Classpath(ClasspathError::WrongName{expected: "Completes".to_string(), found: "Transformed".to_string()})),
+            vm.define_class(loader, None, fixture!("Completes")).map(|_| ()));
+    }
+}
@@ -0,0 +1,2988 @@
+use crate::classes::*;
+use crate::clock;
+use crate::compression;
+use crate::console;
+use crate::debugger;
+use crate::direct;
+use crate::entropy;
+use crate::events::Event;
+use crate::gc::GcCause;
+use crate::intrinsics;
+#[cfg(feature = "jit")]
+use crate::jit;
+#[cfg(feature = "native-libraries")]
+use crate::libraries;
+use crate::loaders;
+use crate::method_handles;
+use crate::natives;
+use crate::opcodes::*;
+use crate::reflection;
+use crate::resolve;
+use crate::roots::{self, RootVisitor};
+use crate::sandbox::{self, Decision};
+use crate::shutdown;
+use crate::threads;
+use crate::verify;
+use crate::heap::ObjectRef;
+use crate::runtime::*;
+use crate::vm::{self, Vm, VmError};
+use std::path::Path;
+use std::rc::Rc;
+
+// Executes a method to completion. For instance methods, the receiver is the first argument.
+// Exceptions that aren't handled within the method are returned as VmError::UncaughtException,
+// so that the caller can look for a handler of its own.
+pub fn invoke(vm: &mut Vm, class: &Rc<RuntimeClass>, method_index: usize, args: Vec<Value>) -> Result<Option<Value>, VmError> {
+    vm.nesting_mut().interpreters += 1;
+    let result = if vm.is_instrumented() {
+        run::<true>(vm, class, method_index, args)
+    } else {
+        run::<false>(vm, class, method_index, args)
+    };
+    vm.nesting_mut().interpreters -= 1;
+    result
+}
+
+// Carries on running a green thread that unwound with VmError::Suspended, from the activation it
+// left on top of its stack. Since it could only unwind from its outermost call to invoke, the
+// whole stack is its own. The incoming result is what a native it was blocked in returned, or
+// None if it stopped before an instruction, which then runs again.
+pub fn resume(vm: &mut Vm, incoming: Option<Result<Option<Value>, VmError>>) -> Result<Option<Value>, VmError> {
+    let current = vm.suspended_activations().pop().ok_or_else(|| VmError::InvalidBytecode("No suspended frame to resume".to_string()))?;
+    vm.nesting_mut().interpreters += 1;
+    let result = if vm.is_instrumented() {
+        run_from::<true>(vm, current, 0, incoming)
+    } else {
+        run_from::<false>(vm, current, 0, incoming)
+    };
+    vm.nesting_mut().interpreters -= 1;
+    result
+}
+
+// Runs a method along with everything it calls, without recursing on the Rust stack: a call
+// suspends the caller on an explicit stack of activations, and a return resumes it. This means
+// Java recursion is bounded only by the stack depth limit. Class initializers, exception
+// constructors and bootstrap methods are still run by a nested call to invoke, since they're
+// triggered from the middle of an instruction, but those can't nest indefinitely.
+//
+// The dispatch loop is compiled twice: once with calls to the execution hooks, the debugger and
+// the event bus, and once without, so that they add no overhead to each instruction when nothing
+// is listening.
+fn run<const HOOKED: bool>(vm: &mut Vm, class: &Rc<RuntimeClass>, method_index: usize, args: Vec<Value>) -> Result<Option<Value>, VmError> {
+    let current = match enter::<HOOKED>(vm, class, method_index, args)? {
+        Entered::Method(activation) => activation,
+        Entered::Native(result) => return result,
+    };
+    // Callers waiting for callees are kept on the VM, so that their locals can be scanned for
+    // roots. Anything below the base belongs to an enclosing call to run.
+    let base = vm.suspended_activations().len();
+    run_from::<HOOKED>(vm, current, base, None)
+}
+
+fn run_from<const HOOKED: bool>(vm: &mut Vm, mut current: Activation, base: usize, mut incoming: Option<Result<Option<Value>, VmError>>) -> Result<Option<Value>, VmError> {
+    loop {
+        let class = Rc::clone(&current.class);
+        let method = &class.class.methods[current.method_index];
+        let code = match method.code() {
+            Some(code) => code,
+            None => {
+                vm.suspended_activations().truncate(base);
+                return Err(VmError::InvalidBytecode(format!("{} lost its Code attribute", class.name)));
+            },
+        };
+        let instructions = class.decoded_method(current.method_index, || decode(code.code, vm.fuses_instructions()));
+        let mut frame = Frame::resume(&class, code, instructions, &mut current);
+
+        let result = match execute::<HOOKED>(vm, &mut frame, method, incoming.take()) {
+            Ok(Flow::Invoke(callee, callee_index, args)) => {
+                match enter::<HOOKED>(vm, &callee, callee_index, args) {
+                    Ok(Entered::Method(activation)) => {
+                        frame.suspend(&mut current);
+                        let caller = std::mem::replace(&mut current, activation);
+                        vm.suspended_activations().push(caller);
+                    },
+                    Ok(Entered::Native(Err(VmError::Suspended))) => {
+                        // The native blocked, and the scheduler will pass in its result.
+                        frame.suspend(&mut current);
+                        vm.suspended_activations().push(current);
+                        return Err(VmError::Suspended);
+                    },
+                    Ok(Entered::Native(result)) => {
+                        frame.suspend(&mut current);
+                        incoming = Some(result);
+                    },
+                    Err(err) => {
+                        // The callee couldn't be entered, e.g. because the stack overflowed, so
+                        // the invoke instruction itself throws.
+                        if let (true, VmError::UncaughtException(ref exception)) = (HOOKED, &err) {
+                            vm.post_event(&Event::Exception{class: &class, method, pc: frame.instruction_pc, exception});
+                        }
+                        frame.suspend(&mut current);
+                        incoming = Some(Err(err));
+                    },
+                }
+                continue;
+            },
+            Ok(Flow::Return(value)) => Ok(value),
+            Ok(Flow::Continue) => unreachable!("execute only stops to make a call or to return"),
+            Err(VmError::Suspended) => {
+                // The thread stopped before the instruction, which runs when it resumes.
+                frame.pc = frame.instruction_pc;
+                frame.suspend(&mut current);
+                vm.suspended_activations().push(current);
+                return Err(VmError::Suspended);
+            },
+            Err(err) => Err(err),
+        };
+
+        let result = desynchronize(vm, current.monitor.take(), result);
+        leave::<HOOKED>(vm, &class, method, &result);
+        if vm.suspended_activations().len() == base {
+            return result;
+        }
+        current = vm.suspended_activations().pop().unwrap();
+        incoming = Some(result);
+    }
+}
+
+// The state of a method invocation while it isn't running: either it hasn't started yet, or it's
+// waiting for a callee to return. The locals and operand stack are moved into a Frame while the
+// method runs, and back out when it makes a call.
+pub struct Activation {
+    class: Rc<RuntimeClass>,
+    method_index: usize,
+    pc: usize,
+    instruction_pc: usize,
+    locals: Vec<Value>,
+    stack: Vec<Value>,
+    monitor: Option<ObjectRef>, // Held while a synchronized method runs.
+}
+
+impl Activation {
+    fn new(class: &Rc<RuntimeClass>, method_index: usize, code: &Code, args: Vec<Value>, monitor: Option<ObjectRef>) -> Activation {
+        let mut locals = vec![];
+        for arg in args {
+            let is_category_2 = arg.is_category_2();
+            locals.push(arg);
+            if is_category_2 {
+                locals.push(Value::Int(0)); // Placeholder for the second half of a long or double.
+            }
+        }
+        if locals.len() < code.max_locals as usize {
+            locals.resize(code.max_locals as usize, Value::Int(0));
+        }
+
+        Activation {
+            class: Rc::clone(class),
+            method_index,
+            pc: 0,
+            instruction_pc: 0,
+            locals,
+            stack: Vec::with_capacity(code.max_stack as usize),
+            monitor,
+        }
+    }
+}
+
+impl Activation {
+    // Visits the references held by the method's live locals and operand stack.
+    pub fn visit_roots(&self, visitor: &mut dyn RootVisitor) {
+        roots::visit_frame(&self.class, self.method_index, self.instruction_pc, &self.locals, &self.stack, visitor);
+    }
+}
+
+enum Entered {
+    Method(Activation),
+    Native(Result<Option<Value>, VmError>), // Native methods run to completion as they're entered.
+}
+
+// Pushes a frame for the method, failing if that would overflow the stack.
+fn enter<const HOOKED: bool>(vm: &mut Vm, class: &Rc<RuntimeClass>, method_index: usize, args: Vec<Value>) -> Result<Entered, VmError> {
+    let method = &class.class.methods[method_index];
+    let is_native = method.flags.contains(MethodFlags::NATIVE);
+    let code = match method.code() {
+        Some(code) => Some(code),
+        None if is_native => None,
+        None => return Err(VmError::InvalidBytecode(
+            format!("{}.{} has no Code attribute", class.name, class.class.utf8(&method.name).unwrap_or("<unknown>")))),
+    };
+    // Instructions aren't checked when the class is loaded, but before the method first runs.
+    if code.is_some() && vm.verifies(class.loader) {
+        class.verified_method(method_index, || verify::check_instructions(&class.class, method))?;
+    }
+
+    vm.enter_frame(class, method_index)?;
+    // A synchronized method holds the monitor of its receiver, or of its class's mirror if it's
+    // static, until it returns or throws. The arguments are already popped, so the thread waits
+    // for the monitor here rather than unwinding.
+    let monitor = match method.flags.contains(MethodFlags::SYNCHRONIZED) {
+        true => match synchronize(vm, class, method, &args) {
+            Ok(object) => Some(object),
+            Err(err) => {
+                vm.exit_frame();
+                return Err(err);
+            },
+        },
+        false => None,
+    };
+    class.count_invocation(method_index);
+    if HOOKED {
+        vm.post_event(&Event::MethodEntry{class, method});
+    }
+
+    match code {
+        Some(code) => Ok(Entered::Method(Activation::new(class, method_index, &code, args, monitor))),
+        None => {
+            vm.nesting_mut().natives += 1;
+            let result = vm.with_local_frame(|vm| invoke_native(vm, class, method, args));
+            vm.nesting_mut().natives -= 1;
+            if result.as_ref().is_err_and(VmError::is_internal) {
+                vm.record_error_stack();
+            }
+            let result = desynchronize(vm, monitor, result);
+            leave::<HOOKED>(vm, class, method, &result);
+            Ok(Entered::Native(result))
+        },
+    }
+}
+
+fn synchronize(vm: &mut Vm, class: &Rc<RuntimeClass>, method: &Method, args: &[Value]) -> Result<ObjectRef, VmError> {
+    let object = match (method.flags.contains(MethodFlags::STATIC), args.first()) {
+        (true, _) => vm.class_mirror(class)?,
+        (false, Some(Value::Reference(Some(receiver)))) => receiver.clone(),
+        (false, _) => return Err(VmError::InvalidBytecode(format!("{} has a synchronized method with no receiver", class.name))),
+    };
+    threads::enter_monitor(vm, &object)?;
+    Ok(object)
+}
+
+// Exits a synchronized method's monitor as it returns or throws. Should the thread somehow no
+// longer own it, the method throws IllegalMonitorStateException instead. A synchronized native
+// that blocks also exits it, much as Object.wait would.
+fn desynchronize(vm: &mut Vm, monitor: Option<ObjectRef>, result: Result<Option<Value>, VmError>) -> Result<Option<Value>, VmError> {
+    match monitor {
+        Some(object) => threads::exit_monitor(vm, &object).and(result),
+        None => result,
+    }
+}
+
+// Pops the frame for a method that has returned or thrown.
+fn leave<const HOOKED: bool>(vm: &mut Vm, class: &RuntimeClass, method: &Method, result: &Result<Option<Value>, VmError>) {
+    if HOOKED {
+        vm.post_event(&Event::MethodExit{class, method, result});
+    }
+    vm.exit_frame();
+}
+
+// Runs a native registered with Vm::register_native, or else one of the few the VM provides
+// itself.
+fn invoke_native(vm: &mut Vm, class: &Rc<RuntimeClass>, method: &Method, args: Vec<Value>) -> Result<Option<Value>, VmError> {
+    let name = class.class.utf8(&method.name)?;
+    let descriptor = class.class.utf8(&method.descriptor)?;
+    if let Some(native) = vm.registered_native(&class.name, name, descriptor) {
+        return natives::invoke(vm, class, &native, &format!("{}.{}{}", class.name, name, descriptor), descriptor, &args);
+    }
+    match (class.name.as_str(), name, descriptor, args.as_slice()) {
+        ("java/lang/System", "gc", "()V", &[]) => {
+            vm.collect_garbage(GcCause::SystemGc);
+            Ok(None)
+        },
+        ("java/lang/Object", "getClass", "()Ljava/lang/Class;", &[Value::Reference(Some(ref object))]) => {
+            Ok(Some(Value::Reference(Some(vm.class_mirror(object.class())?))))
+        },
+        ("java/lang/System", "identityHashCode", "(Ljava/lang/Object;)I", &[Value::Reference(ref object)]) |
+        ("java/lang/Object", "hashCode", "()I", &[Value::Reference(ref object)]) => {
+            Ok(Some(Value::Int(object.as_ref().map_or(0, |object| vm.identity_hash(object)))))
+        },
+        ("java/lang/Class", "getPackage", "()Ljava/lang/Package;", &[Value::Reference(Some(ref mirror))]) => {
+            let class = vm.mirror_class(mirror).ok_or_else(|| VmError::InvalidBytecode("Class object isn't a mirror".to_string()))?;
+            Ok(Some(Value::Reference(vm.package_object(&class)?)))
+        },
+        ("java/lang/Class", "desiredAssertionStatus", "()Z", &[Value::Reference(Some(ref mirror))]) => {
+            let class = vm.mirror_class(mirror).ok_or_else(|| VmError::InvalidBytecode("Class object isn't a mirror".to_string()))?;
+            Ok(Some(Value::Int(vm.assertions_enabled(&class) as i32)))
+        },
+        ("java/lang/String", "intern", "()Ljava/lang/String;", &[Value::Reference(Some(ref string))]) => {
+            Ok(Some(Value::Reference(Some(vm.intern(string)?))))
+        },
+        ("java/lang/System", "load", "(Ljava/lang/String;)V", &[Value::Reference(ref path)]) |
+        ("java/lang/System", "loadLibrary", "(Ljava/lang/String;)V", &[Value::Reference(ref path)]) => {
+            let path = path.as_ref().ok_or_else(|| vm.throw_new("java/lang/NullPointerException"))?;
+            let path = vm.read_string(path)?;
+            match name {
+                "load" => vm.load_library_file(Path::new(&path))?,
+                _ => vm.load_library(&path)?,
+            }
+            Ok(None)
+        },
+        ("java/lang/System", "getenv", "(Ljava/lang/String;)Ljava/lang/String;", &[Value::Reference(ref variable)]) => {
+            let variable = variable.as_ref().ok_or_else(|| vm.throw_new("java/lang/NullPointerException"))?;
+            let variable = vm.read_string(variable)?;
+            let value = match vm.sandbox_mut().getenv(&variable) {
+                Decision::Allow => std::env::var(&variable).ok(),
+                Decision::Deny => return Err(sandbox::denied(vm, &format!("getenv {}", variable))),
+                Decision::Virtualize(value) => value,
+            };
+            match value {
+                Some(value) => Ok(Some(Value::Reference(Some(vm.new_string(&value)?)))),
+                None => Ok(Some(Value::null())),
+            }
+        },
+        ("java/lang/System", "getProperty", "(Ljava/lang/String;)Ljava/lang/String;", &[Value::Reference(ref key)]) => {
+            let key = property_key(vm, key)?;
+            match vm.property(&key).map(str::to_string) {
+                Some(value) => Ok(Some(Value::Reference(Some(vm.new_string(&value)?)))),
+                None => Ok(Some(Value::null())),
+            }
+        },
+        ("java/lang/System", "setProperty", "(Ljava/lang/String;Ljava/lang/String;)Ljava/lang/String;", &[Value::Reference(ref key), Value::Reference(ref value)]) => {
+            let key = property_key(vm, key)?;
+            let value = value.as_ref().ok_or_else(|| vm.throw_new("java/lang/NullPointerException"))?;
+            let value = vm.read_string(value)?;
+            match vm.set_property(&key, &value) {
+                Some(previous) => Ok(Some(Value::Reference(Some(vm.new_string(&previous)?)))),
+                None => Ok(Some(Value::null())),
+            }
+        },
+        ("java/lang/System", "mapLibraryName", "(Ljava/lang/String;)Ljava/lang/String;", &[Value::Reference(ref library)]) => {
+            let library = library.as_ref().ok_or_else(|| vm.throw_new("java/lang/NullPointerException"))?;
+            let mapped = vm::map_library_name(&vm.read_string(library)?);
+            Ok(Some(Value::Reference(Some(vm.new_string(&mapped)?))))
+        },
+        ("java/lang/System", "arraycopy", ..) | ("java/lang/Object", "clone", ..) | ("java/lang/String", ..) => {
+            intrinsics::invoke_native(vm, &class.name, name, descriptor, &args)
+        },
+        (class_name @ ("java/io/FileInputStream" | "java/io/FileOutputStream"), ..) => console::invoke_native(vm, class_name, name, descriptor, &args),
+        ("java/lang/Class", "forName" | "getDeclaredFields" | "getDeclaredMethods" | "getDeclaredConstructors" | "getPrimitiveClass", ..) |
+        ("java/lang/reflect/Field" | "java/lang/reflect/Method" | "java/lang/reflect/Constructor", ..) |
+        ("sun/misc/Unsafe", "objectFieldOffset" | "compareAndSetInt" | "compareAndSetLong" | "compareAndSetReference", ..) => {
+            reflection::invoke_native(vm, &class.name, name, descriptor, &args)
+        },
+        ("java/lang/invoke/MethodHandle" | "java/lang/invoke/MethodHandles" | "java/lang/invoke/MethodHandles$Lookup" | "java/lang/invoke/MethodType", ..) => {
+            method_handles::invoke_native(vm, &class.name, name, descriptor, &args)
+        },
+        ("java/util/zip/CRC32" | "java/util/zip/Adler32" | "java/util/zip/Inflater" | "java/util/zip/Deflater", ..) => {
+            compression::invoke_native(vm, &class.name, name, descriptor, &args)
+        },
+        ("java/lang/System", "currentTimeMillis" | "nanoTime", ..) => clock::invoke_native(vm, &class.name, name, descriptor, &args),
+        ("java/util/Random", ..) => entropy::invoke_native(vm, &class.name, name, descriptor, &args),
+        ("java/lang/Runtime", ..) => shutdown::invoke_native(vm, &class.name, name, descriptor, &args),
+        ("java/lang/Thread" | "java/util/concurrent/locks/LockSupport", ..) | ("sun/misc/Unsafe", "park" | "unpark", ..) |
+        ("java/lang/Object", "wait" | "notify" | "notifyAll", ..) => {
+            threads::invoke_native(vm, &class.name, name, descriptor, &args)
+        },
+        ("java/lang/ClassLoader", ..) => loaders::invoke_native(vm, name, descriptor, &args),
+        (class_name @ ("sun/misc/Unsafe" | "java/nio/DirectByteBuffer"), ..) => direct::invoke_native(vm, class_name, name, descriptor, &args),
+        _ => {
+            #[cfg(feature = "native-libraries")]
+            if let Some(function) = vm.libraries().find(&class.name, name, descriptor) {
+                return libraries::invoke(vm, class, function, method.flags.contains(MethodFlags::STATIC), descriptor, &args);
+            }
+            Err(VmError::Unsupported(format!("native method {}.{}{}", class.name, name, descriptor)))
+        },
+    }
+}
+
+// Reads the key passed to System.getProperty or setProperty, which mustn't be null or empty.
+fn property_key(vm: &mut Vm, key: &Option<ObjectRef>) -> Result<String, VmError> {
+    let key = key.as_ref().ok_or_else(|| vm.throw_new("java/lang/NullPointerException"))?;
+    let key = vm.read_string(key)?;
+    if key.is_empty() {
+        return Err(vm.throw_new_with_message("java/lang/IllegalArgumentException", "key can't be empty"));
+    }
+    Ok(key)
+}
+
+// Runs the frame's instructions until it returns, throws an exception it doesn't handle, or makes
+// a call. When the frame is resumed after a call, the result of the call is passed in and handled
+// as though the invoke instruction had just finished.
+fn execute<const HOOKED: bool>(vm: &mut Vm, frame: &mut Frame, method: &Method, incoming: Option<Result<Option<Value>, VmError>>) -> Result<Flow, VmError> {
+    let mut result = match incoming {
+        Some(Ok(value)) => {
+            if let Some(value) = value {
+                frame.push(value);
+            }
+            Ok(())
+        },
+        Some(Err(err)) => Err(err),
+        None => Ok(()),
+    };
+    #[cfg(feature = "jit")]
+    if !HOOKED && frame.pc == 0 && result.is_ok() {
+        if let Some(flow) = run_compiled(vm, frame) {
+            return Ok(flow);
+        }
+    }
+
+    loop {
+        match result {
+            Ok(()) => (),
+            Err(VmError::UncaughtException(exception)) => {
+                match find_handler(vm, frame, &exception)? {
+                    Some(handler_pc) => {
+                        frame.stack.clear();
+                        frame.push(Value::Reference(Some(exception)));
+                        frame.pc = handler_pc as usize;
+                    },
+                    None => return Err(VmError::UncaughtException(exception)),
+                }
+            },
+            Err(err) => return Err(err),
+        }
+
+        let mut instruction = *frame.instructions.get(frame.pc).ok_or_else(|| VmError::InvalidBytecode(
+            format!("Execution ran off the end of the code at pc {}", frame.pc)))?;
+        if HOOKED && instruction.fused {
+            // Hooks and breakpoints see every instruction, so superinstructions are taken apart.
+            instruction = decode_one(frame.code.code, frame.pc);
+        }
+        frame.instruction_pc = frame.pc;
+        frame.pc += instruction.length as usize;
+        vm.set_pc(frame.instruction_pc);
+        vm.count_instruction()?;
+        if HOOKED {
+            if vm.is_debugging() {
+                debugger::check(vm, frame.class, method, &frame.code, frame.instruction_pc, &mut frame.locals, &frame.stack);
+            }
+            if let Some(hooks) = vm.hooks_mut() {
+                hooks.on_instruction(frame.class, method, frame.instruction_pc, frame.code.code[frame.instruction_pc], &frame.stack);
+            }
+        }
+
+        result = match (instruction.handler)(vm, frame, instruction.operands) {
+            // Loops are counted at their backward branches, and compiled as they run.
+            Ok(Flow::Continue) if frame.pc < frame.instruction_pc => {
+                frame.class.count_backedge(frame.method_index);
+                #[cfg(feature = "jit")]
+                if !HOOKED && frame.stack.is_empty() {
+                    if let Some(flow) = run_compiled(vm, frame) {
+                        return Ok(flow);
+                    }
+                }
+                Ok(())
+            },
+            Ok(Flow::Continue) => Ok(()),
+            Ok(flow) => return Ok(flow),
+            Err(err) => {
+                if let (true, VmError::UncaughtException(ref exception)) = (HOOKED, &err) {
+                    vm.post_event(&Event::Exception{class: frame.class, method, pc: frame.instruction_pc, exception});
+                }
+                if err.is_internal() {
+                    vm.record_error_stack();
+                }
+                Err(err)
+            },
+        };
+    }
+}
+
+// Switches to compiled code from the current pc if the method has been compiled, compiling it
+// first if it has just become hot. Returns None to carry on interpreting, which may be
+// from a different pc if the compiled code deoptimized.
+#[cfg(feature = "jit")]
+fn run_compiled(vm: &mut Vm, frame: &mut Frame) -> Option<Flow> {
+    let compiled = vm.jit()?.hot_method(frame.class, frame.method_index)?;
+    match compiled.run(frame.pc, &mut frame.locals, &mut frame.stack) {
+        jit::Exit::Returned(value) => Some(Flow::Return(value)),
+        jit::Exit::Deoptimized(pc) => {
+            frame.pc = pc;
+            None
+        },
+        jit::Exit::NotEntered => None,
+    }
+}
+
+// An instruction decoded ahead of time, so that the dispatch loop can jump straight to the code
+// for its opcode with its operands already read. This is direct threading: a method is decoded
+// into a stream of handlers the first time it runs, rather than matching on each opcode every
+// time it's executed.
+#[derive(Clone, Copy)]
+pub struct Instruction {
+    handler: Handler,
+    operands: Operands,
+    length: u32,
+    fused: bool, // A superinstruction, which runs this instruction and those after it.
+}
+
+// An instruction's operands, e.g. a local variable index and increment for iinc, a constant pool
+// index, or a branch offset. Unused operands are zero.
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+struct Operands(i32, i32);
+
+type Handler = fn(&mut Vm, &mut Frame, Operands) -> Result<Flow, VmError>;
+
+// Each handler is step specialized to a constant opcode, which the compiler reduces to the code
+// for that one instruction.
+fn handle<const OPCODE: u8>(vm: &mut Vm, frame: &mut Frame, operands: Operands) -> Result<Flow, VmError> {
+    step(vm, frame, OPCODE, operands)
+}
+
+macro_rules! handler_row {
+    ($high:literal) => {
+        [
+            handle::<{$high * 16 + 0}>, handle::<{$high * 16 + 1}>, handle::<{$high * 16 + 2}>, handle::<{$high * 16 + 3}>,
+            handle::<{$high * 16 + 4}>, handle::<{$high * 16 + 5}>, handle::<{$high * 16 + 6}>, handle::<{$high * 16 + 7}>,
+            handle::<{$high * 16 + 8}>, handle::<{$high * 16 + 9}>, handle::<{$high * 16 + 10}>, handle::<{$high * 16 + 11}>,
+            handle::<{$high * 16 + 12}>, handle::<{$high * 16 + 13}>, handle::<{$high * 16 + 14}>, handle::<{$high * 16 + 15}>,
+        ]
+    };
+}
+
+static HANDLERS: [[Handler; 16]; 16] = [
+    handler_row!(0), handler_row!(1), handler_row!(2), handler_row!(3),
+    handler_row!(4), handler_row!(5), handler_row!(6), handler_row!(7),
+    handler_row!(8), handler_row!(9), handler_row!(10), handler_row!(11),
+    handler_row!(12), handler_row!(13), handler_row!(14), handler_row!(15),
+];
+
+fn handler(opcode: u8) -> Handler {
+    HANDLERS[(opcode >> 4) as usize][(opcode & 0xf) as usize]
+}
+
+// Decodes a method's bytecode into a stream of instructions indexed by pc, so that branch
+// targets, exception handlers and line numbers can still refer to bytecode offsets. The pcs in
+// the middle of an instruction hold one that fails if it's executed, as do instructions that are
+// cut off by the end of the code, so that bad bytecode is only reported if it's reached.
+fn decode(code: &[u8], fuse: bool) -> Rc<[Instruction]> {
+    let mut instructions = vec![Instruction {handler: not_an_instruction, operands: Operands::default(), length: 1, fused: false}; code.len()];
+    let mut starts = vec![];
+    let mut pc = 0;
+    while pc < code.len() {
+        let (instruction, length) = match decode_instruction(code, pc) {
+            Some((handler, operands, length)) => {
+                starts.push(pc);
+                (Instruction {handler, operands, length: length as u32, fused: false}, length)
+            },
+            None => (Instruction {handler: truncated, operands: Operands::default(), length: 1, fused: false}, code.len() - pc),
+        };
+        instructions[pc] = instruction;
+        pc += length;
+    }
+    if fuse {
+        fuse_instructions(code, &starts, &mut instructions);
+    }
+    instructions.into()
+}
+
+// Decodes the instruction at the pc on its own, for running a superinstruction's first part.
+fn decode_one(code: &[u8], pc: usize) -> Instruction {
+    match decode_instruction(code, pc) {
+        Some((handler, operands, length)) => Instruction {handler, operands, length: length as u32, fused: false},
+        None => Instruction {handler: truncated, operands: Operands::default(), length: 1, fused: false},
+    }
+}
+
+// Replaces the first instruction of each common sequence with a superinstruction that runs the
+// whole sequence in one trip round the dispatch loop. The rest of the sequence is left as it
+// was, so a branch or exception handler that lands in the middle still finds the instruction it
+// expects, and so does a thread resuming there. The starts are the pcs of the instructions that
+// were decoded in full, in order.
+fn fuse_instructions(code: &[u8], starts: &[usize], instructions: &mut [Instruction]) {
+    for (i, &pc) in starts.iter().enumerate() {
+        let part = |n: usize| starts.get(i + n).map(|&pc| (code[pc], pc));
+        let (handler, operands, end) = match (loaded_local(code, pc, ALOAD, ALOAD_0), part(1)) {
+            (Some(local), Some((GETFIELD, field_pc))) => (load_field as Handler, Operands(local, instructions[field_pc].operands.0), field_pc + 3),
+            _ => match (loaded_local(code, pc, ILOAD, ILOAD_0), part(1), part(2)) {
+                (Some(a), Some((_, b_pc)), Some((IADD, add_pc))) => match loaded_local(code, b_pc, ILOAD, ILOAD_0) {
+                    Some(b) => (add_locals as Handler, Operands(a, b), add_pc + 1),
+                    None => continue,
+                },
+                _ => continue,
+            },
+        };
+        instructions[pc] = Instruction {handler, operands, length: (end - pc) as u32, fused: true};
+    }
+}
+
+// The local that the instruction at the pc loads, if it's a load of the given kind without wide.
+fn loaded_local(code: &[u8], pc: usize, load: u8, load_0: u8) -> Option<i32> {
+    match code[pc] {
+        opcode if opcode == load => Some(code[pc + 1] as i32),
+        opcode if (load_0..load_0 + 4).contains(&opcode) => Some((opcode - load_0) as i32),
+        _ => None,
+    }
+}
+
+// aload, getfield: reads a field of an object held in a local, most often this.
+fn load_field(vm: &mut Vm, frame: &mut Frame, operands: Operands) -> Result<Flow, VmError> {
+    frame.load(operands.0 as usize)?;
+    frame.next_part(vm)?;
+    step(vm, frame, GETFIELD, Operands(operands.1, 0))
+}
+
+// iload, iload, iadd: adds two int locals.
+fn add_locals(vm: &mut Vm, frame: &mut Frame, operands: Operands) -> Result<Flow, VmError> {
+    frame.load(operands.0 as usize)?;
+    frame.next_part(vm)?;
+    frame.load(operands.1 as usize)?;
+    frame.next_part(vm)?;
+    step(vm, frame, IADD, Operands::default())
+}
+
+// Returns the handler, operands and length of the instruction at the pc, or None if it's cut off.
+fn decode_instruction(code: &[u8], pc: usize) -> Option<(Handler, Operands, usize)> {
+    let u8_at = |offset: usize| code.get(pc + offset).map(|&byte| byte as i32);
+    let i8_at = |offset: usize| code.get(pc + offset).map(|&byte| byte as i8 as i32);
+    let u16_at = |offset: usize| Some((u8_at(offset)? << 8) | u8_at(offset + 1)?);
+    let i16_at = |offset: usize| u16_at(offset).map(|value| value as i16 as i32);
+    let i32_at = |offset: usize| Some((u16_at(offset)? << 16) | u16_at(offset + 2)?);
+
+    let opcode = code[pc];
+    let (operands, length) = match opcode {
+        BIPUSH => (Operands(i8_at(1)?, 0), 2),
+        SIPUSH => (Operands(i16_at(1)?, 0), 3),
+        LDC | ILOAD..=ALOAD | ISTORE..=ASTORE | RET | NEWARRAY => (Operands(u8_at(1)?, 0), 2),
+        LDC_W | LDC2_W | GETSTATIC..=INVOKESTATIC | NEW | ANEWARRAY | CHECKCAST | INSTANCEOF => (Operands(u16_at(1)?, 0), 3),
+        IINC => (Operands(u8_at(1)?, i8_at(2)?), 3),
+        IFEQ..=JSR | IFNULL | IFNONNULL => (Operands(i16_at(1)?, 0), 3),
+        GOTO_W | JSR_W => (Operands(i32_at(1)?, 0), 5),
+        INVOKEINTERFACE | INVOKEDYNAMIC => {
+            // The count and the zero bytes after the index aren't needed, but must be there.
+            u8_at(3)?;
+            u8_at(4)?;
+            (Operands(u16_at(1)?, 0), 5)
+        },
+        MULTIANEWARRAY => (Operands(u16_at(1)?, u8_at(3)?), 4),
+        WIDE => {
+            let modified_opcode = *code.get(pc + 1)?;
+            return match modified_opcode {
+                ILOAD..=ALOAD | ISTORE..=ASTORE | RET => Some((handler(modified_opcode), Operands(u16_at(2)?, 0), 4)),
+                IINC => Some((handler(IINC), Operands(u16_at(2)?, i16_at(4)?), 6)),
+                _ => Some((handler(WIDE), Operands(modified_opcode as i32, 0), 2)),
+            };
+        },
+        TABLESWITCH | LOOKUPSWITCH => {
+            // The operands are read as the switch runs. Bad counts are reported then too, in
+            // which case the rest of the code is taken to be part of the switch.
+            let operands_start = (pc + 4) & !3;
+            let table_length = if opcode == TABLESWITCH {
+                let low = i32_at(operands_start + 4 - pc)? as i64;
+                let high = i32_at(operands_start + 8 - pc)? as i64;
+                12 + 4 * (high - low + 1)
+            } else {
+                8 + 8 * i32_at(operands_start + 4 - pc)? as i64
+            };
+            let length = operands_start as i64 + table_length - pc as i64;
+            let length = if table_length < 0 { code.len() - pc } else { length as usize };
+            if pc + length > code.len() {
+                return None;
+            }
+            (Operands::default(), length)
+        },
+        _ => (Operands::default(), 1),
+    };
+    Some((handler(opcode), operands, length))
+}
+
+fn not_an_instruction(_vm: &mut Vm, frame: &mut Frame, _operands: Operands) -> Result<Flow, VmError> {
+    Err(VmError::InvalidBytecode(format!("No instruction starts at pc {}", frame.instruction_pc)))
+}
+
+fn truncated(_vm: &mut Vm, frame: &mut Frame, _operands: Operands) -> Result<Flow, VmError> {
+    Err(VmError::InvalidBytecode(format!("Execution ran off the end of the code at pc {}", frame.instruction_pc)))
+}
+
+// Finds the handler for an exception thrown by the current instruction, per JVM spec 2.10.
+// Handlers are tried in the order they appear in the exception table.
+fn find_handler(vm: &mut Vm, frame: &Frame, exception: &ObjectRef) -> Result<Option<u16>, VmError> {
+    let pc = frame.instruction_pc;
+    for row in frame.code.exception_table {
+        if pc < row.start_pc as usize || pc >= row.end_pc as usize {
+            continue;
+        }
+
+        // A catch type of zero means the handler catches everything, which is used for `finally`.
+        if row.catch_type.0 == 0 {
+            return Ok(Some(row.handler_pc));
+        }
+
+        let catch_class = resolve::class_ref(vm, frame.class, &row.catch_type)?;
+        if exception.class().is_subclass_of(&catch_class) {
+            return Ok(Some(row.handler_pc));
+        }
+    }
+
+    Ok(None)
+}
+
+enum Flow {
+    Continue,
+    Return(Option<Value>),
+    Invoke(Rc<RuntimeClass>, usize, Vec<Value>), // The resolved method and its arguments.
+}
+
+struct Frame<'a> {
+    class: &'a Rc<RuntimeClass>,
+    code: Code<'a>,
+    instructions: Rc<[Instruction]>,
+    pc: usize,
+    instruction_pc: usize, // The pc of the instruction currently being executed.
+    locals: Vec<Value>,
+    stack: Vec<Value>,
+    method_index: usize,
+}
+
+impl<'a> Frame<'a> {
+    fn resume(class: &'a Rc<RuntimeClass>, code: Code<'a>, instructions: Rc<[Instruction]>, activation: &mut Activation) -> Frame<'a> {
+        Frame {
+            class,
+            code,
+            instructions,
+            pc: activation.pc,
+            instruction_pc: activation.instruction_pc,
+            locals: std::mem::take(&mut activation.locals),
+            stack: std::mem::take(&mut activation.stack),
+            method_index: activation.method_index,
+        }
+    }
+
+    fn suspend(self, activation: &mut Activation) {
+        activation.pc = self.pc;
+        activation.instruction_pc = self.instruction_pc;
+        activation.locals = self.locals;
+        activation.stack = self.stack;
+    }
+
+    // Moves on from a load to the next part of a superinstruction, which is counted and reported
+    // just as the dispatch loop would if it ran on its own. Should the thread stop there, it
+    // resumes from that part, which is still in the instruction stream.
+    #[inline]
+    fn next_part(&mut self, vm: &mut Vm) -> Result<(), VmError> {
+        self.instruction_pc += match self.code.code[self.instruction_pc] {
+            ILOAD | ALOAD => 2,
+            _ => 1,
+        };
+        vm.set_pc(self.instruction_pc);
+        vm.count_instruction()
+    }
+
+    // Reads the operands of tableswitch and lookupswitch, which are the only instructions that
+    // aren't fully decoded in advance.
+    fn read_i32(&mut self) -> Result<i32, VmError> {
+        let bytes = self.code.code.get(self.pc..self.pc + 4).ok_or_else(|| VmError::InvalidBytecode(
+            format!("Execution ran off the end of the code at pc {}", self.pc)))?;
+        self.pc += 4;
+        Ok(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    // The operands of tableswitch and lookupswitch start at the next multiple of four bytes from
+    // the start of the method.
+    fn skip_switch_padding(&mut self) {
+        self.pc = (self.instruction_pc + 4) & !3;
+    }
+
+    // Branch offsets are relative to the start of the branching instruction.
+    #[inline]
+    fn branch(&mut self, offset: i32) -> Result<Flow, VmError> {
+        let target = self.instruction_pc as i64 + offset as i64;
+        if target < 0 || target >= self.code.code.len() as i64 {
+            return Err(VmError::InvalidBytecode(format!("Branch from pc {} to {} leaves the method", self.instruction_pc, target)));
+        }
+
+        self.pc = target as usize;
+        Ok(Flow::Continue)
+    }
+
+    #[inline]
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    #[inline]
+    fn pop(&mut self) -> Result<Value, VmError> {
+        match self.stack.pop() {
+            Some(value) => Ok(value),
+            None => Err(self.stack_underflow()),
+        }
+    }
+
+    #[inline]
+    fn pop_int(&mut self) -> Result<i32, VmError> {
+        match self.pop()? {
+            Value::Int(value) => Ok(value),
+            other => Err(self.type_mismatch("int", &other)),
+        }
+    }
+
+    #[inline]
+    fn pop_long(&mut self) -> Result<i64, VmError> {
+        match self.pop()? {
+            Value::Long(value) => Ok(value),
+            other => Err(self.type_mismatch("long", &other)),
+        }
+    }
+
+    #[inline]
+    fn pop_float(&mut self) -> Result<f32, VmError> {
+        match self.pop()? {
+            Value::Float(value) => Ok(value),
+            other => Err(self.type_mismatch("float", &other)),
+        }
+    }
+
+    #[inline]
+    fn pop_double(&mut self) -> Result<f64, VmError> {
+        match self.pop()? {
+            Value::Double(value) => Ok(value),
+            other => Err(self.type_mismatch("double", &other)),
+        }
+    }
+
+    #[inline]
+    fn pop_reference(&mut self) -> Result<Option<ObjectRef>, VmError> {
+        match self.pop()? {
+            Value::Reference(value) => Ok(value),
+            other => Err(self.type_mismatch("reference", &other)),
+        }
+    }
+
+    // Pops enough values to make up the given number of words, where longs and doubles count as
+    // two words and everything else as one. Returns them in stack order, bottom first. It's an
+    // error for this to split a long or double in half.
+    fn pop_words(&mut self, words: usize) -> Result<Vec<Value>, VmError> {
+        let mut values = vec![];
+        let mut popped = 0;
+        while popped < words {
+            let value = self.pop()?;
+            popped += if value.is_category_2() { 2 } else { 1 };
+            values.push(value);
+        }
+
+        if popped > words {
+            return Err(VmError::InvalidBytecode(format!("Instruction at pc {} would split a category 2 value", self.instruction_pc)));
+        }
+
+        values.reverse();
+        Ok(values)
+    }
+
+    fn pop_args(&mut self, count: usize) -> Result<Vec<Value>, VmError> {
+        if self.stack.len() < count {
+            return Err(self.stack_underflow());
+        }
+
+        let split_at = self.stack.len() - count;
+        Ok(self.stack.split_off(split_at))
+    }
+
+    #[inline]
+    fn load(&mut self, index: usize) -> Result<(), VmError> {
+        match self.locals.get(index) {
+            Some(value) => {
+                let value = value.clone();
+                self.push(value);
+                Ok(())
+            },
+            None => Err(self.local_out_of_range(index)),
+        }
+    }
+
+    #[inline]
+    fn store(&mut self, index: usize) -> Result<(), VmError> {
+        let value = self.pop()?;
+        let slots = if value.is_category_2() { 2 } else { 1 };
+        if index + slots > self.locals.len() {
+            return Err(self.local_out_of_range(index));
+        }
+
+        self.locals[index] = value;
+        Ok(())
+    }
+
+    #[inline]
+    fn increment(&mut self, index: usize, delta: i32) -> Result<(), VmError> {
+        match self.locals.get_mut(index) {
+            Some(Value::Int(ref mut value)) => {
+                *value = value.wrapping_add(delta);
+                Ok(())
+            },
+            other => Err(VmError::InvalidBytecode(format!("Cannot increment local variable {} holding {:?} at pc {}", index, other, self.instruction_pc))),
+        }
+    }
+
+    // Returns from a subroutine to the address held in the given local variable.
+    fn ret(&mut self, index: usize) -> Result<Flow, VmError> {
+        match self.locals.get(index) {
+            Some(Value::ReturnAddress(address)) => {
+                self.pc = *address;
+                Ok(Flow::Continue)
+            },
+            other => Err(VmError::InvalidBytecode(format!("ret to local variable {} holding {:?} at pc {}", index, other, self.instruction_pc))),
+        }
+    }
+
+    // The errors are kept out of line so that the common paths of the helpers above are small
+    // enough to inline into every instruction.
+    #[cold]
+    fn stack_underflow(&self) -> VmError {
+        VmError::InvalidBytecode(format!("Operand stack underflow at pc {}", self.instruction_pc))
+    }
+
+    #[cold]
+    fn local_out_of_range(&self, index: usize) -> VmError {
+        VmError::InvalidBytecode(format!("Local variable {} out of range at pc {}", index, self.instruction_pc))
+    }
+
+    #[cold]
+    fn type_mismatch(&self, expected: &str, actual: &Value) -> VmError {
+        VmError::InvalidBytecode(format!("Expected {} on the operand stack at pc {} but found {:?}", expected, self.instruction_pc, actual))
+    }
+}
+
+macro_rules! binary_op {
+    ($frame:ident, $pop:ident, $variant:ident, |$a:ident, $b:ident| $result:expr) => {{
+        let $b = $frame.$pop()?;
+        let $a = $frame.$pop()?;
+        $frame.push(Value::$variant($result));
+    }};
+}
+
+macro_rules! unary_op {
+    ($frame:ident, $pop:ident, $variant:ident, |$a:ident| $result:expr) => {{
+        let $a = $frame.$pop()?;
+        $frame.push(Value::$variant($result));
+    }};
+}
+
+macro_rules! branch_if {
+    ($frame:ident, $operands:ident, $condition:expr) => {{
+        if $condition {
+            $frame.branch($operands.0)
+        } else {
+            Ok(Flow::Continue)
+        }
+    }};
+}
+
+// Implements fcmpl/fcmpg and dcmpl/dcmpg, which differ only in how they treat NaN.
+fn compare_floats<F: PartialOrd>(a: F, b: F, nan_result: i32) -> i32 {
+    if a > b {
+        1
+    } else if a < b {
+        -1
+    } else if a == b {
+        0
+    } else {
+        nan_result
+    }
+}
+
+// Executes a single instruction, whose operands have already been decoded. This is only called
+// through the handler table, with a constant opcode, so that once it's inlined each handler is
+// reduced to one arm. Debug builds skip the inlining, which would make them slow to compile.
+#[cfg_attr(not(debug_assertions), inline(always))]
+fn step(vm: &mut Vm, frame: &mut Frame, opcode: u8, operands: Operands) -> Result<Flow, VmError> {
+    match opcode {
+        NOP => (),
+        ACONST_NULL => frame.push(Value::null()),
+        ICONST_M1..=ICONST_5 => frame.push(Value::Int(opcode as i32 - ICONST_0 as i32)),
+        LCONST_0..=LCONST_1 => frame.push(Value::Long((opcode - LCONST_0) as i64)),
+        FCONST_0..=FCONST_2 => frame.push(Value::Float((opcode - FCONST_0) as f32)),
+        DCONST_0..=DCONST_1 => frame.push(Value::Double((opcode - DCONST_0) as f64)),
+        BIPUSH | SIPUSH => frame.push(Value::Int(operands.0)),
+
+        LDC | LDC_W | LDC2_W => {
+            let index = ConstantIndex(operands.0 as u16);
+            let value = resolve::load_constant(vm, frame.class, &index)?;
+            if value.is_category_2() != (opcode == LDC2_W) {
+                return Err(VmError::InvalidBytecode(format!("Cannot load constant {} with opcode {:#04x}", index.0, opcode)));
+            }
+            frame.push(value);
+        },
+
+        ILOAD..=ALOAD => frame.load(operands.0 as usize)?,
+        ILOAD_0..=ALOAD_3 => frame.load(((opcode - ILOAD_0) % 4) as usize)?,
+        IALOAD..=SALOAD => {
+            let index = frame.pop_int()?;
+            let array = frame.pop_reference()?.ok_or_else(|| vm.throw_new("java/lang/NullPointerException"))?;
+            let slot = array_slot(vm, &array, index)?;
+            let value = array.fields.borrow().get(slot);
+            frame.push(value);
+        },
+        ISTORE..=ASTORE => frame.store(operands.0 as usize)?,
+        ISTORE_0..=ASTORE_3 => frame.store(((opcode - ISTORE_0) % 4) as usize)?,
+        IASTORE..=SASTORE => {
+            let value = frame.pop()?;
+            let index = frame.pop_int()?;
+            let array = frame.pop_reference()?.ok_or_else(|| vm.throw_new("java/lang/NullPointerException"))?;
+            let slot = array_slot(vm, &array, index)?;
+            let value = match (opcode, value) {
+                // Boolean arrays share byte storage, so they're narrowed here.
+                (BASTORE, Value::Int(value)) if array.class().name == "[Z" => Value::Int(value & 1),
+                (AASTORE, Value::Reference(Some(value))) => {
+                    let component = array.class().component_class.as_ref().ok_or_else(|| VmError::InvalidBytecode(
+                        format!("aastore into {} at pc {}", array.class().name, frame.instruction_pc)))?;
+                    if !value.class().is_assignable_to(component) {
+                        return Err(vm.throw_new_with_message("java/lang/ArrayStoreException", &value.class().java_name()));
+                    }
+                    Value::Reference(Some(value))
+                },
+                (_, value) => value,
+            };
+            if !array.fields.borrow_mut().set(slot, value) {
+                return Err(VmError::InvalidBytecode(format!("Cannot store into {} with opcode {:#04x} at pc {}", array.class().name, opcode, frame.instruction_pc)));
+            }
+        },
+
+        IINC => frame.increment(operands.0 as usize, operands.1)?,
+        // Valid wide instructions are decoded as the instructions they modify, with wider operands.
+        WIDE => return Err(VmError::InvalidBytecode(format!("wide cannot modify opcode {:#04x}", operands.0))),
+
+        // The stack manipulation instructions are defined in terms of 32-bit words, so that e.g.
+        // dup2 duplicates either two ints or a single long. See JVM spec 2.11.1.
+        POP => {
+            frame.pop_words(1)?;
+        },
+        POP2 => {
+            frame.pop_words(2)?;
+        },
+        // A plain dup of an int or reference is by far the most common case, so it skips the
+        // word counting.
+        DUP if frame.stack.last().is_some_and(|top| !top.is_category_2()) => {
+            let top = frame.stack[frame.stack.len() - 1].clone();
+            frame.push(top);
+        },
+        DUP | DUP2 => {
+            let top = frame.pop_words(if opcode == DUP { 1 } else { 2 })?;
+            frame.stack.extend(top.iter().cloned());
+            frame.stack.extend(top);
+        },
+        DUP_X1 | DUP_X2 | DUP2_X1 | DUP2_X2 => {
+            let top = frame.pop_words(if opcode == DUP_X1 || opcode == DUP_X2 { 1 } else { 2 })?;
+            let under = frame.pop_words(if opcode == DUP_X1 || opcode == DUP2_X1 { 1 } else { 2 })?;
+            frame.stack.extend(top.iter().cloned());
+            frame.stack.extend(under);
+            frame.stack.extend(top);
+        },
+        SWAP => {
+            let top = frame.pop_words(1)?;
+            let under = frame.pop_words(1)?;
+            frame.stack.extend(top);
+            frame.stack.extend(under);
+        },
+
+        IADD => binary_op!(frame, pop_int, Int, |a, b| a.wrapping_add(b)),
+        LADD => binary_op!(frame, pop_long, Long, |a, b| a.wrapping_add(b)),
+        FADD => binary_op!(frame, pop_float, Float, |a, b| a + b),
+        DADD => binary_op!(frame, pop_double, Double, |a, b| a + b),
+        ISUB => binary_op!(frame, pop_int, Int, |a, b| a.wrapping_sub(b)),
+        LSUB => binary_op!(frame, pop_long, Long, |a, b| a.wrapping_sub(b)),
+        FSUB => binary_op!(frame, pop_float, Float, |a, b| a - b),
+        DSUB => binary_op!(frame, pop_double, Double, |a, b| a - b),
+        IMUL => binary_op!(frame, pop_int, Int, |a, b| a.wrapping_mul(b)),
+        LMUL => binary_op!(frame, pop_long, Long, |a, b| a.wrapping_mul(b)),
+        FMUL => binary_op!(frame, pop_float, Float, |a, b| a * b),
+        DMUL => binary_op!(frame, pop_double, Double, |a, b| a * b),
+        // Integer division by zero throws, whereas floating point division yields infinity or NaN.
+        // Dividing the minimum value by -1 overflows back to the minimum value, per JVM spec 6.5.
+        IDIV | IREM => {
+            let b = frame.pop_int()?;
+            let a = frame.pop_int()?;
+            if b == 0 {
+                return Err(vm.throw_new_with_message("java/lang/ArithmeticException", "/ by zero"));
+            }
+            frame.push(Value::Int(if opcode == IDIV { a.wrapping_div(b) } else { a.wrapping_rem(b) }));
+        },
+        LDIV | LREM => {
+            let b = frame.pop_long()?;
+            let a = frame.pop_long()?;
+            if b == 0 {
+                return Err(vm.throw_new_with_message("java/lang/ArithmeticException", "/ by zero"));
+            }
+            frame.push(Value::Long(if opcode == LDIV { a.wrapping_div(b) } else { a.wrapping_rem(b) }));
+        },
+        FDIV => binary_op!(frame, pop_float, Float, |a, b| a / b),
+        DDIV => binary_op!(frame, pop_double, Double, |a, b| a / b),
+        FREM => binary_op!(frame, pop_float, Float, |a, b| a % b),
+        DREM => binary_op!(frame, pop_double, Double, |a, b| a % b),
+        INEG => unary_op!(frame, pop_int, Int, |a| a.wrapping_neg()),
+        LNEG => unary_op!(frame, pop_long, Long, |a| a.wrapping_neg()),
+        FNEG => unary_op!(frame, pop_float, Float, |a| -a),
+        DNEG => unary_op!(frame, pop_double, Double, |a| -a),
+
+        // Shift distances are masked to the width of the shifted type, per JVM spec 6.5.
+        ISHL => binary_op!(frame, pop_int, Int, |a, b| a.wrapping_shl(b as u32 & 0x1f)),
+        ISHR => binary_op!(frame, pop_int, Int, |a, b| a.wrapping_shr(b as u32 & 0x1f)),
+        IUSHR => binary_op!(frame, pop_int, Int, |a, b| (a as u32).wrapping_shr(b as u32 & 0x1f) as i32),
+        LSHL | LSHR | LUSHR => {
+            let distance = frame.pop_int()? as u32 & 0x3f;
+            let value = frame.pop_long()?;
+            frame.push(Value::Long(match opcode {
+                LSHL => value.wrapping_shl(distance),
+                LSHR => value.wrapping_shr(distance),
+                _ => (value as u64).wrapping_shr(distance) as i64,
+            }));
+        },
+        IAND => binary_op!(frame, pop_int, Int, |a, b| a & b),
+        LAND => binary_op!(frame, pop_long, Long, |a, b| a & b),
+        IOR => binary_op!(frame, pop_int, Int, |a, b| a | b),
+        LOR => binary_op!(frame, pop_long, Long, |a, b| a | b),
+        IXOR => binary_op!(frame, pop_int, Int, |a, b| a ^ b),
+        LXOR => binary_op!(frame, pop_long, Long, |a, b| a ^ b),
+
+        // Rust's float-to-int casts saturate and map NaN to zero, exactly as Java's do.
+        I2L => unary_op!(frame, pop_int, Long, |a| a as i64),
+        I2F => unary_op!(frame, pop_int, Float, |a| a as f32),
+        I2D => unary_op!(frame, pop_int, Double, |a| a as f64),
+        L2I => unary_op!(frame, pop_long, Int, |a| a as i32),
+        L2F => unary_op!(frame, pop_long, Float, |a| a as f32),
+        L2D => unary_op!(frame, pop_long, Double, |a| a as f64),
+        F2I => unary_op!(frame, pop_float, Int, |a| a as i32),
+        F2L => unary_op!(frame, pop_float, Long, |a| a as i64),
+        F2D => unary_op!(frame, pop_float, Double, |a| a as f64),
+        D2I => unary_op!(frame, pop_double, Int, |a| a as i32),
+        D2L => unary_op!(frame, pop_double, Long, |a| a as i64),
+        D2F => unary_op!(frame, pop_double, Float, |a| a as f32),
+        I2B => unary_op!(frame, pop_int, Int, |a| a as i8 as i32),
+        I2C => unary_op!(frame, pop_int, Int, |a| a as u16 as i32),
+        I2S => unary_op!(frame, pop_int, Int, |a| a as i16 as i32),
+
+        LCMP => binary_op!(frame, pop_long, Int, |a, b| a.cmp(&b) as i32),
+        FCMPL => binary_op!(frame, pop_float, Int, |a, b| compare_floats(a, b, -1)),
+        FCMPG => binary_op!(frame, pop_float, Int, |a, b| compare_floats(a, b, 1)),
+        DCMPL => binary_op!(frame, pop_double, Int, |a, b| compare_floats(a, b, -1)),
+        DCMPG => binary_op!(frame, pop_double, Int, |a, b| compare_floats(a, b, 1)),
+
+        IFEQ => return branch_if!(frame, operands, frame.pop_int()? == 0),
+        IFNE => return branch_if!(frame, operands, frame.pop_int()? != 0),
+        IFLT => return branch_if!(frame, operands, frame.pop_int()? < 0),
+        IFGE => return branch_if!(frame, operands, frame.pop_int()? >= 0),
+        IFGT => return branch_if!(frame, operands, frame.pop_int()? > 0),
+        IFLE => return branch_if!(frame, operands, frame.pop_int()? <= 0),
+        IF_ICMPEQ..=IF_ICMPLE => {
+            let b = frame.pop_int()?;
+            let a = frame.pop_int()?;
+            return branch_if!(frame, operands, match opcode {
+                IF_ICMPEQ => a == b,
+                IF_ICMPNE => a != b,
+                IF_ICMPLT => a < b,
+                IF_ICMPGE => a >= b,
+                IF_ICMPGT => a > b,
+                _ => a <= b,
+            });
+        },
+        IF_ACMPEQ | IF_ACMPNE => {
+            let b = frame.pop_reference()?;
+            let a = frame.pop_reference()?;
+            return branch_if!(frame, operands, (a == b) == (opcode == IF_ACMPEQ));
+        },
+        IFNULL => return branch_if!(frame, operands, frame.pop_reference()?.is_none()),
+        IFNONNULL => return branch_if!(frame, operands, frame.pop_reference()?.is_some()),
+        GOTO | GOTO_W => return frame.branch(operands.0),
+        JSR | JSR_W => {
+            frame.push(Value::ReturnAddress(frame.pc));
+            return frame.branch(operands.0);
+        },
+        RET => return frame.ret(operands.0 as usize),
+        TABLESWITCH => {
+            let key = frame.pop_int()?;
+            frame.skip_switch_padding();
+            let default = frame.read_i32()?;
+            let low = frame.read_i32()?;
+            let high = frame.read_i32()?;
+            if low > high {
+                return Err(VmError::InvalidBytecode(format!("tableswitch at pc {} has low {} above high {}", frame.instruction_pc, low, high)));
+            }
+
+            let offset = if key < low || key > high {
+                default
+            } else {
+                frame.pc += 4 * (key as i64 - low as i64) as usize;
+                frame.read_i32()?
+            };
+            return frame.branch(offset);
+        },
+        LOOKUPSWITCH => {
+            let key = frame.pop_int()?;
+            frame.skip_switch_padding();
+            let default = frame.read_i32()?;
+            let pair_count = frame.read_i32()?;
+            if pair_count < 0 {
+                return Err(VmError::InvalidBytecode(format!("lookupswitch at pc {} has {} pairs", frame.instruction_pc, pair_count)));
+            }
+
+            // The match-offset pairs are sorted by key, so we can binary search them in place.
+            let pairs_start = frame.pc;
+            let (mut low, mut high) = (0, pair_count as usize);
+            let mut offset = default;
+            while low < high {
+                let middle = low + (high - low) / 2;
+                frame.pc = pairs_start + 8 * middle;
+                let candidate = frame.read_i32()?;
+                if candidate == key {
+                    offset = frame.read_i32()?;
+                    break;
+                } else if candidate < key {
+                    low = middle + 1;
+                } else {
+                    high = middle;
+                }
+            }
+            return frame.branch(offset);
+        },
+
+        IRETURN => return Ok(Flow::Return(Some(Value::Int(frame.pop_int()?)))),
+        LRETURN => return Ok(Flow::Return(Some(Value::Long(frame.pop_long()?)))),
+        FRETURN => return Ok(Flow::Return(Some(Value::Float(frame.pop_float()?)))),
+        DRETURN => return Ok(Flow::Return(Some(Value::Double(frame.pop_double()?)))),
+        ARETURN => return Ok(Flow::Return(Some(Value::Reference(frame.pop_reference()?)))),
+        RETURN => return Ok(Flow::Return(None)),
+
+        GETSTATIC | PUTSTATIC => {
+            let index = ConstantIndex(operands.0 as u16);
+            let (declaring_class, slot) = resolve::static_field_ref(vm, frame.class, &index)?;
+            vm.initialize_or_suspend(&declaring_class)?;
+
+            if opcode == GETSTATIC {
+                frame.push(declaring_class.get_static(slot));
+            } else {
+                let value = frame.pop()?;
+                declaring_class.put_static(slot, value);
+            }
+        },
+        GETFIELD | PUTFIELD => {
+            let index = ConstantIndex(operands.0 as u16);
+            let (slot, checks_receiver) = resolve::instance_field_ref(vm, frame.class, &index)?;
+
+            if opcode == GETFIELD {
+                let object = frame.pop_reference()?.ok_or_else(|| vm.throw_new("java/lang/NullPointerException"))?;
+                if checks_receiver {
+                    resolve::check_receiver(vm, frame.class, &index, object.class())?;
+                }
+                let value = match object.is_array() {
+                    false => object.fields.borrow().try_get(slot),
+                    true => None,
+                };
+                frame.push(value.ok_or_else(|| missing_field(frame.class, &index, &object))?);
+            } else {
+                let value = frame.pop()?;
+                let object = frame.pop_reference()?.ok_or_else(|| vm.throw_new("java/lang/NullPointerException"))?;
+                if checks_receiver {
+                    resolve::check_receiver(vm, frame.class, &index, object.class())?;
+                }
+                if object.is_array() || !object.fields.borrow_mut().set(slot, value) {
+                    return Err(missing_field(frame.class, &index, &object));
+                }
+            }
+        },
+
+        INVOKEVIRTUAL | INVOKESPECIAL | INVOKESTATIC | INVOKEINTERFACE => {
+            return resolve_invocation(vm, frame, opcode, &ConstantIndex(operands.0 as u16));
+        },
+
+        NEW => {
+            let index = ConstantIndex(operands.0 as u16);
+            let class = resolve::class_ref(vm, frame.class, &index)?;
+            if class.is_interface() || class.class.flags.contains(ClassFlags::ABSTRACT) {
+                return Err(VmError::InvalidBytecode(format!("Cannot instantiate abstract class or interface {}", class.name)));
+            }
+            vm.initialize_or_suspend(&class)?;
+            vm.check_heap()?;
+            let object = vm.new_object(&class);
+            if class.is_throwable() {
+                vm.fill_in_stack_trace(&object)?;
+            }
+            frame.push(Value::Reference(Some(object)));
+        },
+
+        NEWARRAY => {
+            let class_name = match operands.0 {
+                4 => "[Z",
+                5 => "[C",
+                6 => "[F",
+                7 => "[D",
+                8 => "[B",
+                9 => "[S",
+                10 => "[I",
+                11 => "[J",
+                atype => return Err(VmError::InvalidBytecode(format!("Unknown newarray type {} at pc {}", atype, frame.instruction_pc))),
+            };
+            let class = vm.load_class(class_name)?;
+            let length = frame.pop_int()?;
+            let array = new_array(vm, &class, &[length])?;
+            frame.push(Value::Reference(Some(array)));
+        },
+        ANEWARRAY => {
+            let index = ConstantIndex(operands.0 as u16);
+            let component = resolve::class_ref(vm, frame.class, &index)?;
+            let class = vm.load_class_with(component.loader, &format!("[{}", resolve::reference_descriptor(&component.name)))?;
+            let length = frame.pop_int()?;
+            let array = new_array(vm, &class, &[length])?;
+            frame.push(Value::Reference(Some(array)));
+        },
+        MULTIANEWARRAY => {
+            let index = ConstantIndex(operands.0 as u16);
+            let dimensions = operands.1 as usize;
+            let class = resolve::class_ref(vm, frame.class, &index)?;
+            if dimensions == 0 || class.name.bytes().take_while(|&b| b == b'[').count() < dimensions {
+                return Err(VmError::InvalidBytecode(format!("multianewarray of {} with {} dimensions", class.name, dimensions)));
+            }
+
+            let lengths: Vec<i32> = frame.pop_args(dimensions)?.into_iter().map(|length| match length {
+                Value::Int(length) => Ok(length),
+                other => Err(frame.type_mismatch("int", &other)),
+            }).collect::<Result<_, _>>()?;
+            let array = new_array(vm, &class, &lengths)?;
+            frame.push(Value::Reference(Some(array)));
+        },
+        ARRAYLENGTH => {
+            let array = frame.pop_reference()?.ok_or_else(|| vm.throw_new("java/lang/NullPointerException"))?;
+            let length = array.fields.borrow().len();
+            frame.push(Value::Int(length as i32));
+        },
+
+        ATHROW => {
+            let exception = frame.pop_reference()?.ok_or_else(|| vm.throw_new("java/lang/NullPointerException"))?;
+            return Err(VmError::UncaughtException(exception));
+        },
+
+        CHECKCAST | INSTANCEOF => {
+            let index = ConstantIndex(operands.0 as u16);
+            let target = resolve::class_ref(vm, frame.class, &index)?;
+            let object = frame.pop_reference()?;
+            let is_instance = object.as_ref().is_some_and(|object| object.class().is_assignable_to(&target));
+
+            if opcode == INSTANCEOF {
+                frame.push(Value::Int(is_instance as i32));
+            } else if is_instance || object.is_none() {
+                frame.push(Value::Reference(object));
+            } else {
+                let source = object.unwrap().class().java_name();
+                let message = format!("class {} cannot be cast to class {}", source, target.java_name());
+                return Err(vm.throw_new_with_message("java/lang/ClassCastException", &message));
+            }
+        },
+
+        MONITORENTER => {
+            let object = frame.pop_reference()?.ok_or_else(|| vm.throw_new("java/lang/NullPointerException"))?;
+            if let Err(err) = threads::suspend_for_monitor(vm, &object) {
+                // The instruction runs again when the thread resumes.
+                frame.push(Value::Reference(Some(object)));
+                return Err(err);
+            }
+            threads::enter_monitor(vm, &object)?;
+        },
+        MONITOREXIT => {
+            let object = frame.pop_reference()?.ok_or_else(|| vm.throw_new("java/lang/NullPointerException"))?;
+            threads::exit_monitor(vm, &object)?;
+        },
+
+        _ => return Err(VmError::UnsupportedOpcode(opcode)),
+    }
+
+    Ok(Flow::Continue)
+}
+
+// Creates an array with the given length in each dimension, so that e.g. lengths of [3, 4] give
+// an array of three arrays of four elements. Any remaining dimensions are left null. No array is
+// created if any of the lengths is negative, per JVM spec 6.5.multianewarray.
+fn new_array(vm: &mut Vm, class: &Rc<RuntimeClass>, lengths: &[i32]) -> Result<ObjectRef, VmError> {
+    if let Some(length) = lengths.iter().find(|&&length| length < 0) {
+        return Err(vm.throw_new_with_message("java/lang/NegativeArraySizeException", &length.to_string()));
+    }
+
+    let array = vm.new_array(class, lengths[0] as usize)?;
+    if lengths.len() > 1 {
+        let component = class.component_class.as_ref().ok_or_else(|| VmError::InvalidBytecode(
+            format!("{} has fewer than {} dimensions", class.name, lengths.len())))?;
+        for element in array.fields.borrow_mut().values_mut().unwrap_or_default() {
+            *element = Value::Reference(Some(new_array(vm, component, &lengths[1..])?));
+        }
+    }
+    Ok(array)
+}
+
+// Converts an array index into a slot in the array's storage, throwing if it's out of bounds.
+fn array_slot(vm: &mut Vm, array: &ObjectRef, index: i32) -> Result<usize, VmError> {
+    let length = array.fields.borrow().len();
+    if index < 0 || index as usize >= length {
+        let message = format!("Index {} out of bounds for length {}", index, length);
+        return Err(vm.throw_new_with_message("java/lang/ArrayIndexOutOfBoundsException", &message));
+    }
+    Ok(index as usize)
+}
+
+// The error for getfield or putfield on an object without the field, or with a value of the
+// wrong type for it. Unverified code can get this far, so it's reported rather than panicking.
+fn missing_field(class: &RuntimeClass, index: &ConstantIndex, object: &ObjectRef) -> VmError {
+    let field = match class.class.member_ref(index) {
+        Ok(field) => format!("{}.{}", field.class, field.name),
+        Err(err) => return err.into(),
+    };
+    VmError::InvalidBytecode(format!("Field {} used on an instance of {}", field, object.class().name))
+}
+
+// Pops the arguments for an invoke instruction and selects the method to run, per JVM spec 5.4.3.3
+// and the descriptions of each invoke instruction in chapter 6.
+fn resolve_invocation(vm: &mut Vm, frame: &mut Frame, opcode: u8, index: &ConstantIndex) -> Result<Flow, VmError> {
+    let (declaring_class, method_index, parameter_count, checks_receiver) = resolve::method_ref(vm, frame.class, index)?;
+    let method = &declaring_class.class.methods[method_index];
+    let name = declaring_class.class.utf8(&method.name)?;
+    let descriptor = declaring_class.class.utf8(&method.descriptor)?;
+    let is_static = method.flags.contains(MethodFlags::STATIC);
+    if is_static != (opcode == INVOKESTATIC) {
+        let expected = if is_static { "non-static" } else { "static" };
+        let message = format!("Expected {} method {}.{}{}", expected, declaring_class.name, name, descriptor);
+        return Err(vm.throw_new_with_message("java/lang/IncompatibleClassChangeError", &message));
+    }
+    if opcode == INVOKESTATIC {
+        vm.initialize_or_suspend(&declaring_class)?;
+    }
+
+    let arg_count = parameter_count + if opcode == INVOKESTATIC { 0 } else { 1 };
+    let mut args = frame.pop_args(arg_count)?;
+
+    // invokeExact and invoke take the call site's descriptor, so they're run here rather than as
+    // natives with their declared one.
+    if method_handles::is_signature_polymorphic(&declaring_class, method_index) {
+        let call_site = frame.class.class.member_ref(index)?.descriptor;
+        let handle = match args.remove(0) {
+            Value::Reference(Some(handle)) => handle,
+            Value::Reference(None) => return Err(vm.throw_new("java/lang/NullPointerException")),
+            ref other => return Err(frame.type_mismatch("reference", other)),
+        };
+        let result = method_handles::invoke_handle(vm, frame.class, &handle, call_site, name == "invokeExact", args)?;
+        if let Some(result) = result {
+            frame.push(result);
+        }
+        return Ok(Flow::Continue);
+    }
+
+    let receiver_class = match opcode {
+        INVOKESTATIC => return Ok(Flow::Invoke(declaring_class, method_index, args)),
+        _ => match args[0] {
+            Value::Reference(Some(ref receiver)) => Rc::clone(receiver.class()),
+            Value::Reference(None) => return Err(vm.throw_new("java/lang/NullPointerException")),
+            ref other => return Err(frame.type_mismatch("reference", other)),
+        },
+    };
+    if checks_receiver {
+        resolve::check_receiver(vm, frame.class, index, &receiver_class)?;
+    }
+
+    // The resolved method runs as is for invokespecial, and for virtual calls on an instance of
+    // the class that declares it. Otherwise the receiver's class may override it, so the method
+    // is selected through the call site's inline cache.
+    if opcode == INVOKESPECIAL || Rc::ptr_eq(&receiver_class, &declaring_class) {
+        check_implemented(vm, &receiver_class, &declaring_class, method_index)?;
+        return Ok(Flow::Invoke(declaring_class, method_index, args));
+    }
+    if let Some((selected_class, selected_index)) = frame.class.inline_cache_lookup(index.0, &receiver_class) {
+        return Ok(Flow::Invoke(selected_class, selected_index, args));
+    }
+
+    if opcode == INVOKEINTERFACE && !receiver_class.is_subclass_of(&declaring_class) {
+        let message = format!("Class {} does not implement the requested interface {}", receiver_class.name, declaring_class.name);
+        return Err(vm.throw_new_with_message("java/lang/IncompatibleClassChangeError", &message));
+    }
+    let (selected_class, selected_index) = match resolve_method(&receiver_class, name, descriptor)? {
+        Some(selected) => selected,
+        None => (declaring_class, method_index),
+    };
+    check_implemented(vm, &receiver_class, &selected_class, selected_index)?;
+
+    frame.class.inline_cache_record(index.0, &receiver_class, &selected_class, selected_index);
+    Ok(Flow::Invoke(selected_class, selected_index, args))
+}
+
+// Throws AbstractMethodError if the method selected for a call is abstract, which happens when
+// the receiver's class was compiled before the method was added to a class or interface above it.
+pub fn check_implemented(vm: &mut Vm, receiver_class: &RuntimeClass, class: &RuntimeClass, method_index: usize) -> Result<(), VmError> {
+    let method = &class.class.methods[method_index];
+    if !method.flags.contains(MethodFlags::ABSTRACT) {
+        return Ok(());
+    }
+    let message = format!("Receiver class {} does not define or inherit an implementation of the resolved method {}.{}{}",
+        receiver_class.name, class.name, class.class.utf8(&method.name)?, class.class.utf8(&method.descriptor)?);
+    Err(vm.throw_new_with_message("java/lang/AbstractMethodError", &message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use crate::direct::DirectMemoryError;
+    use crate::hooks::ExecutionHooks;
+    use crate::inline_cache::InlineCache;
+    use crate::limits::{LimitViolation, Limits};
+    use crate::vm::{self, DEFAULT_MAX_STACK_DEPTH};
+    use std::cell::RefCell;
+
+    const EXCEPTIONS: &[&[u8]] = &[
+        fixture!("Exceptions"),
+        fixture!("Boom"),
+        fixture!("Bang"),
+        fixture!("Holder"),
+    ];
+
+    const LITERALS: &[&[u8]] = &[
+        fixture!("Literals"),
+        fixture!("OtherLiterals"),
+    ];
+
+    const CONSTANTS: &[&[u8]] = &[
+        fixture!("Constants"),
+        fixture!("Holder"),
+    ];
+
+    fn run_int(vm: &mut Vm, class: &str, method: &str) -> i32 {
+        match vm.invoke_static(class, method, "()I", vec![]) {
+            Ok(Some(Value::Int(result))) => result,
+            other => panic!("Expected an int result from {}.{}; got {:#?}", class, method, other),
+        }
+    }
+
+    const SWITCHES: &[&[u8]] = &[
+        fixture!("Switches"),
+    ];
+
+    const WIDE_AND_SUBROUTINES: &[&[u8]] = &[
+        fixture!("Wide"),
+        fixture!("Subroutines"),
+    ];
+
+    const STACK_OPS: &[&[u8]] = &[
+        fixture!("StackOps"),
+        fixture!("Dups"),
+    ];
+
+    const RECURSION: &[&[u8]] = &[
+        fixture!("Recursion"),
+    ];
+
+    const ARITHMETIC: &[&[u8]] = &[
+        fixture!("Arithmetic"),
+    ];
+
+    fn call_int(vm: &mut Vm, class: &str, method: &str, arg: i32) -> i32 {
+        match vm.invoke_static(class, method, "(I)I", vec![Value::Int(arg)]) {
+            Ok(Some(Value::Int(result))) => result,
+            other => panic!("Expected an int result from {}.{}({}); got {:#?}", class, method, arg, other),
+        }
+    }
+
+    fn run(vm: &mut Vm, class: &str, method: &str, descriptor: &str) -> Value {
+        match vm.invoke_static(class, method, descriptor, vec![]) {
+            Ok(Some(result)) => result,
+            other => panic!("Expected a result from {}.{}; got {:#?}", class, method, other),
+        }
+    }
+
+    fn run_object(vm: &mut Vm, class: &str, method: &str, descriptor: &str) -> ObjectRef {
+        match run(vm, class, method, descriptor) {
+            Value::Reference(Some(object)) => object,
+            other => panic!("Expected an object from {}.{}; got {:#?}", class, method, other),
+        }
+    }
+
+    fn run_string(vm: &mut Vm, class: &str, method: &str) -> String {
+        let string = run_object(vm, class, method, "()Ljava/lang/String;");
+        vm.read_string(&string).unwrap()
+    }
+
+    fn string_field(vm: &Vm, object: &ObjectRef, name: &str) -> String {
+        match vm::get_field(object, name).unwrap() {
+            Value::Reference(Some(string)) => vm.read_string(&string).unwrap(),
+            other => panic!("Expected {} to be a string; got {:#?}", name, other),
+        }
+    }
+
+    fn object_field(object: &ObjectRef, name: &str) -> ObjectRef {
+        match vm::get_field(object, name).unwrap() {
+            Value::Reference(Some(value)) => value,
+            other => panic!("Expected {} to be an object; got {:#?}", name, other),
+        }
+    }
+
+    fn expect_uncaught(vm: &mut Vm, class: &str, method: &str, exception_class: &str) {
+        match vm.invoke_static(class, method, "()V", vec![]) {
+            Err(VmError::UncaughtException(ref exception)) if exception.class().name == exception_class => (),
+            other => panic!("Expected uncaught {} from {}.{}; got {:#?}", exception_class, class, method, other),
+        }
+    }
+
+    #[test]
+    fn test_catch_exact_exception_type() {
+        assert_eq!(1, run_int(&mut vm_with(EXCEPTIONS), "Exceptions", "catchExactType"));
+    }
+
+    #[test]
+    fn test_catch_exception_by_superclass() {
+        assert_eq!(2, run_int(&mut vm_with(EXCEPTIONS), "Exceptions", "catchSuperclass"));
+    }
+
+    #[test]
+    fn test_catch_exception_thrown_by_callee() {
+        assert_eq!(3, run_int(&mut vm_with(EXCEPTIONS), "Exceptions", "catchFromCallee"));
+    }
+
+    #[test]
+    fn test_exception_unwinds_several_frames() {
+        assert_eq!(4, run_int(&mut vm_with(EXCEPTIONS), "Exceptions", "catchFromDeepCallee"));
+    }
+
+    #[test]
+    fn test_handler_for_unrelated_type_is_skipped() {
+        assert_eq!(5, run_int(&mut vm_with(EXCEPTIONS), "Exceptions", "skipsNonMatchingHandler"));
+    }
+
+    #[test]
+    fn test_first_matching_handler_wins() {
+        assert_eq!(6, run_int(&mut vm_with(EXCEPTIONS), "Exceptions", "firstMatchingHandlerWins"));
+    }
+
+    #[test]
+    fn test_finally_block_runs_before_exception_propagates() {
+        assert_eq!(7, run_int(&mut vm_with(EXCEPTIONS), "Exceptions", "finallyRunsBeforePropagating"));
+    }
+
+    #[test]
+    fn test_exception_thrown_from_handler_is_caught_by_outer_handler() {
+        assert_eq!(8, run_int(&mut vm_with(EXCEPTIONS), "Exceptions", "rethrowFromHandler"));
+    }
+
+    #[test]
+    fn test_handler_receives_the_thrown_object() {
+        assert_eq!(9, run_int(&mut vm_with(EXCEPTIONS), "Exceptions", "caughtExceptionIsTheThrownObject"));
+    }
+
+    #[test]
+    fn test_handler_starts_with_only_the_exception_on_the_stack() {
+        assert_eq!(10, run_int(&mut vm_with(EXCEPTIONS), "Exceptions", "handlerClearsOperandStack"));
+    }
+
+    #[test]
+    fn test_getfield_on_null_throws_null_pointer_exception() {
+        assert_eq!(11, run_int(&mut vm_with(EXCEPTIONS), "Exceptions", "catchNullPointerFromFieldAccess"));
+    }
+
+    #[test]
+    fn test_invokevirtual_on_null_throws_null_pointer_exception() {
+        assert_eq!(12, run_int(&mut vm_with(EXCEPTIONS), "Exceptions", "catchNullPointerFromInvocation"));
+    }
+
+    #[test]
+    fn test_athrow_of_null_throws_null_pointer_exception() {
+        assert_eq!(13, run_int(&mut vm_with(EXCEPTIONS), "Exceptions", "catchNullPointerFromThrowingNull"));
+    }
+
+    #[test]
+    fn test_uncaught_exception_is_returned_to_embedder() {
+        expect_uncaught(&mut vm_with(EXCEPTIONS), "Exceptions", "uncaught", "Boom");
+    }
+
+    #[test]
+    fn test_uncaught_error_is_returned_to_embedder() {
+        expect_uncaught(&mut vm_with(EXCEPTIONS), "Exceptions", "uncaughtError", "java/lang/Error");
+    }
+
+    #[test]
+    fn test_ldc_int() {
+        assert_eq!(Value::Int(123456789), run(&mut vm_with(LITERALS), "Literals", "bigInt", "()I"));
+    }
+
+    #[test]
+    fn test_ldc_float() {
+        assert_eq!(Value::Float(3.5), run(&mut vm_with(LITERALS), "Literals", "aFloat", "()F"));
+    }
+
+    #[test]
+    fn test_ldc2_w_long() {
+        assert_eq!(Value::Long(1234567890123), run(&mut vm_with(LITERALS), "Literals", "aLong", "()J"));
+    }
+
+    #[test]
+    fn test_ldc2_w_double() {
+        assert_eq!(Value::Double(2.5e100), run(&mut vm_with(LITERALS), "Literals", "aDouble", "()D"));
+    }
+
+    #[test]
+    fn test_ldc_string() {
+        assert_eq!("hello, world", run_string(&mut vm_with(LITERALS), "Literals", "aString"));
+    }
+
+    #[test]
+    fn test_ldc_non_ascii_string() {
+        assert_eq!("h\u{e9}llo \u{2603}", run_string(&mut vm_with(LITERALS), "Literals", "nonAscii"));
+    }
+
+    #[test]
+    fn test_ldc_w_string() {
+        assert_eq!("loaded with ldc_w", run_string(&mut vm_with(CONSTANTS), "Constants", "wideString"));
+    }
+
+    #[test]
+    fn test_string_literals_are_interned_across_classes() {
+        assert_eq!(Value::Int(1), run(&mut vm_with(LITERALS), "Literals", "stringsAreInterned", "()Z"));
+    }
+
+    #[test]
+    fn test_intern_returns_pooled_literal() {
+        let mut vm = vm_with(&[fixture!("Interning")]);
+        assert_eq!(Value::Int(1), run(&mut vm, "Interning", "internReturnsLiteral", "()Z"));
+    }
+
+    #[test]
+    fn test_intern_adds_new_strings_to_pool() {
+        let mut vm = vm_with(&[fixture!("Interning")]);
+        assert_eq!(Value::Int(1), run(&mut vm, "Interning", "internAddsNewStrings", "()Z"));
+        assert!(vm.string_pool().get("new").is_some());
+    }
+
+    #[test]
+    fn test_boxing_caches() {
+        let mut vm = vm_with(&[fixture!("Boxing")]);
+        assert_eq!(Value::Int(1), run(&mut vm, "Boxing", "smallIntegersAreShared", "()Z"));
+        assert_eq!(Value::Int(1), run(&mut vm, "Boxing", "largeIntegersAreNot", "()Z"));
+        assert_eq!(Value::Int(1), run(&mut vm, "Boxing", "otherTypesAreCached", "()Z"));
+        assert_eq!(Value::Int(1), run(&mut vm, "Boxing", "wideCharactersAreNot", "()Z"));
+        assert_eq!(Value::Int(42), run(&mut vm, "Boxing", "sum", "()I"));
+    }
+
+    #[test]
+    fn test_identity_hash_codes() {
+        let mut vm = vm_with(&[fixture!("Hashing")]);
+        assert_eq!(Value::Int(1), run(&mut vm, "Hashing", "hashIsStable", "()Z"));
+        assert_eq!(Value::Int(1), run(&mut vm, "Hashing", "distinctObjectsHaveDistinctHashes", "()Z"));
+        assert_eq!(Value::Int(0), run(&mut vm, "Hashing", "nullHash", "()I"));
+        assert_eq!(Value::Int(1), run(&mut vm, "Hashing", "stringsHashTheirContents", "()Z"));
+    }
+
+    #[test]
+    fn test_direct_memory() {
+        let mut vm = vm_with(&[fixture!("DirectMemory")]);
+        assert_eq!(Value::Int(1), run(&mut vm, "DirectMemory", "unsafeRoundTrip", "()Z"));
+        assert_eq!(Value::Int(0x01020304 + 1 + 4), run(&mut vm, "DirectMemory", "bufferRoundTrip", "()I"));
+        expect_uncaught(&mut vm, "DirectMemory", "bufferOutOfBounds", "java/lang/IndexOutOfBoundsException");
+        match vm.invoke_static("DirectMemory", "useAfterFree", "()V", vec![]) {
+            Err(VmError::DirectMemory(DirectMemoryError::InvalidAddress{length: 1, ..})) => (),
+            other => panic!("Expected an invalid address; got {:#?}", other),
+        }
+
+        // Sizes the host can't allocate are Java's problem rather than the process's.
+        expect_uncaught(&mut vm, "DirectMemory", "allocateTooMuch", "java/lang/OutOfMemoryError");
+        expect_uncaught(&mut vm, "DirectMemory", "reallocateTooMuch", "java/lang/OutOfMemoryError");
+        match vm.invoke_static("DirectMemory", "copyTooMuch", "()V", vec![]) {
+            Err(VmError::DirectMemory(DirectMemoryError::InvalidAddress{length: 0x7fff_ffff_ffff_ffff, ..})) => (),
+            other => panic!("Expected an invalid address; got {:#?}", other),
+        }
+    }
+
+    #[test]
+    fn test_max_direct_memory() {
+        let mut vm = vm_with(&[fixture!("DirectMemory")]);
+        vm.set_max_direct_memory(Some(4096));
+        assert_eq!(Ok(None), vm.invoke_static("DirectMemory", "dropBuffers", "()V", vec![]));
+        assert!(vm.gc_totals().collections > 0);
+        expect_uncaught(&mut vm, "DirectMemory", "holdBuffers", "java/lang/OutOfMemoryError");
+        vm.collect_garbage(GcCause::Requested);
+        assert_eq!(0, vm.direct_memory().reserved());
+    }
+
+    #[test]
+    fn test_direct_memory_counts_towards_allocation_limit() {
+        let mut vm = vm_with(&[fixture!("DirectMemory")]);
+        vm.set_limits(Limits {max_allocated_bytes: Some(50_000), ..Limits::default()});
+        assert_eq!(Err(VmError::LimitExceeded(LimitViolation::AllocatedBytes(50_000))), vm.invoke_static("DirectMemory", "holdBuffers", "()V", vec![]));
+
+        vm.set_limits(Limits::default());
+        let address = vm.allocate_direct(None, 10_000).unwrap();
+        assert_eq!(10_000, vm.allocated_bytes());
+        vm.free_direct(address).unwrap();
+        assert_eq!(0, vm.allocated_bytes());
+    }
+
+    const DEFINE_CLASS: &[&[u8]] = &[fixture!("DefineClass"), fixture!("DefineClass$BytesLoader")];
+
+    fn byte_array(vm: &mut Vm, bytes: &[u8]) -> Value {
+        let class = vm.load_class("[B").unwrap();
+        let array = vm.new_array(&class, bytes.len()).unwrap();
+        for (element, &byte) in array.fields.borrow_mut().bytes_mut().unwrap().iter_mut().zip(bytes) {
+            *element = byte as i8;
+        }
+        Value::Reference(Some(array))
+    }
+
+    #[test]
+    fn test_define_class() {
+        let mut vm = vm_with(DEFINE_CLASS);
+        let completes = byte_array(&mut vm, fixture!("Completes"));
+        let name = match vm.invoke_static("DefineClass", "defineAndName", "([B)Ljava/lang/String;", vec![completes.clone()]) {
+            Ok(Some(Value::Reference(Some(name)))) => vm.read_string(&name).unwrap(),
+            other => panic!("Expected a name; got {:#?}", other),
+        };
+        assert_eq!("Completes", name);
+        assert_eq!(Ok(Some(Value::Int(1))), vm.invoke_static("DefineClass", "separateCopies", "([B)Z", vec![completes.clone()]));
+
+        let garbage = byte_array(&mut vm, b"\xca\xfe\xba\xbe");
+        for (method, args, exception_class) in [
+            ("defineTwice", completes.clone(), "java/lang/LinkageError"),
+            ("defineWrongName", completes.clone(), "java/lang/NoClassDefFoundError"),
+            ("defineOutOfBounds", completes, "java/lang/IndexOutOfBoundsException"),
+            ("defineGarbage", garbage, "java/lang/ClassFormatError"),
+        ] {
+            match vm.invoke_static("DefineClass", method, "([B)V", vec![args]) {
+                Err(VmError::UncaughtException(ref exception)) if exception.class().name == exception_class => (),
+                other => panic!("Expected uncaught {} from {}; got {:#?}", exception_class, method, other),
+            }
+        }
+    }
+
+    // Defines and drops Unloadable once, so that whatever it loads along the way is already there,
+    // and returns its bytes.
+    fn define_and_drop_once(vm: &mut Vm) -> Value {
+        let unloadable = byte_array(vm, fixture!("Unloadable"));
+        vm.invoke_static("DefineClass", "defineAndDrop", "([BI)V", vec![unloadable.clone(), Value::Int(1)]).unwrap();
+        vm.collect_garbage(GcCause::Requested);
+        unloadable
+    }
+
+    #[test]
+    fn test_unreachable_loaders_are_unloaded() {
+        let mut vm = vm_with(DEFINE_CLASS);
+        let unloadable = define_and_drop_once(&mut vm);
+        let (classes, loaders, objects) = (vm.loaded_class_count(), vm.class_loaders().len(), vm.heap_occupancy().0);
+        for _ in 0..3 {
+            vm.invoke_static("DefineClass", "defineAndDrop", "([BI)V", vec![unloadable.clone(), Value::Int(5)]).unwrap();
+            assert_eq!(classes + 5, vm.loaded_class_count());
+            assert_eq!(loaders + 5, vm.class_loaders().len());
+            assert_eq!(5, vm.collect_garbage(GcCause::Requested).unloaded_classes);
+            assert_eq!((classes, loaders, objects), (vm.loaded_class_count(), vm.class_loaders().len(), vm.heap_occupancy().0));
+        }
+    }
+
+    #[test]
+    fn test_reachable_loaders_keep_their_classes() {
+        let mut vm = vm_with(DEFINE_CLASS);
+        let unloadable = define_and_drop_once(&mut vm);
+        for method in ["defineAndKeepLoader", "defineAndKeepClass"] {
+            vm.invoke_static("DefineClass", method, "([B)V", vec![unloadable.clone()]).unwrap();
+            let class = vm.loaded_classes().find(|class| class.name == "Unloadable").cloned().unwrap();
+            vm.initialize(&class).unwrap();
+            let instance = match class.static_values()[0] {
+                Value::Reference(Some(ref instance)) => instance.downgrade(),
+                ref other => panic!("Expected an instance; got {:?}", other),
+            };
+            let weak_class = Rc::downgrade(&class);
+            drop(class);
+
+            assert_eq!(0, vm.collect_garbage(GcCause::Requested).unloaded_classes, "{}", method);
+            assert!(instance.upgrade().is_some());
+            vm.invoke_static("DefineClass", "release", "()V", vec![]).unwrap();
+            assert_eq!(1, vm.collect_garbage(GcCause::Requested).unloaded_classes, "{}", method);
+            assert!(instance.upgrade().is_none());
+            assert!(weak_class.upgrade().is_none());
+        }
+    }
+
+    #[test]
+    fn test_ldc_class() {
+        let mut vm = vm_with(LITERALS);
+        let class = run_object(&mut vm, "Literals", "stringClass", "()Ljava/lang/Class;");
+        assert_eq!("java/lang/Class", class.class().name);
+        assert_eq!("java.lang.String", string_field(&vm, &class, "name"));
+    }
+
+    #[test]
+    fn test_ldc_array_class() {
+        let mut vm = vm_with(LITERALS);
+        let class = run_object(&mut vm, "Literals", "arrayClass", "()Ljava/lang/Class;");
+        assert_eq!("[[LLiterals;", string_field(&vm, &class, "name"));
+    }
+
+    #[test]
+    fn test_class_literals_are_unique() {
+        assert_eq!(Value::Int(1), run(&mut vm_with(LITERALS), "Literals", "classLiteralsAreUnique", "()Z"));
+    }
+
+    const MIRRORS: &[&[u8]] = &[
+        fixture!("Mirrors"),
+        fixture!("Vehicle"),
+        fixture!("Car"),
+        fixture!("Hatchback"),
+    ];
+
+    #[test]
+    fn test_get_class() {
+        assert_eq!(Value::Int(1), run(&mut vm_with(MIRRORS), "Mirrors", "getClassReturnsLiteral", "()Z"));
+    }
+
+    #[test]
+    fn test_class_hierarchy() {
+        let mut vm = vm_with(MIRRORS);
+        let superclass = run_object(&mut vm, "Mirrors", "superclass", "()Ljava/lang/Class;");
+        assert_eq!("Car", string_field(&vm, &superclass, "name"));
+        assert_eq!(Value::Int(1), run(&mut vm, "Mirrors", "interfacesAreListed", "()Z"));
+        assert_eq!(Value::Int(1), run(&mut vm, "Mirrors", "rootsHaveNoSuperclass", "()Z"));
+        assert_eq!(Value::Int(1), run(&mut vm, "Mirrors", "arraysExtendObject", "()Z"));
+        assert_eq!(Value::Int(1), run(&mut vm, "Mirrors", "isInterface", "()Z"));
+    }
+
+    #[test]
+    fn test_class_modifiers() {
+        let mut vm = vm_with(MIRRORS);
+        let mut modifiers = |which| vm.invoke_static("Mirrors", "modifiers", "(I)I", vec![Value::Int(which)]).unwrap();
+        assert_eq!(Some(Value::Int(0x0001)), modifiers(0));
+        assert_eq!(Some(Value::Int(0x0010)), modifiers(1));
+        assert_eq!(Some(Value::Int(0x0600)), modifiers(2));
+        assert_eq!(Some(Value::Int(0x0411)), modifiers(3));
+        assert_eq!(Some(Value::Int(0x0410)), modifiers(4));
+    }
+
+    #[test]
+    fn test_ldc_method_type() {
+        let mut vm = vm_with(CONSTANTS);
+        let method_type = run_object(&mut vm, "Constants", "methodType", "()Ljava/lang/Object;");
+        assert_eq!("java/lang/invoke/MethodType", method_type.class().name);
+        assert_eq!("(I[Ljava/lang/String;)J", string_field(&vm, &method_type, "descriptor"));
+    }
+
+    fn check_method_handle(method: &str, expected_kind: i32, expected_name: &str, expected_type: &str) {
+        let mut vm = vm_with(CONSTANTS);
+        let handle = run_object(&mut vm, "Constants", method, "()Ljava/lang/Object;");
+        assert_eq!("java/lang/invoke/MethodHandle", handle.class().name);
+        assert_eq!(Value::Int(expected_kind), vm::get_field(&handle, "referenceKind").unwrap());
+        assert_eq!(expected_name, string_field(&vm, &handle, "name"));
+        assert_eq!(expected_type, string_field(&vm, &object_field(&handle, "type"), "descriptor"));
+    }
+
+    #[test]
+    fn test_ldc_static_method_handle() {
+        check_method_handle("staticMethodHandle", 6, "bootstrap",
+            "(Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;Ljava/lang/Class;Ljava/lang/String;)Ljava/lang/String;");
+    }
+
+    #[test]
+    fn test_ldc_virtual_method_handle_takes_receiver() {
+        check_method_handle("virtualMethodHandle", 5, "get", "(LHolder;)I");
+    }
+
+    #[test]
+    fn test_ldc_field_getter_handle() {
+        check_method_handle("fieldGetterHandle", 1, "value", "(LHolder;)I");
+    }
+
+    #[test]
+    fn test_ldc_constructor_handle_returns_new_instance() {
+        check_method_handle("constructorHandle", 8, "<init>", "()LHolder;");
+    }
+
+    #[test]
+    fn test_method_handle_constants_are_cached() {
+        assert_eq!(1, run_int(&mut vm_with(CONSTANTS), "Constants", "methodHandleIsCached"));
+    }
+
+    #[test]
+    fn test_ldc_method_handle_to_missing_method() {
+        let mut vm = vm_with(CONSTANTS);
+        match vm.invoke_static("Constants", "missingMethodHandle", "()Ljava/lang/Object;", vec![]) {
+            Err(VmError::UncaughtException(ref error)) if error.class().name == "java/lang/NoSuchMethodError" => {
+                assert_eq!("Constants.missing()V", string_field(&vm, error, "detailMessage"));
+            },
+            other => panic!("Expected NoSuchMethodError; got {:#?}", other),
+        }
+    }
+
+    // Linkage loads the classes it was compiled against, except that those under
+    // linkage/changed replace the originals.
+    const LINKAGE: &[&[u8]] = &[
+        fixture!("linkage/Linkage"),
+        fixture!("linkage/changed/Changed"),
+        fixture!("linkage/changed/Flipped"),
+        fixture!("linkage/Shape"),
+        fixture!("linkage/changed/Square"),
+        fixture!("linkage/Circle1"),
+        fixture!("linkage/changed/Circle2"),
+        fixture!("linkage/changed/other/Exposed"),
+        fixture!("linkage/changed/other/Hidden"),
+        fixture!("linkage/Heir"),
+    ];
+
+    fn expect_linkage_error(vm: &mut Vm, method: &str, error_class: &str, message: &str) {
+        match vm.invoke_static("Linkage", method, "()I", vec![]) {
+            Err(VmError::UncaughtException(ref error)) if error.class().name == error_class => {
+                assert_eq!(message, string_field(vm, error, "detailMessage"));
+            },
+            other => panic!("Expected {} from Linkage.{}; got {:#?}", error_class, method, other),
+        }
+    }
+
+    #[test]
+    fn test_linkage_errors() {
+        let mut vm = vm_with(LINKAGE);
+        expect_linkage_error(&mut vm, "removedField", "java/lang/NoSuchFieldError", "removedField");
+        expect_linkage_error(&mut vm, "removedMethod", "java/lang/NoSuchMethodError", "Changed.removedMethod()I");
+        expect_linkage_error(&mut vm, "staticFieldMadeInstance", "java/lang/IncompatibleClassChangeError", "Expected static field Changed.staticField");
+        expect_linkage_error(&mut vm, "instanceFieldMadeStatic", "java/lang/IncompatibleClassChangeError", "Expected non-static field Changed.instanceField");
+        expect_linkage_error(&mut vm, "staticMethodMadeInstance", "java/lang/IncompatibleClassChangeError", "Expected static method Changed.staticMethod()I");
+        expect_linkage_error(&mut vm, "instanceMethodMadeStatic", "java/lang/IncompatibleClassChangeError", "Expected non-static method Changed.instanceMethod()I");
+        expect_linkage_error(&mut vm, "classMadeInterface", "java/lang/IncompatibleClassChangeError", "Found interface Flipped, but class was expected");
+        expect_linkage_error(&mut vm, "unimplementedMethod", "java/lang/AbstractMethodError",
+            "Receiver class Square does not define or inherit an implementation of the resolved method Shape.corners()I");
+        expect_linkage_error(&mut vm, "circularHierarchy", "java/lang/ClassCircularityError", "Circle1");
+    }
+
+    #[test]
+    fn test_access_errors() {
+        let mut vm = vm_with(LINKAGE);
+        expect_linkage_error(&mut vm, "privateField", "java/lang/IllegalAccessError", "class Linkage tried to access private field other.Exposed.privateField");
+        expect_linkage_error(&mut vm, "protectedMethod", "java/lang/IllegalAccessError", "class Linkage tried to access protected method other.Exposed.protectedMethod()I");
+        expect_linkage_error(&mut vm, "packageMethod", "java/lang/IllegalAccessError", "class Linkage tried to access package-private method other.Exposed.packageMethod()I");
+        expect_linkage_error(&mut vm, "classMadePackagePrivate", "java/lang/IllegalAccessError", "failed to access class other.Hidden from class Linkage");
+        assert_eq!(Some(Value::Int(2)), vm.invoke_static("Linkage", "protectedMethodFromSubclass", "()I", vec![]).unwrap());
+    }
+
+    #[test]
+    fn test_protected_members_need_a_subclass_receiver() {
+        let mut vm = vm_with(LINKAGE);
+        assert_eq!(Some(Value::Int(4)), vm.invoke_static("Heir", "ownProtectedField", "()I", vec![]).unwrap());
+        assert_eq!(Some(Value::Int(5)), vm.invoke_static("Heir", "ownProtectedInstanceMethod", "()I", vec![]).unwrap());
+        for (method, member) in [("othersProtectedField", "field other.Exposed.protectedField"), ("othersProtectedInstanceMethod", "method other.Exposed.protectedInstanceMethod()I")] {
+            match vm.invoke_static("Heir", method, "()I", vec![]) {
+                Err(VmError::UncaughtException(ref error)) if error.class().name == "java/lang/IllegalAccessError" => {
+                    assert_eq!(format!("class Heir tried to access protected {}", member), string_field(&vm, error, "detailMessage"));
+                },
+                other => panic!("Expected IllegalAccessError; got {:#?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_nestmates_share_private_members() {
+        let mut vm = vm_with(&[fixture!("Nest"), fixture!("Nest$Member"), fixture!("Impostor")]);
+        assert_eq!(Some(Value::Int(42)), vm.invoke_static("Nest$Member", "peek", "()I", vec![]).unwrap());
+        assert_eq!(Some(Value::Int(7)), vm.invoke_static("Nest", "callMember", "()I", vec![]).unwrap());
+
+        // Nest doesn't list Impostor as a member, so Impostor is in a nest of its own.
+        match vm.invoke_static("Impostor", "peek", "()I", vec![]) {
+            Err(VmError::UncaughtException(ref error)) if error.class().name == "java/lang/IllegalAccessError" => {
+                assert_eq!("class Impostor tried to access private method Nest.reveal()I", string_field(&vm, error, "detailMessage"));
+            },
+            other => panic!("Expected IllegalAccessError; got {:#?}", other),
+        }
+    }
+
+    #[test]
+    fn test_resolution_failures_are_cached() {
+        let mut vm = vm_with(LINKAGE);
+        expect_linkage_error(&mut vm, "missingClass", "java/lang/NoClassDefFoundError", "Missing");
+
+        // The class turning up later doesn't change the outcome of resolving the same reference.
+        vm.add_class(fixture!("linkage/Missing")).unwrap();
+        expect_linkage_error(&mut vm, "missingClass", "java/lang/NoClassDefFoundError", "Missing");
+        assert!(vm.load_class("Missing").is_ok());
+    }
+
+    #[test]
+    fn test_ldc_dynamic_constant_calls_bootstrap_method() {
+        let mut vm = vm_with(CONSTANTS);
+        let constant = run_object(&mut vm, "Constants", "dynamicConstant", "()Ljava/lang/Object;");
+        assert_eq!("forty-two", vm.read_string(&constant).unwrap());
+    }
+
+    #[test]
+    fn test_dynamic_constant_is_only_bootstrapped_once() {
+        assert_eq!(1, run_int(&mut vm_with(CONSTANTS), "Constants", "dynamicConstantIsCached"));
+    }
+
+    #[test]
+    fn test_tableswitch_matches_each_case() {
+        let mut vm = vm_with(SWITCHES);
+        assert_eq!(10, call_int(&mut vm, "Switches", "dense", 1));
+        assert_eq!(20, call_int(&mut vm, "Switches", "dense", 2));
+        assert_eq!(30, call_int(&mut vm, "Switches", "dense", 3));
+        assert_eq!(50, call_int(&mut vm, "Switches", "dense", 5));
+    }
+
+    #[test]
+    fn test_tableswitch_gap_in_range_goes_to_default() {
+        assert_eq!(-1, call_int(&mut vm_with(SWITCHES), "Switches", "dense", 4));
+    }
+
+    #[test]
+    fn test_tableswitch_out_of_range_goes_to_default() {
+        let mut vm = vm_with(SWITCHES);
+        assert_eq!(-1, call_int(&mut vm, "Switches", "dense", 0));
+        assert_eq!(-1, call_int(&mut vm, "Switches", "dense", 6));
+        assert_eq!(-1, call_int(&mut vm, "Switches", "dense", i32::MIN));
+        assert_eq!(-1, call_int(&mut vm, "Switches", "dense", i32::MAX));
+    }
+
+    #[test]
+    fn test_tableswitch_with_negative_keys() {
+        let mut vm = vm_with(SWITCHES);
+        assert_eq!(1, call_int(&mut vm, "Switches", "denseWithNegativeKeys", -2));
+        assert_eq!(2, call_int(&mut vm, "Switches", "denseWithNegativeKeys", -1));
+        assert_eq!(3, call_int(&mut vm, "Switches", "denseWithNegativeKeys", 0));
+        assert_eq!(4, call_int(&mut vm, "Switches", "denseWithNegativeKeys", 1));
+        assert_eq!(0, call_int(&mut vm, "Switches", "denseWithNegativeKeys", -3));
+    }
+
+    #[test]
+    fn test_lookupswitch_matches_each_case() {
+        let mut vm = vm_with(SWITCHES);
+        assert_eq!(1, call_int(&mut vm, "Switches", "sparse", -1000000));
+        assert_eq!(2, call_int(&mut vm, "Switches", "sparse", -5));
+        assert_eq!(3, call_int(&mut vm, "Switches", "sparse", 100));
+        assert_eq!(4, call_int(&mut vm, "Switches", "sparse", 4096));
+        assert_eq!(5, call_int(&mut vm, "Switches", "sparse", 1000000));
+        assert_eq!(6, call_int(&mut vm, "Switches", "sparse", i32::MAX));
+    }
+
+    #[test]
+    fn test_lookupswitch_unmatched_key_goes_to_default() {
+        let mut vm = vm_with(SWITCHES);
+        assert_eq!(0, call_int(&mut vm, "Switches", "sparse", 0));
+        assert_eq!(0, call_int(&mut vm, "Switches", "sparse", -1000001));
+        assert_eq!(0, call_int(&mut vm, "Switches", "sparse", 101));
+        assert_eq!(0, call_int(&mut vm, "Switches", "sparse", i32::MIN));
+    }
+
+    #[test]
+    fn test_switch_falls_through_until_break() {
+        assert_eq!(111, call_int(&mut vm_with(SWITCHES), "Switches", "fallThrough", 1));
+        assert_eq!(110, call_int(&mut vm_with(SWITCHES), "Switches", "fallThrough", 2));
+        assert_eq!(1000, call_int(&mut vm_with(SWITCHES), "Switches", "fallThrough", 4));
+        assert_eq!(0, call_int(&mut vm_with(SWITCHES), "Switches", "fallThrough", 5));
+    }
+
+    #[test]
+    fn test_lookupswitch_without_default_case() {
+        let mut vm = vm_with(SWITCHES);
+        assert_eq!(1, call_int(&mut vm, "Switches", "noDefault", 7));
+        assert_eq!(2, call_int(&mut vm, "Switches", "noDefault", 700));
+        assert_eq!(3, call_int(&mut vm, "Switches", "noDefault", 8));
+    }
+
+    #[test]
+    fn test_wide_iload_and_istore() {
+        assert_eq!(515, run_int(&mut vm_with(WIDE_AND_SUBROUTINES), "Wide", "loadAndStore"));
+    }
+
+    #[test]
+    fn test_wide_iinc() {
+        assert_eq!(260, run_int(&mut vm_with(WIDE_AND_SUBROUTINES), "Wide", "increment"));
+    }
+
+    #[test]
+    fn test_wide_iinc_with_large_constant() {
+        assert_eq!(30259, run_int(&mut vm_with(WIDE_AND_SUBROUTINES), "Wide", "incrementByLargeConstant"));
+    }
+
+    #[test]
+    fn test_wide_iinc_with_negative_constant() {
+        assert_eq!(259 - 32768, run_int(&mut vm_with(WIDE_AND_SUBROUTINES), "Wide", "decrementByLargeConstant"));
+    }
+
+    #[test]
+    fn test_wide_iinc_of_low_local_with_large_constant() {
+        assert_eq!(1001, run_int(&mut vm_with(WIDE_AND_SUBROUTINES), "Wide", "incrementNarrowLocalByLargeConstant"));
+    }
+
+    #[test]
+    fn test_wide_long_and_double_locals() {
+        let result = vm_with(WIDE_AND_SUBROUTINES).invoke_static("Wide", "wideLong", "(J)J", vec![Value::Long(1 << 40)]);
+        assert_eq!(Ok(Some(Value::Long(3 * ((1 << 40) + 259)))), result);
+    }
+
+    #[test]
+    fn test_wide_aload_and_astore() {
+        let mut vm = vm_with(WIDE_AND_SUBROUTINES);
+        let string = vm.new_string("passed through").unwrap();
+        let result = vm.invoke_static("Wide", "wideReference", "(Ljava/lang/Object;)Ljava/lang/Object;", vec![Value::Reference(Some(string.clone()))]);
+        assert_eq!(Ok(Some(Value::Reference(Some(string)))), result);
+    }
+
+    #[test]
+    fn test_iinc() {
+        assert_eq!(141, call_int(&mut vm_with(WIDE_AND_SUBROUTINES), "Wide", "narrowIncrement", 42));
+    }
+
+    #[test]
+    fn test_iinc_wraps_on_overflow() {
+        assert_eq!(i32::MIN + 98, call_int(&mut vm_with(WIDE_AND_SUBROUTINES), "Wide", "narrowIncrement", i32::MAX));
+    }
+
+    #[test]
+    fn test_jsr_and_ret() {
+        assert_eq!(2, run_int(&mut vm_with(WIDE_AND_SUBROUTINES), "Subroutines", "callTwice"));
+    }
+
+    #[test]
+    fn test_jsr_w_and_wide_ret() {
+        assert_eq!(2, run_int(&mut vm_with(WIDE_AND_SUBROUTINES), "Subroutines", "callTwiceWide"));
+    }
+
+    #[test]
+    fn test_pop() {
+        assert_eq!(1, run_int(&mut vm_with(STACK_OPS), "StackOps", "pop"));
+    }
+
+    #[test]
+    fn test_pop2_of_two_ints() {
+        assert_eq!(1, run_int(&mut vm_with(STACK_OPS), "StackOps", "pop2OfTwoInts"));
+    }
+
+    #[test]
+    fn test_pop2_of_long() {
+        assert_eq!(1, run_int(&mut vm_with(STACK_OPS), "StackOps", "pop2OfLong"));
+    }
+
+    #[test]
+    fn test_dup() {
+        assert_eq!(221, run_int(&mut vm_with(STACK_OPS), "StackOps", "dup"));
+    }
+
+    #[test]
+    fn test_dup_x1() {
+        assert_eq!(3231, run_int(&mut vm_with(STACK_OPS), "StackOps", "dupX1"));
+    }
+
+    #[test]
+    fn test_dup_x2_of_three_ints() {
+        assert_eq!(43241, run_int(&mut vm_with(STACK_OPS), "StackOps", "dupX2OfThreeInts"));
+    }
+
+    #[test]
+    fn test_dup_x2_over_long() {
+        assert_eq!(2521, run_int(&mut vm_with(STACK_OPS), "StackOps", "dupX2OverLong"));
+    }
+
+    #[test]
+    fn test_dup2_of_two_ints() {
+        assert_eq!(32321, run_int(&mut vm_with(STACK_OPS), "StackOps", "dup2OfTwoInts"));
+    }
+
+    #[test]
+    fn test_dup2_of_long() {
+        assert_eq!(551, run_int(&mut vm_with(STACK_OPS), "StackOps", "dup2OfLong"));
+    }
+
+    #[test]
+    fn test_dup2_x1_of_two_ints() {
+        assert_eq!(432431, run_int(&mut vm_with(STACK_OPS), "StackOps", "dup2X1OfTwoInts"));
+    }
+
+    #[test]
+    fn test_dup2_x1_of_long() {
+        assert_eq!(5251, run_int(&mut vm_with(STACK_OPS), "StackOps", "dup2X1OfLong"));
+    }
+
+    #[test]
+    fn test_dup2_x2_of_four_ints() {
+        assert_eq!(432143, run_int(&mut vm_with(STACK_OPS), "StackOps", "dup2X2OfFourInts"));
+    }
+
+    #[test]
+    fn test_dup2_x2_of_long_over_two_ints() {
+        assert_eq!(5215, run_int(&mut vm_with(STACK_OPS), "StackOps", "dup2X2OfLongOverTwoInts"));
+    }
+
+    #[test]
+    fn test_dup2_x2_of_two_ints_over_long() {
+        assert_eq!(21521, run_int(&mut vm_with(STACK_OPS), "StackOps", "dup2X2OfTwoIntsOverLong"));
+    }
+
+    #[test]
+    fn test_dup2_x2_of_long_over_long() {
+        assert_eq!(6561, run_int(&mut vm_with(STACK_OPS), "StackOps", "dup2X2OfLongOverLong"));
+    }
+
+    #[test]
+    fn test_swap() {
+        assert_eq!(231, run_int(&mut vm_with(STACK_OPS), "StackOps", "swap"));
+    }
+
+    #[test]
+    fn test_pop_cannot_split_long() {
+        match vm_with(STACK_OPS).invoke_static("StackOps", "popOfLong", "()I", vec![]) {
+            Err(VmError::InvalidBytecode(_)) => (),
+            other => panic!("Expected invalid bytecode; got {:#?}", other),
+        }
+    }
+
+    #[test]
+    fn test_dup_x1_cannot_split_long() {
+        match vm_with(STACK_OPS).invoke_static("StackOps", "dupX1OverLong", "()I", vec![]) {
+            Err(VmError::InvalidBytecode(_)) => (),
+            other => panic!("Expected invalid bytecode; got {:#?}", other),
+        }
+    }
+
+    #[test]
+    fn test_dup2_cannot_split_long() {
+        match vm_with(STACK_OPS).invoke_static("StackOps", "dup2OfIntAndLong", "()I", vec![]) {
+            Err(VmError::InvalidBytecode(_)) => (),
+            other => panic!("Expected invalid bytecode; got {:#?}", other),
+        }
+    }
+
+    #[test]
+    fn test_swap_cannot_split_long() {
+        match vm_with(STACK_OPS).invoke_static("StackOps", "swapWithLong", "()I", vec![]) {
+            Err(VmError::InvalidBytecode(_)) => (),
+            other => panic!("Expected invalid bytecode; got {:#?}", other),
+        }
+    }
+
+    #[test]
+    fn test_fields_used_on_objects_without_them() {
+        let mut vm = vm_with(&[fixture!("FieldMisuse")]);
+        for (method, descriptor) in [("getFieldOfObject", "()I"), ("putFieldOfObject", "()V"), ("getFieldOfArray", "()I"), ("putFieldOfArray", "()V")] {
+            match vm.invoke_static("FieldMisuse", method, descriptor, vec![]) {
+                Err(VmError::InvalidBytecode(message)) => assert!(message.starts_with("Field FieldMisuse.value used on an instance of "), "{}", message),
+                other => panic!("Expected invalid bytecode from {}; got {:#?}", method, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_dup_x1_when_assigning_int_field() {
+        assert_eq!(84, call_int(&mut vm_with(STACK_OPS), "Dups", "assignIntField", 42));
+    }
+
+    #[test]
+    fn test_dup2_x1_when_assigning_long_field() {
+        let result = vm_with(STACK_OPS).invoke_static("Dups", "assignLongField", "(J)J", vec![Value::Long(1 << 50)]);
+        assert_eq!(Ok(Some(Value::Long(1 << 51))), result);
+    }
+
+    #[test]
+    fn test_dup2_when_incrementing_long_static() {
+        assert_eq!(Value::Long(83), run(&mut vm_with(STACK_OPS), "Dups", "incrementLongStatic", "()J"));
+    }
+
+    #[test]
+    fn test_dup2_when_post_incrementing_double_static() {
+        assert_eq!(Value::Double(4.0), run(&mut vm_with(STACK_OPS), "Dups", "postIncrementDoubleStatic", "()D"));
+    }
+
+    #[test]
+    fn test_dup2_x1_when_post_incrementing_long_field() {
+        assert_eq!(Value::Long(1011), run(&mut vm_with(STACK_OPS), "Dups", "incrementLongField", "()J"));
+    }
+
+    #[test]
+    fn test_stack_overflow_error_is_thrown_at_depth_limit() {
+        let mut vm = vm_with(RECURSION);
+        vm.set_max_stack_depth(100);
+        assert_eq!(99, run_int(&mut vm, "Recursion", "depthReached"));
+    }
+
+    #[test]
+    fn test_stack_overflow_error_at_default_depth_limit() {
+        let mut vm = vm_with(RECURSION);
+        assert_eq!(DEFAULT_MAX_STACK_DEPTH as i32 - 1, run_int(&mut vm, "Recursion", "depthReached"));
+    }
+
+    #[test]
+    fn test_stack_can_overflow_repeatedly() {
+        let mut vm = vm_with(RECURSION);
+        vm.set_max_stack_depth(50);
+        assert_eq!(48, run_int(&mut vm, "Recursion", "overflowTwice"));
+    }
+
+    #[test]
+    fn test_stack_overflow_error_is_an_error() {
+        let mut vm = vm_with(RECURSION);
+        vm.set_max_stack_depth(50);
+        assert_eq!(1, run_int(&mut vm, "Recursion", "catchAsError"));
+    }
+
+    #[test]
+    fn test_uncaught_stack_overflow_error() {
+        let mut vm = vm_with(RECURSION);
+        vm.set_max_stack_depth(50);
+        expect_uncaught(&mut vm, "Recursion", "uncaughtOverflow", "java/lang/StackOverflowError");
+        assert_eq!(0, vm.stack_depth());
+    }
+
+    #[test]
+    fn test_recursion_within_depth_limit() {
+        let mut vm = vm_with(RECURSION);
+        vm.set_max_stack_depth(201);
+        assert_eq!(20100, call_int(&mut vm, "Recursion", "sum", 200));
+    }
+
+    #[test]
+    fn test_recursion_beyond_depth_limit() {
+        let mut vm = vm_with(RECURSION);
+        vm.set_max_stack_depth(200);
+        match vm.invoke_static("Recursion", "sum", "(I)I", vec![Value::Int(200)]) {
+            Err(VmError::UncaughtException(ref error)) if error.class().name == "java/lang/StackOverflowError" => (),
+            other => panic!("Expected StackOverflowError; got {:#?}", other),
+        }
+    }
+
+    #[test]
+    fn test_deep_recursion_does_not_use_host_stack() {
+        let mut vm = vm_with(RECURSION);
+        vm.set_max_stack_depth(200_001);
+        let expected = (200_000i64 * 200_001 / 2) as i32;
+        assert_eq!(expected, call_int(&mut vm, "Recursion", "sum", 200_000));
+        assert_eq!(0, vm.stack_depth());
+    }
+
+    #[test]
+    fn test_stack_overflow_error_can_be_constructed_beyond_the_limit() {
+        let mut vm = vm_with(RECURSION);
+        vm.set_max_stack_depth(0);
+        match vm.invoke_static("Recursion", "sum", "(I)I", vec![Value::Int(1)]) {
+            Err(VmError::UncaughtException(ref error)) if error.class().name == "java/lang/StackOverflowError" => (),
+            other => panic!("Expected StackOverflowError; got {:#?}", other),
+        }
+        assert_eq!(0, vm.stack_depth());
+    }
+
+    fn divide(method: &str, descriptor: &str, a: Value, b: Value) -> Result<Option<Value>, VmError> {
+        vm_with(ARITHMETIC).invoke_static("Arithmetic", method, descriptor, vec![a, b])
+    }
+
+    fn expect_divide_by_zero(result: Result<Option<Value>, VmError>) {
+        match result {
+            Err(VmError::UncaughtException(ref exception)) if exception.class().name == "java/lang/ArithmeticException" => (),
+            other => panic!("Expected ArithmeticException; got {:#?}", other),
+        }
+    }
+
+    #[test]
+    fn test_idiv() {
+        assert_eq!(Ok(Some(Value::Int(-3))), divide("divide", "(II)I", Value::Int(-7), Value::Int(2)));
+    }
+
+    #[test]
+    fn test_irem() {
+        assert_eq!(Ok(Some(Value::Int(-1))), divide("remainder", "(II)I", Value::Int(-7), Value::Int(2)));
+    }
+
+    #[test]
+    fn test_idiv_overflow() {
+        assert_eq!(Ok(Some(Value::Int(i32::MIN))), divide("divide", "(II)I", Value::Int(i32::MIN), Value::Int(-1)));
+        assert_eq!(Ok(Some(Value::Int(0))), divide("remainder", "(II)I", Value::Int(i32::MIN), Value::Int(-1)));
+    }
+
+    #[test]
+    fn test_ldiv_and_lrem() {
+        assert_eq!(Ok(Some(Value::Long(-3))), divide("divideLong", "(JJ)J", Value::Long(-7), Value::Long(2)));
+        assert_eq!(Ok(Some(Value::Long(-1))), divide("remainderLong", "(JJ)J", Value::Long(-7), Value::Long(2)));
+        assert_eq!(Ok(Some(Value::Long(i64::MIN))), divide("divideLong", "(JJ)J", Value::Long(i64::MIN), Value::Long(-1)));
+    }
+
+    #[test]
+    fn test_integer_division_by_zero_throws() {
+        expect_divide_by_zero(divide("divide", "(II)I", Value::Int(1), Value::Int(0)));
+        expect_divide_by_zero(divide("remainder", "(II)I", Value::Int(1), Value::Int(0)));
+        expect_divide_by_zero(divide("divideLong", "(JJ)J", Value::Long(1), Value::Long(0)));
+        expect_divide_by_zero(divide("remainderLong", "(JJ)J", Value::Long(1), Value::Long(0)));
+    }
+
+    #[test]
+    fn test_float_division_by_zero_does_not_throw() {
+        assert_eq!(Ok(Some(Value::Float(f32::INFINITY))), divide("divideFloat", "(FF)F", Value::Float(1.0), Value::Float(0.0)));
+    }
+
+    #[test]
+    fn test_division_by_zero_message() {
+        assert_eq!("/ by zero", run_string(&mut vm_with(ARITHMETIC), "Arithmetic", "catchDivideByZero"));
+        assert_eq!("/ by zero", run_string(&mut vm_with(ARITHMETIC), "Arithmetic", "catchLongRemainderByZero"));
+    }
+
+    #[test]
+    fn test_division_by_zero_is_caught_in_same_frame() {
+        assert_eq!(2, call_int(&mut vm_with(ARITHMETIC), "Arithmetic", "handlerSeesStateBeforeDivision", 0));
+        assert_eq!(5, call_int(&mut vm_with(ARITHMETIC), "Arithmetic", "handlerSeesStateBeforeDivision", 2));
+    }
+
+    const CASTS: &[&[u8]] = &[
+        fixture!("Casts"),
+        fixture!("Shape"),
+        fixture!("Polygon"),
+        fixture!("Square"),
+        fixture!("Circle"),
+    ];
+
+    fn instance(vm: &mut Vm, class: &str) -> Value {
+        let class = vm.load_class(class).unwrap();
+        if class.is_array() {
+            Value::Reference(Some(vm.new_array(&class, 1).unwrap()))
+        } else {
+            Value::Reference(Some(vm.new_object(&class)))
+        }
+    }
+
+    fn is_instance(vm: &mut Vm, method: &str, object: Value) -> bool {
+        match vm.invoke_static("Casts", method, "(Ljava/lang/Object;)Z", vec![object]) {
+            Ok(Some(Value::Int(result))) => result != 0,
+            other => panic!("Expected a boolean result from Casts.{}; got {:#?}", method, other),
+        }
+    }
+
+    fn cast(vm: &mut Vm, method: &str, object: Value) -> Result<Option<Value>, VmError> {
+        vm.invoke_static("Casts", method, "(Ljava/lang/Object;)Ljava/lang/Object;", vec![object])
+    }
+
+    #[test]
+    fn test_instanceof_checks_classes_and_interfaces() {
+        let mut vm = vm_with(CASTS);
+        let square = instance(&mut vm, "Square");
+        let circle = instance(&mut vm, "Circle");
+        assert!(is_instance(&mut vm, "isSquare", square.clone()));
+        assert!(is_instance(&mut vm, "isPolygon", square.clone()));
+        assert!(is_instance(&mut vm, "isShape", square));
+        assert!(is_instance(&mut vm, "isShape", circle.clone()));
+        assert!(!is_instance(&mut vm, "isPolygon", circle.clone()));
+        assert!(!is_instance(&mut vm, "isSquare", circle));
+    }
+
+    #[test]
+    fn test_instanceof_null_is_false() {
+        let mut vm = vm_with(CASTS);
+        assert!(!is_instance(&mut vm, "isShape", Value::Reference(None)));
+        assert!(!is_instance(&mut vm, "isCloneable", Value::Reference(None)));
+    }
+
+    #[test]
+    fn test_instanceof_arrays() {
+        let mut vm = vm_with(CASTS);
+        let squares = instance(&mut vm, "[LSquare;");
+        let objects = instance(&mut vm, "[Ljava/lang/Object;");
+        let ints = instance(&mut vm, "[I");
+        let nested_ints = instance(&mut vm, "[[I");
+        assert!(is_instance(&mut vm, "isShapeArray", squares.clone()));
+        assert!(is_instance(&mut vm, "isObjectArray", squares));
+        assert!(!is_instance(&mut vm, "isShapeArray", objects));
+        assert!(is_instance(&mut vm, "isIntArray", ints.clone()));
+        assert!(!is_instance(&mut vm, "isObjectArray", ints.clone()));
+        assert!(is_instance(&mut vm, "isObjectArray", nested_ints.clone()));
+        assert!(!is_instance(&mut vm, "isIntArray", nested_ints));
+        assert!(is_instance(&mut vm, "isCloneable", ints.clone()));
+        assert!(is_instance(&mut vm, "isSerializable", ints));
+    }
+
+    #[test]
+    fn test_checkcast_returns_same_object() {
+        let mut vm = vm_with(CASTS);
+        let square = instance(&mut vm, "Square");
+        let squares = instance(&mut vm, "[LSquare;");
+        assert_eq!(Ok(Some(square.clone())), cast(&mut vm, "castToPolygon", square));
+        assert_eq!(Ok(Some(squares.clone())), cast(&mut vm, "castToShapeArray", squares));
+    }
+
+    #[test]
+    fn test_checkcast_allows_null() {
+        let mut vm = vm_with(CASTS);
+        assert_eq!(Ok(Some(Value::Reference(None))), cast(&mut vm, "castToPolygon", Value::Reference(None)));
+    }
+
+    #[test]
+    fn test_checkcast_failure_throws() {
+        let mut vm = vm_with(CASTS);
+        let circle = instance(&mut vm, "Circle");
+        let objects = instance(&mut vm, "[Ljava/lang/Object;");
+        for object in [circle, objects] {
+            match cast(&mut vm, "castToShapeArray", object) {
+                Err(VmError::UncaughtException(ref exception)) if exception.class().name == "java/lang/ClassCastException" => (),
+                other => panic!("Expected ClassCastException; got {:#?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_checkcast_failure_message_names_both_classes() {
+        let mut vm = vm_with(CASTS);
+        let circle = instance(&mut vm, "Circle");
+        let ints = instance(&mut vm, "[I");
+        for (object, expected) in [
+            (circle, "class Circle cannot be cast to class Polygon"),
+            (ints, "class [I cannot be cast to class Polygon"),
+        ] {
+            let message = match vm.invoke_static("Casts", "castFailureMessage", "(Ljava/lang/Object;)Ljava/lang/String;", vec![object]) {
+                Ok(Some(Value::Reference(Some(message)))) => vm.read_string(&message).unwrap(),
+                other => panic!("Expected a message; got {:#?}", other),
+            };
+            assert_eq!(expected, message);
+        }
+    }
+
+    const ARRAYS: &[&[u8]] = &[
+        fixture!("Arrays"),
+    ];
+
+    fn call_arrays(vm: &mut Vm, method: &str, arg: i32) -> Result<Option<Value>, VmError> {
+        vm.invoke_static("Arrays", method, "(I)I", vec![Value::Int(arg)])
+    }
+
+    fn expect_thrown(result: Result<Option<Value>, VmError>, exception_class: &str) {
+        match result {
+            Err(VmError::UncaughtException(ref exception)) if exception.class().name == exception_class => (),
+            other => panic!("Expected {}; got {:#?}", exception_class, other),
+        }
+    }
+
+    #[test]
+    fn test_int_array_elements() {
+        assert_eq!(45, call_int(&mut vm_with(ARRAYS), "Arrays", "sumInts", 10));
+        assert_eq!(0, call_int(&mut vm_with(ARRAYS), "Arrays", "sumInts", 0));
+    }
+
+    #[test]
+    fn test_wide_array_elements() {
+        assert_eq!(Value::Long((1 << 40) + 5), run(&mut vm_with(ARRAYS), "Arrays", "sumLongs", "()J"));
+        assert_eq!(Value::Double(0.875), run(&mut vm_with(ARRAYS), "Arrays", "sumDoubles", "()D"));
+    }
+
+    #[test]
+    fn test_small_array_elements() {
+        assert_eq!(194, run_int(&mut vm_with(ARRAYS), "Arrays", "smallTypes"));
+    }
+
+    #[test]
+    fn test_reference_array_elements_default_to_null() {
+        assert_eq!(Value::null(), run(&mut vm_with(ARRAYS), "Arrays", "defaultElement", "()Ljava/lang/Object;"));
+    }
+
+    #[test]
+    fn test_multianewarray() {
+        assert_eq!(347, run_int(&mut vm_with(ARRAYS), "Arrays", "grid"));
+        assert_eq!(Value::Int(1), run(&mut vm_with(ARRAYS), "Arrays", "partialGrid", "()Z"));
+    }
+
+    #[test]
+    fn test_array_index_out_of_bounds() {
+        expect_thrown(vm_with(ARRAYS).invoke_static("Arrays", "readPastEnd", "()I", vec![]), "java/lang/ArrayIndexOutOfBoundsException");
+        expect_uncaught(&mut vm_with(ARRAYS), "Arrays", "writeBeforeStart", "java/lang/ArrayIndexOutOfBoundsException");
+    }
+
+    #[test]
+    fn test_array_index_out_of_bounds_message() {
+        assert_eq!("Index 3 out of bounds for length 3", run_string(&mut vm_with(ARRAYS), "Arrays", "readPastEndMessage"));
+        assert_eq!("Index -1 out of bounds for length 3", run_string(&mut vm_with(ARRAYS), "Arrays", "writeBeforeStartMessage"));
+    }
+
+    #[test]
+    fn test_negative_array_size() {
+        expect_thrown(call_arrays(&mut vm_with(ARRAYS), "negativeSize", -1), "java/lang/NegativeArraySizeException");
+        expect_thrown(call_arrays(&mut vm_with(ARRAYS), "negativeInnerSize", -1), "java/lang/NegativeArraySizeException");
+        assert_eq!("-3", run_string(&mut vm_with(ARRAYS), "Arrays", "negativeSizeMessage"));
+    }
+
+    #[test]
+    fn test_empty_outer_array_still_checks_inner_size() {
+        assert_eq!(0, call_int(&mut vm_with(ARRAYS), "Arrays", "negativeInnerSize", 5));
+    }
+
+    #[test]
+    fn test_arraylength_of_null_throws() {
+        expect_thrown(vm_with(ARRAYS).invoke_static("Arrays", "lengthOfNull", "()I", vec![]), "java/lang/NullPointerException");
+    }
+
+    #[test]
+    fn test_array_store_exception() {
+        expect_uncaught(&mut vm_with(ARRAYS), "Arrays", "storeWrongType", "java/lang/ArrayStoreException");
+        assert_eq!("java.lang.Object", run_string(&mut vm_with(ARRAYS), "Arrays", "storeWrongTypeMessage"));
+    }
+
+    // Records the events for methods of one class as strings, so they can be compared easily.
+    struct Recorder {
+        class_name: &'static str,
+        events: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Recorder {
+        fn install(vm: &mut Vm, class_name: &'static str) -> Rc<RefCell<Vec<String>>> {
+            let events = Rc::new(RefCell::new(vec![]));
+            vm.set_hooks(Box::new(Recorder {class_name, events: Rc::clone(&events)}));
+            events
+        }
+
+        fn record(&self, class: &RuntimeClass, method: &Method, event: String) {
+            if class.name == self.class_name {
+                let method_name = class.class.utf8(&method.name).unwrap();
+                self.events.borrow_mut().push(format!("{} {}", method_name, event));
+            }
+        }
+    }
+
+    impl ExecutionHooks for Recorder {
+        fn on_method_enter(&mut self, class: &RuntimeClass, method: &Method) {
+            self.record(class, method, "entered".to_string());
+        }
+
+        fn on_method_exit(&mut self, class: &RuntimeClass, method: &Method, result: &Result<Option<Value>, VmError>) {
+            let event = match *result {
+                Ok(_) => "returned",
+                Err(_) => "threw",
+            };
+            self.record(class, method, event.to_string());
+        }
+
+        fn on_instruction(&mut self, class: &RuntimeClass, method: &Method, pc: usize, opcode: u8, _stack: &[Value]) {
+            self.record(class, method, format!("{}: {:#04x}", pc, opcode));
+        }
+
+        fn on_exception_thrown(&mut self, class: &RuntimeClass, method: &Method, pc: usize, exception: &ObjectRef) {
+            self.record(class, method, format!("{}: threw {}", pc, exception.class().name));
+        }
+    }
+
+    #[test]
+    fn test_hooks_see_each_instruction() {
+        let mut vm = vm_with(ARITHMETIC);
+        let events = Recorder::install(&mut vm, "Arithmetic");
+        assert_eq!(Ok(Some(Value::Int(3))), vm.invoke_static("Arithmetic", "divide", "(II)I", vec![Value::Int(7), Value::Int(2)]));
+        assert_eq!(vec![
+            "divide entered",
+            "divide 0: 0x1a",
+            "divide 1: 0x1b",
+            "divide 2: 0x6c",
+            "divide 3: 0xac",
+            "divide returned",
+        ], *events.borrow());
+    }
+
+    #[test]
+    fn test_hooks_see_exceptions_where_they_are_thrown() {
+        let mut vm = vm_with(ARITHMETIC);
+        let events = Recorder::install(&mut vm, "Arithmetic");
+        assert_eq!("/ by zero", run_string(&mut vm, "Arithmetic", "catchDivideByZero"));
+
+        let events = events.borrow();
+        let calls_and_throws: Vec<&str> = events.iter()
+            .map(String::as_str)
+            .filter(|event| !event.contains(": 0x"))
+            .collect();
+        assert_eq!(vec![
+            "catchDivideByZero entered",
+            "divide entered",
+            "divide 2: threw java/lang/ArithmeticException",
+            "divide threw",
+            "catchDivideByZero returned",
+        ], calls_and_throws);
+        assert!(events.contains(&"catchDivideByZero 9: 0x4b".to_string()), "Expected the handler to run");
+    }
+
+    #[test]
+    fn test_take_hooks_uninstalls_them() {
+        let mut vm = vm_with(ARITHMETIC);
+        let events = Recorder::install(&mut vm, "Arithmetic");
+        assert!(vm.take_hooks().is_some());
+        assert!(!vm.has_hooks());
+        vm.invoke_static("Arithmetic", "divide", "(II)I", vec![Value::Int(7), Value::Int(2)]).unwrap();
+        assert!(events.borrow().is_empty());
+    }
+
+    const DISPATCH: &[&[u8]] = &[
+        fixture!("Dispatch"),
+        fixture!("Named"),
+        fixture!("Animal"),
+        fixture!("Bird"),
+        fixture!("Snake"),
+    ];
+
+    fn cached_entries(class: &RuntimeClass) -> Vec<ResolvedEntry> {
+        (1..=class.class.constants.len() as u16).filter_map(|index| class.cached_entry(index)).collect()
+    }
+
+    #[test]
+    fn test_virtual_calls_select_override_after_resolution_is_cached() {
+        let mut vm = vm_with(DISPATCH);
+        assert_eq!(12, run_int(&mut vm, "Dispatch", "totalLegs"));
+        assert_eq!(12, run_int(&mut vm, "Dispatch", "totalLegs"));
+    }
+
+    #[test]
+    fn test_interface_calls_select_implementation_after_resolution_is_cached() {
+        let mut vm = vm_with(DISPATCH);
+        assert_eq!(8, run_int(&mut vm, "Dispatch", "sumOfIds"));
+        assert_eq!(8, run_int(&mut vm, "Dispatch", "sumOfIds"));
+    }
+
+    #[test]
+    fn test_resolved_entries_are_cached_on_the_class() {
+        let mut vm = vm_with(DISPATCH);
+        let class = vm.load_class("Dispatch").unwrap();
+        assert!(cached_entries(&class).is_empty());
+
+        run_int(&mut vm, "Dispatch", "totalLegs");
+        let entries = cached_entries(&class);
+        let has_method = |name: &str| entries.iter().any(|entry| match *entry {
+            ResolvedEntry::Method{ref class, method_index, ..} => class.class.utf8(&class.class.methods[method_index].name).unwrap() == name,
+            _ => false,
+        });
+        assert!(has_method("legs"));
+        assert!(has_method("<init>"));
+        assert!(entries.iter().any(|entry| match *entry {
+            ResolvedEntry::StaticField{ref class, ..} => class.name == "Dispatch",
+            _ => false,
+        }));
+        assert!(entries.iter().any(|entry| match *entry {
+            ResolvedEntry::Class(ref class) => class.name == "Bird",
+            _ => false,
+        }));
+        assert_eq!(Some(Value::Int(5)), class.static_value("calls"));
+    }
+
+    // The inline caches in the class for calls to the named method.
+    fn inline_caches(class: &RuntimeClass, owner: &str, name: &str) -> Vec<InlineCache> {
+        (1..=class.class.constants.len() as u16)
+            .filter(|&index| class.class.member_ref(&ConstantIndex(index)).is_ok_and(|method| method.class == owner && method.name == name))
+            .map(|index| class.inline_cache(index))
+            .collect()
+    }
+
+    #[test]
+    fn test_virtual_call_site_caches_each_receiver_class() {
+        let mut vm = vm_with(DISPATCH);
+        run_int(&mut vm, "Dispatch", "totalLegs");
+        let class = vm.load_class("Dispatch").unwrap();
+        let caches = inline_caches(&class, "Animal", "legs");
+        assert_eq!(1, caches.len());
+        // Calls on Animal itself use the resolved method directly, so only Bird and Snake are cached.
+        assert!(matches!(caches[0], InlineCache::Polymorphic(ref entries) if entries.len() == 2));
+    }
+
+    #[test]
+    fn test_interface_call_site_caches_each_receiver_class() {
+        let mut vm = vm_with(DISPATCH);
+        run_int(&mut vm, "Dispatch", "sumOfIds");
+        let class = vm.load_class("Dispatch").unwrap();
+        assert_eq!(3, inline_caches(&class, "Named", "id")[0].len());
+    }
+
+    #[test]
+    fn test_invalidating_inline_caches_empties_them() {
+        let mut vm = vm_with(DISPATCH);
+        run_int(&mut vm, "Dispatch", "sumOfIds");
+        vm.invalidate_inline_caches();
+        let class = vm.load_class("Dispatch").unwrap();
+        assert!(inline_caches(&class, "Named", "id")[0].is_empty());
+        assert_eq!(8, run_int(&mut vm, "Dispatch", "sumOfIds"));
+    }
+
+    fn decoded(code: &[u8], pc: usize) -> (Operands, u32) {
+        let instruction = decode(code, false)[pc];
+        (instruction.operands, instruction.length)
+    }
+
+    #[test]
+    fn test_decode_reads_operands() {
+        let code = [BIPUSH, 0xfe, SIPUSH, 0x12, 0x34, IINC, 3, 0xff, GOTO, 0xff, 0xf7, MULTIANEWARRAY, 0, 7, 2, RETURN];
+        assert_eq!((Operands(-2, 0), 2), decoded(&code, 0));
+        assert_eq!((Operands(0x1234, 0), 3), decoded(&code, 2));
+        assert_eq!((Operands(3, -1), 3), decoded(&code, 5));
+        assert_eq!((Operands(-9, 0), 3), decoded(&code, 8));
+        assert_eq!((Operands(7, 2), 4), decoded(&code, 11));
+        assert_eq!((Operands(0, 0), 1), decoded(&code, 15));
+    }
+
+    #[test]
+    fn test_decode_wide_as_modified_instruction() {
+        let code = [WIDE, IINC, 0x01, 0x00, 0xff, 0xfe, WIDE, ILOAD, 0x01, 0x02, RETURN];
+        assert_eq!((Operands(256, -2), 6), decoded(&code, 0));
+        assert_eq!((Operands(258, 0), 4), decoded(&code, 6));
+        assert_eq!((Operands(0, 0), 1), decoded(&code, 10));
+    }
+
+    #[test]
+    fn test_decode_switch_lengths() {
+        let tableswitch = [NOP, TABLESWITCH, 0, 0, 0, 0, 0, 9, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 9, 0, 0, 0, 9, RETURN];
+        assert_eq!(23, decoded(&tableswitch, 1).1);
+        assert_eq!(1, decoded(&tableswitch, 24).1);
+
+        let lookupswitch = [LOOKUPSWITCH, 0, 0, 0, 0, 0, 0, 9, 0, 0, 0, 1, 0, 0, 0, 5, 0, 0, 0, 9, RETURN];
+        assert_eq!(20, decoded(&lookupswitch, 0).1);
+        assert_eq!(1, decoded(&lookupswitch, 20).1);
+    }
+
+    #[test]
+    fn test_decode_truncated_invokes() {
+        // They're left to fail when they run, rather than taking their index from the rest.
+        assert_eq!((Operands(0, 0), 1), decoded(&[INVOKEINTERFACE, 0, 1, 1], 0));
+        assert_eq!((Operands(0, 0), 1), decoded(&[INVOKEDYNAMIC, 0, 1], 0));
+    }
+
+    #[test]
+    fn test_decode_fuses_superinstructions() {
+        let code = [ALOAD_0, GETFIELD, 0, 5, ILOAD_1, ILOAD, 2, IADD, WIDE, ILOAD, 0, 1, ILOAD_0, IADD, IRETURN];
+        let instructions = decode(&code, true);
+        let fused = |pc: usize| (instructions[pc].fused, instructions[pc].operands, instructions[pc].length);
+        assert_eq!((true, Operands(0, 5), 4), fused(0));
+        assert_eq!((true, Operands(1, 2), 4), fused(4));
+        // The later parts are still there for anything that jumps to them.
+        assert_eq!((false, Operands(2, 0), 2), fused(5));
+        assert_eq!((false, Operands(0, 0), 1), fused(7));
+        // Wide loads aren't fused.
+        assert_eq!((false, Operands(1, 0), 4), fused(8));
+        assert!(!decode(&code, false).iter().any(|instruction| instruction.fused));
+    }
+
+    #[test]
+    fn test_superinstructions_run_as_their_parts_would() {
+        let run = |fuse: bool| {
+            let mut vm = vm_with(&[fixture!("Benchmarks"), fixture!("Particle")]);
+            vm.set_instruction_fusion(fuse);
+            // The sieve adds int locals, and walking the particles reads fields of locals.
+            let primes = vm.invoke_static("Benchmarks", "sieve", "(I)I", vec![Value::Int(100)]).unwrap();
+            let total = vm.invoke_static("Benchmarks", "particles", "(I)J", vec![Value::Int(10)]).unwrap();
+            (primes, total, vm.executed_instructions())
+        };
+        assert_eq!(run(false), run(true));
+        assert_eq!(11, run_int(&mut vm_with(EXCEPTIONS), "Exceptions", "catchNullPointerFromFieldAccess"));
+    }
+
+    #[test]
+    fn test_benchmark_workloads() {
+        let mut vm = vm_with(&[fixture!("Benchmarks"), fixture!("Particle")]);
+        let mut call = |name: &str, descriptor: &str, arg: i32| vm.invoke_static("Benchmarks", name, descriptor, vec![Value::Int(arg)]).unwrap();
+        assert_eq!(Some(Value::Int(285)), call("sumOfSquares", "(I)I", 10));
+        assert_eq!(Some(Value::Long(8)), call("collatzSteps", "(I)J", 4));
+        assert_eq!(Some(Value::Int(25)), call("sieve", "(I)I", 100));
+        assert_eq!(Some(Value::Int(55)), call("fib", "(I)I", 10));
+        assert_eq!(Some(Value::Long(4)), call("particles", "(I)J", 6));
+        match call("leibnizPi", "(I)D", 1000) {
+            Some(Value::Double(pi)) => assert!((pi - std::f64::consts::PI).abs() < 0.01),
+            other => panic!("Expected a double; got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_suspended_activations_report_live_references() {
+        let mut vm = vm_with(&[fixture!("Roots")]);
+        let class = vm.load_class("Roots").unwrap();
+        let method_index = class.find_declared_method("pick", "(Ljava/lang/Object;Ljava/lang/Object;)Ljava/lang/Object;").unwrap().unwrap();
+        let code = class.class.methods[method_index].code().unwrap();
+        let first = vm.intern_string("first").unwrap();
+        let second = vm.intern_string("second").unwrap();
+        let args = vec![Value::Reference(Some(first.clone())), Value::Reference(Some(second.clone()))];
+        let mut activation = Activation::new(&class, method_index, &code, args, None);
+
+        let roots = |activation: &Activation| {
+            let mut roots = vec![];
+            activation.visit_roots(&mut |root: roots::Root, object: &ObjectRef| match root {
+                roots::Root::Local{slot, ..} => roots.push((format!("local {}", slot), object.clone())),
+                roots::Root::OperandStack{depth, ..} => roots.push((format!("stack {}", depth), object.clone())),
+                _ => panic!("Unexpected root"),
+            });
+            roots
+        };
+        assert_eq!(vec![("local 0".to_string(), first.clone()), ("local 1".to_string(), second.clone())], roots(&activation));
+
+        // Once the first argument has been copied, it's dead, but the stack still holds it.
+        activation.instruction_pc = 2;
+        activation.stack.push(Value::Reference(Some(first.clone())));
+        assert_eq!(vec![("local 1".to_string(), second), ("stack 0".to_string(), first)], roots(&activation));
+    }
+
+    #[test]
+    fn test_compare_floats_orders_numbers() {
+        assert_eq!(1, compare_floats(2.0, 1.0, 0));
+        assert_eq!(-1, compare_floats(1.0, 2.0, 0));
+        assert_eq!(0, compare_floats(-0.0, 0.0, 0));
+    }
+
+    #[test]
+    fn test_compare_floats_with_nan() {
+        assert_eq!(-1, compare_floats(f32::NAN, 1.0, -1));
+        assert_eq!(1, compare_floats(1.0, f64::NAN, 1));
+    }
+}
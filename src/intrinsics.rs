@@ -0,0 +1,314 @@
+use crate::descriptor::FieldType;
+use crate::heap::ObjectRef;
+use crate::loaders::LoaderId;
+use crate::runtime::*;
+use crate::vm::{self, Vm, VmError};
+use std::convert::TryFrom;
+
+// Built-in versions of the core library methods that most programs spend much of their time in:
+// System.arraycopy, Object.clone and the basic String methods. These are natives in the
+// bootstrap classes, so they run as Rust code rather than being interpreted, and they work on
+// arrays' storage in bulk rather than an element at a time. Object.getClass and hashCode are
+// built into the interpreter alongside the other natives it provides.
+pub fn invoke_native(vm: &mut Vm, class: &str, name: &str, descriptor: &str, args: &[Value]) -> Result<Option<Value>, VmError> {
+    match (class, name, descriptor, args) {
+        ("java/lang/System", "arraycopy", "(Ljava/lang/Object;ILjava/lang/Object;II)V",
+            &[Value::Reference(ref src), Value::Int(src_pos), Value::Reference(ref dest), Value::Int(dest_pos), Value::Int(length)]) => {
+            match (src, dest) {
+                (Some(src), Some(dest)) => arraycopy(vm, src, src_pos, dest, dest_pos, length).map(|()| None),
+                _ => Err(vm.throw_new("java/lang/NullPointerException")),
+            }
+        },
+        ("java/lang/Object", "clone", "()Ljava/lang/Object;", &[Value::Reference(Some(ref this))]) => {
+            let cloneable = vm.load_class_with(LoaderId::BOOTSTRAP, "java/lang/Cloneable")?;
+            if !this.class().is_assignable_to(&cloneable) {
+                return Err(vm.throw_new_with_message("java/lang/CloneNotSupportedException", &this.class().java_name()));
+            }
+            Ok(Some(Value::Reference(Some(vm.clone_object(this)?))))
+        },
+        ("java/lang/String", "length", "()I", &[Value::Reference(Some(ref this))]) => {
+            with_chars(this, |chars| Some(Value::Int(chars.len() as i32)))
+        },
+        ("java/lang/String", "charAt", "(I)C", &[Value::Reference(Some(ref this)), Value::Int(index)]) => {
+            match with_chars(this, |chars| usize::try_from(index).ok().and_then(|index| chars.get(index).copied()))? {
+                Some(c) => Ok(Some(Value::Int(c as i32))),
+                None => {
+                    let message = format!("Index {} out of bounds for length {}", index, with_chars(this, <[u16]>::len)?);
+                    Err(vm.throw_new_with_message("java/lang/StringIndexOutOfBoundsException", &message))
+                },
+            }
+        },
+        ("java/lang/String", "equals", "(Ljava/lang/Object;)Z", &[Value::Reference(Some(ref this)), Value::Reference(ref other)]) => {
+            let equal = match *other {
+                Some(ref other) if other == this => true,
+                Some(ref other) if other.class().name == "java/lang/String" => with_chars(this, |this| with_chars(other, |other| this == other))??,
+                _ => false,
+            };
+            Ok(Some(Value::Int(equal as i32)))
+        },
+        ("java/lang/String", "hashCode", "()I", &[Value::Reference(Some(ref this))]) => {
+            let hash = with_chars(this, |chars| chars.iter().fold(0i32, |hash, &c| hash.wrapping_mul(31).wrapping_add(c as i32)))?;
+            Ok(Some(Value::Int(hash)))
+        },
+        ("java/lang/String", "indexOf", "(I)I", &[Value::Reference(Some(ref this)), Value::Int(c)]) => {
+            // Code points outside the BMP are searched for as surrogate pairs.
+            let index = match u32::try_from(c).ok().and_then(char::from_u32) {
+                Some(c) => {
+                    let mut units = [0; 2];
+                    let units = c.encode_utf16(&mut units);
+                    with_chars(this, |chars| chars.windows(units.len()).position(|window| window == units))?
+                },
+                None => None,
+            };
+            Ok(Some(Value::Int(index.map_or(-1, |index| index as i32))))
+        },
+        ("java/lang/String", "compareTo", "(Ljava/lang/String;)I", &[Value::Reference(Some(ref this)), Value::Reference(ref other)]) => {
+            let other = other.as_ref().ok_or_else(|| vm.throw_new("java/lang/NullPointerException"))?;
+            let difference = with_chars(this, |this| with_chars(other, |other| {
+                match this.iter().zip(other).find(|(a, b)| a != b) {
+                    Some((&a, &b)) => a as i32 - b as i32,
+                    None => this.len() as i32 - other.len() as i32,
+                }
+            }))??;
+            Ok(Some(Value::Int(difference)))
+        },
+        ("java/lang/String", "getBytes", "()[B", &[Value::Reference(Some(ref this))]) => {
+            // Encodes as UTF-8, with unpaired surrogates replaced by '?' as the JDK does.
+            let text = with_chars(this, |chars| {
+                char::decode_utf16(chars.iter().copied()).map(|c| c.unwrap_or('?')).collect::<String>()
+            })?;
+            let class = vm.load_class_with(LoaderId::BOOTSTRAP, "[B")?;
+            let bytes = vm.new_array(&class, text.len())?;
+            if let Some(elements) = bytes.fields.borrow_mut().bytes_mut() {
+                for (element, &byte) in elements.iter_mut().zip(text.as_bytes()) {
+                    *element = byte as i8;
+                }
+            }
+            Ok(Some(Value::Reference(Some(bytes))))
+        },
+        _ => Err(VmError::Unsupported(format!("native method {}.{}{}", class, name, descriptor))),
+    }
+}
+
+// Runs the function on a String's UTF-16 code units.
+fn with_chars<T>(string: &ObjectRef, f: impl FnOnce(&[u16]) -> T) -> Result<T, VmError> {
+    match vm::get_field(string, "value")? {
+        Value::Reference(Some(value)) => match value.fields.borrow().chars() {
+            Some(chars) => Ok(f(chars)),
+            None => Err(VmError::InvalidBytecode(format!("String value is a {}", value.class().name))),
+        },
+        other => Err(VmError::InvalidBytecode(format!("String has invalid value {:?}", other))),
+    }
+}
+
+// Copies a range of one array into another, with the checks and messages of HotSpot's
+// System.arraycopy. The ranges may overlap if the arrays are the same, in which case the copy
+// acts as though it went through a temporary array. When copying references into an array whose
+// component type doesn't accept every element, each element is checked as it's copied, and the
+// elements before one that doesn't fit are left copied.
+fn arraycopy(vm: &mut Vm, src: &ObjectRef, src_pos: i32, dest: &ObjectRef, dest_pos: i32, length: i32) -> Result<(), VmError> {
+    let (src_type, dest_type) = match (src.class().component_type(), dest.class().component_type()) {
+        (Some(src_type), Some(dest_type)) => (src_type, dest_type),
+        (None, _) => return Err(array_store(vm, format!("arraycopy: source type {} is not an array", src.class().java_name()))),
+        (_, None) => return Err(array_store(vm, format!("arraycopy: destination type {} is not an array", dest.class().java_name()))),
+    };
+    if src_type.is_reference() != dest_type.is_reference() || (!src_type.is_reference() && src_type != dest_type) {
+        let message = format!("arraycopy: type mismatch: can not copy {} into {}", array_type(&src_type, None), array_type(&dest_type, None));
+        return Err(array_store(vm, message));
+    }
+
+    let (src_length, dest_length) = (src.fields.borrow().len(), dest.fields.borrow().len());
+    let out_of_bounds = if src_pos < 0 {
+        Some(format!("arraycopy: source index {} out of bounds for {}", src_pos, array_type(&src_type, Some(src_length))))
+    } else if dest_pos < 0 {
+        Some(format!("arraycopy: destination index {} out of bounds for {}", dest_pos, array_type(&dest_type, Some(dest_length))))
+    } else if length < 0 {
+        Some(format!("arraycopy: length {} is negative", length))
+    } else if src_pos as usize + length as usize > src_length {
+        Some(format!("arraycopy: last source index {} out of bounds for {}", src_pos as usize + length as usize, array_type(&src_type, Some(src_length))))
+    } else if dest_pos as usize + length as usize > dest_length {
+        Some(format!("arraycopy: last destination index {} out of bounds for {}", dest_pos as usize + length as usize, array_type(&dest_type, Some(dest_length))))
+    } else {
+        None
+    };
+    if let Some(message) = out_of_bounds {
+        return Err(vm.throw_new_with_message("java/lang/ArrayIndexOutOfBoundsException", &message));
+    }
+
+    let (src_pos, dest_pos, length) = (src_pos as usize, dest_pos as usize, length as usize);
+    if src == dest {
+        src.fields.borrow_mut().copy_within(src_pos, dest_pos, length);
+        return Ok(());
+    }
+    if src.class().is_assignable_to(dest.class()) {
+        dest.fields.borrow_mut().copy_from(dest_pos, &src.fields.borrow(), src_pos, length);
+        return Ok(());
+    }
+
+    let component = dest.class().component_class.clone();
+    for offset in 0..length {
+        let element = src.fields.borrow().get(src_pos + offset);
+        if let (Value::Reference(Some(ref object)), Some(ref component)) = (&element, &component) {
+            if !object.class().is_assignable_to(component) {
+                let message = format!("arraycopy: element type {} cannot be stored to destination type {}", object.class().java_name(), dest.class().java_name());
+                return Err(array_store(vm, message));
+            }
+        }
+        dest.fields.borrow_mut().set(dest_pos + offset, element);
+    }
+    Ok(())
+}
+
+fn array_store(vm: &mut Vm, message: String) -> VmError {
+    vm.throw_new_with_message("java/lang/ArrayStoreException", &message)
+}
+
+// Describes an array as HotSpot's arraycopy messages do, such as int[10] or object array[3].
+fn array_type(component: &FieldType, length: Option<usize>) -> String {
+    let name = match *component {
+        FieldType::Boolean => "boolean",
+        FieldType::Byte => "byte",
+        FieldType::Char => "char",
+        FieldType::Short => "short",
+        FieldType::Int => "int",
+        FieldType::Long => "long",
+        FieldType::Float => "float",
+        FieldType::Double => "double",
+        FieldType::Object(_) | FieldType::Array(_) => "object array",
+    };
+    format!("{}[{}]", name, length.map_or(String::new(), |length| length.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+
+
+    fn run(vm: &mut Vm, name: &str, descriptor: &str, args: Vec<Value>) -> Value {
+        vm.invoke_static("Intrinsics", name, descriptor, args).unwrap().unwrap()
+    }
+
+    fn array(value: Value) -> ObjectRef {
+        match value {
+            Value::Reference(Some(array)) => array,
+            other => panic!("Expected an array; got {:?}", other),
+        }
+    }
+
+    fn string(vm: &mut Vm, text: &str) -> Value {
+        Value::Reference(Some(vm.new_string(text).unwrap()))
+    }
+
+    fn strings(vm: &Vm, value: Value) -> Vec<Option<String>> {
+        let elements = array(value).fields.borrow().values().unwrap().to_vec();
+        elements.iter().map(|element| match *element {
+            Value::Reference(Some(ref string)) => Some(vm.read_string(string).unwrap()),
+            _ => None,
+        }).collect()
+    }
+
+    // Checks that the result is the given exception, with the given message.
+    fn expect_thrown(vm: &Vm, result: Result<Option<Value>, VmError>, class: &str, message: &str) {
+        match result {
+            Err(VmError::UncaughtException(exception)) => {
+                assert_eq!(class, exception.class().name);
+                match vm::get_field(&exception, "detailMessage").unwrap() {
+                    Value::Reference(Some(detail)) => assert_eq!(message, vm.read_string(&detail).unwrap()),
+                    other => panic!("Expected a message; got {:?}", other),
+                }
+            },
+            other => panic!("Expected {}; got {:?}", class, other),
+        }
+    }
+
+    #[test]
+    fn test_arraycopy_handles_overlapping_ranges() {
+        let mut vm = vm_with(&[fixture!("Intrinsics"), fixture!("Intrinsics$Uncloneable")]);
+        assert_eq!(Some(&[1, 1, 2, 3, 4][..]), array(run(&mut vm, "shiftRight", "()[I", vec![])).fields.borrow().ints());
+        assert_eq!(Some(&[2, 3, 4, 5, 5][..]), array(run(&mut vm, "shiftLeft", "()[I", vec![])).fields.borrow().ints());
+        let shifted = run(&mut vm, "shiftObjectsRight", "()[Ljava/lang/Object;", vec![]);
+        assert_eq!(vec![Some("a".to_string()), Some("a".to_string()), Some("b".to_string())], strings(&vm, shifted));
+    }
+
+    #[test]
+    fn test_arraycopy_checks_reference_elements() {
+        let mut vm = vm_with(&[fixture!("Intrinsics"), fixture!("Intrinsics$Uncloneable")]);
+        let widened = run(&mut vm, "widen", "()[Ljava/lang/Object;", vec![]);
+        assert_eq!(vec![None, Some("a".to_string()), Some("b".to_string())], strings(&vm, widened));
+        assert_eq!(Value::Int(2), run(&mut vm, "partialCopy", "()I", vec![]));
+    }
+
+    #[test]
+    fn test_arraycopy_failures() {
+        let mut vm = vm_with(&[fixture!("Intrinsics"), fixture!("Intrinsics$Uncloneable")]);
+        let ints = vm.load_class("[I").unwrap();
+        let longs = vm.load_class("[J").unwrap();
+        let (src, dest) = (vm.new_array(&ints, 10).unwrap(), vm.new_array(&longs, 10).unwrap());
+        let (src, dest) = (Value::Reference(Some(src)), Value::Reference(Some(dest)));
+        let text = string(&mut vm, "text");
+        let copy = |vm: &mut Vm, src: &Value, src_pos: i32, dest: &Value, length: i32| {
+            vm.invoke_static("Intrinsics", "copy", "(Ljava/lang/Object;ILjava/lang/Object;II)V", vec![src.clone(), Value::Int(src_pos), dest.clone(), Value::Int(0), Value::Int(length)])
+        };
+        let results = [
+            (copy(&mut vm, &src, 0, &dest, 1), "java/lang/ArrayStoreException", "arraycopy: type mismatch: can not copy int[] into long[]"),
+            (copy(&mut vm, &text, 0, &src, 1), "java/lang/ArrayStoreException", "arraycopy: source type java.lang.String is not an array"),
+            (copy(&mut vm, &src, 5, &src, 6), "java/lang/ArrayIndexOutOfBoundsException", "arraycopy: last source index 11 out of bounds for int[10]"),
+            (copy(&mut vm, &src, -1, &src, 1), "java/lang/ArrayIndexOutOfBoundsException", "arraycopy: source index -1 out of bounds for int[10]"),
+            (copy(&mut vm, &src, 0, &src, -1), "java/lang/ArrayIndexOutOfBoundsException", "arraycopy: length -1 is negative"),
+        ];
+        for (result, class, message) in results {
+            expect_thrown(&vm, result, class, message);
+        }
+        match copy(&mut vm, &Value::null(), 0, &src, 0) {
+            Err(VmError::UncaughtException(exception)) => assert_eq!("java/lang/NullPointerException", exception.class().name),
+            other => panic!("Expected NullPointerException; got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_clone() {
+        let mut vm = vm_with(&[fixture!("Intrinsics"), fixture!("Intrinsics$Uncloneable")]);
+        assert_eq!(Value::Int(1), run(&mut vm, "cloneArray", "()Z", vec![]));
+        assert_eq!(Value::Int(1), run(&mut vm, "cloneObject", "()Z", vec![]));
+        assert_eq!(Value::Int(1), run(&mut vm, "cloneUncloneable", "()Z", vec![]));
+    }
+
+    #[test]
+    fn test_string_intrinsics() {
+        let mut vm = vm_with(&[fixture!("Intrinsics"), fixture!("Intrinsics$Uncloneable")]);
+        let (text, same, other) = (string(&mut vm, "text"), string(&mut vm, "text"), string(&mut vm, "texts"));
+        assert_eq!(Value::Int(4), run(&mut vm, "length", "(Ljava/lang/String;)I", vec![text.clone()]));
+        assert_eq!(Value::Int('x' as i32), run(&mut vm, "charAt", "(Ljava/lang/String;I)C", vec![text.clone(), Value::Int(2)]));
+        assert_eq!(Value::Int(1), run(&mut vm, "stringsEqual", "(Ljava/lang/String;Ljava/lang/Object;)Z", vec![text.clone(), same.clone()]));
+        assert_eq!(Value::Int(0), run(&mut vm, "stringsEqual", "(Ljava/lang/String;Ljava/lang/Object;)Z", vec![text.clone(), other.clone()]));
+        assert_eq!(Value::Int(0), run(&mut vm, "stringsEqual", "(Ljava/lang/String;Ljava/lang/Object;)Z", vec![text.clone(), Value::null()]));
+        assert_eq!(Value::Int(3556653), run(&mut vm, "hash", "(Ljava/lang/String;)I", vec![same]));
+        assert_eq!(Value::Int(2), run(&mut vm, "indexOf", "(Ljava/lang/String;I)I", vec![text.clone(), Value::Int('x' as i32)]));
+        assert_eq!(Value::Int(-1), run(&mut vm, "indexOf", "(Ljava/lang/String;I)I", vec![text.clone(), Value::Int('q' as i32)]));
+        assert_eq!(Value::Int(-1), run(&mut vm, "compare", "(Ljava/lang/String;Ljava/lang/String;)I", vec![text.clone(), other.clone()]));
+        let tx = string(&mut vm, "tx");
+        assert_eq!(Value::Int('x' as i32 - 'e' as i32), run(&mut vm, "compare", "(Ljava/lang/String;Ljava/lang/String;)I", vec![tx, text.clone()]));
+
+        let result = vm.invoke_static("Intrinsics", "charAt", "(Ljava/lang/String;I)C", vec![text, Value::Int(4)]);
+        expect_thrown(&vm, result, "java/lang/StringIndexOutOfBoundsException", "Index 4 out of bounds for length 4");
+    }
+
+    #[test]
+    fn test_get_bytes_encodes_utf8() {
+        let mut vm = vm_with(&[fixture!("Intrinsics"), fixture!("Intrinsics$Uncloneable")]);
+        let text = string(&mut vm, "caf\u{e9} \u{1F600}");
+        let bytes = array(run(&mut vm, "bytes", "(Ljava/lang/String;)[B", vec![text]));
+        let bytes: Vec<u8> = bytes.fields.borrow().bytes().unwrap().iter().map(|&b| b as u8).collect();
+        assert_eq!("caf\u{e9} \u{1F600}".as_bytes(), &bytes[..]);
+    }
+
+    #[test]
+    fn test_index_of_finds_supplementary_characters() {
+        let mut vm = vm_with(&[fixture!("Intrinsics"), fixture!("Intrinsics$Uncloneable")]);
+        let text = string(&mut vm, "a\u{1F600}b");
+        assert_eq!(Value::Int(1), run(&mut vm, "indexOf", "(Ljava/lang/String;I)I", vec![text.clone(), Value::Int(0x1F600)]));
+        assert_eq!(Value::Int(3), run(&mut vm, "indexOf", "(Ljava/lang/String;I)I", vec![text.clone(), Value::Int('b' as i32)]));
+        assert_eq!(Value::Int(-1), run(&mut vm, "indexOf", "(Ljava/lang/String;I)I", vec![text, Value::Int(-5)]));
+    }
+}
@@ -0,0 +1,467 @@
+use crate::classes::{Class, ClassHeader};
+use crate::classloader::{self, ClassLoaderError};
+use crate::classpath::{self, ClassSource, ClasspathError};
+use crate::crash;
+use crate::zip::{ZipArchive, ZipEntry, ZipError};
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{error, fmt, fs, thread};
+
+pub const MANIFEST_PATH: &str = "META-INF/MANIFEST.MF";
+
+// A class entry's name, with its class or why it couldn't be read.
+pub type LoadedEntry = (String, Result<Class, EntryError>);
+
+// A class entry's name, with its header or why it couldn't be read.
+pub type ScannedEntry = (String, Result<ClassHeader, EntryError>);
+
+// The Java version whose classes a multi-release JAR provides by default. The bootstrap classes
+// are Java 8's, so versioned entries are ignored unless a later version is asked for.
+pub const DEFAULT_RUNTIME_VERSION: u32 = 8;
+
+const VERSIONS_PATH: &str = "META-INF/versions/";
+
+// A JAR file on the classpath. The whole archive is read when it's opened, and entries are
+// decompressed as they're asked for.
+pub struct JarSource {
+    path: PathBuf,
+    archive: ZipArchive,
+    manifest: Manifest,
+    multi_release: bool,
+    runtime_version: u32,
+    versions: Vec<u32>, // Those with versioned entries, newest first.
+}
+
+impl JarSource {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<JarSource, ClasspathError> {
+        let path = path.as_ref().to_path_buf();
+        let data = fs::read(&path).map_err(|err| ClasspathError::Io{path: path.clone(), kind: err.kind()})?;
+        JarSource::from_bytes(path, data)
+    }
+
+    // Reads a JAR from memory. The path is used to resolve the manifest's Class-Path, and in
+    // errors.
+    pub fn from_bytes(path: PathBuf, data: Vec<u8>) -> Result<JarSource, ClasspathError> {
+        let archive_error = |cause: ZipError| ClasspathError::Archive{path: path.clone(), cause};
+        let archive = ZipArchive::new(data).map_err(archive_error)?;
+        let manifest = match archive.read(MANIFEST_PATH).map_err(archive_error)? {
+            Some(bytes) => Manifest::parse(&String::from_utf8_lossy(&bytes)),
+            None => Manifest::default(),
+        };
+
+        // Per JEP 238, versioned entries are only used if the manifest says the JAR is
+        // multi-release, and only for versions from 9 on.
+        let multi_release = manifest.attribute("Multi-Release").is_some_and(|value| value.eq_ignore_ascii_case("true"));
+        let mut versions: Vec<u32> = vec![];
+        if multi_release {
+            versions = archive.entries().iter()
+                .filter_map(|entry| entry.name.strip_prefix(VERSIONS_PATH)?.split('/').next()?.parse().ok())
+                .filter(|&version| version >= 9)
+                .collect();
+            versions.sort_unstable_by(|a, b| b.cmp(a));
+            versions.dedup();
+        }
+        Ok(JarSource {path, archive, manifest, multi_release, runtime_version: DEFAULT_RUNTIME_VERSION, versions})
+    }
+
+    pub fn runtime_version(&self) -> u32 {
+        self.runtime_version
+    }
+
+    pub fn is_multi_release(&self) -> bool {
+        self.multi_release
+    }
+
+    // The name of the entry that's used for the given path. Entries under META-INF aren't
+    // versioned.
+    pub fn entry_name(&self, path: &str) -> String {
+        if !path.starts_with("META-INF/") {
+            for version in self.versions.iter().filter(|&&version| version <= self.runtime_version) {
+                let versioned = format!("{}{}/{}", VERSIONS_PATH, version, path);
+                if self.archive.contains(&versioned) {
+                    return versioned;
+                }
+            }
+        }
+        path.to_string()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn archive(&self) -> &ZipArchive {
+        &self.archive
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    // The entries of the manifest's Class-Path attribute, which are URLs relative to the
+    // directory containing the JAR.
+    pub fn class_path(&self) -> Vec<PathBuf> {
+        let base = self.path.parent().unwrap_or_else(|| Path::new(""));
+        self.manifest.attribute("Class-Path").map_or(vec![], |class_path| {
+            class_path.split_whitespace().map(|url| base.join(decode_url_path(url))).collect()
+        })
+    }
+
+    // The class named by the manifest's Main-Class attribute, which java -jar runs, in internal
+    // form.
+    pub fn main_class(&self) -> Option<String> {
+        self.manifest.attribute("Main-Class").map(|main_class| main_class.trim().replace('.', "/"))
+    }
+
+    // The entries that are classes, other than versioned entries and module descriptors, in the
+    // order they're stored.
+    pub fn class_entries(&self) -> Vec<&ZipEntry> {
+        self.archive.entries().iter()
+            .filter(|entry| entry.name.ends_with(".class") && !entry.name.starts_with("META-INF/") && !entry.name.ends_with("module-info.class"))
+            .collect()
+    }
+
+    // Reads and parses every class entry, on as many threads as there are CPUs, giving each
+    // entry's name with its class or why it couldn't be read, in the order they're stored.
+    pub fn load_classes(&self) -> Vec<LoadedEntry> {
+        let entries = self.class_entries();
+        let classes = parallel_map(&entries, |entry| {
+            let bytes = self.archive.read_entry(entry)?;
+            Ok(classloader::load_class(&bytes)?)
+        });
+        entries.into_iter().map(|entry| entry.name.clone()).zip(classes.into_iter().map(flatten_panic)).collect()
+    }
+
+    // Reads just the header of every class entry, as load_classes reads whole classes, for when
+    // the names, versions, flags and supertypes of the classes are all that's needed.
+    pub fn scan_classes(&self) -> Vec<ScannedEntry> {
+        let entries = self.class_entries();
+        let headers = parallel_map(&entries, |entry| {
+            let bytes = self.archive.read_entry(entry)?;
+            Ok(classloader::load_class_header(&bytes)?)
+        });
+        entries.into_iter().map(|entry| entry.name.clone()).zip(headers.into_iter().map(flatten_panic)).collect()
+    }
+}
+
+// Parses every class in the JAR at the path in parallel, as JarSource::load_classes does, for
+// tools that scan whole JARs. Only opening the JAR fails as a whole.
+pub fn load_jar_parallel<P: AsRef<Path>>(path: P) -> Result<Vec<LoadedEntry>, ClasspathError> {
+    Ok(JarSource::open(path)?.load_classes())
+}
+
+// Reads the header of every class in the JAR at the path, as JarSource::scan_classes does.
+pub fn scan_jar<P: AsRef<Path>>(path: P) -> Result<Vec<ScannedEntry>, ClasspathError> {
+    Ok(JarSource::open(path)?.scan_classes())
+}
+
+// Applies the function to each item on a pool of threads, returning the results in order. An item
+// whose call panicked gets the panic's message instead, and the other items are unaffected.
+pub fn parallel_map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<Result<R, String>> {
+    let threads = thread::available_parallelism().map_or(1, |count| count.get()).min(items.len());
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, Result<R, String>)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads).map(|_| scope.spawn(|| {
+            let mut results = vec![];
+            loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                match items.get(index) {
+                    Some(item) => {
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| f(item)));
+                        results.push((index, result.map_err(|payload| crash::panic_message(&*payload).to_string())));
+                    },
+                    None => return results,
+                }
+            }
+        })).collect();
+        // Panics in f are caught above, so a worker only fails to join if parallel_map itself has a
+        // bug, which is passed on rather than losing items.
+        workers.into_iter().flat_map(|worker| worker.join().unwrap_or_else(|payload| panic::resume_unwind(payload))).collect()
+    });
+    results.sort_by_key(|&(index, _)| index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+fn flatten_panic<R>(result: Result<Result<R, EntryError>, String>) -> Result<R, EntryError> {
+    result.unwrap_or_else(|message| Err(EntryError::Panicked(message)))
+}
+
+// Why a class entry of a JAR couldn't be read.
+#[derive(Debug, PartialEq)]
+pub enum EntryError {
+    Archive(ZipError),
+    ClassLoad(ClassLoaderError),
+    Panicked(String), // Reading the entry hit a bug in the VM, with the panic's message.
+}
+
+impl std::convert::From<ZipError> for EntryError {
+    fn from(err: ZipError) -> EntryError {
+        EntryError::Archive(err)
+    }
+}
+
+impl std::convert::From<ClassLoaderError> for EntryError {
+    fn from(err: ClassLoaderError) -> EntryError {
+        EntryError::ClassLoad(err)
+    }
+}
+
+impl fmt::Display for EntryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EntryError::Archive(ref cause) => write!(f, "Unreadable entry: {}", cause),
+            EntryError::ClassLoad(ref cause) => write!(f, "Invalid class file: {}", cause),
+            EntryError::Panicked(ref message) => write!(f, "Internal error reading entry: {}", message),
+        }
+    }
+}
+
+impl error::Error for EntryError {
+    fn description(&self) -> &str {
+        match *self {
+            EntryError::Archive(_) => "Unreadable entry",
+            EntryError::ClassLoad(_) => "Invalid class file",
+            EntryError::Panicked(_) => "Internal error reading entry",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            EntryError::Archive(ref cause) => Some(cause),
+            EntryError::ClassLoad(ref cause) => Some(cause),
+            EntryError::Panicked(_) => None,
+        }
+    }
+}
+
+impl ClassSource for JarSource {
+    fn manifest(&self) -> Option<&Manifest> {
+        Some(&self.manifest)
+    }
+
+    // Each lookup in a multi-release JAR uses the entry for the newest version up to this one, or
+    // the unversioned entry if there's none.
+    fn set_runtime_version(&mut self, version: u32) {
+        self.runtime_version = version;
+    }
+
+    fn origin(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    fn find_resource(&self, path: &str) -> Result<Option<Vec<u8>>, ClasspathError> {
+        classpath::check_resource_path(path)?;
+        self.archive.read(&self.entry_name(path)).map_err(|cause| ClasspathError::Archive{path: self.path.clone(), cause})
+    }
+}
+
+// Undoes percent-encoding, e.g. of spaces in a Class-Path entry.
+fn decode_url_path(url: &str) -> String {
+    let bytes = url.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// The attributes of a JAR manifest: the main ones, and those of the per-entry sections that
+// follow, each of which starts with a Name attribute. Sections for packages, named like
+// com/example/, override the main attributes for the classes in the package.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Manifest {
+    attributes: HashMap<String, String>, // Keyed by lower-cased name, since names are case-insensitive.
+    sections: HashMap<String, HashMap<String, String>>, // By entry name.
+}
+
+impl Manifest {
+    pub fn parse(text: &str) -> Manifest {
+        let mut sections = vec![HashMap::new()];
+        let mut current: Option<(String, String)> = None;
+        for line in text.lines() {
+            // A line starting with a space continues the previous one, since lines are limited to
+            // 72 bytes. Blank lines separate sections.
+            if let Some(continuation) = line.strip_prefix(' ') {
+                if let Some((_, ref mut value)) = current {
+                    value.push_str(continuation);
+                }
+                continue;
+            }
+            if let Some((name, value)) = current.take() {
+                sections.last_mut().expect("There's always a section").insert(name, value);
+            }
+            if line.is_empty() {
+                if !sections.last().expect("There's always a section").is_empty() {
+                    sections.push(HashMap::new());
+                }
+                continue;
+            }
+            if let Some((name, value)) = line.split_once(": ") {
+                current = Some((name.to_ascii_lowercase(), value.to_string()));
+            }
+        }
+        if let Some((name, value)) = current {
+            sections.last_mut().expect("There's always a section").insert(name, value);
+        }
+
+        let mut sections = sections.into_iter();
+        let attributes = sections.next().expect("There's always a section");
+        let sections = sections.filter_map(|mut section| Some((section.remove("name")?, section))).collect();
+        Manifest {attributes, sections}
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.get(&name.to_ascii_lowercase()).map(String::as_str)
+    }
+
+    // An attribute of the named entry's section, if it has one.
+    pub fn entry_attribute(&self, entry: &str, name: &str) -> Option<&str> {
+        self.sections.get(entry)?.get(&name.to_ascii_lowercase()).map(String::as_str)
+    }
+
+    // An attribute as it applies to a package, e.g. com/example: from the package's section if it
+    // has one, or else from the main attributes.
+    pub fn package_attribute(&self, package: &str, name: &str) -> Option<&str> {
+        self.entry_attribute(&format!("{}/", package), name).or_else(|| self.attribute(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classpath::Classpath;
+
+    fn jar(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/jars").join(name)
+    }
+
+    #[test]
+    fn test_manifest_continuation_lines() {
+        let manifest = Manifest::parse("Manifest-Version: 1.0\r\nClass-Path: a.jar b\r\n .jar\r\n\r\nName: c\r\nClass-Path: d.jar\r\n");
+        assert_eq!(Some("1.0"), manifest.attribute("manifest-version"));
+        assert_eq!(Some("a.jar b.jar"), manifest.attribute("Class-Path"));
+        assert_eq!(None, manifest.attribute("Name"));
+        assert_eq!(Some("d.jar"), manifest.entry_attribute("c", "class-path"));
+    }
+
+    #[test]
+    fn test_package_attributes() {
+        let manifest = Manifest::parse("Sealed: false\nImplementation-Version: 1\n\nName: com/example/\nSealed: true\n");
+        assert_eq!(Some("true"), manifest.package_attribute("com/example", "Sealed"));
+        assert_eq!(Some("1"), manifest.package_attribute("com/example", "Implementation-Version"));
+        assert_eq!(Some("false"), manifest.package_attribute("com/other", "Sealed"));
+    }
+
+    #[test]
+    fn test_decode_url_path() {
+        assert_eq!("my libs/a.jar", decode_url_path("my%20libs/a.jar"));
+        assert_eq!("100%", decode_url_path("100%"));
+    }
+
+    #[test]
+    fn test_reads_compressed_and_stored_entries() {
+        let app = JarSource::open(jar("app.jar")).unwrap();
+        assert_eq!(Some("com.example.App"), app.manifest().attribute("Main-Class"));
+        assert_eq!(Some("com/example/App".to_string()), app.main_class());
+        assert!(app.find_class("com/example/App").unwrap().is_some());
+        assert_eq!(Some(b"Hello from a JAR\n".to_vec()), app.find_resource("com/example/message.txt").unwrap());
+        assert_eq!(None, app.find_class("com/example/lib/Lib").unwrap());
+
+        let lib = JarSource::open(jar("lib.jar")).unwrap();
+        assert!(lib.find_class("com/example/lib/Lib").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_classpath_follows_manifest_class_path() {
+        let classpath = Classpath::parse(&jar("app.jar")).unwrap();
+        assert_eq!(2, classpath.len()); // The missing JAR in the manifest is skipped.
+        assert!(classpath.find_class("com/example/lib/Lib").unwrap().is_some());
+    }
+
+    fn multi_release(version: u32) -> JarSource {
+        let mut jar = JarSource::open(jar("multi-release.jar")).unwrap();
+        jar.set_runtime_version(version);
+        jar
+    }
+
+    #[test]
+    fn test_multi_release_entries() {
+        assert!(multi_release(8).is_multi_release());
+        assert!(!JarSource::open(jar("app.jar")).unwrap().is_multi_release());
+        assert_eq!("com/example/Release.class", multi_release(8).entry_name("com/example/Release.class"));
+        assert_eq!("com/example/Release.class", multi_release(10).entry_name("com/example/Release.class"));
+        assert_eq!("META-INF/versions/11/com/example/Release.class", multi_release(17).entry_name("com/example/Release.class"));
+        assert_eq!("META-INF/versions/9/com/example/release.txt", multi_release(17).entry_name("com/example/release.txt"));
+        assert_eq!(Some(b"8\n".to_vec()), multi_release(8).find_resource("com/example/release.txt").unwrap());
+        assert_eq!(Some(b"9\n".to_vec()), multi_release(11).find_resource("com/example/release.txt").unwrap());
+    }
+
+    #[test]
+    fn test_load_jar_parallel() {
+        let classes = load_jar_parallel(jar("verify.jar")).unwrap();
+        let names: Vec<&str> = classes.iter().map(|(entry, _)| entry.as_str()).collect();
+        assert_eq!(vec!["Boom.class", "Legacy.class", "Unverifiable.class"], names);
+        let jar = JarSource::open(jar("verify.jar")).unwrap();
+        for ((entry, class), zip_entry) in classes.iter().zip(jar.class_entries()) {
+            let serial = classloader::load_class(&jar.archive().read_entry(zip_entry).unwrap()).unwrap();
+            assert_eq!(&serial, class.as_ref().unwrap(), "{}", entry);
+        }
+        assert!(matches!(load_jar_parallel(jar.path().with_file_name("missing.jar")), Err(ClasspathError::Io{..})));
+    }
+
+    #[test]
+    fn test_scan_jar() {
+        let headers = scan_jar(jar("verify.jar")).unwrap();
+        let classes = load_jar_parallel(jar("verify.jar")).unwrap();
+        assert_eq!(classes.len(), headers.len());
+        for ((entry, header), (_, class)) in headers.iter().zip(&classes) {
+            let (header, class) = (header.as_ref().unwrap(), class.as_ref().unwrap());
+            assert_eq!(class.name().unwrap(), header.name, "{}", entry);
+            assert_eq!(class.super_class_name().unwrap(), header.super_class.as_deref());
+            assert_eq!((class.major_version, class.flags), (header.major_version, header.flags));
+        }
+        assert!(matches!(scan_jar(jar("missing.jar")), Err(ClasspathError::Io{..})));
+    }
+
+    #[test]
+    fn test_parallel_map_keeps_order() {
+        let items: Vec<u32> = (0..1000).collect();
+        assert_eq!(items.iter().map(|item| Ok(item * 2)).collect::<Vec<_>>(), parallel_map(&items, |item| item * 2));
+        assert!(parallel_map(&[] as &[u32], |item| *item).is_empty());
+    }
+
+    #[test]
+    fn test_parallel_map_catches_panics() {
+        let items: Vec<u32> = (0..100).collect();
+        let results = parallel_map(&items, |&item| if item % 10 == 3 { panic!("Bad item {}", item) } else { item });
+        for (item, result) in items.into_iter().zip(results) {
+            match item % 10 {
+                3 => assert_eq!(Err(format!("Bad item {}", item)), result),
+                _ => assert_eq!(Ok(item), result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_archive() {
+        match JarSource::from_bytes(PathBuf::from("bad.jar"), b"not a jar".to_vec()) {
+            Err(ClasspathError::Archive{cause: ZipError::NotAnArchive, ..}) => (),
+            other => panic!("Expected an invalid archive; got {:?}", other.map(|jar| jar.path)),
+        }
+    }
+}
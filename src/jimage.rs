@@ -0,0 +1,448 @@
+use crate::classpath::{self, ClassSource, ClasspathError};
+use crate::inflate::{self, InflateError};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::path::{Path, PathBuf};
+use std::{error, fmt, fs, str};
+
+const MAGIC: u32 = 0xcafe_dada;
+const MAJOR_VERSION: u16 = 1;
+const HEADER_SIZE: usize = 7 * 4;
+const HASH_MULTIPLIER: u32 = 0x0100_0193;
+
+// Each resource may be compressed more than once, by successive jlink plugins. Each layer starts
+// with a header naming the plugin that undoes it.
+const COMPRESSED_MAGIC: u32 = 0xcafe_fafa;
+const COMPRESSED_HEADER_SIZE: usize = 4 + 8 + 8 + 4 + 4 + 1;
+
+// The kinds of attribute a location may have. Those not given are zero.
+const ATTRIBUTE_MODULE: usize = 1;
+const ATTRIBUTE_PARENT: usize = 2;
+const ATTRIBUTE_BASE: usize = 3;
+const ATTRIBUTE_EXTENSION: usize = 4;
+const ATTRIBUTE_OFFSET: usize = 5;
+const ATTRIBUTE_COMPRESSED: usize = 6;
+const ATTRIBUTE_UNCOMPRESSED: usize = 7;
+const ATTRIBUTE_COUNT: usize = 8;
+
+// The modules image of a JDK 9 or later, lib/modules, in the jimage format jlink writes. This lets
+// joyvm read the platform classes of a real JDK rather than its own. Like a JAR, the whole image
+// is read when it's opened, and resources are decompressed as they're asked for.
+//
+// Resources are named /module/path within the image, e.g. /java.base/java/lang/Object.class.
+// Each package belongs to exactly one module, so the module holding a class is found from its
+// package.
+pub struct JimageSource {
+    path: PathBuf,
+    data: Vec<u8>,
+    big_endian: bool,
+    table_length: usize,
+    redirect_start: usize,
+    offsets_start: usize,
+    locations_start: usize,
+    strings_start: usize,
+    content_start: usize,
+    packages: HashMap<String, String>, // The module of each package that has classes.
+}
+
+// The attributes of a resource, decoded from its entry in the locations table.
+struct Location {
+    attributes: [u64; ATTRIBUTE_COUNT],
+}
+
+impl Location {
+    fn get(&self, kind: usize) -> usize {
+        self.attributes[kind] as usize
+    }
+}
+
+impl JimageSource {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<JimageSource, ClasspathError> {
+        let path = path.as_ref().to_path_buf();
+        let data = fs::read(&path).map_err(|err| ClasspathError::Io{path: path.clone(), kind: err.kind()})?;
+        JimageSource::from_bytes(path, data)
+    }
+
+    // Opens the image of the JDK installed in the given directory, e.g. $JAVA_HOME.
+    pub fn from_java_home<P: AsRef<Path>>(java_home: P) -> Result<JimageSource, ClasspathError> {
+        JimageSource::open(java_home.as_ref().join("lib").join("modules"))
+    }
+
+    // Reads an image from memory. The path is only used in errors.
+    pub fn from_bytes(path: PathBuf, data: Vec<u8>) -> Result<JimageSource, ClasspathError> {
+        JimageSource::parse(data).map(|mut image| {
+            image.path = path.clone();
+            image
+        }).map_err(|cause| ClasspathError::Image{path, cause})
+    }
+
+    fn parse(data: Vec<u8>) -> Result<JimageSource, JimageError> {
+        // The image is in the byte order of the platform it was built for.
+        let magic: [u8; 4] = data.get(..4).ok_or(JimageError::NotAnImage)?.try_into().expect("Slice has length 4");
+        let big_endian = if u32::from_le_bytes(magic) == MAGIC {
+            false
+        } else if u32::from_be_bytes(magic) == MAGIC {
+            true
+        } else {
+            return Err(JimageError::NotAnImage);
+        };
+        let mut image = JimageSource {
+            path: PathBuf::new(),
+            data,
+            big_endian,
+            table_length: 0,
+            redirect_start: HEADER_SIZE,
+            offsets_start: 0,
+            locations_start: 0,
+            strings_start: 0,
+            content_start: 0,
+            packages: HashMap::new(),
+        };
+
+        let version = image.u32_at(4)?;
+        let (major, minor) = ((version >> 16) as u16, version as u16);
+        if major != MAJOR_VERSION {
+            return Err(JimageError::UnsupportedVersion(major, minor));
+        }
+        image.table_length = image.u32_at(16)? as usize;
+        let locations_size = image.u32_at(20)? as usize;
+        let strings_size = image.u32_at(24)? as usize;
+        image.offsets_start = image.redirect_start + image.table_length * 4;
+        image.locations_start = image.offsets_start + image.table_length * 4;
+        image.strings_start = image.locations_start + locations_size;
+        image.content_start = image.strings_start + strings_size;
+        if image.content_start > image.data.len() {
+            return Err(JimageError::Truncated);
+        }
+
+        // The images jlink writes also have directories of modules and packages, under /modules
+        // and /packages, but going through every class is simpler and only done once.
+        let mut packages = HashMap::new();
+        for index in 0..image.table_length {
+            let location = image.location(image.u32_at(image.offsets_start + index * 4)? as usize)?;
+            let module = image.string(location.get(ATTRIBUTE_MODULE))?;
+            if image.string(location.get(ATTRIBUTE_EXTENSION))? != "class" || module.is_empty() || module == "modules" || module == "packages" {
+                continue;
+            }
+            let package = image.string(location.get(ATTRIBUTE_PARENT))?;
+            packages.entry(package.to_string()).or_insert_with(|| module.to_string());
+        }
+        image.packages = packages;
+        Ok(image)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // The number of resources in the image.
+    pub fn len(&self) -> usize {
+        self.table_length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table_length == 0
+    }
+
+    // The module that has the classes of a package, e.g. java.base for java/lang.
+    pub fn module_of(&self, package: &str) -> Option<&str> {
+        self.packages.get(package).map(String::as_str)
+    }
+
+    // The full names of every resource in the image, as read takes them.
+    pub fn names(&self) -> Result<Vec<String>, JimageError> {
+        (0..self.table_length)
+            .map(|index| self.full_name(&self.location(self.u32_at(self.offsets_start + index * 4)? as usize)?))
+            .collect()
+    }
+
+    // Reads the resource with the given name within the image, e.g.
+    // /java.base/java/lang/Object.class, decompressing it if need be.
+    pub fn read(&self, name: &str) -> Result<Option<Vec<u8>>, JimageError> {
+        let location = match self.find_location(name)? {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        let start = self.content_start + location.get(ATTRIBUTE_OFFSET);
+        let compressed = location.get(ATTRIBUTE_COMPRESSED) != 0;
+        let size = if compressed { location.get(ATTRIBUTE_COMPRESSED) } else { location.get(ATTRIBUTE_UNCOMPRESSED) };
+        let mut content = self.data.get(start..start + size).ok_or(JimageError::Truncated)?.to_vec();
+        while compressed && self.read_u32(&content, 0) == Some(COMPRESSED_MAGIC) {
+            content = self.decompress(&content)?;
+        }
+        Ok(Some(content))
+    }
+
+    // Looks a name up in the image's perfect hash table. Each name's hash picks an entry in the
+    // redirect table, which either gives the name's slot directly, as a negative number, or
+    // gives the seed for a second hash that does. The hash doesn't rule out other names, so the
+    // location found is checked against the name.
+    fn find_location(&self, name: &str) -> Result<Option<Location>, JimageError> {
+        if self.table_length == 0 {
+            return Ok(None);
+        }
+        let bucket = hash(name, HASH_MULTIPLIER) as usize % self.table_length;
+        let index = match self.u32_at(self.redirect_start + bucket * 4)? as i32 {
+            0 => return Ok(None),
+            redirect if redirect < 0 => (-1 - redirect) as usize,
+            seed => hash(name, seed as u32) as usize % self.table_length,
+        };
+        if index >= self.table_length {
+            return Err(JimageError::Truncated);
+        }
+        let location = self.location(self.u32_at(self.offsets_start + index * 4)? as usize)?;
+        Ok(if self.full_name(&location)? == name { Some(location) } else { None })
+    }
+
+    // Decodes the attributes at the given offset in the locations table. Each starts with a byte
+    // giving its kind in the top five bits and its length less one in the bottom three, followed
+    // by its value in big-endian order. A kind of zero ends the list.
+    fn location(&self, offset: usize) -> Result<Location, JimageError> {
+        let mut attributes = [0; ATTRIBUTE_COUNT];
+        let mut position = self.locations_start + offset;
+        loop {
+            let byte = *self.data[..self.strings_start].get(position).ok_or(JimageError::Truncated)?;
+            let (kind, length) = ((byte >> 3) as usize, (byte & 7) as usize + 1);
+            if kind == 0 {
+                return Ok(Location {attributes});
+            }
+            let value = self.data.get(position + 1..position + 1 + length).ok_or(JimageError::Truncated)?;
+            if kind < ATTRIBUTE_COUNT {
+                attributes[kind] = value.iter().fold(0, |value, &byte| value << 8 | byte as u64);
+            }
+            position += 1 + length;
+        }
+    }
+
+    fn full_name(&self, location: &Location) -> Result<String, JimageError> {
+        let mut name = String::new();
+        let module = self.string(location.get(ATTRIBUTE_MODULE))?;
+        if !module.is_empty() {
+            name.push('/');
+            name.push_str(module);
+            name.push('/');
+        }
+        let parent = self.string(location.get(ATTRIBUTE_PARENT))?;
+        if !parent.is_empty() {
+            name.push_str(parent);
+            name.push('/');
+        }
+        name.push_str(self.string(location.get(ATTRIBUTE_BASE))?);
+        let extension = self.string(location.get(ATTRIBUTE_EXTENSION))?;
+        if !extension.is_empty() {
+            name.push('.');
+            name.push_str(extension);
+        }
+        Ok(name)
+    }
+
+    // Reads the null-terminated string at the given offset in the strings table. Strings are
+    // stored in modified UTF-8, which is the same as UTF-8 for the names of modules and classes
+    // that are in practice.
+    fn string(&self, offset: usize) -> Result<&str, JimageError> {
+        let strings = &self.data[self.strings_start..self.content_start];
+        let bytes = strings.get(offset..).ok_or(JimageError::Truncated)?;
+        let end = bytes.iter().position(|&byte| byte == 0).ok_or(JimageError::Truncated)?;
+        str::from_utf8(&bytes[..end]).map_err(|_| JimageError::InvalidString(offset))
+    }
+
+    // Undoes one layer of compression. Only jlink's zip plugin is supported; its output is zlib
+    // data, i.e. DEFLATE data after a two-byte header.
+    fn decompress(&self, content: &[u8]) -> Result<Vec<u8>, JimageError> {
+        let compressed_size = self.read_u64(content, 4).ok_or(JimageError::Truncated)? as usize;
+        let uncompressed_size = self.read_u64(content, 12).ok_or(JimageError::Truncated)? as usize;
+        let decompressor = self.string(self.read_u32(content, 20).ok_or(JimageError::Truncated)? as usize)?;
+        let compressed = content.get(COMPRESSED_HEADER_SIZE..COMPRESSED_HEADER_SIZE + compressed_size).ok_or(JimageError::Truncated)?;
+        if decompressor != "zip" {
+            return Err(JimageError::UnsupportedCompression(decompressor.to_string()));
+        }
+        let deflated = compressed.get(2..).ok_or(JimageError::Truncated)?;
+        Ok(inflate::inflate(deflated, uncompressed_size)?)
+    }
+
+    fn u32_at(&self, offset: usize) -> Result<u32, JimageError> {
+        self.read_u32(&self.data, offset).ok_or(JimageError::Truncated)
+    }
+
+    fn read_u32(&self, data: &[u8], offset: usize) -> Option<u32> {
+        let bytes = data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+
+    fn read_u64(&self, data: &[u8], offset: usize) -> Option<u64> {
+        let bytes = data.get(offset..offset + 8)?.try_into().ok()?;
+        Some(if self.big_endian { u64::from_be_bytes(bytes) } else { u64::from_le_bytes(bytes) })
+    }
+}
+
+impl ClassSource for JimageSource {
+    fn origin(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    // Resources are looked up in the module of their package, so those outside any package with
+    // classes, such as module-info.class, aren't found.
+    fn find_resource(&self, path: &str) -> Result<Option<Vec<u8>>, ClasspathError> {
+        classpath::check_resource_path(path)?;
+        let module = match path.rsplit_once('/').and_then(|(package, _)| self.module_of(package)) {
+            Some(module) => module,
+            None => return Ok(None),
+        };
+        self.read(&format!("/{}/{}", module, path)).map_err(|cause| ClasspathError::Image{path: self.path.clone(), cause})
+    }
+}
+
+// The FNV-1a style hash that jimage tables are keyed by, over the name's UTF-8 bytes.
+fn hash(name: &str, seed: u32) -> u32 {
+    name.bytes().fold(seed, |hash, byte| hash.wrapping_mul(HASH_MULTIPLIER) ^ byte as u32) & 0x7fff_ffff
+}
+
+#[derive(Debug, PartialEq)]
+pub enum JimageError {
+    NotAnImage,
+    UnsupportedVersion(u16, u16),
+    Truncated,
+    InvalidString(usize), // The offset of a string that isn't valid UTF-8.
+    UnsupportedCompression(String), // The jlink plugin that compressed a resource.
+    Inflate(InflateError),
+}
+
+impl std::convert::From<InflateError> for JimageError {
+    fn from(cause: InflateError) -> JimageError {
+        JimageError::Inflate(cause)
+    }
+}
+
+impl fmt::Display for JimageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JimageError::NotAnImage => write!(f, "Not a jimage file"),
+            JimageError::UnsupportedVersion(major, minor) => write!(f, "Unsupported jimage version {}.{}", major, minor),
+            JimageError::Truncated => write!(f, "Image is truncated"),
+            JimageError::InvalidString(offset) => write!(f, "Invalid string at offset {}", offset),
+            JimageError::UnsupportedCompression(ref decompressor) => write!(f, "Unsupported compression: {}", decompressor),
+            JimageError::Inflate(ref cause) => write!(f, "Failed to decompress resource: {}", cause),
+        }
+    }
+}
+
+impl error::Error for JimageError {
+    fn description(&self) -> &str {
+        match *self {
+            JimageError::NotAnImage => "Not a jimage file",
+            JimageError::UnsupportedVersion(..) => "Unsupported jimage version",
+            JimageError::Truncated => "Image is truncated",
+            JimageError::InvalidString(_) => "Invalid string",
+            JimageError::UnsupportedCompression(_) => "Unsupported compression",
+            JimageError::Inflate(_) => "Failed to decompress resource",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            JimageError::Inflate(ref cause) => Some(cause),
+            JimageError::NotAnImage => None,
+            JimageError::UnsupportedVersion(..) => None,
+            JimageError::Truncated => None,
+            JimageError::InvalidString(_) => None,
+            JimageError::UnsupportedCompression(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::Vm;
+
+    fn image() -> JimageSource {
+        JimageSource::open(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/jimage/modules")).unwrap()
+    }
+
+    fn bootstrap_class(name: &str) -> Vec<u8> {
+        fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("bootstrap").join(format!("{}.class", name))).unwrap()
+    }
+
+    #[test]
+    fn test_hash_matches_jimage() {
+        // As computed by jdk.internal.jimage.ImageStringsReader.hashCode.
+        assert_eq!(0x7b31_f51f, hash("/java.base/java/lang/Object.class", HASH_MULTIPLIER));
+        assert_eq!(0x7bf2_fa5b, hash("/java.base/java/lang/Object.class", 7));
+    }
+
+    #[test]
+    fn test_reads_stored_and_compressed_classes() {
+        let image = image();
+        assert_eq!(3, image.len());
+        assert_eq!(Some("java.base"), image.module_of("java/lang"));
+        assert_eq!(Some(bootstrap_class("java/lang/Object")), image.find_class("java/lang/Object").unwrap());
+        assert_eq!(Some(bootstrap_class("java/lang/String")), image.find_class("java/lang/String").unwrap());
+        assert_eq!(None, image.find_class("java/lang/Missing").unwrap());
+        assert_eq!(None, image.find_class("Completes").unwrap());
+    }
+
+    #[test]
+    fn test_reads_resources_by_full_name() {
+        let image = image();
+        assert_eq!(Some(b"Hello from a jimage\n".to_vec()), image.read("/jdk.example/jdk/example/message.txt").unwrap());
+        assert_eq!(None, image.read("/java.base/jdk/example/message.txt").unwrap());
+        assert_eq!(None, image.find_resource("jdk/example/message.txt").unwrap()); // The package has no classes.
+    }
+
+    #[test]
+    fn test_invalid_image() {
+        match JimageSource::from_bytes(PathBuf::from("bad"), b"not an image".to_vec()) {
+            Err(ClasspathError::Image{cause: JimageError::NotAnImage, ..}) => (),
+            other => panic!("Expected an invalid image; got {:?}", other.map(|image| image.path)),
+        }
+        let mut truncated = fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/jimage/modules")).unwrap();
+        truncated.truncate(HEADER_SIZE + 8);
+        match JimageSource::from_bytes(PathBuf::from("truncated"), truncated) {
+            Err(ClasspathError::Image{cause: JimageError::Truncated, ..}) => (),
+            other => panic!("Expected a truncated image; got {:?}", other.map(|image| image.path)),
+        }
+    }
+
+    #[test]
+    fn test_bootstrap_loader_reads_image() {
+        let mut vm = Vm::new();
+        let bootstrap = vm.class_loader_mut(crate::loaders::LoaderId::BOOTSTRAP).unwrap();
+        let mut classpath = classpath::Classpath::new();
+        classpath.push(image());
+        bootstrap.set_classpath(classpath);
+        assert_eq!("java/lang/String", vm.load_class("java/lang/String").unwrap().name);
+    }
+
+    fn assert_every_class_loads(image: &JimageSource) {
+        let names = image.names().unwrap();
+        let classes: Vec<_> = names.iter().filter(|name| name.ends_with(".class")).collect();
+        assert!(!classes.is_empty());
+        for name in classes {
+            let bytes = image.read(name).unwrap().unwrap();
+            if let Err(err) = crate::classloader::load_class(&bytes) {
+                panic!("Couldn't load {}: {}", name, err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_every_class_loads() {
+        assert_every_class_loads(&image());
+    }
+
+    #[test]
+    #[ignore] // Needs a JDK 9 or later; run with JAVA_HOME set.
+    fn test_every_class_in_installed_jdk_loads() {
+        assert_every_class_loads(&JimageSource::from_java_home(std::env::var("JAVA_HOME").unwrap()).unwrap());
+    }
+
+    #[test]
+    #[ignore] // Needs a JDK 9 or later; run with JAVA_HOME set.
+    fn test_reads_installed_jdk() {
+        let image = JimageSource::from_java_home(std::env::var("JAVA_HOME").unwrap()).unwrap();
+        assert_eq!(Some("java.base"), image.module_of("java/lang"));
+        assert_eq!(Some("java.sql"), image.module_of("java/sql"));
+        let object = image.find_class("java/lang/Object").unwrap().unwrap();
+        assert_eq!("java/lang/Object", crate::classloader::load_class(&object).unwrap().name().unwrap());
+    }
+}
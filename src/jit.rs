@@ -0,0 +1,890 @@
+use crate::classes::*;
+use crate::descriptor::*;
+use crate::opcodes::*;
+use crate::runtime::*;
+use cranelift_codegen::ir::{self, condcodes::IntCC, types, AbiParam, InstBuilder, MemFlags};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Switch, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Module};
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+
+// The number of invocations and loop iterations after which a method is compiled.
+pub const DEFAULT_THRESHOLD: u32 = 1000;
+
+// Compiles hot methods to native code with Cranelift. Only static methods that work purely on
+// ints and longs are compiled: their locals, arithmetic, comparisons and branches. Anything else,
+// such as a field access, a call or an allocation, leaves the method in the interpreter.
+//
+// Compiled code can be entered at the start of a method, or at any branch target where the
+// operand stack is empty, so a long-running loop is compiled while it runs rather than on the
+// next call. Instructions that might throw, which are only the divisions, check for the error
+// case and deoptimize: they hand the method's state back to the interpreter, which runs the
+// instruction itself and carries on from there.
+pub struct Jit {
+    module: JITModule,
+    threshold: u32,
+}
+
+// The state of a method as far as the JIT is concerned, kept on its RuntimeClass.
+#[derive(Clone, Default)]
+pub enum Tier {
+    #[default]
+    Interpreted, // Until the method is hot, according to its counters.
+    Compiled(Rc<CompiledMethod>),
+    Uncompilable, // The method uses something the JIT doesn't support.
+}
+
+// How a call to compiled code finished.
+#[derive(Debug, PartialEq)]
+pub enum Exit {
+    Returned(Option<Value>),
+    Deoptimized(usize), // The interpreter must carry on from this pc.
+    NotEntered, // There's no compiled code for this pc, or the locals don't have the right types.
+}
+
+impl Jit {
+    // Fails if Cranelift doesn't support the host machine.
+    pub fn new(threshold: u32) -> Option<Jit> {
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed").ok()?;
+        let isa = cranelift_native::builder().ok()?.finish(settings::Flags::new(flags)).ok()?;
+        Some(Jit {
+            module: JITModule::new(JITBuilder::with_isa(isa, default_libcall_names())),
+            threshold,
+        })
+    }
+
+    pub fn set_threshold(&mut self, threshold: u32) {
+        self.threshold = threshold;
+    }
+
+    // Compiles the method if its invocations and backward branches have reached the threshold.
+    // Returns its compiled code, if it has any.
+    pub fn hot_method(&mut self, class: &RuntimeClass, method_index: usize) -> Option<Rc<CompiledMethod>> {
+        match *class.jit_tier(method_index) {
+            Tier::Compiled(ref compiled) => return Some(Rc::clone(compiled)),
+            Tier::Uncompilable => return None,
+            Tier::Interpreted if class.counters(method_index).total() < self.threshold as u64 => return None,
+            Tier::Interpreted => (),
+        }
+
+        let tier = match self.compile(class, method_index) {
+            Some(compiled) => Tier::Compiled(Rc::new(compiled)),
+            None => Tier::Uncompilable,
+        };
+        *class.jit_tier(method_index) = tier.clone();
+        match tier {
+            Tier::Compiled(compiled) => Some(compiled),
+            _ => None,
+        }
+    }
+
+    fn compile(&mut self, class: &RuntimeClass, method_index: usize) -> Option<CompiledMethod> {
+        let method = &class.class.methods[method_index];
+        let code = method.code()?;
+        if !method.flags.contains(MethodFlags::STATIC) {
+            return None;
+        }
+
+        let descriptor = MethodDescriptor::parse(class.class.utf8(&method.descriptor).ok()?).ok()?;
+        let return_kind = match descriptor.return_type {
+            Some(ref return_type) => Some(Kind::of(return_type)?),
+            None => None,
+        };
+        let mut locals = vec![None; code.max_locals as usize];
+        let mut slot = 0;
+        for parameter in &descriptor.parameters {
+            let kind = Kind::of(parameter)?;
+            *locals.get_mut(slot)? = Some(kind);
+            slot += kind.size();
+        }
+
+        let method = analyse(class, code.code, State {locals, stack: vec![]}, return_kind)?;
+        self.translate(&method, code.max_locals as usize, return_kind)
+    }
+
+    fn translate(&mut self, method: &Analysis, max_locals: usize, return_kind: Option<Kind>) -> Option<CompiledMethod> {
+        let mut context = self.module.make_context();
+        let pointer_type = self.module.target_config().pointer_type();
+        context.func.signature.params.push(AbiParam::new(pointer_type));
+        context.func.signature.params.push(AbiParam::new(types::I32));
+        context.func.signature.returns.push(AbiParam::new(types::I32));
+
+        let mut builder_context = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+        let entry_block = builder.create_block();
+        builder.append_block_params_for_function_params(entry_block);
+        builder.switch_to_block(entry_block);
+        let buffer = builder.block_params(entry_block)[0];
+        let entry_pc = builder.block_params(entry_block)[1];
+        for slot in 0..max_locals {
+            for kind in [Kind::Int, Kind::Long] {
+                builder.declare_var(variable(slot, kind), kind.ir_type());
+            }
+        }
+
+        // Every instruction that starts a basic block gets a Cranelift block, taking the operand
+        // stack as block parameters.
+        let mut blocks = BTreeMap::new();
+        for &pc in &method.leaders {
+            let block = builder.create_block();
+            for kind in &method.states[&pc].stack {
+                builder.append_block_param(block, kind.ir_type());
+            }
+            blocks.insert(pc, block);
+        }
+
+        let entries: BTreeMap<usize, Vec<Option<Kind>>> = method.leaders.iter()
+            .map(|pc| (*pc, &method.states[pc]))
+            .filter(|(_, state)| state.stack.is_empty())
+            .map(|(pc, state)| (pc, state.locals.clone()))
+            .collect();
+        let mut switch = Switch::new();
+        let mut entry_blocks = vec![];
+        for &pc in entries.keys() {
+            let block = builder.create_block();
+            switch.set_entry(pc as u128, block);
+            entry_blocks.push((pc, block));
+        }
+        let not_entered = builder.create_block();
+        switch.emit(&mut builder, entry_pc, not_entered);
+
+        let mut translator = Translator {builder, buffer, max_locals, stack: vec![], deopts: BTreeMap::new()};
+        for (pc, block) in entry_blocks {
+            translator.builder.switch_to_block(block);
+            for (slot, kind) in entries[&pc].iter().enumerate() {
+                if let Some(kind) = *kind {
+                    let value = translator.read_slot(slot, kind);
+                    translator.builder.def_var(variable(slot, kind), value);
+                }
+            }
+            translator.builder.ins().jump(blocks[&pc], &[]);
+        }
+        translator.builder.switch_to_block(not_entered);
+        let status = translator.builder.ins().iconst(types::I32, NOT_ENTERED as i64);
+        translator.builder.ins().return_(&[status]);
+
+        let mut open = false;
+        for (&pc, state) in &method.states {
+            if let Some(&block) = blocks.get(&pc) {
+                if open {
+                    translator.builder.ins().jump(block, &translator.stack);
+                }
+                translator.builder.switch_to_block(block);
+                translator.stack = translator.builder.block_params(block).to_vec();
+            }
+            let (op, length) = method.ops[&pc];
+            open = translator.translate(pc, op, length, state, &blocks);
+        }
+
+        let deopts = std::mem::take(&mut translator.deopts);
+        translator.builder.seal_all_blocks();
+        translator.builder.finalize();
+
+        let id = self.module.declare_anonymous_function(&context.func.signature).ok()?;
+        let defined = self.module.define_function(id, &mut context);
+        self.module.clear_context(&mut context);
+        defined.ok()?;
+        self.module.finalize_definitions().ok()?;
+
+        // The module never frees the code it generates, even when it's dropped, so the function
+        // stays valid for as long as anything refers to it.
+        let code = self.module.get_finalized_function(id);
+        let function = unsafe { std::mem::transmute::<*const u8, CompiledFunction>(code) };
+        let buffer_size = max_locals + method.max_stack.max(1);
+        Some(CompiledMethod {function, entries, deopts, max_locals, buffer_size, return_kind})
+    }
+}
+
+// Compiled code takes a buffer holding the locals, followed by space for the operand stack, and
+// the pc to start at. It returns RETURNED after writing the result to the start of the buffer,
+// NOT_ENTERED if it can't start at that pc, or otherwise the pc to deoptimize at, after writing
+// out the locals and operand stack. Every value takes up one slot, with ints sign-extended.
+type CompiledFunction = unsafe extern "C" fn(*mut i64, i32) -> i32;
+
+const RETURNED: i32 = -1;
+const NOT_ENTERED: i32 = -2;
+
+pub struct CompiledMethod {
+    function: CompiledFunction,
+    entries: BTreeMap<usize, Vec<Option<Kind>>>, // The types of the locals at each entry point.
+    deopts: BTreeMap<usize, State>,
+    max_locals: usize,
+    buffer_size: usize,
+    return_kind: Option<Kind>,
+}
+
+impl CompiledMethod {
+    // Runs the method from the given pc. If it deoptimizes, the locals and operand stack are
+    // updated so that the interpreter can carry on from the pc it returns.
+    pub fn run(&self, pc: usize, locals: &mut [Value], stack: &mut Vec<Value>) -> Exit {
+        let entry = match self.entries.get(&pc) {
+            Some(entry) if stack.is_empty() => entry,
+            _ => return Exit::NotEntered,
+        };
+        let mut buffer = vec![0i64; self.buffer_size];
+        for (slot, kind) in entry.iter().enumerate() {
+            buffer[slot] = match (*kind, &locals[slot]) {
+                (None, _) => continue,
+                (Some(Kind::Int), &Value::Int(value)) => value as i64,
+                (Some(Kind::Long), &Value::Long(value)) => value,
+                _ => return Exit::NotEntered,
+            };
+        }
+
+        let status = unsafe { (self.function)(buffer.as_mut_ptr(), pc as i32) };
+        match status {
+            RETURNED => Exit::Returned(self.return_kind.map(|kind| kind.value(buffer[0]))),
+            NOT_ENTERED => Exit::NotEntered,
+            pc => {
+                let state = &self.deopts[&(pc as usize)];
+                for (slot, kind) in state.locals.iter().enumerate() {
+                    if let Some(kind) = *kind {
+                        locals[slot] = kind.value(buffer[slot]);
+                    }
+                }
+                stack.clear();
+                for (depth, kind) in state.stack.iter().enumerate() {
+                    stack.push(kind.value(buffer[self.max_locals + depth]));
+                }
+                Exit::Deoptimized(pc as usize)
+            },
+        }
+    }
+}
+
+// The types of value compiled code can work with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Int,
+    Long,
+}
+
+impl Kind {
+    // Booleans, bytes, chars and shorts are ints as far as the bytecode is concerned.
+    fn of(field_type: &FieldType) -> Option<Kind> {
+        match *field_type {
+            FieldType::Boolean | FieldType::Byte | FieldType::Char | FieldType::Short | FieldType::Int => Some(Kind::Int),
+            FieldType::Long => Some(Kind::Long),
+            _ => None,
+        }
+    }
+
+    // The number of local variable slots a value takes up.
+    fn size(self) -> usize {
+        match self {
+            Kind::Int => 1,
+            Kind::Long => 2,
+        }
+    }
+
+    fn ir_type(self) -> ir::Type {
+        match self {
+            Kind::Int => types::I32,
+            Kind::Long => types::I64,
+        }
+    }
+
+    fn value(self, slot: i64) -> Value {
+        match self {
+            Kind::Int => Value::Int(slot as i32),
+            Kind::Long => Value::Long(slot),
+        }
+    }
+}
+
+// The instructions the JIT supports, with their operands decoded. Branch targets are absolute.
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    Nop,
+    IntConstant(i32),
+    LongConstant(i64),
+    Load(Kind, usize),
+    Store(Kind, usize),
+    Increment(usize, i32),
+    Pop,
+    Pop2,
+    Dup,
+    Arithmetic(u8), // Any int or long binary operation other than division, by opcode.
+    Divide(u8), // idiv, irem, ldiv and lrem, which throw if the divisor is zero.
+    Negate(Kind),
+    Convert(u8),
+    CompareLongs,
+    If(IntCC, usize), // Compares an int with zero.
+    IfCompare(IntCC, usize),
+    Goto(usize),
+    Return(Option<Kind>),
+}
+
+impl Op {
+    // The pcs execution can continue at after this instruction.
+    fn successors(self, pc: usize, length: usize) -> Vec<usize> {
+        match self {
+            Op::If(_, target) | Op::IfCompare(_, target) => vec![target, pc + length],
+            Op::Goto(target) => vec![target],
+            Op::Return(_) => vec![],
+            _ => vec![pc + length],
+        }
+    }
+
+    fn ends_block(self) -> bool {
+        matches!(self, Op::If(..) | Op::IfCompare(..) | Op::Goto(_) | Op::Return(_))
+    }
+}
+
+// Decodes the instruction at pc, or returns None if the JIT doesn't support it.
+fn decode(class: &RuntimeClass, code: &[u8], pc: usize) -> Option<(Op, usize)> {
+    let u8_at = |offset: usize| code.get(pc + offset).copied();
+    let u16_at = |offset: usize| Some(u16::from_be_bytes([u8_at(offset)?, u8_at(offset + 1)?]));
+    let i32_at = |offset: usize| Some(i32::from_be_bytes([u8_at(offset)?, u8_at(offset + 1)?, u8_at(offset + 2)?, u8_at(offset + 3)?]));
+    let target = |offset: i32| {
+        let target = pc as i64 + offset as i64;
+        if target >= 0 && target < code.len() as i64 { Some(target as usize) } else { None }
+    };
+
+    let opcode = u8_at(0)?;
+    Some(match opcode {
+        NOP => (Op::Nop, 1),
+        ICONST_M1..=ICONST_5 => (Op::IntConstant(opcode as i32 - ICONST_0 as i32), 1),
+        LCONST_0 | LCONST_1 => (Op::LongConstant((opcode - LCONST_0) as i64), 1),
+        BIPUSH => (Op::IntConstant(u8_at(1)? as i8 as i32), 2),
+        SIPUSH => (Op::IntConstant(u16_at(1)? as i16 as i32), 3),
+        LDC => (constant(class, u8_at(1)? as u16)?, 2),
+        LDC_W | LDC2_W => (constant(class, u16_at(1)?)?, 3),
+        ILOAD | LLOAD | ISTORE | LSTORE => (local(opcode, u8_at(1)? as usize), 2),
+        ILOAD_0..=ILOAD_3 => (Op::Load(Kind::Int, (opcode - ILOAD_0) as usize), 1),
+        LLOAD_0..=LLOAD_3 => (Op::Load(Kind::Long, (opcode - LLOAD_0) as usize), 1),
+        ISTORE_0..=ISTORE_3 => (Op::Store(Kind::Int, (opcode - ISTORE_0) as usize), 1),
+        LSTORE_0..=LSTORE_3 => (Op::Store(Kind::Long, (opcode - LSTORE_0) as usize), 1),
+        IINC => (Op::Increment(u8_at(1)? as usize, u8_at(2)? as i8 as i32), 3),
+        WIDE => match u8_at(1)? {
+            IINC => (Op::Increment(u16_at(2)? as usize, u16_at(4)? as i16 as i32), 6),
+            modified @ (ILOAD | LLOAD | ISTORE | LSTORE) => (local(modified, u16_at(2)? as usize), 4),
+            _ => return None,
+        },
+        POP => (Op::Pop, 1),
+        POP2 => (Op::Pop2, 1),
+        DUP => (Op::Dup, 1),
+        IADD | LADD | ISUB | LSUB | IMUL | LMUL | ISHL | LSHL | ISHR | LSHR | IUSHR | LUSHR |
+        IAND | LAND | IOR | LOR | IXOR | LXOR => (Op::Arithmetic(opcode), 1),
+        IDIV | LDIV | IREM | LREM => (Op::Divide(opcode), 1),
+        INEG => (Op::Negate(Kind::Int), 1),
+        LNEG => (Op::Negate(Kind::Long), 1),
+        I2L | L2I | I2B | I2C | I2S => (Op::Convert(opcode), 1),
+        LCMP => (Op::CompareLongs, 1),
+        IFEQ..=IFLE => (Op::If(condition(opcode - IFEQ), target(u16_at(1)? as i16 as i32)?), 3),
+        IF_ICMPEQ..=IF_ICMPLE => (Op::IfCompare(condition(opcode - IF_ICMPEQ), target(u16_at(1)? as i16 as i32)?), 3),
+        GOTO => (Op::Goto(target(u16_at(1)? as i16 as i32)?), 3),
+        GOTO_W => (Op::Goto(target(i32_at(1)?)?), 5),
+        IRETURN => (Op::Return(Some(Kind::Int)), 1),
+        LRETURN => (Op::Return(Some(Kind::Long)), 1),
+        RETURN => (Op::Return(None), 1),
+        _ => return None,
+    })
+}
+
+fn constant(class: &RuntimeClass, index: u16) -> Option<Op> {
+    match *ConstantIndex(index).lookup(&class.class.constants).ok()? {
+        Constant::Integer(value) => Some(Op::IntConstant(value as i32)),
+        Constant::Long(value) => Some(Op::LongConstant(value as i64)),
+        _ => None,
+    }
+}
+
+fn local(opcode: u8, index: usize) -> Op {
+    match opcode {
+        ILOAD => Op::Load(Kind::Int, index),
+        LLOAD => Op::Load(Kind::Long, index),
+        ISTORE => Op::Store(Kind::Int, index),
+        _ => Op::Store(Kind::Long, index),
+    }
+}
+
+// The conditions of the if instructions, in opcode order: eq, ne, lt, ge, gt, le.
+fn condition(offset: u8) -> IntCC {
+    [IntCC::Equal, IntCC::NotEqual, IntCC::SignedLessThan, IntCC::SignedGreaterThanOrEqual,
+        IntCC::SignedGreaterThan, IntCC::SignedLessThanOrEqual][offset as usize]
+}
+
+fn is_long_opcode(opcode: u8) -> bool {
+    matches!(opcode, LADD | LSUB | LMUL | LDIV | LREM | LSHL | LSHR | LUSHR | LAND | LOR | LXOR)
+}
+
+// The types of the locals and operand stack before an instruction runs. A local is None if it
+// hasn't been assigned, or holds different types on different paths, so can't be read.
+#[derive(Clone, Debug, PartialEq)]
+struct State {
+    locals: Vec<Option<Kind>>,
+    stack: Vec<Kind>,
+}
+
+impl State {
+    fn push(&mut self, kind: Kind) {
+        self.stack.push(kind);
+    }
+
+    fn pop(&mut self, kind: Kind) -> Option<()> {
+        if self.stack.pop()? == kind { Some(()) } else { None }
+    }
+
+    fn store(&mut self, kind: Kind, index: usize) -> Option<()> {
+        *self.locals.get_mut(index)? = Some(kind);
+        if kind == Kind::Long {
+            *self.locals.get_mut(index + 1)? = None;
+        }
+        // Overwriting the second half of a long invalidates the long.
+        if index > 0 && self.locals[index - 1] == Some(Kind::Long) {
+            self.locals[index - 1] = None;
+        }
+        Some(())
+    }
+
+    // Merges the state from another path into this one, returning whether it changed, or None
+    // if the operand stacks don't match.
+    fn merge(&mut self, other: &State) -> Option<bool> {
+        if self.stack != other.stack {
+            return None;
+        }
+        let mut changed = false;
+        for (local, other) in self.locals.iter_mut().zip(&other.locals) {
+            if local.is_some() && local != other {
+                *local = None;
+                changed = true;
+            }
+        }
+        Some(changed)
+    }
+
+    // Applies the instruction's effect on the types, failing if they're wrong for it.
+    fn execute(&mut self, op: Op, return_kind: Option<Kind>) -> Option<()> {
+        match op {
+            Op::Nop | Op::Goto(_) => (),
+            Op::IntConstant(_) => self.push(Kind::Int),
+            Op::LongConstant(_) => self.push(Kind::Long),
+            Op::Load(kind, index) => {
+                if *self.locals.get(index)? != Some(kind) {
+                    return None;
+                }
+                self.push(kind);
+            },
+            Op::Store(kind, index) => {
+                self.pop(kind)?;
+                self.store(kind, index)?;
+            },
+            Op::Increment(index, _) => {
+                if *self.locals.get(index)? != Some(Kind::Int) {
+                    return None;
+                }
+            },
+            Op::Pop => self.pop(Kind::Int)?,
+            Op::Pop2 => {
+                if self.stack.pop()? == Kind::Int {
+                    self.pop(Kind::Int)?;
+                }
+            },
+            Op::Dup => {
+                if *self.stack.last()? != Kind::Int {
+                    return None;
+                }
+                self.push(Kind::Int);
+            },
+            Op::Arithmetic(opcode) | Op::Divide(opcode) => {
+                let kind = if is_long_opcode(opcode) { Kind::Long } else { Kind::Int };
+                let shift = matches!(opcode, ISHL | LSHL | ISHR | LSHR | IUSHR | LUSHR);
+                self.pop(if shift { Kind::Int } else { kind })?;
+                self.pop(kind)?;
+                self.push(kind);
+            },
+            Op::Negate(kind) => {
+                self.pop(kind)?;
+                self.push(kind);
+            },
+            Op::Convert(opcode) => {
+                self.pop(if opcode == L2I { Kind::Long } else { Kind::Int })?;
+                self.push(if opcode == I2L { Kind::Long } else { Kind::Int });
+            },
+            Op::CompareLongs => {
+                self.pop(Kind::Long)?;
+                self.pop(Kind::Long)?;
+                self.push(Kind::Int);
+            },
+            Op::If(..) => self.pop(Kind::Int)?,
+            Op::IfCompare(..) => {
+                self.pop(Kind::Int)?;
+                self.pop(Kind::Int)?;
+            },
+            Op::Return(kind) => {
+                if kind != return_kind {
+                    return None;
+                }
+                if let Some(kind) = kind {
+                    self.pop(kind)?;
+                }
+            },
+        }
+        Some(())
+    }
+}
+
+struct Analysis {
+    ops: BTreeMap<usize, (Op, usize)>, // Every instruction in the method, with its length.
+    states: BTreeMap<usize, State>, // The state before each reachable instruction.
+    leaders: BTreeSet<usize>, // The reachable instructions that start basic blocks.
+    max_stack: usize,
+}
+
+// Works out the types of the locals and operand stack at each instruction, in the same way as
+// the type-checking verifier. Fails if the method uses an unsupported instruction anywhere.
+fn analyse(class: &RuntimeClass, code: &[u8], initial: State, return_kind: Option<Kind>) -> Option<Analysis> {
+    let mut ops = BTreeMap::new();
+    let mut pc = 0;
+    while pc < code.len() {
+        let (op, length) = decode(class, code, pc)?;
+        ops.insert(pc, (op, length));
+        pc += length;
+    }
+
+    let mut states = BTreeMap::new();
+    states.insert(0, initial);
+    let mut worklist = vec![0];
+    while let Some(pc) = worklist.pop() {
+        let (op, length) = ops[&pc];
+        let mut state = states[&pc].clone();
+        state.execute(op, return_kind)?;
+        for successor in op.successors(pc, length) {
+            if !ops.contains_key(&successor) {
+                return None; // Execution falls off the end, or branches into an instruction.
+            }
+            match states.get_mut(&successor) {
+                Some(existing) => {
+                    if existing.merge(&state)? {
+                        worklist.push(successor);
+                    }
+                },
+                None => {
+                    states.insert(successor, state.clone());
+                    worklist.push(successor);
+                },
+            }
+        }
+    }
+
+    let mut leaders = BTreeSet::new();
+    leaders.insert(0);
+    for (&pc, &(op, length)) in &ops {
+        if op.ends_block() {
+            leaders.extend(op.successors(pc, length));
+            leaders.insert(pc + length);
+        }
+    }
+    leaders.retain(|pc| states.contains_key(pc));
+    let max_stack = states.values().map(|state| state.stack.len()).max().unwrap_or(0);
+    Some(Analysis {ops, states, leaders, max_stack})
+}
+
+// Each local has a separate variable for each type it's used with.
+fn variable(slot: usize, kind: Kind) -> Variable {
+    Variable::from_u32((slot * 2 + kind as usize) as u32)
+}
+
+struct Translator<'a> {
+    builder: FunctionBuilder<'a>,
+    buffer: ir::Value,
+    max_locals: usize,
+    stack: Vec<ir::Value>,
+    deopts: BTreeMap<usize, State>,
+}
+
+impl Translator<'_> {
+    // Emits the code for one instruction, returning whether execution can fall through to the
+    // next one.
+    fn translate(&mut self, pc: usize, op: Op, length: usize, state: &State, blocks: &BTreeMap<usize, ir::Block>) -> bool {
+        match op {
+            Op::Nop => (),
+            Op::IntConstant(value) => self.push_constant(Kind::Int, value as i64),
+            Op::LongConstant(value) => self.push_constant(Kind::Long, value),
+            Op::Load(kind, index) => {
+                let value = self.builder.use_var(variable(index, kind));
+                self.stack.push(value);
+            },
+            Op::Store(kind, index) => {
+                let value = self.pop();
+                self.builder.def_var(variable(index, kind), value);
+            },
+            Op::Increment(index, delta) => {
+                let value = self.builder.use_var(variable(index, Kind::Int));
+                let value = self.builder.ins().iadd_imm(value, delta as i64);
+                self.builder.def_var(variable(index, Kind::Int), value);
+            },
+            Op::Pop => {
+                self.pop();
+            },
+            Op::Pop2 => {
+                if *state.stack.last().unwrap() == Kind::Int {
+                    self.pop();
+                }
+                self.pop();
+            },
+            Op::Dup => {
+                let value = *self.stack.last().unwrap();
+                self.stack.push(value);
+            },
+            Op::Arithmetic(opcode) => {
+                let right = self.pop();
+                let left = self.pop();
+                let ins = self.builder.ins();
+                let result = match opcode {
+                    IADD | LADD => ins.iadd(left, right),
+                    ISUB | LSUB => ins.isub(left, right),
+                    IMUL | LMUL => ins.imul(left, right),
+                    IAND | LAND => ins.band(left, right),
+                    IOR | LOR => ins.bor(left, right),
+                    IXOR | LXOR => ins.bxor(left, right),
+                    // Cranelift masks shift distances to the width of the value, as Java does.
+                    ISHL | LSHL => ins.ishl(left, right),
+                    ISHR | LSHR => ins.sshr(left, right),
+                    _ => ins.ushr(left, right),
+                };
+                self.stack.push(result);
+            },
+            Op::Divide(opcode) => self.divide(pc, opcode, state),
+            Op::Negate(_) => {
+                let value = self.pop();
+                let result = self.builder.ins().ineg(value);
+                self.stack.push(result);
+            },
+            Op::Convert(opcode) => {
+                let value = self.pop();
+                let ins = self.builder.ins();
+                let result = match opcode {
+                    I2L => ins.sextend(types::I64, value),
+                    L2I => ins.ireduce(types::I32, value),
+                    I2B => {
+                        let byte = ins.ireduce(types::I8, value);
+                        self.builder.ins().sextend(types::I32, byte)
+                    },
+                    I2C => {
+                        let char = ins.ireduce(types::I16, value);
+                        self.builder.ins().uextend(types::I32, char)
+                    },
+                    _ => {
+                        let short = ins.ireduce(types::I16, value);
+                        self.builder.ins().sextend(types::I32, short)
+                    },
+                };
+                self.stack.push(result);
+            },
+            Op::CompareLongs => {
+                let right = self.pop();
+                let left = self.pop();
+                let greater = self.builder.ins().icmp(IntCC::SignedGreaterThan, left, right);
+                let less = self.builder.ins().icmp(IntCC::SignedLessThan, left, right);
+                let greater = self.builder.ins().uextend(types::I32, greater);
+                let less = self.builder.ins().uextend(types::I32, less);
+                let result = self.builder.ins().isub(greater, less);
+                self.stack.push(result);
+            },
+            Op::If(condition, target) => {
+                let value = self.pop();
+                let taken = self.builder.ins().icmp_imm(condition, value, 0);
+                self.builder.ins().brif(taken, blocks[&target], &self.stack, blocks[&(pc + length)], &self.stack);
+                return false;
+            },
+            Op::IfCompare(condition, target) => {
+                let right = self.pop();
+                let left = self.pop();
+                let taken = self.builder.ins().icmp(condition, left, right);
+                self.builder.ins().brif(taken, blocks[&target], &self.stack, blocks[&(pc + length)], &self.stack);
+                return false;
+            },
+            Op::Goto(target) => {
+                self.builder.ins().jump(blocks[&target], &self.stack);
+                return false;
+            },
+            Op::Return(kind) => {
+                if kind.is_some() {
+                    let value = self.pop();
+                    self.write_slot(0, value);
+                }
+                self.return_status(RETURNED);
+                return false;
+            },
+        }
+        true
+    }
+
+    // Division by zero deoptimizes so that the interpreter can throw the ArithmeticException.
+    // Dividing the minimum value by -1 overflows, which traps in native code, so that case is
+    // computed separately: Java defines the quotient as the dividend and the remainder as zero.
+    fn divide(&mut self, pc: usize, opcode: u8, state: &State) {
+        let stack = self.stack.clone();
+        let divisor = self.pop();
+        let dividend = self.pop();
+
+        let deopt_block = self.builder.create_block();
+        let continue_block = self.builder.create_block();
+        let is_zero = self.builder.ins().icmp_imm(IntCC::Equal, divisor, 0);
+        self.builder.ins().brif(is_zero, deopt_block, &[], continue_block, &[]);
+        self.builder.switch_to_block(deopt_block);
+        self.deoptimize(pc, state, &stack);
+
+        self.builder.switch_to_block(continue_block);
+        let kind = if is_long_opcode(opcode) { Kind::Long } else { Kind::Int };
+        let is_minus_one = self.builder.ins().icmp_imm(IntCC::Equal, divisor, -1);
+        let one = self.builder.ins().iconst(kind.ir_type(), 1);
+        let safe_divisor = self.builder.ins().select(is_minus_one, one, divisor);
+        let result = if matches!(opcode, IDIV | LDIV) {
+            let quotient = self.builder.ins().sdiv(dividend, safe_divisor);
+            let negated = self.builder.ins().ineg(dividend);
+            self.builder.ins().select(is_minus_one, negated, quotient)
+        } else {
+            let remainder = self.builder.ins().srem(dividend, safe_divisor);
+            let zero = self.builder.ins().iconst(kind.ir_type(), 0);
+            self.builder.ins().select(is_minus_one, zero, remainder)
+        };
+        self.stack.push(result);
+    }
+
+    // Writes out the locals and operand stack as they are before the instruction at pc, and
+    // returns to the interpreter.
+    fn deoptimize(&mut self, pc: usize, state: &State, stack: &[ir::Value]) {
+        for (slot, kind) in state.locals.iter().enumerate() {
+            if let Some(kind) = *kind {
+                let value = self.builder.use_var(variable(slot, kind));
+                self.write_slot(slot, value);
+            }
+        }
+        for (depth, &value) in stack.iter().enumerate() {
+            self.write_slot(self.max_locals + depth, value);
+        }
+        self.deopts.insert(pc, state.clone());
+        self.return_status(pc as i32);
+    }
+
+    fn push_constant(&mut self, kind: Kind, value: i64) {
+        let value = self.builder.ins().iconst(kind.ir_type(), value);
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> ir::Value {
+        self.stack.pop().expect("the analysis checked the stack depth")
+    }
+
+    fn read_slot(&mut self, slot: usize, kind: Kind) -> ir::Value {
+        let value = self.builder.ins().load(types::I64, MemFlags::trusted(), self.buffer, (slot * 8) as i32);
+        match kind {
+            Kind::Int => self.builder.ins().ireduce(types::I32, value),
+            Kind::Long => value,
+        }
+    }
+
+    fn write_slot(&mut self, slot: usize, value: ir::Value) {
+        let value = match self.builder.func.dfg.value_type(value) {
+            types::I32 => self.builder.ins().sextend(types::I64, value),
+            _ => value,
+        };
+        self.builder.ins().store(MemFlags::trusted(), value, self.buffer, (slot * 8) as i32);
+    }
+
+    fn return_status(&mut self, status: i32) {
+        let status = self.builder.ins().iconst(types::I32, status as i64);
+        self.builder.ins().return_(&[status]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use crate::limits::Limits;
+    use crate::vm::{Vm, VmError};
+
+    fn call(vm: &mut Vm, class: &str, name: &str, descriptor: &str, args: Vec<Value>) -> Result<Option<Value>, VmError> {
+        vm.invoke_static(class, name, descriptor, args)
+    }
+
+    fn tier(vm: &mut Vm, class: &str, name: &str, descriptor: &str) -> Tier {
+        let class = vm.load_class(class).unwrap();
+        let method_index = class.find_declared_method(name, descriptor).unwrap().unwrap();
+        let tier = class.jit_tier(method_index).clone();
+        tier
+    }
+
+    #[test]
+    fn test_compiles_hot_int_loop() {
+        let mut vm = vm_with(&[fixture!("Benchmarks")]);
+        vm.set_jit_threshold(Some(1));
+        assert_eq!(Some(Value::Int(285)), call(&mut vm, "Benchmarks", "sumOfSquares", "(I)I", vec![Value::Int(10)]).unwrap());
+        assert!(matches!(tier(&mut vm, "Benchmarks", "sumOfSquares", "(I)I"), Tier::Compiled(_)));
+        assert_eq!(Some(Value::Int(0)), call(&mut vm, "Benchmarks", "sumOfSquares", "(I)I", vec![Value::Int(0)]).unwrap());
+    }
+
+    #[test]
+    fn test_compiled_long_arithmetic_matches_interpreter() {
+        let mut interpreted = vm_with(&[fixture!("Benchmarks")]);
+        interpreted.set_jit_threshold(None);
+        let mut compiled = vm_with(&[fixture!("Benchmarks")]);
+        compiled.set_jit_threshold(Some(1));
+        let args = vec![Value::Int(50)];
+        let expected = call(&mut interpreted, "Benchmarks", "collatzSteps", "(I)J", args.clone()).unwrap();
+        assert_eq!(expected, call(&mut compiled, "Benchmarks", "collatzSteps", "(I)J", args).unwrap());
+        assert!(matches!(tier(&mut compiled, "Benchmarks", "collatzSteps", "(I)J"), Tier::Compiled(_)));
+    }
+
+    #[test]
+    fn test_division_by_zero_deoptimizes() {
+        let mut vm = vm_with(&[fixture!("Arithmetic")]);
+        vm.set_jit_threshold(Some(1));
+        let mut divide = |a: i32, b: i32| call(&mut vm, "Arithmetic", "divide", "(II)I", vec![Value::Int(a), Value::Int(b)]);
+        assert_eq!(Some(Value::Int(3)), divide(7, 2).unwrap());
+        match divide(1, 0) {
+            Err(VmError::UncaughtException(exception)) => assert_eq!("java/lang/ArithmeticException", exception.class().name),
+            other => panic!("Expected an ArithmeticException; got {:?}", other.map(|_| ())),
+        }
+        assert!(matches!(tier(&mut vm, "Arithmetic", "divide", "(II)I"), Tier::Compiled(_)));
+    }
+
+    #[test]
+    fn test_division_overflow() {
+        let mut vm = vm_with(&[fixture!("Arithmetic")]);
+        vm.set_jit_threshold(Some(1));
+        let int_args = vec![Value::Int(i32::MIN), Value::Int(-1)];
+        let long_args = vec![Value::Long(i64::MIN), Value::Long(-1)];
+        assert_eq!(Some(Value::Int(i32::MIN)), call(&mut vm, "Arithmetic", "divide", "(II)I", int_args.clone()).unwrap());
+        assert_eq!(Some(Value::Int(0)), call(&mut vm, "Arithmetic", "remainder", "(II)I", int_args).unwrap());
+        assert_eq!(Some(Value::Long(i64::MIN)), call(&mut vm, "Arithmetic", "divideLong", "(JJ)J", long_args.clone()).unwrap());
+        assert_eq!(Some(Value::Long(0)), call(&mut vm, "Arithmetic", "remainderLong", "(JJ)J", long_args).unwrap());
+        assert!(matches!(tier(&mut vm, "Arithmetic", "remainderLong", "(JJ)J"), Tier::Compiled(_)));
+    }
+
+    #[test]
+    fn test_unsupported_methods_stay_interpreted() {
+        let mut vm = vm_with(&[fixture!("Benchmarks")]);
+        vm.set_jit_threshold(Some(1));
+        assert_eq!(Some(Value::Int(25)), call(&mut vm, "Benchmarks", "sieve", "(I)I", vec![Value::Int(100)]).unwrap());
+        assert_eq!(Some(Value::Int(55)), call(&mut vm, "Benchmarks", "fib", "(I)I", vec![Value::Int(10)]).unwrap());
+        assert!(matches!(tier(&mut vm, "Benchmarks", "sieve", "(I)I"), Tier::Uncompilable));
+        assert!(matches!(tier(&mut vm, "Benchmarks", "fib", "(I)I"), Tier::Uncompilable));
+    }
+
+    #[test]
+    fn test_loops_are_compiled_while_running() {
+        let mut vm = vm_with(&[fixture!("Benchmarks")]);
+        vm.set_jit_threshold(Some(100));
+        let result = call(&mut vm, "Benchmarks", "sumOfSquares", "(I)I", vec![Value::Int(100_000)]).unwrap();
+        let expected = (0..100_000i32).fold(0i32, |total, i| total.wrapping_add(i.wrapping_mul(i)));
+        assert_eq!(Some(Value::Int(expected)), result);
+        assert!(vm.executed_instructions() < 10_000);
+    }
+
+    #[test]
+    fn test_instruction_limits_disable_compilation() {
+        let mut vm = vm_with(&[fixture!("Benchmarks")]);
+        vm.set_jit_threshold(Some(1));
+        vm.set_limits(Limits {max_instructions: Some(1_000_000), ..Limits::default()});
+        call(&mut vm, "Benchmarks", "sumOfSquares", "(I)I", vec![Value::Int(100)]).unwrap();
+        assert!(matches!(tier(&mut vm, "Benchmarks", "sumOfSquares", "(I)I"), Tier::Interpreted));
+    }
+}
@@ -0,0 +1,604 @@
+use crate::bytecode::{self, Operand};
+use crate::classes::*;
+use crate::opcodes::*;
+use std::fmt;
+
+// A JSON value, enough to export parsed classes for tools that aren't written in Rust. Objects keep
+// their keys in the order they were given. Formatting with {} gives compact JSON, and {:#} gives
+// it indented.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64), // Infinities and NaN, which JSON can't represent, are written as strings.
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    // The value for the key, if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref entries) => entries.iter().find(|&(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        let pretty = f.alternate();
+        let newline = |f: &mut fmt::Formatter, depth: usize| match pretty {
+            true => write!(f, "\n{}", "  ".repeat(depth)),
+            false => Ok(()),
+        };
+        match *self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Int(value) => write!(f, "{}", value),
+            Json::Float(value) if value.is_finite() => write!(f, "{}", value),
+            Json::Float(value) => write_string(f, &value.to_string()),
+            Json::String(ref value) => write_string(f, value),
+            Json::Array(ref values) if values.is_empty() => write!(f, "[]"),
+            Json::Array(ref values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    newline(f, depth + 1)?;
+                    value.write(f, depth + 1)?;
+                }
+                newline(f, depth)?;
+                write!(f, "]")
+            },
+            Json::Object(ref entries) if entries.is_empty() => write!(f, "{{}}"),
+            Json::Object(ref entries) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in entries.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    newline(f, depth + 1)?;
+                    write_string(f, key)?;
+                    write!(f, "{}", if pretty { ": " } else { ":" })?;
+                    value.write(f, depth + 1)?;
+                }
+                newline(f, depth)?;
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Json {
+        Json::Bool(value)
+    }
+}
+
+impl From<u8> for Json {
+    fn from(value: u8) -> Json {
+        Json::Int(value as i64)
+    }
+}
+
+impl From<u16> for Json {
+    fn from(value: u16) -> Json {
+        Json::Int(value as i64)
+    }
+}
+
+impl From<i32> for Json {
+    fn from(value: i32) -> Json {
+        Json::Int(value as i64)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(value: i64) -> Json {
+        Json::Int(value)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Json {
+        Json::Int(value as i64)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(value: f64) -> Json {
+        Json::Float(value)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Json {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Json {
+        Json::String(value)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Json {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(values: Vec<T>) -> Json {
+        Json::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl From<&ConstantIndex> for Json {
+    fn from(index: &ConstantIndex) -> Json {
+        Json::Int(index.0 as i64)
+    }
+}
+
+fn object(entries: Vec<(&str, Json)>) -> Json {
+    Json::Object(entries.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+}
+
+fn indexes(indexes: &[ConstantIndex]) -> Json {
+    Json::Array(indexes.iter().map(Json::from).collect())
+}
+
+// The schema follows the class file format: references between structures are constant pool
+// indexes, and everything is named as in the JVM spec. For convenience, the class and its members
+// also give their names resolved, which are null if the indexes are invalid, and Code attributes
+// give their instructions decoded.
+//
+//     {"minor_version": 0, "major_version": 52, "constant_pool": [{"index": 1, "tag": "Methodref",
+//      "class": 2, "name_and_type": 3}, ...], "access_flags": {"value": 33, "names": ["ACC_PUBLIC",
+//      "ACC_SUPER"]}, "this_class": 8, "this_class_name": "Foo", ..., "fields": [...],
+//      "methods": [...], "attributes": [{"name": "SourceFile", "source_file": 10}]}
+impl Class {
+    pub fn to_json(&self) -> String {
+        self.json().to_string()
+    }
+
+    pub fn json(&self) -> Json {
+        object(vec![
+            ("minor_version", self.minor_version.into()),
+            ("major_version", self.major_version.into()),
+            ("constant_pool", Json::Array(self.constants.iter().enumerate()
+                .filter(|&(_, constant)| *constant != Constant::Dummy)
+                .map(|(index, constant)| constant_json(index + 1, constant))
+                .collect())),
+            ("access_flags", flags_json(self.flags.bits(), CLASS_FLAG_NAMES)),
+            ("this_class", (&self.this_class).into()),
+            ("this_class_name", self.name().ok().into()),
+            ("super_class", (&self.super_class).into()),
+            ("super_class_name", self.super_class_name().ok().flatten().into()),
+            ("interfaces", indexes(&self.interfaces)),
+            ("fields", Json::Array(self.fields.iter()
+                .map(|field| self.member_json(field.flags.bits(), FIELD_FLAG_NAMES, &field.name, &field.descriptor, &field.attributes))
+                .collect())),
+            ("methods", Json::Array(self.methods.iter()
+                .map(|method| self.member_json(method.flags.bits(), METHOD_FLAG_NAMES, &method.name, &method.descriptor, &method.attributes))
+                .collect())),
+            ("attributes", attributes_json(&self.attributes)),
+        ])
+    }
+
+    fn member_json(&self, flags: u16, names: &[(u16, &str)], name: &ConstantIndex, descriptor: &ConstantIndex, attributes: &[Attribute]) -> Json {
+        object(vec![
+            ("access_flags", flags_json(flags, names)),
+            ("name_index", name.into()),
+            ("name", self.utf8(name).ok().into()),
+            ("descriptor_index", descriptor.into()),
+            ("descriptor", self.utf8(descriptor).ok().into()),
+            ("attributes", attributes_json(attributes)),
+        ])
+    }
+}
+
+fn flags_json(bits: u16, names: &[(u16, &str)]) -> Json {
+    let names: Vec<&str> = names.iter().filter(|&&(flag, _)| bits & flag != 0).map(|&(_, name)| name).collect();
+    object(vec![("value", bits.into()), ("names", names.into())])
+}
+
+fn constant_json(index: usize, constant: &Constant) -> Json {
+    let mut entries = vec![("index", index.into())];
+    match *constant {
+        Constant::Utf8(ref value) => entries.extend(vec![("tag", "Utf8".into()), ("value", value.as_str().into())]),
+        Constant::Integer(value) => entries.extend(vec![("tag", "Integer".into()), ("value", (value as i32).into())]),
+        Constant::Float(value) => entries.extend(vec![("tag", "Float".into()), ("value", (value as f64).into())]),
+        Constant::Long(value) => entries.extend(vec![("tag", "Long".into()), ("value", (value as i64).into())]),
+        Constant::Double(value) => entries.extend(vec![("tag", "Double".into()), ("value", value.into())]),
+        Constant::ClassRef(ref name) => entries.extend(vec![("tag", "Class".into()), ("name", name.into())]),
+        Constant::StringRef(ref string) => entries.extend(vec![("tag", "String".into()), ("string", string.into())]),
+        Constant::FieldRef{ref class, ref name_and_type} => {
+            entries.extend(vec![("tag", "Fieldref".into()), ("class", class.into()), ("name_and_type", name_and_type.into())]);
+        },
+        Constant::MethodRef{ref class, ref name_and_type} => {
+            entries.extend(vec![("tag", "Methodref".into()), ("class", class.into()), ("name_and_type", name_and_type.into())]);
+        },
+        Constant::InterfaceMethodRef{ref class, ref name_and_type} => {
+            entries.extend(vec![("tag", "InterfaceMethodref".into()), ("class", class.into()), ("name_and_type", name_and_type.into())]);
+        },
+        Constant::NameAndTypeRef{ref name, ref descriptor} => {
+            entries.extend(vec![("tag", "NameAndType".into()), ("name", name.into()), ("descriptor", descriptor.into())]);
+        },
+        Constant::MethodHandleRef(ref handle) => {
+            let (kind, reference) = match *handle {
+                MethodHandle::GetField(ref index) => ("REF_getField", index),
+                MethodHandle::GetStatic(ref index) => ("REF_getStatic", index),
+                MethodHandle::PutField(ref index) => ("REF_putField", index),
+                MethodHandle::PutStatic(ref index) => ("REF_putStatic", index),
+                MethodHandle::InvokeVirtual(ref index) => ("REF_invokeVirtual", index),
+                MethodHandle::InvokeStatic(ref index) => ("REF_invokeStatic", index),
+                MethodHandle::InvokeSpecial(ref index) => ("REF_invokeSpecial", index),
+                MethodHandle::NewInvokeSpecial(ref index) => ("REF_newInvokeSpecial", index),
+                MethodHandle::InvokeInterface(ref index) => ("REF_invokeInterface", index),
+            };
+            entries.extend(vec![("tag", "MethodHandle".into()), ("reference_kind", kind.into()), ("reference", reference.into())]);
+        },
+        Constant::MethodType(ref descriptor) => entries.extend(vec![("tag", "MethodType".into()), ("descriptor", descriptor.into())]),
+        Constant::Dynamic{ref bootstrap_method_attr, ref name_and_type} => entries.extend(vec![
+            ("tag", "Dynamic".into()),
+            ("bootstrap_method_attr", bootstrap_method_attr.0.into()),
+            ("name_and_type", name_and_type.into()),
+        ]),
+        Constant::InvokeDynamicInfo{ref bootstrap_method_attr, ref name_and_type} => entries.extend(vec![
+            ("tag", "InvokeDynamic".into()),
+            ("bootstrap_method_attr", bootstrap_method_attr.0.into()),
+            ("name_and_type", name_and_type.into()),
+        ]),
+        Constant::Module(ref name) => entries.extend(vec![("tag", "Module".into()), ("name", name.into())]),
+        Constant::Package(ref name) => entries.extend(vec![("tag", "Package".into()), ("name", name.into())]),
+        Constant::Dummy => entries.push(("tag", "Dummy".into())),
+    }
+    object(entries)
+}
+
+fn attributes_json(attributes: &[Attribute]) -> Json {
+    Json::Array(attributes.iter().map(attribute_json).collect())
+}
+
+fn attribute_json(attribute: &Attribute) -> Json {
+    match *attribute {
+        Attribute::ConstantValue{ref constant_value, ..} => object(vec![("name", "ConstantValue".into()), ("constant_value", constant_value.into())]),
+        Attribute::Code(ref body) => object(vec![
+            ("name", "Code".into()),
+            ("max_stack", body.max_stack.into()),
+            ("max_locals", body.max_locals.into()),
+            ("code_length", body.code.len().into()),
+            ("instructions", instructions_json(&body.code)),
+            ("exception_table", Json::Array(body.exception_table.iter().map(|row| object(vec![
+                ("start_pc", row.start_pc.into()),
+                ("end_pc", row.end_pc.into()),
+                ("handler_pc", row.handler_pc.into()),
+                ("catch_type", (&row.catch_type).into()),
+            ])).collect())),
+            ("attributes", attributes_json(&body.attributes)),
+        ]),
+        Attribute::StackMapTable{ref entries, ..} => {
+            object(vec![("name", "StackMapTable".into()), ("entries", Json::Array(entries.iter().map(frame_json).collect()))])
+        },
+        Attribute::Exceptions{ref index_table, ..} => object(vec![("name", "Exceptions".into()), ("exceptions", indexes(index_table))]),
+        Attribute::InnerClasses{ref classes, ..} => object(vec![
+            ("name", "InnerClasses".into()),
+            ("classes", Json::Array(classes.iter().map(|inner| object(vec![
+                ("inner_class", (&inner.inner_class).into()),
+                ("outer_class", (&inner.outer_class).into()),
+                ("inner_name", (&inner.inner_class_name).into()),
+                ("access_flags", flags_json(inner.flags.bits(), INNER_CLASS_FLAG_NAMES)),
+            ])).collect())),
+        ]),
+        Attribute::EnclosingMethod{ref class, ref method, ..} => {
+            object(vec![("name", "EnclosingMethod".into()), ("class", class.into()), ("method", method.into())])
+        },
+        Attribute::Synthetic{..} => object(vec![("name", "Synthetic".into())]),
+        Attribute::Signature{ref signature, ..} => object(vec![("name", "Signature".into()), ("signature", signature.into())]),
+        Attribute::SourceFile{ref source_file, ..} => object(vec![("name", "SourceFile".into()), ("source_file", source_file.into())]),
+        Attribute::SourceDebug{ref debug_extension, ..} => object(vec![
+            ("name", "SourceDebugExtension".into()),
+            ("debug_extension", String::from_utf8_lossy(debug_extension).into_owned().into()),
+        ]),
+        Attribute::LineNumberTable{ref table, ..} => object(vec![
+            ("name", "LineNumberTable".into()),
+            ("line_numbers", Json::Array(table.iter()
+                .map(|&(start_pc, line_number)| object(vec![("start_pc", start_pc.into()), ("line_number", line_number.into())]))
+                .collect())),
+        ]),
+        Attribute::LocalVariableTable{ref variables, ..} => object(vec![
+            ("name", "LocalVariableTable".into()),
+            ("local_variables", Json::Array(variables.iter().map(|variable| object(vec![
+                ("start_pc", variable.start_pc.into()),
+                ("length", variable.length.into()),
+                ("name", (&variable.name).into()),
+                ("descriptor", (&variable.descriptor).into()),
+                ("index", variable.index.into()),
+            ])).collect())),
+        ]),
+        Attribute::LocalVariableTypeTable{ref variable_types, ..} => object(vec![
+            ("name", "LocalVariableTypeTable".into()),
+            ("local_variable_types", Json::Array(variable_types.iter().map(|variable| object(vec![
+                ("start_pc", variable.start_pc.into()),
+                ("length", variable.length.into()),
+                ("name", (&variable.name).into()),
+                ("signature", (&variable.signature).into()),
+                ("index", variable.index.into()),
+            ])).collect())),
+        ]),
+        Attribute::Deprecated{..} => object(vec![("name", "Deprecated".into())]),
+        Attribute::RuntimeVisibleAnnotations{ref annotations, ..} => {
+            object(vec![("name", "RuntimeVisibleAnnotations".into()), ("annotations", annotations_json(annotations))])
+        },
+        Attribute::RuntimeInvisibleAnnotations{ref annotations, ..} => {
+            object(vec![("name", "RuntimeInvisibleAnnotations".into()), ("annotations", annotations_json(annotations))])
+        },
+        Attribute::RuntimeVisibleParameterAnnotations{ref annotations_by_param_index, ..} => object(vec![
+            ("name", "RuntimeVisibleParameterAnnotations".into()),
+            ("parameter_annotations", Json::Array(annotations_by_param_index.iter().map(|annotations| annotations_json(&annotations.0)).collect())),
+        ]),
+        Attribute::RuntimeInvisibleParameterAnnotations{ref annotations_by_param_index, ..} => object(vec![
+            ("name", "RuntimeInvisibleParameterAnnotations".into()),
+            ("parameter_annotations", Json::Array(annotations_by_param_index.iter().map(|annotations| annotations_json(&annotations.0)).collect())),
+        ]),
+        Attribute::AnnotationDefault{ref value, ..} => object(vec![("name", "AnnotationDefault".into()), ("default_value", element_value_json(value))]),
+        Attribute::BootstrapMethods{ref methods, ..} => object(vec![
+            ("name", "BootstrapMethods".into()),
+            ("bootstrap_methods", Json::Array(methods.iter().map(|method| object(vec![
+                ("bootstrap_method_ref", (&method.method).into()),
+                ("bootstrap_arguments", indexes(&method.arguments)),
+            ])).collect())),
+        ]),
+        Attribute::Module(ref module) => object(vec![
+            ("name", "Module".into()),
+            ("module_name", (&module.name).into()),
+            ("module_flags", module.flags.bits().into()),
+            ("module_version", (&module.version).into()),
+            ("requires", Json::Array(module.requires.iter().map(|requires| object(vec![
+                ("requires", (&requires.module).into()),
+                ("requires_flags", requires.flags.bits().into()),
+                ("requires_version", (&requires.version).into()),
+            ])).collect())),
+            ("exports", packages_json(&module.exports)),
+            ("opens", packages_json(&module.opens)),
+            ("uses", indexes(&module.uses)),
+            ("provides", Json::Array(module.provides.iter().map(|provides| object(vec![
+                ("provides", (&provides.service).into()),
+                ("provides_with", indexes(&provides.implementations)),
+            ])).collect())),
+        ]),
+        Attribute::ModulePackages{ref packages, ..} => object(vec![("name", "ModulePackages".into()), ("packages", indexes(packages))]),
+        Attribute::ModuleMainClass{ref main_class, ..} => object(vec![("name", "ModuleMainClass".into()), ("main_class", main_class.into())]),
+        Attribute::NestHost{ref host_class, ..} => object(vec![("name", "NestHost".into()), ("host_class", host_class.into())]),
+        Attribute::NestMembers{ref classes, ..} => object(vec![("name", "NestMembers".into()), ("classes", indexes(classes))]),
+    }
+}
+
+fn packages_json(packages: &[ModulePackage]) -> Json {
+    Json::Array(packages.iter().map(|package| object(vec![
+        ("package", (&package.package).into()),
+        ("flags", package.flags.into()),
+        ("to", indexes(&package.targets)),
+    ])).collect())
+}
+
+fn frame_json(frame: &StackMapFrame) -> Json {
+    match *frame {
+        StackMapFrame::SameFrame{offset_delta} => object(vec![("frame_type", "same".into()), ("offset_delta", offset_delta.into())]),
+        StackMapFrame::SameLocalsOneStackItemFrame{offset_delta, ref stack_item} => object(vec![
+            ("frame_type", "same_locals_1_stack_item".into()),
+            ("offset_delta", offset_delta.into()),
+            ("stack", Json::Array(vec![verification_type_json(stack_item)])),
+        ]),
+        StackMapFrame::SameLocalsOneStackItemFrameExtended{offset_delta, ref stack_item} => object(vec![
+            ("frame_type", "same_locals_1_stack_item_frame_extended".into()),
+            ("offset_delta", offset_delta.into()),
+            ("stack", Json::Array(vec![verification_type_json(stack_item)])),
+        ]),
+        StackMapFrame::ChopFrame{offset_delta, num_absent_locals} => object(vec![
+            ("frame_type", "chop".into()),
+            ("offset_delta", offset_delta.into()),
+            ("absent_locals", num_absent_locals.into()),
+        ]),
+        StackMapFrame::SameFrameExtended{offset_delta} => object(vec![("frame_type", "same_frame_extended".into()), ("offset_delta", offset_delta.into())]),
+        StackMapFrame::AppendFrame{offset_delta, ref new_locals} => object(vec![
+            ("frame_type", "append".into()),
+            ("offset_delta", offset_delta.into()),
+            ("locals", Json::Array(new_locals.iter().map(verification_type_json).collect())),
+        ]),
+        StackMapFrame::FullFrame{offset_delta, ref locals, ref stack_items} => object(vec![
+            ("frame_type", "full_frame".into()),
+            ("offset_delta", offset_delta.into()),
+            ("locals", Json::Array(locals.iter().map(verification_type_json).collect())),
+            ("stack", Json::Array(stack_items.iter().map(verification_type_json).collect())),
+        ]),
+    }
+}
+
+fn verification_type_json(verification_type: &VerificationType) -> Json {
+    match *verification_type {
+        VerificationType::Top => object(vec![("type", "top".into())]),
+        VerificationType::Integer => object(vec![("type", "int".into())]),
+        VerificationType::Float => object(vec![("type", "float".into())]),
+        VerificationType::Long => object(vec![("type", "long".into())]),
+        VerificationType::Double => object(vec![("type", "double".into())]),
+        VerificationType::Null => object(vec![("type", "null".into())]),
+        VerificationType::UninitializedThis => object(vec![("type", "uninitialized_this".into())]),
+        VerificationType::Object(ref class) => object(vec![("type", "object".into()), ("class", class.into())]),
+        VerificationType::Uninitialized(offset) => object(vec![("type", "uninitialized".into()), ("offset", offset.into())]),
+    }
+}
+
+fn annotations_json(annotations: &[Annotation]) -> Json {
+    Json::Array(annotations.iter().map(annotation_json).collect())
+}
+
+fn annotation_json(annotation: &Annotation) -> Json {
+    object(vec![
+        ("type", (&annotation.type_index).into()),
+        ("elements", Json::Array(annotation.indexes_with_values.iter()
+            .map(|(name, value)| object(vec![("name", name.into()), ("value", element_value_json(value))]))
+            .collect())),
+    ])
+}
+
+fn element_value_json(value: &ElementValue) -> Json {
+    let constant = |tag: &str, index: &ConstantIndex| object(vec![("tag", tag.into()), ("const_value", index.into())]);
+    match *value {
+        ElementValue::Byte(ref index) => constant("B", index),
+        ElementValue::Char(ref index) => constant("C", index),
+        ElementValue::Double(ref index) => constant("D", index),
+        ElementValue::Float(ref index) => constant("F", index),
+        ElementValue::Integer(ref index) => constant("I", index),
+        ElementValue::Long(ref index) => constant("J", index),
+        ElementValue::Short(ref index) => constant("S", index),
+        ElementValue::Boolean(ref index) => constant("Z", index),
+        ElementValue::String(ref index) => constant("s", index),
+        ElementValue::Enum{ref enum_type, ref enum_value} => {
+            object(vec![("tag", "e".into()), ("type_name", enum_type.into()), ("const_name", enum_value.into())])
+        },
+        ElementValue::Class(ref index) => object(vec![("tag", "c".into()), ("class_info", index.into())]),
+        ElementValue::Annotation(ref annotation) => object(vec![("tag", "@".into()), ("annotation_value", annotation_json(annotation))]),
+        ElementValue::Array(ref values) => {
+            object(vec![("tag", "[".into()), ("values", Json::Array(values.iter().map(element_value_json).collect()))])
+        },
+    }
+}
+
+// Each instruction gives its pc, its opcode and mnemonic as they appear in the code, and its
+// operands. Code that doesn't decode ends with an object giving the error, at the pc where it
+// went wrong.
+fn instructions_json(code: &[u8]) -> Json {
+    let mut instructions = vec![];
+    let mut pc = 0;
+    while pc < code.len() {
+        let instruction = match bytecode::decode_at(code, pc) {
+            Ok(instruction) => instruction,
+            Err(err) => {
+                instructions.push(object(vec![("pc", pc.into()), ("error", err.to_string().into())]));
+                break;
+            },
+        };
+        let wide = code[pc] == WIDE;
+        let mut entries = vec![
+            ("pc", pc.into()),
+            ("opcode", code[pc].into()),
+            ("mnemonic", mnemonic(if wide { instruction.opcode } else { code[pc] }).into()),
+        ];
+        if wide {
+            entries.push(("wide", true.into()));
+        }
+        match instruction.operand {
+            Operand::None => (),
+            Operand::Local(local) => entries.push(("local", local.into())),
+            Operand::Increment{local, delta} => entries.extend(vec![("local", local.into()), ("delta", (delta as i32).into())]),
+            Operand::Int(value) => entries.push(("value", value.into())),
+            Operand::Constant(index) => entries.push(("constant", index.into())),
+            Operand::Branch(target) => entries.push(("target", target.into())),
+            Operand::Switch{default, ref targets} => entries.extend(vec![
+                ("default", default.into()),
+                ("targets", Json::Array(targets.iter().map(|&(key, target)| object(vec![("key", key.into()), ("target", target.into())])).collect())),
+            ]),
+            Operand::MultiArray{class, dimensions} => entries.extend(vec![("constant", class.into()), ("dimensions", dimensions.into())]),
+        }
+        instructions.push(object(entries));
+        pc += instruction.length;
+    }
+    Json::Array(instructions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::parsed_fixture;
+
+    #[test]
+    fn test_formatting() {
+        let json = object(vec![
+            ("text", "a \"quoted\"\n\u{1}".into()),
+            ("numbers", vec![Json::Int(-3), Json::Float(0.5), Json::Float(f64::NAN)].into()),
+            ("empty", Json::Array(vec![])),
+            ("nothing", Json::Null),
+        ]);
+        assert_eq!(r#"{"text":"a \"quoted\"\n\u0001","numbers":[-3,0.5,"NaN"],"empty":[],"nothing":null}"#, json.to_string());
+        assert_eq!("{\n  \"numbers\": [\n    1\n  ],\n  \"empty\": {}\n}", format!("{:#}", object(vec![
+            ("numbers", vec![1].into()),
+            ("empty", Json::Object(vec![])),
+        ])));
+    }
+
+    #[test]
+    fn test_class_json() {
+        let json = parsed_fixture!("Arithmetic").json();
+        assert_eq!(Some(&Json::Int(52)), json.get("major_version"));
+        assert_eq!(Some(&Json::from("Arithmetic")), json.get("this_class_name"));
+        assert_eq!(Some(&Json::from("java/lang/Object")), json.get("super_class_name"));
+        let flags = json.get("access_flags").unwrap();
+        assert_eq!("{\"value\":33,\"names\":[\"ACC_PUBLIC\",\"ACC_SUPER\"]}", flags.to_string());
+
+        let pool = match json.get("constant_pool") {
+            Some(Json::Array(pool)) => pool,
+            other => panic!("Expected an array; got {:?}", other),
+        };
+        assert_eq!("{\"index\":1,\"tag\":\"Methodref\",\"class\":2,\"name_and_type\":3}", pool[0].to_string());
+        assert_eq!("{\"index\":4,\"tag\":\"Utf8\",\"value\":\"java/lang/Object\"}", pool[3].to_string());
+    }
+
+    #[test]
+    fn test_method_json() {
+        let json = parsed_fixture!("Arithmetic").json();
+        let constructor = match json.get("methods") {
+            Some(Json::Array(methods)) => &methods[0],
+            other => panic!("Expected an array; got {:?}", other),
+        };
+        assert_eq!(Some(&Json::from("<init>")), constructor.get("name"));
+        assert_eq!(Some(&Json::from("()V")), constructor.get("descriptor"));
+        let code = match constructor.get("attributes") {
+            Some(Json::Array(attributes)) => &attributes[0],
+            other => panic!("Expected an array; got {:?}", other),
+        };
+        assert_eq!(Some(&Json::from("Code")), code.get("name"));
+        assert_eq!(concat!(
+            r#"[{"pc":0,"opcode":42,"mnemonic":"aload_0","local":0},"#,
+            r#"{"pc":1,"opcode":183,"mnemonic":"invokespecial","constant":1},"#,
+            r#"{"pc":4,"opcode":177,"mnemonic":"return"}]"#,
+        ), code.get("instructions").unwrap().to_string());
+        assert_eq!(
+            r#"[{"name":"LineNumberTable","line_numbers":[{"start_pc":0,"line_number":1}]}]"#,
+            code.get("attributes").unwrap().to_string(),
+        );
+    }
+
+    #[test]
+    fn test_invalid_code() {
+        let json = instructions_json(&[NOP, BIPUSH]);
+        let error = bytecode::BytecodeError::Truncated(1).to_string();
+        assert_eq!(format!(r#"[{{"pc":0,"opcode":0,"mnemonic":"nop"}},{{"pc":1,"error":"{}"}}]"#, error), json.to_string());
+    }
+}
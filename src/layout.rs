@@ -0,0 +1,156 @@
+use crate::descriptor::FieldType;
+
+// The size of an object header, as in HotSpot without compressed class pointers.
+pub const HEADER_SIZE: usize = 16;
+
+// Where each instance field of a class would sit in memory, in the style of HotSpot's field
+// layout: inherited fields keep their offsets, and the class's own fields are placed largest
+// first, each aligned to its size, filling any gaps left by the superclass before extending the
+// object. This is a model for accounting rather than how objects are stored, which is by slot.
+// The sizes it gives are what the allocation budget and the heap's byte count charge for each
+// object, and the offsets are what Unsafe.objectFieldOffset hands out and compareAndSet takes.
+// Packing the fields shrinks those sizes but not the memory objects really take, as the particles
+// workload in benches/interpreter.rs shows by reporting both.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldLayout {
+    offsets: Vec<usize>, // Indexed by field slot, including inherited fields.
+    gaps: Vec<(usize, usize)>, // Unused ranges below end, as start and end offsets.
+    end: usize, // The offset just past the last field.
+}
+
+impl Default for FieldLayout {
+    fn default() -> FieldLayout {
+        FieldLayout {offsets: vec![], gaps: vec![], end: HEADER_SIZE}
+    }
+}
+
+impl FieldLayout {
+    // Lays out a subclass's own fields, given their sizes in slot order, after this layout's.
+    pub fn extend(&self, sizes: &[usize]) -> FieldLayout {
+        let mut layout = self.clone();
+        let first_slot = layout.offsets.len();
+        layout.offsets.resize(first_slot + sizes.len(), 0);
+
+        let mut order: Vec<usize> = (0..sizes.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(sizes[i])); // Stable, so ties keep declaration order.
+        for i in order {
+            layout.offsets[first_slot + i] = layout.place(sizes[i]);
+        }
+        layout
+    }
+
+    // Finds space for a field of the given size, in the first gap it fits or else at the end.
+    fn place(&mut self, size: usize) -> usize {
+        for i in 0..self.gaps.len() {
+            let (start, end) = self.gaps[i];
+            let offset = align(start, size);
+            if offset + size <= end {
+                self.gaps.remove(i);
+                self.add_gap(offset + size, end);
+                self.add_gap(start, offset);
+                self.gaps.sort();
+                return offset;
+            }
+        }
+
+        let offset = align(self.end, size);
+        self.add_gap(self.end, offset);
+        self.end = offset + size;
+        offset
+    }
+
+    fn add_gap(&mut self, start: usize, end: usize) {
+        if start < end {
+            self.gaps.push((start, end));
+        }
+    }
+
+    // The offset of the field in the given slot from the start of the object.
+    pub fn offset(&self, slot: usize) -> Option<usize> {
+        self.offsets.get(slot).copied()
+    }
+
+    // The slot of the field at the given offset, the inverse of offset.
+    pub fn slot_at(&self, offset: usize) -> Option<usize> {
+        self.offsets.iter().position(|&field_offset| field_offset == offset)
+    }
+
+    // The size of an instance, including the header, padded to a multiple of 8 bytes.
+    pub fn instance_size(&self) -> usize {
+        align(self.end, 8)
+    }
+}
+
+// The number of bytes a field of the given type takes up, with references taking 8.
+pub fn field_size(field_type: &FieldType) -> usize {
+    match *field_type {
+        FieldType::Boolean | FieldType::Byte => 1,
+        FieldType::Char | FieldType::Short => 2,
+        FieldType::Int | FieldType::Float => 4,
+        FieldType::Long | FieldType::Double | FieldType::Object(_) | FieldType::Array(_) => 8,
+    }
+}
+
+fn align(offset: usize, alignment: usize) -> usize {
+    offset.div_ceil(alignment) * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+
+    // The size the fields would take laid out one after another, each aligned to its size, for
+    // comparison with what sorting them saves.
+    fn size_in_declaration_order(sizes: &[usize]) -> usize {
+        align(sizes.iter().fold(HEADER_SIZE, |end, &size| align(end, size) + size), 8)
+    }
+
+    #[test]
+    fn test_fields_are_sorted_by_size() {
+        // byte, long, boolean, int, as declared.
+        let layout = FieldLayout::default().extend(&[1, 8, 1, 4]);
+        assert_eq!(vec![Some(28), Some(16), Some(29), Some(24)], (0..4).map(|slot| layout.offset(slot)).collect::<Vec<_>>());
+        assert_eq!(32, layout.instance_size());
+        assert_eq!(40, size_in_declaration_order(&[1, 8, 1, 4]));
+    }
+
+    #[test]
+    fn test_subclasses_fill_gaps() {
+        let parent = FieldLayout::default().extend(&[8, 1]);
+        assert_eq!(32, parent.instance_size());
+        let child = parent.extend(&[8, 4, 2]);
+        assert_eq!(Some(16), child.offset(0));
+        assert_eq!(Some(24), child.offset(1));
+        assert_eq!(Some(32), child.offset(2));
+        assert_eq!(Some(28), child.offset(3));
+        assert_eq!(Some(26), child.offset(4));
+        assert_eq!(40, child.instance_size());
+    }
+
+    #[test]
+    fn test_slot_at() {
+        let layout = FieldLayout::default().extend(&[4, 8]);
+        assert_eq!(Some(1), layout.slot_at(16));
+        assert_eq!(Some(0), layout.slot_at(24));
+        assert_eq!(None, layout.slot_at(20));
+    }
+
+    #[test]
+    fn test_class_layout() {
+        let mut vm = vm_with(&[fixture!("Particle")]);
+        let class = vm.load_class("Particle").unwrap();
+        assert_eq!(Some(16), class.field_offset("id"));
+        assert_eq!(Some(32), class.field_offset("next"));
+        assert_eq!(Some(44), class.field_offset("alive"));
+        assert_eq!(Some(45), class.field_offset("kind"));
+        assert_eq!(48, class.layout().instance_size());
+        // boolean, byte, long, int, double, Particle, as declared.
+        assert_eq!(56, size_in_declaration_order(&[1, 1, 8, 4, 8, 8]));
+    }
+
+    #[test]
+    fn test_empty_objects_are_just_a_header() {
+        assert_eq!(HEADER_SIZE, FieldLayout::default().instance_size());
+    }
+}
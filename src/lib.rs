@@ -0,0 +1,80 @@
+#[macro_use] extern crate bitflags;
+
+pub mod access;
+#[cfg(feature = "archives")]
+pub mod archive;
+pub mod bench;
+pub mod bootstrap;
+pub mod bytecode;
+pub mod call;
+pub mod classes;
+pub mod classloader;
+pub mod classpath;
+pub mod clock;
+pub mod compression;
+pub mod console;
+pub mod counters;
+pub mod crash;
+pub mod debugger;
+pub mod deflate;
+pub mod depgraph;
+pub mod descriptor;
+pub mod diff;
+pub mod disasm;
+pub mod direct;
+pub mod entropy;
+pub mod env;
+pub mod events;
+pub mod gc;
+pub mod gclog;
+pub mod handles;
+pub mod heap;
+pub mod histogram;
+pub mod hooks;
+pub mod inference;
+pub mod inflate;
+pub mod inline_cache;
+pub mod instrument;
+pub mod interpreter;
+pub mod intrinsics;
+pub mod jar;
+pub mod jimage;
+pub mod json;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod layout;
+#[cfg(feature = "native-libraries")]
+pub mod libraries;
+pub mod limits;
+pub mod loaders;
+pub mod method_area;
+pub mod method_handles;
+pub mod modules;
+pub mod natives;
+pub mod opcodes;
+pub mod options;
+pub mod outcome;
+pub mod profiler;
+pub mod recorder;
+pub mod reflection;
+#[cfg(feature = "remote")]
+pub mod remote;
+pub mod resolve;
+pub mod roots;
+pub mod runtime;
+pub mod safepoint;
+pub mod sandbox;
+pub mod shutdown;
+pub mod signals;
+pub mod stackmap;
+pub mod strings;
+pub mod symbols;
+#[cfg(test)]
+mod test_support;
+pub mod threaddump;
+pub mod threads;
+pub mod trace;
+pub mod verify;
+pub mod vm;
+pub mod walker;
+pub mod zip;
@@ -0,0 +1,823 @@
+use crate::descriptor::{FieldType, MethodDescriptor};
+use crate::env::Env;
+use crate::handles::{GlobalRef, LocalRef};
+use crate::heap::ObjectRef;
+use crate::loaders::LoaderId;
+use crate::runtime::*;
+use crate::symbols::Symbol;
+use crate::vm::{Vm, VmError};
+use libloading::Library;
+use std::collections::HashMap;
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::OnceLock;
+
+// Shared libraries loaded by System.load and System.loadLibrary, whose exported functions
+// implement native methods as in JNI. A method's function is found by its mangled name (JNI spec
+// chapter 2, "Resolving Native Method Names"): the short name, Java_ followed by the class and
+// method names, and then the long name, which adds the parameter descriptor so that overloads
+// can be told apart. Natives registered with Vm::register_native take precedence.
+//
+// Functions are called with a JNIEnv whose function table has the commonly used functions in it:
+// finding classes, references, exceptions, fields and method calls by ID, strings and int
+// arrays. Calling any other function aborts the process with a message saying which. Function
+// and method arguments are passed as the platform's C calling convention passes them, which
+// joyvm only knows for 64-bit Unix; there, up to 8 arguments besides floats and doubles,
+// counting the JNIEnv and the class or receiver, and up to 8 floats and doubles are supported. Strings are converted to and from standard UTF-8
+// rather than JNI's modified UTF-8.
+//
+// Unlike in the JVM, libraries aren't tied to the class loader that loaded them: every loaded
+// library is searched for every native method.
+
+pub const JNI_VERSION: i32 = 0x0001_0008; // JNI_VERSION_1_8.
+
+// A function found in a loaded library. Only Libraries::find makes these, so the functions
+// invoke calls are always ones whose names say they implement the method.
+#[derive(Clone, Copy, Debug)]
+pub struct NativeFunction(*const c_void);
+
+#[derive(Default)]
+pub struct Libraries {
+    loaded: Vec<(PathBuf, Library)>,
+    members: Vec<Member>, // Those JNI has handed out IDs for; an ID is an index into this, plus one.
+    member_ids: HashMap<(LoaderId, Symbol, String, String, bool), usize>,
+}
+
+// A field or method that natives refer to by ID.
+#[derive(Clone)]
+struct Member {
+    class: Rc<RuntimeClass>,
+    name: String,
+    descriptor: String,
+}
+
+impl Libraries {
+    pub fn new() -> Libraries {
+        Libraries::default()
+    }
+
+    // Loads the library, unless it's already loaded.
+    pub fn load(&mut self, path: &Path) -> Result<(), libloading::Error> {
+        if self.loaded.iter().any(|(loaded, _)| loaded == path) {
+            return Ok(());
+        }
+        // Running a library's initializers is as safe as running its natives, which is the
+        // point of loading it.
+        let library = unsafe { Library::new(path) }?;
+        self.loaded.push((path.to_path_buf(), library));
+        Ok(())
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.loaded.iter().map(|(path, _)| path.as_path())
+    }
+
+    // Finds the function implementing a native method in the loaded libraries.
+    pub fn find(&self, class: &str, name: &str, descriptor: &str) -> Option<NativeFunction> {
+        let short_name = short_name(class, name);
+        let long_name = long_name(class, name, descriptor);
+        [short_name, long_name].iter().find_map(|symbol| {
+            self.loaded.iter().find_map(|(_, library)| {
+                // Only the symbol's address is read here; it's called by invoke.
+                unsafe { library.get::<*const c_void>(symbol.as_bytes()) }.ok().map(|function| NativeFunction(*function))
+            })
+        })
+    }
+
+    fn member_id(&mut self, class: &Rc<RuntimeClass>, name: &str, descriptor: &str, is_static: bool) -> usize {
+        let key = (class.loader, class.name.clone(), name.to_string(), descriptor.to_string(), is_static);
+        if let Some(&id) = self.member_ids.get(&key) {
+            return id;
+        }
+        self.members.push(Member {class: Rc::clone(class), name: name.to_string(), descriptor: descriptor.to_string()});
+        self.member_ids.insert(key, self.members.len());
+        self.members.len()
+    }
+
+    fn member(&self, id: usize) -> Option<Member> {
+        id.checked_sub(1).and_then(|index| self.members.get(index)).cloned()
+    }
+}
+
+pub fn short_name(class: &str, name: &str) -> String {
+    format!("Java_{}_{}", mangle(class), mangle(name))
+}
+
+pub fn long_name(class: &str, name: &str, descriptor: &str) -> String {
+    let parameters = descriptor.strip_prefix('(').and_then(|rest| rest.split(')').next()).unwrap_or("");
+    format!("{}__{}", short_name(class, name), mangle(parameters))
+}
+
+// Escapes the characters that can't appear in C identifiers, and the underscore itself.
+fn mangle(name: &str) -> String {
+    let mut mangled = String::new();
+    for c in name.chars() {
+        match c {
+            '/' => mangled.push('_'),
+            '_' => mangled.push_str("_1"),
+            ';' => mangled.push_str("_2"),
+            '[' => mangled.push_str("_3"),
+            c if c.is_ascii_alphanumeric() => mangled.push(c),
+            c => {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    mangled.push_str(&format!("_0{:04x}", unit));
+                }
+            },
+        }
+    }
+    mangled
+}
+
+// The most arguments of each kind that natives can take, counting the JNIEnv and the class or
+// receiver.
+const MAX_WORDS: usize = 8;
+const MAX_FLOATS: usize = 8;
+
+type Words = [usize; MAX_WORDS];
+type Floats = [f64; MAX_FLOATS];
+
+// Runs a native function from a library. For instance methods, the receiver is the first
+// argument.
+pub fn invoke(vm: &mut Vm, class: &Rc<RuntimeClass>, function: NativeFunction, is_static: bool, descriptor: &str, args: &[Value]) -> Result<Option<Value>, VmError> {
+    let descriptor = vm.method_descriptor(descriptor)?;
+    let (receiver, args) = match (is_static, args.split_first()) {
+        (true, _) => (Value::Reference(Some(vm.class_mirror(class)?)), args),
+        (false, Some((receiver, args))) => (receiver.clone(), args),
+        (false, None) => return Err(VmError::InvalidBytecode("Instance native called without a receiver".to_string())),
+    };
+
+    let env = Box::into_raw(Box::new(JniEnv::new(vm, class)));
+    let result = unsafe { call(&mut *env, function, &descriptor, &receiver, args) };
+    let mut env = unsafe { Box::from_raw(env) };
+    let result = result.and_then(|value| env.result(value, descriptor.return_type.as_ref()));
+    match (env.error.take(), env.pending.take()) {
+        (Some(err), _) => Err(err),
+        (None, Some(exception)) => Err(VmError::UncaughtException(exception)),
+        (None, None) => result,
+    }
+}
+
+// What a native function returned: an integer or pointer, or a float or double.
+enum Returned {
+    Word(usize),
+    Float(f64),
+}
+
+// Lays the arguments out as the C calling convention on 64-bit Unix would: words in one
+// sequence of registers and floats in another. A function with fewer of either ignores the
+// registers it doesn't use, so every function can be called as one taking the most of both.
+#[cfg(all(unix, target_pointer_width = "64", any(target_arch = "x86_64", target_arch = "aarch64")))]
+unsafe fn call(env: &mut JniEnv, function: NativeFunction, descriptor: &MethodDescriptor, receiver: &Value, args: &[Value]) -> Result<Returned, VmError> {
+    let mut words = vec![env as *mut JniEnv as usize, env.jobject(receiver)?];
+    let mut floats = vec![];
+    for arg in args {
+        match *arg {
+            Value::Int(value) => words.push(value as isize as usize),
+            Value::Long(value) => words.push(value as usize),
+            Value::Float(value) => floats.push(f64::from_bits(value.to_bits() as u64)),
+            Value::Double(value) => floats.push(value),
+            Value::Reference(_) => words.push(env.jobject(arg)?),
+            Value::ReturnAddress(_) => return Err(VmError::InvalidBytecode("Return address passed to a native".to_string())),
+        }
+    }
+    if words.len() > MAX_WORDS || floats.len() > MAX_FLOATS {
+        let message = format!("{} has too many arguments to be called through JNI", env.class.name);
+        return Err(env.vm().throw_new_with_message("java/lang/UnsatisfiedLinkError", &message));
+    }
+    let mut w: Words = [0; MAX_WORDS];
+    let mut f: Floats = [0.0; MAX_FLOATS];
+    w[..words.len()].copy_from_slice(&words);
+    f[..floats.len()].copy_from_slice(&floats);
+
+    if matches!(descriptor.return_type, Some(FieldType::Float) | Some(FieldType::Double)) {
+        let function: unsafe extern "C" fn(usize, usize, usize, usize, usize, usize, usize, usize, f64, f64, f64, f64, f64, f64, f64, f64) -> f64 = std::mem::transmute(function.0);
+        Ok(Returned::Float(function(w[0], w[1], w[2], w[3], w[4], w[5], w[6], w[7], f[0], f[1], f[2], f[3], f[4], f[5], f[6], f[7])))
+    } else {
+        let function: unsafe extern "C" fn(usize, usize, usize, usize, usize, usize, usize, usize, f64, f64, f64, f64, f64, f64, f64, f64) -> usize = std::mem::transmute(function.0);
+        Ok(Returned::Word(function(w[0], w[1], w[2], w[3], w[4], w[5], w[6], w[7], f[0], f[1], f[2], f[3], f[4], f[5], f[6], f[7])))
+    }
+}
+
+#[cfg(not(all(unix, target_pointer_width = "64", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+unsafe fn call(env: &mut JniEnv, _function: NativeFunction, _descriptor: &MethodDescriptor, _receiver: &Value, _args: &[Value]) -> Result<Returned, VmError> {
+    Err(env.vm().throw_new_with_message("java/lang/UnsatisfiedLinkError", "JNI calls aren't supported on this platform"))
+}
+
+// The state behind a JNIEnv pointer, which is a pointer to a pointer to the function table. The
+// JNIEnv lasts for one call of a native function, as do the local references it hands out.
+// References are encoded as non-zero integers: locals as their index in locals, and globals as
+// their index among the VM's globals, shifted left and with the lowest bit set.
+#[repr(C)]
+struct JniEnv {
+    functions: *const usize, // Must come first.
+    vm: *mut Vm,
+    class: Rc<RuntimeClass>, // The class declaring the native.
+    locals: Vec<LocalRef>,
+    pending: Option<ObjectRef>, // The exception thrown, to be rethrown when the native returns.
+    error: Option<VmError>, // An error other than an exception, which the native can't handle.
+}
+
+impl JniEnv {
+    fn new(vm: &mut Vm, class: &Rc<RuntimeClass>) -> JniEnv {
+        JniEnv {functions: function_table().as_ptr(), vm, class: Rc::clone(class), locals: vec![], pending: None, error: None}
+    }
+
+    fn vm(&mut self) -> &mut Vm {
+        // The Vm outlives every call to a native, and isn't used elsewhere while one runs.
+        unsafe { &mut *self.vm }
+    }
+
+    fn env(&mut self) -> Env<'_> {
+        let class = Rc::clone(&self.class);
+        Env::new(self.vm(), class)
+    }
+
+    fn jobject(&mut self, value: &Value) -> Result<usize, VmError> {
+        match *value {
+            Value::Reference(None) => Ok(0),
+            Value::Reference(Some(ref object)) => Ok(self.new_local(object.clone())),
+            ref other => Err(VmError::NativeSignature(format!("expected a reference, got {:?}", other))),
+        }
+    }
+
+    fn new_local(&mut self, object: ObjectRef) -> usize {
+        let local = self.vm().new_local_ref(object);
+        self.locals.push(local);
+        self.locals.len() << 1
+    }
+
+    fn object(&mut self, jobject: usize) -> Option<ObjectRef> {
+        if jobject == 0 {
+            return None;
+        }
+        let index = (jobject >> 1) - 1;
+        if jobject & 1 == 1 {
+            self.vm().global_ref(GlobalRef::from_index(index)).cloned()
+        } else {
+            let local = *self.locals.get(index)?;
+            self.vm().local_ref(local).cloned()
+        }
+    }
+
+    // Like object, but throws NullPointerException for null.
+    fn non_null(&mut self, jobject: usize) -> Result<ObjectRef, VmError> {
+        self.object(jobject).ok_or_else(|| self.vm().throw_new("java/lang/NullPointerException"))
+    }
+
+    fn class_of(&mut self, jclass: usize) -> Result<Rc<RuntimeClass>, VmError> {
+        let mirror = self.non_null(jclass)?;
+        self.vm().mirror_class(&mirror).ok_or_else(|| VmError::NativeSignature("expected a Class object".to_string()))
+    }
+
+    fn result(&mut self, returned: Returned, return_type: Option<&FieldType>) -> Result<Option<Value>, VmError> {
+        Ok(match (returned, return_type) {
+            (_, None) => None,
+            (Returned::Float(value), Some(&FieldType::Float)) => Some(Value::Float(f32::from_bits(value.to_bits() as u32))),
+            (Returned::Float(value), Some(_)) => Some(Value::Double(value)),
+            (Returned::Word(word), Some(&FieldType::Boolean)) => Some(Value::Int((word as u8 != 0) as i32)),
+            (Returned::Word(word), Some(&FieldType::Byte)) => Some(Value::Int(word as i8 as i32)),
+            (Returned::Word(word), Some(&FieldType::Char)) => Some(Value::Int(word as u16 as i32)),
+            (Returned::Word(word), Some(&FieldType::Short)) => Some(Value::Int(word as i16 as i32)),
+            (Returned::Word(word), Some(&FieldType::Long)) => Some(Value::Long(word as i64)),
+            (Returned::Word(word), Some(field_type)) if field_type.is_reference() => Some(Value::Reference(self.object(word))),
+            (Returned::Word(word), Some(_)) => Some(Value::Int(word as i32)),
+        })
+    }
+
+    // Runs a JNI function's body, recording an exception it throws as pending and any other
+    // error for when the native returns, and returning the default in place of a result.
+    fn attempt<T>(&mut self, default: T, f: impl FnOnce(&mut JniEnv) -> Result<T, VmError>) -> T {
+        match f(self) {
+            Ok(value) => value,
+            Err(VmError::UncaughtException(exception)) => {
+                self.pending = Some(exception);
+                default
+            },
+            Err(err) => {
+                self.error.get_or_insert(err);
+                default
+            },
+        }
+    }
+
+    fn member_id(&mut self, jclass: usize, name: *const c_char, descriptor: *const c_char, is_static: bool, is_method: bool) -> Result<usize, VmError> {
+        let class = self.class_of(jclass)?;
+        let name = unsafe { c_string(name) };
+        let descriptor = unsafe { c_string(descriptor) };
+        if is_method && resolve_method(&class, &name, &descriptor)?.is_none() {
+            return Err(self.vm().throw_new_with_message("java/lang/NoSuchMethodError", &name));
+        }
+        if !is_method && class.field_slot(&name).is_none() {
+            return Err(self.vm().throw_new_with_message("java/lang/NoSuchFieldError", &name));
+        }
+        Ok(self.vm().libraries_mut().member_id(&class, &name, &descriptor, is_static))
+    }
+
+    fn member(&mut self, id: usize) -> Result<Member, VmError> {
+        self.vm().libraries().member(id).ok_or_else(|| VmError::NativeSignature(format!("invalid field or method ID {}", id)))
+    }
+
+    // Calls the method, virtually if there's a receiver, with arguments from a jvalue array.
+    fn call(&mut self, receiver: Option<usize>, method: usize, args: *const JValue) -> Result<Option<Value>, VmError> {
+        let method = self.member(method)?;
+        let parameters = MethodDescriptor::parse(&method.descriptor)?.parameters;
+        let mut values = vec![];
+        for (index, parameter) in parameters.iter().enumerate() {
+            let arg = unsafe { *args.add(index) };
+            values.push(unsafe {
+                match *parameter {
+                    FieldType::Boolean => Value::Int((arg.z != 0) as i32),
+                    FieldType::Byte => Value::Int(arg.b as i32),
+                    FieldType::Char => Value::Int(arg.c as i32),
+                    FieldType::Short => Value::Int(arg.s as i32),
+                    FieldType::Int => Value::Int(arg.i),
+                    FieldType::Long => Value::Long(arg.j),
+                    FieldType::Float => Value::Float(arg.f),
+                    FieldType::Double => Value::Double(arg.d),
+                    FieldType::Object(_) | FieldType::Array(_) => Value::Reference(self.object(arg.l)),
+                }
+            });
+        }
+        match receiver {
+            Some(receiver) => {
+                let receiver = self.non_null(receiver)?;
+                self.env().call_method(&receiver, &method.name, &method.descriptor, values)
+            },
+            None => self.env().call_static(&method.class.name, &method.name, &method.descriptor, values),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+union JValue {
+    z: u8,
+    b: i8,
+    c: u16,
+    s: i16,
+    i: i32,
+    j: i64,
+    f: f32,
+    d: f64,
+    l: usize,
+}
+
+unsafe fn c_string(chars: *const c_char) -> String {
+    CStr::from_ptr(chars).to_string_lossy().into_owned()
+}
+
+// Turns the JNIEnv pointer passed to a JNI function back into the state behind it.
+unsafe fn jni<'a>(env: *mut JniEnv) -> &'a mut JniEnv {
+    &mut *env
+}
+
+const FUNCTION_COUNT: usize = 234;
+
+// The function table, with each function at its index in the JNI specification.
+fn function_table() -> &'static [usize; FUNCTION_COUNT] {
+    static TABLE: OnceLock<[usize; FUNCTION_COUNT]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0; FUNCTION_COUNT];
+        for (index, slot) in table.iter_mut().enumerate() {
+            *slot = UNSUPPORTED[index] as *const () as usize;
+        }
+        table[4] = get_version as *const () as usize;
+        table[6] = find_class as *const () as usize;
+        table[10] = get_superclass as *const () as usize;
+        table[13] = throw as *const () as usize;
+        table[14] = throw_new as *const () as usize;
+        table[15] = exception_occurred as *const () as usize;
+        table[17] = exception_clear as *const () as usize;
+        table[21] = new_global_ref as *const () as usize;
+        table[22] = delete_global_ref as *const () as usize;
+        table[23] = delete_local_ref as *const () as usize;
+        table[24] = is_same_object as *const () as usize;
+        table[25] = new_local_ref as *const () as usize;
+        table[31] = get_object_class as *const () as usize;
+        table[32] = is_instance_of as *const () as usize;
+        table[33] = get_method_id as *const () as usize;
+        table[36] = call_object_method_a as *const () as usize;
+        table[51] = call_int_method_a as *const () as usize;
+        table[63] = call_void_method_a as *const () as usize;
+        table[94] = get_field_id as *const () as usize;
+        table[95] = get_object_field as *const () as usize;
+        table[100] = get_int_field as *const () as usize;
+        table[104] = set_object_field as *const () as usize;
+        table[109] = set_int_field as *const () as usize;
+        table[113] = get_static_method_id as *const () as usize;
+        table[116] = call_static_object_method_a as *const () as usize;
+        table[131] = call_static_int_method_a as *const () as usize;
+        table[143] = call_static_void_method_a as *const () as usize;
+        table[167] = new_string_utf as *const () as usize;
+        table[168] = get_string_utf_length as *const () as usize;
+        table[169] = get_string_utf_chars as *const () as usize;
+        table[170] = release_string_utf_chars as *const () as usize;
+        table[171] = get_array_length as *const () as usize;
+        table[179] = new_int_array as *const () as usize;
+        table[203] = get_int_array_region as *const () as usize;
+        table[211] = set_int_array_region as *const () as usize;
+        table[228] = exception_check as *const () as usize;
+        table
+    })
+}
+
+// The functions left out of the table abort the process, since a native can't carry on without
+// what it called them for. There's one per slot, so that each can say which it is.
+macro_rules! unsupported_functions {
+    ($($index:literal),*) => {
+        const UNSUPPORTED: &[unsafe extern "C" fn()] = &[$({
+            unsafe extern "C" fn unsupported() {
+                eprintln!("joyvm: JNI function {} isn't supported", $index);
+                std::process::abort();
+            }
+            unsupported
+        }),*];
+    };
+}
+
+unsupported_functions!(
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29,
+    30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59,
+    60, 61, 62, 63, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 80, 81, 82, 83, 84, 85, 86, 87, 88, 89,
+    90, 91, 92, 93, 94, 95, 96, 97, 98, 99, 100, 101, 102, 103, 104, 105, 106, 107, 108, 109, 110, 111, 112, 113, 114, 115, 116, 117, 118, 119,
+    120, 121, 122, 123, 124, 125, 126, 127, 128, 129, 130, 131, 132, 133, 134, 135, 136, 137, 138, 139, 140, 141, 142, 143, 144, 145, 146, 147, 148, 149,
+    150, 151, 152, 153, 154, 155, 156, 157, 158, 159, 160, 161, 162, 163, 164, 165, 166, 167, 168, 169, 170, 171, 172, 173, 174, 175, 176, 177, 178, 179,
+    180, 181, 182, 183, 184, 185, 186, 187, 188, 189, 190, 191, 192, 193, 194, 195, 196, 197, 198, 199, 200, 201, 202, 203, 204, 205, 206, 207, 208, 209,
+    210, 211, 212, 213, 214, 215, 216, 217, 218, 219, 220, 221, 222, 223, 224, 225, 226, 227, 228, 229, 230, 231, 232, 233
+);
+
+unsafe extern "C" fn get_version(_env: *mut JniEnv) -> i32 {
+    JNI_VERSION
+}
+
+unsafe extern "C" fn find_class(env: *mut JniEnv, name: *const c_char) -> usize {
+    jni(env).attempt(0, |jni| {
+        let class = jni.env().find_class(&c_string(name))?;
+        let mirror = jni.vm().class_mirror(&class)?;
+        Ok(jni.new_local(mirror))
+    })
+}
+
+unsafe extern "C" fn get_superclass(env: *mut JniEnv, class: usize) -> usize {
+    jni(env).attempt(0, |jni| {
+        let class = jni.class_of(class)?;
+        match class.super_class.clone() {
+            Some(super_class) if !class.is_interface() => {
+                let mirror = jni.vm().class_mirror(&super_class)?;
+                Ok(jni.new_local(mirror))
+            },
+            _ => Ok(0),
+        }
+    })
+}
+
+unsafe extern "C" fn throw(env: *mut JniEnv, exception: usize) -> i32 {
+    jni(env).attempt(-1, |jni| {
+        jni.pending = Some(jni.non_null(exception)?);
+        Ok(0)
+    })
+}
+
+unsafe extern "C" fn throw_new(env: *mut JniEnv, class: usize, message: *const c_char) -> i32 {
+    jni(env).attempt(-1, |jni| {
+        let class = jni.class_of(class)?;
+        Err(jni.env().throw_new_with_message(&class.name, &c_string(message)))
+    })
+}
+
+unsafe extern "C" fn exception_occurred(env: *mut JniEnv) -> usize {
+    let jni = jni(env);
+    match jni.pending.clone() {
+        Some(exception) => jni.new_local(exception),
+        None => 0,
+    }
+}
+
+unsafe extern "C" fn exception_clear(env: *mut JniEnv) {
+    jni(env).pending = None;
+}
+
+unsafe extern "C" fn exception_check(env: *mut JniEnv) -> u8 {
+    jni(env).pending.is_some() as u8
+}
+
+unsafe extern "C" fn new_global_ref(env: *mut JniEnv, object: usize) -> usize {
+    let jni = jni(env);
+    match jni.object(object) {
+        Some(object) => ((jni.vm().new_global_ref(object).index() + 1) << 1) | 1,
+        None => 0,
+    }
+}
+
+unsafe extern "C" fn delete_global_ref(env: *mut JniEnv, global: usize) {
+    if global & 1 == 1 {
+        jni(env).vm().delete_global_ref(GlobalRef::from_index((global >> 1) - 1));
+    }
+}
+
+unsafe extern "C" fn delete_local_ref(env: *mut JniEnv, local: usize) {
+    let jni = jni(env);
+    if local != 0 && local & 1 == 0 {
+        if let Some(&local) = jni.locals.get((local >> 1) - 1) {
+            jni.vm().delete_local_ref(local);
+        }
+    }
+}
+
+unsafe extern "C" fn is_same_object(env: *mut JniEnv, left: usize, right: usize) -> u8 {
+    let jni = jni(env);
+    (jni.object(left) == jni.object(right)) as u8
+}
+
+unsafe extern "C" fn new_local_ref(env: *mut JniEnv, object: usize) -> usize {
+    let jni = jni(env);
+    match jni.object(object) {
+        Some(object) => jni.new_local(object),
+        None => 0,
+    }
+}
+
+unsafe extern "C" fn get_object_class(env: *mut JniEnv, object: usize) -> usize {
+    jni(env).attempt(0, |jni| {
+        let class = Rc::clone(jni.non_null(object)?.class());
+        let mirror = jni.vm().class_mirror(&class)?;
+        Ok(jni.new_local(mirror))
+    })
+}
+
+unsafe extern "C" fn is_instance_of(env: *mut JniEnv, object: usize, class: usize) -> u8 {
+    jni(env).attempt(0, |jni| {
+        let class = jni.class_of(class)?;
+        Ok(jni.object(object).is_none_or(|object| object.class().is_assignable_to(&class)) as u8)
+    })
+}
+
+unsafe extern "C" fn get_method_id(env: *mut JniEnv, class: usize, name: *const c_char, descriptor: *const c_char) -> usize {
+    jni(env).attempt(0, |jni| jni.member_id(class, name, descriptor, false, true))
+}
+
+unsafe extern "C" fn get_static_method_id(env: *mut JniEnv, class: usize, name: *const c_char, descriptor: *const c_char) -> usize {
+    jni(env).attempt(0, |jni| jni.member_id(class, name, descriptor, true, true))
+}
+
+unsafe extern "C" fn get_field_id(env: *mut JniEnv, class: usize, name: *const c_char, descriptor: *const c_char) -> usize {
+    jni(env).attempt(0, |jni| jni.member_id(class, name, descriptor, false, false))
+}
+
+unsafe extern "C" fn call_object_method_a(env: *mut JniEnv, object: usize, method: usize, args: *const JValue) -> usize {
+    jni(env).attempt(0, |jni| {
+        let result = jni.call(Some(object), method, args)?;
+        jni.jobject(&result.unwrap_or_else(Value::null))
+    })
+}
+
+unsafe extern "C" fn call_int_method_a(env: *mut JniEnv, object: usize, method: usize, args: *const JValue) -> i32 {
+    jni(env).attempt(0, |jni| match jni.call(Some(object), method, args)? {
+        Some(Value::Int(value)) => Ok(value),
+        other => Err(VmError::NativeSignature(format!("expected an int result, got {:?}", other))),
+    })
+}
+
+unsafe extern "C" fn call_void_method_a(env: *mut JniEnv, object: usize, method: usize, args: *const JValue) {
+    jni(env).attempt((), |jni| jni.call(Some(object), method, args).map(|_| ()))
+}
+
+unsafe extern "C" fn call_static_object_method_a(env: *mut JniEnv, _class: usize, method: usize, args: *const JValue) -> usize {
+    jni(env).attempt(0, |jni| {
+        let result = jni.call(None, method, args)?;
+        jni.jobject(&result.unwrap_or_else(Value::null))
+    })
+}
+
+unsafe extern "C" fn call_static_int_method_a(env: *mut JniEnv, _class: usize, method: usize, args: *const JValue) -> i32 {
+    jni(env).attempt(0, |jni| match jni.call(None, method, args)? {
+        Some(Value::Int(value)) => Ok(value),
+        other => Err(VmError::NativeSignature(format!("expected an int result, got {:?}", other))),
+    })
+}
+
+unsafe extern "C" fn call_static_void_method_a(env: *mut JniEnv, _class: usize, method: usize, args: *const JValue) {
+    jni(env).attempt((), |jni| jni.call(None, method, args).map(|_| ()))
+}
+
+unsafe extern "C" fn get_object_field(env: *mut JniEnv, object: usize, field: usize) -> usize {
+    jni(env).attempt(0, |jni| {
+        let (object, field) = (jni.non_null(object)?, jni.member(field)?);
+        let value = jni.env().get_field(&object, &field.name)?;
+        jni.jobject(&value)
+    })
+}
+
+unsafe extern "C" fn get_int_field(env: *mut JniEnv, object: usize, field: usize) -> i32 {
+    jni(env).attempt(0, |jni| {
+        let (object, field) = (jni.non_null(object)?, jni.member(field)?);
+        match jni.env().get_field(&object, &field.name)? {
+            Value::Int(value) => Ok(value),
+            other => Err(VmError::NativeSignature(format!("expected an int field, got {:?}", other))),
+        }
+    })
+}
+
+unsafe extern "C" fn set_object_field(env: *mut JniEnv, object: usize, field: usize, value: usize) {
+    jni(env).attempt((), |jni| {
+        let (object, field, value) = (jni.non_null(object)?, jni.member(field)?, jni.object(value));
+        jni.env().set_field(&object, &field.name, Value::Reference(value))
+    })
+}
+
+unsafe extern "C" fn set_int_field(env: *mut JniEnv, object: usize, field: usize, value: i32) {
+    jni(env).attempt((), |jni| {
+        let (object, field) = (jni.non_null(object)?, jni.member(field)?);
+        jni.env().set_field(&object, &field.name, Value::Int(value))
+    })
+}
+
+unsafe extern "C" fn new_string_utf(env: *mut JniEnv, chars: *const c_char) -> usize {
+    jni(env).attempt(0, |jni| {
+        let string = jni.env().new_string(&c_string(chars))?;
+        Ok(jni.new_local(string))
+    })
+}
+
+unsafe extern "C" fn get_string_utf_length(env: *mut JniEnv, string: usize) -> i32 {
+    jni(env).attempt(0, |jni| {
+        let string = jni.non_null(string)?;
+        Ok(jni.env().read_string(&string)?.len() as i32)
+    })
+}
+
+// The characters are copied into a buffer that release_string_utf_chars frees.
+unsafe extern "C" fn get_string_utf_chars(env: *mut JniEnv, string: usize, is_copy: *mut u8) -> *const c_char {
+    jni(env).attempt(std::ptr::null(), |jni| {
+        let string = jni.non_null(string)?;
+        let text = jni.env().read_string(&string)?;
+        if !is_copy.is_null() {
+            *is_copy = 1;
+        }
+        Ok(CString::new(text.replace('\0', "\u{FFFD}")).unwrap_or_default().into_raw() as *const c_char)
+    })
+}
+
+unsafe extern "C" fn release_string_utf_chars(_env: *mut JniEnv, _string: usize, chars: *const c_char) {
+    if !chars.is_null() {
+        drop(CString::from_raw(chars as *mut c_char));
+    }
+}
+
+unsafe extern "C" fn get_array_length(env: *mut JniEnv, array: usize) -> i32 {
+    jni(env).attempt(0, |jni| {
+        let array = jni.non_null(array)?;
+        Ok(jni.env().array_length(&array)? as i32)
+    })
+}
+
+unsafe extern "C" fn new_int_array(env: *mut JniEnv, length: i32) -> usize {
+    jni(env).attempt(0, |jni| {
+        if length < 0 {
+            return Err(jni.vm().throw_new_with_message("java/lang/NegativeArraySizeException", &length.to_string()));
+        }
+        let array = jni.env().new_array("[I", length as usize)?;
+        Ok(jni.new_local(array))
+    })
+}
+
+unsafe extern "C" fn get_int_array_region(env: *mut JniEnv, array: usize, start: i32, length: i32, buffer: *mut i32) {
+    jni(env).attempt((), |jni| {
+        let array = jni.non_null(array)?;
+        let range = jni.region(&array, start, length)?;
+        if let Some(ints) = array.fields.borrow().ints() {
+            std::ptr::copy_nonoverlapping(ints[range].as_ptr(), buffer, length as usize);
+        }
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn set_int_array_region(env: *mut JniEnv, array: usize, start: i32, length: i32, buffer: *const i32) {
+    jni(env).attempt((), |jni| {
+        let array = jni.non_null(array)?;
+        let range = jni.region(&array, start, length)?;
+        if let Some(ints) = array.fields.borrow_mut().ints_mut() {
+            std::ptr::copy_nonoverlapping(buffer, ints[range].as_mut_ptr(), length as usize);
+        }
+        Ok(())
+    })
+}
+
+impl JniEnv {
+    // The elements of an int array that a region covers, throwing
+    // ArrayIndexOutOfBoundsException if they aren't all in the array.
+    fn region(&mut self, array: &ObjectRef, start: i32, length: i32) -> Result<std::ops::Range<usize>, VmError> {
+        if array.fields.borrow().ints().is_none() {
+            return Err(VmError::NativeSignature(format!("expected an int array, got {}", array.class().name)));
+        }
+        let array_length = self.env().array_length(array)?;
+        if start < 0 || length < 0 || start as usize + length as usize > array_length {
+            let message = format!("Array region {}..{} out of bounds for length {}", start, start as i64 + length as i64, array_length);
+            return Err(self.vm().throw_new_with_message("java/lang/ArrayIndexOutOfBoundsException", &message));
+        }
+        Ok(start as usize..(start + length) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use std::process::Command;
+
+    // Builds testdata/native/jni.c into libjnitest, once per test run, returning its directory.
+    fn library_dir() -> &'static Path {
+        static DIR: OnceLock<PathBuf> = OnceLock::new();
+        DIR.get_or_init(|| {
+            let dir = std::env::temp_dir().join(format!("joyvm-jni-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/native/jni.c");
+            let status = Command::new("cc").args(["-shared", "-fPIC", "-o"]).arg(dir.join(crate::vm::map_library_name("jnitest"))).arg(source).status().unwrap();
+            assert!(status.success(), "Couldn't compile jni.c");
+            dir
+        })
+    }
+
+    // Has Jni load the compiled test library.
+    fn load_jnitest(vm: &mut Vm) {
+        vm.set_library_path(vec![library_dir().to_path_buf()]);
+        let name = vm.new_string("jnitest").unwrap();
+        assert_eq!(Ok(None), vm.invoke_static("Jni", "load", "(Ljava/lang/String;)V", vec![Value::Reference(Some(name))]));
+    }
+
+    #[test]
+    fn test_names_are_mangled() {
+        assert_eq!("Java_Jni_add", short_name("Jni", "add"));
+        assert_eq!("Java_com_example_My_1Class_run", short_name("com/example/My_Class", "run"));
+        assert_eq!("Java_Jni_overloaded__ILjava_lang_String_2_3J", long_name("Jni", "overloaded", "(ILjava/lang/String;[J)V"));
+        assert_eq!("Java_Jni_none__", long_name("Jni", "none", "()I"));
+        assert_eq!("Java_Jni_caf_000e9", short_name("Jni", "caf\u{e9}"));
+    }
+
+    #[test]
+    fn test_arguments_and_results_cross_into_c() {
+        let mut vm = vm_with(&[fixture!("Jni")]);
+        load_jnitest(&mut vm);
+        assert_eq!(Ok(Some(Value::Int(5))), vm.invoke_static("Jni", "add", "(II)I", vec![Value::Int(2), Value::Int(3)]));
+        let args = vec![Value::Int(1), Value::Double(0.5), Value::Float(0.25), Value::Long(100), Value::Int(1)];
+        assert_eq!(Ok(Some(Value::Double(-101.75))), vm.invoke_static("Jni", "mix", "(IDFJZ)D", args));
+
+        let name = vm.new_string("joyvm").unwrap();
+        match vm.invoke_static("Jni", "greet", "(Ljava/lang/String;)Ljava/lang/String;", vec![Value::Reference(Some(name))]) {
+            Ok(Some(Value::Reference(Some(greeting)))) => assert_eq!("hello, joyvm (5 bytes)", vm.read_string(&greeting).unwrap()),
+            other => panic!("Expected a greeting; got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_natives_use_arrays_and_fields() {
+        let mut vm = vm_with(&[fixture!("Jni")]);
+        load_jnitest(&mut vm);
+        assert_eq!(Ok(Some(Value::Int(30))), vm.invoke_static("Jni", "sumOfSquares", "(I)I", vec![Value::Int(5)]));
+        assert_eq!(Ok(Some(Value::Int(5))), vm.invoke_static("Jni", "bumpTwice", "()I", vec![]));
+    }
+
+    #[test]
+    fn test_natives_call_back_into_java() {
+        let mut vm = vm_with(&[fixture!("Jni")]);
+        load_jnitest(&mut vm);
+        assert_eq!(Ok(Some(Value::Int(25))), vm.invoke_static("Jni", "callBackWith", "(I)I", vec![Value::Int(5)]));
+    }
+
+    #[test]
+    fn test_exceptions_thrown_by_natives_can_be_caught() {
+        let mut vm = vm_with(&[fixture!("Jni")]);
+        load_jnitest(&mut vm);
+        match vm.invoke_static("Jni", "catchFailure", "()Ljava/lang/String;", vec![]) {
+            Ok(Some(Value::Reference(Some(message)))) => assert_eq!("thrown from C", vm.read_string(&message).unwrap()),
+            other => panic!("Expected the exception's message; got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_overloads_are_found_by_long_name() {
+        let mut vm = vm_with(&[fixture!("Jni")]);
+        load_jnitest(&mut vm);
+        assert_eq!(Ok(Some(Value::Int(1))), vm.invoke_static("Jni", "overloaded", "(I)I", vec![Value::Int(0)]));
+        assert_eq!(Ok(Some(Value::Int(2))), vm.invoke_static("Jni", "overloaded", "(J)I", vec![Value::Long(0)]));
+    }
+
+    #[test]
+    fn test_natives_missing_from_libraries_are_unsupported() {
+        let mut vm = vm_with(&[fixture!("Jni")]);
+        load_jnitest(&mut vm);
+        assert!(matches!(vm.invoke_static("Jni", "missing", "()I", vec![]), Err(VmError::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_libraries_are_loaded_once() {
+        let mut vm = vm_with(&[fixture!("Jni")]);
+        load_jnitest(&mut vm);
+        let path = library_dir().join(crate::vm::map_library_name("jnitest"));
+        vm.load_library_file(&path).unwrap();
+        vm.load_library("jnitest").unwrap();
+        assert_eq!(vec![path.as_path()], vm.libraries().paths().collect::<Vec<_>>());
+    }
+}
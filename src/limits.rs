@@ -0,0 +1,258 @@
+use crate::heap::Object;
+use crate::layout;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{error, fmt};
+
+// Limits on the resources a program may use, for running untrusted code. Set them with
+// Vm::set_limits; any that are None are unlimited.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Limits {
+    pub max_instructions: Option<u64>,
+    pub max_duration: Option<Duration>, // Wall-clock time, measured from when the limits are set.
+    pub max_allocated_bytes: Option<u64>, // The total estimated size of every object allocated, plus the direct memory in use.
+    pub max_loaded_classes: Option<usize>, // Including bootstrap classes, once they're used.
+}
+
+// The limit that stopped a program, holding the value it was set to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LimitViolation {
+    Instructions(u64),
+    Duration(Duration),
+    AllocatedBytes(u64),
+    LoadedClasses(usize),
+}
+
+impl fmt::Display for LimitViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LimitViolation::Instructions(limit) => write!(f, "Executed more than {} instructions", limit),
+            LimitViolation::Duration(limit) => write!(f, "Ran for longer than {:?}", limit),
+            LimitViolation::AllocatedBytes(limit) => write!(f, "Allocated more than {} bytes", limit),
+            LimitViolation::LoadedClasses(limit) => write!(f, "Loaded more than {} classes", limit),
+        }
+    }
+}
+
+impl error::Error for LimitViolation {
+    fn description(&self) -> &str {
+        match *self {
+            LimitViolation::Instructions(_) => "Instruction limit exceeded",
+            LimitViolation::Duration(_) => "Time limit exceeded",
+            LimitViolation::AllocatedBytes(_) => "Allocation limit exceeded",
+            LimitViolation::LoadedClasses(_) => "Class loading limit exceeded",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        None
+    }
+}
+
+// Reading the clock on every instruction would be slow, so it's only checked this often.
+const CHECK_INTERVAL: u64 = 1024;
+
+// Tracks resource usage against the limits. Counting an instruction is a single comparison in the
+// common case; everything else is checked on a slow path that runs every CHECK_INTERVAL
+// instructions, or on the next instruction after allocating past the limit.
+pub struct Budget {
+    limits: Limits,
+    deadline: Option<Instant>,
+    instructions: u64,
+    allocated_bytes: u64,
+    next_check: u64,
+    timer: Option<Arc<AtomicBool>>, // Set on each slow path, which the cooperative scheduler uses as its timeslice.
+}
+
+impl Budget {
+    pub fn new(limits: Limits) -> Budget {
+        let mut budget = Budget {
+            deadline: limits.max_duration.map(|duration| Instant::now() + duration),
+            limits,
+            instructions: 0,
+            allocated_bytes: 0,
+            next_check: 0,
+            timer: None,
+        };
+        budget.schedule_check();
+        budget
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocated_bytes
+    }
+
+    pub fn set_timer(&mut self, timer: Option<Arc<AtomicBool>>) {
+        self.timer = timer;
+    }
+
+    pub fn timer(&self) -> Option<&Arc<AtomicBool>> {
+        self.timer.as_ref()
+    }
+
+    #[inline]
+    pub fn count_instruction(&mut self) -> Result<(), LimitViolation> {
+        self.instructions += 1;
+        if self.instructions < self.next_check {
+            return Ok(());
+        }
+        self.check()
+    }
+
+    pub fn record_allocation(&mut self, bytes: u64) {
+        self.allocated_bytes += bytes;
+        if self.limits.max_allocated_bytes.is_some_and(|limit| self.allocated_bytes > limit) {
+            self.next_check = 0;
+        }
+    }
+
+    // Credits back memory that was counted by record_allocation, for direct memory that's been
+    // freed. Heap objects aren't credited when they're collected.
+    pub fn release_allocation(&mut self, bytes: u64) {
+        self.allocated_bytes = self.allocated_bytes.saturating_sub(bytes);
+    }
+
+    // Fails if the allocation limit has been exceeded, for callers that can report it at once.
+    pub fn check_allocation(&self) -> Result<(), LimitViolation> {
+        match self.limits.max_allocated_bytes {
+            Some(limit) if self.allocated_bytes > limit => Err(LimitViolation::AllocatedBytes(limit)),
+            _ => Ok(()),
+        }
+    }
+
+    // Fails if loading another class would take the number loaded over the limit.
+    pub fn check_class_load(&self, loaded: usize) -> Result<(), LimitViolation> {
+        match self.limits.max_loaded_classes {
+            Some(limit) if loaded >= limit => Err(LimitViolation::LoadedClasses(limit)),
+            _ => Ok(()),
+        }
+    }
+
+    fn check(&mut self) -> Result<(), LimitViolation> {
+        if let Some(limit) = self.limits.max_instructions.filter(|&limit| self.instructions > limit) {
+            return Err(LimitViolation::Instructions(limit));
+        }
+        self.check_allocation()?;
+        if let (Some(deadline), Some(limit)) = (self.deadline, self.limits.max_duration) {
+            if Instant::now() >= deadline {
+                return Err(LimitViolation::Duration(limit));
+            }
+        }
+
+        if let Some(ref timer) = self.timer {
+            timer.store(true, Ordering::Relaxed);
+        }
+        self.schedule_check();
+        Ok(())
+    }
+
+    fn schedule_check(&mut self) {
+        let next_check = self.instructions + CHECK_INTERVAL;
+        self.next_check = match self.limits.max_instructions {
+            Some(limit) => next_check.min(limit.saturating_add(1)),
+            None => next_check,
+        };
+    }
+}
+
+impl Default for Budget {
+    fn default() -> Budget {
+        Budget::new(Limits::default())
+    }
+}
+
+// A rough estimate of how much memory an object would take up in HotSpot: a 16 byte header, and
+// then the fields as laid out by the class, or the array elements at their natural sizes.
+pub fn estimated_size(object: &Object) -> u64 {
+    if object.is_array() {
+        let elements = object.fields.borrow();
+        return (layout::HEADER_SIZE as u64) + elements.element_size() * elements.len() as u64;
+    }
+    object.class().layout().instance_size() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instruction_limit() {
+        let mut budget = Budget::new(Limits {max_instructions: Some(3), ..Limits::default()});
+        for _ in 0..3 {
+            assert_eq!(Ok(()), budget.count_instruction());
+        }
+        assert_eq!(Err(LimitViolation::Instructions(3)), budget.count_instruction());
+    }
+
+    #[test]
+    fn test_no_limits() {
+        let mut budget = Budget::default();
+        for _ in 0..CHECK_INTERVAL * 3 {
+            assert_eq!(Ok(()), budget.count_instruction());
+        }
+        budget.record_allocation(u64::MAX / 2);
+        assert_eq!(Ok(()), budget.count_instruction());
+        assert_eq!(CHECK_INTERVAL * 3 + 1, budget.instructions());
+    }
+
+    #[test]
+    fn test_allocation_limit_is_checked_on_next_instruction() {
+        let mut budget = Budget::new(Limits {max_allocated_bytes: Some(100), ..Limits::default()});
+        budget.record_allocation(100);
+        assert_eq!(Ok(()), budget.count_instruction());
+        budget.record_allocation(1);
+        assert_eq!(Err(LimitViolation::AllocatedBytes(100)), budget.check_allocation());
+        assert_eq!(Err(LimitViolation::AllocatedBytes(100)), budget.count_instruction());
+    }
+
+    #[test]
+    fn test_released_allocations_are_credited_back() {
+        let mut budget = Budget::new(Limits {max_allocated_bytes: Some(100), ..Limits::default()});
+        budget.record_allocation(150);
+        budget.release_allocation(100);
+        assert_eq!(50, budget.allocated_bytes());
+        assert_eq!(Ok(()), budget.check_allocation());
+        budget.release_allocation(100);
+        assert_eq!(0, budget.allocated_bytes());
+    }
+
+    #[test]
+    fn test_duration_limit() {
+        let mut budget = Budget::new(Limits {max_duration: Some(Duration::from_millis(0)), ..Limits::default()});
+        let result = (0..CHECK_INTERVAL * 2).map(|_| budget.count_instruction()).find(Result::is_err);
+        assert_eq!(Some(Err(LimitViolation::Duration(Duration::from_millis(0)))), result);
+    }
+
+    #[test]
+    fn test_class_load_limit() {
+        let budget = Budget::new(Limits {max_loaded_classes: Some(2), ..Limits::default()});
+        assert_eq!(Ok(()), budget.check_class_load(1));
+        assert_eq!(Err(LimitViolation::LoadedClasses(2)), budget.check_class_load(2));
+    }
+
+    #[test]
+    fn test_timer_is_set_on_each_slow_path() {
+        let mut budget = Budget::default();
+        let timer = Arc::new(AtomicBool::new(false));
+        budget.set_timer(Some(Arc::clone(&timer)));
+        for _ in 0..CHECK_INTERVAL - 1 {
+            budget.count_instruction().unwrap();
+        }
+        assert!(!timer.load(Ordering::Relaxed));
+        budget.count_instruction().unwrap();
+        assert!(timer.swap(false, Ordering::Relaxed));
+        for _ in 0..CHECK_INTERVAL {
+            budget.count_instruction().unwrap();
+        }
+        assert!(timer.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,336 @@
+use crate::classes::Class;
+use crate::classloader;
+use crate::classpath::{ClassSource, Classpath};
+use crate::heap::{ObjectRef, WeakObjectRef};
+use crate::runtime::{RuntimeClass, Value};
+use crate::vm::{self, Vm, VmError};
+use std::collections::HashMap;
+use std::path::Path;
+
+// Identifies a class loader. A class is identified by its name together with the loader that
+// defined it, so the same name may be loaded by different loaders as different classes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LoaderId(usize);
+
+impl LoaderId {
+    // The built-in loaders, as in the JDK since Java 9. The bootstrap loader defines the platform
+    // classes joyvm provides; the platform loader delegates to it; the application loader
+    // delegates to the platform loader and reads the classpath.
+    pub const BOOTSTRAP: LoaderId = LoaderId(0);
+    pub const PLATFORM: LoaderId = LoaderId(1);
+    pub const APPLICATION: LoaderId = LoaderId(2);
+
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+// Where one loader finds the classes it defines itself: those added to it directly, then its
+// classpath. Loading goes to the parent first, per the ClassLoader.loadClass contract, so a
+// loader only defines classes its ancestors can't find.
+pub struct ClassLoader {
+    name: String,
+    parent: Option<LoaderId>,
+    classpath: Classpath,
+    available: HashMap<String, Vec<u8>>, // Added directly but not yet loaded.
+    archived: HashMap<String, (Class, usize)>, // Mapped from a class data archive, with their classpath entries.
+    object: Option<WeakObjectRef>, // The java.lang.ClassLoader, for loaders created by Java code.
+    packages: HashMap<String, Package>, // The packages of the classes this loader has defined.
+    verify: Option<bool>, // Whether its classes are verified, if not as the VM's mode says.
+}
+
+impl ClassLoader {
+    fn new(name: &str, parent: Option<LoaderId>) -> ClassLoader {
+        ClassLoader {name: name.to_string(), parent, classpath: Classpath::new(), available: HashMap::new(), archived: HashMap::new(), object: None, packages: HashMap::new(), verify: None}
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn parent(&self) -> Option<LoaderId> {
+        self.parent
+    }
+
+    pub fn classpath(&self) -> &Classpath {
+        &self.classpath
+    }
+
+    pub fn classpath_mut(&mut self) -> &mut Classpath {
+        &mut self.classpath
+    }
+
+    pub fn set_classpath(&mut self, classpath: Classpath) {
+        self.classpath = classpath;
+    }
+
+    // Overrides the VM's verification mode for the classes this loader defines from now on, or
+    // goes back to following it if None.
+    pub fn set_verify(&mut self, verify: Option<bool>) {
+        self.verify = verify;
+    }
+
+    pub fn verify(&self) -> Option<bool> {
+        self.verify
+    }
+
+    pub fn object(&self) -> Option<&WeakObjectRef> {
+        self.object.as_ref()
+    }
+
+    // Loaders created by Java code can be unloaded along with their classes once nothing refers
+    // to the loader object, its classes or their instances. The others last as long as the VM.
+    pub fn is_unloadable(&self) -> bool {
+        self.object.is_some()
+    }
+
+    // Makes a class available to this loader, returning its binary name. The class file is kept
+    // as it is until the class is loaded, so that transformers see it then.
+    pub fn add_class(&mut self, bytes: &[u8]) -> Result<String, VmError> {
+        let name = classloader::load_class(bytes)?.name()?.to_string();
+        self.available.insert(name.clone(), bytes.to_vec());
+        Ok(name)
+    }
+
+    // Takes the class file of the named class from those added directly, or failing that reads
+    // it from the classpath, along with the index of the classpath entry it came from. Returns
+    // None if this loader can't find it.
+    pub fn find_class(&mut self, name: &str) -> Result<Option<FoundClass>, VmError> {
+        if let Some(bytes) = self.available.remove(name) {
+            return Ok(Some(FoundClass {bytes, source: None}));
+        }
+        Ok(self.classpath.find_class_in_source(name)?.map(|(source, bytes)| FoundClass {bytes, source: Some(source)}))
+    }
+
+    // Makes already parsed classes available, as if they'd been read from the given entries of
+    // the classpath. See archive::ClassArchive::map.
+    pub fn map_archived(&mut self, classes: Vec<(String, Class, usize)>) {
+        self.archived.extend(classes.into_iter().map(|(name, class, source)| (name, (class, source))));
+    }
+
+    // Takes the named class from those mapped from an archive, with the index of the classpath
+    // entry it stands for.
+    pub fn take_archived(&mut self, name: &str) -> Option<(Class, usize)> {
+        self.archived.remove(name)
+    }
+
+    pub fn package(&self, name: &str) -> Option<&Package> {
+        self.packages.get(name)
+    }
+
+    pub fn packages(&self) -> impl Iterator<Item = &Package> {
+        self.packages.values()
+    }
+
+    // Defines the package of a class this loader is about to define, if it's the first of its
+    // package, taking the package's attributes from the manifest of the JAR the class came from.
+    // As in URLClassLoader, a sealed package only takes classes from the JAR that sealed it, and
+    // a package can't be sealed by a JAR once classes have come from elsewhere.
+    pub fn define_package(&mut self, class_name: &str, source: Option<usize>) -> Result<(), VmError> {
+        let name = package_name(class_name);
+        let manifest = source.and_then(|index| self.classpath.source(index)).and_then(ClassSource::manifest);
+        let attribute = |attribute: &str| manifest.and_then(|manifest| manifest.package_attribute(name, attribute)).map(str::to_string);
+        let sealed = attribute("Sealed").is_some_and(|sealed| sealed.eq_ignore_ascii_case("true"));
+        match self.packages.get(name) {
+            Some(package) if package.sealed && package.source != source => {
+                Err(VmError::SealingViolation(format!("sealing violation: package {} is sealed", name.replace('/', "."))))
+            },
+            Some(package) if !package.sealed && sealed => {
+                Err(VmError::SealingViolation(format!("sealing violation: can't seal package {}: already loaded", name.replace('/', "."))))
+            },
+            Some(_) => Ok(()),
+            None => {
+                let package = Package {
+                    name: name.to_string(),
+                    specification_title: attribute("Specification-Title"),
+                    specification_version: attribute("Specification-Version"),
+                    specification_vendor: attribute("Specification-Vendor"),
+                    implementation_title: attribute("Implementation-Title"),
+                    implementation_version: attribute("Implementation-Version"),
+                    implementation_vendor: attribute("Implementation-Vendor"),
+                    sealed,
+                    source,
+                };
+                self.packages.insert(name.to_string(), package);
+                Ok(())
+            },
+        }
+    }
+}
+
+// A callback for Vm::set_class_load_listener, given the class and where it was loaded from.
+pub type ClassLoadListener = Box<dyn FnMut(&RuntimeClass, Option<&Path>)>;
+
+// A class file a loader found, and the index of the classpath entry it came from, if any.
+pub struct FoundClass {
+    pub bytes: Vec<u8>,
+    pub source: Option<usize>,
+}
+
+// A runtime package: the classes one loader defines in a package, described by the attributes of
+// the JAR manifest the first of them came from, as java.lang.Package reports them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Package {
+    pub name: String, // In internal form, like com/example, or empty for the unnamed package.
+    pub specification_title: Option<String>,
+    pub specification_version: Option<String>,
+    pub specification_vendor: Option<String>,
+    pub implementation_title: Option<String>,
+    pub implementation_version: Option<String>,
+    pub implementation_vendor: Option<String>,
+    pub sealed: bool,
+    pub source: Option<usize>, // The classpath entry the first class came from.
+}
+
+// The package of a class, given its binary name.
+pub fn package_name(class_name: &str) -> &str {
+    class_name.rfind('/').map_or("", |end| &class_name[..end])
+}
+
+// Every loader the VM knows about, starting with the built-in ones. Ids aren't reused after a
+// loader is unloaded.
+pub struct ClassLoaders {
+    loaders: Vec<Option<ClassLoader>>,
+}
+
+impl Default for ClassLoaders {
+    fn default() -> ClassLoaders {
+        ClassLoaders::new()
+    }
+}
+
+impl ClassLoaders {
+    pub fn new() -> ClassLoaders {
+        ClassLoaders {
+            loaders: vec![
+                Some(ClassLoader::new("bootstrap", None)),
+                Some(ClassLoader::new("platform", Some(LoaderId::BOOTSTRAP))),
+                Some(ClassLoader::new("app", Some(LoaderId::PLATFORM))),
+            ],
+        }
+    }
+
+    // Creates a user-defined loader that delegates to the given parent.
+    pub fn define(&mut self, name: &str, parent: LoaderId) -> LoaderId {
+        self.loaders.push(Some(ClassLoader::new(name, Some(parent))));
+        LoaderId(self.loaders.len() - 1)
+    }
+
+    pub fn get(&self, loader: LoaderId) -> Option<&ClassLoader> {
+        self.loaders.get(loader.0)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, loader: LoaderId) -> Option<&mut ClassLoader> {
+        self.loaders.get_mut(loader.0)?.as_mut()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (LoaderId, &ClassLoader)> {
+        self.loaders.iter().enumerate().filter_map(|(index, loader)| Some((LoaderId(index), loader.as_ref()?)))
+    }
+
+    // Forgets an unloaded loader, returning it.
+    pub fn remove(&mut self, loader: LoaderId) -> Option<ClassLoader> {
+        self.loaders.get_mut(loader.0)?.take()
+    }
+
+    // The number of loaders that haven't been unloaded.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The loader followed by its ancestors, ending with the bootstrap loader.
+    pub fn delegation_chain(&self, loader: LoaderId) -> Vec<LoaderId> {
+        let mut chain = vec![];
+        let mut next = Some(loader);
+        while let Some(loader) = next {
+            chain.push(loader);
+            next = self.get(loader).and_then(ClassLoader::parent);
+        }
+        chain
+    }
+}
+
+// The natives of java.lang.ClassLoader. Each ClassLoader object created by Java code is backed by
+// a loader of the VM's own, whose id it keeps in its loaderId field.
+pub fn invoke_native(vm: &mut Vm, name: &str, descriptor: &str, args: &[Value]) -> Result<Option<Value>, VmError> {
+    match (name, descriptor, args) {
+        ("register", "(I)I", &[Value::Reference(Some(ref this)), Value::Int(parent)]) => {
+            let parent = LoaderId(parent as usize);
+            vm.class_loader_mut(parent)?;
+            let loader = vm.new_class_loader(&this.class().java_name(), parent);
+            vm.class_loader_mut(loader)?.object = Some(this.downgrade());
+            Ok(Some(Value::Int(loader.index() as i32)))
+        },
+        ("defineClass", "(Ljava/lang/String;[BII)Ljava/lang/Class;", &[Value::Reference(Some(ref this)), Value::Reference(ref name), Value::Reference(ref bytes), Value::Int(offset), Value::Int(length)]) => {
+            let loader = loader_id(this)?;
+            let bytes = match *bytes {
+                Some(ref bytes) => bytes,
+                None => return Err(vm.throw_new("java/lang/NullPointerException")),
+            };
+            let data = {
+                let storage = bytes.fields.borrow();
+                let all = storage.bytes().ok_or_else(|| VmError::InvalidBytecode(format!("Expected a byte array; got {}", bytes.class().name)))?;
+                if offset < 0 || length < 0 || offset as usize + length as usize > all.len() {
+                    None
+                } else {
+                    Some(all[offset as usize..(offset + length) as usize].iter().map(|&byte| byte as u8).collect::<Vec<u8>>())
+                }
+            };
+            let data = match data {
+                Some(data) => data,
+                None => return Err(vm.throw_new("java/lang/IndexOutOfBoundsException")),
+            };
+            let name = match *name {
+                Some(ref name) => Some(vm.read_string(name)?.replace('.', "/")),
+                None => None,
+            };
+
+            // Problems with the class itself are thrown to the caller, as the JDK does.
+            match vm.define_class(loader, name.as_deref(), &data) {
+                Ok(class) => Ok(Some(Value::Reference(Some(vm.class_mirror(&class)?)))),
+                Err(err @ VmError::ConstantLookup(_)) => Err(vm.throw_new_with_message("java/lang/ClassFormatError", &err.to_string())),
+                Err(err) => match err.linkage_error() {
+                    Some((error_class, message)) => Err(vm.throw_new_with_message(error_class, &message)),
+                    None => Err(err),
+                },
+            }
+        },
+        _ => Err(VmError::Unsupported(format!("native method java/lang/ClassLoader.{}{}", name, descriptor))),
+    }
+}
+
+// The VM's loader behind a java.lang.ClassLoader object.
+pub fn loader_id(loader: &ObjectRef) -> Result<LoaderId, VmError> {
+    match vm::get_field(loader, "loaderId")? {
+        Value::Int(id) => Ok(LoaderId(id as usize)),
+        other => Err(VmError::InvalidBytecode(format!("ClassLoader has invalid loaderId {:?}", other))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+
+    #[test]
+    fn test_builtin_loaders_delegate_to_bootstrap() {
+        let mut loaders = ClassLoaders::new();
+        assert_eq!(vec![LoaderId::APPLICATION, LoaderId::PLATFORM, LoaderId::BOOTSTRAP], loaders.delegation_chain(LoaderId::APPLICATION));
+        let custom = loaders.define("custom", LoaderId::APPLICATION);
+        assert_eq!(4, loaders.delegation_chain(custom).len());
+        assert_eq!("custom", loaders.get(custom).unwrap().name());
+    }
+
+    #[test]
+    fn test_classes_are_found_once() {
+        let mut loaders = ClassLoaders::new();
+        let loader = loaders.get_mut(LoaderId::APPLICATION).unwrap();
+        assert_eq!(Ok("Completes".to_string()), loader.add_class(fixture!("Completes")));
+        assert_eq!(None, loader.find_class("Completes").unwrap().unwrap().source);
+        assert!(loader.find_class("Completes").unwrap().is_none());
+    }
+}
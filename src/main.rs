@@ -1,8 +0,0 @@
-#[macro_use] extern crate bitflags;
-
-mod classes;
-mod classloader;
-
-fn main() {
-    println!("Hello, world!");
-}
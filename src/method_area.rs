@@ -0,0 +1,93 @@
+use crate::loaders::LoaderId;
+use crate::runtime::RuntimeClass;
+use crate::symbols::Symbol;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// The classes the VM has loaded, by loader and name. A class is recorded under the loader that
+// defined it, and under each loader that initiated loading it and delegated to that one, so a
+// later lookup through any of them finds the same class without going to the loaders again.
+#[derive(Default)]
+pub struct MethodArea {
+    namespaces: Vec<HashMap<Symbol, Rc<RuntimeClass>>>, // Indexed by loader.
+}
+
+impl MethodArea {
+    pub fn new() -> MethodArea {
+        MethodArea {namespaces: vec![]}
+    }
+
+    pub fn get(&self, loader: LoaderId, name: &str) -> Option<&Rc<RuntimeClass>> {
+        self.namespaces.get(loader.index())?.get(name)
+    }
+
+    // Records that the loader has loaded the class, either by defining it or by delegating.
+    pub fn record(&mut self, loader: LoaderId, class: &Rc<RuntimeClass>) {
+        if self.namespaces.len() <= loader.index() {
+            self.namespaces.resize_with(loader.index() + 1, HashMap::new);
+        }
+        self.namespaces[loader.index()].insert(class.name.clone(), Rc::clone(class));
+    }
+
+    // Every loaded class once, under its defining loader.
+    pub fn classes(&self) -> impl Iterator<Item = &Rc<RuntimeClass>> {
+        self.namespaces.iter().enumerate().flat_map(|(index, namespace)| {
+            namespace.values().filter(move |class| class.loader.index() == index)
+        })
+    }
+
+    // The classes the loader has loaded, including those it delegated to its ancestors.
+    pub fn classes_loaded_by(&self, loader: LoaderId) -> impl Iterator<Item = &Rc<RuntimeClass>> {
+        self.namespaces.get(loader.index()).into_iter().flat_map(HashMap::values)
+    }
+
+    // Forgets the classes an unloaded loader defined, wherever they're recorded, and returns
+    // them.
+    pub fn remove_loader(&mut self, loader: LoaderId) -> Vec<Rc<RuntimeClass>> {
+        let defined = self.classes_loaded_by(loader).filter(|class| class.loader == loader).cloned().collect();
+        if let Some(namespace) = self.namespaces.get_mut(loader.index()) {
+            namespace.clear();
+        }
+        for namespace in &mut self.namespaces {
+            namespace.retain(|_, class| class.loader != loader);
+        }
+        defined
+    }
+
+    pub fn len(&self) -> usize {
+        self.classes().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classloader;
+    use crate::symbols::SymbolTable;
+
+    fn class(loader: LoaderId) -> Rc<RuntimeClass> {
+        let class = classloader::load_class(include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/bootstrap/java/lang/Object.class"))).unwrap();
+        let mut class = RuntimeClass::new(class, None, vec![], &mut SymbolTable::new()).unwrap();
+        class.loader = loader;
+        Rc::new(class)
+    }
+
+    #[test]
+    fn test_classes_are_counted_under_their_defining_loader() {
+        let mut area = MethodArea::new();
+        let object = class(LoaderId::BOOTSTRAP);
+        area.record(LoaderId::BOOTSTRAP, &object);
+        area.record(LoaderId::APPLICATION, &object);
+        assert_eq!(1, area.len());
+        assert!(Rc::ptr_eq(&object, area.get(LoaderId::APPLICATION, "java/lang/Object").unwrap()));
+        assert!(area.get(LoaderId::PLATFORM, "java/lang/Object").is_none());
+        assert_eq!(1, area.classes_loaded_by(LoaderId::APPLICATION).count());
+
+        area.record(LoaderId::APPLICATION, &class(LoaderId::APPLICATION));
+        assert_eq!(2, area.len());
+    }
+}
@@ -0,0 +1,663 @@
+use crate::access;
+use crate::classes::*;
+use crate::descriptor::{FieldType, MethodDescriptor};
+use crate::heap::ObjectRef;
+use crate::interpreter;
+use crate::reflection;
+use crate::resolve;
+use crate::runtime::*;
+use crate::vm::{self, Vm, VmError};
+use std::rc::Rc;
+
+// The runtime half of java.lang.invoke. A MethodHandle records one of the reference kinds below,
+// the class and name of its member, and its MethodType; calls through it look the member up
+// again each time, as the interpreter does for an uncached call. invokeExact and invoke are
+// signature polymorphic (JVM spec 2.9.3), so the interpreter passes them the descriptor of the
+// call site rather than calling them as natives.
+
+// The reference kinds of JVM spec 5.4.3.5, as MethodHandles.Lookup numbers them too.
+pub const REF_GET_FIELD: i32 = 1;
+pub const REF_GET_STATIC: i32 = 2;
+pub const REF_PUT_FIELD: i32 = 3;
+pub const REF_PUT_STATIC: i32 = 4;
+pub const REF_INVOKE_VIRTUAL: i32 = 5;
+pub const REF_INVOKE_STATIC: i32 = 6;
+pub const REF_INVOKE_SPECIAL: i32 = 7;
+pub const REF_NEW_INVOKE_SPECIAL: i32 = 8;
+pub const REF_INVOKE_INTERFACE: i32 = 9;
+
+const METHOD_HANDLE: &str = "java/lang/invoke/MethodHandle";
+const POLYMORPHIC_DESCRIPTOR: &str = "([Ljava/lang/Object;)Ljava/lang/Object;";
+
+pub fn invoke_native(vm: &mut Vm, class: &str, name: &str, descriptor: &str, args: &[Value]) -> Result<Option<Value>, VmError> {
+    match (class, name, descriptor, args) {
+        ("java/lang/invoke/MethodType", "methodType", "(Ljava/lang/Class;[Ljava/lang/Class;)Ljava/lang/invoke/MethodType;",
+            &[Value::Reference(ref rtype), Value::Reference(ref ptypes)]) => {
+            let method_type = method_type_of(vm, rtype, ptypes)?;
+            Ok(Some(Value::Reference(Some(method_type))))
+        },
+        ("java/lang/invoke/MethodHandles", "lookup", "()Ljava/lang/invoke/MethodHandles$Lookup;", &[]) => {
+            let caller = vm.frame_class(1).ok_or_else(|| VmError::Unsupported("MethodHandles.lookup called from outside Java".to_string()))?;
+            Ok(Some(Value::Reference(Some(new_lookup(vm, &caller)?))))
+        },
+        ("java/lang/invoke/MethodHandles$Lookup", "findMethod",
+            "(ILjava/lang/Class;Ljava/lang/String;Ljava/lang/invoke/MethodType;)Ljava/lang/invoke/MethodHandle;",
+            &[Value::Reference(Some(ref lookup)), Value::Int(kind), Value::Reference(ref refc), Value::Reference(ref name), Value::Reference(ref method_type)]) => {
+            let descriptor = match *method_type {
+                Some(ref method_type) => type_descriptor(vm, method_type)?,
+                None => return Err(vm.throw_new("java/lang/NullPointerException")),
+            };
+            find(vm, lookup, kind, refc, name, &descriptor)
+        },
+        ("java/lang/invoke/MethodHandles$Lookup", "findField",
+            "(ILjava/lang/Class;Ljava/lang/String;Ljava/lang/Class;)Ljava/lang/invoke/MethodHandle;",
+            &[Value::Reference(Some(ref lookup)), Value::Int(kind), Value::Reference(ref refc), Value::Reference(ref name), Value::Reference(ref field_type)]) => {
+            let descriptor = match *field_type {
+                Some(ref field_type) => mirror_descriptor(vm, field_type)?,
+                None => return Err(vm.throw_new("java/lang/NullPointerException")),
+            };
+            find(vm, lookup, kind, refc, name, &descriptor)
+        },
+        (METHOD_HANDLE, "invokeWithArguments", "(Ljava/lang/invoke/MethodHandle;[Ljava/lang/Object;)Ljava/lang/Object;",
+            &[Value::Reference(ref handle), Value::Reference(ref arguments)]) => {
+            let handle = handle.clone().ok_or_else(|| vm.throw_new("java/lang/NullPointerException"))?;
+            invoke_with_arguments(vm, &handle, arguments)
+        },
+        _ => Err(VmError::Unsupported(format!("native method {}.{}{}", class, name, descriptor))),
+    }
+}
+
+// Finds the signature polymorphic method of MethodHandle with the given name, which method
+// resolution falls back to when no method has the call site's descriptor (JVM spec 5.4.3.3).
+pub fn find_signature_polymorphic(class: &Rc<RuntimeClass>, name: &str) -> Result<Option<usize>, VmError> {
+    if class.name != METHOD_HANDLE {
+        return Ok(None);
+    }
+    let index = class.find_declared_method(name, POLYMORPHIC_DESCRIPTOR)?;
+    Ok(index.filter(|&index| is_signature_polymorphic(class, index)))
+}
+
+pub fn is_signature_polymorphic(class: &RuntimeClass, method_index: usize) -> bool {
+    let flags = class.class.methods[method_index].flags;
+    class.name == METHOD_HANDLE && flags.contains(MethodFlags::NATIVE | MethodFlags::VARARGS)
+}
+
+// Creates a java.lang.invoke.MethodType, first resolving every class named in the descriptor
+// from the given class.
+pub fn new_method_type(vm: &mut Vm, class: &RuntimeClass, descriptor: &str) -> Result<ObjectRef, VmError> {
+    let parsed = vm.method_descriptor(descriptor)?;
+    for field_type in parsed.parameters.iter().chain(parsed.return_type.iter()) {
+        if let Some(name) = resolve::class_name_of(field_type) {
+            vm.load_class_with(class.loader, &name)?;
+        }
+    }
+
+    let rtype = reflection::type_mirror(vm, class, parsed.return_type.as_ref())?;
+    let mut ptypes = vec![];
+    for parameter in &parsed.parameters {
+        ptypes.push(reflection::type_mirror(vm, class, Some(parameter))?);
+    }
+    method_type(vm, rtype, ptypes, descriptor)
+}
+
+fn method_type(vm: &mut Vm, rtype: ObjectRef, ptypes: Vec<ObjectRef>, descriptor: &str) -> Result<ObjectRef, VmError> {
+    let method_type_class = vm.load_class("java/lang/invoke/MethodType")?;
+    vm.initialize(&method_type_class)?;
+    let method_type = vm.new_object(&method_type_class);
+    let ptypes = reflection::new_array(vm, "[Ljava/lang/Class;", ptypes)?.unwrap_or_else(Value::null);
+    let descriptor = vm.intern_string(descriptor)?;
+    vm::set_field(&method_type, "rtype", Value::Reference(Some(rtype)))?;
+    vm::set_field(&method_type, "ptypes", ptypes)?;
+    vm::set_field(&method_type, "descriptor", Value::Reference(Some(descriptor)))?;
+    Ok(method_type)
+}
+
+// MethodType.methodType, which builds the descriptor from the Class objects it's given.
+fn method_type_of(vm: &mut Vm, rtype: &Option<ObjectRef>, ptypes: &Option<ObjectRef>) -> Result<ObjectRef, VmError> {
+    let (rtype, ptypes) = match (rtype, ptypes) {
+        (Some(rtype), Some(ptypes)) => (rtype.clone(), ptypes.clone()),
+        _ => return Err(vm.throw_new("java/lang/NullPointerException")),
+    };
+    let elements: Vec<Value> = {
+        let storage = ptypes.fields.borrow();
+        (0..storage.len()).map(|index| storage.get(index)).collect()
+    };
+
+    let mut parameters = vec![];
+    let mut descriptor = String::from("(");
+    for element in elements {
+        let parameter = match element {
+            Value::Reference(Some(parameter)) => parameter,
+            _ => return Err(vm.throw_new("java/lang/NullPointerException")),
+        };
+        let parameter_descriptor = mirror_descriptor(vm, &parameter)?;
+        if parameter_descriptor == "V" {
+            return Err(vm.throw_new_with_message("java/lang/IllegalArgumentException", "parameter type cannot be void"));
+        }
+        descriptor.push_str(&parameter_descriptor);
+        parameters.push(parameter);
+    }
+    descriptor.push(')');
+    descriptor.push_str(&mirror_descriptor(vm, &rtype)?);
+    method_type(vm, rtype, parameters, &descriptor)
+}
+
+// Creates a java.lang.invoke.MethodHandle of the given kind for a member of the owner class,
+// checking that the caller may use it. The handle's type is derived from the member's descriptor
+// as described in JVM spec 5.4.3.5.
+pub fn new_method_handle(vm: &mut Vm, caller: &Rc<RuntimeClass>, kind: i32, owner: &Rc<RuntimeClass>, name: &str, descriptor: &str) -> Result<ObjectRef, VmError> {
+    access::check_class(caller, owner)?;
+    vm.check_access(caller, owner)?;
+
+    let owner_descriptor = resolve::reference_descriptor(&owner.name);
+    let type_descriptor = match kind {
+        REF_GET_FIELD | REF_GET_STATIC | REF_PUT_FIELD | REF_PUT_STATIC => {
+            check_field(vm, caller, kind, owner, name, descriptor)?;
+            match kind {
+                REF_GET_FIELD => format!("({}){}", owner_descriptor, descriptor),
+                REF_PUT_FIELD => format!("({}{})V", owner_descriptor, descriptor),
+                REF_GET_STATIC => format!("(){}", descriptor),
+                _ => format!("({})V", descriptor),
+            }
+        },
+        REF_INVOKE_VIRTUAL | REF_INVOKE_STATIC | REF_INVOKE_SPECIAL | REF_NEW_INVOKE_SPECIAL | REF_INVOKE_INTERFACE => {
+            check_method(vm, caller, kind, owner, name, descriptor)?;
+            let after_open_paren = &descriptor[1..];
+            match kind {
+                REF_INVOKE_STATIC => descriptor.to_string(),
+                REF_INVOKE_SPECIAL => format!("({}{}", resolve::reference_descriptor(&caller.name), after_open_paren),
+                REF_NEW_INVOKE_SPECIAL => {
+                    let parameters: String = MethodDescriptor::parse(descriptor)?.parameters.iter().map(FieldType::descriptor).collect();
+                    format!("({}){}", parameters, owner_descriptor)
+                },
+                _ => format!("({}{}", owner_descriptor, after_open_paren),
+            }
+        },
+        _ => return Err(VmError::InvalidBytecode(format!("Invalid method handle reference kind {}", kind))),
+    };
+
+    let method_handle_class = vm.load_class(METHOD_HANDLE)?;
+    vm.initialize(&method_handle_class)?;
+    let method_handle = vm.new_object(&method_handle_class);
+    let declaring_class = vm.class_mirror(owner)?;
+    let name = vm.intern_string(name)?;
+    let method_type = new_method_type(vm, caller, &type_descriptor)?;
+    vm::set_field(&method_handle, "referenceKind", Value::Int(kind))?;
+    vm::set_field(&method_handle, "declaringClass", Value::Reference(Some(declaring_class)))?;
+    vm::set_field(&method_handle, "name", Value::Reference(Some(name)))?;
+    vm::set_field(&method_handle, "type", Value::Reference(Some(method_type)))?;
+    Ok(method_handle)
+}
+
+// Checks that a field handle refers to a field of the given type that's static exactly when the
+// kind is, and that the caller may use it. Setters can't change final fields of other classes.
+fn check_field(vm: &mut Vm, caller: &Rc<RuntimeClass>, kind: i32, owner: &Rc<RuntimeClass>, name: &str, descriptor: &str) -> Result<(), VmError> {
+    let not_found = || VmError::FieldNotFound {class: owner.name.to_string(), name: name.to_string()};
+    let (declaring_class, flags) = access::find_field(owner, name)?.ok_or_else(not_found)?;
+    if declaring_class.members().field(name, descriptor).is_none() {
+        return Err(not_found());
+    }
+
+    let is_static = flags.contains(FieldFlags::STATIC);
+    if is_static != (kind == REF_GET_STATIC || kind == REF_PUT_STATIC) {
+        let expected = if is_static { "non-static" } else { "static" };
+        return Err(VmError::IncompatibleClassChange(format!("Expected {} field {}.{}", expected, owner.name, name)));
+    }
+    let is_setter = kind == REF_PUT_FIELD || kind == REF_PUT_STATIC;
+    if is_setter && flags.contains(FieldFlags::FINAL) && !Rc::ptr_eq(caller, &declaring_class) {
+        return Err(VmError::IllegalAccess(format!("Field {}.{} is final", declaring_class.name, name)));
+    }
+    access::check_field(vm, caller, owner, name, None)
+}
+
+// Checks that a method handle refers to a method that's static exactly when the kind is, and to
+// a constructor exactly when the kind is newInvokeSpecial, and that the caller may use it.
+fn check_method(vm: &mut Vm, caller: &Rc<RuntimeClass>, kind: i32, owner: &Rc<RuntimeClass>, name: &str, descriptor: &str) -> Result<(), VmError> {
+    let not_found = || VmError::MethodNotFound {class: owner.name.to_string(), name: name.to_string(), descriptor: descriptor.to_string()};
+    if (name == "<init>") != (kind == REF_NEW_INVOKE_SPECIAL) {
+        return Err(not_found());
+    }
+    let (declaring_class, method_index) = resolve_method(owner, name, descriptor)?.ok_or_else(not_found)?;
+    if kind == REF_NEW_INVOKE_SPECIAL && !Rc::ptr_eq(owner, &declaring_class) {
+        return Err(not_found());
+    }
+
+    let is_static = declaring_class.class.methods[method_index].flags.contains(MethodFlags::STATIC);
+    if is_static != (kind == REF_INVOKE_STATIC) {
+        let expected = if is_static { "non-static" } else { "static" };
+        return Err(VmError::IncompatibleClassChange(format!("Expected {} method {}.{}{}", expected, owner.name, name, descriptor)));
+    }
+    access::check_method(vm, caller, owner, &declaring_class, method_index, None)
+}
+
+// Creates the Lookup that MethodHandles.lookup returns to the given class, and that bootstrap
+// methods are called with.
+pub fn new_lookup(vm: &mut Vm, class: &Rc<RuntimeClass>) -> Result<ObjectRef, VmError> {
+    let lookup_class = vm.load_class("java/lang/invoke/MethodHandles$Lookup")?;
+    vm.initialize(&lookup_class)?;
+    let lookup = vm.new_object(&lookup_class);
+    let mirror = vm.class_mirror(class)?;
+    vm::set_field(&lookup, "lookupClass", Value::Reference(Some(mirror)))?;
+    Ok(lookup)
+}
+
+// Lookup.find*, which fail with the checked exceptions of the reflection API rather than the
+// linkage errors that the same failures raise for a method handle constant.
+fn find(vm: &mut Vm, lookup: &ObjectRef, kind: i32, refc: &Option<ObjectRef>, name: &Option<ObjectRef>, descriptor: &str) -> Result<Option<Value>, VmError> {
+    let (refc, name) = match (refc, name) {
+        (Some(refc), Some(name)) => (refc, vm.read_string(name)?),
+        _ => return Err(vm.throw_new("java/lang/NullPointerException")),
+    };
+    let caller = match vm::get_field(lookup, "lookupClass")? {
+        Value::Reference(Some(ref mirror)) => vm.mirror_class(mirror),
+        _ => None,
+    };
+    let caller = caller.ok_or_else(|| VmError::InvalidBytecode("Lookup has no lookup class".to_string()))?;
+    let owner = match vm.mirror_class(refc) {
+        Some(owner) => owner,
+        None if kind <= REF_PUT_STATIC => return Err(vm.throw_new_with_message("java/lang/NoSuchFieldException", &name)),
+        None => return Err(vm.throw_new_with_message("java/lang/NoSuchMethodException", &name)),
+    };
+
+    match new_method_handle(vm, &caller, kind, &owner, &name, descriptor) {
+        Ok(handle) => Ok(Some(Value::Reference(Some(handle)))),
+        Err(VmError::MethodNotFound{class, name, descriptor}) => {
+            let message = format!("no such method: {}.{}{}", class.replace('/', "."), name, descriptor);
+            Err(vm.throw_new_with_message("java/lang/NoSuchMethodException", &message))
+        },
+        Err(VmError::FieldNotFound{class, name}) => {
+            let message = format!("no such field: {}.{}/{}", class.replace('/', "."), name, descriptor);
+            Err(vm.throw_new_with_message("java/lang/NoSuchFieldException", &message))
+        },
+        Err(VmError::IllegalAccess(message)) | Err(VmError::IncompatibleClassChange(message)) => {
+            Err(vm.throw_new_with_message("java/lang/IllegalAccessException", &message))
+        },
+        Err(err) => Err(reflection::thrown(vm, err)),
+    }
+}
+
+// MethodHandle.invokeWithArguments, which calls the handle as invoke would from a call site that
+// passes each argument as an Object and expects an Object back.
+fn invoke_with_arguments(vm: &mut Vm, handle: &ObjectRef, arguments: &Option<ObjectRef>) -> Result<Option<Value>, VmError> {
+    let args: Vec<Value> = match *arguments {
+        Some(ref arguments) => {
+            let storage = arguments.fields.borrow();
+            (0..storage.len()).map(|index| storage.get(index)).collect()
+        },
+        None => vec![],
+    };
+    let call_site = format!("({})Ljava/lang/Object;", "Ljava/lang/Object;".repeat(args.len()));
+    let caller = Rc::clone(handle.class());
+    invoke_handle(vm, &caller, handle, &call_site, false, args)
+}
+
+// Calls a method handle from a call site with the given descriptor, passing the arguments that
+// follow the handle. invokeExact requires the descriptor to match the handle's type exactly;
+// invoke converts the arguments and result as MethodHandle.asType would. Classes the call site
+// names are loaded as the caller sees them.
+pub fn invoke_handle(vm: &mut Vm, caller: &RuntimeClass, handle: &ObjectRef, call_site: &str, exact: bool, args: Vec<Value>) -> Result<Option<Value>, VmError> {
+    let method_type = match vm::get_field(handle, "type")? {
+        Value::Reference(Some(method_type)) => method_type,
+        other => return Err(VmError::InvalidBytecode(format!("MethodHandle has invalid type {:?}", other))),
+    };
+    let handle_type = type_descriptor(vm, &method_type)?;
+    if exact && call_site != handle_type {
+        let message = format!("expected {} but found {}", handle_type, call_site);
+        return Err(vm.throw_new_with_message("java/lang/invoke/WrongMethodTypeException", &message));
+    }
+
+    let target = MethodDescriptor::parse(&handle_type)?;
+    let args = if exact {
+        args
+    } else {
+        let site = MethodDescriptor::parse(call_site)?;
+        let wrong_type = |vm: &mut Vm| {
+            let message = format!("cannot convert {} to {}", handle_type, call_site);
+            vm.throw_new_with_message("java/lang/invoke/WrongMethodTypeException", &message)
+        };
+        if site.parameters.len() != target.parameters.len() {
+            return Err(wrong_type(vm));
+        }
+        let ptypes: Vec<Value> = match vm::get_field(&method_type, "ptypes")? {
+            Value::Reference(Some(ptypes)) => {
+                let storage = ptypes.fields.borrow();
+                (0..storage.len()).map(|index| storage.get(index)).collect()
+            },
+            other => return Err(VmError::InvalidBytecode(format!("MethodType has invalid ptypes {:?}", other))),
+        };
+        let mut converted = vec![];
+        for (((arg, from), to), ptype) in args.into_iter().zip(&site.parameters).zip(&target.parameters).zip(&ptypes) {
+            let to_class = match *ptype {
+                Value::Reference(Some(ref mirror)) => vm.mirror_class(mirror),
+                _ => None,
+            };
+            match convert(vm, arg, from, to, to_class.as_ref())? {
+                Some(arg) => converted.push(arg),
+                None => return Err(wrong_type(vm)),
+            }
+        }
+        converted
+    };
+
+    let kind = match vm::get_field(handle, "referenceKind")? {
+        Value::Int(kind) => kind,
+        other => return Err(VmError::InvalidBytecode(format!("MethodHandle has invalid referenceKind {:?}", other))),
+    };
+    let owner = match vm::get_field(handle, "declaringClass")? {
+        Value::Reference(Some(ref mirror)) => vm.mirror_class(mirror),
+        _ => None,
+    };
+    let owner = owner.ok_or_else(|| VmError::InvalidBytecode("MethodHandle has no declaring class".to_string()))?;
+    let name = match vm::get_field(handle, "name")? {
+        Value::Reference(Some(name)) => vm.read_string(&name)?,
+        other => return Err(VmError::InvalidBytecode(format!("MethodHandle has invalid name {:?}", other))),
+    };
+    let result = invoke_member(vm, kind, &owner, &name, &target, args)?;
+    if exact {
+        return Ok(result);
+    }
+
+    let site_return = MethodDescriptor::parse(call_site)?.return_type;
+    match (&target.return_type, &site_return, result) {
+        (_, None, _) => Ok(None),
+        (None, Some(to), _) => Ok(Some(Value::default_for(to))),
+        (Some(from), Some(to), Some(result)) => {
+            let to_class = match resolve::class_name_of(to) {
+                Some(name) => Some(vm.load_class_with(caller.loader, &name).map_err(|err| reflection::thrown(vm, err))?),
+                None => None,
+            };
+            match convert(vm, result, from, to, to_class.as_ref())? {
+                Some(result) => Ok(Some(result)),
+                None => {
+                    let message = format!("cannot convert {} to {}", handle_type, call_site);
+                    Err(vm.throw_new_with_message("java/lang/invoke/WrongMethodTypeException", &message))
+                },
+            }
+        },
+        (Some(_), Some(_), None) => Err(VmError::InvalidBytecode(format!("{}.{} returned no value", owner.name, name))),
+    }
+}
+
+// Converts a value from one type to another as asType would: primitives are widened, boxed or
+// unboxed, and references are cast. Returns None if the types can't be converted at all, and
+// throws ClassCastException or NullPointerException if this particular value can't be.
+fn convert(vm: &mut Vm, value: Value, from: &FieldType, to: &FieldType, to_class: Option<&Rc<RuntimeClass>>) -> Result<Option<Value>, VmError> {
+    match (from.is_reference(), to_class) {
+        (false, None) => Ok(reflection::widen(from, value, to)),
+        (false, Some(to_class)) => {
+            let boxed = reflection::box_value(vm, from, value)?;
+            Ok(boxed.filter(|boxed| boxed.class().is_assignable_to(to_class)).map(|boxed| Value::Reference(Some(boxed))))
+        },
+        (true, Some(to_class)) => match value {
+            Value::Reference(Some(ref object)) if !object.class().is_assignable_to(to_class) => {
+                let message = format!("Cannot cast {} to {}", object.class().java_name(), to_class.java_name());
+                Err(vm.throw_new_with_message("java/lang/ClassCastException", &message))
+            },
+            value => Ok(Some(value)),
+        },
+        (true, None) => match value {
+            Value::Reference(Some(object)) => {
+                let class = Rc::clone(object.class());
+                match reflection::unbox(vm, &class, to, &Some(object))? {
+                    Some(value) => Ok(Some(value)),
+                    None => {
+                        let message = format!("Cannot cast {} to {}", class.java_name(), reflection::type_name(to));
+                        Err(vm.throw_new_with_message("java/lang/ClassCastException", &message))
+                    },
+                }
+            },
+            Value::Reference(None) => Err(vm.throw_new("java/lang/NullPointerException")),
+            other => Err(VmError::InvalidBytecode(format!("Expected a reference of type {}; got {:?}", from.descriptor(), other))),
+        },
+    }
+}
+
+// Uses the member a handle refers to, as the instruction for its reference kind would. The
+// arguments already have the handle's parameter types.
+fn invoke_member(vm: &mut Vm, kind: i32, owner: &Rc<RuntimeClass>, name: &str, handle_type: &MethodDescriptor, mut args: Vec<Value>) -> Result<Option<Value>, VmError> {
+    let field_not_found = || VmError::FieldNotFound {class: owner.name.to_string(), name: name.to_string()};
+    match kind {
+        REF_GET_FIELD | REF_PUT_FIELD => {
+            let object = receiver(vm, &args)?;
+            let slot = owner.field_slot(name).ok_or_else(field_not_found)?;
+            if kind == REF_GET_FIELD {
+                return Ok(Some(object.fields.borrow().get(slot)));
+            }
+            object.fields.borrow_mut().set(slot, args.remove(1));
+            Ok(None)
+        },
+        REF_GET_STATIC | REF_PUT_STATIC => {
+            let (declaring_class, slot) = resolve_static_field(owner, name).ok_or_else(field_not_found)?;
+            vm.initialize(&declaring_class)?;
+            if kind == REF_GET_STATIC {
+                return Ok(Some(declaring_class.get_static(slot)));
+            }
+            declaring_class.put_static(slot, args.remove(0));
+            Ok(None)
+        },
+        REF_NEW_INVOKE_SPECIAL => {
+            let parameters: String = handle_type.parameters.iter().map(FieldType::descriptor).collect();
+            let descriptor = format!("({})V", parameters);
+            let index = owner.find_declared_method("<init>", &descriptor)?.ok_or_else(|| VmError::MethodNotFound {
+                class: owner.name.to_string(),
+                name: name.to_string(),
+                descriptor: descriptor.clone(),
+            })?;
+            vm.initialize(owner)?;
+            let object = vm.new_object(owner);
+            args.insert(0, Value::Reference(Some(object.clone())));
+            interpreter::invoke(vm, owner, index, args)?;
+            Ok(Some(Value::Reference(Some(object))))
+        },
+        REF_INVOKE_VIRTUAL | REF_INVOKE_STATIC | REF_INVOKE_SPECIAL | REF_INVOKE_INTERFACE => {
+            // The handle's type has the receiver as its first parameter, except for static methods.
+            let skip = if kind == REF_INVOKE_STATIC { 0 } else { 1 };
+            let parameters: String = handle_type.parameters.iter().skip(skip).map(FieldType::descriptor).collect();
+            let return_type = handle_type.return_type.as_ref().map_or_else(|| "V".to_string(), FieldType::descriptor);
+            let descriptor = format!("({}){}", parameters, return_type);
+            let not_found = || VmError::MethodNotFound {class: owner.name.to_string(), name: name.to_string(), descriptor: descriptor.clone()};
+            let (declaring_class, method_index) = resolve_method(owner, name, &descriptor)?.ok_or_else(not_found)?;
+
+            if kind == REF_INVOKE_STATIC {
+                vm.initialize(&declaring_class)?;
+                return interpreter::invoke(vm, &declaring_class, method_index, args);
+            }
+            let receiver_class = Rc::clone(receiver(vm, &args)?.class());
+            let is_private = declaring_class.class.methods[method_index].flags.contains(MethodFlags::PRIVATE);
+            let (selected_class, selected_index) = if kind == REF_INVOKE_SPECIAL || is_private {
+                (declaring_class, method_index)
+            } else {
+                resolve_method(&receiver_class, name, &descriptor)?.unwrap_or((declaring_class, method_index))
+            };
+            interpreter::check_implemented(vm, &receiver_class, &selected_class, selected_index)?;
+            interpreter::invoke(vm, &selected_class, selected_index, args)
+        },
+        _ => Err(VmError::InvalidBytecode(format!("Invalid method handle reference kind {}", kind))),
+    }
+}
+
+// The object an instance member is used on, which the handle's type guarantees is an instance
+// of the member's class if it isn't null.
+fn receiver(vm: &mut Vm, args: &[Value]) -> Result<ObjectRef, VmError> {
+    match args.first() {
+        Some(Value::Reference(Some(receiver))) => Ok(receiver.clone()),
+        Some(Value::Reference(None)) => Err(vm.throw_new("java/lang/NullPointerException")),
+        other => Err(VmError::InvalidBytecode(format!("Expected a receiver; got {:?}", other))),
+    }
+}
+
+// The descriptor a MethodType was created with.
+fn type_descriptor(vm: &mut Vm, method_type: &ObjectRef) -> Result<String, VmError> {
+    match vm::get_field(method_type, "descriptor")? {
+        Value::Reference(Some(descriptor)) => vm.read_string(&descriptor),
+        other => Err(VmError::InvalidBytecode(format!("MethodType has invalid descriptor {:?}", other))),
+    }
+}
+
+// The descriptor of the type a Class object stands for, e.g. I, [J or Ljava/lang/String;.
+fn mirror_descriptor(vm: &mut Vm, mirror: &ObjectRef) -> Result<String, VmError> {
+    if let Some(class) = vm.mirror_class(mirror) {
+        return Ok(resolve::reference_descriptor(&class.name));
+    }
+    let name = match vm::get_field(mirror, "name")? {
+        Value::Reference(Some(name)) => vm.read_string(&name)?,
+        other => return Err(VmError::InvalidBytecode(format!("Class object has invalid name {:?}", other))),
+    };
+    let descriptor = match name.as_str() {
+        "boolean" => "Z",
+        "byte" => "B",
+        "char" => "C",
+        "short" => "S",
+        "int" => "I",
+        "long" => "J",
+        "float" => "F",
+        "double" => "D",
+        "void" => "V",
+        _ => return Err(VmError::InvalidBytecode(format!("Class object {} isn't a mirror", name))),
+    };
+    Ok(descriptor.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+
+    const HANDLES: &[&[u8]] = &[
+        fixture!("Handles"),
+        fixture!("Handles$Derived"),
+        fixture!("Handles$Outsider"),
+    ];
+
+    fn call(vm: &mut Vm, class: &str, name: &str, descriptor: &str, args: Vec<Value>) -> Result<Option<Value>, VmError> {
+        vm.invoke_static(class, name, descriptor, args)
+    }
+
+    fn read_string(vm: &Vm, result: Result<Option<Value>, VmError>) -> String {
+        match result {
+            Ok(Some(Value::Reference(Some(string)))) => vm.read_string(&string).unwrap(),
+            other => panic!("Expected a string; got {:?}", other),
+        }
+    }
+
+    // Checks that the result is an exception of the given class, returning its message.
+    fn expect_thrown(vm: &Vm, result: Result<Option<Value>, VmError>, class: &str) -> Option<String> {
+        match result {
+            Err(VmError::UncaughtException(ref exception)) if exception.class().name == class => match vm::get_field(exception, "detailMessage").unwrap() {
+                Value::Reference(Some(message)) => Some(vm.read_string(&message).unwrap()),
+                _ => None,
+            },
+            other => panic!("Expected {}; got {:?}", class, other),
+        }
+    }
+
+    #[test]
+    fn test_method_types() {
+        let mut vm = vm_with(HANDLES);
+        assert_eq!(Ok(Some(Value::Int(1))), call(&mut vm, "Handles", "methodTypes", "()Z", vec![]));
+    }
+
+    #[test]
+    fn test_invoke_exact() {
+        let mut vm = vm_with(HANDLES);
+        assert_eq!(Ok(Some(Value::Int(5))), call(&mut vm, "Handles", "invokeExactly", "()I", vec![]));
+
+        let result = call(&mut vm, "Handles", "invokeWithWrongType", "()Ljava/lang/Object;", vec![]);
+        let message = expect_thrown(&vm, result, "java/lang/invoke/WrongMethodTypeException");
+        assert_eq!(Some("expected (II)I but found (II)Ljava/lang/Object;".to_string()), message);
+    }
+
+    #[test]
+    fn test_invoke_converts_arguments_and_result() {
+        let mut vm = vm_with(HANDLES);
+        assert_eq!(Ok(Some(Value::Long(14))), call(&mut vm, "Handles", "invokeConverting", "()J", vec![]));
+
+        let result = call(&mut vm, "Handles", "invokeCastFailure", "()Ljava/lang/Object;", vec![]);
+        let message = expect_thrown(&vm, result, "java/lang/ClassCastException");
+        assert_eq!(Some("Cannot cast java.lang.String to Handles".to_string()), message);
+    }
+
+    #[test]
+    fn test_virtual_handle_selects_override() {
+        let mut vm = vm_with(HANDLES);
+        let descriptor = "(LHandles;)Ljava/lang/String;";
+        for (class, expected) in [("Handles", "base"), ("Handles$Derived", "derived")] {
+            let class = vm.load_class(class).unwrap();
+            let receiver = Value::Reference(Some(vm.new_object(&class)));
+            let result = call(&mut vm, "Handles", "invokeVirtual", descriptor, vec![receiver]);
+            assert_eq!(expected, read_string(&vm, result));
+        }
+
+        let result = call(&mut vm, "Handles", "invokeVirtual", descriptor, vec![Value::null()]);
+        expect_thrown(&vm, result, "java/lang/NullPointerException");
+    }
+
+    #[test]
+    fn test_special_handle_calls_super_method() {
+        let mut vm = vm_with(HANDLES);
+        let result = call(&mut vm, "Handles$Derived", "describeSuper", "()Ljava/lang/String;", vec![]);
+        assert_eq!("base", read_string(&vm, result));
+    }
+
+    #[test]
+    fn test_constructor_and_field_handles() {
+        let mut vm = vm_with(HANDLES);
+        assert_eq!(Ok(Some(Value::Int(6))), call(&mut vm, "Handles", "construct", "()I", vec![]));
+        assert_eq!(Ok(Some(Value::Long(105))), call(&mut vm, "Handles", "fields", "()J", vec![]));
+    }
+
+    #[test]
+    fn test_invoke_with_arguments() {
+        let mut vm = vm_with(HANDLES);
+        match call(&mut vm, "Handles", "invokeWithArguments", "()Ljava/lang/Object;", vec![]) {
+            Ok(Some(Value::Reference(Some(boxed)))) => {
+                assert_eq!("java/lang/Integer", boxed.class().name);
+                assert_eq!(Value::Int(42), vm::get_field(&boxed, "value").unwrap());
+            },
+            other => panic!("Expected a boxed int; got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_lookup_checks_access() {
+        let mut vm = vm_with(HANDLES);
+        assert_eq!(Ok(Some(Value::Int(7))), call(&mut vm, "Handles", "invokePrivate", "()I", vec![]));
+
+        let result = call(&mut vm, "Handles$Outsider", "findSecret", "()Ljava/lang/Object;", vec![]);
+        expect_thrown(&vm, result, "java/lang/IllegalAccessException");
+        let result = call(&mut vm, "Handles$Outsider", "findFinalSetter", "()Ljava/lang/Object;", vec![]);
+        let message = expect_thrown(&vm, result, "java/lang/IllegalAccessException");
+        assert_eq!(Some("Field Handles.fixed is final".to_string()), message);
+    }
+
+    #[test]
+    fn test_lookup_of_missing_members() {
+        let mut vm = vm_with(HANDLES);
+        let int = Value::Reference(Some(vm.primitive_mirror("int").unwrap()));
+        let long = Value::Reference(Some(vm.primitive_mirror("long").unwrap()));
+
+        let name = Value::Reference(Some(vm.new_string("missing").unwrap()));
+        let result = call(&mut vm, "Handles", "findStatic", "(Ljava/lang/String;Ljava/lang/Class;)Ljava/lang/Object;", vec![name, int.clone()]);
+        let message = expect_thrown(&vm, result, "java/lang/NoSuchMethodException");
+        assert_eq!(Some("no such method: Handles.missing()I".to_string()), message);
+
+        // The field exists, but with a different type.
+        let name = Value::Reference(Some(vm.new_string("count").unwrap()));
+        let result = call(&mut vm, "Handles", "findGetter", "(Ljava/lang/String;Ljava/lang/Class;)Ljava/lang/Object;", vec![name, long.clone()]);
+        let message = expect_thrown(&vm, result, "java/lang/NoSuchFieldException");
+        assert_eq!(Some("no such field: Handles.count/J".to_string()), message);
+
+        // total is static, so it can't have an instance getter.
+        let name = Value::Reference(Some(vm.new_string("total").unwrap()));
+        let result = call(&mut vm, "Handles", "findGetter", "(Ljava/lang/String;Ljava/lang/Class;)Ljava/lang/Object;", vec![name, long]);
+        let message = expect_thrown(&vm, result, "java/lang/IllegalAccessException");
+        assert_eq!(Some("Expected non-static field Handles.total".to_string()), message);
+    }
+
+    #[test]
+    fn test_invoking_null_handle_throws() {
+        let mut vm = vm_with(HANDLES);
+        let result = call(&mut vm, "Handles", "invokeNull", "()V", vec![]);
+        expect_thrown(&vm, result, "java/lang/NullPointerException");
+    }
+}
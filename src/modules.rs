@@ -0,0 +1,435 @@
+use crate::classes::*;
+use crate::classloader::{self, ClassLoaderError};
+use crate::classpath::{ClassSource, ClasspathError};
+use crate::loaders::{self, LoaderId};
+use std::collections::{HashMap, HashSet};
+use std::{error, fmt};
+
+const JAVA_BASE: &str = "java.base";
+
+// A module's declaration, as read from the Module attribute of its module-info class. Package
+// names are in internal form, like com/example.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModuleDescriptor {
+    pub name: String,
+    pub open: bool, // Every package is open for deep reflection.
+    pub automatic: bool, // Reads every module and exports every package, like a plain JAR.
+    pub requires: Vec<Requires>,
+    pub exports: Vec<Exports>,
+    pub opens: Vec<Exports>,
+    pub packages: HashSet<String>,
+    pub main_class: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Requires {
+    pub module: String,
+    pub transitive: bool, // Modules that read this one read the required module too.
+    pub optional: bool, // Declared requires static, so the module needn't be present at runtime.
+}
+
+// An exports or opens declaration, to every module if there are no targets.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exports {
+    pub package: String,
+    pub targets: Vec<String>,
+}
+
+impl Exports {
+    fn includes(&self, package: &str, module: Option<&str>) -> bool {
+        self.package == package && (self.targets.is_empty() || module.is_some_and(|module| self.targets.iter().any(|target| target == module)))
+    }
+}
+
+impl ModuleDescriptor {
+    // Reads the descriptor of a module-info class. Its packages are those listed in its
+    // ModulePackages attribute, which the jar tool adds, along with those it exports or opens.
+    pub fn from_class(class: &Class) -> Result<ModuleDescriptor, ModuleError> {
+        if !class.flags.contains(ClassFlags::MODULE) {
+            return Err(ModuleError::NotAModule);
+        }
+        let mut descriptor = None;
+        let mut packages = HashSet::new();
+        let mut main_class = None;
+        for attribute in &class.attributes {
+            match *attribute {
+                Attribute::Module(ref module) => {
+                    let ModuleAttribute {ref name, flags, ref requires, ref exports, ref opens, ..} = **module;
+                    let package_list = |list: &[ModulePackage]| -> Result<Vec<Exports>, ModuleError> {
+                        list.iter().map(|entry| Ok(Exports {
+                            package: class.package_name(&entry.package)?.to_string(),
+                            targets: entry.targets.iter().map(|target| Ok(class.module_name(target)?.to_string())).collect::<Result<_, ModuleError>>()?,
+                        })).collect()
+                    };
+                    let requires = requires.iter().map(|required| Ok(Requires {
+                        module: class.module_name(&required.module)?.to_string(),
+                        transitive: required.flags.contains(RequiresFlags::TRANSITIVE),
+                        optional: required.flags.contains(RequiresFlags::STATIC_PHASE),
+                    })).collect::<Result<_, ModuleError>>()?;
+                    descriptor = Some(ModuleDescriptor {
+                        name: class.module_name(name)?.to_string(),
+                        open: flags.contains(ModuleFlags::OPEN),
+                        automatic: false,
+                        requires,
+                        exports: package_list(exports)?,
+                        opens: package_list(opens)?,
+                        packages: HashSet::new(),
+                        main_class: None,
+                    });
+                },
+                Attribute::ModulePackages{packages: ref listed, ..} => {
+                    for package in listed {
+                        packages.insert(class.package_name(package)?.to_string());
+                    }
+                },
+                Attribute::ModuleMainClass{main_class: ref index, ..} => main_class = Some(class.class_name(index)?.to_string()),
+                _ => (),
+            }
+        }
+
+        let mut descriptor = descriptor.ok_or(ModuleError::NotAModule)?;
+        packages.extend(descriptor.exports.iter().chain(&descriptor.opens).map(|entry| entry.package.clone()));
+        descriptor.packages = packages;
+        descriptor.main_class = main_class;
+        Ok(descriptor)
+    }
+
+    // Reads the descriptor of the module in a modular JAR or directory, or None if there's no
+    // module-info class.
+    pub fn read(source: &dyn ClassSource) -> Result<Option<ModuleDescriptor>, ModuleError> {
+        match source.find_resource("module-info.class")? {
+            Some(bytes) => Ok(Some(ModuleDescriptor::from_class(&classloader::load_class(&bytes)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    // A module for classes without a module-info class, such as those of a plain JAR.
+    pub fn automatic(name: &str, packages: &[&str]) -> ModuleDescriptor {
+        ModuleDescriptor {
+            name: name.to_string(),
+            open: true,
+            automatic: true,
+            requires: vec![],
+            exports: vec![],
+            opens: vec![],
+            packages: packages.iter().map(|package| package.to_string()).collect(),
+            main_class: None,
+        }
+    }
+
+    // Whether code in the given module may use the package's public classes. None is the unnamed
+    // module.
+    pub fn exports(&self, package: &str, module: Option<&str>) -> bool {
+        self.automatic || self.exports.iter().any(|exports| exports.includes(package, module))
+    }
+
+    // Whether code in the given module may reflect on the package's non-public members.
+    pub fn opens(&self, package: &str, module: Option<&str>) -> bool {
+        self.open || self.opens.iter().any(|opens| opens.includes(package, module))
+    }
+}
+
+// The modules a loader has defined.
+struct ResolvedModule {
+    descriptor: ModuleDescriptor,
+    loader: LoaderId,
+    reads: HashSet<String>, // Including itself.
+}
+
+// A set of modules resolved against each other, each defined to a class loader, as in
+// java.lang.ModuleLayer. Joyvm has only the boot layer. A class whose package isn't in any of
+// the layer's modules is in its loader's unnamed module, which reads every module and exports
+// every package; named modules other than automatic ones can't read it.
+//
+// If the layer has no java.base of its own, joyvm's bootstrap classes stand in for it: any
+// package the bootstrap loader defines that no module claims is in java.base, which exports
+// everything.
+#[derive(Default)]
+pub struct ModuleLayer {
+    modules: HashMap<String, ResolvedModule>,
+    packages: HashMap<(LoaderId, String), String>, // The module of each package.
+    base_stand_in: bool,
+}
+
+impl ModuleLayer {
+    // A layer with no modules, in which every class is in an unnamed module, as before Java 9.
+    pub fn empty() -> ModuleLayer {
+        ModuleLayer::default()
+    }
+
+    // Resolves the modules into the boot layer. Each module's requirements must be among them,
+    // unless they're optional, and no two modules of the same loader may have the same package.
+    pub fn boot(modules: Vec<(ModuleDescriptor, LoaderId)>) -> Result<ModuleLayer, ModuleError> {
+        let mut layer = ModuleLayer::empty();
+        for (descriptor, loader) in modules {
+            for package in &descriptor.packages {
+                if let Some(other) = layer.packages.insert((loader, package.clone()), descriptor.name.clone()) {
+                    return Err(ModuleError::SplitPackage{package: package.clone(), modules: (other, descriptor.name.clone())});
+                }
+            }
+            if layer.modules.contains_key(&descriptor.name) {
+                return Err(ModuleError::DuplicateModule(descriptor.name));
+            }
+            layer.modules.insert(descriptor.name.clone(), ResolvedModule {descriptor, loader, reads: HashSet::new()});
+        }
+        if !layer.modules.contains_key(JAVA_BASE) {
+            let descriptor = ModuleDescriptor::automatic(JAVA_BASE, &[]);
+            layer.modules.insert(JAVA_BASE.to_string(), ResolvedModule {descriptor, loader: LoaderId::BOOTSTRAP, reads: HashSet::new()});
+            layer.base_stand_in = true;
+        }
+
+        let mut reads = HashMap::new();
+        for (name, module) in &layer.modules {
+            let mut read = HashSet::new();
+            read.insert(name.clone());
+            read.insert(JAVA_BASE.to_string());
+            if module.descriptor.automatic {
+                read.extend(layer.modules.keys().cloned());
+            }
+            for required in &module.descriptor.requires {
+                if !layer.modules.contains_key(&required.module) {
+                    if required.optional {
+                        continue;
+                    }
+                    return Err(ModuleError::MissingModule{module: name.clone(), requires: required.module.clone()});
+                }
+                read.insert(required.module.clone());
+                layer.add_implied_reads(&required.module, &mut read);
+            }
+            reads.insert(name.clone(), read);
+        }
+        for (name, read) in reads {
+            layer.modules.get_mut(&name).expect("Resolved modules exist").reads = read;
+        }
+        Ok(layer)
+    }
+
+    // Adds the modules that reading the given one implies reading, through requires transitive.
+    fn add_implied_reads(&self, module: &str, reads: &mut HashSet<String>) {
+        let module = match self.modules.get(module) {
+            Some(module) => module,
+            None => return,
+        };
+        for required in module.descriptor.requires.iter().filter(|required| required.transitive) {
+            if self.modules.contains_key(&required.module) && reads.insert(required.module.clone()) {
+                self.add_implied_reads(&required.module, reads);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+
+    pub fn descriptor(&self, module: &str) -> Option<&ModuleDescriptor> {
+        Some(&self.modules.get(module)?.descriptor)
+    }
+
+    pub fn modules(&self) -> impl Iterator<Item = &ModuleDescriptor> {
+        self.modules.values().map(|module| &module.descriptor)
+    }
+
+    // The loader the module is defined to.
+    pub fn loader(&self, module: &str) -> Option<LoaderId> {
+        Some(self.modules.get(module)?.loader)
+    }
+
+    // The module of a class with the given binary name defined by the given loader, or None if
+    // it's in the loader's unnamed module.
+    pub fn module_of(&self, loader: LoaderId, class_name: &str) -> Option<&str> {
+        let package = loaders::package_name(class_name);
+        match self.packages.get(&(loader, package.to_string())) {
+            Some(module) => Some(module),
+            None if self.base_stand_in && loader == LoaderId::BOOTSTRAP => Some(JAVA_BASE),
+            None => None,
+        }
+    }
+
+    // Whether the first module reads the second. The unnamed module reads every module.
+    pub fn reads(&self, from: Option<&str>, to: Option<&str>) -> bool {
+        match (from, to) {
+            (None, _) => true,
+            (Some(from), None) => self.modules.get(from).is_some_and(|module| module.descriptor.automatic),
+            (Some(from), Some(to)) => self.modules.get(from).is_some_and(|module| module.reads.contains(to)),
+        }
+    }
+
+    // Checks that a class may use another class's public members as far as modules are
+    // concerned: the class's module must read the other's, which must export its package to it.
+    // Returns why not if it may not.
+    pub fn check_access(&self, from: (LoaderId, &str), to: (LoaderId, &str)) -> Result<(), String> {
+        let from_module = self.module_of(from.0, from.1);
+        let to_module = self.module_of(to.0, to.1);
+        if from_module == to_module {
+            return Ok(());
+        }
+        let describe = |class: &str, module: Option<&str>| match module {
+            Some(module) => format!("class {} (in module {})", class.replace('/', "."), module),
+            None => format!("class {} (in unnamed module)", class.replace('/', ".")),
+        };
+        let module_name = |module: Option<&str>| module.map_or("the unnamed module".to_string(), |module| format!("module {}", module));
+        let reason = if !self.reads(from_module, to_module) {
+            format!("{} does not read {}", module_name(from_module), module_name(to_module))
+        } else {
+            let package = loaders::package_name(to.1);
+            match to_module.and_then(|module| self.descriptor(module)) {
+                Some(descriptor) if !descriptor.exports(package, from_module) => {
+                    format!("{} does not export {} to {}", module_name(to_module), package.replace('/', "."), module_name(from_module))
+                },
+                _ => return Ok(()),
+            }
+        };
+        Err(format!("{} cannot access {} because {}", describe(from.1, from_module), describe(to.1, to_module), reason))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ModuleError {
+    ClassLoad(ClassLoaderError),
+    Classpath(ClasspathError),
+    ConstantLookup(ConstantLookupError),
+    DuplicateModule(String),
+    MissingModule{module: String, requires: String},
+    NotAModule, // The class isn't a module-info class.
+    SplitPackage{package: String, modules: (String, String)},
+}
+
+impl std::convert::From<ClassLoaderError> for ModuleError {
+    fn from(err: ClassLoaderError) -> ModuleError {
+        ModuleError::ClassLoad(err)
+    }
+}
+
+impl std::convert::From<ClasspathError> for ModuleError {
+    fn from(err: ClasspathError) -> ModuleError {
+        ModuleError::Classpath(err)
+    }
+}
+
+impl std::convert::From<ConstantLookupError> for ModuleError {
+    fn from(err: ConstantLookupError) -> ModuleError {
+        ModuleError::ConstantLookup(err)
+    }
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ModuleError::ClassLoad(ref err) => write!(f, "Failed to parse module-info class: {}", err),
+            ModuleError::Classpath(ref err) => write!(f, "{}", err),
+            ModuleError::ConstantLookup(ref err) => write!(f, "Invalid constant in module-info class: {}", err),
+            ModuleError::DuplicateModule(ref name) => write!(f, "Module {} is defined more than once", name),
+            ModuleError::MissingModule{ref module, ref requires} => write!(f, "Module {} not found, required by {}", requires, module),
+            ModuleError::NotAModule => write!(f, "Not a module-info class"),
+            ModuleError::SplitPackage{ref package, modules: (ref first, ref second)} => {
+                write!(f, "Modules {} and {} both contain package {}", first, second, package.replace('/', "."))
+            },
+        }
+    }
+}
+
+impl error::Error for ModuleError {
+    fn description(&self) -> &str {
+        match *self {
+            ModuleError::ClassLoad(_) => "Failed to parse module-info class",
+            ModuleError::Classpath(_) => "Failed to read module-info class",
+            ModuleError::ConstantLookup(_) => "Invalid constant in module-info class",
+            ModuleError::DuplicateModule(_) => "Module defined more than once",
+            ModuleError::MissingModule{..} => "Required module not found",
+            ModuleError::NotAModule => "Not a module-info class",
+            ModuleError::SplitPackage{..} => "Package in more than one module",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            ModuleError::ClassLoad(ref err) => Some(err),
+            ModuleError::Classpath(ref err) => Some(err),
+            ModuleError::ConstantLookup(ref err) => Some(err),
+            ModuleError::DuplicateModule(_) => None,
+            ModuleError::MissingModule{..} => None,
+            ModuleError::NotAModule => None,
+            ModuleError::SplitPackage{..} => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jar::JarSource;
+
+    fn module_jar(name: &str) -> ModuleDescriptor {
+        let jar = JarSource::open(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/modules/").to_string() + name).unwrap();
+        ModuleDescriptor::read(&jar).unwrap().unwrap()
+    }
+
+    fn requires(module: &str, transitive: bool) -> Requires {
+        Requires {module: module.to_string(), transitive, optional: false}
+    }
+
+    fn module(name: &str, requires: Vec<Requires>, exports: &[&str]) -> ModuleDescriptor {
+        ModuleDescriptor {
+            name: name.to_string(),
+            open: false,
+            automatic: false,
+            requires,
+            exports: exports.iter().map(|package| Exports {package: package.to_string(), targets: vec![]}).collect(),
+            opens: vec![],
+            packages: exports.iter().map(|package| package.to_string()).collect(),
+            main_class: None,
+        }
+    }
+
+    #[test]
+    fn test_descriptors_are_read_from_modular_jars() {
+        let api = module_jar("api.jar");
+        assert_eq!("com.example.api", api.name);
+        assert_eq!(vec![Exports {package: "com/example/api".to_string(), targets: vec![]}], api.exports);
+        assert_eq!(2, api.packages.len());
+        assert!(api.packages.contains("com/example/api/internal"));
+        assert_eq!(vec![Requires {module: "java.base".to_string(), transitive: false, optional: false}], api.requires);
+        assert!(api.exports("com/example/api", Some("com.example.app")));
+        assert!(!api.exports("com/example/api/internal", None));
+        assert_eq!(vec!["com.example.api".to_string()], module_jar("app.jar").requires.into_iter().map(|required| required.module).filter(|name| name != "java.base").collect::<Vec<_>>());
+
+        let not_a_module = JarSource::open(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/jars/app.jar")).unwrap();
+        assert_eq!(Ok(None), ModuleDescriptor::read(&not_a_module));
+    }
+
+    #[test]
+    fn test_boot_layer_readability() {
+        let layer = ModuleLayer::boot(vec![
+            (module("a", vec![requires("b", false)], &["a"]), LoaderId::APPLICATION),
+            (module("b", vec![requires("c", true)], &["b"]), LoaderId::APPLICATION),
+            (module("c", vec![], &["c"]), LoaderId::APPLICATION),
+        ]).unwrap();
+        assert!(layer.reads(Some("a"), Some("b")));
+        assert!(layer.reads(Some("a"), Some("c"))); // Implied by b's requires transitive.
+        assert!(layer.reads(Some("a"), Some("java.base")));
+        assert!(!layer.reads(Some("c"), Some("a")));
+        assert!(!layer.reads(Some("a"), None));
+        assert!(layer.reads(None, Some("c")));
+
+        assert_eq!(Some("a"), layer.module_of(LoaderId::APPLICATION, "a/Main"));
+        assert_eq!(None, layer.module_of(LoaderId::APPLICATION, "d/Main"));
+        assert_eq!(Some("java.base"), layer.module_of(LoaderId::BOOTSTRAP, "java/lang/Object"));
+        assert_eq!(Ok(()), layer.check_access((LoaderId::APPLICATION, "a/Main"), (LoaderId::BOOTSTRAP, "java/lang/Object")));
+        assert_eq!(
+            Err("class c.C (in module c) cannot access class a.Main (in module a) because module c does not read module a".to_string()),
+            layer.check_access((LoaderId::APPLICATION, "c/C"), (LoaderId::APPLICATION, "a/Main")));
+    }
+
+    #[test]
+    fn test_invalid_boot_layers() {
+        let missing = ModuleLayer::boot(vec![(module("a", vec![requires("b", false)], &[]), LoaderId::APPLICATION)]);
+        assert_eq!(Some(ModuleError::MissingModule{module: "a".to_string(), requires: "b".to_string()}), missing.err());
+        let mut optional = requires("b", false);
+        optional.optional = true;
+        assert!(ModuleLayer::boot(vec![(module("a", vec![optional], &[]), LoaderId::APPLICATION)]).is_ok());
+
+        let split = ModuleLayer::boot(vec![(module("a", vec![], &["p"]), LoaderId::APPLICATION), (module("b", vec![], &["p"]), LoaderId::APPLICATION)]);
+        assert!(matches!(split.err(), Some(ModuleError::SplitPackage{ref package, ..}) if package == "p"));
+        assert!(ModuleLayer::boot(vec![(module("a", vec![], &["p"]), LoaderId::APPLICATION), (module("b", vec![], &["p"]), LoaderId::PLATFORM)]).is_ok());
+    }
+}
@@ -0,0 +1,363 @@
+use crate::descriptor::FieldType;
+use crate::env::Env;
+use crate::heap::ObjectRef;
+use crate::runtime::{RuntimeClass, Value};
+use crate::vm::{Vm, VmError};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Native methods implemented in Rust, bound with Vm::register_native to the methods declared
+// native that they stand in for. Any closure taking an Env and then the method's arguments, with
+// the receiver first for instance methods, can be bound: arguments are converted from Values to
+// the closure's parameter types, and its result back to a Value, by FromValue and IntoValue.
+//
+//     vm.register_native("Counter", "add", "(II)I", |_: &mut Env, a: i32, b: i32| a + b);
+//
+// Natives registered this way take precedence over those the VM provides itself.
+
+pub type NativeFn = Rc<dyn Fn(&mut Env, &[Value]) -> Result<Option<Value>, VmError>>;
+
+#[derive(Default)]
+pub struct NativeRegistry {
+    methods: HashMap<(String, String, String), NativeFn>, // By class name, method name and descriptor.
+}
+
+impl NativeRegistry {
+    pub fn new() -> NativeRegistry {
+        NativeRegistry::default()
+    }
+
+    // Binds the method to the native, replacing any native it was bound to.
+    pub fn register<Args>(&mut self, class: &str, name: &str, descriptor: &str, native: impl Native<Args> + 'static) {
+        let native: NativeFn = Rc::new(move |env: &mut Env, args: &[Value]| native.invoke(env, args));
+        self.methods.insert((class.to_string(), name.to_string(), descriptor.to_string()), native);
+    }
+
+    // Unbinds the method, returning whether it was bound.
+    pub fn unregister(&mut self, class: &str, name: &str, descriptor: &str) -> bool {
+        self.methods.remove(&(class.to_string(), name.to_string(), descriptor.to_string())).is_some()
+    }
+
+    pub fn lookup(&self, class: &str, name: &str, descriptor: &str) -> Option<NativeFn> {
+        self.methods.get(&(class.to_string(), name.to_string(), descriptor.to_string())).cloned()
+    }
+}
+
+// Runs a registered native, checking that what it returns fits the method's descriptor so that a
+// mistake in binding it shows up here rather than as a corrupted operand stack.
+pub fn invoke(vm: &mut Vm, class: &Rc<RuntimeClass>, native: &NativeFn, signature: &str, descriptor: &str, args: &[Value]) -> Result<Option<Value>, VmError> {
+    let descriptor = vm.method_descriptor(descriptor)?;
+    let result = native(&mut Env::new(vm, Rc::clone(class)), args)?;
+    let fits = match (&descriptor.return_type, &result) {
+        (None, None) => true,
+        (Some(return_type), Some(value)) => fits(return_type, value),
+        _ => false,
+    };
+    if !fits {
+        return Err(VmError::NativeSignature(format!("native {} returned {:?}", signature, result)));
+    }
+    Ok(result)
+}
+
+fn fits(field_type: &FieldType, value: &Value) -> bool {
+    match (field_type, value) {
+        (&FieldType::Long, &Value::Long(_)) => true,
+        (&FieldType::Float, &Value::Float(_)) => true,
+        (&FieldType::Double, &Value::Double(_)) => true,
+        (_, &Value::Reference(_)) => field_type.is_reference(),
+        (_, &Value::Int(_)) => matches!(*field_type, FieldType::Boolean | FieldType::Byte | FieldType::Char | FieldType::Short | FieldType::Int),
+        _ => false,
+    }
+}
+
+// A Rust value that a Java argument can be converted to. Conversions fail for Values of the wrong
+// kind, which means the native was bound to a method with a different descriptor.
+pub trait FromValue: Sized {
+    fn from_value(env: &mut Env, value: &Value) -> Result<Self, VmError>;
+}
+
+// A Rust value that can be returned from a native. Natives of void methods return ().
+pub trait IntoValue {
+    fn into_value(self) -> Result<Option<Value>, VmError>;
+}
+
+fn mismatch(expected: &str, value: &Value) -> VmError {
+    VmError::NativeSignature(format!("expected {} argument, got {:?}", expected, value))
+}
+
+impl FromValue for Value {
+    fn from_value(_env: &mut Env, value: &Value) -> Result<Value, VmError> {
+        Ok(value.clone())
+    }
+}
+
+impl FromValue for i32 {
+    fn from_value(_env: &mut Env, value: &Value) -> Result<i32, VmError> {
+        match *value {
+            Value::Int(value) => Ok(value),
+            _ => Err(mismatch("an int", value)),
+        }
+    }
+}
+
+// Java's byte, short and char arguments arrive as ints, and are truncated as the JVM would.
+impl FromValue for i8 {
+    fn from_value(env: &mut Env, value: &Value) -> Result<i8, VmError> {
+        i32::from_value(env, value).map(|value| value as i8)
+    }
+}
+
+impl FromValue for i16 {
+    fn from_value(env: &mut Env, value: &Value) -> Result<i16, VmError> {
+        i32::from_value(env, value).map(|value| value as i16)
+    }
+}
+
+impl FromValue for u16 {
+    fn from_value(env: &mut Env, value: &Value) -> Result<u16, VmError> {
+        i32::from_value(env, value).map(|value| value as u16)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(env: &mut Env, value: &Value) -> Result<bool, VmError> {
+        i32::from_value(env, value).map(|value| value != 0)
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(_env: &mut Env, value: &Value) -> Result<i64, VmError> {
+        match *value {
+            Value::Long(value) => Ok(value),
+            _ => Err(mismatch("a long", value)),
+        }
+    }
+}
+
+impl FromValue for f32 {
+    fn from_value(_env: &mut Env, value: &Value) -> Result<f32, VmError> {
+        match *value {
+            Value::Float(value) => Ok(value),
+            _ => Err(mismatch("a float", value)),
+        }
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(_env: &mut Env, value: &Value) -> Result<f64, VmError> {
+        match *value {
+            Value::Double(value) => Ok(value),
+            _ => Err(mismatch("a double", value)),
+        }
+    }
+}
+
+impl FromValue for Option<ObjectRef> {
+    fn from_value(_env: &mut Env, value: &Value) -> Result<Option<ObjectRef>, VmError> {
+        match *value {
+            Value::Reference(ref object) => Ok(object.clone()),
+            _ => Err(mismatch("a reference", value)),
+        }
+    }
+}
+
+// Natives that take an ObjectRef rather than an Option throw NullPointerException when passed
+// null, as a receiver always is non-null.
+impl FromValue for ObjectRef {
+    fn from_value(env: &mut Env, value: &Value) -> Result<ObjectRef, VmError> {
+        Option::<ObjectRef>::from_value(env, value)?.ok_or_else(|| env.throw_new("java/lang/NullPointerException"))
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Result<Option<Value>, VmError> {
+        Ok(None)
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Result<Option<Value>, VmError> {
+        Ok(Some(self))
+    }
+}
+
+impl IntoValue for i32 {
+    fn into_value(self) -> Result<Option<Value>, VmError> {
+        Ok(Some(Value::Int(self)))
+    }
+}
+
+impl IntoValue for i8 {
+    fn into_value(self) -> Result<Option<Value>, VmError> {
+        Ok(Some(Value::Int(self as i32)))
+    }
+}
+
+impl IntoValue for i16 {
+    fn into_value(self) -> Result<Option<Value>, VmError> {
+        Ok(Some(Value::Int(self as i32)))
+    }
+}
+
+impl IntoValue for u16 {
+    fn into_value(self) -> Result<Option<Value>, VmError> {
+        Ok(Some(Value::Int(self as i32)))
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Result<Option<Value>, VmError> {
+        Ok(Some(Value::Int(self as i32)))
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Result<Option<Value>, VmError> {
+        Ok(Some(Value::Long(self)))
+    }
+}
+
+impl IntoValue for f32 {
+    fn into_value(self) -> Result<Option<Value>, VmError> {
+        Ok(Some(Value::Float(self)))
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Result<Option<Value>, VmError> {
+        Ok(Some(Value::Double(self)))
+    }
+}
+
+impl IntoValue for Option<ObjectRef> {
+    fn into_value(self) -> Result<Option<Value>, VmError> {
+        Ok(Some(Value::Reference(self)))
+    }
+}
+
+impl IntoValue for ObjectRef {
+    fn into_value(self) -> Result<Option<Value>, VmError> {
+        Ok(Some(Value::Reference(Some(self))))
+    }
+}
+
+// Natives throw by returning an error, such as one from Vm::throw_new.
+impl<T: IntoValue> IntoValue for Result<T, VmError> {
+    fn into_value(self) -> Result<Option<Value>, VmError> {
+        self?.into_value()
+    }
+}
+
+// A closure that can be bound to a native method. Args is the tuple of its parameter types after
+// the Env, which only serves to tell the implementations for different arities apart.
+pub trait Native<Args> {
+    fn invoke(&self, env: &mut Env, args: &[Value]) -> Result<Option<Value>, VmError>;
+}
+
+macro_rules! impl_native {
+    ($count:literal $(, $arg:ident)*) => {
+        impl<F, R, $($arg: FromValue),*> Native<($($arg,)*)> for F where F: Fn(&mut Env, $($arg),*) -> R, R: IntoValue {
+            #[allow(non_snake_case)]
+            fn invoke(&self, env: &mut Env, args: &[Value]) -> Result<Option<Value>, VmError> {
+                let ($($arg,)*) = match *args {
+                    [$(ref $arg),*] => ($($arg::from_value(env, $arg)?,)*),
+                    _ => return Err(VmError::NativeSignature(format!("native takes {} arguments, but was passed {}", $count, args.len()))),
+                };
+                (self)(env, $($arg),*).into_value()
+            }
+        }
+    };
+}
+
+impl_native!(0);
+impl_native!(1, A);
+impl_native!(2, A, B);
+impl_native!(3, A, B, C);
+impl_native!(4, A, B, C, D);
+impl_native!(5, A, B, C, D, E);
+impl_native!(6, A, B, C, D, E, G);
+impl_native!(7, A, B, C, D, E, G, H);
+impl_native!(8, A, B, C, D, E, G, H, I);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use std::cell::RefCell;
+
+
+    #[test]
+    fn test_arguments_and_results_are_converted() {
+        let mut vm = vm_with(&[fixture!("Natives")]);
+        vm.register_native("Natives", "add", "(II)I", |_: &mut Env, a: i32, b: i32| a + b);
+        vm.register_native("Natives", "scale", "(JD)J", |_: &mut Env, value: i64, factor: f64| (value as f64 * factor) as i64);
+        vm.register_native("Natives", "isEven", "(I)Z", |_: &mut Env, value: i32| value % 2 == 0);
+        vm.register_native("Natives", "upper", "(C)C", |_: &mut Env, c: u16| c - 32);
+        vm.register_native("Natives", "echo", "(Ljava/lang/Object;)Ljava/lang/Object;", |_: &mut Env, value: Option<ObjectRef>| value);
+
+        assert_eq!(Ok(Some(Value::Int(25))), vm.invoke_static("Natives", "sumOfSquares", "(II)I", vec![Value::Int(3), Value::Int(4)]));
+        assert_eq!(Ok(Some(Value::Long(15))), vm.invoke_static("Natives", "scale", "(JD)J", vec![Value::Long(10), Value::Double(1.5)]));
+        assert_eq!(Ok(Some(Value::Int(1))), vm.invoke_static("Natives", "isEven", "(I)Z", vec![Value::Int(4)]));
+        assert_eq!(Ok(Some(Value::Int('Q' as i32))), vm.invoke_static("Natives", "upper", "(C)C", vec![Value::Int('q' as i32)]));
+        let string = vm.new_string("echo").unwrap();
+        assert_eq!(Ok(Some(Value::Reference(Some(string.clone())))), vm.invoke_static("Natives", "echo", "(Ljava/lang/Object;)Ljava/lang/Object;", vec![Value::Reference(Some(string))]));
+    }
+
+    #[test]
+    fn test_instance_natives_get_the_receiver() {
+        let mut vm = vm_with(&[fixture!("Natives")]);
+        vm.register_native("Natives", "self", "()LNatives;", |_: &mut Env, this: ObjectRef| this);
+        assert_eq!(Ok(Some(Value::Int(1))), vm.invoke_static("Natives", "selfIsSame", "()Z", vec![]));
+    }
+
+    #[test]
+    fn test_void_natives_and_closures() {
+        let mut vm = vm_with(&[fixture!("Natives")]);
+        let recorded = Rc::new(RefCell::new(vec![]));
+        let sink = Rc::clone(&recorded);
+        vm.register_native("Natives", "record", "(Ljava/lang/Object;)V", move |_: &mut Env, value: Option<ObjectRef>| sink.borrow_mut().push(value.is_some()));
+        assert_eq!(Ok(None), vm.invoke_static("Natives", "record", "(Ljava/lang/Object;)V", vec![Value::null()]));
+        assert_eq!(vec![false], *recorded.borrow());
+    }
+
+    #[test]
+    fn test_natives_can_throw() {
+        let mut vm = vm_with(&[fixture!("Natives")]);
+        vm.register_native("Natives", "add", "(II)I", |env: &mut Env, _: i32, _: i32| -> Result<i32, VmError> {
+            Err(env.throw_new("java/lang/ArithmeticException"))
+        });
+        match vm.invoke_static("Natives", "sumOfSquares", "(II)I", vec![Value::Int(1), Value::Int(2)]) {
+            Err(VmError::UncaughtException(exception)) => assert_eq!("java/lang/ArithmeticException", exception.class().name),
+            other => panic!("Expected ArithmeticException; got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_natives_that_dont_fit_the_descriptor_are_reported() {
+        let mut vm = vm_with(&[fixture!("Natives")]);
+        vm.register_native("Natives", "add", "(II)I", |_: &mut Env, a: i64| a);
+        assert!(matches!(vm.invoke_static("Natives", "add", "(II)I", vec![Value::Int(1), Value::Int(2)]), Err(VmError::NativeSignature(_))));
+        vm.register_native("Natives", "add", "(II)I", |_: &mut Env, a: i32, b: i32| (a + b) as i64);
+        assert!(matches!(vm.invoke_static("Natives", "add", "(II)I", vec![Value::Int(1), Value::Int(2)]), Err(VmError::NativeSignature(_))));
+        vm.register_native("Natives", "add", "(II)I", |_: &mut Env, _: f32, _: i32| 0);
+        assert!(matches!(vm.invoke_static("Natives", "add", "(II)I", vec![Value::Int(1), Value::Int(2)]), Err(VmError::NativeSignature(_))));
+    }
+
+    #[test]
+    fn test_unregistered_natives_are_unsupported() {
+        let mut vm = vm_with(&[fixture!("Natives")]);
+        assert!(matches!(vm.invoke_static("Natives", "unbound", "()I", vec![]), Err(VmError::Unsupported(_))));
+        vm.register_native("Natives", "unbound", "()I", |_: &mut Env| 7);
+        assert_eq!(Ok(Some(Value::Int(7))), vm.invoke_static("Natives", "unbound", "()I", vec![]));
+        assert!(vm.unregister_native("Natives", "unbound", "()I"));
+        assert!(matches!(vm.invoke_static("Natives", "unbound", "()I", vec![]), Err(VmError::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_registered_natives_replace_the_vms_own() {
+        let mut vm = vm_with(&[fixture!("Natives")]);
+        vm.register_native("java/lang/System", "identityHashCode", "(Ljava/lang/Object;)I", |_: &mut Env, _: Option<ObjectRef>| 42);
+        let string = vm.new_string("hash").unwrap();
+        assert_eq!(Ok(Some(Value::Int(42))), vm.invoke_static("java/lang/System", "identityHashCode", "(Ljava/lang/Object;)I", vec![Value::Reference(Some(string))]));
+    }
+}
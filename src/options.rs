@@ -0,0 +1,239 @@
+use crate::classpath::Classpath;
+use crate::gclog::{self, GcLog};
+use crate::limits::Limits;
+use crate::runtime::RuntimeClass;
+use crate::vm::{Vm, VmError, DEFAULT_MAX_STACK_DEPTH};
+use std::path::Path;
+
+// Roughly how much of a thread's stack a Java frame takes, for turning a stack size in bytes, as
+// -Xss gives it, into a limit on the number of frames.
+pub const STACK_BYTES_PER_FRAME: u64 = 1024 * 1024 / DEFAULT_MAX_STACK_DEPTH as u64;
+
+// The settings the java launcher takes as flags, for building a VM in one go. Each setting that
+// isn't given is left as Vm::new has it.
+//
+//     let vm = VmOptions::new().classpath("app.jar").property("mode", "fast").max_heap(64 << 20).build()?;
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VmOptions {
+    classpath: Option<String>,
+    properties: Vec<(String, String)>,
+    max_heap: Option<u64>,
+    stack_size: Option<u64>,
+    assertions: bool,
+    verbose_class: bool,
+    verbose_gc: bool,
+    limits: Limits,
+}
+
+impl VmOptions {
+    pub fn new() -> VmOptions {
+        VmOptions::default()
+    }
+
+    // Directories and JARs to load classes from, separated as in the platform's PATH, like -cp.
+    // It's also given to the program as the java.class.path property.
+    pub fn classpath(mut self, classpath: &str) -> VmOptions {
+        self.classpath = Some(classpath.to_string());
+        self
+    }
+
+    // Sets a system property, like -Dkey=value. Later values for a key replace earlier ones.
+    pub fn property(mut self, key: &str, value: &str) -> VmOptions {
+        self.properties.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    // Limits the size of the heap in bytes, like -Xmx. See Vm::set_max_heap.
+    pub fn max_heap(mut self, bytes: u64) -> VmOptions {
+        self.max_heap = Some(bytes);
+        self
+    }
+
+    // Sets the size of the Java stack in bytes, like -Xss, which bounds how deeply calls nest.
+    pub fn stack_size(mut self, bytes: u64) -> VmOptions {
+        self.stack_size = Some(bytes);
+        self
+    }
+
+    // Checks assert statements outside the platform classes, like -ea.
+    pub fn enable_assertions(mut self, enabled: bool) -> VmOptions {
+        self.assertions = enabled;
+        self
+    }
+
+    // Prints each class as it's loaded on stdout, like -verbose:class.
+    pub fn verbose_class(mut self, verbose: bool) -> VmOptions {
+        self.verbose_class = verbose;
+        self
+    }
+
+    // Logs each garbage collection on stdout as -Xlog:gc does, like -verbose:gc.
+    pub fn verbose_gc(mut self, verbose: bool) -> VmOptions {
+        self.verbose_gc = verbose;
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> VmOptions {
+        self.limits = limits;
+        self
+    }
+
+    pub fn build(self) -> Result<Vm, VmError> {
+        let mut vm = Vm::new();
+        if let Some(ref classpath) = self.classpath {
+            vm.set_classpath(Classpath::parse(classpath)?);
+            vm.set_property("java.class.path", classpath);
+        }
+        for (key, value) in &self.properties {
+            vm.set_property(key, value);
+        }
+        vm.set_max_heap(self.max_heap);
+        if let Some(bytes) = self.stack_size {
+            vm.set_max_stack_depth((bytes / STACK_BYTES_PER_FRAME).max(1) as usize);
+        }
+        vm.set_assertions_enabled(self.assertions);
+        if self.verbose_class {
+            vm.set_class_load_listener(Some(Box::new(print_class_load)));
+        }
+        if self.verbose_gc {
+            vm.subscribe(gclog::EVENTS, Box::new(GcLog::to_stdout(false)));
+        }
+        vm.set_limits(self.limits);
+        Ok(vm)
+    }
+}
+
+// Prints a class load as HotSpot's -verbose:class did before unified logging.
+fn print_class_load(class: &RuntimeClass, origin: Option<&Path>) {
+    match origin {
+        Some(origin) => println!("[Loaded {} from {}]", class.java_name(), origin.display()),
+        None => println!("[Loaded {}]", class.java_name()),
+    }
+}
+
+// Parses a size in bytes as the launcher's memory flags take it: a number, optionally followed by
+// k, m or g, in either case, for kibibytes, mebibytes or gibibytes.
+pub fn parse_size(size: &str) -> Option<u64> {
+    let (digits, unit) = match size.char_indices().last()? {
+        (index, 'k') | (index, 'K') => (&size[..index], 1 << 10),
+        (index, 'm') | (index, 'M') => (&size[..index], 1 << 20),
+        (index, 'g') | (index, 'G') => (&size[..index], 1 << 30),
+        _ => (size, 1),
+    };
+    if !digits.bytes().all(|digit| digit.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+    use crate::runtime::Value;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // The class and message of the exception that escaped.
+    fn thrown(vm: &Vm, result: Result<Option<Value>, VmError>) -> (String, Option<String>) {
+        match result {
+            Err(VmError::UncaughtException(exception)) => {
+                let message = match crate::vm::get_field(&exception, "detailMessage") {
+                    Ok(Value::Reference(Some(message))) => Some(vm.read_string(&message).unwrap()),
+                    _ => None,
+                };
+                (exception.class().name.to_string(), message)
+            },
+            other => panic!("Expected an exception; got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(Some(512), parse_size("512"));
+        assert_eq!(Some(64 << 10), parse_size("64k"));
+        assert_eq!(Some(256 << 20), parse_size("256M"));
+        assert_eq!(Some(2 << 30), parse_size("2g"));
+        assert_eq!(None, parse_size(""));
+        assert_eq!(None, parse_size("m"));
+        assert_eq!(None, parse_size("-1m"));
+        assert_eq!(None, parse_size("1.5g"));
+        assert_eq!(None, parse_size("99999999999999g"));
+    }
+
+    #[test]
+    fn test_build_applies_options() {
+        let classpath = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata");
+        let vm = VmOptions::new()
+            .classpath(classpath)
+            .property("a", "first")
+            .property("a", "second")
+            .max_heap(1 << 20)
+            .stack_size(STACK_BYTES_PER_FRAME * 100)
+            .limits(Limits {max_instructions: Some(1000), ..Limits::default()})
+            .build()
+            .unwrap();
+        assert_eq!(1, vm.classpath().len());
+        assert_eq!(Some(classpath), vm.property("java.class.path"));
+        assert_eq!(Some("second"), vm.property("a"));
+        assert_eq!(Some(1 << 20), vm.max_heap());
+        assert_eq!(100, vm.max_stack_depth());
+        assert_eq!(Some(1000), vm.limits().max_instructions);
+
+        let vm = VmOptions::new().build().unwrap();
+        assert!(vm.classpath().is_empty());
+        assert_eq!(None, vm.property("java.class.path"));
+        assert_eq!(None, vm.max_heap());
+        assert_eq!(DEFAULT_MAX_STACK_DEPTH, vm.max_stack_depth());
+    }
+
+    #[test]
+    fn test_assertions() {
+        let mut vm = VmOptions::new().build().unwrap();
+        vm.add_class(fixture!("Asserts")).unwrap();
+        assert_eq!(Ok(Some(Value::Int(0))), vm.invoke_static("Asserts", "enabled", "()Z", vec![]));
+        assert_eq!(Ok(None), vm.invoke_static("Asserts", "check", "(I)V", vec![Value::Int(-1)]));
+
+        let mut vm = VmOptions::new().enable_assertions(true).build().unwrap();
+        vm.add_class(fixture!("Asserts")).unwrap();
+        assert_eq!(Ok(Some(Value::Int(1))), vm.invoke_static("Asserts", "enabled", "()Z", vec![]));
+        assert_eq!(Ok(None), vm.invoke_static("Asserts", "check", "(I)V", vec![Value::Int(1)]));
+        let result = vm.invoke_static("Asserts", "check", "(I)V", vec![Value::Int(-1)]);
+        assert_eq!(("java/lang/AssertionError".to_string(), Some("not positive".to_string())), thrown(&vm, result));
+        let result = vm.invoke_static("Asserts", "fail", "()V", vec![]);
+        assert_eq!(("java/lang/AssertionError".to_string(), None), thrown(&vm, result));
+    }
+
+    #[test]
+    fn test_max_heap() {
+        let mut vm = VmOptions::new().max_heap(256 << 10).build().unwrap();
+        vm.add_class(fixture!("FillsHeap")).unwrap();
+        assert_eq!(Ok(Some(Value::Int(1000))), vm.invoke_static("FillsHeap", "churn", "(I)I", vec![Value::Int(1000)]));
+        assert_eq!(Ok(Some(Value::Int(100))), vm.invoke_static("FillsHeap", "retain", "(I)I", vec![Value::Int(100)]));
+        let result = vm.invoke_static("FillsHeap", "retain", "(I)I", vec![Value::Int(1000)]);
+        assert_eq!(("java/lang/OutOfMemoryError".to_string(), Some("Java heap space".to_string())), thrown(&vm, result));
+
+        // The retained arrays are freed once the exception escapes, so the heap is usable again.
+        assert_eq!(Ok(Some(Value::Int(100))), vm.invoke_static("FillsHeap", "retain", "(I)I", vec![Value::Int(100)]));
+        assert_eq!(Ok(Some(Value::Int(-1))), vm.invoke_static("FillsHeap", "retainOrGiveUp", "(I)I", vec![Value::Int(1000)]));
+    }
+
+    #[test]
+    fn test_verbose_listeners() {
+        let loaded = Rc::new(RefCell::new(vec![]));
+        let mut vm = VmOptions::new().classpath(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata")).build().unwrap();
+        vm.load_class("java/lang/Error").unwrap();
+        vm.load_class("[I").unwrap();
+        let recorded = Rc::clone(&loaded);
+        vm.set_class_load_listener(Some(Box::new(move |class: &RuntimeClass, origin: Option<&Path>| {
+            recorded.borrow_mut().push((class.name.to_string(), origin.map(|origin| origin.ends_with("testdata"))));
+        })));
+        vm.load_class("Asserts").unwrap();
+        vm.load_class("[LAsserts;").unwrap();
+        assert_eq!(vec![("Asserts".to_string(), Some(true))], *loaded.borrow());
+
+        loaded.borrow_mut().clear();
+        vm.load_class("java/lang/AssertionError").unwrap();
+        assert_eq!(vec![("java/lang/AssertionError".to_string(), None)], *loaded.borrow());
+    }
+}
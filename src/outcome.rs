@@ -0,0 +1,196 @@
+use crate::classes::MethodFlags;
+use crate::limits::LimitViolation;
+use crate::heap::ObjectRef;
+use crate::runtime::*;
+use crate::vm::{self, ActiveFrame, Vm, VmError};
+use std::fmt;
+
+// How a Java program run with Vm::run_main finished.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Completed, // main returned normally.
+    Exited(i32), // The program called System.exit or Runtime.halt, perhaps from a shutdown hook.
+    Threw(UncaughtThrowable), // An exception escaped main.
+    LimitExceeded(LimitViolation), // The program was stopped for using too many resources.
+}
+
+impl Outcome {
+    // The process exit status a Java launcher would use for this outcome.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Outcome::Completed => 0,
+            Outcome::Exited(status) => status,
+            Outcome::Threw(_) | Outcome::LimitExceeded(_) => 1,
+        }
+    }
+}
+
+// A callback for Vm::set_uncaught_handler, given the name of the thread a throwable escaped and
+// the throwable.
+pub type UncaughtHandler = Box<dyn FnMut(&str, &UncaughtThrowable)>;
+
+// A snapshot of a Throwable that escaped from Java code, so that embedders can report it without
+// poking at the VM's objects. The original object is kept in case they need more than this.
+#[derive(Debug, PartialEq)]
+pub struct UncaughtThrowable {
+    pub class_name: String,
+    pub message: Option<String>,
+    pub stack_trace: Vec<StackTraceElement>,
+    pub cause: Option<Box<UncaughtThrowable>>,
+    pub throwable: ObjectRef,
+}
+
+impl UncaughtThrowable {
+    pub fn from_object(vm: &Vm, throwable: &ObjectRef) -> Result<UncaughtThrowable, VmError> {
+        UncaughtThrowable::from_chain(vm, throwable, &mut vec![])
+    }
+
+    // Follows the chain of causes, stopping if it loops back on itself.
+    fn from_chain(vm: &Vm, throwable: &ObjectRef, seen: &mut Vec<ObjectRef>) -> Result<UncaughtThrowable, VmError> {
+        seen.push(throwable.clone());
+        let cause = match vm::get_field(throwable, "cause")? {
+            Value::Reference(Some(ref cause)) if !seen.contains(cause) => Some(Box::new(UncaughtThrowable::from_chain(vm, cause, seen)?)),
+            _ => None,
+        };
+
+        let stack_trace = match vm::get_field(throwable, "stackTrace")? {
+            Value::Reference(Some(elements)) => elements.fields.borrow().values().unwrap_or_default().iter()
+                .filter_map(|element| match *element {
+                    Value::Reference(Some(ref element)) => Some(StackTraceElement::from_object(vm, element)),
+                    _ => None,
+                })
+                .collect::<Result<_, _>>()?,
+            _ => vec![],
+        };
+
+        Ok(UncaughtThrowable {
+            class_name: throwable.class().java_name(),
+            message: read_optional_string(vm, throwable, "detailMessage")?,
+            stack_trace,
+            cause,
+            throwable: throwable.clone(),
+        })
+    }
+}
+
+// Formats the throwable as Throwable.printStackTrace does.
+impl fmt::Display for UncaughtThrowable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.message {
+            Some(ref message) => write!(f, "{}: {}", self.class_name, message)?,
+            None => write!(f, "{}", self.class_name)?,
+        }
+        for element in &self.stack_trace {
+            write!(f, "\n\tat {}", element)?;
+        }
+        if let Some(ref cause) = self.cause {
+            write!(f, "\nCaused by: {}", cause)?;
+        }
+        Ok(())
+    }
+}
+
+// One frame of a Java stack trace, as recorded when the throwable was created.
+#[derive(Debug, PartialEq, Eq)]
+pub struct StackTraceElement {
+    pub class_name: String,
+    pub method_name: String,
+    pub file_name: Option<String>,
+    pub line_number: Option<u16>,
+    pub is_native: bool,
+}
+
+impl StackTraceElement {
+    fn from_object(vm: &Vm, element: &ObjectRef) -> Result<StackTraceElement, VmError> {
+        let line_number = match vm::get_field(element, "lineNumber")? {
+            Value::Int(line_number) => line_number,
+            other => return Err(VmError::InvalidBytecode(format!("StackTraceElement has invalid line number {:?}", other))),
+        };
+
+        Ok(StackTraceElement {
+            class_name: read_optional_string(vm, element, "declaringClass")?.unwrap_or_default(),
+            method_name: read_optional_string(vm, element, "methodName")?.unwrap_or_default(),
+            file_name: read_optional_string(vm, element, "fileName")?,
+            line_number: if line_number >= 0 { Some(line_number as u16) } else { None },
+            is_native: line_number == NATIVE_LINE_NUMBER,
+        })
+    }
+
+    // Where a frame that's still running is, for a stack dump.
+    pub fn from_frame(frame: &ActiveFrame) -> StackTraceElement {
+        let class = &frame.class.class;
+        let method = &class.methods[frame.method_index];
+        StackTraceElement {
+            class_name: frame.class.java_name(),
+            method_name: class.utf8(&method.name).unwrap_or("<unknown>").to_string(),
+            file_name: class.source_file().map(str::to_string),
+            line_number: method.code().and_then(|code| code.line_number(frame.pc)),
+            is_native: method.flags.contains(MethodFlags::NATIVE),
+        }
+    }
+}
+
+// Java's StackTraceElement marks native frames with this line number, and unknown lines with -1.
+pub const NATIVE_LINE_NUMBER: i32 = -2;
+pub const UNKNOWN_LINE_NUMBER: i32 = -1;
+
+impl fmt::Display for StackTraceElement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}(", self.class_name, self.method_name)?;
+        match (&self.file_name, self.line_number) {
+            _ if self.is_native => write!(f, "Native Method")?,
+            (Some(ref file_name), Some(line_number)) => write!(f, "{}:{}", file_name, line_number)?,
+            (Some(ref file_name), None) => write!(f, "{}", file_name)?,
+            (None, _) => write!(f, "Unknown Source")?,
+        }
+        write!(f, ")")
+    }
+}
+
+fn read_optional_string(vm: &Vm, object: &ObjectRef, field: &str) -> Result<Option<String>, VmError> {
+    match vm::get_field(object, field)? {
+        Value::Reference(Some(string)) => Ok(Some(vm.read_string(&string)?)),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(file_name: Option<&str>, line_number: Option<u16>, is_native: bool) -> StackTraceElement {
+        StackTraceElement {
+            class_name: "pkg.Foo".to_string(),
+            method_name: "bar".to_string(),
+            file_name: file_name.map(str::to_string),
+            line_number,
+            is_native,
+        }
+    }
+
+    #[test]
+    fn test_format_stack_trace_element_with_line() {
+        assert_eq!("pkg.Foo.bar(Foo.java:12)", element(Some("Foo.java"), Some(12), false).to_string());
+    }
+
+    #[test]
+    fn test_format_stack_trace_element_without_line() {
+        assert_eq!("pkg.Foo.bar(Foo.java)", element(Some("Foo.java"), None, false).to_string());
+    }
+
+    #[test]
+    fn test_format_stack_trace_element_without_source() {
+        assert_eq!("pkg.Foo.bar(Unknown Source)", element(None, None, false).to_string());
+    }
+
+    #[test]
+    fn test_format_native_stack_trace_element() {
+        assert_eq!("pkg.Foo.bar(Native Method)", element(Some("Foo.java"), None, true).to_string());
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(0, Outcome::Completed.exit_code());
+        assert_eq!(3, Outcome::Exited(3).exit_code());
+    }
+}
@@ -0,0 +1,175 @@
+use crate::events::Event;
+use crate::vm::{ActiveFrame, Vm};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Often enough to see where a run of a second or so goes, without slowing it noticeably.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(10);
+
+// Samples the Java stacks of every thread at a regular interval, to show where the program spends
+// its time. Start it with Vm::start_profiler and stop it with Vm::stop_profiler, which returns
+// what it found. Each sample is also posted as an event, for subscribers to method samples.
+//
+// A host thread wakes at each interval and arms the safepoint poll, and the running thread takes
+// the sample when it next polls. Samples are therefore only taken between instructions, which
+// skews them towards the instructions after long ones, and not while compiled code is running or
+// while no Java code is. With cooperative threads, each sample also counts towards the running
+// thread's timeslice.
+pub struct Profiler {
+    due: Arc<AtomicBool>,
+    stop: Option<Sender<()>>, // Dropped to stop the timer.
+    timer: Option<JoinHandle<()>>,
+    profile: Profile,
+}
+
+impl Profiler {
+    pub fn start(interval: Duration, poll: Arc<AtomicBool>) -> Profiler {
+        let due = Arc::new(AtomicBool::new(false));
+        let (stop, stopped) = mpsc::channel::<()>();
+        let timer_due = Arc::clone(&due);
+        let timer = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                timer_due.store(true, Ordering::Relaxed);
+                poll.store(true, Ordering::Relaxed);
+            }
+        });
+        Profiler {due, stop: Some(stop), timer: Some(timer), profile: Profile::default()}
+    }
+
+    #[inline]
+    pub fn is_due(&self) -> bool {
+        self.due.load(Ordering::Relaxed)
+    }
+
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    // Stops the timer, returning everything sampled.
+    pub fn finish(mut self) -> Profile {
+        self.stop_timer();
+        std::mem::take(&mut self.profile)
+    }
+
+    fn stop_timer(&mut self) {
+        self.stop.take();
+        if let Some(timer) = self.timer.take() {
+            let _ = timer.join();
+        }
+    }
+}
+
+impl Drop for Profiler {
+    fn drop(&mut self) {
+        self.stop_timer();
+    }
+}
+
+// Takes a sample of every thread's stack, if one is due. Called by the running thread when it sees
+// the safepoint poll.
+pub fn poll(vm: &mut Vm) {
+    if !vm.profiler().is_some_and(Profiler::is_due) {
+        return;
+    }
+    let stacks = vm.thread_stacks();
+    if let Some(profiler) = vm.profiler_mut() {
+        profiler.due.store(false, Ordering::Relaxed);
+        for (_, frames) in &stacks {
+            profiler.profile.record(frames);
+        }
+    }
+    for (thread, frames) in &stacks {
+        if !frames.is_empty() {
+            vm.post_event(&Event::MethodSample{thread: *thread, frames});
+        }
+    }
+}
+
+// How often each distinct stack was seen, across all threads.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    stacks: BTreeMap<String, u64>, // Frames from the outermost in, separated by semicolons.
+    samples: u64,
+}
+
+impl Profile {
+    // The number of stacks sampled: one for each thread that was running Java code, each time.
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    pub fn stacks(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.stacks.iter().map(|(stack, &count)| (stack.as_str(), count))
+    }
+
+    // Frames are innermost first, as Vm::thread_stacks gives them.
+    fn record(&mut self, frames: &[ActiveFrame]) {
+        if frames.is_empty() {
+            return;
+        }
+        let names: Vec<String> = frames.iter().rev().map(|frame| {
+            let method = &frame.class.class.methods[frame.method_index];
+            format!("{}.{}", frame.class.name, frame.class.class.utf8(&method.name).unwrap_or("<unknown>"))
+        }).collect();
+        *self.stacks.entry(names.join(";")).or_insert(0) += 1;
+        self.samples += 1;
+    }
+}
+
+// Folded stacks, one per line with its count, as flamegraph.pl and speedscope read them:
+//
+//     Main.main;Main.work;java/lang/Math.sqrt 12
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (stack, count) in &self.stacks {
+            writeln!(f, "{} {}", stack, count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use crate::runtime::Value;
+    use std::time::Instant;
+
+
+    #[test]
+    fn test_samples_running_code() {
+        let mut vm = vm_with(&[fixture!("Recursion")]);
+        vm.start_profiler(Duration::from_millis(1));
+        // Samples depend on timing, so keep going until there are some.
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while vm.profiler().unwrap().profile().samples() == 0 && Instant::now() < deadline {
+            vm.invoke_static("Recursion", "sum", "(I)I", vec![Value::Int(50)]).unwrap();
+        }
+        let profile = vm.stop_profiler().unwrap();
+        assert!(profile.samples() > 0);
+        assert!(vm.stop_profiler().is_none());
+
+        let folded = profile.to_string();
+        for line in folded.lines() {
+            assert!(line.starts_with("Recursion.sum"), "Unexpected stack {}", line);
+            let count: u64 = line.rsplit(' ').next().unwrap().parse().unwrap();
+            assert!(count > 0);
+        }
+        assert_eq!(profile.samples(), profile.stacks().map(|(_, count)| count).sum::<u64>());
+    }
+
+    #[test]
+    fn test_no_samples_while_idle() {
+        let mut vm = vm_with(&[fixture!("Recursion")]);
+        vm.start_profiler(Duration::from_millis(1));
+        thread::sleep(Duration::from_millis(20));
+        let profile = vm.stop_profiler().unwrap();
+        assert_eq!(Profile::default(), profile);
+        assert_eq!("", profile.to_string());
+    }
+}
@@ -0,0 +1,529 @@
+use crate::events::{Event, EventKinds, SubscriptionId};
+use crate::gc::GcCause;
+use crate::runtime::RuntimeClass;
+use crate::threads::ThreadId;
+use crate::vm::Vm;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+// Recordings start with this, followed by the version of the format.
+const MAGIC: &[u8] = b"JOYREC";
+const VERSION: u8 = 1;
+
+// Each record is one of these tags followed by its fields. Numbers are unsigned LEB128, so small
+// ones take a byte, and times are nanoseconds since the recording started. Strings such as class
+// names are written once, in a string record giving them an id, and referred to by id after that.
+const STRING: u8 = 1; // id, length, UTF-8
+const CLASS_LOAD: u8 = 2; // time, class, origin + 1 or 0 for none
+const GC_START: u8 = 3; // time, cause
+const GC_FINISH: u8 = 4; // time, id, pause, bytes before, bytes after, unloaded classes
+const ALLOCATION_SAMPLE: u8 = 5; // time, thread, class, size
+const METHOD_SAMPLE: u8 = 6; // time, thread, frame count, then the method and pc of each
+
+// Which events a recording takes.
+const RECORDED: EventKinds = EventKinds::from_bits_truncate(EventKinds::CLASS_LOAD.bits() | EventKinds::GC_START.bits()
+    | EventKinds::GC_FINISH.bits() | EventKinds::ALLOCATION_SAMPLE.bits() | EventKinds::METHOD_SAMPLE.bits());
+
+// Writes class loads, collections, allocation samples and method samples to a compact binary
+// stream as they happen, for long runs that would produce too much text to log. Read the stream
+// back with RecordingReader. Recording with Recording::start takes care of subscribing it and
+// running the profiler, which takes the method samples.
+pub struct FlightRecorder {
+    output: Box<dyn Write>,
+    start: Instant,
+    strings: HashMap<String, u64>,
+    record: Vec<u8>, // The record being encoded.
+    error: Option<io::Error>, // Recording stops at the first write that fails.
+}
+
+impl FlightRecorder {
+    pub fn new(mut output: Box<dyn Write>) -> io::Result<FlightRecorder> {
+        output.write_all(MAGIC)?;
+        output.write_all(&[VERSION])?;
+        Ok(FlightRecorder {output, start: Instant::now(), strings: HashMap::new(), record: vec![], error: None})
+    }
+
+    pub fn to_file(path: &Path) -> io::Result<FlightRecorder> {
+        FlightRecorder::new(Box::new(BufWriter::new(File::create(path)?)))
+    }
+
+    // Flushes the output, returning the first error writing it if there was one.
+    pub fn finish(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.output.flush(),
+        }
+    }
+
+    fn record(&mut self, event: &Event) {
+        let time = self.start.elapsed().as_nanos() as u64;
+        // Strings are looked up first, since any new ones need writing before the record.
+        let mut record = std::mem::take(&mut self.record);
+        record.clear();
+        match *event {
+            Event::ClassLoad{class, origin} => {
+                let class = self.string(&class.name);
+                let origin = origin.map_or(0, |origin| self.string(&origin.display().to_string()) + 1);
+                encode(&mut record, CLASS_LOAD, &[time, class, origin]);
+            },
+            Event::GcStart{cause, ..} => encode(&mut record, GC_START, &[time, encode_cause(cause)]),
+            Event::GcFinish{stats} => encode(&mut record, GC_FINISH, &[
+                time,
+                stats.id,
+                stats.pause.as_nanos() as u64,
+                stats.bytes_before,
+                stats.bytes_after,
+                stats.unloaded_classes as u64,
+            ]),
+            Event::AllocationSample{thread, object, size} => {
+                let class = self.string(&object.class().name);
+                encode(&mut record, ALLOCATION_SAMPLE, &[time, thread, class, size]);
+            },
+            Event::MethodSample{thread, frames} => {
+                encode(&mut record, METHOD_SAMPLE, &[time, thread, frames.len() as u64]);
+                for frame in frames {
+                    let method = self.string(&method_name(&frame.class, frame.method_index));
+                    write_number(&mut record, method);
+                    write_number(&mut record, frame.pc as u64);
+                }
+            },
+            _ => (),
+        }
+        self.write(&record);
+        self.record = record;
+    }
+
+    // The string's id, writing it out if it's new.
+    fn string(&mut self, text: &str) -> u64 {
+        if let Some(&id) = self.strings.get(text) {
+            return id;
+        }
+        let id = self.strings.len() as u64;
+        self.strings.insert(text.to_string(), id);
+        let mut record = vec![STRING];
+        write_number(&mut record, id);
+        write_number(&mut record, text.len() as u64);
+        record.extend_from_slice(text.as_bytes());
+        self.write(&record);
+        id
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        if self.error.is_none() && !bytes.is_empty() {
+            if let Err(err) = self.output.write_all(bytes) {
+                self.error = Some(err);
+            }
+        }
+    }
+}
+
+fn method_name(class: &RuntimeClass, method_index: usize) -> String {
+    let method = &class.class.methods[method_index];
+    let name = class.class.utf8(&method.name).unwrap_or("<unknown>");
+    let descriptor = class.class.utf8(&method.descriptor).unwrap_or("");
+    format!("{}.{}{}", class.name, name, descriptor)
+}
+
+fn encode(record: &mut Vec<u8>, tag: u8, fields: &[u64]) {
+    record.push(tag);
+    for &field in fields {
+        write_number(record, field);
+    }
+}
+
+fn write_number(output: &mut Vec<u8>, mut number: u64) {
+    while number >= 0x80 {
+        output.push((number & 0x7f) as u8 | 0x80);
+        number >>= 7;
+    }
+    output.push(number as u8);
+}
+
+fn encode_cause(cause: GcCause) -> u64 {
+    match cause {
+        GcCause::Requested => 0,
+        GcCause::SystemGc => 1,
+        GcCause::DirectMemory => 2,
+        GcCause::AllocationFailure => 3,
+        GcCause::HeapInspection => 4,
+    }
+}
+
+fn decode_cause(cause: u64) -> Option<GcCause> {
+    match cause {
+        0 => Some(GcCause::Requested),
+        1 => Some(GcCause::SystemGc),
+        2 => Some(GcCause::DirectMemory),
+        3 => Some(GcCause::AllocationFailure),
+        4 => Some(GcCause::HeapInspection),
+        _ => None,
+    }
+}
+
+// A flight recorder subscribed to a VM. Method samples are taken by the profiler, which is
+// started for the recording unless it's already running, in which case it keeps its interval.
+pub struct Recording {
+    recorder: Rc<RefCell<FlightRecorder>>,
+    subscription: SubscriptionId,
+    started_profiler: bool,
+}
+
+impl Recording {
+    pub fn start(vm: &mut Vm, recorder: FlightRecorder, sample_interval: Duration) -> Recording {
+        let recorder = Rc::new(RefCell::new(recorder));
+        let subscribed = Rc::clone(&recorder);
+        let subscription = vm.subscribe(RECORDED, Box::new(move |event: &Event| subscribed.borrow_mut().record(event)));
+        let started_profiler = vm.profiler().is_none();
+        if started_profiler {
+            vm.start_profiler(sample_interval);
+        }
+        Recording {recorder, subscription, started_profiler}
+    }
+
+    // Unsubscribes the recorder and flushes what it recorded.
+    pub fn stop(self, vm: &mut Vm) -> io::Result<()> {
+        vm.unsubscribe(self.subscription);
+        if self.started_profiler {
+            vm.stop_profiler();
+        }
+        let result = self.recorder.borrow_mut().finish();
+        result
+    }
+}
+
+// An event read back from a recording, with its time since the recording started.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Record {
+    ClassLoad{time: Duration, class: String, origin: Option<String>},
+    GcStart{time: Duration, cause: GcCause},
+    GcFinish{time: Duration, id: u64, pause: Duration, bytes_before: u64, bytes_after: u64, unloaded_classes: u64},
+    AllocationSample{time: Duration, thread: ThreadId, class: String, size: u64},
+    MethodSample{time: Duration, thread: ThreadId, frames: Vec<SampledFrame>}, // Innermost first.
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SampledFrame {
+    pub method: String, // The class, name and descriptor, as in Main.run(I)V.
+    pub pc: usize,
+}
+
+impl Record {
+    pub fn time(&self) -> Duration {
+        match *self {
+            Record::ClassLoad{time, ..} | Record::GcStart{time, ..} | Record::GcFinish{time, ..}
+                | Record::AllocationSample{time, ..} | Record::MethodSample{time, ..} => time,
+        }
+    }
+}
+
+// A line per record, as joyvm recording prints them:
+//
+//     12.345ms gc finish #0, 2048 -> 1024 bytes in 0.120ms, 0 classes unloaded
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let millis = |time: Duration| time.as_secs_f64() * 1000.0;
+        write!(f, "{:.3}ms ", millis(self.time()))?;
+        match *self {
+            Record::ClassLoad{ref class, origin: Some(ref origin), ..} => write!(f, "class load {} from {}", class, origin),
+            Record::ClassLoad{ref class, origin: None, ..} => write!(f, "class load {}", class),
+            Record::GcStart{cause, ..} => write!(f, "gc start ({})", cause),
+            Record::GcFinish{id, pause, bytes_before, bytes_after, unloaded_classes, ..} => write!(f,
+                "gc finish #{}, {} -> {} bytes in {:.3}ms, {} classes unloaded", id, bytes_before, bytes_after, millis(pause), unloaded_classes),
+            Record::AllocationSample{thread, ref class, size, ..} => write!(f, "allocation sample on thread {}: {} of {} bytes", thread, class, size),
+            Record::MethodSample{thread, ref frames, ..} => {
+                write!(f, "method sample on thread {}:", thread)?;
+                for frame in frames {
+                    write!(f, " {}@{}", frame.method, frame.pc)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RecordingError {
+    Io(io::ErrorKind),
+    NotARecording,
+    UnsupportedVersion(u8),
+    Truncated, // The stream ended part way through a record.
+    Malformed(String),
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RecordingError::Io(ref kind) => write!(f, "Failed to read the recording: {:?}", kind),
+            RecordingError::NotARecording => write!(f, "Not a recording"),
+            RecordingError::UnsupportedVersion(version) => write!(f, "Unsupported recording version {}", version),
+            RecordingError::Truncated => write!(f, "Recording is truncated"),
+            RecordingError::Malformed(ref message) => write!(f, "Malformed recording: {}", message),
+        }
+    }
+}
+
+impl error::Error for RecordingError {
+    fn description(&self) -> &str {
+        match *self {
+            RecordingError::Io(_) => "Failed to read the recording",
+            RecordingError::NotARecording => "Not a recording",
+            RecordingError::UnsupportedVersion(_) => "Unsupported recording version",
+            RecordingError::Truncated => "Recording is truncated",
+            RecordingError::Malformed(_) => "Malformed recording",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        None
+    }
+}
+
+impl From<io::Error> for RecordingError {
+    fn from(err: io::Error) -> RecordingError {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => RecordingError::Truncated,
+            kind => RecordingError::Io(kind),
+        }
+    }
+}
+
+// Reads the records of a recording in the order they were written. Iteration stops after the
+// first error.
+pub struct RecordingReader<R> {
+    input: R,
+    strings: HashMap<u64, String>,
+    failed: bool,
+}
+
+impl RecordingReader<BufReader<File>> {
+    pub fn open(path: &Path) -> Result<RecordingReader<BufReader<File>>, RecordingError> {
+        RecordingReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> RecordingReader<R> {
+    pub fn new(mut input: R) -> Result<RecordingReader<R>, RecordingError> {
+        let mut header = [0; MAGIC.len() + 1];
+        input.read_exact(&mut header).map_err(|_| RecordingError::NotARecording)?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(RecordingError::NotARecording);
+        }
+        if header[MAGIC.len()] != VERSION {
+            return Err(RecordingError::UnsupportedVersion(header[MAGIC.len()]));
+        }
+        Ok(RecordingReader {input, strings: HashMap::new(), failed: false})
+    }
+
+    // The next record, or None at the end of the stream.
+    fn read_record(&mut self) -> Result<Option<Record>, RecordingError> {
+        loop {
+            let mut tag = [0];
+            if self.input.read(&mut tag)? == 0 {
+                return Ok(None);
+            }
+            let record = match tag[0] {
+                STRING => {
+                    let id = self.number()?;
+                    let mut bytes = vec![0; self.number()? as usize];
+                    self.input.read_exact(&mut bytes)?;
+                    let text = String::from_utf8(bytes).map_err(|_| RecordingError::Malformed("String isn't UTF-8".to_string()))?;
+                    self.strings.insert(id, text);
+                    continue;
+                },
+                CLASS_LOAD => Record::ClassLoad{
+                    time: self.time()?,
+                    class: self.string()?,
+                    origin: match self.number()? {
+                        0 => None,
+                        id => Some(self.string_with_id(id - 1)?),
+                    },
+                },
+                GC_START => {
+                    let time = self.time()?;
+                    let cause = self.number()?;
+                    let cause = decode_cause(cause).ok_or_else(|| RecordingError::Malformed(format!("Unknown GC cause {}", cause)))?;
+                    Record::GcStart{time, cause}
+                },
+                GC_FINISH => Record::GcFinish{
+                    time: self.time()?,
+                    id: self.number()?,
+                    pause: self.time()?,
+                    bytes_before: self.number()?,
+                    bytes_after: self.number()?,
+                    unloaded_classes: self.number()?,
+                },
+                ALLOCATION_SAMPLE => Record::AllocationSample{
+                    time: self.time()?,
+                    thread: self.number()?,
+                    class: self.string()?,
+                    size: self.number()?,
+                },
+                METHOD_SAMPLE => {
+                    let (time, thread) = (self.time()?, self.number()?);
+                    let count = self.number()?;
+                    let mut frames = vec![];
+                    for _ in 0..count {
+                        frames.push(SampledFrame {method: self.string()?, pc: self.number()? as usize});
+                    }
+                    Record::MethodSample{time, thread, frames}
+                },
+                tag => return Err(RecordingError::Malformed(format!("Unknown record type {}", tag))),
+            };
+            return Ok(Some(record));
+        }
+    }
+
+    fn number(&mut self) -> Result<u64, RecordingError> {
+        let (mut number, mut shift) = (0u64, 0);
+        loop {
+            let mut byte = [0];
+            self.input.read_exact(&mut byte)?;
+            if shift > 63 {
+                return Err(RecordingError::Malformed("Number is too long".to_string()));
+            }
+            number |= u64::from(byte[0] & 0x7f) << shift;
+            if byte[0] & 0x80 == 0 {
+                return Ok(number);
+            }
+            shift += 7;
+        }
+    }
+
+    fn time(&mut self) -> Result<Duration, RecordingError> {
+        Ok(Duration::from_nanos(self.number()?))
+    }
+
+    fn string(&mut self) -> Result<String, RecordingError> {
+        let id = self.number()?;
+        self.string_with_id(id)
+    }
+
+    fn string_with_id(&self, id: u64) -> Result<String, RecordingError> {
+        self.strings.get(&id).cloned().ok_or_else(|| RecordingError::Malformed(format!("Unknown string {}", id)))
+    }
+}
+
+impl<R: Read> Iterator for RecordingReader<R> {
+    type Item = Result<Record, RecordingError>;
+
+    fn next(&mut self) -> Option<Result<Record, RecordingError>> {
+        if self.failed {
+            return None;
+        }
+        let record = self.read_record().transpose();
+        self.failed = matches!(record, Some(Err(_)));
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+    use crate::runtime::Value;
+
+    // Output that can still be read once the recorder owning it has been subscribed.
+    #[derive(Clone, Default)]
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(bytes)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn read(bytes: &[u8]) -> Result<Vec<Record>, RecordingError> {
+        RecordingReader::new(bytes)?.collect()
+    }
+
+    #[test]
+    fn test_records_events() {
+        let mut vm = Vm::new();
+        vm.set_allocation_sample_interval(1);
+        let output = SharedOutput::default();
+        let recording = Recording::start(&mut vm, FlightRecorder::new(Box::new(output.clone())).unwrap(), Duration::from_millis(1));
+        vm.add_class(fixture!("Recursion")).unwrap();
+        vm.new_string("recorded").unwrap();
+        vm.collect_garbage(GcCause::Requested);
+        // Samples depend on timing, so keep going until there are some.
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while vm.profiler().unwrap().profile().samples() == 0 && Instant::now() < deadline {
+            vm.invoke_static("Recursion", "sum", "(I)I", vec![Value::Int(50)]).unwrap();
+        }
+        recording.stop(&mut vm).unwrap();
+        assert!(vm.profiler().is_none());
+
+        let records = read(&output.0.borrow()).unwrap();
+        assert!(records.windows(2).all(|pair| pair[0].time() <= pair[1].time()));
+        assert!(records.iter().any(|record| matches!(record, Record::ClassLoad{class, origin: None, ..} if class == "Recursion")));
+        assert!(records.iter().any(|record| matches!(record, Record::AllocationSample{class, thread: 1, ..} if class == "[C")));
+        assert!(records.iter().any(|record| matches!(record, Record::GcStart{cause: GcCause::Requested, ..})));
+        assert!(records.iter().any(|record| matches!(record, Record::GcFinish{id: 0, ..})));
+        let sample = records.iter().find_map(|record| match record {
+            Record::MethodSample{frames, ..} => Some(frames),
+            _ => None,
+        }).unwrap();
+        assert!(sample.iter().all(|frame| frame.method == "Recursion.sum(I)I"));
+
+        // Nothing more is recorded once it's stopped.
+        let length = output.0.borrow().len();
+        vm.collect_garbage(GcCause::Requested);
+        assert_eq!(length, output.0.borrow().len());
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        for (id, text) in ["Main", "Main.main([Ljava/lang/String;)V", "app.jar"].iter().enumerate() {
+            encode(&mut bytes, STRING, &[id as u64, text.len() as u64]);
+            bytes.extend_from_slice(text.as_bytes());
+        }
+        encode(&mut bytes, CLASS_LOAD, &[5, 0, 3]);
+        encode(&mut bytes, METHOD_SAMPLE, &[300, 1, 1, 1, 200]);
+        encode(&mut bytes, GC_START, &[1 << 40, 1]);
+        assert_eq!(vec![
+            Record::ClassLoad{time: Duration::from_nanos(5), class: "Main".to_string(), origin: Some("app.jar".to_string())},
+            Record::MethodSample{time: Duration::from_nanos(300), thread: 1, frames: vec![
+                SampledFrame {method: "Main.main([Ljava/lang/String;)V".to_string(), pc: 200},
+            ]},
+            Record::GcStart{time: Duration::from_nanos(1 << 40), cause: GcCause::SystemGc},
+        ], read(&bytes).unwrap());
+        assert_eq!("0.000ms class load Main from app.jar", read(&bytes).unwrap()[0].to_string());
+    }
+
+    #[test]
+    fn test_reading_bad_recordings() {
+        assert_eq!(Err(RecordingError::NotARecording), read(b"JOY"));
+        assert_eq!(Err(RecordingError::NotARecording), read(b"NOTJOY\x01"));
+        assert_eq!(Err(RecordingError::UnsupportedVersion(9)), read(b"JOYREC\x09"));
+        assert_eq!(Ok(vec![]), read(b"JOYREC\x01"));
+        assert_eq!(Err(RecordingError::Truncated), read(b"JOYREC\x01\x03\x80"));
+        assert_eq!(Err(RecordingError::Malformed("Unknown string 0".to_string())), read(b"JOYREC\x01\x02\x00\x00\x00"));
+        assert_eq!(Err(RecordingError::Malformed("Unknown record type 99".to_string())), read(b"JOYREC\x01\x63"));
+
+        let mut reader = RecordingReader::new(&b"JOYREC\x01\x63\x03\x00\x00"[..]).unwrap();
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_write_number() {
+        let mut bytes = vec![];
+        write_number(&mut bytes, 0);
+        write_number(&mut bytes, 127);
+        write_number(&mut bytes, 300);
+        assert_eq!(vec![0x00, 0x7f, 0xac, 0x02], bytes);
+    }
+}
@@ -0,0 +1,788 @@
+use crate::access;
+use crate::classes::*;
+use crate::descriptor::{FieldType, MethodDescriptor};
+use crate::heap::ObjectRef;
+use crate::interpreter;
+use crate::loaders::{self, LoaderId};
+use crate::runtime::*;
+use crate::vm::{self, Vm, VmError};
+use std::rc::Rc;
+
+// The modifiers that Field.getModifiers, Method.getModifiers and Constructor.getModifiers report,
+// as java.lang.reflect.Modifier defines them. Flags such as ACC_SYNTHETIC aren't modifiers.
+const FIELD_MODIFIERS: u16 = 0x00df;
+const METHOD_MODIFIERS: u16 = 0x0d3f;
+const CONSTRUCTOR_MODIFIERS: u16 = 0x0007;
+
+// The classes that box each primitive type.
+const BOXES: [(FieldType, &str); 8] = [
+    (FieldType::Boolean, "java/lang/Boolean"),
+    (FieldType::Byte, "java/lang/Byte"),
+    (FieldType::Char, "java/lang/Character"),
+    (FieldType::Short, "java/lang/Short"),
+    (FieldType::Int, "java/lang/Integer"),
+    (FieldType::Long, "java/lang/Long"),
+    (FieldType::Float, "java/lang/Float"),
+    (FieldType::Double, "java/lang/Double"),
+];
+
+// Core reflection: Class.forName, the Class methods that list a class's members, and the Field,
+// Method and Constructor objects they return. Those hold the mirror of the declaring class and
+// the member's name or method index, and everything else is looked up in its class file each
+// time. Primitive values pass through reflection boxed; arguments are unboxed and widened as
+// method invocation conversion allows (JLS 5.3), and results are boxed with valueOf. Also the
+// Unsafe methods that address instance fields by their offsets in the class's FieldLayout.
+pub fn invoke_native(vm: &mut Vm, class: &str, name: &str, descriptor: &str, args: &[Value]) -> Result<Option<Value>, VmError> {
+    match (class, name, descriptor, args) {
+        ("java/lang/Class", "getPrimitiveClass", "(Ljava/lang/String;)Ljava/lang/Class;", &[Value::Reference(ref name)]) => {
+            let name = read_name(vm, name)?;
+            match vm.primitive_mirror(&name) {
+                Ok(mirror) => Ok(Some(Value::Reference(Some(mirror)))),
+                Err(VmError::ClassNotFound(_)) => Err(vm.throw_new_with_message("java/lang/ClassNotFoundException", &name)),
+                Err(err) => Err(err),
+            }
+        },
+        ("java/lang/Class", "forName", "(Ljava/lang/String;)Ljava/lang/Class;", &[Value::Reference(ref name)]) => {
+            let loader = vm.frame_class(1).map_or(LoaderId::APPLICATION, |caller| caller.loader);
+            for_name(vm, name, true, loader)
+        },
+        ("java/lang/Class", "forName", "(Ljava/lang/String;ZLjava/lang/ClassLoader;)Ljava/lang/Class;",
+            &[Value::Reference(ref name), Value::Int(initialize), Value::Reference(ref loader)]) => {
+            // A null loader means the bootstrap loader.
+            let loader = match *loader {
+                Some(ref loader) => loaders::loader_id(loader)?,
+                None => LoaderId::BOOTSTRAP,
+            };
+            for_name(vm, name, initialize != 0, loader)
+        },
+        ("java/lang/Class", "getDeclaredFields", "()[Ljava/lang/reflect/Field;", &[Value::Reference(Some(ref mirror))]) => {
+            declared_fields(vm, mirror)
+        },
+        ("java/lang/Class", "getDeclaredMethods", "()[Ljava/lang/reflect/Method;", &[Value::Reference(Some(ref mirror))]) => {
+            declared_methods(vm, mirror, false)
+        },
+        ("java/lang/Class", "getDeclaredConstructors", "()[Ljava/lang/reflect/Constructor;", &[Value::Reference(Some(ref mirror))]) => {
+            declared_methods(vm, mirror, true)
+        },
+        ("java/lang/reflect/Field", "get", "(Ljava/lang/Object;)Ljava/lang/Object;", &[Value::Reference(Some(ref field)), Value::Reference(ref object)]) => {
+            get(vm, field, object)
+        },
+        ("java/lang/reflect/Field", "set", "(Ljava/lang/Object;Ljava/lang/Object;)V",
+            &[Value::Reference(Some(ref field)), Value::Reference(ref object), Value::Reference(ref value)]) => {
+            set(vm, field, object, value).map(|()| None)
+        },
+        ("java/lang/reflect/Method", "invoke", "(Ljava/lang/Object;[Ljava/lang/Object;)Ljava/lang/Object;",
+            &[Value::Reference(Some(ref method)), Value::Reference(ref receiver), Value::Reference(ref args)]) => {
+            invoke_method(vm, method, receiver, args)
+        },
+        ("java/lang/reflect/Constructor", "newInstance", "([Ljava/lang/Object;)Ljava/lang/Object;",
+            &[Value::Reference(Some(ref constructor)), Value::Reference(ref args)]) => {
+            new_instance(vm, constructor, args)
+        },
+        ("sun/misc/Unsafe", "objectFieldOffset", "(Ljava/lang/reflect/Field;)J", &[_, Value::Reference(Some(ref field))]) => {
+            field_offset(vm, field)
+        },
+        ("sun/misc/Unsafe", "compareAndSetInt", "(Ljava/lang/Object;JII)Z", &[_, Value::Reference(ref object), Value::Long(offset), ref expected, ref value]) |
+        ("sun/misc/Unsafe", "compareAndSetLong", "(Ljava/lang/Object;JJJ)Z", &[_, Value::Reference(ref object), Value::Long(offset), ref expected, ref value]) |
+        ("sun/misc/Unsafe", "compareAndSetReference", "(Ljava/lang/Object;JLjava/lang/Object;Ljava/lang/Object;)Z",
+            &[_, Value::Reference(ref object), Value::Long(offset), ref expected, ref value]) => {
+            compare_and_set(vm, object, offset, expected, value)
+        },
+        _ => Err(VmError::Unsupported(format!("native method {}.{}{}", class, name, descriptor))),
+    }
+}
+
+// Loads a class by its binary name, as Class.forName does. Binary names use dots where class
+// files use slashes, and array classes are named by their descriptors, in the same way.
+fn for_name(vm: &mut Vm, name: &Option<ObjectRef>, initialize: bool, loader: LoaderId) -> Result<Option<Value>, VmError> {
+    let java_name = read_name(vm, name)?;
+    let internal_name = java_name.replace('.', "/");
+    if java_name.contains('/') {
+        return Err(vm.throw_new_with_message("java/lang/ClassNotFoundException", &java_name));
+    }
+    let class = match vm.load_class_with(loader, &internal_name) {
+        Ok(class) => class,
+        Err(VmError::ClassNotFound(ref missing)) if *missing == internal_name => {
+            return Err(vm.throw_new_with_message("java/lang/ClassNotFoundException", &java_name));
+        },
+        Err(err) => return Err(thrown(vm, err)),
+    };
+    if initialize {
+        vm.initialize(&class)?;
+    }
+    Ok(Some(Value::Reference(Some(vm.class_mirror(&class)?))))
+}
+
+// The Field objects for the fields a class declares itself. Primitive types and arrays declare
+// none.
+fn declared_fields(vm: &mut Vm, mirror: &ObjectRef) -> Result<Option<Value>, VmError> {
+    let field_class = vm.load_class("java/lang/reflect/Field")?;
+    vm.initialize(&field_class)?;
+    let mut fields = vec![];
+    if let Some(class) = vm.mirror_class(mirror) {
+        for field in &class.class.fields {
+            let field_type = FieldType::parse(class.class.utf8(&field.descriptor)?)?;
+            let object = vm.new_object(&field_class);
+            let name = vm.intern_string(class.class.utf8(&field.name)?)?;
+            let type_mirror = type_mirror(vm, &class, Some(&field_type))?;
+            vm::set_field(&object, "clazz", Value::Reference(Some(mirror.clone())))?;
+            vm::set_field(&object, "name", Value::Reference(Some(name)))?;
+            vm::set_field(&object, "type", Value::Reference(Some(type_mirror)))?;
+            vm::set_field(&object, "modifiers", Value::Int((field.flags.bits() & FIELD_MODIFIERS) as i32))?;
+            fields.push(object);
+        }
+    }
+    new_array(vm, "[Ljava/lang/reflect/Field;", fields)
+}
+
+// The Method objects for the methods a class declares itself, or the Constructor objects for its
+// constructors. Static initializers are neither.
+fn declared_methods(vm: &mut Vm, mirror: &ObjectRef, constructors: bool) -> Result<Option<Value>, VmError> {
+    let (member_class, array_class) = if constructors {
+        ("java/lang/reflect/Constructor", "[Ljava/lang/reflect/Constructor;")
+    } else {
+        ("java/lang/reflect/Method", "[Ljava/lang/reflect/Method;")
+    };
+    let member_class = vm.load_class(member_class)?;
+    vm.initialize(&member_class)?;
+    let mut members = vec![];
+    if let Some(class) = vm.mirror_class(mirror) {
+        for (index, method) in class.class.methods.iter().enumerate() {
+            let name = class.class.utf8(&method.name)?;
+            if (name == "<init>") != constructors || name == "<clinit>" {
+                continue;
+            }
+            let descriptor = MethodDescriptor::parse(class.class.utf8(&method.descriptor)?)?;
+            let object = vm.new_object(&member_class);
+            let parameter_types = descriptor.parameters.iter()
+                .map(|parameter| type_mirror(vm, &class, Some(parameter)))
+                .collect::<Result<Vec<_>, VmError>>()?;
+            let parameter_types = new_array(vm, "[Ljava/lang/Class;", parameter_types)?;
+            vm::set_field(&object, "clazz", Value::Reference(Some(mirror.clone())))?;
+            vm::set_field(&object, "slot", Value::Int(index as i32))?;
+            vm::set_field(&object, "parameterTypes", parameter_types.unwrap_or_else(Value::null))?;
+            if constructors {
+                vm::set_field(&object, "modifiers", Value::Int((method.flags.bits() & CONSTRUCTOR_MODIFIERS) as i32))?;
+            } else {
+                let name = vm.intern_string(name)?;
+                let return_type = type_mirror(vm, &class, descriptor.return_type.as_ref())?;
+                vm::set_field(&object, "name", Value::Reference(Some(name)))?;
+                vm::set_field(&object, "returnType", Value::Reference(Some(return_type)))?;
+                vm::set_field(&object, "modifiers", Value::Int((method.flags.bits() & METHOD_MODIFIERS) as i32))?;
+            }
+            members.push(object);
+        }
+    }
+    new_array(vm, array_class, members)
+}
+
+fn get(vm: &mut Vm, field: &ObjectRef, object: &Option<ObjectRef>) -> Result<Option<Value>, VmError> {
+    let (class, name, flags, field_type) = field_of(vm, field)?;
+    check_access(vm, field, &class, |vm, caller| access::check_field(vm, caller, &class, &name, object.as_ref().map(|object| &**object.class())))?;
+    let value = if flags.contains(FieldFlags::STATIC) {
+        vm.initialize(&class)?;
+        class.static_value(&name)
+    } else {
+        let object = check_receiver(vm, &class, object)?;
+        class.field_slot(&name).map(|slot| object.fields.borrow().get(slot))
+    };
+    let value = value.ok_or_else(|| VmError::FieldNotFound {class: class.name.to_string(), name: name.clone()})?;
+    Ok(Some(Value::Reference(box_value(vm, &field_type, value)?)))
+}
+
+// Final fields can only be set if access checks are off, and static final ones never can.
+fn set(vm: &mut Vm, field: &ObjectRef, object: &Option<ObjectRef>, value: &Option<ObjectRef>) -> Result<(), VmError> {
+    let (class, name, flags, field_type) = field_of(vm, field)?;
+    check_access(vm, field, &class, |vm, caller| access::check_field(vm, caller, &class, &name, object.as_ref().map(|object| &**object.class())))?;
+    let is_static = flags.contains(FieldFlags::STATIC);
+    if flags.contains(FieldFlags::FINAL) && (is_static || !is_accessible(field)?) {
+        let message = format!("Can not set {}final field {}.{}", if is_static { "static " } else { "" }, class.java_name(), name);
+        return Err(vm.throw_new_with_message("java/lang/IllegalAccessException", &message));
+    }
+    let object = if is_static { None } else { Some(check_receiver(vm, &class, object)?) };
+
+    let converted = match unbox(vm, &class, &field_type, value)? {
+        Some(converted) => converted,
+        None => {
+            let value_type = value.as_ref().map_or_else(|| "null value".to_string(), |value| value.class().java_name());
+            let message = format!("Can not set {} field {}.{} to {}", type_name(&field_type), class.java_name(), name, value_type);
+            return Err(vm.throw_new_with_message("java/lang/IllegalArgumentException", &message));
+        },
+    };
+    match object {
+        Some(object) => vm::set_field(&object, &name, converted),
+        None => {
+            vm.initialize(&class)?;
+            let slot = class.static_slot(&name).ok_or_else(|| VmError::FieldNotFound {class: class.name.to_string(), name: name.clone()})?;
+            class.put_static(slot, converted);
+            Ok(())
+        },
+    }
+}
+
+// Only instance fields have offsets; static ones live with their class rather than in objects.
+fn field_offset(vm: &mut Vm, field: &ObjectRef) -> Result<Option<Value>, VmError> {
+    let (class, name, flags, _) = field_of(vm, field)?;
+    if flags.contains(FieldFlags::STATIC) {
+        return Err(vm.throw_new_with_message("java/lang/IllegalArgumentException", "static field"));
+    }
+    let offset = class.field_offset(&name).ok_or_else(|| VmError::FieldNotFound {class: class.name.to_string(), name: name.clone()})?;
+    Ok(Some(Value::Long(offset as i64)))
+}
+
+// Sets the field at the offset if it holds the expected value, comparing references by identity.
+// Only the thread holding the baton runs, so nothing can change the field in between.
+fn compare_and_set(vm: &mut Vm, object: &Option<ObjectRef>, offset: i64, expected: &Value, value: &Value) -> Result<Option<Value>, VmError> {
+    let object = object.as_ref().ok_or_else(|| vm.throw_new("java/lang/NullPointerException"))?;
+    let slot = if offset < 0 || object.is_array() { None } else { object.class().layout().slot_at(offset as usize) };
+    let current = slot.map(|slot| object.fields.borrow().get(slot));
+    match (slot, current) {
+        (Some(slot), Some(current)) if std::mem::discriminant(&current) == std::mem::discriminant(expected) => {
+            let matched = current == *expected;
+            if matched {
+                object.fields.borrow_mut().set(slot, value.clone());
+            }
+            Ok(Some(Value::Int(matched as i32)))
+        },
+        _ => {
+            Err(vm.throw_new_with_message("java/lang/IllegalArgumentException", &format!("Invalid field offset {}", offset)))
+        },
+    }
+}
+
+// Instance methods are dispatched on the receiver's class, as invokevirtual does, so overrides
+// are called. Private methods aren't overridden.
+fn invoke_method(vm: &mut Vm, method: &ObjectRef, receiver: &Option<ObjectRef>, args: &Option<ObjectRef>) -> Result<Option<Value>, VmError> {
+    let (class, index) = method_of(vm, method)?;
+    check_access(vm, method, &class, |vm, caller| access::check_method(vm, caller, &class, &class, index, receiver.as_ref().map(|receiver| &**receiver.class())))?;
+    let flags = class.class.methods[index].flags;
+    let name = class.class.utf8(&class.class.methods[index].name)?;
+    let descriptor = class.class.utf8(&class.class.methods[index].descriptor)?;
+    let parsed = vm.method_descriptor(descriptor)?;
+
+    let mut values = vec![];
+    let (target_class, target_index) = if flags.contains(MethodFlags::STATIC) {
+        (Rc::clone(&class), index)
+    } else {
+        let receiver = check_receiver(vm, &class, receiver)?;
+        let receiver_class = Rc::clone(receiver.class());
+        values.push(Value::Reference(Some(receiver)));
+        match resolve_method(&receiver_class, name, descriptor)? {
+            Some((selected_class, selected_index)) if !flags.contains(MethodFlags::PRIVATE) => {
+                interpreter::check_implemented(vm, &receiver_class, &selected_class, selected_index)?;
+                (selected_class, selected_index)
+            },
+            _ => (Rc::clone(&class), index),
+        }
+    };
+    values.extend(arguments(vm, &class, &parsed.parameters, args)?);
+    if flags.contains(MethodFlags::STATIC) {
+        vm.initialize(&class)?;
+    }
+
+    let result = call(vm, &target_class, target_index, values)?;
+    match (&parsed.return_type, result) {
+        (Some(return_type), Some(result)) => Ok(Some(Value::Reference(box_value(vm, return_type, result)?))),
+        _ => Ok(Some(Value::null())), // Void methods return null.
+    }
+}
+
+fn new_instance(vm: &mut Vm, constructor: &ObjectRef, args: &Option<ObjectRef>) -> Result<Option<Value>, VmError> {
+    let (class, index) = method_of(vm, constructor)?;
+    check_access(vm, constructor, &class, |vm, caller| access::check_method(vm, caller, &class, &class, index, None))?;
+    if class.class.flags.intersects(ClassFlags::ABSTRACT | ClassFlags::INTERFACE) {
+        return Err(vm.throw_new("java/lang/InstantiationException"));
+    }
+    let parsed = vm.method_descriptor(class.class.utf8(&class.class.methods[index].descriptor)?)?;
+    let values = arguments(vm, &class, &parsed.parameters, args)?;
+
+    vm.initialize(&class)?;
+    let object = vm.new_object(&class);
+    let mut all = vec![Value::Reference(Some(object.clone()))];
+    all.extend(values);
+    call(vm, &class, index, all)?;
+    Ok(Some(Value::Reference(Some(object))))
+}
+
+// Runs a method called through reflection, wrapping any exception it throws in an
+// InvocationTargetException.
+fn call(vm: &mut Vm, class: &Rc<RuntimeClass>, method_index: usize, args: Vec<Value>) -> Result<Option<Value>, VmError> {
+    match interpreter::invoke(vm, class, method_index, args) {
+        Err(VmError::UncaughtException(target)) => match invocation_target_exception(vm, target) {
+            Ok(exception) => Err(VmError::UncaughtException(exception)),
+            Err(err) => Err(err),
+        },
+        result => result,
+    }
+}
+
+fn invocation_target_exception(vm: &mut Vm, target: ObjectRef) -> Result<ObjectRef, VmError> {
+    let class = vm.load_class("java/lang/reflect/InvocationTargetException")?;
+    vm.initialize(&class)?;
+    let exception = vm.new_object(&class);
+    vm.fill_in_stack_trace(&exception)?;
+    let init = class.find_declared_method("<init>", "(Ljava/lang/Throwable;)V")?.ok_or_else(|| VmError::MethodNotFound {
+        class: class.name.to_string(),
+        name: "<init>".to_string(),
+        descriptor: "(Ljava/lang/Throwable;)V".to_string(),
+    })?;
+    interpreter::invoke(vm, &class, init, vec![Value::Reference(Some(exception.clone())), Value::Reference(Some(target))])?;
+    Ok(exception)
+}
+
+// Checks that the class calling into reflection may use the member, as it could if it named the
+// member in its own code, unless the checks were turned off with setAccessible.
+fn check_access(vm: &mut Vm, member: &ObjectRef, class: &Rc<RuntimeClass>, check: impl FnOnce(&mut Vm, &Rc<RuntimeClass>) -> Result<(), VmError>) -> Result<(), VmError> {
+    if is_accessible(member)? {
+        return Ok(());
+    }
+    let caller = match vm.frame_class(1) {
+        Some(caller) => caller,
+        None => return Ok(()), // Called from Rust rather than Java.
+    };
+    let result = access::check_class(&caller, class)
+        .and_then(|()| vm.check_access(&caller, class))
+        .and_then(|()| check(vm, &caller));
+    match result {
+        Err(VmError::IllegalAccess(message)) => Err(vm.throw_new_with_message("java/lang/IllegalAccessException", &message)),
+        result => result,
+    }
+}
+
+fn is_accessible(member: &ObjectRef) -> Result<bool, VmError> {
+    Ok(vm::get_field(member, "override")? == Value::Int(1))
+}
+
+// The object that an instance member is used on, which must be an instance of the class that
+// declares the member.
+fn check_receiver(vm: &mut Vm, class: &RuntimeClass, receiver: &Option<ObjectRef>) -> Result<ObjectRef, VmError> {
+    match *receiver {
+        Some(ref receiver) if receiver.class().is_assignable_to(class) => Ok(receiver.clone()),
+        Some(_) => Err(vm.throw_new_with_message("java/lang/IllegalArgumentException", "object is not an instance of declaring class")),
+        None => Err(vm.throw_new("java/lang/NullPointerException")),
+    }
+}
+
+// Converts the arguments of a reflective call to the method's parameter types. A null array
+// passes no arguments.
+fn arguments(vm: &mut Vm, class: &RuntimeClass, parameters: &[FieldType], args: &Option<ObjectRef>) -> Result<Vec<Value>, VmError> {
+    let args: Vec<Option<ObjectRef>> = match *args {
+        Some(ref args) => {
+            let storage = args.fields.borrow();
+            (0..storage.len()).map(|index| match storage.get(index) {
+                Value::Reference(arg) => arg,
+                _ => None,
+            }).collect()
+        },
+        None => vec![],
+    };
+    if args.len() != parameters.len() {
+        let message = format!("wrong number of arguments: {} expected: {}", args.len(), parameters.len());
+        return Err(vm.throw_new_with_message("java/lang/IllegalArgumentException", &message));
+    }
+
+    let mut values = vec![];
+    for (parameter, arg) in parameters.iter().zip(&args) {
+        match unbox(vm, class, parameter, arg)? {
+            Some(value) => values.push(value),
+            None => return Err(vm.throw_new_with_message("java/lang/IllegalArgumentException", "argument type mismatch")),
+        }
+    }
+    Ok(values)
+}
+
+// Converts a value passed through reflection to a member's type, as seen by the class that
+// declares the member. References must be instances of the type, and boxed primitives are
+// unboxed and widened. Returns None if the value can't be converted.
+pub fn unbox(vm: &mut Vm, class: &RuntimeClass, target: &FieldType, value: &Option<ObjectRef>) -> Result<Option<Value>, VmError> {
+    let value = match *value {
+        Some(ref value) => value,
+        None if target.is_reference() => return Ok(Some(Value::null())),
+        None => return Ok(None),
+    };
+    if target.is_reference() {
+        let target_name = match *target {
+            FieldType::Object(ref name) => name.clone(),
+            _ => target.descriptor(),
+        };
+        let target_class = vm.load_class_with(class.loader, &target_name).map_err(|err| thrown(vm, err))?;
+        let assignable = value.class().is_assignable_to(&target_class);
+        return Ok(if assignable { Some(Value::Reference(Some(value.clone()))) } else { None });
+    }
+    match BOXES.iter().find(|&&(_, box_class)| value.class().name == box_class) {
+        Some((source, _)) => Ok(widen(source, vm::get_field(value, "value")?, target)),
+        None => Ok(None),
+    }
+}
+
+// Widens a primitive value per JLS 5.1.2, or returns None if the types don't allow it.
+pub fn widen(source: &FieldType, value: Value, target: &FieldType) -> Option<Value> {
+    use crate::descriptor::FieldType::*;
+    if source == target {
+        return Some(value);
+    }
+    match (source, value, target) {
+        (Byte, Value::Int(value), Short | Int) | (Short | Char, Value::Int(value), Int) => Some(Value::Int(value)),
+        (Byte | Short | Char | Int, Value::Int(value), Long) => Some(Value::Long(value as i64)),
+        (Byte | Short | Char | Int, Value::Int(value), Float) => Some(Value::Float(value as f32)),
+        (Byte | Short | Char | Int, Value::Int(value), Double) => Some(Value::Double(value as f64)),
+        (Long, Value::Long(value), Float) => Some(Value::Float(value as f32)),
+        (Long, Value::Long(value), Double) => Some(Value::Double(value as f64)),
+        (Float, Value::Float(value), Double) => Some(Value::Double(value as f64)),
+        _ => None,
+    }
+}
+
+// Boxes a primitive value of the given type with its box class's valueOf. References are passed
+// through as they are.
+pub fn box_value(vm: &mut Vm, field_type: &FieldType, value: Value) -> Result<Option<ObjectRef>, VmError> {
+    let box_class = match BOXES.iter().find(|&(primitive, _)| primitive == field_type) {
+        Some(&(_, box_class)) => box_class,
+        None => return match value {
+            Value::Reference(reference) => Ok(reference),
+            other => Err(VmError::InvalidBytecode(format!("Expected a reference of type {}; got {:?}", field_type.descriptor(), other))),
+        },
+    };
+    let descriptor = format!("({})L{};", field_type.descriptor(), box_class);
+    match vm.invoke_static(box_class, "valueOf", &descriptor, vec![value])? {
+        Some(Value::Reference(boxed)) => Ok(boxed),
+        other => Err(VmError::InvalidBytecode(format!("{}.valueOf returned {:?}", box_class, other))),
+    }
+}
+
+// The Class object for a type that a member's descriptor names, loaded as the class declaring
+// the member sees it. None means void.
+pub fn type_mirror(vm: &mut Vm, class: &RuntimeClass, field_type: Option<&FieldType>) -> Result<ObjectRef, VmError> {
+    let class_name = match field_type {
+        Some(FieldType::Object(name)) => name.clone(),
+        Some(field_type @ FieldType::Array(_)) => field_type.descriptor(),
+        Some(field_type) => return vm.primitive_mirror(&type_name(field_type)),
+        None => return vm.primitive_mirror("void"),
+    };
+    let loaded = vm.load_class_with(class.loader, &class_name).map_err(|err| thrown(vm, err))?;
+    vm.class_mirror(&loaded)
+}
+
+// The name of a type as Java source writes it, e.g. int or java.lang.String[].
+pub fn type_name(field_type: &FieldType) -> String {
+    match *field_type {
+        FieldType::Boolean => "boolean".to_string(),
+        FieldType::Byte => "byte".to_string(),
+        FieldType::Char => "char".to_string(),
+        FieldType::Short => "short".to_string(),
+        FieldType::Int => "int".to_string(),
+        FieldType::Long => "long".to_string(),
+        FieldType::Float => "float".to_string(),
+        FieldType::Double => "double".to_string(),
+        FieldType::Object(ref name) => name.replace('/', "."),
+        FieldType::Array(ref component) => format!("{}[]", type_name(component)),
+    }
+}
+
+// The class declaring a Field, along with the field's name, flags and type.
+fn field_of(vm: &mut Vm, field: &ObjectRef) -> Result<(Rc<RuntimeClass>, String, FieldFlags, FieldType), VmError> {
+    let class = declaring_class(vm, field)?;
+    let name = match vm::get_field(field, "name")? {
+        Value::Reference(Some(name)) => vm.read_string(&name)?,
+        other => return Err(VmError::InvalidBytecode(format!("Field has invalid name {:?}", other))),
+    };
+    match class.members().field_named(&name) {
+        Some(index) => {
+            let declared = &class.class.fields[index];
+            let field_type = FieldType::parse(class.class.utf8(&declared.descriptor)?)?;
+            Ok((Rc::clone(&class), name, declared.flags, field_type))
+        },
+        None => Err(VmError::FieldNotFound {class: class.name.to_string(), name}),
+    }
+}
+
+// The class declaring a Method or Constructor, along with the method's index in its class file.
+fn method_of(vm: &mut Vm, method: &ObjectRef) -> Result<(Rc<RuntimeClass>, usize), VmError> {
+    let class = declaring_class(vm, method)?;
+    match vm::get_field(method, "slot")? {
+        Value::Int(slot) if (slot as usize) < class.class.methods.len() => Ok((class, slot as usize)),
+        other => Err(VmError::InvalidBytecode(format!("{} has invalid slot {:?}", method.class().name, other))),
+    }
+}
+
+fn declaring_class(vm: &mut Vm, member: &ObjectRef) -> Result<Rc<RuntimeClass>, VmError> {
+    match vm::get_field(member, "clazz")? {
+        Value::Reference(Some(mirror)) => vm.mirror_class(&mirror).ok_or_else(|| VmError::InvalidBytecode("Class object isn't a mirror".to_string())),
+        other => Err(VmError::InvalidBytecode(format!("{} has invalid clazz {:?}", member.class().name, other))),
+    }
+}
+
+fn read_name(vm: &mut Vm, name: &Option<ObjectRef>) -> Result<String, VmError> {
+    match *name {
+        Some(ref name) => vm.read_string(name),
+        None => Err(vm.throw_new("java/lang/NullPointerException")),
+    }
+}
+
+pub fn new_array(vm: &mut Vm, array_class: &str, elements: Vec<ObjectRef>) -> Result<Option<Value>, VmError> {
+    let array_class = vm.load_class(array_class)?;
+    let array = vm.new_array(&array_class, elements.len())?;
+    for (index, element) in elements.into_iter().enumerate() {
+        array.fields.borrow_mut().set(index, Value::Reference(Some(element)));
+    }
+    Ok(Some(Value::Reference(Some(array))))
+}
+
+// Turns a failure to load a class that a member refers to into the linkage error Java code sees.
+pub fn thrown(vm: &mut Vm, err: VmError) -> VmError {
+    match err.linkage_error() {
+        Some((error_class, message)) => vm.throw_new_with_message(error_class, &message),
+        None => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+
+    const REFLECTION: &[&[u8]] = &[
+        fixture!("Reflection"),
+        fixture!("Reflection$Derived"),
+        fixture!("Reflection$Abstract"),
+        fixture!("Reflection$Outsider"),
+    ];
+
+    fn string(vm: &mut Vm, text: &str) -> Value {
+        Value::Reference(Some(vm.new_string(text).unwrap()))
+    }
+
+    fn mirror(vm: &mut Vm, name: &str) -> Value {
+        let class = vm.load_class(name).unwrap();
+        Value::Reference(Some(vm.class_mirror(&class).unwrap()))
+    }
+
+    fn boxed(vm: &mut Vm, value: i32) -> ObjectRef {
+        box_value(vm, &FieldType::Int, Value::Int(value)).unwrap().unwrap()
+    }
+
+    fn objects(vm: &mut Vm, elements: Vec<ObjectRef>) -> Value {
+        new_array(vm, "[Ljava/lang/Object;", elements).unwrap().unwrap()
+    }
+
+    // The value inside a boxed primitive that a reflective call returned.
+    fn unboxed(result: Result<Option<Value>, VmError>) -> Value {
+        match result {
+            Ok(Some(Value::Reference(Some(boxed)))) => vm::get_field(&boxed, "value").unwrap(),
+            other => panic!("Expected a boxed value; got {:?}", other),
+        }
+    }
+
+    fn strings(vm: &Vm, result: Result<Option<Value>, VmError>) -> Vec<String> {
+        let array = match result {
+            Ok(Some(Value::Reference(Some(array)))) => array,
+            other => panic!("Expected an array; got {:?}", other),
+        };
+        let elements = array.fields.borrow().values().unwrap().to_vec();
+        elements.iter().map(|element| match *element {
+            Value::Reference(Some(ref string)) => vm.read_string(string).unwrap(),
+            ref other => panic!("Expected a string; got {:?}", other),
+        }).collect()
+    }
+
+    // Checks that the result is an exception of the given class, returning the exception.
+    fn expect_thrown(result: Result<Option<Value>, VmError>, class: &str) -> ObjectRef {
+        match result {
+            Err(VmError::UncaughtException(exception)) if exception.class().name == class => exception,
+            other => panic!("Expected {}; got {:?}", class, other),
+        }
+    }
+
+    fn message(vm: &Vm, exception: &ObjectRef) -> String {
+        match vm::get_field(exception, "detailMessage").unwrap() {
+            Value::Reference(Some(message)) => vm.read_string(&message).unwrap(),
+            other => panic!("Expected a message; got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_for_name() {
+        let mut vm = vm_with(REFLECTION);
+        let name = string(&mut vm, "Reflection$Derived");
+        match vm.invoke_static("Reflection", "forName", "(Ljava/lang/String;)Ljava/lang/String;", vec![name]) {
+            Ok(Some(Value::Reference(Some(name)))) => assert_eq!("Reflection$Derived", vm.read_string(&name).unwrap()),
+            other => panic!("Expected a class name; got {:?}", other),
+        }
+
+        let name = string(&mut vm, "[Ljava.lang.String;");
+        assert!(vm.invoke_static("Reflection", "forName", "(Ljava/lang/String;)Ljava/lang/String;", vec![name]).is_ok());
+
+        for missing in &["no.such.Thing", "java/lang/String", "int"] {
+            let name = string(&mut vm, missing);
+            let exception = expect_thrown(vm.invoke_static("Reflection", "forName", "(Ljava/lang/String;)Ljava/lang/String;", vec![name]), "java/lang/ClassNotFoundException");
+            assert_eq!(*missing, message(&vm, &exception));
+        }
+        assert_eq!(Ok(Some(Value::Int(1))), vm.invoke_static("Reflection", "bootstrapLoaderOnlyFindsPlatformClasses", "()Z", vec![]));
+    }
+
+    #[test]
+    fn test_primitive_mirrors() {
+        let mut vm = vm_with(REFLECTION);
+        assert_eq!(Ok(Some(Value::Int(1))), vm.invoke_static("Reflection", "primitiveMirrors", "()Z", vec![]));
+        assert_eq!(vm.primitive_mirror("int").unwrap(), vm.primitive_mirror("int").unwrap());
+        assert_eq!(Err(VmError::ClassNotFound("integer".to_string())), vm.primitive_mirror("integer"));
+    }
+
+    #[test]
+    fn test_declared_members() {
+        let mut vm = vm_with(REFLECTION);
+        let names = vm.invoke_static("Reflection", "fieldNames", "()[Ljava/lang/String;", vec![]);
+        assert_eq!(vec!["count", "total", "label", "instances", "SHARED"], strings(&vm, names));
+        let derived = mirror(&mut vm, "Reflection$Derived");
+        let names = vm.invoke_static("Reflection", "methodNames", "(Ljava/lang/Class;)[Ljava/lang/String;", vec![derived]);
+        assert_eq!(vec!["describe"], strings(&vm, names));
+        let int = Value::Reference(Some(vm.primitive_mirror("int").unwrap()));
+        let names = vm.invoke_static("Reflection", "methodNames", "(Ljava/lang/Class;)[Ljava/lang/String;", vec![int]);
+        assert!(strings(&vm, names).is_empty());
+        assert_eq!(Ok(Some(Value::Int(1))), vm.invoke_static("Reflection", "describesMembers", "()Z", vec![]));
+    }
+
+    #[test]
+    fn test_field_get_and_set() {
+        let mut vm = vm_with(REFLECTION);
+        assert_eq!(Value::Int(1), unboxed(vm.invoke_static("Reflection", "getCount", "()Ljava/lang/Object;", vec![])));
+        assert_eq!(Value::Int(0), unboxed(vm.invoke_static("Reflection", "getStatic", "()Ljava/lang/Object;", vec![])));
+        assert_eq!(Ok(Some(Value::Long(41 + 97 + 3))), vm.invoke_static("Reflection", "setFields", "()J", vec![]));
+
+        // Narrowing and mismatched types aren't allowed.
+        let set = "(Ljava/lang/String;Ljava/lang/Object;Ljava/lang/Object;Z)V";
+        let reflection = vm.load_class("Reflection").unwrap();
+        let target = Value::Reference(Some(vm.new_object(&reflection)));
+        let (name, value) = (string(&mut vm, "count"), string(&mut vm, "text"));
+        let exception = expect_thrown(vm.invoke_static("Reflection", "set", set, vec![name.clone(), target.clone(), value.clone(), Value::Int(1)]), "java/lang/IllegalArgumentException");
+        assert_eq!("Can not set int field Reflection.count to java.lang.String", message(&vm, &exception));
+        let long = Value::Reference(box_value(&mut vm, &FieldType::Long, Value::Long(1)).unwrap());
+        expect_thrown(vm.invoke_static("Reflection", "set", set, vec![name.clone(), target.clone(), long, Value::Int(1)]), "java/lang/IllegalArgumentException");
+
+        // The target must be an instance of the declaring class, and instance fields need one.
+        let one = Value::Reference(Some(boxed(&mut vm, 1)));
+        let exception = expect_thrown(vm.invoke_static("Reflection", "set", set, vec![name.clone(), value, one.clone(), Value::Int(1)]), "java/lang/IllegalArgumentException");
+        assert_eq!("object is not an instance of declaring class", message(&vm, &exception));
+        expect_thrown(vm.invoke_static("Reflection", "set", set, vec![name, Value::null(), one, Value::Int(1)]), "java/lang/NullPointerException");
+    }
+
+    #[test]
+    fn test_final_fields() {
+        let mut vm = vm_with(REFLECTION);
+        let set = "(Ljava/lang/String;Ljava/lang/Object;Ljava/lang/Object;Z)V";
+        let reflection = vm.load_class("Reflection").unwrap();
+        vm.initialize(&reflection).unwrap();
+        let target = vm.new_object(&reflection);
+        let (label, text) = (string(&mut vm, "label"), string(&mut vm, "changed"));
+        let target_value = Value::Reference(Some(target.clone()));
+        let exception = expect_thrown(vm.invoke_static("Reflection", "set", set, vec![label.clone(), target_value.clone(), text.clone(), Value::Int(0)]), "java/lang/IllegalAccessException");
+        assert_eq!("Can not set final field Reflection.label", message(&vm, &exception));
+        assert_eq!(Ok(None), vm.invoke_static("Reflection", "set", set, vec![label, target_value, text.clone(), Value::Int(1)]));
+        assert_eq!(text, vm::get_field(&target, "label").unwrap());
+
+        let shared = string(&mut vm, "SHARED");
+        let exception = expect_thrown(vm.invoke_static("Reflection", "set", set, vec![shared, Value::null(), text, Value::Int(1)]), "java/lang/IllegalAccessException");
+        assert_eq!("Can not set static final field Reflection.SHARED", message(&vm, &exception));
+    }
+
+    #[test]
+    fn test_unsafe_compare_and_set() {
+        let mut vm = vm_with(REFLECTION);
+        let reflection = vm.load_class("Reflection").unwrap();
+        let mirror = vm.class_mirror(&reflection).unwrap();
+        let fields = match declared_fields(&mut vm, &mirror) {
+            Ok(Some(Value::Reference(Some(array)))) => array.fields.borrow().values().unwrap().to_vec(),
+            other => panic!("Expected an array; got {:?}", other),
+        };
+        let offset_of = |vm: &mut Vm, index: usize| invoke_native(vm, "sun/misc/Unsafe", "objectFieldOffset", "(Ljava/lang/reflect/Field;)J", &[Value::null(), fields[index].clone()]);
+        let (count, label) = (offset_of(&mut vm, 0).unwrap().unwrap(), offset_of(&mut vm, 2).unwrap().unwrap());
+        assert_eq!(Value::Long(reflection.field_offset("count").unwrap() as i64), count);
+        expect_thrown(offset_of(&mut vm, 3), "java/lang/IllegalArgumentException");
+
+        let object = vm.new_object(&reflection);
+        let target = Value::Reference(Some(object.clone()));
+        let int = |expected, value| [Value::null(), target.clone(), count.clone(), Value::Int(expected), Value::Int(value)];
+        assert_eq!(Ok(Some(Value::Int(0))), invoke_native(&mut vm, "sun/misc/Unsafe", "compareAndSetInt", "(Ljava/lang/Object;JII)Z", &int(1, 5)));
+        assert_eq!(Ok(Some(Value::Int(1))), invoke_native(&mut vm, "sun/misc/Unsafe", "compareAndSetInt", "(Ljava/lang/Object;JII)Z", &int(0, 5)));
+        assert_eq!(Value::Int(5), vm::get_field(&object, "count").unwrap());
+
+        // References are compared by identity, not by equals.
+        let (first, second) = (string(&mut vm, "text"), string(&mut vm, "text"));
+        let reference = "(Ljava/lang/Object;JLjava/lang/Object;Ljava/lang/Object;)Z";
+        let swap = |expected: &Value, value: &Value| [Value::null(), target.clone(), label.clone(), expected.clone(), value.clone()];
+        assert_eq!(Ok(Some(Value::Int(1))), invoke_native(&mut vm, "sun/misc/Unsafe", "compareAndSetReference", reference, &swap(&Value::null(), &first)));
+        assert_eq!(Ok(Some(Value::Int(0))), invoke_native(&mut vm, "sun/misc/Unsafe", "compareAndSetReference", reference, &swap(&second, &Value::null())));
+        assert_eq!(Ok(Some(Value::Int(1))), invoke_native(&mut vm, "sun/misc/Unsafe", "compareAndSetReference", reference, &swap(&first, &second)));
+
+        // The offset must be of a field of the type being set.
+        expect_thrown(invoke_native(&mut vm, "sun/misc/Unsafe", "compareAndSetInt", "(Ljava/lang/Object;JII)Z",
+            &[Value::null(), target.clone(), label, Value::Int(0), Value::Int(1)]), "java/lang/IllegalArgumentException");
+        expect_thrown(invoke_native(&mut vm, "sun/misc/Unsafe", "compareAndSetInt", "(Ljava/lang/Object;JII)Z",
+            &[Value::null(), target, Value::Long(3), Value::Int(0), Value::Int(1)]), "java/lang/IllegalArgumentException");
+    }
+
+    #[test]
+    fn test_access_checks() {
+        let mut vm = vm_with(REFLECTION);
+        let exception = expect_thrown(vm.invoke_static("Reflection$Outsider", "readCount", "(Z)Ljava/lang/Object;", vec![Value::Int(0)]), "java/lang/IllegalAccessException");
+        assert_eq!("class Reflection$Outsider tried to access private field Reflection.count", message(&vm, &exception));
+        assert_eq!(Value::Int(1), unboxed(vm.invoke_static("Reflection$Outsider", "readCount", "(Z)Ljava/lang/Object;", vec![Value::Int(1)])));
+    }
+
+    #[test]
+    fn test_method_invoke() {
+        let mut vm = vm_with(REFLECTION);
+        let invoke = "(Ljava/lang/Class;Ljava/lang/String;Ljava/lang/Object;[Ljava/lang/Object;)Ljava/lang/Object;";
+        assert_eq!(Ok(Some(Value::Int(16))), vm.invoke_static("Reflection", "invokeAdd", "()I", vec![]));
+
+        // Overrides are called, as with invokevirtual.
+        let (reflection, describe) = (mirror(&mut vm, "Reflection"), string(&mut vm, "describe"));
+        let derived_class = vm.load_class("Reflection$Derived").unwrap();
+        let derived = Value::Reference(Some(vm.new_object(&derived_class)));
+        match vm.invoke_static("Reflection", "invoke", invoke, vec![reflection.clone(), describe, derived, Value::null()]) {
+            Ok(Some(Value::Reference(Some(result)))) => assert_eq!("derived", vm.read_string(&result).unwrap()),
+            other => panic!("Expected a string; got {:?}", other),
+        }
+
+        // Static methods ignore the receiver, and primitive arguments are widened.
+        let (half, three) = (string(&mut vm, "half"), boxed(&mut vm, 3));
+        let args = objects(&mut vm, vec![three]);
+        assert_eq!(Value::Double(1.5), unboxed(vm.invoke_static("Reflection", "invoke", invoke, vec![reflection.clone(), half.clone(), Value::null(), args])));
+        let nothing = string(&mut vm, "nothing");
+        assert_eq!(Ok(Some(Value::null())), vm.invoke_static("Reflection", "invoke", invoke, vec![reflection.clone(), nothing, Value::null(), Value::null()]));
+
+        let exception = expect_thrown(vm.invoke_static("Reflection", "invoke", invoke, vec![reflection.clone(), half.clone(), Value::null(), Value::null()]), "java/lang/IllegalArgumentException");
+        assert_eq!("wrong number of arguments: 0 expected: 1", message(&vm, &exception));
+        let text = vm.new_string("3").unwrap();
+        let args = objects(&mut vm, vec![text]);
+        let exception = expect_thrown(vm.invoke_static("Reflection", "invoke", invoke, vec![reflection.clone(), half, Value::null(), args]), "java/lang/IllegalArgumentException");
+        assert_eq!("argument type mismatch", message(&vm, &exception));
+
+        // Exceptions thrown by the method are wrapped.
+        let (divide, one, zero) = (string(&mut vm, "divide"), boxed(&mut vm, 1), boxed(&mut vm, 0));
+        let args = objects(&mut vm, vec![one, zero]);
+        let exception = expect_thrown(vm.invoke_static("Reflection", "invoke", invoke, vec![reflection, divide, Value::null(), args]), "java/lang/reflect/InvocationTargetException");
+        match vm::get_field(&exception, "target").unwrap() {
+            Value::Reference(Some(target)) => assert_eq!("java/lang/ArithmeticException", target.class().name),
+            other => panic!("Expected a target exception; got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_constructor_new_instance() {
+        let mut vm = vm_with(REFLECTION);
+        assert_eq!(Ok(Some(Value::Int(7))), vm.invoke_static("Reflection", "constructPrivately", "()I", vec![]));
+
+        let construct = "(Ljava/lang/Class;I[Ljava/lang/Object;)Ljava/lang/Object;";
+        let reflection = mirror(&mut vm, "Reflection");
+        match vm.invoke_static("Reflection", "construct", construct, vec![reflection, Value::Int(0), Value::null()]) {
+            Ok(Some(Value::Reference(Some(object)))) => {
+                assert_eq!("Reflection", object.class().name);
+                assert_eq!(Ok(Value::Int(1)), vm::get_field(&object, "count"));
+            },
+            other => panic!("Expected an instance; got {:?}", other),
+        }
+        let abstract_class = mirror(&mut vm, "Reflection$Abstract");
+        expect_thrown(vm.invoke_static("Reflection", "construct", construct, vec![abstract_class, Value::Int(0), Value::null()]), "java/lang/InstantiationException");
+    }
+}
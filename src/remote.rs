@@ -0,0 +1,203 @@
+use crate::classpath::{self, ClassSource, ClasspathError};
+use crate::jar::{JarSource, Manifest};
+use std::cell::{OnceCell, RefCell};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+// A classpath entry at an http or https URL, as in the search path of a URLClassLoader. As there,
+// a URL ending in / is a directory, whose resources are fetched one at a time as they're asked
+// for, and any other URL is a JAR, which is fetched whole the first time anything is asked for.
+// What's fetched is kept, as is the fact that a resource doesn't exist, so nothing is fetched
+// twice.
+pub struct UrlSource {
+    url: String,
+    agent: ureq::Agent,
+    runtime_version: Option<u32>,
+    jar: OnceCell<Option<JarSource>>, // None if there's no JAR at the URL.
+    resources: RefCell<HashMap<String, Option<Vec<u8>>>>, // Those fetched from a directory.
+}
+
+impl UrlSource {
+    pub fn new(url: &str) -> Result<UrlSource, ClasspathError> {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(ClasspathError::Remote{url: url.to_string(), message: "Only http and https URLs are supported".to_string()});
+        }
+        let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+        Ok(UrlSource {url: url.to_string(), agent, runtime_version: None, jar: OnceCell::new(), resources: RefCell::new(HashMap::new())})
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.url.ends_with('/')
+    }
+
+    // Fetches the contents of a URL, or None if the server says there's nothing there.
+    fn fetch(&self, url: &str) -> Result<Option<Vec<u8>>, ClasspathError> {
+        let error = |message: String| ClasspathError::Remote{url: url.to_string(), message};
+        let response = match self.agent.get(url).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) | Err(ureq::Error::Status(410, _)) => return Ok(None),
+            Err(ureq::Error::Status(status, response)) => return Err(error(format!("HTTP {} {}", status, response.status_text()))),
+            Err(err) => return Err(error(err.to_string())),
+        };
+        let mut bytes = vec![];
+        response.into_reader().read_to_end(&mut bytes).map_err(|err| error(err.to_string()))?;
+        Ok(Some(bytes))
+    }
+
+    fn jar(&self) -> Result<Option<&JarSource>, ClasspathError> {
+        if let Some(jar) = self.jar.get() {
+            return Ok(jar.as_ref());
+        }
+        let jar = match self.fetch(&self.url)? {
+            Some(bytes) => {
+                let mut jar = JarSource::from_bytes(PathBuf::from(&self.url), bytes)?;
+                if let Some(version) = self.runtime_version {
+                    jar.set_runtime_version(version);
+                }
+                Some(jar)
+            },
+            None => None,
+        };
+        Ok(self.jar.get_or_init(|| jar).as_ref())
+    }
+}
+
+impl ClassSource for UrlSource {
+    fn set_runtime_version(&mut self, version: u32) {
+        self.runtime_version = Some(version);
+        if let Some(Some(jar)) = self.jar.get_mut() {
+            jar.set_runtime_version(version);
+        }
+    }
+
+    // Only known once the JAR has been fetched.
+    fn manifest(&self) -> Option<&Manifest> {
+        self.jar.get()?.as_ref().map(JarSource::manifest)
+    }
+
+    // A JAR's manifest Class-Path isn't followed.
+    fn find_resource(&self, path: &str) -> Result<Option<Vec<u8>>, ClasspathError> {
+        classpath::check_resource_path(path)?;
+        if !self.is_directory() {
+            return match self.jar()? {
+                Some(jar) => jar.find_resource(path),
+                None => Ok(None),
+            };
+        }
+        if let Some(resource) = self.resources.borrow().get(path) {
+            return Ok(resource.clone());
+        }
+        let resource = self.fetch(&format!("{}{}", self.url, path))?;
+        self.resources.borrow_mut().insert(path.to_string(), resource.clone());
+        Ok(resource)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classpath::Classpath;
+    use crate::vm::Vm;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    // Serves the files in testdata over HTTP on a local port, answering paths under /broken with
+    // an error. Returns the base URL and the number of requests served so far.
+    fn serve() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request_line = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                reader.read_line(&mut request_line).unwrap();
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+                counted.fetch_add(1, Ordering::SeqCst);
+
+                let path = request_line.split_whitespace().nth(1).unwrap_or("/").trim_start_matches('/');
+                let file = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join(path);
+                let (status, body) = if path.starts_with("broken/") {
+                    ("500 Internal Server Error", vec![])
+                } else {
+                    match fs::read(&file) {
+                        Ok(body) => ("200 OK", body),
+                        Err(_) => ("404 Not Found", vec![]),
+                    }
+                };
+                write!(stream, "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, body.len()).unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    fn fixture(name: &str) -> Vec<u8> {
+        fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join(name)).unwrap()
+    }
+
+    #[test]
+    fn test_directory_resources_are_fetched_once() {
+        let (url, requests) = serve();
+        let source = UrlSource::new(&url).unwrap();
+        assert_eq!(Some(fixture("Completes.class")), source.find_class("Completes").unwrap());
+        assert_eq!(None, source.find_class("Missing").unwrap());
+        assert_eq!(2, requests.load(Ordering::SeqCst));
+        assert_eq!(Some(fixture("Completes.class")), source.find_class("Completes").unwrap());
+        assert_eq!(None, source.find_class("Missing").unwrap());
+        assert_eq!(2, requests.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_jars_are_fetched_whole() {
+        let (url, requests) = serve();
+        let source = UrlSource::new(&format!("{}jars/app.jar", url)).unwrap();
+        assert!(source.find_class("com/example/App").unwrap().is_some());
+        assert_eq!(Some(b"Hello from a JAR\n".to_vec()), source.find_resource("com/example/message.txt").unwrap());
+        assert_eq!(None, source.find_class("com/example/lib/Lib").unwrap());
+        assert_eq!(1, requests.load(Ordering::SeqCst));
+
+        let missing = UrlSource::new(&format!("{}jars/missing.jar", url)).unwrap();
+        assert_eq!(None, missing.find_class("com/example/App").unwrap());
+    }
+
+    #[test]
+    fn test_fetch_errors() {
+        let (url, _) = serve();
+        match UrlSource::new(&format!("{}broken/", url)).unwrap().find_class("Completes") {
+            Err(ClasspathError::Remote{ref message, ..}) if message.starts_with("HTTP 500") => (),
+            other => panic!("Expected a server error; got {:?}", other),
+        }
+        assert_eq!(
+            Err(ClasspathError::Remote{url: "ftp://example.com/".to_string(), message: "Only http and https URLs are supported".to_string()}),
+            UrlSource::new("ftp://example.com/").map(|source| source.url));
+    }
+
+    #[test]
+    fn test_classes_load_from_urls() {
+        let (url, _) = serve();
+        let mut classpath = Classpath::new();
+        classpath.push(UrlSource::new(&url).unwrap());
+        let mut vm = Vm::new();
+        vm.set_classpath(classpath);
+        assert_eq!("Completes", vm.load_class("Completes").unwrap().name);
+    }
+}
@@ -0,0 +1,284 @@
+use crate::access::{self, Access};
+use crate::classes::*;
+use crate::descriptor::FieldType;
+use crate::heap::ObjectRef;
+use crate::method_handles;
+use crate::reflection;
+use crate::runtime::*;
+use crate::vm::{Vm, VmError};
+use std::rc::Rc;
+
+// Resolves a loadable constant into the value pushed by ldc, ldc_w and ldc2_w, per JVM spec 5.4.3
+// and 5.1. Everything but numbers is cached on the class after its first resolution, which for
+// method types, method handles and dynamic constants also ensures that every later ldc of the
+// same entry produces the same object.
+pub fn load_constant(vm: &mut Vm, class: &Rc<RuntimeClass>, index: &ConstantIndex) -> Result<Value, VmError> {
+    if let Some(ResolvedEntry::Constant(value)) = class.cached_entry(index.0) {
+        return Ok(value);
+    }
+    check_failed(vm, class, index)?;
+
+    let value = match *index.lookup(&class.class.constants)? {
+        Constant::Integer(value) => return Ok(Value::Int(value as i32)),
+        Constant::Float(value) => return Ok(Value::Float(value)),
+        Constant::Long(value) => return Ok(Value::Long(value as i64)),
+        Constant::Double(value) => return Ok(Value::Double(value)),
+        Constant::StringRef(ref text) => Value::Reference(Some(vm.intern_string(class.class.utf8(text)?)?)),
+        Constant::ClassRef(_) => {
+            // The entry is shared with instructions like new, so it's cached as a class.
+            let target = class_ref(vm, class, index)?;
+            return Ok(Value::Reference(Some(vm.class_mirror(&target)?)));
+        },
+        Constant::MethodType(ref descriptor) => {
+            let method_type = method_handles::new_method_type(vm, class, class.class.utf8(descriptor)?).map_err(|err| failed(vm, class, index, err))?;
+            Value::Reference(Some(method_type))
+        },
+        Constant::MethodHandleRef(ref handle) => {
+            Value::Reference(Some(new_method_handle(vm, class, handle).map_err(|err| failed(vm, class, index, err))?))
+        },
+        Constant::Dynamic{ref bootstrap_method_attr, ref name_and_type} => resolve_dynamic(vm, class, bootstrap_method_attr, name_and_type)?,
+        _ => return Err(VmError::ConstantLookup(ConstantLookupError::UnexpectedType(index.0))),
+    };
+
+    class.cache_entry(index.0, ResolvedEntry::Constant(value.clone()));
+    Ok(value)
+}
+
+// Resolves a class reference, loading the class the first time.
+pub fn class_ref(vm: &mut Vm, class: &Rc<RuntimeClass>, index: &ConstantIndex) -> Result<Rc<RuntimeClass>, VmError> {
+    if let Some(ResolvedEntry::Class(target)) = class.cached_entry(index.0) {
+        return Ok(target);
+    }
+    check_failed(vm, class, index)?;
+
+    let target = load_accessible(vm, class, class.class.class_name(index)?).map_err(|err| failed(vm, class, index, err))?;
+    class.cache_entry(index.0, ResolvedEntry::Class(Rc::clone(&target)));
+    Ok(target)
+}
+
+// Resolves a field reference used by getstatic or putstatic into the class that declares the
+// field and its slot there. The declaring class still needs initializing before it's used.
+pub fn static_field_ref(vm: &mut Vm, class: &Rc<RuntimeClass>, index: &ConstantIndex) -> Result<(Rc<RuntimeClass>, usize), VmError> {
+    if let Some(ResolvedEntry::StaticField{class: declaring_class, slot}) = class.cached_entry(index.0) {
+        return Ok((declaring_class, slot));
+    }
+    check_failed(vm, class, index)?;
+
+    let field = class.class.member_ref(index)?;
+    let owner = load_accessible(vm, class, field.class).map_err(|err| failed(vm, class, index, err))?;
+    let (declaring_class, slot) = match resolve_static_field(&owner, field.name) {
+        Some(found) => found,
+        // The field resolves, but the instruction can't use it. Another instruction might, so
+        // this isn't cached.
+        None if owner.field_slot(field.name).is_some() => {
+            let message = format!("Expected static field {}.{}", field.class, field.name);
+            return Err(vm.throw_new_with_message("java/lang/IncompatibleClassChangeError", &message));
+        },
+        None => return Err(failed(vm, class, index, VmError::FieldNotFound {class: field.class.to_string(), name: field.name.to_string()})),
+    };
+    access::check_field(vm, class, &owner, field.name, None).map_err(|err| failed(vm, class, index, err))?;
+    class.cache_entry(index.0, ResolvedEntry::StaticField{class: Rc::clone(&declaring_class), slot});
+    Ok((declaring_class, slot))
+}
+
+// Resolves a field reference used by getfield or putfield into the field's slot, which is the
+// same in every subclass of the class that declares it, and whether each use must check the
+// object it's used on with check_receiver.
+pub fn instance_field_ref(vm: &mut Vm, class: &Rc<RuntimeClass>, index: &ConstantIndex) -> Result<(usize, bool), VmError> {
+    if let Some(ResolvedEntry::InstanceField{slot, checks_receiver}) = class.cached_entry(index.0) {
+        return Ok((slot, checks_receiver));
+    }
+    check_failed(vm, class, index)?;
+
+    let field = class.class.member_ref(index)?;
+    let owner = load_accessible(vm, class, field.class).map_err(|err| failed(vm, class, index, err))?;
+    let slot = match owner.field_slot(field.name) {
+        Some(slot) => slot,
+        None if resolve_static_field(&owner, field.name).is_some() => {
+            let message = format!("Expected non-static field {}.{}", field.class, field.name);
+            return Err(vm.throw_new_with_message("java/lang/IncompatibleClassChangeError", &message));
+        },
+        None => return Err(failed(vm, class, index, VmError::FieldNotFound {class: field.class.to_string(), name: field.name.to_string()})),
+    };
+    access::check_field(vm, class, &owner, field.name, None).map_err(|err| failed(vm, class, index, err))?;
+    let checks_receiver = access::find_field(&owner, field.name)?
+        .is_some_and(|(declaring_class, flags)| access::checks_receiver(class, &declaring_class, Access::of_field(flags)));
+    class.cache_entry(index.0, ResolvedEntry::InstanceField{slot, checks_receiver});
+    Ok((slot, checks_receiver))
+}
+
+// Resolves a method reference against the class it names, per JVM spec 5.4.3.3 and 5.4.3.4.
+// Returns the declaring class, the method's index there, the number of parameters, not counting
+// the receiver, and whether each call must check the receiver with check_receiver. Virtual calls
+// must still select the method to run from the receiver.
+pub fn method_ref(vm: &mut Vm, class: &Rc<RuntimeClass>, index: &ConstantIndex) -> Result<(Rc<RuntimeClass>, usize, usize, bool), VmError> {
+    if let Some(ResolvedEntry::Method{class: declaring_class, method_index, parameter_count, checks_receiver}) = class.cached_entry(index.0) {
+        return Ok((declaring_class, method_index, parameter_count, checks_receiver));
+    }
+    check_failed(vm, class, index)?;
+
+    let method = class.class.member_ref(index)?;
+    let parameter_count = vm.method_descriptor(method.descriptor)?.parameters.len();
+    let owner = load_accessible(vm, class, method.class).map_err(|err| failed(vm, class, index, err))?;
+
+    // Methodref entries must name a class and InterfaceMethodref entries an interface.
+    let is_interface_ref = matches!(*index.lookup(&class.class.constants)?, Constant::InterfaceMethodRef{..});
+    if is_interface_ref != owner.is_interface() {
+        let message = if is_interface_ref {
+            format!("Found class {}, but interface was expected", owner.name)
+        } else {
+            format!("Found interface {}, but class was expected", owner.name)
+        };
+        return Err(failed(vm, class, index, VmError::IncompatibleClassChange(message)));
+    }
+
+    // Calls to invokeExact and invoke resolve to them whatever the call site's descriptor.
+    let polymorphic = || method_handles::find_signature_polymorphic(&owner, method.name);
+    let (declaring_class, method_index) = match resolve_method(&owner, method.name, method.descriptor)? {
+        Some(found) => found,
+        None => match polymorphic()? {
+            Some(method_index) => (Rc::clone(&owner), method_index),
+            None => return Err(failed(vm, class, index, VmError::MethodNotFound {
+            class: method.class.to_string(),
+            name: method.name.to_string(),
+            descriptor: method.descriptor.to_string(),
+        })),
+        },
+    };
+    access::check_method(vm, class, &owner, &declaring_class, method_index, None).map_err(|err| failed(vm, class, index, err))?;
+    let flags = declaring_class.class.methods[method_index].flags;
+    let checks_receiver = !owner.is_array() && !flags.contains(MethodFlags::STATIC) && method.name != "<init>"
+        && access::checks_receiver(class, &declaring_class, Access::of_method(flags));
+    class.cache_entry(index.0, ResolvedEntry::Method{class: Rc::clone(&declaring_class), method_index, parameter_count, checks_receiver});
+    Ok((declaring_class, method_index, parameter_count, checks_receiver))
+}
+
+// Checks that a class may use the field or method that a resolved reference names on an
+// instance of the receiver's class, for the references that resolution found need it. The
+// outcome depends on the object, so unlike resolution failures it isn't cached.
+pub fn check_receiver(vm: &mut Vm, class: &Rc<RuntimeClass>, index: &ConstantIndex, receiver: &RuntimeClass) -> Result<(), VmError> {
+    if receiver.is_subclass_of(class) {
+        return Ok(());
+    }
+    let member = class.class.member_ref(index)?;
+    let owner = vm.load_class_with(class.loader, member.class)?;
+    let result = match *index.lookup(&class.class.constants)? {
+        Constant::FieldRef{..} => access::check_field(vm, class, &owner, member.name, Some(receiver)),
+        _ => match resolve_method(&owner, member.name, member.descriptor)? {
+            Some((declaring_class, method_index)) => access::check_method(vm, class, &owner, &declaring_class, method_index, Some(receiver)),
+            None => Ok(()),
+        },
+    };
+    result.map_err(|err| match err.linkage_error() {
+        Some((error_class, message)) => vm.throw_new_with_message(error_class, &message),
+        None => err,
+    })
+}
+
+// Loads a class that the given class refers to, checking that the class may access it.
+fn load_accessible(vm: &mut Vm, class: &RuntimeClass, name: &str) -> Result<Rc<RuntimeClass>, VmError> {
+    let target = vm.load_class_with(class.loader, name)?;
+    access::check_class(class, &target)?;
+    vm.check_access(class, &target)?;
+    Ok(target)
+}
+
+// Throws the error that resolving the entry failed with before, if it did.
+fn check_failed(vm: &mut Vm, class: &RuntimeClass, index: &ConstantIndex) -> Result<(), VmError> {
+    match class.cached_entry(index.0) {
+        Some(ResolvedEntry::Failed{error_class, message}) => Err(vm.throw_new_with_message(error_class, &message)),
+        _ => Ok(()),
+    }
+}
+
+// Turns a failure to resolve the entry into the linkage error that Java code sees, and caches it
+// so that later attempts fail the same way. Other errors, such as exceptions thrown by a static
+// initializer or a class loader, are passed through and the entry can be resolved again.
+fn failed(vm: &mut Vm, class: &RuntimeClass, index: &ConstantIndex, err: VmError) -> VmError {
+    match err.linkage_error() {
+        Some((error_class, message)) => {
+            class.cache_entry(index.0, ResolvedEntry::Failed{error_class, message: message.clone()});
+            vm.throw_new_with_message(error_class, &message)
+        },
+        None => err,
+    }
+}
+
+// Creates a java.lang.invoke.MethodHandle for the referenced member.
+fn new_method_handle(vm: &mut Vm, class: &Rc<RuntimeClass>, handle: &MethodHandle) -> Result<ObjectRef, VmError> {
+    let (kind, index) = match *handle {
+        MethodHandle::GetField(ref index) => (method_handles::REF_GET_FIELD, index),
+        MethodHandle::GetStatic(ref index) => (method_handles::REF_GET_STATIC, index),
+        MethodHandle::PutField(ref index) => (method_handles::REF_PUT_FIELD, index),
+        MethodHandle::PutStatic(ref index) => (method_handles::REF_PUT_STATIC, index),
+        MethodHandle::InvokeVirtual(ref index) => (method_handles::REF_INVOKE_VIRTUAL, index),
+        MethodHandle::InvokeStatic(ref index) => (method_handles::REF_INVOKE_STATIC, index),
+        MethodHandle::InvokeSpecial(ref index) => (method_handles::REF_INVOKE_SPECIAL, index),
+        MethodHandle::NewInvokeSpecial(ref index) => (method_handles::REF_NEW_INVOKE_SPECIAL, index),
+        MethodHandle::InvokeInterface(ref index) => (method_handles::REF_INVOKE_INTERFACE, index),
+    };
+
+    let member = class.class.member_ref(index)?;
+    let owner = load_accessible(vm, class, member.class)?;
+    method_handles::new_method_handle(vm, class, kind, &owner, member.name, member.descriptor)
+}
+
+// Resolves a dynamically-computed constant by calling its bootstrap method with a Lookup, the
+// constant's name and type, and the static arguments, per JVM spec 5.4.3.6. The bootstrap method
+// is called through its handle as invoke would call it, so the arguments are boxed or cast to its
+// parameter types and the result to the constant's type. Variable arity bootstrap methods don't
+// have their trailing arguments collected into an array yet.
+fn resolve_dynamic(vm: &mut Vm, class: &Rc<RuntimeClass>, bootstrap_index: &MethodIndex, name_and_type: &ConstantIndex) -> Result<Value, VmError> {
+    let (name, descriptor) = class.class.name_and_type(name_and_type)?;
+    let constant_type = vm.field_type(descriptor)?;
+    let bootstrap = class.class.bootstrap_method(bootstrap_index).ok_or_else(|| VmError::InvalidBytecode(
+        format!("Bootstrap method {} not found in {}", bootstrap_index.0, class.name)))?;
+    let handle = match load_constant(vm, class, &bootstrap.method)? {
+        Value::Reference(Some(handle)) if handle.class().name == "java/lang/invoke/MethodHandle" => handle,
+        other => return Err(VmError::InvalidBytecode(format!("Bootstrap method for dynamic constant {} is {:?}", name, other))),
+    };
+
+    let lookup = method_handles::new_lookup(vm, class)?;
+    let mut args = vec![
+        Value::Reference(Some(lookup)),
+        Value::Reference(Some(vm.intern_string(name)?)),
+        Value::Reference(Some(reflection::type_mirror(vm, class, Some(&constant_type))?)),
+    ];
+    let mut call_site = String::from("(Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;Ljava/lang/Class;");
+    for argument in &bootstrap.arguments {
+        let value = load_constant(vm, class, argument)?;
+        call_site.push_str(match value {
+            Value::Int(_) => "I",
+            Value::Long(_) => "J",
+            Value::Float(_) => "F",
+            Value::Double(_) => "D",
+            _ => "Ljava/lang/Object;",
+        });
+        args.push(value);
+    }
+    call_site.push(')');
+    call_site.push_str(descriptor);
+
+    match method_handles::invoke_handle(vm, class, &handle, &call_site, false, args)? {
+        Some(value) => Ok(value),
+        None => Err(VmError::InvalidBytecode(format!("Bootstrap method for dynamic constant {} returned nothing", name))),
+    }
+}
+
+// The name to load for a reference type, or None for primitives.
+pub fn class_name_of(field_type: &FieldType) -> Option<String> {
+    match *field_type {
+        FieldType::Object(ref name) => Some(name.clone()),
+        FieldType::Array(_) => Some(field_type.descriptor()),
+        _ => None,
+    }
+}
+
+// The descriptor for values of the named class. Array class names are already descriptors.
+pub fn reference_descriptor(class_name: &str) -> String {
+    if class_name.starts_with('[') {
+        class_name.to_string()
+    } else {
+        format!("L{};", class_name)
+    }
+}
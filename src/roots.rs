@@ -0,0 +1,197 @@
+use crate::bytecode;
+use crate::classes::*;
+use crate::heap::ObjectRef;
+use crate::opcodes::*;
+use crate::runtime::*;
+
+// A place outside the heap that holds a reference to a heap object, as reported by
+// Vm::visit_roots. Everything reachable from the roots is live; anything else is garbage.
+#[derive(Clone, Copy)]
+pub enum Root<'a> {
+    Local{class: &'a RuntimeClass, method_index: usize, slot: usize},
+    OperandStack{class: &'a RuntimeClass, method_index: usize, depth: usize},
+    StaticField{class: &'a RuntimeClass, slot: usize},
+    InternedString(&'a str),
+    ClassMirror(&'a str), // The name of the class the mirror represents.
+    Package(&'a str), // The name of the package, in internal form.
+    GlobalRef(usize), // The index of the handle.
+    LocalRef(usize),
+    Thread(u64), // The id of a thread that has started and not finished.
+    Monitor, // An object whose monitor is owned, or waited for or on.
+}
+
+// Receives each root in turn. Values are tagged with their types, so a root is never an int or
+// float that happens to look like a pointer. Closures taking a Root and an ObjectRef work as
+// visitors.
+pub trait RootVisitor {
+    fn visit_root(&mut self, root: Root, object: &ObjectRef);
+}
+
+impl<F: FnMut(Root, &ObjectRef)> RootVisitor for F {
+    fn visit_root(&mut self, root: Root, object: &ObjectRef) {
+        self(root, object)
+    }
+}
+
+// Records which local variables of a method are live at each instruction, meaning they may be
+// read before they're next overwritten. A dead local may still hold a reference to an object
+// that the method has finished with, which shouldn't keep the object alive.
+pub struct ReferenceMap {
+    live: Vec<Vec<bool>>, // Indexed by pc, then by local slot. Empty for pcs mid-instruction.
+}
+
+impl ReferenceMap {
+    // Works out liveness for the method's code. If the code can't be decoded, every local is
+    // treated as live, which is always safe.
+    pub fn new(code: &Code) -> ReferenceMap {
+        let max_locals = code.max_locals as usize;
+        let instructions = match bytecode::decode(code.code) {
+            Ok(instructions) => instructions,
+            Err(_) => return ReferenceMap {live: vec![vec![true; max_locals]; code.code.len()]},
+        };
+
+        // A subroutine can return to the instruction after any jsr.
+        let return_points: Vec<usize> = instructions.iter()
+            .filter(|instruction| matches!(instruction.opcode, JSR | JSR_W))
+            .map(|instruction| instruction.pc + instruction.length)
+            .collect();
+
+        let mut live = vec![vec![]; code.code.len()];
+        for instruction in &instructions {
+            live[instruction.pc] = vec![false; max_locals];
+        }
+
+        // Iterates backwards over the method until nothing changes, propagating liveness from
+        // each instruction to its predecessors.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for instruction in instructions.iter().rev() {
+                let mut successors = instruction.successors();
+                if instruction.opcode == RET {
+                    successors.extend(&return_points);
+                }
+
+                let mut live_in = vec![false; max_locals];
+                for successor in successors {
+                    union(&mut live_in, &live[successor]);
+                }
+                for slot in instruction.writes_locals() {
+                    if let Some(live) = live_in.get_mut(slot) {
+                        *live = false;
+                    }
+                }
+                for slot in instruction.reads_locals() {
+                    if let Some(live) = live_in.get_mut(slot) {
+                        *live = true;
+                    }
+                }
+                // If the instruction throws, its handler runs before anything is written.
+                for row in code.exception_table {
+                    if (row.start_pc as usize..row.end_pc as usize).contains(&instruction.pc) {
+                        union(&mut live_in, &live[row.handler_pc as usize]);
+                    }
+                }
+
+                if live_in != live[instruction.pc] {
+                    live[instruction.pc] = live_in;
+                    changed = true;
+                }
+            }
+        }
+
+        ReferenceMap {live}
+    }
+
+    // Whether the local may be read by the instruction at pc or anything after it.
+    pub fn is_live(&self, pc: usize, slot: usize) -> bool {
+        self.live.get(pc).and_then(|live| live.get(slot)).copied().unwrap_or(true)
+    }
+}
+
+fn union(live: &mut [bool], other: &[bool]) {
+    for (live, &other) in live.iter_mut().zip(other) {
+        *live |= other;
+    }
+}
+
+// Visits the references held by a frame that's stopped at the given pc: its live locals, and
+// everything on its operand stack.
+pub fn visit_frame(class: &RuntimeClass, method_index: usize, pc: usize, locals: &[Value], stack: &[Value], visitor: &mut dyn RootVisitor) {
+    let map = class.reference_map(method_index);
+    for (slot, local) in locals.iter().enumerate() {
+        if let Value::Reference(Some(ref object)) = *local {
+            if map.as_ref().is_none_or(|map| map.is_live(pc, slot)) {
+                visitor.visit_root(Root::Local{class, method_index, slot}, object);
+            }
+        }
+    }
+    for (depth, value) in stack.iter().enumerate() {
+        if let Value::Reference(Some(ref object)) = *value {
+            visitor.visit_root(Root::OperandStack{class, method_index, depth}, object);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use crate::vm::Vm;
+
+
+    // The live locals at each instruction of the method, by pc.
+    fn live_locals(vm: &mut Vm, name: &str) -> Vec<(usize, Vec<usize>)> {
+        let class = vm.load_class("Roots").unwrap();
+        let (method_index, method) = class.class.methods.iter().enumerate()
+            .find(|(_, method)| class.class.utf8(&method.name).unwrap() == name)
+            .unwrap();
+        let code = method.code().unwrap();
+        let map = class.reference_map(method_index).unwrap();
+        bytecode::decode(code.code).unwrap().iter()
+            .map(|instruction| (instruction.pc, (0..code.max_locals as usize).filter(|&slot| map.is_live(instruction.pc, slot)).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_locals_die_after_last_use() {
+        let mut vm = vm_with(&[fixture!("Roots")]);
+        // aload_0, astore_2, aload_1, areturn
+        assert_eq!(vec![(0, vec![0, 1]), (1, vec![1]), (2, vec![1]), (3, vec![])], live_locals(&mut vm, "pick"));
+    }
+
+    #[test]
+    fn test_locals_used_in_a_loop_stay_live() {
+        let mut vm = vm_with(&[fixture!("Roots")]);
+        assert_eq!(vec![
+            (0, vec![0, 1]), (1, vec![0, 1]), (4, vec![0, 1]), (5, vec![0, 1]), (8, vec![]), (9, vec![]),
+            (10, vec![0, 1]), (13, vec![0, 1]), (16, vec![0]), (17, vec![]),
+        ], live_locals(&mut vm, "countDown"));
+    }
+
+    #[test]
+    fn test_locals_used_by_handlers_are_live_in_try_blocks() {
+        let mut vm = vm_with(&[fixture!("Roots")]);
+        // aload_0, checkcast, areturn, then the handler: astore_2, aload_1, areturn
+        assert_eq!(vec![(0, vec![0, 1]), (1, vec![1]), (4, vec![]), (5, vec![1]), (6, vec![1]), (7, vec![])],
+            live_locals(&mut vm, "fallback"));
+    }
+
+    #[test]
+    fn test_visit_roots_reports_statics_and_strings() {
+        let mut vm = vm_with(&[fixture!("Roots")]);
+        vm.invoke_static("Roots", "setUp", "()V", vec![]).unwrap();
+        let mut roots = vec![];
+        vm.visit_roots(&mut |root: Root, object: &ObjectRef| roots.push(format!("{} -> {}", root_name(&root), object.class().name)));
+        assert!(roots.contains(&"static Roots.0 -> java/lang/Object".to_string()));
+        assert!(roots.contains(&"string interned -> java/lang/String".to_string()));
+    }
+
+    fn root_name(root: &Root) -> String {
+        match *root {
+            Root::StaticField{class, slot} => format!("static {}.{}", class.name, slot),
+            Root::InternedString(text) => format!("string {}", text),
+            _ => "other".to_string(),
+        }
+    }
+}
@@ -0,0 +1,517 @@
+use crate::classes::*;
+use crate::counters::MethodCounters;
+use crate::descriptor::FieldType;
+use crate::heap::ObjectRef;
+use crate::inline_cache::InlineCache;
+use crate::interpreter::Instruction;
+use crate::layout::{self, FieldLayout};
+use crate::loaders::LoaderId;
+use crate::roots::ReferenceMap;
+use crate::symbols::{Symbol, SymbolTable};
+use crate::verify::StructureError;
+#[cfg(feature = "jit")]
+use crate::jit::Tier;
+use crate::vm::VmError;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// A single value on the operand stack or in a local variable slot. Longs and doubles are held
+// as one Value on the operand stack, but take up two local variable slots.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    Reference(Option<ObjectRef>),
+    ReturnAddress(usize), // Pushed by jsr, and only ever consumed by astore and ret.
+}
+
+impl Value {
+    pub fn null() -> Value {
+        Value::Reference(None)
+    }
+
+    pub fn default_for(field_type: &FieldType) -> Value {
+        match *field_type {
+            FieldType::Long => Value::Long(0),
+            FieldType::Float => Value::Float(0.0),
+            FieldType::Double => Value::Double(0.0),
+            FieldType::Object(_) | FieldType::Array(_) => Value::null(),
+            _ => Value::Int(0),
+        }
+    }
+
+    pub fn is_category_2(&self) -> bool {
+        matches!(*self, Value::Long(_) | Value::Double(_))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InitState {
+    Uninitialized,
+    Initializing,
+    Initialized,
+}
+
+// A class that has been loaded into the VM, along with its static state.
+pub struct RuntimeClass {
+    pub name: Symbol,
+    pub class: Class,
+    pub super_class: Option<Rc<RuntimeClass>>,
+    pub interfaces: Vec<Rc<RuntimeClass>>,
+    pub component_class: Option<Rc<RuntimeClass>>, // Set for arrays of references.
+    pub loader: LoaderId, // The defining loader.
+    pub init_state: Cell<InitState>,
+    linked: Cell<bool>,
+
+    // The class's own methods and fields by name and descriptor, for resolution and reflection.
+    members: MemberTable,
+
+    // The values of the static fields declared by this class, indexed by the slots in
+    // static_slots. Inherited static fields live in the class that declares them. The values
+    // are only allocated when the class is prepared, as it's linked.
+    static_slots: HashMap<Symbol, usize>,
+    static_defaults: Vec<Value>,
+    static_values: RefCell<Vec<Value>>,
+
+    // Instance fields are laid out with inherited fields first, so that a slot index is valid
+    // for instances of this class and all of its subclasses.
+    field_slots: HashMap<Symbol, usize>,
+    field_defaults: Vec<Value>,
+    layout: FieldLayout,
+
+    // Constant pool entries that have been resolved, indexed by constant pool index.
+    cp_cache: RefCell<Vec<Option<ResolvedEntry>>>,
+
+    // Inline caches for the invokevirtual and invokeinterface instructions in this class, indexed
+    // by the constant pool index of the method they call, so that call sites sharing a method
+    // reference share a cache. Allocated on the first virtual call.
+    inline_caches: RefCell<Vec<InlineCache>>,
+
+    // Each method's bytecode, decoded for the interpreter the first time the method runs.
+    decoded_methods: RefCell<Vec<Option<Rc<[Instruction]>>>>,
+
+    // Whether each method's instructions passed the checks made before it first runs, so that a
+    // method that failed them fails again with the same error rather than being checked again.
+    verified_methods: RefCell<Vec<Option<Result<(), StructureError>>>>,
+
+    // Which locals are live at each pc of each method, worked out when roots are first scanned.
+    reference_maps: RefCell<Vec<Option<Rc<ReferenceMap>>>>,
+
+    // How hot each method is.
+    counters: Box<[Cell<MethodCounters>]>,
+
+    // How far each method has got towards being compiled to native code.
+    #[cfg(feature = "jit")]
+    jit_tiers: RefCell<Vec<Tier>>,
+}
+
+// The result of resolving a constant pool entry, cached so that later executions of the
+// instructions that use it skip the lookup by name. This plays the role of HotSpot's cpCache.
+// Loadable constants such as method handles are cached here too, since every ldc of the same
+// entry must produce the same object. Failures are cached too, since every later attempt to
+// resolve the entry must throw the same error, per JVM spec 5.4.3.
+#[derive(Clone)]
+pub enum ResolvedEntry {
+    Constant(Value),
+    Class(Rc<RuntimeClass>),
+    StaticField{class: Rc<RuntimeClass>, slot: usize}, // The declaring class and its static slot.
+    InstanceField{slot: usize, checks_receiver: bool},
+    Method{class: Rc<RuntimeClass>, method_index: usize, parameter_count: usize, checks_receiver: bool}, // The declaring class.
+    Failed{error_class: &'static str, message: String},
+}
+
+impl RuntimeClass {
+    // The class's names are interned in the symbol table, which should be the VM's.
+    pub fn new(class: Class, super_class: Option<Rc<RuntimeClass>>, interfaces: Vec<Rc<RuntimeClass>>, symbols: &mut SymbolTable) -> Result<RuntimeClass, VmError> {
+        let name = symbols.intern(class.name()?);
+        let constant_count = class.constants.len();
+        let method_count = class.methods.len();
+        let mut field_defaults = super_class.as_ref().map_or_else(Vec::new, |sup| sup.field_defaults.clone());
+        let mut field_slots = HashMap::new();
+        let mut static_slots = HashMap::new();
+        let mut static_defaults = vec![];
+        let mut field_sizes = vec![];
+
+        for field in &class.fields {
+            let field_name = symbols.intern(class.utf8(&field.name)?);
+            let field_type = FieldType::parse(class.utf8(&field.descriptor)?)?;
+            let default = Value::default_for(&field_type);
+            if field.flags.contains(FieldFlags::STATIC) {
+                static_slots.insert(field_name, static_defaults.len());
+                static_defaults.push(default);
+            } else {
+                field_slots.insert(field_name, field_defaults.len());
+                field_defaults.push(default);
+                field_sizes.push(layout::field_size(&field_type));
+            }
+        }
+        let layout = super_class.as_ref().map_or_else(FieldLayout::default, |sup| sup.layout.clone()).extend(&field_sizes);
+        let members = MemberTable::new(&class, symbols)?;
+
+        Ok(RuntimeClass {
+            name,
+            class,
+            super_class,
+            interfaces,
+            component_class: None,
+            loader: LoaderId::BOOTSTRAP,
+            init_state: Cell::new(InitState::Uninitialized),
+            linked: Cell::new(false),
+            members,
+            static_slots,
+            static_defaults,
+            static_values: RefCell::new(vec![]),
+            field_slots,
+            field_defaults,
+            layout,
+            cp_cache: RefCell::new(vec![None; constant_count + 1]),
+            inline_caches: RefCell::new(vec![]),
+            decoded_methods: RefCell::new(vec![None; method_count]),
+            verified_methods: RefCell::new(vec![None; method_count]),
+            reference_maps: RefCell::new(vec![None; method_count]),
+            counters: (0..method_count).map(|_| Cell::default()).collect(),
+            #[cfg(feature = "jit")]
+            jit_tiers: RefCell::new(vec![Tier::default(); method_count]),
+        })
+    }
+
+    // Creates an array class. Arrays extend java/lang/Object and implement java/lang/Cloneable
+    // and java/io/Serializable, which must be passed in, per JVM spec 4.10.1.2.
+    pub fn new_array(name: &str, object_class: Rc<RuntimeClass>, interfaces: Vec<Rc<RuntimeClass>>, component_class: Option<Rc<RuntimeClass>>, symbols: &mut SymbolTable) -> Result<RuntimeClass, VmError> {
+        let mut class = RuntimeClass::new(array_class_file(name), Some(object_class), interfaces, symbols)?;
+        class.component_class = component_class;
+        Ok(class)
+    }
+
+    pub fn is_linked(&self) -> bool {
+        self.linked.get()
+    }
+
+    // Creates the class's static fields with their default values, per JVM spec 5.4.2. This is
+    // the last step of linking; ConstantValue attributes are only applied on initialization.
+    pub fn prepare(&self) {
+        if !self.linked.replace(true) {
+            *self.static_values.borrow_mut() = self.static_defaults.clone();
+        }
+    }
+
+    pub fn is_interface(&self) -> bool {
+        self.class.flags.contains(ClassFlags::INTERFACE)
+    }
+
+    // Whether instances of this class can be assigned to variables of the other class's type.
+    pub fn is_subclass_of(&self, other: &RuntimeClass) -> bool {
+        self.is_same_class(other)
+            || self.super_class.as_ref().is_some_and(|sup| sup.is_subclass_of(other))
+            || self.interfaces.iter().any(|iface| iface.is_subclass_of(other))
+    }
+
+    // Classes are the same if they have the same name and defining loader.
+    pub fn is_same_class(&self, other: &RuntimeClass) -> bool {
+        self.name == other.name && self.loader == other.loader
+    }
+
+    // Whether this is java/lang/Throwable or one of its subclasses.
+    pub fn is_throwable(&self) -> bool {
+        self.name == "java/lang/Throwable" || self.super_class.as_ref().is_some_and(|sup| sup.is_throwable())
+    }
+
+    pub fn is_array(&self) -> bool {
+        self.name.starts_with('[')
+    }
+
+    // The type of the elements of an array class, or None if this isn't an array class.
+    pub fn component_type(&self) -> Option<FieldType> {
+        match FieldType::parse(&self.name) {
+            Ok(FieldType::Array(component)) => Some(*component),
+            _ => None,
+        }
+    }
+
+    // The modifiers returned by Class.getModifiers(). ACC_SUPER isn't a modifier, and array
+    // classes take their visibility from their component type, per JLS 10.8.
+    pub fn modifiers(&self) -> u16 {
+        if !self.is_array() {
+            return (self.class.flags - ClassFlags::SUPER).bits();
+        }
+        let visibility = self.component_class.as_ref().map_or(ClassFlags::PUBLIC, |component| {
+            ClassFlags::from_bits_truncate(component.modifiers()) & ClassFlags::PUBLIC
+        });
+        (visibility | ClassFlags::FINAL | ClassFlags::ABSTRACT).bits()
+    }
+
+    // The name of the class as returned by Class.getName(), e.g. java.lang.String or [I.
+    pub fn java_name(&self) -> String {
+        self.name.replace('/', ".")
+    }
+
+    // Whether a reference to an instance of this class can be stored in a variable of the target
+    // type, as checked by checkcast and instanceof. See JVM spec 6.5.checkcast.
+    pub fn is_assignable_to(&self, target: &RuntimeClass) -> bool {
+        if !self.is_array() || !target.is_array() {
+            return self.is_subclass_of(target);
+        }
+
+        match (&self.component_class, &target.component_class) {
+            (Some(component), Some(target_component)) => component.is_assignable_to(target_component),
+            (None, None) => self.name == target.name, // Arrays of primitives must match exactly.
+            _ => false,
+        }
+    }
+
+    pub fn new_instance_fields(&self) -> Vec<Value> {
+        self.field_defaults.clone()
+    }
+
+    // Finds the slot of the named instance field, which may be declared by a superclass.
+    pub fn field_slot(&self, name: &str) -> Option<usize> {
+        match self.field_slots.get(name) {
+            Some(slot) => Some(*slot),
+            None => self.super_class.as_ref().and_then(|sup| sup.field_slot(name)),
+        }
+    }
+
+    // Where each instance field would sit in memory, for sizing objects and for natives that
+    // address fields by offset.
+    pub fn layout(&self) -> &FieldLayout {
+        &self.layout
+    }
+
+    // The offset of the named instance field from the start of the object.
+    pub fn field_offset(&self, name: &str) -> Option<usize> {
+        self.field_slot(name).and_then(|slot| self.layout.offset(slot))
+    }
+
+    // The names of every instance field, including inherited ones, indexed by slot.
+    pub fn field_names(&self) -> Vec<&str> {
+        let mut names = self.super_class.as_ref().map_or_else(Vec::new, |sup| sup.field_names());
+        names.resize(self.field_defaults.len(), "");
+        for (name, &slot) in &self.field_slots {
+            names[slot] = name;
+        }
+        names
+    }
+
+    // The slot of a static field declared directly on this class.
+    pub fn static_slot(&self, name: &str) -> Option<usize> {
+        self.static_slots.get(name).cloned()
+    }
+
+    pub fn get_static(&self, slot: usize) -> Value {
+        self.static_values.borrow()[slot].clone()
+    }
+
+    pub fn put_static(&self, slot: usize, value: Value) {
+        self.static_values.borrow_mut()[slot] = value;
+    }
+
+    // Reads a static field declared directly on this class by name, for use outside the
+    // interpreter.
+    pub fn static_value(&self, name: &str) -> Option<Value> {
+        self.static_slot(name).and_then(|slot| self.static_values.borrow().get(slot).cloned())
+    }
+
+    pub fn cached_entry(&self, index: u16) -> Option<ResolvedEntry> {
+        self.cp_cache.borrow().get(index as usize).cloned().flatten()
+    }
+
+    pub fn cache_entry(&self, index: u16, entry: ResolvedEntry) {
+        if let Some(slot) = self.cp_cache.borrow_mut().get_mut(index as usize) {
+            *slot = Some(entry);
+        }
+    }
+
+    // The method cached for calls through the method reference on receivers of the given class.
+    #[inline]
+    pub fn inline_cache_lookup(&self, index: u16, receiver: &Rc<RuntimeClass>) -> Option<(Rc<RuntimeClass>, usize)> {
+        self.inline_caches.borrow().get(index as usize).and_then(|cache| cache.lookup(receiver))
+    }
+
+    pub fn inline_cache_record(&self, index: u16, receiver: &Rc<RuntimeClass>, class: &Rc<RuntimeClass>, method_index: usize) {
+        let mut caches = self.inline_caches.borrow_mut();
+        if caches.is_empty() {
+            caches.resize(self.class.constants.len() + 1, InlineCache::Empty);
+        }
+        if let Some(cache) = caches.get_mut(index as usize) {
+            cache.record(receiver, class, method_index);
+        }
+    }
+
+    // A copy of the inline cache for calls through the method reference, for diagnostics.
+    pub fn inline_cache(&self, index: u16) -> InlineCache {
+        self.inline_caches.borrow().get(index as usize).cloned().unwrap_or_default()
+    }
+
+    pub fn clear_inline_caches(&self) {
+        self.inline_caches.borrow_mut().clear();
+    }
+
+    // The outcome of checking the method's instructions, checking them if this is the first time
+    // it's run.
+    pub fn verified_method(&self, method_index: usize, verify: impl FnOnce() -> Result<(), StructureError>) -> Result<(), StructureError> {
+        let mut verified_methods = self.verified_methods.borrow_mut();
+        verified_methods[method_index].get_or_insert_with(verify).clone()
+    }
+
+    // The method's decoded instructions, decoding them if this is the first time it's run.
+    pub fn decoded_method(&self, method_index: usize, decode: impl FnOnce() -> Rc<[Instruction]>) -> Rc<[Instruction]> {
+        let mut decoded_methods = self.decoded_methods.borrow_mut();
+        Rc::clone(decoded_methods[method_index].get_or_insert_with(decode))
+    }
+
+    // The liveness of the method's locals, or None if it has no bytecode.
+    pub fn reference_map(&self, method_index: usize) -> Option<Rc<ReferenceMap>> {
+        let code = self.class.methods[method_index].code()?;
+        let mut reference_maps = self.reference_maps.borrow_mut();
+        Some(Rc::clone(reference_maps[method_index].get_or_insert_with(|| Rc::new(ReferenceMap::new(&code)))))
+    }
+
+    // The values of the static fields declared by this class, by slot.
+    pub fn static_values(&self) -> std::cell::Ref<'_, Vec<Value>> {
+        self.static_values.borrow()
+    }
+
+    // The objects this class holds from outside the heap: the values of its static fields and
+    // its resolved constants.
+    pub fn held_objects(&self) -> Vec<ObjectRef> {
+        let mut objects = vec![];
+        for value in self.static_values.borrow().iter() {
+            if let Value::Reference(Some(ref object)) = *value {
+                objects.push(object.clone());
+            }
+        }
+        for entry in self.cp_cache.borrow().iter().flatten() {
+            if let ResolvedEntry::Constant(Value::Reference(Some(ref object))) = *entry {
+                objects.push(object.clone());
+            }
+        }
+        objects
+    }
+
+    // The classes this one refers to: its supertypes and component type, and those its constant
+    // pool entries resolved to. Inline caches aren't included, since they can always be cleared.
+    pub fn referenced_classes(&self) -> Vec<Rc<RuntimeClass>> {
+        let mut classes: Vec<Rc<RuntimeClass>> = self.super_class.iter()
+            .chain(&self.interfaces)
+            .chain(&self.component_class)
+            .cloned()
+            .collect();
+        for entry in self.cp_cache.borrow().iter().flatten() {
+            match *entry {
+                ResolvedEntry::Class(ref class) | ResolvedEntry::StaticField{ref class, ..} | ResolvedEntry::Method{ref class, ..} => {
+                    classes.push(Rc::clone(class));
+                },
+                _ => (),
+            }
+        }
+        classes
+    }
+
+    // Drops the static fields and caches of a class that's been unloaded. That frees what its
+    // static fields refer to, and breaks any cycles between classes so their metadata is freed
+    // once the last instance goes.
+    pub fn unload(&self) {
+        self.static_values.borrow_mut().clear();
+        for entry in self.cp_cache.borrow_mut().iter_mut() {
+            *entry = None;
+        }
+        self.clear_inline_caches();
+    }
+
+    pub fn counters(&self, method_index: usize) -> MethodCounters {
+        self.counters[method_index].get()
+    }
+
+    #[inline]
+    pub fn count_invocation(&self, method_index: usize) {
+        let counters = &self.counters[method_index];
+        counters.set(MethodCounters {invocations: counters.get().invocations + 1, ..counters.get()});
+    }
+
+    #[inline]
+    pub fn count_backedge(&self, method_index: usize) {
+        let counters = &self.counters[method_index];
+        counters.set(MethodCounters {backedges: counters.get().backedges + 1, ..counters.get()});
+    }
+
+    #[cfg(feature = "jit")]
+    pub fn jit_tier(&self, method_index: usize) -> std::cell::RefMut<'_, Tier> {
+        std::cell::RefMut::map(self.jit_tiers.borrow_mut(), |tiers| &mut tiers[method_index])
+    }
+
+    // Finds the index of a method declared directly on this class.
+    pub fn find_declared_method(&self, name: &str, descriptor: &str) -> Result<Option<usize>, VmError> {
+        Ok(self.members.method(name, descriptor))
+    }
+
+    pub fn members(&self) -> &MemberTable {
+        &self.members
+    }
+}
+
+// Array classes have no class file, so we synthesize one per JVM spec 5.3.3: a public final
+// abstract class extending java/lang/Object, with no fields or methods of its own.
+fn array_class_file(name: &str) -> Class {
+    Class {
+        minor_version: 0,
+        major_version: 52,
+        constants: vec![
+            Constant::Utf8(name.to_string()),
+            Constant::ClassRef(ConstantIndex(1)),
+            Constant::Utf8("java/lang/Object".to_string()),
+            Constant::ClassRef(ConstantIndex(3)),
+        ],
+        flags: ClassFlags::PUBLIC | ClassFlags::FINAL | ClassFlags::ABSTRACT,
+        this_class: ConstantIndex(2),
+        super_class: ConstantIndex(4),
+        interfaces: vec![],
+        fields: vec![],
+        methods: vec![],
+        attributes: vec![],
+    }
+}
+
+// Finds the class that declares the named static field, searching superinterfaces and then
+// superclasses per JVM spec 5.4.3.2. Returns the declaring class and the field's slot within it.
+pub fn resolve_static_field(class: &Rc<RuntimeClass>, name: &str) -> Option<(Rc<RuntimeClass>, usize)> {
+    if let Some(slot) = class.static_slot(name) {
+        return Some((Rc::clone(class), slot));
+    }
+
+    class.interfaces.iter()
+        .find_map(|iface| resolve_static_field(iface, name))
+        .or_else(|| class.super_class.as_ref().and_then(|sup| resolve_static_field(sup, name)))
+}
+
+// Finds the method with the given name and descriptor, searching superclasses and then
+// superinterfaces. Returns the declaring class and the index of the method within it.
+pub fn resolve_method(class: &Rc<RuntimeClass>, name: &str, descriptor: &str) -> Result<Option<(Rc<RuntimeClass>, usize)>, VmError> {
+    let mut current = Some(Rc::clone(class));
+    while let Some(candidate) = current {
+        if let Some(idx) = candidate.find_declared_method(name, descriptor)? {
+            return Ok(Some((candidate, idx)));
+        }
+        current = candidate.super_class.clone();
+    }
+
+    resolve_interface_method(class, name, descriptor)
+}
+
+fn resolve_interface_method(class: &Rc<RuntimeClass>, name: &str, descriptor: &str) -> Result<Option<(Rc<RuntimeClass>, usize)>, VmError> {
+    for iface in &class.interfaces {
+        if let Some(idx) = iface.find_declared_method(name, descriptor)? {
+            return Ok(Some((Rc::clone(iface), idx)));
+        }
+        if let Some(found) = resolve_interface_method(iface, name, descriptor)? {
+            return Ok(Some(found));
+        }
+    }
+
+    match class.super_class {
+        Some(ref sup) => resolve_interface_method(sup, name, descriptor),
+        None => Ok(None),
+    }
+}
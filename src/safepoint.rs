@@ -0,0 +1,115 @@
+use crate::vm::Vm;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// Work that needs every thread stopped at a known point, such as a collection that moves
+// objects. It runs on whichever thread reaches the safepoint last.
+pub type VmOperation = Box<dyn FnOnce(&mut Vm)>;
+
+// The rendezvous between a thread that wants the world stopped and the threads running Java
+// code. Requesting an operation arms the poll that each thread makes before every instruction;
+// each thread that sees it armed arrives here and stops, and the last to arrive runs the
+// operations and releases the others. With a single thread, that's just the thread itself.
+//
+// The poll is a relaxed load of a flag that's almost never set, so it costs a load and a
+// well-predicted branch. The flag is shared so that host threads waiting to run Java code can arm
+// it too, to ask for their turn (see threads). Compiled code doesn't poll: a thread running it
+// reaches a safepoint once it returns to the interpreter.
+pub struct Safepoint {
+    operations: Vec<VmOperation>,
+    threads: usize, // The number of threads that must arrive before the operations run.
+    arrived: usize,
+    completed: u64, // The number of safepoints so far, so that stopped threads can tell when they're released.
+    armed: Arc<AtomicBool>,
+}
+
+impl Default for Safepoint {
+    fn default() -> Safepoint {
+        Safepoint {operations: vec![], threads: 1, arrived: 0, completed: 0, armed: Arc::new(AtomicBool::new(false))}
+    }
+}
+
+impl Safepoint {
+    pub fn new() -> Safepoint {
+        Safepoint::default()
+    }
+
+    pub fn request(&mut self, operation: VmOperation) {
+        self.operations.push(operation);
+        self.armed.store(true, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn is_requested(&self) -> bool {
+        self.armed.load(Ordering::Relaxed)
+    }
+
+    pub fn has_operations(&self) -> bool {
+        !self.operations.is_empty()
+    }
+
+    // The flag the poll checks, for arming it from other host threads.
+    pub fn poll_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.armed)
+    }
+
+    // Clears the poll once a thread has seen it, before it finds out what was asked for.
+    pub fn disarm(&self) {
+        self.armed.store(false, Ordering::Relaxed);
+    }
+
+    // Records that a thread has stopped. If it's the last, the operations are returned for it to
+    // run, and every stopped thread is released; otherwise it must stay stopped until
+    // completed() changes.
+    pub fn arrive(&mut self) -> Option<Vec<VmOperation>> {
+        self.arrived += 1;
+        if self.arrived < self.threads {
+            return None;
+        }
+        self.arrived = 0;
+        self.completed += 1;
+        self.disarm();
+        Some(std::mem::take(&mut self.operations))
+    }
+
+    pub fn completed(&self) -> u64 {
+        self.completed
+    }
+
+    // Sets how many threads take part in the rendezvous, as threads start and finish.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_thread_to_arrive_runs_operations() {
+        let mut safepoint = Safepoint::new();
+        safepoint.set_threads(2);
+        assert!(!safepoint.is_requested());
+        safepoint.request(Box::new(|_| ()));
+        assert!(safepoint.is_requested());
+
+        assert!(safepoint.arrive().is_none());
+        assert_eq!(0, safepoint.completed());
+        assert_eq!(1, safepoint.arrive().unwrap().len());
+        assert_eq!(1, safepoint.completed());
+        assert!(!safepoint.is_requested());
+    }
+
+    #[test]
+    fn test_single_thread_runs_operations_at_once() {
+        let mut safepoint = Safepoint::new();
+        safepoint.request(Box::new(|_| ()));
+        safepoint.request(Box::new(|_| ()));
+        assert_eq!(2, safepoint.arrive().unwrap().len());
+    }
+}
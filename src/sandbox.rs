@@ -0,0 +1,196 @@
+use crate::vm::{Vm, VmError};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+// Decides what Java code may do to the host, for running untrusted code. Install a policy with
+// Vm::set_sandbox_policy. The natives that reach outside the VM (opening files, reading the
+// environment and loading native libraries) each ask it first, and it can let them go ahead, deny
+// them, which throws SecurityException, or answer in the host's place. System.in, System.out and
+// System.err aren't covered, since they're whatever the embedder gives Vm::set_stdin and friends.
+//
+// Everything is allowed by default, so policies only need to override the kinds of access they
+// restrict.
+pub trait SandboxPolicy {
+    // Reading an environment variable with System.getenv. Virtualizing gives the value to return,
+    // or None to make it look unset.
+    fn getenv(&mut self, _name: &str) -> Decision<Option<String>> {
+        Decision::Allow
+    }
+
+    // Opening a file with FileInputStream. Virtualizing gives what to read instead.
+    fn read_file(&mut self, _path: &Path) -> Decision<Box<dyn Read>> {
+        Decision::Allow
+    }
+
+    // Opening a file with FileOutputStream, either truncating it or appending to it. Virtualizing
+    // gives where to write instead.
+    fn write_file(&mut self, _path: &Path, _append: bool) -> Decision<Box<dyn Write>> {
+        Decision::Allow
+    }
+
+    // Loading a native library with System.load or System.loadLibrary. Virtualizing gives a
+    // different library to load.
+    fn load_library(&mut self, _path: &Path) -> Decision<PathBuf> {
+        Decision::Allow
+    }
+}
+
+pub enum Decision<T> {
+    Allow,
+    Deny,
+    Virtualize(T), // Use this in place of the host's resource.
+}
+
+// The default policy, which lets Java code at everything the host process can reach.
+pub struct Unrestricted;
+
+impl SandboxPolicy for Unrestricted {}
+
+// A policy that keeps Java code away from the host entirely: files and libraries are denied, and
+// the environment looks empty.
+pub struct Isolated;
+
+impl SandboxPolicy for Isolated {
+    fn getenv(&mut self, _name: &str) -> Decision<Option<String>> {
+        Decision::Virtualize(None)
+    }
+
+    fn read_file(&mut self, _path: &Path) -> Decision<Box<dyn Read>> {
+        Decision::Deny
+    }
+
+    fn write_file(&mut self, _path: &Path, _append: bool) -> Decision<Box<dyn Write>> {
+        Decision::Deny
+    }
+
+    fn load_library(&mut self, _path: &Path) -> Decision<PathBuf> {
+        Decision::Deny
+    }
+}
+
+// The SecurityException thrown when the policy denies an access, which is described as, say,
+// "read /etc/passwd".
+pub fn denied(vm: &mut Vm, access: &str) -> VmError {
+    vm.throw_new_with_message("java/lang/SecurityException", &format!("Access denied: {}", access))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use crate::console::SharedBuffer;
+    use crate::heap::ObjectRef;
+    use crate::runtime::*;
+    use std::io::Cursor;
+    use std::{env, fs, process};
+
+    fn string(vm: &mut Vm, text: &str) -> Value {
+        Value::Reference(Some(vm.new_string(text).unwrap()))
+    }
+
+    fn getenv(vm: &mut Vm, name: &str) -> Option<String> {
+        let name = string(vm, name);
+        match vm.invoke_static("Sandboxed", "getenv", "(Ljava/lang/String;)Ljava/lang/String;", vec![name]) {
+            Ok(Some(Value::Reference(value))) => value.map(|value| vm.read_string(&value).unwrap()),
+            other => panic!("Expected a string; got {:?}", other),
+        }
+    }
+
+    fn read(vm: &mut Vm, name: &str) -> Result<Vec<u8>, VmError> {
+        let name = string(vm, name);
+        match vm.invoke_static("Sandboxed", "read", "(Ljava/lang/String;)[B", vec![name])? {
+            Some(Value::Reference(Some(bytes))) => Ok(bytes.fields.borrow().bytes().unwrap().iter().map(|&b| b as u8).collect()),
+            other => panic!("Expected a byte array; got {:?}", other),
+        }
+    }
+
+    fn write(vm: &mut Vm, name: &str, append: bool, text: &str) -> Result<Option<Value>, VmError> {
+        let args = vec![string(vm, name), Value::Int(append as i32), string(vm, text)];
+        vm.invoke_static("Sandboxed", "write", "(Ljava/lang/String;ZLjava/lang/String;)Z", args)
+    }
+
+    // The class and message of the exception that the result holds.
+    fn thrown(vm: &Vm, result: Result<impl std::fmt::Debug, VmError>) -> (String, String) {
+        let exception: ObjectRef = match result {
+            Err(VmError::UncaughtException(exception)) => exception,
+            other => panic!("Expected an exception; got {:?}", other),
+        };
+        let message = match crate::vm::get_field(&exception, "detailMessage") {
+            Ok(Value::Reference(Some(message))) => vm.read_string(&message).unwrap(),
+            other => panic!("Expected a message; got {:?}", other),
+        };
+        (exception.class().name.to_string(), message)
+    }
+
+    #[test]
+    fn test_unrestricted_reaches_the_host() {
+        let mut vm = vm_with(&[fixture!("Sandboxed")]);
+        vm.set_sandbox_policy(Box::new(Unrestricted));
+        assert_eq!(env::var("PATH").ok(), getenv(&mut vm, "PATH"));
+        assert_eq!(None, getenv(&mut vm, "JOYVM_SURELY_UNSET"));
+
+        let dir = env::temp_dir().join(format!("joyvm-sandbox-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("written.txt").display().to_string();
+        assert_eq!(Ok(Some(Value::Int(1))), write(&mut vm, &file, false, "first"));
+        assert_eq!(Ok(Some(Value::Int(1))), write(&mut vm, &file, true, " second"));
+        assert_eq!(Ok(b"first second".to_vec()), read(&mut vm, &file));
+        assert_eq!(0, vm.console_mut().open_file_count());
+
+        let missing = dir.join("missing.txt").display().to_string();
+        let result = read(&mut vm, &missing);
+        assert_eq!(("java/io/FileNotFoundException".to_string(), format!("{} (No such file or directory)", missing)), thrown(&vm, result));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_isolated_denies_the_host() {
+        let mut vm = vm_with(&[fixture!("Sandboxed")]);
+        vm.set_sandbox_policy(Box::new(Isolated));
+        assert!(env::var("PATH").is_ok());
+        assert_eq!(None, getenv(&mut vm, "PATH"));
+
+        let result = read(&mut vm, "/etc/passwd");
+        assert_eq!(("java/lang/SecurityException".to_string(), "Access denied: read /etc/passwd".to_string()), thrown(&vm, result));
+        let result = write(&mut vm, "/tmp/joyvm-isolated.txt", false, "escaped");
+        assert_eq!(("java/lang/SecurityException".to_string(), "Access denied: write /tmp/joyvm-isolated.txt".to_string()), thrown(&vm, result));
+        assert!(!Path::new("/tmp/joyvm-isolated.txt").exists());
+
+        let path = string(&mut vm, "/lib/libevil.so");
+        let result = vm.invoke_static("Sandboxed", "load", "(Ljava/lang/String;)V", vec![path]);
+        assert_eq!(("java/lang/SecurityException".to_string(), "Access denied: load /lib/libevil.so".to_string()), thrown(&vm, result));
+    }
+
+    #[test]
+    fn test_virtualized_access() {
+        struct Virtual(SharedBuffer);
+        impl SandboxPolicy for Virtual {
+            fn getenv(&mut self, name: &str) -> Decision<Option<String>> {
+                match name {
+                    "HOME" => Decision::Virtualize(Some("/sandbox".to_string())),
+                    _ => Decision::Deny,
+                }
+            }
+
+            fn read_file(&mut self, path: &Path) -> Decision<Box<dyn Read>> {
+                Decision::Virtualize(Box::new(Cursor::new(format!("contents of {}", path.display()).into_bytes())))
+            }
+
+            fn write_file(&mut self, _path: &Path, _append: bool) -> Decision<Box<dyn Write>> {
+                Decision::Virtualize(Box::new(self.0.clone()))
+            }
+        }
+
+        let written = SharedBuffer::new();
+        let mut vm = vm_with(&[fixture!("Sandboxed")]);
+        vm.set_sandbox_policy(Box::new(Virtual(written.clone())));
+        assert_eq!(Some("/sandbox".to_string()), getenv(&mut vm, "HOME"));
+        let name = string(&mut vm, "USER");
+        let result = vm.invoke_static("Sandboxed", "getenv", "(Ljava/lang/String;)Ljava/lang/String;", vec![name]);
+        assert_eq!(("java/lang/SecurityException".to_string(), "Access denied: getenv USER".to_string()), thrown(&vm, result));
+
+        assert_eq!(Ok(b"contents of /etc/passwd".to_vec()), read(&mut vm, "/etc/passwd"));
+        assert_eq!(Ok(Some(Value::Int(1))), write(&mut vm, "/etc/passwd", false, "virtual"));
+        assert_eq!("virtual", written.text());
+    }
+}
@@ -0,0 +1,132 @@
+use crate::heap::ObjectRef;
+use crate::loaders::LoaderId;
+use crate::runtime::*;
+use crate::threads;
+use crate::vm::{Vm, VmError};
+
+// The class that holds the hooks registered with Runtime.addShutdownHook.
+const HOOKS_CLASS: &str = "java/lang/ApplicationShutdownHooks";
+
+// Shutdown begins when main returns or throws, or when the program calls Runtime.exit (which
+// System.exit calls). The hooks then run one after another, and the VM halts by unwinding with
+// VmError::SystemExit. Runtime.halt unwinds straight away, without running them.
+pub fn invoke_native(vm: &mut Vm, class: &str, name: &str, descriptor: &str, args: &[Value]) -> Result<Option<Value>, VmError> {
+    match (class, name, descriptor, args) {
+        ("java/lang/Runtime", "exit", "(I)V", &[Value::Reference(Some(_)), Value::Int(status)]) => {
+            run_hooks(vm)?;
+            Err(VmError::SystemExit(status))
+        },
+        ("java/lang/Runtime", "halt", "(I)V", &[Value::Reference(Some(_)), Value::Int(status)]) => Err(VmError::SystemExit(status)),
+        _ => Err(VmError::Unsupported(format!("native method {}.{}{}", class, name, descriptor))),
+    }
+}
+
+// Runs the shutdown hooks unless shutdown has already begun, in which case there are none left
+// to run. An exception escaping a hook is reported on stderr, as it would be if it escaped the
+// hook's thread, and the other hooks still run. A hook that calls exit or halt stops the rest.
+pub fn run_hooks(vm: &mut Vm) -> Result<(), VmError> {
+    // Hooks can't have been added unless the class that holds them was loaded.
+    if vm.find_loaded_class(LoaderId::BOOTSTRAP, HOOKS_CLASS).is_none() {
+        return Ok(());
+    }
+    let hooks = match vm.invoke_static(HOOKS_CLASS, "take", "()[Ljava/lang/Thread;", vec![])? {
+        Some(Value::Reference(Some(hooks))) => hooks,
+        other => return Err(VmError::InvalidBytecode(format!("Shutdown hooks are {:?} rather than an array", other))),
+    };
+    let count = hooks.fields.borrow().len();
+    for index in 0..count {
+        let hook = hooks.fields.borrow().get(index);
+        if let Value::Reference(Some(hook)) = hook {
+            run_hook(vm, &hook)?;
+        }
+    }
+    Ok(())
+}
+
+fn run_hook(vm: &mut Vm, hook: &ObjectRef) -> Result<(), VmError> {
+    match threads::invoke_run(vm, hook) {
+        Err(VmError::UncaughtException(throwable)) => threads::handle_uncaught(vm, hook, &throwable),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use crate::console::SharedBuffer;
+    use crate::outcome::Outcome;
+
+    // Runs ShutdownHooks, finishing as `how` says, and returns the outcome and what it printed.
+    fn run(how: &str) -> (Outcome, String, String) {
+        let mut vm = vm_with(&[
+            fixture!("ShutdownHooks"),
+            fixture!("ShutdownHooks$Printer"),
+            fixture!("ShutdownHooks$Exiting"),
+            fixture!("ShutdownHooks$Failing"),
+            fixture!("ShutdownHooks$Adding"),
+        ]);
+        let (stdout, stderr) = (SharedBuffer::new(), SharedBuffer::new());
+        vm.set_stdout(stdout.clone());
+        vm.set_stderr(stderr.clone());
+        let outcome = vm.run_main("ShutdownHooks", &[how]).unwrap();
+        (outcome, stdout.text(), stderr.text())
+    }
+
+    // What main prints while registering and removing its hooks.
+    const REGISTERED: &str = "Hook previously registered\ntrue\nfalse\n";
+
+    #[test]
+    fn test_hooks_run_in_order_after_main_returns() {
+        let (outcome, stdout, _) = run("return");
+        assert_eq!(Outcome::Completed, outcome);
+        assert_eq!(format!("{}main returned\nfirst\nsecond\n", REGISTERED), stdout);
+    }
+
+    #[test]
+    fn test_exit_runs_hooks_then_exits_with_its_status() {
+        let (outcome, stdout, _) = run("exit");
+        assert_eq!(Outcome::Exited(3), outcome);
+        assert_eq!(3, outcome.exit_code());
+        assert_eq!(format!("{}first\nsecond\n", REGISTERED), stdout);
+    }
+
+    #[test]
+    fn test_halt_skips_hooks() {
+        let (outcome, stdout, _) = run("halt");
+        assert_eq!(Outcome::Exited(4), outcome);
+        assert_eq!(REGISTERED, stdout);
+    }
+
+    #[test]
+    fn test_hooks_run_after_main_throws() {
+        let (outcome, stdout, _) = run("throw");
+        match outcome {
+            Outcome::Threw(ref throwable) => assert_eq!("java.lang.IllegalStateException", throwable.class_name),
+            ref other => panic!("Expected main to throw; got {:?}", other),
+        }
+        assert_eq!(format!("{}first\nsecond\n", REGISTERED), stdout);
+    }
+
+    #[test]
+    fn test_exit_from_a_hook_stops_the_rest() {
+        let (outcome, stdout, _) = run("exitFromHook");
+        assert_eq!(Outcome::Exited(7), outcome);
+        assert_eq!(format!("{}main returned\nfirst\nsecond\nexiting from a hook\n", REGISTERED), stdout);
+    }
+
+    #[test]
+    fn test_failing_hook_is_reported_and_others_still_run() {
+        let (outcome, stdout, stderr) = run("failingHook");
+        assert_eq!(Outcome::Completed, outcome);
+        assert_eq!(format!("{}main returned\nfirst\nsecond\nShutdown in progress\n", REGISTERED), stdout);
+        assert!(stderr.starts_with("Exception in thread \"failing hook\" java.lang.RuntimeException: boom\n\tat ShutdownHooks$Failing.run"), "{}", stderr);
+    }
+
+    #[test]
+    fn test_shutdown_without_hooks_loads_nothing() {
+        let mut vm = Vm::new();
+        assert_eq!(Ok(()), vm.shutdown());
+        assert!(vm.find_loaded_class(LoaderId::BOOTSTRAP, HOOKS_CLASS).is_none());
+    }
+}
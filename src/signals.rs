@@ -0,0 +1,81 @@
+use crate::vm::Vm;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::sync::Arc;
+
+// What to do when the process gets SIGQUIT, from Ctrl-\ or kill -QUIT, which for a JVM is to
+// print a thread dump and carry on. It runs on the Java thread that next reaches a safepoint.
+pub type QuitHandler = Box<dyn FnMut(&mut Vm)>;
+
+// Set by the signal handler, for the VM to see at its next safepoint.
+static QUIT: AtomicBool = AtomicBool::new(false);
+// The safepoint poll of the VM that handles SIGQUIT, which the signal handler arms.
+static POLL: AtomicPtr<AtomicBool> = AtomicPtr::new(ptr::null_mut());
+
+#[cfg(unix)]
+mod unix {
+    use std::os::raw::c_int;
+
+    pub const SIGQUIT: c_int = 3;
+
+    extern "C" {
+        pub fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+}
+
+// A signal handler may only touch atomics, so this just sets the flags.
+#[cfg(unix)]
+extern "C" fn on_quit(_signum: std::os::raw::c_int) {
+    QUIT.store(true, Ordering::Relaxed);
+    let poll = POLL.load(Ordering::Relaxed);
+    if !poll.is_null() {
+        // The poll is never freed once it's been given to the handler.
+        unsafe { (*poll).store(true, Ordering::Relaxed) };
+    }
+}
+
+// Catches SIGQUIT, rather than letting it end the process, and arms the poll when it comes. The
+// signal is process-wide, so only the VM installed last hears it. Returns whether signals are
+// supported here, which they're only on Unix.
+pub fn install_quit_handler(poll: Arc<AtomicBool>) -> bool {
+    // A handler that's running may still be using the previous poll, so that's leaked.
+    POLL.store(Arc::into_raw(poll) as *mut AtomicBool, Ordering::Relaxed);
+    #[cfg(unix)]
+    unsafe {
+        unix::signal(unix::SIGQUIT, on_quit);
+    }
+    cfg!(unix)
+}
+
+// Whether SIGQUIT has come since this was last asked, clearing it.
+pub fn take_quit() -> bool {
+    QUIT.swap(false, Ordering::Relaxed)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use crate::runtime::Value;
+    use std::cell::Cell;
+    use std::os::raw::c_int;
+    use std::rc::Rc;
+
+    extern "C" {
+        fn raise(signum: c_int) -> c_int;
+    }
+
+    #[test]
+    fn test_quit_runs_handler_at_safepoint() {
+        let mut vm = vm_with(&[fixture!("Debuggee")]);
+        let depths = Rc::new(Cell::new(None));
+        let seen = Rc::clone(&depths);
+        assert!(vm.set_quit_handler(Box::new(move |vm: &mut Vm| seen.set(Some(vm.stack_depth())))));
+
+        assert_eq!(0, unsafe { raise(unix::SIGQUIT) });
+        assert!(vm.safepoint().is_requested());
+        vm.invoke_static("Debuggee", "square", "(I)I", vec![Value::Int(3)]).unwrap();
+        assert_eq!(Some(1), depths.get());
+        assert!(!take_quit());
+    }
+}
@@ -0,0 +1,378 @@
+use crate::bytecode::{self, Operand};
+use crate::classes::*;
+use crate::descriptor::{DescriptorError, MethodDescriptor};
+use crate::inference::{self, Frame, Hierarchy, InferenceError, Problem, Slot, Type};
+use std::collections::{BTreeMap, BTreeSet};
+use std::{error, fmt};
+
+// Compares the frames that methods' StackMapTables declare with those that type inference
+// computes from their code, to help debug bytecode generators. The JVM's type checking verifier
+// (JVM spec 4.10.1) takes the declared frames on trust and only checks the code against them, so
+// a wrong frame shows up as a VerifyError some distance from the mistake, if at all. Declared
+// types may be more general than inferred ones, as when javac declares an interface type or drops
+// a local that has gone out of scope, so only declared types that the inferred ones can't be
+// assigned to are divergences.
+
+pub fn check_class(class: &Class, hierarchy: &mut dyn Hierarchy) -> Result<Vec<Divergence>, StackMapError> {
+    let mut divergences = vec![];
+    for method in &class.methods {
+        divergences.extend(check_method(class, method, hierarchy)?);
+    }
+    Ok(divergences)
+}
+
+// Reports the pcs where the declared and inferred frames differ, in order. Methods without code
+// have no frames, and those without a StackMapTable declare none, as is right for code without
+// branches.
+pub fn check_method(class: &Class, method: &Method, hierarchy: &mut dyn Hierarchy) -> Result<Vec<Divergence>, StackMapError> {
+    let code = match method.code() {
+        Some(code) => code,
+        None => return Ok(vec![]),
+    };
+    let signature = format!("{}{}", class.utf8(&method.name)?, class.utf8(&method.descriptor)?);
+    let inferred = inference::infer_frames(class, method, hierarchy)?;
+    let declared = declared_frames(class, method)?;
+
+    // Every branch target and exception handler that can be reached needs a frame.
+    let instructions = bytecode::decode(code.code).map_err(|cause| InferenceError::Bytecode{method: signature.clone(), cause})?;
+    let mut targets: BTreeSet<usize> = code.exception_table.iter().map(|row| row.handler_pc as usize).collect();
+    for instruction in &instructions {
+        match instruction.operand {
+            Operand::Branch(target) => {
+                targets.insert(target);
+            },
+            Operand::Switch{default, targets: ref cases} => {
+                targets.insert(default);
+                targets.extend(cases.iter().map(|&(_, target)| target));
+            },
+            _ => (),
+        }
+    }
+
+    let mut differences: Vec<(usize, Difference)> = targets.into_iter()
+        .filter(|pc| inferred.contains_key(pc) && !declared.contains_key(pc))
+        .map(|pc| (pc, Difference::MissingFrame))
+        .collect();
+    for (&pc, frame) in &declared {
+        match inferred.get(&pc) {
+            Some(inferred) => differences.extend(compare(frame, inferred, hierarchy)?.into_iter().map(|difference| (pc, difference))),
+            None => differences.push((pc, Difference::Unreachable)),
+        }
+    }
+    differences.sort_by_key(|&(pc, _)| pc);
+    Ok(differences.into_iter().map(|(pc, difference)| Divergence {method: signature.clone(), pc, difference}).collect())
+}
+
+// The frames that the method's StackMapTable declares, by pc, with the locals laid out as type
+// inference lays them out: a slot each, with longs and doubles followed by top, up to max_locals.
+// Each entry of the table describes its frame as a change to the previous one, starting from a
+// frame with just the method's parameters as locals (JVM spec 4.7.4).
+pub fn declared_frames(class: &Class, method: &Method) -> Result<BTreeMap<usize, Frame>, StackMapError> {
+    let mut frames = BTreeMap::new();
+    let code = match method.code() {
+        Some(code) => code,
+        None => return Ok(frames),
+    };
+    let entries = code.attributes.iter().find_map(|attribute| match *attribute {
+        Attribute::StackMapTable{ref entries, ..} => Some(entries),
+        _ => None,
+    });
+
+    let mut locals = parameters(class, method)?;
+    let mut previous: Option<usize> = None;
+    for entry in entries.into_iter().flatten() {
+        let (offset_delta, stack) = match *entry {
+            StackMapFrame::SameFrame{offset_delta} => (offset_delta as usize, vec![]),
+            StackMapFrame::SameFrameExtended{offset_delta} => (offset_delta as usize, vec![]),
+            StackMapFrame::SameLocalsOneStackItemFrame{offset_delta, ref stack_item} => (offset_delta as usize, vec![declared_type(class, stack_item)?]),
+            StackMapFrame::SameLocalsOneStackItemFrameExtended{offset_delta, ref stack_item} => (offset_delta as usize, vec![declared_type(class, stack_item)?]),
+            StackMapFrame::ChopFrame{offset_delta, num_absent_locals} => {
+                locals.truncate(locals.len().saturating_sub(num_absent_locals as usize));
+                (offset_delta as usize, vec![])
+            },
+            StackMapFrame::AppendFrame{offset_delta, ref new_locals} => {
+                for local in new_locals {
+                    locals.push(declared_type(class, local)?);
+                }
+                (offset_delta as usize, vec![])
+            },
+            StackMapFrame::FullFrame{offset_delta, locals: ref full_locals, ref stack_items} => {
+                locals = full_locals.iter().map(|local| declared_type(class, local)).collect::<Result<_, _>>()?;
+                (offset_delta as usize, stack_items.iter().map(|item| declared_type(class, item)).collect::<Result<_, _>>()?)
+            },
+        };
+        // Frames after the first are at least one byte apart, so their deltas are one less.
+        let pc = previous.map_or(offset_delta, |previous| previous + offset_delta + 1);
+        previous = Some(pc);
+
+        let mut slots = vec![];
+        for local in &locals {
+            slots.push(local.clone());
+            if local.is_category_2() {
+                slots.push(Type::Top);
+            }
+        }
+        if slots.len() < code.max_locals as usize {
+            slots.resize(code.max_locals as usize, Type::Top);
+        }
+        let this_uninitialized = slots.contains(&Type::UninitializedThis);
+        frames.insert(pc, Frame {locals: slots, stack, this_uninitialized});
+    }
+    Ok(frames)
+}
+
+// The locals of the frame that a StackMapTable's first entry is relative to.
+fn parameters(class: &Class, method: &Method) -> Result<Vec<Type>, StackMapError> {
+    let class_name = class.name()?;
+    let descriptor = MethodDescriptor::parse(class.utf8(&method.descriptor)?)?;
+    let mut locals = vec![];
+    if !method.flags.contains(MethodFlags::STATIC) {
+        let constructing = class.utf8(&method.name)? == "<init>" && class_name != "java/lang/Object";
+        locals.push(if constructing { Type::UninitializedThis } else { Type::Reference(class_name.to_string()) });
+    }
+    locals.extend(descriptor.parameters.iter().map(Type::from_field_type));
+    Ok(locals)
+}
+
+fn declared_type(class: &Class, verification_type: &VerificationType) -> Result<Type, StackMapError> {
+    Ok(match *verification_type {
+        VerificationType::Top => Type::Top,
+        VerificationType::Integer => Type::Int,
+        VerificationType::Float => Type::Float,
+        VerificationType::Long => Type::Long,
+        VerificationType::Double => Type::Double,
+        VerificationType::Null => Type::Null,
+        VerificationType::UninitializedThis => Type::UninitializedThis,
+        VerificationType::Object(ref index) => Type::Reference(class.class_name(index)?.to_string()),
+        VerificationType::Uninitialized(offset) => Type::Uninitialized(offset as usize),
+    })
+}
+
+// The ways in which the inferred frame doesn't fit the declared one.
+fn compare(declared: &Frame, inferred: &Frame, hierarchy: &mut dyn Hierarchy) -> Result<Vec<Difference>, StackMapError> {
+    let mut differences = vec![];
+    if declared.locals.len() > inferred.locals.len() {
+        differences.push(Difference::TooManyLocals{declared: declared.locals.len(), max_locals: inferred.locals.len()});
+    }
+    for (index, (declared, inferred)) in declared.locals.iter().zip(&inferred.locals).enumerate() {
+        if !inference::is_assignable(inferred, declared, hierarchy)? {
+            differences.push(Difference::Type{slot: Slot::Local(index), declared: declared.clone(), inferred: inferred.clone()});
+        }
+    }
+    if declared.stack.len() != inferred.stack.len() {
+        differences.push(Difference::StackHeight{declared: declared.stack.len(), inferred: inferred.stack.len()});
+        return Ok(differences);
+    }
+    for (index, (declared, inferred)) in declared.stack.iter().zip(&inferred.stack).enumerate() {
+        if !inference::is_assignable(inferred, declared, hierarchy)? {
+            differences.push(Difference::Type{slot: Slot::Stack(index), declared: declared.clone(), inferred: inferred.clone()});
+        }
+    }
+    Ok(differences)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Divergence {
+    pub method: String, // The name and descriptor.
+    pub pc: usize,
+    pub difference: Difference,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Difference {
+    MissingFrame, // At a branch target or exception handler.
+    StackHeight{declared: usize, inferred: usize},
+    TooManyLocals{declared: usize, max_locals: usize},
+    Type{slot: Slot, declared: Type, inferred: Type},
+    Unreachable, // A frame is declared where no instruction can be reached.
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} @{}: {}", self.method, self.pc, self.difference)
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Difference::MissingFrame => write!(f, "No frame is declared at this branch target"),
+            Difference::StackHeight{declared, inferred} => write!(f, "Stack height is declared as {} but inferred as {}", declared, inferred),
+            Difference::TooManyLocals{declared, max_locals} => write!(f, "{} locals are declared but max_locals is {}", declared, max_locals),
+            Difference::Type{slot, ref declared, ref inferred} => write!(f, "{} is declared as {} but inferred as {}", slot, declared, inferred),
+            Difference::Unreachable => write!(f, "A frame is declared where no instruction is reached"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StackMapError {
+    Comparison(Problem), // Comparing types needed a class that isn't known.
+    ConstantLookup(ConstantLookupError),
+    Descriptor(DescriptorError),
+    Inference(InferenceError),
+}
+
+impl std::convert::From<Problem> for StackMapError {
+    fn from(err: Problem) -> StackMapError {
+        StackMapError::Comparison(err)
+    }
+}
+
+impl std::convert::From<ConstantLookupError> for StackMapError {
+    fn from(err: ConstantLookupError) -> StackMapError {
+        StackMapError::ConstantLookup(err)
+    }
+}
+
+impl std::convert::From<DescriptorError> for StackMapError {
+    fn from(err: DescriptorError) -> StackMapError {
+        StackMapError::Descriptor(err)
+    }
+}
+
+impl std::convert::From<InferenceError> for StackMapError {
+    fn from(err: InferenceError) -> StackMapError {
+        StackMapError::Inference(err)
+    }
+}
+
+impl fmt::Display for StackMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StackMapError::Comparison(ref cause) => write!(f, "Can't compare frames: {}", cause),
+            StackMapError::ConstantLookup(ref cause) => write!(f, "Invalid constant pool: {}", cause),
+            StackMapError::Descriptor(ref cause) => write!(f, "Invalid descriptor: {}", cause),
+            StackMapError::Inference(ref cause) => write!(f, "Can't infer frames: {}", cause),
+        }
+    }
+}
+
+impl error::Error for StackMapError {
+    fn description(&self) -> &str {
+        match *self {
+            StackMapError::Comparison(_) => "Can't compare frames",
+            StackMapError::ConstantLookup(_) => "Invalid constant pool",
+            StackMapError::Descriptor(_) => "Invalid descriptor",
+            StackMapError::Inference(_) => "Can't infer frames",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            StackMapError::Comparison(ref cause) => Some(cause),
+            StackMapError::ConstantLookup(ref cause) => Some(cause),
+            StackMapError::Descriptor(ref cause) => Some(cause),
+            StackMapError::Inference(ref cause) => Some(cause),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::parsed_fixture;
+    use crate::classloader;
+    use crate::inference::ClassInfo;
+    use std::collections::HashMap;
+
+    // Answers from the bootstrap classes and the given classes.
+    fn hierarchy(classes: &[&Class]) -> impl Hierarchy {
+        let bootstrap: Vec<Class> = crate::bootstrap::CLASSES.iter().map(|&(_, bytes)| classloader::load_class(bytes).unwrap()).collect();
+        let known: HashMap<String, ClassInfo> = bootstrap.iter().chain(classes.iter().copied())
+            .map(|class| (class.name().unwrap().to_string(), ClassInfo::of(class).unwrap()))
+            .collect();
+        move |name: &str| known.get(name).cloned()
+    }
+
+    fn method_index(class: &Class, name: &str) -> usize {
+        class.methods.iter().position(|method| class.utf8(&method.name).unwrap() == name).unwrap()
+    }
+
+    fn stack_map_table<'a>(class: &'a mut Class, name: &str) -> &'a mut Vec<StackMapFrame> {
+        let index = method_index(class, name);
+        let code_attributes = class.methods[index].attributes.iter_mut().find_map(|attribute| match *attribute {
+            Attribute::Code(ref mut body) => Some(&mut body.attributes),
+            _ => None,
+        }).unwrap();
+        code_attributes.iter_mut().find_map(|attribute| match *attribute {
+            Attribute::StackMapTable{ref mut entries, ..} => Some(entries),
+            _ => None,
+        }).unwrap()
+    }
+
+    fn divergences(class: &Class, name: &str) -> Vec<Divergence> {
+        check_method(class, &class.methods[method_index(class, name)], &mut hierarchy(&[class])).unwrap()
+    }
+
+    #[test]
+    fn test_compiled_classes_match_their_stack_maps() {
+        let classes = [parsed_fixture!("Exceptions"), parsed_fixture!("Boom"), parsed_fixture!("Bang"), parsed_fixture!("Dispatch"), parsed_fixture!("Arrays"), parsed_fixture!("Switches"), parsed_fixture!("Graph"), parsed_fixture!("Casts")];
+        let mut hierarchy = hierarchy(&classes.iter().collect::<Vec<_>>());
+        for class in &classes {
+            assert_eq!(Ok(vec![]), check_class(class, &mut hierarchy));
+        }
+        for &(_, bytes) in crate::bootstrap::CLASSES {
+            assert_eq!(Ok(vec![]), check_class(&classloader::load_class(bytes).unwrap(), &mut hierarchy));
+        }
+    }
+
+    #[test]
+    fn test_declared_frames_are_expanded() {
+        let class = parsed_fixture!("Arrays");
+        let frames = declared_frames(&class, &class.methods[method_index(&class, "sumInts")]).unwrap();
+        let int_array = Type::Reference("[I".to_string());
+        assert_eq!(vec![6, 22, 26, 44], frames.keys().copied().collect::<Vec<_>>());
+        assert_eq!(vec![Type::Int, int_array.clone(), Type::Int, Type::Top], frames[&6].locals);
+        assert_eq!(vec![Type::Int, int_array.clone(), Type::Top, Type::Top], frames[&22].locals);
+        assert_eq!(vec![Type::Int, int_array.clone(), Type::Int, Type::Int], frames[&26].locals);
+        assert_eq!(vec![Type::Int, int_array, Type::Int, Type::Top], frames[&44].locals);
+        assert!(frames.values().all(|frame| frame.stack.is_empty() && !frame.this_uninitialized));
+    }
+
+    #[test]
+    fn test_stack_maps_start_from_the_parameters() {
+        let class = parsed_fixture!("Exceptions");
+        assert_eq!(Ok(vec![Type::UninitializedThis]), parameters(&class, &class.methods[method_index(&class, "<init>")]));
+        let class = parsed_fixture!("Arrays");
+        assert_eq!(Ok(vec![Type::Int]), parameters(&class, &class.methods[method_index(&class, "sumInts")]));
+    }
+
+    #[test]
+    fn test_wrong_types_are_reported() {
+        let mut class = parsed_fixture!("Arrays");
+        if let StackMapFrame::AppendFrame{ref mut new_locals, ..} = stack_map_table(&mut class, "sumInts")[0] {
+            new_locals[1] = VerificationType::Float;
+        }
+        let divergences = divergences(&class, "sumInts");
+        assert_eq!(vec![Divergence {method: "sumInts(I)I".to_string(), pc: 6, difference: Difference::Type{slot: Slot::Local(2), declared: Type::Float, inferred: Type::Int}}], divergences);
+        assert_eq!("sumInts(I)I @6: locals[2] is declared as float but inferred as integer", divergences[0].to_string());
+    }
+
+    #[test]
+    fn test_missing_frames_are_reported() {
+        let mut class = parsed_fixture!("Arrays");
+        stack_map_table(&mut class, "sumInts").pop();
+        assert_eq!(vec![Divergence {method: "sumInts(I)I".to_string(), pc: 44, difference: Difference::MissingFrame}], divergences(&class, "sumInts"));
+    }
+
+    #[test]
+    fn test_stack_heights_are_compared() {
+        let mut class = parsed_fixture!("Arrays");
+        let table = stack_map_table(&mut class, "sumInts");
+        table[3] = StackMapFrame::SameLocalsOneStackItemFrame{offset_delta: 17, stack_item: VerificationType::Integer};
+        assert_eq!(vec![Divergence {method: "sumInts(I)I".to_string(), pc: 44, difference: Difference::StackHeight{declared: 1, inferred: 0}}], divergences(&class, "sumInts"));
+    }
+
+    #[test]
+    fn test_frames_where_nothing_is_reached_are_reported() {
+        let mut class = parsed_fixture!("Arrays");
+        // A frame in the middle of the newarray instruction, with the next one moved to keep its pc.
+        let table = stack_map_table(&mut class, "sumInts");
+        table.insert(0, StackMapFrame::SameFrame{offset_delta: 2});
+        if let StackMapFrame::AppendFrame{ref mut offset_delta, ..} = table[1] {
+            *offset_delta = 3;
+        }
+        assert_eq!(vec![Divergence {method: "sumInts(I)I".to_string(), pc: 2, difference: Difference::Unreachable}], divergences(&class, "sumInts"));
+    }
+}
@@ -0,0 +1,127 @@
+use crate::heap::{ObjectRef, WeakObjectRef};
+use std::collections::HashMap;
+
+// The runtime string pool, holding the canonical String object for each string literal and
+// each string passed to String.intern. By default the pool is a GC root, so interned strings
+// live as long as the VM; a weak pool lets them be freed once nothing else refers to them, in
+// which case interning the same text again creates a new object.
+#[derive(Default)]
+pub struct StringPool {
+    strings: HashMap<String, Entry>,
+    weak: bool,
+}
+
+enum Entry {
+    Strong(ObjectRef),
+    Weak(WeakObjectRef),
+}
+
+impl StringPool {
+    pub fn new() -> StringPool {
+        StringPool::default()
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    // Switches between holding the pooled strings strongly and weakly, converting every entry.
+    pub fn set_weak(&mut self, weak: bool) {
+        self.weak = weak;
+        let strings = std::mem::take(&mut self.strings);
+        for (text, entry) in strings {
+            if let Some(string) = entry.get() {
+                self.insert(text, string);
+            }
+        }
+    }
+
+    pub fn get(&self, text: &str) -> Option<ObjectRef> {
+        self.strings.get(text).and_then(Entry::get)
+    }
+
+    pub fn insert(&mut self, text: String, string: ObjectRef) {
+        let entry = if self.weak { Entry::Weak(string.downgrade()) } else { Entry::Strong(string) };
+        self.strings.insert(text, entry);
+    }
+
+    // The strings the pool keeps alive, which are GC roots. This is empty for a weak pool.
+    pub fn strong_strings(&self) -> impl Iterator<Item = (&str, &ObjectRef)> {
+        self.strings.iter().filter_map(|(text, entry)| match *entry {
+            Entry::Strong(ref string) => Some((text.as_str(), string)),
+            Entry::Weak(_) => None,
+        })
+    }
+
+    // Forgets weakly held strings that have been freed.
+    pub fn purge(&mut self) {
+        self.strings.retain(|_, entry| entry.get().is_some());
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+impl Entry {
+    fn get(&self) -> Option<ObjectRef> {
+        match *self {
+            Entry::Strong(ref string) => Some(string.clone()),
+            Entry::Weak(ref string) => string.upgrade(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::roots::Root;
+    use crate::heap::ObjectRef;
+    use crate::vm::Vm;
+
+    fn pooled_roots(vm: &Vm) -> Vec<String> {
+        let mut roots = vec![];
+        vm.visit_roots(&mut |root: Root, _: &ObjectRef| if let Root::InternedString(text) = root {
+            roots.push(text.to_string());
+        });
+        roots
+    }
+
+    #[test]
+    fn test_strong_pool_keeps_strings_alive() {
+        let mut vm = Vm::new();
+        let first = vm.intern_string("kept").unwrap().downgrade();
+        assert!(first.upgrade().is_some());
+        assert_eq!(first.upgrade(), Some(vm.intern_string("kept").unwrap()));
+        assert_eq!(vec!["kept".to_string()], pooled_roots(&vm));
+    }
+
+    #[test]
+    fn test_weak_pool_lets_strings_be_freed() {
+        let mut vm = Vm::new();
+        vm.set_weak_string_pool(true);
+        let string = vm.intern_string("temporary").unwrap();
+        assert_eq!(Some(string.clone()), vm.string_pool().get("temporary"));
+        assert!(pooled_roots(&vm).is_empty());
+
+        drop(string);
+        assert_eq!(None, vm.string_pool().get("temporary"));
+        vm.set_weak_string_pool(false);
+        assert!(vm.string_pool().is_empty());
+    }
+
+    #[test]
+    fn test_switching_to_a_strong_pool_keeps_live_strings() {
+        let mut vm = Vm::new();
+        vm.set_weak_string_pool(true);
+        let string = vm.intern_string("live").unwrap();
+        vm.set_weak_string_pool(false);
+        let weak = string.downgrade();
+        drop(string);
+        assert!(weak.upgrade().is_some());
+        assert_eq!(1, vm.string_pool().len());
+    }
+}
@@ -0,0 +1,174 @@
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::Arc;
+use std::fmt;
+
+// The VM-wide table of class names, member names and descriptors. Each class file has its own
+// constant pool, so the same names turn up in thousands of them; the VM's own copies are interned
+// here instead, so that there's just one of each however many classes use it.
+#[derive(Default)]
+pub struct SymbolTable {
+    symbols: HashSet<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    // The symbol for the text, adding it if it's new.
+    pub fn intern(&mut self, text: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(text) {
+            return symbol.clone();
+        }
+        let symbol = Symbol(Arc::from(text));
+        self.symbols.insert(symbol.clone());
+        symbol
+    }
+
+    pub fn get(&self, text: &str) -> Option<Symbol> {
+        self.symbols.get(text).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    // Forgets the symbols that only the table still holds, such as the names of unloaded classes.
+    pub fn purge(&mut self) {
+        self.symbols.retain(|symbol| Arc::strong_count(&symbol.0) > 1);
+    }
+}
+
+// Interned text. Symbols from the same table are equal only if they're the same symbol, so
+// comparing them compares pointers; they hash as their text does, so that maps keyed by symbols
+// can be looked up with a &str.
+#[derive(Clone)]
+pub struct Symbol(Arc<str>);
+
+impl Symbol {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl PartialEq<String> for Symbol {
+    fn eq(&self, other: &String) -> bool {
+        *self.0 == **other
+    }
+}
+
+impl PartialEq<Symbol> for str {
+    fn eq(&self, other: &Symbol) -> bool {
+        *self == *other.0
+    }
+}
+
+impl PartialEq<Symbol> for &str {
+    fn eq(&self, other: &Symbol) -> bool {
+        **self == *other.0
+    }
+}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl Borrow<str> for Symbol {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_interned_symbols_are_shared() {
+        let mut table = SymbolTable::new();
+        let first = table.intern("java/lang/Object");
+        let text = String::from("java/lang/Object");
+        let second = table.intern(&text);
+        assert!(Arc::ptr_eq(&first.0, &second.0));
+        assert_eq!(first, second);
+        assert!(first != table.intern("java/lang/String"));
+        assert_eq!(2, table.len());
+        assert_eq!(Some(first), table.get("java/lang/Object"));
+        assert_eq!(None, table.get("java/lang/Thread"));
+    }
+
+    #[test]
+    fn test_symbol_keys_are_found_by_text() {
+        let mut table = SymbolTable::new();
+        let mut slots = HashMap::new();
+        slots.insert(table.intern("count"), 3);
+        assert_eq!(Some(&3), slots.get("count"));
+        assert_eq!("count", table.intern("count").as_str());
+    }
+
+    #[test]
+    fn test_purge_forgets_unused_symbols() {
+        let mut table = SymbolTable::new();
+        let kept = table.intern("Kept");
+        table.intern("Dropped");
+        table.purge();
+        assert_eq!(Some(kept), table.get("Kept"));
+        assert_eq!(None, table.get("Dropped"));
+    }
+}
@@ -0,0 +1,28 @@
+// Helpers shared by the unit tests of several modules.
+use crate::vm::Vm;
+
+// The bytes of a class file under testdata, by its path without the extension.
+macro_rules! fixture {
+    ($name:literal) => {
+        include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/", $name, ".class"))
+    };
+}
+
+// A class file under testdata, parsed.
+macro_rules! parsed_fixture {
+    ($name:literal) => {
+        $crate::classloader::load_class($crate::test_support::fixture!($name)).unwrap()
+    };
+}
+
+pub(crate) use fixture;
+pub(crate) use parsed_fixture;
+
+// A VM with the given class files added to it.
+pub fn vm_with(classes: &[&[u8]]) -> Vm {
+    let mut vm = Vm::new();
+    for class in classes {
+        vm.add_class(class).unwrap();
+    }
+    vm
+}
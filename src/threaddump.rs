@@ -0,0 +1,97 @@
+use crate::gc::ByteSize;
+use crate::outcome::StackTraceElement;
+use crate::runtime::Value;
+use crate::threads::{ThreadId, ThreadSummary, MAIN_THREAD};
+use crate::vm::{self, ActiveFrame, Vm};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+// Every live thread with its Java stack, as HotSpot prints them on SIGQUIT and jstack does, so
+// that operators can read it as they would a JVM's:
+//
+//     Full thread dump joyvm 0.1.0 (Host threading):
+//
+//     "main" #1 prio=5
+//        java.lang.Thread.State: RUNNABLE
+//             at Main.work(Main.java:12)
+//             at Main.main(Main.java:5)
+//
+// Threads are listed in the order they started. Take it at a safepoint, where every thread's
+// frames are where they'll carry on from.
+pub fn thread_dump(vm: &Vm) -> String {
+    let stacks: HashMap<ThreadId, Vec<ActiveFrame>> = vm.thread_stacks().into_iter().collect();
+    let mut dump = String::new();
+    let _ = writeln!(dump, "Full thread dump joyvm {} ({:?} threading):", env!("CARGO_PKG_VERSION"), vm.threads().threading());
+    for thread in vm.threads().summaries() {
+        let daemon = if thread.daemon { " daemon" } else { "" };
+        let _ = writeln!(dump, "\n\"{}\" #{}{} prio={}", thread_name(vm, &thread), thread.id, daemon, thread.priority);
+        let _ = writeln!(dump, "   java.lang.Thread.State: {}", thread.state);
+        for frame in stacks.get(&thread.id).map_or(&[][..], Vec::as_slice) {
+            let _ = writeln!(dump, "\tat {}", StackTraceElement::from_frame(frame));
+        }
+    }
+    dump
+}
+
+// Main's Thread object is only created when it's first asked for.
+fn thread_name(vm: &Vm, thread: &ThreadSummary) -> String {
+    let name = match thread.object.as_ref().map(|object| vm::get_field(object, "name")) {
+        Some(Ok(Value::Reference(Some(name)))) => vm.read_string(&name).ok(),
+        _ => None,
+    };
+    name.unwrap_or_else(|| if thread.id == MAIN_THREAD { "main".to_string() } else { format!("Thread-{}", thread.id) })
+}
+
+// How full the heap is and how much the collector has done, which follows a thread dump.
+pub fn heap_summary(vm: &Vm) -> String {
+    let mut summary = String::new();
+    let (objects, bytes) = vm.heap_occupancy();
+    let _ = writeln!(summary, "Heap:\n  {} objects, {} live", objects, ByteSize(bytes));
+    if let Some(max_heap) = vm.max_heap() {
+        let _ = writeln!(summary, "  limit {}", ByteSize(max_heap));
+    }
+    let totals = vm.gc_totals();
+    let _ = writeln!(summary, "  {} collections, {} reclaimed", totals.collections, ByteSize(totals.reclaimed_bytes));
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use crate::gc::GcCause;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_thread_dump() {
+        let mut vm = vm_with(&[fixture!("Debuggee")]);
+        let dumps = Rc::new(RefCell::new(vec![]));
+        let taken = Rc::clone(&dumps);
+        vm.request_safepoint(Box::new(move |vm: &mut Vm| taken.borrow_mut().push(thread_dump(vm))));
+        vm.invoke_static("Debuggee", "sumOfSquares", "(I)I", vec![Value::Int(2)]).unwrap();
+
+        let dumps = dumps.borrow();
+        assert_eq!(1, dumps.len());
+        assert!(dumps[0].starts_with("Full thread dump joyvm "), "{}", dumps[0]);
+        assert!(dumps[0].ends_with(concat!(
+            "\n\n\"main\" #1 prio=5\n",
+            "   java.lang.Thread.State: RUNNABLE\n",
+            "\tat Debuggee.sumOfSquares(Debuggee.java:8)\n",
+        )), "{}", dumps[0]);
+
+        let idle = thread_dump(&vm);
+        assert!(idle.ends_with("\"main\" #1 prio=5\n   java.lang.Thread.State: RUNNABLE\n"), "{}", idle);
+    }
+
+    #[test]
+    fn test_heap_summary() {
+        let mut vm = Vm::new();
+        vm.set_max_heap(Some(64 * 1024 * 1024));
+        vm.collect_garbage(GcCause::Requested);
+        let summary = heap_summary(&vm);
+        assert!(summary.starts_with("Heap:\n  "), "{}", summary);
+        assert!(summary.contains("\n  limit 65536K\n"), "{}", summary);
+        assert!(summary.contains("\n  1 collections, "), "{}", summary);
+    }
+}
@@ -0,0 +1,1401 @@
+use crate::events::Event;
+use crate::heap::ObjectRef;
+use crate::interpreter;
+use crate::outcome::UncaughtThrowable;
+use crate::roots::{Root, RootVisitor};
+use crate::runtime::*;
+use crate::vm::{self, Nesting, ThreadContext, Vm, VmError};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self as host, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub type ThreadId = u64;
+
+// The thread the embedder runs Java code on, which is the one that runs main.
+pub const MAIN_THREAD: ThreadId = 1;
+
+// Thread.NORM_PRIORITY, which main has.
+const NORM_PRIORITY: i32 = 5;
+
+// How long a thread waits for its turn before asking the running thread to hand over.
+const SWITCH_INTERVAL: Duration = Duration::from_millis(5);
+
+// Each Java thread runs on a host thread of its own, with its own frames, but only one runs at a
+// time: a thread must hold the baton to touch the VM, so the heap and the rest can stay as they
+// are, with Rc rather than Arc. This is CPython's GIL, more or less. A thread that has waited
+// SWITCH_INTERVAL for the baton arms the safepoint poll, and the thread holding it hands it to
+// whichever has waited longest when it sees the poll. While a thread waits, its frames, local
+// handles and the like are kept here, and the VM holds those of the running thread.
+//
+// The embedder's thread holds the baton except while it's waiting for it, so started threads only
+// run while the embedder is running Java code. The embedder can move the Vm in between, but
+// threads that are waiting then can't carry on, since their frames refer to where it was. They're
+// abandoned, left waiting for the baton forever.
+//
+// Alternatively, with Threading::Cooperative, Java threads are green threads that all run on the
+// embedder's thread, for hosts without threads, like wasm, and for testing concurrent code
+// deterministically. Running threads switch every time the budget's slow path runs, which is
+// after a fixed number of instructions. The interpreter doesn't recurse for calls between Java
+// methods, so a thread that is only running Java code in its outermost call to the interpreter
+// can unwind back to the scheduler and leave its frames for later, as can one blocked in a native
+// called from there. That's how they usually yield and wait. Main, and threads running a static
+// initializer or a native, can't unwind, so they run the others on top of their own frames, which
+// holds up any threads beneath them until they're done. Sleeping goes by the VM's clock.
+pub struct Threads {
+    baton: Arc<Baton>,
+    threading: Threading,
+    current: ThreadId,
+    threads: BTreeMap<ThreadId, JavaThread>, // Those that have started and not finished, with main.
+    next_id: ThreadId,
+    hosts: Vec<JoinHandle<()>>,
+    home: *const Vm, // Where the Vm was when the waiting threads last ran.
+    initializers: HashMap<*const RuntimeClass, ThreadId>, // The thread running each class's static initializer.
+    monitors: HashMap<ObjectRef, Monitor>, // Those that are owned or waited on.
+    halt: Option<VmError>, // Why a thread other than main halted the VM, such as a call to System.exit, for main to return.
+    stopping: bool, // Every thread but main unwinds with VmError::ThreadStopped when it next runs.
+}
+
+// How Java threads run. See Vm::set_threading.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Threading {
+    #[default]
+    Host, // Each on a host thread of its own, taking turns with the baton.
+    Cooperative, // As green threads on the embedder's thread.
+}
+
+// A live thread as a thread dump describes it.
+pub struct ThreadSummary {
+    pub id: ThreadId,
+    pub object: Option<ObjectRef>, // The java.lang.Thread, which main may not have yet.
+    pub daemon: bool,
+    pub priority: i32,
+    pub state: ThreadState,
+}
+
+// As java.lang.Thread.State, for those states threads can be seen in here. Threads on host
+// threads that are waiting for the baton are runnable, whatever they're waiting for after that.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ThreadState {
+    New,
+    Runnable,
+    Waiting,
+    TimedWaiting,
+}
+
+impl fmt::Display for ThreadState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ThreadState::New => write!(f, "NEW"),
+            ThreadState::Runnable => write!(f, "RUNNABLE"),
+            ThreadState::Waiting => write!(f, "WAITING"),
+            ThreadState::TimedWaiting => write!(f, "TIMED_WAITING"),
+        }
+    }
+}
+
+struct JavaThread {
+    object: Option<ObjectRef>, // The java.lang.Thread, which for main is created when it's first asked for.
+    context: ThreadContext, // The thread's state while another runs.
+    green: Green,
+    permit: bool, // Made available by LockSupport.unpark and consumed by park.
+    timeslices: i32, // How many the thread has had in its current turn, when it's a green thread.
+}
+
+// Where a thread is with the cooperative scheduler. Threads on host threads are always OnStack.
+enum Green {
+    Unstarted,
+    Unwound(Waiter),
+    OnStack, // Running, or beneath the thread that is on the embedder's stack.
+}
+
+// What a green thread that has unwound is waiting for before it can carry on.
+struct Waiter {
+    deadline: Option<i64>, // By the VM's clock's nano_time.
+    ready: Box<dyn FnMut(&Vm) -> bool>,
+    // Run on the thread as it resumes, for the result of the native it was blocked in. None if
+    // it unwound before an instruction, which runs again.
+    finish: Option<Finish>,
+}
+
+type Finish = Box<dyn FnOnce(&mut Vm) -> Result<Option<Value>, VmError>>;
+
+// An object's monitor, per JVM spec 17.1: the thread that owns it and how many times over, with
+// its entry set of threads waiting to enter it and its wait set of threads in Object.wait that
+// haven't been notified yet. Only the thread holding the baton touches a monitor, so entering
+// one that's free is a matter of writing down the owner. Monitors are kept only while they're
+// owned or waited on.
+#[derive(Default)]
+struct Monitor {
+    owner: Option<ThreadId>,
+    count: u32,
+    entering: Vec<ThreadId>,
+    waiting: Vec<ThreadId>,
+}
+
+impl Monitor {
+    fn is_unused(&self) -> bool {
+        self.owner.is_none() && self.entering.is_empty() && self.waiting.is_empty()
+    }
+}
+
+impl JavaThread {
+    fn is_daemon(&self) -> bool {
+        self.object.as_ref().is_some_and(|object| matches!(vm::get_field(object, "daemon"), Ok(Value::Int(1))))
+    }
+
+    fn priority(&self) -> i32 {
+        match self.object.as_ref().map(|object| vm::get_field(object, "priority")) {
+            Some(Ok(Value::Int(priority))) => priority,
+            _ => NORM_PRIORITY,
+        }
+    }
+}
+
+impl Threads {
+    pub fn new(poll: Arc<AtomicBool>) -> Threads {
+        let mut threads = BTreeMap::new();
+        threads.insert(MAIN_THREAD, JavaThread {object: None, context: ThreadContext::default(), green: Green::OnStack, permit: false, timeslices: 0});
+        Threads {
+            baton: Arc::new(Baton::new(MAIN_THREAD, poll)),
+            threading: Threading::Host,
+            current: MAIN_THREAD,
+            threads,
+            next_id: MAIN_THREAD + 1,
+            hosts: vec![],
+            home: std::ptr::null(),
+            initializers: HashMap::new(),
+            monitors: HashMap::new(),
+            halt: None,
+            stopping: false,
+        }
+    }
+
+    pub fn current(&self) -> ThreadId {
+        self.current
+    }
+
+    pub fn threading(&self) -> Threading {
+        self.threading
+    }
+
+    // Vm::set_threading also sets up the timeslice.
+    pub fn set_threading(&mut self, threading: Threading) {
+        self.threading = threading;
+    }
+
+    // The number of threads that have started and not yet finished, counting main.
+    pub fn count(&self) -> usize {
+        self.threads.len()
+    }
+
+    pub fn is_alive(&self, object: &ObjectRef) -> bool {
+        self.threads.values().any(|thread| thread.object.as_ref() == Some(object))
+    }
+
+    // The Thread objects of the live threads, the references held by those that are waiting, and
+    // the objects whose monitors are in use.
+    pub fn visit_roots(&self, visitor: &mut dyn RootVisitor) {
+        for (&id, thread) in &self.threads {
+            if let Some(ref object) = thread.object {
+                visitor.visit_root(Root::Thread(id), object);
+            }
+            thread.context.visit_roots(visitor);
+        }
+        for object in self.monitors.keys() {
+            visitor.visit_root(Root::Monitor, object);
+        }
+    }
+
+    // The saved state of each waiting thread. The running thread's is in the VM.
+    pub fn waiting_contexts(&self) -> impl Iterator<Item = &ThreadContext> {
+        self.threads.values().map(|thread| &thread.context)
+    }
+
+    // Likewise, along with the threads they belong to.
+    pub fn contexts(&self) -> impl Iterator<Item = (ThreadId, &ThreadContext)> {
+        self.threads.iter().map(|(&id, thread)| (id, &thread.context))
+    }
+
+    // Whether the thread could enter the object's monitor now.
+    fn can_enter(&self, object: &ObjectRef, id: ThreadId) -> bool {
+        self.monitors.get(object).is_none_or(|monitor| monitor.owner.is_none_or(|owner| owner == id))
+    }
+
+    // Enters the object's monitor the given number of times, if the thread can, returning whether
+    // it did.
+    fn try_enter(&mut self, object: &ObjectRef, id: ThreadId, count: u32) -> bool {
+        if !self.can_enter(object, id) {
+            return false;
+        }
+        let monitor = self.monitors.entry(object.clone()).or_default();
+        monitor.owner = Some(id);
+        monitor.count += count;
+        monitor.entering.retain(|&entering| entering != id);
+        true
+    }
+
+    fn forget_if_unused(&mut self, object: &ObjectRef) {
+        if self.monitors.get(object).is_some_and(Monitor::is_unused) {
+            self.monitors.remove(object);
+        }
+    }
+
+    // Forgets a thread that has finished, exiting the monitors it still owns, as a thread that's
+    // stopped does without running its finally blocks.
+    fn remove(&mut self, id: ThreadId) {
+        self.threads.remove(&id);
+        self.monitors.retain(|_, monitor| {
+            if monitor.owner == Some(id) {
+                monitor.owner = None;
+                monitor.count = 0;
+            }
+            monitor.entering.retain(|&entering| entering != id);
+            monitor.waiting.retain(|&waiting| waiting != id);
+            !monitor.is_unused()
+        });
+    }
+
+    // Each live thread, in the order they started.
+    pub fn summaries(&self) -> Vec<ThreadSummary> {
+        self.threads.iter().map(|(&id, thread)| {
+            let state = match thread.green {
+                Green::Unstarted => ThreadState::New,
+                Green::Unwound(Waiter {deadline: Some(_), ..}) => ThreadState::TimedWaiting,
+                Green::Unwound(Waiter {deadline: None, ..}) => ThreadState::Waiting,
+                Green::OnStack => ThreadState::Runnable,
+            };
+            ThreadSummary {id, object: thread.object.clone(), daemon: thread.is_daemon(), priority: thread.priority(), state}
+        }).collect()
+    }
+}
+
+pub fn invoke_native(vm: &mut Vm, class: &str, name: &str, descriptor: &str, args: &[Value]) -> Result<Option<Value>, VmError> {
+    match (class, name, descriptor, args) {
+        ("java/lang/Thread", "currentThread", "()Ljava/lang/Thread;", &[]) => Ok(Some(Value::Reference(Some(current_thread(vm)?)))),
+        ("java/lang/Thread", "yield", "()V", &[]) if can_unwind(vm, 1) => {
+            Err(unwind(vm, Waiter {deadline: None, ready: Box::new(|_| true), finish: Some(Box::new(|_| Ok(None)))}))
+        },
+        ("java/lang/Thread", "yield", "()V", &[]) => yield_now(vm).map(|()| None),
+        ("java/lang/Thread", "start0", "()V", &[Value::Reference(Some(ref thread))]) => start(vm, thread).map(|()| None),
+        ("java/lang/Thread", "isAlive", "()Z", &[Value::Reference(Some(ref thread))]) => Ok(Some(Value::Int(vm.threads().is_alive(thread) as i32))),
+        ("java/lang/Thread", "sleep0", "(J)V", &[Value::Long(millis)]) => {
+            let current = current_thread(vm)?;
+            let timeout = Duration::from_millis(millis as u64);
+            wait_interruptibly(vm, &current, Some(timeout), "sleep interrupted", |_| false)
+        },
+        ("java/lang/Thread", "join0", "(J)V", &[Value::Reference(Some(ref thread)), Value::Long(millis)]) => {
+            let current = current_thread(vm)?;
+            let timeout = if millis == 0 { None } else { Some(Duration::from_millis(millis as u64)) };
+            let thread = thread.clone();
+            wait_interruptibly(vm, &current, timeout, "join interrupted", move |vm| !vm.threads().is_alive(&thread))
+        },
+        ("java/lang/Object", "wait", "(J)V", &[Value::Reference(Some(ref object)), Value::Long(millis)]) => wait_on_monitor(vm, object, millis),
+        ("java/lang/Object", "notify", "()V", &[Value::Reference(Some(ref object))]) => notify_monitor(vm, object, false),
+        ("java/lang/Object", "notifyAll", "()V", &[Value::Reference(Some(ref object))]) => notify_monitor(vm, object, true),
+        ("sun/misc/Unsafe", "park", "(ZJ)V", &[_, Value::Int(absolute), Value::Long(time)]) => wait_for_permit(vm, absolute != 0, time),
+        ("sun/misc/Unsafe", "unpark", "(Ljava/lang/Object;)V", &[_, Value::Reference(ref thread)]) => {
+            let threads = vm.threads_mut();
+            if let Some(thread) = threads.threads.values_mut().find(|live| live.object.is_some() && live.object == *thread) {
+                thread.permit = true;
+            }
+            Ok(None)
+        },
+        ("java/util/concurrent/locks/LockSupport", "getBlocker", "(Ljava/lang/Thread;)Ljava/lang/Object;", &[Value::Reference(ref thread)]) => {
+            let thread = thread.as_ref().ok_or_else(|| vm.throw_new("java/lang/NullPointerException"))?;
+            vm::get_field(thread, "parkBlocker").map(Some)
+        },
+        ("java/util/concurrent/locks/LockSupport", "setBlocker", "(Ljava/lang/Thread;Ljava/lang/Object;)V", &[Value::Reference(Some(ref thread)), ref blocker]) => {
+            vm::set_field(thread, "parkBlocker", blocker.clone()).map(|()| None)
+        },
+        _ => Err(VmError::Unsupported(format!("native method {}.{}{}", class, name, descriptor))),
+    }
+}
+
+// Waits for the current thread's permit, as Unsafe.park does.
+fn wait_for_permit(vm: &mut Vm, absolute: bool, time: i64) -> Result<Option<Value>, VmError> {
+    let timeout = if absolute {
+        let now = vm.clock_mut().current_time_millis();
+        if time <= now {
+            return Ok(None);
+        }
+        Some(Duration::from_millis((time - now) as u64))
+    } else if time < 0 {
+        return Ok(None);
+    } else if time == 0 {
+        None
+    } else {
+        Some(Duration::from_nanos(time as u64))
+    };
+    let thread = current_thread(vm)?;
+    let id = vm.threads().current;
+    if take_permit(vm, id) || is_interrupted(&thread) {
+        return Ok(None);
+    }
+    let ready = move |vm: &Vm| is_interrupted(&thread) || vm.threads().threads.get(&id).is_some_and(|thread| thread.permit);
+    block(vm, timeout, ready, move |vm| {
+        take_permit(vm, id);
+        Ok(None)
+    })
+}
+
+fn take_permit(vm: &mut Vm, id: ThreadId) -> bool {
+    vm.threads_mut().threads.get_mut(&id).is_some_and(|thread| std::mem::take(&mut thread.permit))
+}
+
+// Starts a host thread that runs the Thread object's run method once it gets the baton, or with
+// the cooperative scheduler, leaves it for the scheduler to start.
+pub fn start(vm: &mut Vm, thread: &ObjectRef) -> Result<(), VmError> {
+    abandon_if_moved(vm);
+    // The host thread gets the Java thread's name, unless it has a nul in it, which the host won't take.
+    let name = match vm::get_field(thread, "name")? {
+        Value::Reference(Some(name)) => Some(vm.read_string(&name)?).filter(|name| !name.contains('\0')),
+        _ => None,
+    };
+    let threads = vm.threads_mut();
+    let id = threads.next_id;
+    threads.next_id += 1;
+    if threads.threading == Threading::Cooperative {
+        threads.threads.insert(id, JavaThread {object: Some(thread.clone()), context: ThreadContext::default(), green: Green::Unstarted, permit: false, timeslices: 0});
+        return Ok(());
+    }
+    threads.threads.insert(id, JavaThread {object: Some(thread.clone()), context: ThreadContext::default(), green: Green::OnStack, permit: false, timeslices: 0});
+    threads.hosts.retain(|host| !host.is_finished());
+
+    let baton = Arc::clone(&threads.baton);
+    let mut builder = host::Builder::new();
+    if let Some(name) = name {
+        builder = builder.name(name);
+    }
+    match builder.spawn(move || run_host_thread(&baton, id)) {
+        Ok(host) => {
+            vm.threads_mut().hosts.push(host);
+            Ok(())
+        },
+        Err(err) => {
+            vm.threads_mut().threads.remove(&id);
+            Err(vm.throw_new_with_message("java/lang/OutOfMemoryError", &format!("unable to create native thread: {}", err)))
+        },
+    }
+}
+
+fn run_host_thread(baton: &Baton, id: ThreadId) {
+    // Safety: holding the baton gives this thread sole use of the VM, which can't be dropped
+    // until every thread has finished: dropping it stops them and waits.
+    let vm = unsafe { &mut *baton.acquire(id) };
+    resume(vm, id);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| run_thread(vm, id)));
+    park(vm);
+    vm.threads_mut().remove(id);
+    baton.release(vm);
+    if let Err(panic) = result {
+        panic::resume_unwind(panic);
+    }
+}
+
+fn run_thread(vm: &mut Vm, id: ThreadId) {
+    if let Some(thread) = thread_object(vm, id) {
+        vm.post_event(&Event::ThreadStart{thread: id});
+        let result = check_stopped(vm).and_then(|()| invoke_run(vm, &thread));
+        finish_thread(vm, &thread, result);
+        vm.post_event(&Event::ThreadEnd{thread: id});
+    }
+}
+
+fn thread_object(vm: &Vm, id: ThreadId) -> Option<ObjectRef> {
+    vm.threads().threads.get(&id).and_then(|thread| thread.object.clone())
+}
+
+// Deals with how the thread's run method ended.
+fn finish_thread(vm: &mut Vm, thread: &ObjectRef, result: Result<(), VmError>) {
+    let result = match result {
+        Err(VmError::UncaughtException(throwable)) => handle_uncaught(vm, thread, &throwable),
+        result => result,
+    };
+    match result {
+        Ok(()) | Err(VmError::ThreadStopped) => (),
+        Err(err) => halt(vm, err),
+    }
+}
+
+// Calls the thread's run method on the current thread.
+pub fn invoke_run(vm: &mut Vm, thread: &ObjectRef) -> Result<(), VmError> {
+    let (class, run) = resolve_method(thread.class(), "run", "()V")?.ok_or_else(|| VmError::MethodNotFound {
+        class: thread.class().name.to_string(),
+        name: "run".to_string(),
+        descriptor: "()V".to_string(),
+    })?;
+    interpreter::invoke(vm, &class, run, vec![Value::Reference(Some(thread.clone()))]).map(|_| ())
+}
+
+// Passes an exception that escaped the thread's run method to its UncaughtExceptionHandler, or
+// the default one, and then to the embedder's handler. If there's none of them, prints it.
+pub fn handle_uncaught(vm: &mut Vm, thread: &ObjectRef, throwable: &ObjectRef) -> Result<(), VmError> {
+    let dispatched = dispatch_uncaught(vm, thread, throwable)?;
+    if notify_embedder(vm, thread, throwable)? || dispatched {
+        return Ok(());
+    }
+    report_uncaught(vm, thread, throwable)
+}
+
+// Tells the handler set with Vm::set_uncaught_handler about an exception that escaped the
+// thread, returning whether there was one.
+pub fn notify_embedder(vm: &mut Vm, thread: &ObjectRef, throwable: &ObjectRef) -> Result<bool, VmError> {
+    if !vm.has_uncaught_handler() {
+        return Ok(false);
+    }
+    let name = thread_name(vm, thread)?;
+    let report = UncaughtThrowable::from_object(vm, throwable)?;
+    Ok(vm.notify_uncaught(&name, &report))
+}
+
+fn thread_name(vm: &Vm, thread: &ObjectRef) -> Result<String, VmError> {
+    match vm::get_field(thread, "name")? {
+        Value::Reference(Some(name)) => vm.read_string(&name),
+        _ => Ok(String::new()),
+    }
+}
+
+// Passes the exception to the thread's UncaughtExceptionHandler or the default one, returning
+// whether there was one. Exceptions the handler throws are ignored, as the JVM ignores them.
+pub fn dispatch_uncaught(vm: &mut Vm, thread: &ObjectRef, throwable: &ObjectRef) -> Result<bool, VmError> {
+    let descriptor = "(Ljava/lang/Throwable;)Z";
+    let (class, dispatch) = resolve_method(thread.class(), "dispatchUncaughtException", descriptor)?.ok_or_else(|| VmError::MethodNotFound {
+        class: thread.class().name.to_string(),
+        name: "dispatchUncaughtException".to_string(),
+        descriptor: descriptor.to_string(),
+    })?;
+    let args = vec![Value::Reference(Some(thread.clone())), Value::Reference(Some(throwable.clone()))];
+    match interpreter::invoke(vm, &class, dispatch, args) {
+        Ok(Some(Value::Int(handled))) => Ok(handled != 0),
+        Ok(_) | Err(VmError::UncaughtException(_)) => Ok(true),
+        Err(err) => Err(err),
+    }
+}
+
+// Prints an exception that escaped the thread's run method on stderr, as the JDK's default
+// handler does.
+pub fn report_uncaught(vm: &mut Vm, thread: &ObjectRef, throwable: &ObjectRef) -> Result<(), VmError> {
+    let name = thread_name(vm, thread)?;
+    let report = UncaughtThrowable::from_object(vm, throwable)?;
+    // Nothing can be done about a failure to report it.
+    let _ = writeln!(vm.console_mut().stderr, "Exception in thread \"{}\" {}", name, report);
+    Ok(())
+}
+
+// Records that a thread has halted the VM, by calling System.exit, exceeding a resource limit
+// or failing in a way Java code couldn't catch. Main returns the error the next time it waits,
+// and the other threads stop.
+fn halt(vm: &mut Vm, err: VmError) {
+    let threads = vm.threads_mut();
+    threads.stopping = true;
+    if threads.halt.is_none() {
+        threads.halt = Some(err);
+    }
+}
+
+fn check_stopped(vm: &mut Vm) -> Result<(), VmError> {
+    let threads = vm.threads_mut();
+    if threads.current == MAIN_THREAD {
+        threads.halt.take().map_or(Ok(()), Err)
+    } else if threads.stopping {
+        Err(VmError::ThreadStopped)
+    } else {
+        Ok(())
+    }
+}
+
+// The Thread object of the running thread.
+pub fn current_thread(vm: &mut Vm) -> Result<ObjectRef, VmError> {
+    let id = vm.threads().current;
+    if let Some(thread) = vm.threads().threads.get(&id).and_then(|thread| thread.object.clone()) {
+        return Ok(thread);
+    }
+
+    let class = vm.load_class("java/lang/Thread")?;
+    vm.initialize(&class)?;
+    let thread = vm.new_object(&class);
+    let init = class.find_declared_method("<init>", "(Ljava/lang/String;)V")?.ok_or_else(|| VmError::MethodNotFound {
+        class: class.name.to_string(),
+        name: "<init>".to_string(),
+        descriptor: "(Ljava/lang/String;)V".to_string(),
+    })?;
+    // The constructor asks for the current thread, to inherit whether it's a daemon.
+    if let Some(main) = vm.threads_mut().threads.get_mut(&id) {
+        main.object = Some(thread.clone());
+    }
+    let name = vm.new_string("main")?;
+    interpreter::invoke(vm, &class, init, vec![Value::Reference(Some(thread.clone())), Value::Reference(Some(name))])?;
+    vm::set_field(&thread, "started", Value::Int(1))?;
+    Ok(thread)
+}
+
+// Called at each safepoint, which is where the running thread hands over. A green thread's turn
+// lasts as many timeslices as its priority.
+pub fn tick(vm: &mut Vm) -> Result<(), VmError> {
+    let threads = vm.threads_mut();
+    let current = threads.current;
+    if let (Threading::Cooperative, Some(thread)) = (threads.threading, threads.threads.get_mut(&current)) {
+        thread.timeslices += 1;
+        if thread.timeslices < thread.priority() {
+            return check_stopped(vm);
+        }
+        thread.timeslices = 0;
+    }
+    yield_now(vm)
+}
+
+// Hands the baton to the thread that has waited longest for it, if any, and waits for it back.
+// The running thread does this when it sees the safepoint poll, and on Thread.yield. Green threads
+// unwind if they can, and otherwise give the others a turn each.
+pub fn yield_now(vm: &mut Vm) -> Result<(), VmError> {
+    if vm.threads().threading == Threading::Cooperative {
+        check_stopped(vm)?;
+        if can_unwind(vm, 0) {
+            return Err(unwind(vm, Waiter {deadline: None, ready: Box::new(|_| true), finish: None}));
+        }
+        run_round(vm)?;
+        return check_stopped(vm);
+    }
+    let baton = Arc::clone(&vm.threads().baton);
+    if baton.has_waiters() {
+        let id = park(vm);
+        baton.release(vm);
+        baton.acquire(id);
+        resume(vm, id);
+    }
+    check_stopped(vm)
+}
+
+// Lets the other threads run until the condition holds, for a thread that's waiting for them, or
+// until the timeout passes if there is one. Returns whether the condition held.
+pub fn wait_until(vm: &mut Vm, timeout: Option<Duration>, done: impl FnMut(&Vm) -> bool) -> Result<bool, VmError> {
+    if vm.threads().threading == Threading::Cooperative {
+        return wait_cooperatively(vm, timeout, done);
+    }
+    let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+    wait_for_baton(vm, deadline, done)
+}
+
+fn wait_for_baton(vm: &mut Vm, deadline: Option<Instant>, mut done: impl FnMut(&Vm) -> bool) -> Result<bool, VmError> {
+    loop {
+        if done(vm) {
+            return Ok(true);
+        }
+        let timeout = match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) if !remaining.is_zero() => remaining.min(SWITCH_INTERVAL),
+                _ => return Ok(false),
+            },
+            None => SWITCH_INTERVAL,
+        };
+        let baton = Arc::clone(&vm.threads().baton);
+        let id = park(vm);
+        baton.release_and_wait(vm, id, timeout);
+        resume(vm, id);
+        check_stopped(vm)?;
+    }
+}
+
+// Waits as wait_until does, except that if the thread is interrupted first, or has been already,
+// its interrupt status is cleared and it throws InterruptedException. For the natives that block.
+fn wait_interruptibly(vm: &mut Vm, thread: &ObjectRef, timeout: Option<Duration>, message: &'static str, mut done: impl FnMut(&Vm) -> bool + 'static) -> Result<Option<Value>, VmError> {
+    let (waiting, finishing) = (thread.clone(), thread.clone());
+    let ready = move |vm: &Vm| is_interrupted(&waiting) || done(vm);
+    block(vm, timeout, ready, move |vm| check_interrupted(vm, &finishing, message).map(|()| None))
+}
+
+// Blocks the current thread in a native until it's ready or the timeout passes, and then
+// finishes the native. A green thread unwinds to do this if it can.
+fn block(vm: &mut Vm, timeout: Option<Duration>, mut ready: impl FnMut(&Vm) -> bool + 'static, finish: impl FnOnce(&mut Vm) -> Result<Option<Value>, VmError> + 'static) -> Result<Option<Value>, VmError> {
+    if can_unwind(vm, 1) {
+        let deadline = timeout.map(|timeout| deadline_after(vm, timeout));
+        return Err(unwind(vm, Waiter {deadline, ready: Box::new(ready), finish: Some(Box::new(finish))}));
+    }
+    wait_until(vm, timeout, &mut ready)?;
+    finish(vm)
+}
+
+fn check_interrupted(vm: &mut Vm, thread: &ObjectRef, message: &str) -> Result<(), VmError> {
+    if is_interrupted(thread) {
+        vm::set_field(thread, "interrupted", Value::Int(0))?;
+        return Err(vm.throw_new_with_message("java/lang/InterruptedException", message));
+    }
+    Ok(())
+}
+
+fn is_interrupted(thread: &ObjectRef) -> bool {
+    matches!(vm::get_field(thread, "interrupted"), Ok(Value::Int(1)))
+}
+
+// Waits until every other thread has finished, except for daemons, as the JVM does before it
+// shuts down.
+pub fn wait_for_non_daemons(vm: &mut Vm) -> Result<(), VmError> {
+    let done = wait_until(vm, None, |vm| {
+        let threads = vm.threads();
+        threads.threads.iter().all(|(&id, thread)| id == threads.current || thread.is_daemon())
+    });
+    done.map(|_| ())
+}
+
+// Stops every thread but main, for when the VM halts or is dropped. Each unwinds when it next
+// runs, whether it's running, sleeping or waiting for another thread, and this waits until they
+// have. Their finally blocks don't run, and nothing is reported.
+pub fn stop_others(vm: &mut Vm) {
+    if vm.threads().count() > 1 {
+        vm.threads_mut().stopping = true;
+        while vm.threads().count() > 1 && vm.threads().threading == Threading::Cooperative {
+            // Only threads nested beneath this one can be left, which won't happen on main.
+            if !matches!(run_round(vm), Ok(true)) {
+                break;
+            }
+        }
+        while vm.threads().count() > 1 && vm.threads().threading == Threading::Host {
+            let baton = Arc::clone(&vm.threads().baton);
+            let id = park(vm);
+            baton.release_and_wait(vm, id, SWITCH_INTERVAL);
+            resume(vm, id);
+        }
+    }
+    for host in vm.threads_mut().hosts.drain(..) {
+        // A thread that panicked has already reported it.
+        let _ = host.join();
+    }
+}
+
+// Runs the class's static initializer on the current thread, after which it's marked
+// initialized or erroneous. Meanwhile other threads that need the class wait, as the JVM spec
+// requires, while the initializer itself and anything it calls carry on.
+pub fn run_initializer(vm: &mut Vm, class: &Rc<RuntimeClass>, initializer: impl FnOnce(&mut Vm) -> Result<(), VmError>) -> Result<(), VmError> {
+    let current = vm.threads().current;
+    vm.threads_mut().initializers.insert(Rc::as_ptr(class), current);
+    let result = initializer(vm);
+    vm.threads_mut().initializers.remove(&Rc::as_ptr(class));
+    result
+}
+
+// Waits for another thread to finish initializing the class, if one is.
+pub fn wait_for_initializer(vm: &mut Vm, class: &Rc<RuntimeClass>) -> Result<(), VmError> {
+    let key = Rc::as_ptr(class);
+    let done = wait_until(vm, None, |vm| {
+        let threads = vm.threads();
+        threads.initializers.get(&key).is_none_or(|&thread| thread == threads.current)
+    });
+    done.map(|_| ())
+}
+
+// Enters the object's monitor on the current thread, for monitorenter and synchronized methods,
+// per JVM spec 6.5.monitorenter. If another thread owns it, this one joins its entry set and
+// waits until it's exited.
+pub fn enter_monitor(vm: &mut Vm, object: &ObjectRef) -> Result<(), VmError> {
+    let id = vm.threads().current;
+    if vm.threads_mut().try_enter(object, id, 1) {
+        return Ok(());
+    }
+    vm.threads_mut().monitors.entry(object.clone()).or_default().entering.push(id);
+    let contended = object.clone();
+    let entered = wait_until(vm, None, move |vm| vm.threads().can_enter(&contended, id));
+    let threads = vm.threads_mut();
+    if entered.is_ok() {
+        threads.try_enter(object, id, 1);
+    }
+    if let Some(monitor) = threads.monitors.get_mut(object) {
+        monitor.entering.retain(|&entering| entering != id);
+    }
+    threads.forget_if_unused(object);
+    entered.map(|_| ())
+}
+
+// Unwinds the running green thread, if it can, to run the monitorenter instruction again once
+// the thread that owns the monitor has exited it. It stays in the entry set meanwhile.
+pub fn suspend_for_monitor(vm: &mut Vm, object: &ObjectRef) -> Result<(), VmError> {
+    let id = vm.threads().current;
+    if vm.threads().can_enter(object, id) || !can_unwind(vm, 0) {
+        return Ok(());
+    }
+    let entering = &mut vm.threads_mut().monitors.entry(object.clone()).or_default().entering;
+    if !entering.contains(&id) {
+        entering.push(id);
+    }
+    let contended = object.clone();
+    let ready = move |vm: &Vm| vm.threads().can_enter(&contended, id);
+    Err(unwind(vm, Waiter {deadline: None, ready: Box::new(ready), finish: None}))
+}
+
+// Exits the object's monitor on the current thread, for monitorexit and returning from
+// synchronized methods. Throws IllegalMonitorStateException if the thread doesn't own it.
+pub fn exit_monitor(vm: &mut Vm, object: &ObjectRef) -> Result<(), VmError> {
+    let threads = vm.threads_mut();
+    let id = threads.current;
+    match threads.monitors.get_mut(object) {
+        Some(monitor) if monitor.owner == Some(id) => {
+            monitor.count -= 1;
+            if monitor.count == 0 {
+                monitor.owner = None;
+            }
+            threads.forget_if_unused(object);
+            Ok(())
+        },
+        _ => Err(vm.throw_new("java/lang/IllegalMonitorStateException")),
+    }
+}
+
+// Object.wait: exits the monitor, however many times the thread entered it, and waits in its
+// wait set to be notified, interrupted or timed out, and then enters it again as many times.
+fn wait_on_monitor(vm: &mut Vm, object: &ObjectRef, millis: i64) -> Result<Option<Value>, VmError> {
+    if millis < 0 {
+        return Err(vm.throw_new_with_message("java/lang/IllegalArgumentException", "timeout value is negative"));
+    }
+    let id = vm.threads().current;
+    if vm.threads().monitors.get(object).is_none_or(|monitor| monitor.owner != Some(id)) {
+        return Err(vm.throw_new("java/lang/IllegalMonitorStateException"));
+    }
+    let thread = current_thread(vm)?;
+    check_interrupted(vm, &thread, "wait interrupted")?;
+    let monitor = vm.threads_mut().monitors.get_mut(object).expect("The thread owns the monitor");
+    let count = std::mem::take(&mut monitor.count);
+    monitor.owner = None;
+    monitor.waiting.push(id);
+
+    let timeout = if millis == 0 { None } else { Some(Duration::from_millis(millis as u64)) };
+    let (waiting, finishing, waited_on) = (thread.clone(), thread, object.clone());
+    let ready = move |vm: &Vm| {
+        let notified = vm.threads().monitors.get(&waited_on).is_none_or(|monitor| !monitor.waiting.contains(&id));
+        (notified || is_interrupted(&waiting)) && vm.threads().can_enter(&waited_on, id)
+    };
+    let object = object.clone();
+    block(vm, timeout, ready, move |vm| {
+        // Timing out leaves the thread to take its turn entering the monitor again.
+        if let Some(monitor) = vm.threads_mut().monitors.get_mut(&object) {
+            monitor.waiting.retain(|&waiting| waiting != id);
+        }
+        let reentered = object.clone();
+        wait_until(vm, None, move |vm| vm.threads().can_enter(&reentered, id))?;
+        vm.threads_mut().try_enter(&object, id, count);
+        check_interrupted(vm, &finishing, "wait interrupted").map(|()| None)
+    })
+}
+
+// Object.notify and notifyAll: moves the longest waiting thread, or all of them, out of the wait
+// set, to enter the monitor again once the current thread has exited it.
+fn notify_monitor(vm: &mut Vm, object: &ObjectRef, all: bool) -> Result<Option<Value>, VmError> {
+    let threads = vm.threads_mut();
+    let id = threads.current;
+    match threads.monitors.get_mut(object) {
+        Some(monitor) if monitor.owner == Some(id) => {
+            let notified = if all { monitor.waiting.len() } else { monitor.waiting.len().min(1) };
+            monitor.waiting.drain(..notified);
+            Ok(None)
+        },
+        _ => Err(vm.throw_new("java/lang/IllegalMonitorStateException")),
+    }
+}
+
+// Whether the running thread is a green thread that can unwind to the scheduler: it must be in its
+// outermost call to the interpreter, and in either no native, to unwind before an instruction,
+// or a native called from there, to unwind from that.
+fn can_unwind(vm: &Vm, natives: usize) -> bool {
+    let threads = vm.threads();
+    threads.threading == Threading::Cooperative && threads.current != MAIN_THREAD && vm.nesting() == Nesting {interpreters: 1, natives}
+}
+
+// Leaves the running green thread to resume once the waiter is ready, returning the error that
+// unwinds it.
+fn unwind(vm: &mut Vm, waiter: Waiter) -> VmError {
+    let threads = vm.threads_mut();
+    let current = threads.current;
+    if let Some(thread) = threads.threads.get_mut(&current) {
+        thread.green = Green::Unwound(waiter);
+    }
+    VmError::Suspended
+}
+
+// Unwinds the running green thread, if it can, to run the instruction that needs the class again
+// once another thread has finished initializing it.
+pub fn suspend_for_initializer(vm: &mut Vm, class: &Rc<RuntimeClass>) -> Result<(), VmError> {
+    let key = Rc::as_ptr(class);
+    let threads = vm.threads();
+    let initializing = threads.initializers.get(&key).is_some_and(|&thread| thread != threads.current);
+    if initializing && can_unwind(vm, 0) {
+        let ready = move |vm: &Vm| !vm.threads().initializers.contains_key(&key);
+        return Err(unwind(vm, Waiter {deadline: None, ready: Box::new(ready), finish: None}));
+    }
+    Ok(())
+}
+
+// How a green thread gets its turn.
+enum Turn {
+    Start,
+    Resume(Option<Finish>),
+    Stop,
+}
+
+// Gives each green thread that's ready a turn, in the order they were started, until it next
+// unwinds or finishes. Returns whether any had one.
+fn run_round(vm: &mut Vm) -> Result<bool, VmError> {
+    let ids: Vec<ThreadId> = vm.threads().threads.keys().copied().collect();
+    let mut ran = false;
+    for id in ids {
+        if let Some(turn) = take_turn(vm, id) {
+            run_turn(vm, id, turn);
+            ran = true;
+        }
+    }
+    Ok(ran)
+}
+
+fn take_turn(vm: &mut Vm, id: ThreadId) -> Option<Turn> {
+    let now = vm.clock_mut().nano_time();
+    let stopping = vm.threads().stopping;
+    let thread = vm.threads_mut().threads.get_mut(&id)?;
+    let mut waiter = match std::mem::replace(&mut thread.green, Green::OnStack) {
+        Green::Unstarted => return Some(Turn::Start),
+        Green::Unwound(waiter) => waiter,
+        Green::OnStack => return None,
+    };
+    if stopping {
+        return Some(Turn::Stop);
+    }
+    if waiter.deadline.is_some_and(|deadline| now >= deadline) || (waiter.ready)(vm) {
+        return Some(Turn::Resume(waiter.finish));
+    }
+    if let Some(thread) = vm.threads_mut().threads.get_mut(&id) {
+        thread.green = Green::Unwound(waiter);
+    }
+    None
+}
+
+// Switches to the green thread until it unwinds or finishes, and then back.
+fn run_turn(vm: &mut Vm, id: ThreadId, turn: Turn) {
+    let scheduler = park(vm);
+    resume(vm, id);
+    let thread = thread_object(vm, id);
+    let result = match (turn, thread) {
+        (Turn::Start, Some(ref thread)) => {
+            vm.post_event(&Event::ThreadStart{thread: id});
+            check_stopped(vm).and_then(|()| invoke_run(vm, thread))
+        },
+        (Turn::Resume(finish), _) => {
+            let incoming = finish.map(|finish| finish(vm));
+            interpreter::resume(vm, incoming).map(|_| ())
+        },
+        (Turn::Stop, _) => interpreter::resume(vm, Some(Err(VmError::ThreadStopped))).map(|_| ()),
+        (Turn::Start, None) => Ok(()),
+    };
+    let finished = !matches!(result, Err(VmError::Suspended));
+    if let (true, Some(thread)) = (finished, thread_object(vm, id)) {
+        finish_thread(vm, &thread, result);
+        vm.post_event(&Event::ThreadEnd{thread: id});
+    }
+    park(vm);
+    if finished {
+        vm.threads_mut().remove(id);
+    }
+    resume(vm, scheduler);
+}
+
+// Waits by running the other green threads until the condition holds or the timeout passes.
+fn wait_cooperatively(vm: &mut Vm, timeout: Option<Duration>, mut done: impl FnMut(&Vm) -> bool) -> Result<bool, VmError> {
+    let deadline = timeout.map(|timeout| deadline_after(vm, timeout));
+    loop {
+        if done(vm) {
+            return Ok(true);
+        }
+        if deadline.is_some_and(|deadline| vm.clock_mut().nano_time() >= deadline) {
+            return Ok(false);
+        }
+        let ran = run_round(vm)?;
+        check_stopped(vm)?;
+        if !ran {
+            // Nothing can change until time passes, if anything is waiting for it to.
+            let waiting = vm.threads().threads.values().filter_map(|thread| match thread.green {
+                Green::Unwound(ref waiter) => waiter.deadline,
+                _ => None,
+            });
+            let next = match waiting.chain(deadline).min() {
+                Some(next) => next,
+                None => return Err(VmError::Deadlock),
+            };
+            let now = vm.clock_mut().nano_time();
+            vm.clock_mut().sleep(Duration::from_nanos(next.saturating_sub(now).max(0) as u64));
+        }
+    }
+}
+
+// The time by the VM's clock after the timeout, which may as well be forever if it's too long to
+// represent.
+fn deadline_after(vm: &mut Vm, timeout: Duration) -> i64 {
+    let timeout = timeout.as_nanos().min(i64::MAX as u128) as i64;
+    vm.clock_mut().nano_time().saturating_add(timeout)
+}
+
+// Moves the running thread's state out of the VM before it gives up the baton.
+fn park(vm: &mut Vm) -> ThreadId {
+    abandon_if_moved(vm);
+    let id = vm.threads().current;
+    let mut context = ThreadContext::default();
+    vm.swap_thread_context(&mut context);
+    if let Some(thread) = vm.threads_mut().threads.get_mut(&id) {
+        thread.context = context;
+    }
+    id
+}
+
+fn abandon_if_moved(vm: &mut Vm) {
+    let here: *const Vm = vm;
+    let threads = vm.threads_mut();
+    // Green threads that have unwound have nothing on the host stack to refer to the VM.
+    if threads.home == here || threads.threading == Threading::Cooperative {
+        return;
+    }
+    threads.home = here;
+    let abandoned: HashSet<ThreadId> = threads.threads.keys().copied().filter(|&id| id != MAIN_THREAD).collect();
+    if !abandoned.is_empty() {
+        threads.baton.abandon(&abandoned);
+        threads.threads.retain(|id, _| !abandoned.contains(id));
+        threads.hosts.clear();
+    }
+}
+
+// Moves a thread's state into the VM once it has the baton.
+fn resume(vm: &mut Vm, id: ThreadId) {
+    let threads = vm.threads_mut();
+    threads.current = id;
+    let mut context = threads.threads.get_mut(&id).map(|thread| std::mem::take(&mut thread.context)).unwrap_or_default();
+    vm.swap_thread_context(&mut context);
+}
+
+// Passed from thread to thread along with the baton. Besides saving the waiting threads from
+// holding pointers of their own, this tells the compiler that the VM may have changed by the time
+// a thread gets the baton back.
+struct VmPtr(*mut Vm);
+
+// Safety: the pointer is only followed by the thread holding the baton.
+unsafe impl Send for VmPtr {}
+
+struct Baton {
+    state: Mutex<BatonState>,
+    turn: Condvar,
+    poll: Arc<AtomicBool>, // The safepoint poll, armed to ask the holder to hand over.
+}
+
+struct BatonState {
+    holder: Option<ThreadId>,
+    queue: VecDeque<ThreadId>,
+    handovers: u64, // Counts releases, so that a waiting thread can tell that another has run.
+    abandoned: HashSet<ThreadId>, // Never get the baton.
+    vm: VmPtr,
+}
+
+impl Baton {
+    fn new(holder: ThreadId, poll: Arc<AtomicBool>) -> Baton {
+        let state = BatonState {
+            holder: Some(holder),
+            queue: VecDeque::new(),
+            handovers: 0,
+            abandoned: HashSet::new(),
+            vm: VmPtr(std::ptr::null_mut()),
+        };
+        Baton {state: Mutex::new(state), turn: Condvar::new(), poll}
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BatonState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Blocks until it's the thread's turn, first come first served, and returns the VM.
+    fn acquire(&self, id: ThreadId) -> *mut Vm {
+        let mut state = self.lock();
+        let abandoned = state.abandoned.contains(&id);
+        if state.holder.is_none() && state.queue.is_empty() && !abandoned {
+            state.holder = Some(id);
+            return state.vm.0;
+        }
+        if !abandoned {
+            state.queue.push_back(id);
+        }
+        while state.holder != Some(id) {
+            let (next, wait) = self.turn.wait_timeout(state, SWITCH_INTERVAL).unwrap_or_else(PoisonError::into_inner);
+            state = next;
+            if wait.timed_out() && state.holder != Some(id) && !state.abandoned.contains(&id) {
+                self.poll.store(true, Ordering::Relaxed);
+            }
+        }
+        state.vm.0
+    }
+
+    // Hands the baton to the thread that has waited longest, if any.
+    fn release(&self, vm: &mut Vm) {
+        let mut state = self.lock();
+        self.hand_over(&mut state, vm);
+    }
+
+    // Hands the baton over and waits until another thread has had it, or the timeout passes,
+    // before waiting for it again.
+    fn release_and_wait(&self, vm: &mut Vm, id: ThreadId, timeout: Duration) {
+        let mut state = self.lock();
+        self.hand_over(&mut state, vm);
+        let handovers = state.handovers;
+        let (state, _) = self.turn.wait_timeout_while(state, timeout, |state| state.handovers == handovers).unwrap_or_else(PoisonError::into_inner);
+        drop(state);
+        self.acquire(id);
+    }
+
+    fn hand_over(&self, state: &mut BatonState, vm: &mut Vm) {
+        state.vm = VmPtr(vm);
+        state.holder = state.queue.pop_front();
+        state.handovers += 1;
+        self.turn.notify_all();
+    }
+
+    fn has_waiters(&self) -> bool {
+        !self.lock().queue.is_empty()
+    }
+
+    fn abandon(&self, threads: &HashSet<ThreadId>) {
+        let mut state = self.lock();
+        state.queue.retain(|id| !threads.contains(id));
+        state.abandoned.extend(threads);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use crate::clock::{Clock, ManualClock};
+    use crate::console::SharedBuffer;
+    use crate::events::EventKinds;
+    use crate::outcome::Outcome;
+    use std::cell::RefCell;
+
+    const THREADS: &[&[u8]] = &[
+        fixture!("Threads"),
+        fixture!("Threads$Counter"),
+        fixture!("Threads$Identity"),
+        fixture!("Threads$Task"),
+        fixture!("Threads$SlowInit"),
+        fixture!("Threads$Handler"),
+        fixture!("Threads$Adder"),
+        fixture!("Threads$Mailbox"),
+        fixture!("Threads$Consumer"),
+        fixture!("Threads$Locker"),
+    ];
+
+    fn run(how: &str) -> (Outcome, String, Vm) {
+        run_with(Threading::Host, how)
+    }
+
+    fn run_with(threading: Threading, how: &str) -> (Outcome, String, Vm) {
+        let mut vm = vm_with(THREADS);
+        let stdout = SharedBuffer::new();
+        vm.set_stdout(Box::new(stdout.clone()));
+        vm.set_threading(threading);
+        let stderr = SharedBuffer::new();
+        vm.set_stderr(Box::new(stderr.clone()));
+        let outcome = vm.run_main("Threads", &[how]).unwrap();
+        assert_eq!("", stderr.text());
+        (outcome, stdout.text(), vm)
+    }
+
+    #[test]
+    fn test_threads_interleave() {
+        let (outcome, stdout, vm) = run("interleave");
+        assert_eq!(Outcome::Completed, outcome);
+        // Each counter prints its name when it sees the other has started, so both must have
+        // run while the other was part way through.
+        let mut lines: Vec<&str> = stdout.lines().collect();
+        lines.sort_unstable();
+        assert_eq!(vec!["first saw second", "second saw first"], lines);
+        assert_eq!(1, vm.threads().count());
+    }
+
+    #[test]
+    fn test_current_thread_and_is_alive() {
+        let (outcome, stdout, _) = run("identity");
+        assert_eq!(Outcome::Completed, outcome);
+        assert_eq!("main true\nfalse\nworker true\ntrue\nfalse\nIllegalThreadStateException\n", stdout);
+    }
+
+    #[test]
+    fn test_main_waits_for_threads_before_shutdown() {
+        let (outcome, stdout, _) = run("outlive");
+        assert_eq!(Outcome::Completed, outcome);
+        assert_eq!("main done\nslow done\nhook\n", stdout);
+    }
+
+    #[test]
+    fn test_daemon_threads_are_stopped_at_shutdown() {
+        let (outcome, stdout, vm) = run("daemon");
+        assert_eq!(Outcome::Completed, outcome);
+        assert_eq!("false\ntrue\nIllegalThreadStateException\nmain done\nslow done\nhook\n", stdout);
+        assert_eq!(1, vm.threads().count());
+    }
+
+    #[test]
+    fn test_uncaught_exception_ends_only_its_thread() {
+        let mut vm = vm_with(THREADS);
+        let stdout = SharedBuffer::new();
+        vm.set_stdout(Box::new(stdout.clone()));
+        let stderr = SharedBuffer::new();
+        vm.set_stderr(Box::new(stderr.clone()));
+        assert_eq!(Outcome::Completed, vm.run_main("Threads", &["fail"]).unwrap());
+        assert!(stderr.text().starts_with("Exception in thread \"failing\" java.lang.IllegalStateException: failed\n"));
+        assert_eq!("after\n", stdout.text());
+    }
+
+    #[test]
+    fn test_uncaught_handler_replaces_printing() {
+        let mut vm = vm_with(THREADS);
+        vm.set_stdout(Box::new(SharedBuffer::new()));
+        let stderr = SharedBuffer::new();
+        vm.set_stderr(Box::new(stderr.clone()));
+        let seen = Rc::new(RefCell::new(vec![]));
+        let recorded = Rc::clone(&seen);
+        vm.set_uncaught_handler(Some(Box::new(move |thread: &str, throwable: &UncaughtThrowable| {
+            let top = throwable.stack_trace.first().map(|element| (element.method_name.clone(), element.line_number));
+            recorded.borrow_mut().push((thread.to_string(), throwable.class_name.clone(), throwable.message.clone(), top));
+        })));
+        assert_eq!(Outcome::Completed, vm.run_main("Threads", &["fail"]).unwrap());
+        assert_eq!("", stderr.text());
+        let seen = seen.borrow();
+        assert_eq!(1, seen.len());
+        let (ref thread, ref class_name, ref message, ref top) = seen[0];
+        assert_eq!(("failing", "java.lang.IllegalStateException", Some("failed")), (thread.as_str(), class_name.as_str(), message.as_deref()));
+        assert_eq!(Some(("run".to_string(), Some(89))), *top);
+    }
+
+    #[test]
+    fn test_exit_from_another_thread_halts_the_vm() {
+        let (outcome, stdout, vm) = run("exit");
+        assert_eq!(Outcome::Exited(3), outcome);
+        assert_eq!("", stdout);
+        assert_eq!(1, vm.threads().count());
+    }
+
+    #[test]
+    fn test_threads_wait_for_class_initialization() {
+        let (outcome, stdout, _) = run("init");
+        assert_eq!(Outcome::Completed, outcome);
+        assert_eq!("42\n42\n", stdout);
+    }
+
+    #[test]
+    fn test_sleep() {
+        let (outcome, stdout, _) = run("sleep");
+        assert_eq!(Outcome::Completed, outcome);
+        assert_eq!("true\ntimeout value is negative\n", stdout);
+    }
+
+    #[test]
+    fn test_interrupt_wakes_a_sleeping_thread() {
+        let started = Instant::now();
+        let (outcome, stdout, _) = run("interrupt");
+        assert_eq!(Outcome::Completed, outcome);
+        assert_eq!("sleep interrupted\nfalse\njoined\n", stdout);
+        assert!(started.elapsed() < Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_interrupt_status() {
+        let (outcome, stdout, _) = run("status");
+        assert_eq!(Outcome::Completed, outcome);
+        assert_eq!("true\ntrue\nfalse\nsleep interrupted\nfalse\njoin interrupted\nslow done\nfalse\n", stdout);
+    }
+
+    #[test]
+    fn test_join_with_timeout() {
+        let (outcome, stdout, _) = run("timeout");
+        assert_eq!(Outcome::Completed, outcome);
+        assert_eq!("true\nslow done\nfalse\n", stdout);
+    }
+
+    #[test]
+    fn test_dropping_the_vm_stops_threads() {
+        let mut vm = vm_with(THREADS);
+        vm.set_stdout(Box::new(SharedBuffer::new()));
+        vm.invoke_static("Threads", "startSpinning", "()V", vec![]).unwrap();
+        assert_eq!(2, vm.threads().count());
+    }
+
+    #[test]
+    fn test_moving_the_vm_abandons_waiting_threads() {
+        let mut vm = vm_with(THREADS);
+        vm.set_stdout(Box::new(SharedBuffer::new()));
+        vm.invoke_static("Threads", "startSpinning", "()V", vec![]).unwrap();
+        let mut moved = Box::new(vm);
+        moved.invoke_static("Threads", "startSpinning", "()V", vec![]).unwrap();
+        assert_eq!(2, moved.threads().count());
+    }
+
+    #[test]
+    fn test_park_and_unpark() {
+        let expected = "took permit\ntimed out\ndeadline passed\ntrue\nblocker\nunparked\ntrue\n";
+        for threading in [Threading::Host, Threading::Cooperative] {
+            let (outcome, stdout, _) = run_with(threading, "park");
+            assert_eq!((Outcome::Completed, expected), (outcome, stdout.as_str()), "{:?}", threading);
+        }
+    }
+
+    #[test]
+    fn test_synchronized_threads_exclude_each_other() {
+        let expected = "100\n100\n15\ntimed out\nIllegalMonitorStateException\n";
+        for threading in [Threading::Host, Threading::Cooperative] {
+            let (outcome, stdout, vm) = run_with(threading, "synchronize");
+            assert_eq!((Outcome::Completed, expected), (outcome, stdout.as_str()), "{:?}", threading);
+            assert!(vm.threads().monitors.is_empty(), "{:?}", threading);
+        }
+    }
+
+    #[test]
+    fn test_reentrant_lock_excludes_other_threads() {
+        let expected = "100\n2\nfalse\nfalse\nIllegalMonitorStateException\n";
+        for threading in [Threading::Host, Threading::Cooperative] {
+            let (outcome, stdout, _) = run_with(threading, "lock");
+            assert_eq!((Outcome::Completed, expected), (outcome, stdout.as_str()), "{:?}", threading);
+        }
+    }
+
+    #[test]
+    fn test_uncaught_exception_handlers() {
+        for threading in [Threading::Host, Threading::Cooperative] {
+            let mut vm = vm_with(THREADS);
+            let stdout = SharedBuffer::new();
+            vm.set_stdout(Box::new(stdout.clone()));
+            vm.set_threading(threading);
+            let stderr = SharedBuffer::new();
+            vm.set_stderr(Box::new(stderr.clone()));
+            match vm.run_main("Threads", &["handler"]).unwrap() {
+                Outcome::Threw(thrown) => assert_eq!(Some("main failed".to_string()), thrown.message),
+                other => panic!("Expected main to throw; got {:?}", other),
+            }
+            assert_eq!("own caught failed from own\ndefault caught failed from other\ndefault caught main failed from main\n", stdout.text());
+            assert_eq!("", stderr.text());
+        }
+    }
+
+    #[test]
+    fn test_priorities() {
+        for threading in [Threading::Host, Threading::Cooperative] {
+            let (outcome, stdout, _) = run_with(threading, "priority");
+            assert_eq!(Outcome::Completed, outcome);
+            let lines: Vec<&str> = stdout.lines().collect();
+            assert_eq!(vec!["5", "10", "IllegalArgumentException"], lines[..3]);
+            if threading == Threading::Cooperative {
+                // The high priority thread gets ten timeslices to each one of the low's.
+                let log = lines[3];
+                assert!(log.starts_with("aaaaa") && log.ends_with("bbbbb"), "{}", log);
+            }
+        }
+    }
+
+    #[test]
+    fn test_host_threads_take_java_names() {
+        let mut vm = vm_with(THREADS);
+        vm.set_stdout(Box::new(SharedBuffer::new()));
+        let host = Rc::new(std::cell::RefCell::new(None));
+        let recorded = Rc::clone(&host);
+        vm.register_native("Threads", "recordHost", "()V", move |_: &mut crate::env::Env| {
+            *recorded.borrow_mut() = host::current().name().map(str::to_string);
+        });
+        assert_eq!(Outcome::Completed, vm.run_main("Threads", &["named"]).unwrap());
+        assert_eq!(Some("worker-7".to_string()), host.borrow().clone());
+    }
+
+    #[test]
+    fn test_cooperative_threads_behave_as_host_threads_do() {
+        for (how, expected) in [
+            ("interleave", "second saw first\nfirst saw second\n"),
+            ("identity", "main true\nfalse\nworker true\ntrue\nfalse\nIllegalThreadStateException\n"),
+            ("outlive", "main done\nslow done\nhook\n"),
+            ("daemon", "false\ntrue\nIllegalThreadStateException\nmain done\nslow done\nhook\n"),
+            ("init", "42\n42\n"),
+            ("interrupt", "sleep interrupted\nfalse\njoined\n"),
+            ("status", "true\ntrue\nfalse\nsleep interrupted\nfalse\njoin interrupted\nslow done\nfalse\n"),
+            ("timeout", "true\nslow done\nfalse\n"),
+        ] {
+            let (outcome, stdout, vm) = run_with(Threading::Cooperative, how);
+            assert_eq!((Outcome::Completed, expected), (outcome, stdout.as_str()), "{}", how);
+            assert_eq!(1, vm.threads().count());
+        }
+        let (outcome, _, vm) = run_with(Threading::Cooperative, "exit");
+        assert_eq!(Outcome::Exited(3), outcome);
+        assert_eq!(1, vm.threads().count());
+    }
+
+    #[test]
+    fn test_thread_events() {
+        for threading in [Threading::Host, Threading::Cooperative] {
+            let mut vm = vm_with(THREADS);
+            vm.set_stdout(Box::new(SharedBuffer::new()));
+            vm.set_threading(threading);
+            let events = Rc::new(std::cell::RefCell::new(vec![]));
+            let recorded = Rc::clone(&events);
+            vm.subscribe(EventKinds::THREAD_START | EventKinds::THREAD_END, Box::new(move |event: &Event| {
+                match *event {
+                    Event::ThreadStart{thread} => recorded.borrow_mut().push((thread, true)),
+                    Event::ThreadEnd{thread} => recorded.borrow_mut().push((thread, false)),
+                    _ => unreachable!(),
+                }
+            }));
+            assert_eq!(Outcome::Completed, vm.run_main("Threads", &["interleave"]).unwrap());
+            // Both counters start before either ends, since each waits to see the other.
+            let events = events.borrow();
+            assert_eq!(vec![true, true, false, false], events.iter().map(|&(_, started)| started).collect::<Vec<_>>());
+            assert_ne!(events[0].0, events[1].0);
+            assert!(events[2..].iter().all(|&(thread, _)| thread == events[0].0 || thread == events[1].0));
+        }
+    }
+
+    #[test]
+    fn test_cooperative_scheduling_is_deterministic() {
+        let (outcome, first, _) = run_with(Threading::Cooperative, "race");
+        assert_eq!(Outcome::Completed, outcome);
+        // The threads switch part way through, at the same points every time.
+        assert!(first.contains("ab") && first.contains("ba"), "{}", first);
+        for _ in 0..3 {
+            assert_eq!(first, run_with(Threading::Cooperative, "race").1);
+        }
+    }
+
+    #[test]
+    fn test_cooperative_sleep_follows_the_vm_clock() {
+        let mut vm = vm_with(THREADS);
+        let stdout = SharedBuffer::new();
+        vm.set_stdout(Box::new(stdout.clone()));
+        vm.set_threading(Threading::Cooperative);
+        let clock = ManualClock::new(0);
+        vm.set_clock(Box::new(clock.clone()));
+        let started = Instant::now();
+        assert_eq!(Outcome::Completed, vm.run_main("Threads", &["sleep"]).unwrap());
+        assert_eq!("true\ntimeout value is negative\n", stdout.text());
+        assert_eq!(20, clock.clone().current_time_millis());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_cooperative_deadlock_is_reported() {
+        let mut vm = vm_with(THREADS);
+        vm.set_stdout(Box::new(SharedBuffer::new()));
+        vm.set_threading(Threading::Cooperative);
+        assert_eq!(Err(VmError::Deadlock), vm.run_main("Threads", &["deadlock"]));
+        assert_eq!(1, vm.threads().count());
+    }
+
+    #[test]
+    fn test_green_threads_survive_moving_the_vm() {
+        let mut vm = vm_with(THREADS);
+        vm.set_stdout(Box::new(SharedBuffer::new()));
+        vm.set_threading(Threading::Cooperative);
+        vm.invoke_static("Threads", "startSpinning", "()V", vec![]).unwrap();
+        let mut moved = Box::new(vm);
+        assert_eq!(2, moved.threads().count());
+        moved.invoke_static("Threads", "startSpinning", "()V", vec![]).unwrap();
+        assert_eq!(3, moved.threads().count());
+    }
+}
@@ -0,0 +1,237 @@
+use crate::classes::Method;
+use crate::heap::ObjectRef;
+use crate::hooks::ExecutionHooks;
+use crate::opcodes;
+use crate::runtime::*;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// How many entries from the top of the operand stack each line shows.
+const STACK_ENTRIES_SHOWN: usize = 8;
+
+// Writes a line for each instruction the interpreter executes, for debugging the VM itself:
+//
+//     Arithmetic.divide(II)I 2: idiv [7, 2]
+//
+// That's the method, the pc, the instruction and the operand stack as the instruction finds it,
+// with the top on the right. A line is also written wherever an exception is thrown. Install it
+// with Vm::set_hooks; the output is flushed when it's uninstalled and dropped.
+pub struct Tracer {
+    output: Box<dyn Write>,
+    filter: TraceFilter,
+    last_method: Option<(*const Method, bool)>, // Whether the filter matched the method last traced.
+    failed: bool, // Tracing stops at the first write that fails.
+}
+
+impl Tracer {
+    pub fn new(output: Box<dyn Write>, filter: TraceFilter) -> Tracer {
+        Tracer {output, filter, last_method: None, failed: false}
+    }
+
+    pub fn to_file(path: &Path, filter: TraceFilter) -> io::Result<Tracer> {
+        Ok(Tracer::new(Box::new(BufWriter::new(File::create(path)?)), filter))
+    }
+
+    pub fn to_stderr(filter: TraceFilter) -> Tracer {
+        Tracer::new(Box::new(BufWriter::new(io::stderr())), filter)
+    }
+
+    fn traces(&mut self, class: &RuntimeClass, method: &Method) -> bool {
+        if self.failed {
+            return false;
+        }
+        // Instructions mostly follow others in the same method, so one cached match saves
+        // matching the patterns again for each.
+        match self.last_method {
+            Some((last, traced)) if std::ptr::eq(last, method) => traced,
+            _ => {
+                let traced = self.filter.matches(&class.name, class.class.utf8(&method.name).unwrap_or(""));
+                self.last_method = Some((method, traced));
+                traced
+            },
+        }
+    }
+
+    fn write(&mut self, class: &RuntimeClass, method: &Method, pc: usize, event: &str) {
+        let name = class.class.utf8(&method.name).unwrap_or("<unknown>");
+        let descriptor = class.class.utf8(&method.descriptor).unwrap_or("");
+        if let Err(err) = writeln!(self.output, "{}.{}{} {}: {}", class.name, name, descriptor, pc, event) {
+            eprintln!("Stopped tracing: {}", err);
+            self.failed = true;
+        }
+    }
+}
+
+impl ExecutionHooks for Tracer {
+    fn on_instruction(&mut self, class: &RuntimeClass, method: &Method, pc: usize, opcode: u8, stack: &[Value]) {
+        if self.traces(class, method) {
+            let mnemonic = opcodes::mnemonic(opcode).unwrap_or("<reserved>");
+            self.write(class, method, pc, &format!("{} {}", mnemonic, summarize(stack)));
+        }
+    }
+
+    fn on_exception_thrown(&mut self, class: &RuntimeClass, method: &Method, pc: usize, exception: &ObjectRef) {
+        if self.traces(class, method) {
+            self.write(class, method, pc, &format!("threw {}", exception.class().name));
+        }
+    }
+}
+
+// The top of the stack, with objects given by their classes rather than their contents.
+fn summarize(stack: &[Value]) -> String {
+    let shown = &stack[stack.len().saturating_sub(STACK_ENTRIES_SHOWN)..];
+    let mut entries: Vec<String> = shown.iter().map(|value| match *value {
+        Value::Int(value) => value.to_string(),
+        Value::Long(value) => format!("{}L", value),
+        Value::Float(value) => format!("{:?}f", value),
+        Value::Double(value) => format!("{:?}d", value),
+        Value::Reference(None) => "null".to_string(),
+        Value::Reference(Some(ref object)) => object.class().name.to_string(),
+        Value::ReturnAddress(pc) => format!("returnAddress {}", pc),
+    }).collect();
+    if shown.len() < stack.len() {
+        entries.insert(0, "...".to_string());
+    }
+    format!("[{}]", entries.join(", "))
+}
+
+// Which methods to trace, as patterns like com.example.* or com.example.Main::run. A pattern
+// without :: covers every method of the classes it matches. Class names may use dots or slashes,
+// and * matches any run of characters, including dots. No patterns at all traces everything.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TraceFilter {
+    patterns: Vec<(String, Option<String>)>, // Class names in internal form, and method names.
+}
+
+impl TraceFilter {
+    pub fn new() -> TraceFilter {
+        TraceFilter::default()
+    }
+
+    pub fn pattern(mut self, pattern: &str) -> TraceFilter {
+        let (class, method) = match pattern.find("::") {
+            Some(separator) => (&pattern[..separator], Some(pattern[separator + 2..].to_string())),
+            None => (pattern, None),
+        };
+        self.patterns.push((class.replace('.', "/"), method));
+        self
+    }
+
+    pub fn matches(&self, class: &str, method: &str) -> bool {
+        self.patterns.is_empty() || self.patterns.iter().any(|(class_pattern, method_pattern)| {
+            glob_matches(class_pattern.as_bytes(), class.as_bytes())
+                && method_pattern.as_ref().is_none_or(|pattern| glob_matches(pattern.as_bytes(), method.as_bytes()))
+        })
+    }
+}
+
+// Matches text against a pattern in which * stands for any run of characters. Each * only ever
+// needs to grow from where it last matched, so this takes time proportional to the product of
+// their lengths at worst.
+fn glob_matches(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None; // The last * seen, and where its match ends.
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            star = Some((star_p, star_t + 1));
+            p = star_p + 1;
+            t = star_t + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Output that can still be read once the tracer owning it has been installed.
+    #[derive(Clone, Default)]
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(bytes)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn trace(filter: TraceFilter, method: &str, args: Vec<Value>) -> Vec<String> {
+        let mut vm = vm_with(&[fixture!("Arithmetic")]);
+        let output = SharedOutput::default();
+        vm.set_hooks(Box::new(Tracer::new(Box::new(output.clone()), filter)));
+        let _ = vm.invoke_static("Arithmetic", method, "(II)I", args);
+        let text = String::from_utf8(output.0.borrow().clone()).unwrap();
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_trace_instructions() {
+        assert_eq!(vec![
+            "Arithmetic.divide(II)I 0: iload_0 []",
+            "Arithmetic.divide(II)I 1: iload_1 [7]",
+            "Arithmetic.divide(II)I 2: idiv [7, 2]",
+            "Arithmetic.divide(II)I 3: ireturn [3]",
+        ], trace(TraceFilter::new(), "divide", vec![Value::Int(7), Value::Int(2)]));
+    }
+
+    #[test]
+    fn test_trace_exceptions() {
+        // The exception's constructor runs before it's thrown, and is traced too.
+        let lines = trace(TraceFilter::new(), "divide", vec![Value::Int(7), Value::Int(0)]);
+        assert!(lines.iter().any(|line| line.starts_with("java/lang/ArithmeticException.<init>")));
+        assert_eq!(Some("Arithmetic.divide(II)I 2: threw java/lang/ArithmeticException"), lines.last().map(String::as_str));
+    }
+
+    #[test]
+    fn test_trace_filtered_out() {
+        let args = vec![Value::Int(7), Value::Int(2)];
+        assert!(trace(TraceFilter::new().pattern("Arithmetic::add*"), "divide", args.clone()).is_empty());
+        assert!(trace(TraceFilter::new().pattern("java.lang.*"), "divide", args.clone()).is_empty());
+        assert_eq!(4, trace(TraceFilter::new().pattern("java.lang.*").pattern("Arith*::div*"), "divide", args).len());
+    }
+
+    #[test]
+    fn test_filter_patterns() {
+        let filter = TraceFilter::new().pattern("com.example.*::run").pattern("*.Util");
+        assert!(filter.matches("com/example/Main", "run"));
+        assert!(filter.matches("com/example/deep/Main", "run"));
+        assert!(!filter.matches("com/example/Main", "runAll"));
+        assert!(filter.matches("org/other/Util", "anything"));
+        assert!(!filter.matches("org/other/Utils", "anything"));
+        assert!(TraceFilter::new().matches("Anything", "<init>"));
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches(b"", b""));
+        assert!(glob_matches(b"*", b""));
+        assert!(glob_matches(b"a*b*c", b"aXbYbZc"));
+        assert!(glob_matches(b"*ab", b"aab"));
+        assert!(!glob_matches(b"a*b", b"aXc"));
+        assert!(!glob_matches(b"abc", b"ab"));
+    }
+
+    #[test]
+    fn test_summarize_stack() {
+        assert_eq!("[1L, 1.5f, 2.0d, null, returnAddress 4]",
+            summarize(&[Value::Long(1), Value::Float(1.5), Value::Double(2.0), Value::null(), Value::ReturnAddress(4)]));
+        let deep: Vec<Value> = (0..10).map(Value::Int).collect();
+        assert_eq!("[..., 2, 3, 4, 5, 6, 7, 8, 9]", summarize(&deep));
+    }
+}
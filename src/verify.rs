@@ -0,0 +1,574 @@
+use crate::bytecode::{self, BytecodeError};
+use crate::classes::*;
+use crate::classloader::{self, ClassLoaderError};
+use crate::classpath::ClasspathError;
+use crate::descriptor::{DescriptorError, FieldType, MethodDescriptor};
+use crate::inference::{self, ClassInfo, InferenceError};
+use crate::jar::{self, EntryError, JarSource};
+use crate::loaders::LoaderId;
+use crate::runtime::RuntimeClass;
+use crate::zip::ZipError;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::{error, fmt};
+
+// Which classes are verified, as set by the JVM's -Xverify option. Verification catches malformed
+// classes before they run, but takes time, so like the JVM, the default is to trust the classes
+// of the bootstrap loader and verify the rest. Unverified classes that are malformed may fail in
+// unpredictable ways.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VerifyMode {
+    All,
+    #[default]
+    Remote, // Classes defined by any loader but the bootstrap loader.
+    None,
+}
+
+impl VerifyMode {
+    // Parses the value of -Xverify.
+    pub fn parse(value: &str) -> Option<VerifyMode> {
+        match value {
+            "all" => Some(VerifyMode::All),
+            "remote" => Some(VerifyMode::Remote),
+            "none" => Some(VerifyMode::None),
+            _ => None,
+        }
+    }
+
+    pub fn verifies(self, loader: LoaderId) -> bool {
+        match self {
+            VerifyMode::All => true,
+            VerifyMode::Remote => loader != LoaderId::BOOTSTRAP,
+            VerifyMode::None => false,
+        }
+    }
+}
+
+// Checks that a parsed class is well formed beyond what parsing ensures, as the first two passes
+// of the JVM's verifier do (JVM spec 4.8): that the constant pool entries refer to entries of the
+// right types, that this_class and super_class name classes, that no field or method is declared
+// twice, and that each method that needs code has code of a legal length. Checks that need the
+// class's supertypes are in check_overrides.
+//
+// Like the JVM, this leaves a method's instructions alone until it's first run, since decoding
+// every method of every class that's loaded takes far longer than the rest of these checks, and
+// most methods never run; check_instructions checks them then.
+pub fn check_structure(class: &Class) -> Result<(), StructureError> {
+    check_constants(class)?;
+
+    let name = class.name()?;
+    match class.super_class_name()? {
+        None if name != "java/lang/Object" => return Err(StructureError::MissingSuperClass),
+        Some(super_name) if class.flags.contains(ClassFlags::INTERFACE) && super_name != "java/lang/Object" => {
+            return Err(StructureError::InterfaceSuperClass(super_name.to_string()));
+        },
+        _ => (),
+    }
+    for interface in &class.interfaces {
+        class.class_name(interface)?;
+    }
+
+    let mut fields = HashSet::new();
+    for field in &class.fields {
+        let (name, descriptor) = (class.utf8(&field.name)?, class.utf8(&field.descriptor)?);
+        FieldType::parse(descriptor)?;
+        if !fields.insert((name, descriptor)) {
+            return Err(StructureError::DuplicateField{name: name.to_string(), descriptor: descriptor.to_string()});
+        }
+    }
+
+    let mut methods = HashSet::new();
+    for method in &class.methods {
+        let (name, descriptor) = (class.utf8(&method.name)?, class.utf8(&method.descriptor)?);
+        MethodDescriptor::parse(descriptor)?;
+        if !methods.insert((name, descriptor)) {
+            return Err(StructureError::DuplicateMethod{name: name.to_string(), descriptor: descriptor.to_string()});
+        }
+        check_code(method, &format!("{}{}", name, descriptor))?;
+    }
+    Ok(())
+}
+
+// Abstract and native methods have no code; every other method has exactly one Code attribute.
+fn check_code(method: &Method, signature: &str) -> Result<(), StructureError> {
+    let code_count = method.attributes.iter().filter(|attribute| matches!(**attribute, Attribute::Code(_))).count();
+    let needs_code = !method.flags.intersects(MethodFlags::ABSTRACT | MethodFlags::NATIVE);
+    match (needs_code, code_count) {
+        (true, 1) => (),
+        (true, _) => return Err(StructureError::MissingCode(signature.to_string())),
+        (false, 0) => return Ok(()),
+        (false, _) => return Err(StructureError::UnexpectedCode(signature.to_string())),
+    }
+
+    let code = method.code().expect("Methods with a Code attribute have code");
+    if code.code.is_empty() || code.code.len() > u16::MAX as usize {
+        return Err(StructureError::CodeLength{method: signature.to_string(), length: code.code.len()});
+    }
+    Ok(())
+}
+
+// Checks that a method's code is a sequence of whole instructions with exception handlers inside
+// it. Methods without code have nothing to check.
+pub fn check_instructions(class: &Class, method: &Method) -> Result<(), StructureError> {
+    let code = match method.code() {
+        Some(code) => code,
+        None => return Ok(()),
+    };
+    let signature = || format!("{}{}", class.utf8(&method.name).unwrap_or("<unknown>"), class.utf8(&method.descriptor).unwrap_or("<unknown>"));
+    let instructions = bytecode::decode(code.code).map_err(|cause| StructureError::Bytecode{method: signature(), cause})?;
+    let starts: HashSet<usize> = instructions.iter().map(|instruction| instruction.pc).collect();
+    for row in code.exception_table {
+        let (start, end, handler) = (row.start_pc as usize, row.end_pc as usize, row.handler_pc as usize);
+        let valid = start < end
+            && starts.contains(&start)
+            && (end == code.code.len() || starts.contains(&end))
+            && starts.contains(&handler);
+        if !valid {
+            return Err(StructureError::ExceptionRange{method: signature(), start_pc: row.start_pc, end_pc: row.end_pc, handler_pc: row.handler_pc});
+        }
+    }
+    Ok(())
+}
+
+// Each constant that refers to others must refer to constants of the types JVM spec 4.4 says.
+fn check_constants(class: &Class) -> Result<(), StructureError> {
+    let bootstrap_methods = class.attributes.iter().find_map(|attribute| match *attribute {
+        Attribute::BootstrapMethods{ref methods, ..} => Some(methods.len()),
+        _ => None,
+    }).unwrap_or(0);
+
+    for constant in &class.constants {
+        match *constant {
+            Constant::ClassRef(ref name) | Constant::StringRef(ref name) | Constant::MethodType(ref name) |
+            Constant::Module(ref name) | Constant::Package(ref name) => {
+                class.utf8(name)?;
+            },
+            Constant::FieldRef{class: ref owner, ref name_and_type} |
+            Constant::MethodRef{class: ref owner, ref name_and_type} |
+            Constant::InterfaceMethodRef{class: ref owner, ref name_and_type} => {
+                class.class_name(owner)?;
+                class.name_and_type(name_and_type)?;
+            },
+            Constant::NameAndTypeRef{..} => (),
+            Constant::MethodHandleRef(ref handle) => check_method_handle(class, handle)?,
+            Constant::Dynamic{ref bootstrap_method_attr, ref name_and_type} |
+            Constant::InvokeDynamicInfo{ref bootstrap_method_attr, ref name_and_type} => {
+                if bootstrap_method_attr.0 as usize >= bootstrap_methods {
+                    return Err(StructureError::MissingBootstrapMethod(bootstrap_method_attr.0));
+                }
+                class.name_and_type(name_and_type)?;
+            },
+            Constant::Utf8(_) | Constant::Integer(_) | Constant::Float(_) | Constant::Long(_) | Constant::Double(_) | Constant::Dummy => (),
+        }
+    }
+    Ok(())
+}
+
+// Handles to fields must refer to Fieldref constants, and handles to methods to Methodref or,
+// for static and special invocation, InterfaceMethodref constants.
+fn check_method_handle(class: &Class, handle: &MethodHandle) -> Result<(), StructureError> {
+    let (index, valid) = match *handle {
+        MethodHandle::GetField(ref index) | MethodHandle::GetStatic(ref index) |
+        MethodHandle::PutField(ref index) | MethodHandle::PutStatic(ref index) => {
+            (index, matches!(*index.lookup(&class.constants)?, Constant::FieldRef{..}))
+        },
+        MethodHandle::InvokeVirtual(ref index) | MethodHandle::NewInvokeSpecial(ref index) => {
+            (index, matches!(*index.lookup(&class.constants)?, Constant::MethodRef{..}))
+        },
+        MethodHandle::InvokeStatic(ref index) | MethodHandle::InvokeSpecial(ref index) => {
+            (index, matches!(*index.lookup(&class.constants)?, Constant::MethodRef{..} | Constant::InterfaceMethodRef{..}))
+        },
+        MethodHandle::InvokeInterface(ref index) => (index, matches!(*index.lookup(&class.constants)?, Constant::InterfaceMethodRef{..})),
+    };
+    if !valid {
+        return Err(ConstantLookupError::UnexpectedType(index.0).into());
+    }
+    Ok(())
+}
+
+// Checks that a class doesn't override a final method of its superclasses, per JVM spec 4.10.
+// Private and static methods aren't overridden, and constructors are never inherited.
+pub fn check_overrides(class: &Class, super_class: &RuntimeClass) -> Result<(), StructureError> {
+    for method in &class.methods {
+        let name = class.utf8(&method.name)?;
+        if name == "<init>" || method.flags.intersects(MethodFlags::PRIVATE | MethodFlags::STATIC) {
+            continue;
+        }
+        let descriptor = class.utf8(&method.descriptor)?;
+        let mut ancestor = Some(super_class);
+        while let Some(current) = ancestor {
+            if let Some(index) = current.members().method(name, descriptor) {
+                let inherited = &current.class.methods[index];
+                let overridable = !inherited.flags.intersects(MethodFlags::PRIVATE | MethodFlags::STATIC);
+                if overridable && inherited.flags.contains(MethodFlags::FINAL) {
+                    return Err(StructureError::FinalMethodOverridden{class: current.name.to_string(), method: format!("{}{}", name, descriptor)});
+                }
+            }
+            ancestor = current.super_class.as_deref();
+        }
+    }
+    Ok(())
+}
+
+// Verifies every class in a JAR without running anything, as a build step might, checking the
+// structure of each class and then its code by type inference. Classes are checked against the
+// bootstrap classes, the JAR's other classes and those of the JARs on its manifest's Class-Path.
+// The classes are read and verified on as many threads as there are CPUs.
+pub fn verify_jar<P: AsRef<Path>>(path: P) -> Result<Report, ClasspathError> {
+    let jar = JarSource::open(path)?;
+    let classes: Vec<_> = jar.load_classes().into_iter().map(|(entry, class)| (entry, class.map_err(ClassError::from))).collect();
+
+    // Earlier classes hide later ones of the same name, as on a classpath. Only the headers of
+    // classes that aren't being verified are read, since their code isn't needed.
+    let mut known = HashMap::new();
+    for &(_, bytes) in crate::bootstrap::CLASSES {
+        let header = classloader::load_class_header(bytes).expect("Bootstrap classes are valid");
+        known.entry(header.name.clone()).or_insert_with(|| ClassInfo::of_header(&header));
+    }
+    for class in classes.iter().filter_map(|(_, class)| class.as_ref().ok()) {
+        if let (Ok(name), Ok(info)) = (class.name(), ClassInfo::of(class)) {
+            known.entry(name.to_string()).or_insert(info);
+        }
+    }
+    for dependency in jar.class_path().iter().filter_map(|path| JarSource::open(path).ok()) {
+        for (_, header) in dependency.scan_classes() {
+            if let Ok(header) = header {
+                known.entry(header.name.clone()).or_insert_with(|| ClassInfo::of_header(&header));
+            }
+        }
+    }
+
+    let outcomes = jar::parallel_map(&classes, |(_, class)| class.as_ref().ok().map(|class| verify_class(class, &known)));
+    let mut report = Report {path: jar.path().to_path_buf(), verified: vec![], failures: vec![]};
+    for ((entry, class), outcome) in classes.into_iter().zip(outcomes) {
+        match (class, outcome.unwrap_or_else(|message| Some(Err(ClassError::Panicked(message))))) {
+            (Err(error), _) | (_, Some(Err(error))) => report.failures.push(Failure {entry, error}),
+            _ => report.verified.push(entry),
+        }
+    }
+    Ok(report)
+}
+
+fn verify_class(class: &Class, known: &HashMap<String, ClassInfo>) -> Result<(), ClassError> {
+    check_structure(class)?;
+    for method in &class.methods {
+        check_instructions(class, method)?;
+    }
+    inference::verify_class(class, &mut |name: &str| known.get(name).cloned())?;
+    Ok(())
+}
+
+// The outcome of verifying a JAR, listing its class entries by whether they verified.
+#[derive(Debug, PartialEq)]
+pub struct Report {
+    pub path: PathBuf,
+    pub verified: Vec<String>,
+    pub failures: Vec<Failure>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Failure {
+    pub entry: String,
+    pub error: ClassError,
+}
+
+impl Report {
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+// A line per failure, after a summary. Verification errors span several lines, which are
+// indented under the entry.
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} classes verified, {} failed", self.path.display(), self.verified.len(), self.failures.len())?;
+        for failure in &self.failures {
+            write!(f, "\n  {}: {}", failure.entry, failure.error.to_string().replace('\n', "\n    "))?;
+        }
+        Ok(())
+    }
+}
+
+// Why a class in a JAR didn't verify.
+#[derive(Debug, PartialEq)]
+pub enum ClassError {
+    Archive(ZipError),
+    ClassLoad(ClassLoaderError),
+    Inference(InferenceError),
+    Structure(StructureError),
+    Panicked(String), // Reading or verifying the class hit a bug in the VM, with the panic's message.
+}
+
+impl std::convert::From<EntryError> for ClassError {
+    fn from(err: EntryError) -> ClassError {
+        match err {
+            EntryError::Archive(cause) => ClassError::Archive(cause),
+            EntryError::ClassLoad(cause) => ClassError::ClassLoad(cause),
+            EntryError::Panicked(message) => ClassError::Panicked(message),
+        }
+    }
+}
+
+impl std::convert::From<ZipError> for ClassError {
+    fn from(err: ZipError) -> ClassError {
+        ClassError::Archive(err)
+    }
+}
+
+impl std::convert::From<ClassLoaderError> for ClassError {
+    fn from(err: ClassLoaderError) -> ClassError {
+        ClassError::ClassLoad(err)
+    }
+}
+
+impl std::convert::From<InferenceError> for ClassError {
+    fn from(err: InferenceError) -> ClassError {
+        ClassError::Inference(err)
+    }
+}
+
+impl std::convert::From<StructureError> for ClassError {
+    fn from(err: StructureError) -> ClassError {
+        ClassError::Structure(err)
+    }
+}
+
+impl fmt::Display for ClassError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ClassError::Archive(ref cause) => write!(f, "Unreadable entry: {}", cause),
+            ClassError::ClassLoad(ref cause) => write!(f, "Invalid class file: {}", cause),
+            ClassError::Inference(ref cause) => write!(f, "{}", cause),
+            ClassError::Structure(ref cause) => write!(f, "{}", cause),
+            ClassError::Panicked(ref message) => write!(f, "Internal error: {}", message),
+        }
+    }
+}
+
+impl error::Error for ClassError {
+    fn description(&self) -> &str {
+        match *self {
+            ClassError::Archive(_) => "Unreadable entry",
+            ClassError::ClassLoad(_) => "Invalid class file",
+            ClassError::Inference(_) => "Verification failed",
+            ClassError::Structure(_) => "Malformed class",
+            ClassError::Panicked(_) => "Internal error",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            ClassError::Archive(ref cause) => Some(cause),
+            ClassError::ClassLoad(ref cause) => Some(cause),
+            ClassError::Inference(ref cause) => Some(cause),
+            ClassError::Structure(ref cause) => Some(cause),
+            ClassError::Panicked(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StructureError {
+    Bytecode{method: String, cause: BytecodeError},
+    CodeLength{method: String, length: usize},
+    ConstantLookup(ConstantLookupError),
+    Descriptor(DescriptorError),
+    DuplicateField{name: String, descriptor: String},
+    DuplicateMethod{name: String, descriptor: String},
+    ExceptionRange{method: String, start_pc: u16, end_pc: u16, handler_pc: u16},
+    FinalMethodOverridden{class: String, method: String}, // The class declaring the final method.
+    InterfaceSuperClass(String),
+    MissingBootstrapMethod(u16),
+    MissingCode(String),
+    MissingSuperClass,
+    UnexpectedCode(String),
+}
+
+impl StructureError {
+    // The error Java code sees. The JVM reports most of these problems while parsing the class
+    // file, but overriding a final method is caught by verification.
+    pub fn error_class(&self) -> &'static str {
+        match *self {
+            StructureError::FinalMethodOverridden{..} => "java/lang/VerifyError",
+            _ => "java/lang/ClassFormatError",
+        }
+    }
+}
+
+impl std::convert::From<ConstantLookupError> for StructureError {
+    fn from(err: ConstantLookupError) -> StructureError {
+        StructureError::ConstantLookup(err)
+    }
+}
+
+impl std::convert::From<DescriptorError> for StructureError {
+    fn from(err: DescriptorError) -> StructureError {
+        StructureError::Descriptor(err)
+    }
+}
+
+impl fmt::Display for StructureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StructureError::Bytecode{ref method, ref cause} => write!(f, "Invalid code in method {}: {}", method, cause),
+            StructureError::CodeLength{ref method, length} => write!(f, "Method {} has code of illegal length {}", method, length),
+            StructureError::ConstantLookup(ref cause) => write!(f, "Invalid constant pool: {}", cause),
+            StructureError::Descriptor(ref cause) => write!(f, "Invalid descriptor: {}", cause),
+            StructureError::DuplicateField{ref name, ref descriptor} => write!(f, "Duplicate field name \"{}\" with signature \"{}\"", name, descriptor),
+            StructureError::DuplicateMethod{ref name, ref descriptor} => write!(f, "Duplicate method name \"{}\" with signature \"{}\"", name, descriptor),
+            StructureError::ExceptionRange{ref method, start_pc, end_pc, handler_pc} => {
+                write!(f, "Illegal exception table range {}..{} with handler {} in method {}", start_pc, end_pc, handler_pc, method)
+            },
+            StructureError::FinalMethodOverridden{ref class, ref method} => write!(f, "Overrides final method {}.{}", class, method),
+            StructureError::InterfaceSuperClass(ref name) => write!(f, "Interfaces must have java/lang/Object as superclass, not {}", name),
+            StructureError::MissingBootstrapMethod(index) => write!(f, "No bootstrap method at index {}", index),
+            StructureError::MissingCode(ref method) => write!(f, "Method {} has no Code attribute", method),
+            StructureError::MissingSuperClass => write!(f, "Only java/lang/Object may have no superclass"),
+            StructureError::UnexpectedCode(ref method) => write!(f, "Abstract or native method {} has a Code attribute", method),
+        }
+    }
+}
+
+impl error::Error for StructureError {
+    fn description(&self) -> &str {
+        match *self {
+            StructureError::Bytecode{..} => "Invalid code",
+            StructureError::CodeLength{..} => "Illegal code length",
+            StructureError::ConstantLookup(_) => "Invalid constant pool",
+            StructureError::Descriptor(_) => "Invalid descriptor",
+            StructureError::DuplicateField{..} => "Duplicate field",
+            StructureError::DuplicateMethod{..} => "Duplicate method",
+            StructureError::ExceptionRange{..} => "Illegal exception table range",
+            StructureError::FinalMethodOverridden{..} => "Overrides final method",
+            StructureError::InterfaceSuperClass(_) => "Interface superclass isn't java/lang/Object",
+            StructureError::MissingBootstrapMethod(_) => "Missing bootstrap method",
+            StructureError::MissingCode(_) => "Missing Code attribute",
+            StructureError::MissingSuperClass => "Missing superclass",
+            StructureError::UnexpectedCode(_) => "Unexpected Code attribute",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            StructureError::Bytecode{ref cause, ..} => Some(cause),
+            StructureError::CodeLength{..} => None,
+            StructureError::ConstantLookup(ref cause) => Some(cause),
+            StructureError::Descriptor(ref cause) => Some(cause),
+            StructureError::DuplicateField{..} => None,
+            StructureError::DuplicateMethod{..} => None,
+            StructureError::ExceptionRange{..} => None,
+            StructureError::FinalMethodOverridden{..} => None,
+            StructureError::InterfaceSuperClass(_) => None,
+            StructureError::MissingBootstrapMethod(_) => None,
+            StructureError::MissingCode(_) => None,
+            StructureError::MissingSuperClass => None,
+            StructureError::UnexpectedCode(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+    use crate::classloader;
+
+    fn parse(bytes: &[u8]) -> Class {
+        classloader::load_class(bytes).unwrap()
+    }
+
+    fn fixture() -> Class {
+        parse(fixture!("Exceptions"))
+    }
+
+    #[test]
+    fn test_verify_modes() {
+        assert_eq!(Some(VerifyMode::All), VerifyMode::parse("all"));
+        assert_eq!(Some(VerifyMode::None), VerifyMode::parse("none"));
+        assert_eq!(None, VerifyMode::parse("some"));
+        assert!(VerifyMode::All.verifies(LoaderId::BOOTSTRAP));
+        assert!(!VerifyMode::Remote.verifies(LoaderId::BOOTSTRAP));
+        assert!(VerifyMode::Remote.verifies(LoaderId::APPLICATION));
+        assert!(!VerifyMode::None.verifies(LoaderId::APPLICATION));
+    }
+
+    #[test]
+    fn test_compiled_classes_are_well_formed() {
+        assert_eq!(Ok(()), check_structure(&fixture()));
+        for &(_, bytes) in crate::bootstrap::CLASSES {
+            assert_eq!(Ok(()), check_structure(&parse(bytes)));
+        }
+    }
+
+    #[test]
+    fn test_duplicate_members() {
+        let mut class = fixture();
+        let field = &class.fields[0];
+        let duplicate = Field {flags: FieldFlags::PRIVATE, name: field.name.clone(), descriptor: field.descriptor.clone(), attributes: vec![]};
+        class.fields.push(duplicate);
+        assert_eq!(Err(StructureError::DuplicateField{name: "counter".to_string(), descriptor: "I".to_string()}), check_structure(&class));
+
+        let mut class = fixture();
+        let method = &class.methods[0];
+        let duplicate = Method {flags: MethodFlags::ABSTRACT, name: method.name.clone(), descriptor: method.descriptor.clone(), attributes: vec![]};
+        let (name, descriptor) = (class.utf8(&method.name).unwrap().to_string(), class.utf8(&method.descriptor).unwrap().to_string());
+        class.methods.push(duplicate);
+        assert_eq!(Err(StructureError::DuplicateMethod{name, descriptor}), check_structure(&class));
+    }
+
+    #[test]
+    fn test_code_must_match_method_kind() {
+        let mut class = fixture();
+        class.methods[0].flags |= MethodFlags::NATIVE;
+        assert!(matches!(check_structure(&class), Err(StructureError::UnexpectedCode(_))));
+        class.methods[0].attributes.clear();
+        class.methods[0].flags -= MethodFlags::NATIVE;
+        assert!(matches!(check_structure(&class), Err(StructureError::MissingCode(_))));
+    }
+
+    #[test]
+    fn test_exception_ranges_must_be_inside_code() {
+        let mut class = fixture();
+        let method = class.methods.iter_mut().find(|method| method.code().is_some_and(|code| !code.exception_table.is_empty())).unwrap();
+        for attribute in &mut method.attributes {
+            if let Attribute::Code(ref mut body) = *attribute {
+                body.exception_table[0].end_pc = body.code.len() as u16 + 1;
+            }
+        }
+        assert_eq!(Ok(()), check_structure(&class));
+        let method = class.methods.iter().find(|method| method.code().is_some_and(|code| !code.exception_table.is_empty())).unwrap();
+        assert!(matches!(check_instructions(&class, method), Err(StructureError::ExceptionRange{..})));
+    }
+
+    #[test]
+    fn test_this_class_must_be_a_class() {
+        let mut class = fixture();
+        class.this_class = class.methods[0].name.clone(); // A Utf8 constant.
+        assert!(matches!(check_structure(&class), Err(StructureError::ConstantLookup(ConstantLookupError::UnexpectedType(_)))));
+        let mut class = fixture();
+        class.super_class = ConstantIndex(0);
+        assert_eq!(Err(StructureError::MissingSuperClass), check_structure(&class));
+    }
+
+    #[test]
+    fn test_verify_jar_reports_each_class() {
+        let report = verify_jar(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/jars/verify.jar")).unwrap();
+        assert_eq!(vec!["Boom.class", "Legacy.class"], report.verified);
+        assert_eq!(1, report.failures.len());
+        assert_eq!("Unverifiable.class", report.failures[0].entry);
+        assert!(matches!(report.failures[0].error, ClassError::Inference(_)));
+        assert!(!report.is_success());
+
+        let text = report.to_string();
+        assert!(text.contains("verify.jar: 2 classes verified, 1 failed\n  Unverifiable.class: Bad local variable type\n    Exception Details:\n"), "{}", text);
+    }
+
+    #[test]
+    fn test_verify_jar_knows_the_classes_on_its_class_path() {
+        // The classes in app.jar extend and call those in lib.jar.
+        let report = verify_jar(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/jars/app.jar")).unwrap();
+        assert!(report.is_success(), "{}", report);
+        assert!(!report.verified.is_empty());
+    }
+}
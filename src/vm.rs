@@ -0,0 +1,3114 @@
+use crate::bootstrap::BootstrapSource;
+use crate::classes::*;
+use crate::classloader::{self, ClassLoaderError};
+use crate::classpath::{Classpath, ClasspathError};
+use crate::clock::{Clock, HostClock};
+use crate::compression::ZipStreams;
+use crate::console::Console;
+use crate::crash::Crash;
+use crate::debugger::{Breakpoint, BreakpointId, Debugger, Debugging};
+use crate::call;
+use crate::descriptor::{DescriptorCache, DescriptorError, FieldType, MethodDescriptor};
+use crate::direct::{DirectMemory, DirectMemoryError};
+use crate::entropy::{EntropySource, HostEntropy};
+use crate::events::{self, Event, EventBus, EventKinds, EventListener, SubscriptionId};
+use crate::gc::{self, GcCause, GcListener, GcStats, GcTotals};
+use crate::handles::{GlobalRef, Handles, LocalRef, Locals};
+use crate::heap::*;
+use crate::hooks::ExecutionHooks;
+use crate::inference::{self, ClassInfo, Hierarchy, InferenceError};
+use crate::instrument::ClassFileTransformer;
+use crate::interpreter::{self, Activation};
+#[cfg(feature = "jit")]
+use crate::jit::{self, Jit};
+#[cfg(feature = "native-libraries")]
+use crate::libraries::Libraries;
+use crate::limits::{self, Budget, LimitViolation, Limits};
+use crate::loaders::{self, ClassLoadListener, ClassLoader, ClassLoaders, LoaderId};
+use crate::method_area::MethodArea;
+use crate::modules::ModuleLayer;
+use crate::natives::{Native, NativeFn, NativeRegistry};
+use crate::outcome::{self, Outcome, UncaughtHandler, UncaughtThrowable};
+use crate::profiler::{self, Profile, Profiler};
+use crate::roots::{Root, RootVisitor};
+use crate::runtime::*;
+use crate::safepoint::{Safepoint, VmOperation};
+use crate::sandbox::{self, Decision, SandboxPolicy, Unrestricted};
+use crate::shutdown;
+use crate::signals::{self, QuitHandler};
+use crate::strings::StringPool;
+use crate::symbols::{Symbol, SymbolTable};
+use crate::threads::{self, ThreadId, Threading, Threads};
+use crate::verify::{self, StructureError, VerifyMode};
+use crate::walker::HeapWalker;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use std::{error, fmt};
+
+// The default limit on the number of nested Java frames. Java frames don't use the Rust stack, so
+// this only bounds memory use; it's roughly what HotSpot's default 1MB thread stack allows.
+pub const DEFAULT_MAX_STACK_DEPTH: usize = 10_000;
+
+// The names of the types that have primitive mirrors.
+const PRIMITIVE_NAMES: &[&str] = &["boolean", "byte", "char", "short", "int", "long", "float", "double", "void"];
+
+// Extra frames that may be used beyond the limit while constructing the StackOverflowError
+// itself, in the spirit of HotSpot's stack guard zones.
+const STACK_OVERFLOW_RESERVE: usize = 16;
+
+pub struct Vm {
+    // Each loader finds the classes it defines, which are loaded lazily, on first use.
+    loaders: ClassLoaders,
+    method_area: MethodArea,
+    defining: HashSet<(LoaderId, String)>, // Classes whose supertypes are being loaded.
+    strings: StringPool,
+    symbols: SymbolTable, // Names and descriptors shared by the loaded classes.
+    descriptors: DescriptorCache,
+    mirrors: HashMap<(LoaderId, Symbol), ObjectRef>, // By defining loader and name.
+    primitive_mirrors: HashMap<String, ObjectRef>, // For int.class and friends, by Java name.
+    packages: HashMap<(LoaderId, String), ObjectRef>, // java.lang.Package objects, likewise.
+    frames: Vec<ActiveFrame>,
+    error_stack: Option<Vec<ActiveFrame>>, // Where the first internal error not yet taken was raised.
+    panic: Arc<Mutex<Option<Crash>>>, // The first panic not yet taken on any thread, once the hook records them.
+    suspended: Vec<Activation>, // Frames waiting for a callee to return.
+    nesting: Nesting,
+    handles: Handles, // References held by native code.
+    max_stack_depth: usize,
+    throwing_stack_overflow: bool,
+    heap: Heap,
+    max_heap: Option<u64>,
+    heap_measurement: (u64, u64), // The live bytes when last measured, and the bytes allocated by then.
+    throwing_out_of_memory: bool,
+    direct_memory: DirectMemory,
+    zip_streams: ZipStreams, // Behind java.util.zip's Inflaters and Deflaters.
+    last_gc: Option<GcStats>,
+    gc_totals: GcTotals,
+    gc_listener: Option<SubscriptionId>,
+    class_load_listener: Option<SubscriptionId>,
+    safepoint: Safepoint,
+    threads: Threads,
+    hooks: Option<Box<dyn ExecutionHooks>>,
+    events: EventBus,
+    allocation_sample_interval: u64,
+    allocated_since_sample: u64,
+    profiler: Option<Profiler>,
+    quit_handler: Option<QuitHandler>,
+    uncaught_handler: Option<UncaughtHandler>,
+    debugging: Debugging,
+    natives: NativeRegistry,
+    console: Console,
+    sandbox: Box<dyn SandboxPolicy>,
+    clock: Box<dyn Clock>,
+    entropy: Box<dyn EntropySource>,
+    library_path: Vec<PathBuf>, // Where System.loadLibrary looks, like java.library.path.
+    properties: HashMap<String, String>, // The system properties, as System.getProperty sees them.
+    assertions_enabled: bool,
+    #[cfg(feature = "native-libraries")]
+    libraries: Libraries,
+    transformers: Vec<Box<dyn ClassFileTransformer>>,
+    boot_layer: ModuleLayer,
+    everything_open: bool, // Module access checks are skipped.
+    verify_mode: VerifyMode,
+    budget: Budget,
+    fuse_instructions: bool, // Methods are decoded with superinstructions.
+    #[cfg(feature = "jit")]
+    jit: Option<Jit>, // Created when the first method gets hot.
+    #[cfg(feature = "jit")]
+    jit_threshold: Option<u32>,
+}
+
+// The method a Java frame is executing, tracked so that stack traces can be filled in.
+#[derive(Clone)]
+pub struct ActiveFrame {
+    pub class: Rc<RuntimeClass>,
+    pub method_index: usize,
+    pub pc: usize, // Of the instruction being executed, which for a caller is the call.
+}
+
+// How deeply the running thread's host stack is nested, which decides whether a green thread can
+// unwind to the cooperative scheduler.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Nesting {
+    pub interpreters: usize, // Calls to interpreter::invoke that haven't returned.
+    pub natives: usize, // Natives the interpreter has called that haven't returned.
+}
+
+// What the VM holds for the running thread, which is set aside while another thread runs.
+#[derive(Default)]
+pub struct ThreadContext {
+    frames: Vec<ActiveFrame>,
+    suspended: Vec<Activation>,
+    nesting: Nesting,
+    locals: Locals,
+    throwing_stack_overflow: bool,
+    defining: HashSet<(LoaderId, String)>,
+}
+
+impl ThreadContext {
+    pub fn visit_roots(&self, visitor: &mut dyn RootVisitor) {
+        for (index, object) in self.locals.iter() {
+            visitor.visit_root(Root::LocalRef(index), object);
+        }
+        for activation in &self.suspended {
+            activation.visit_roots(visitor);
+        }
+    }
+
+    pub fn frames(&self) -> &[ActiveFrame] {
+        &self.frames
+    }
+
+    // The loaders of the classes whose methods the thread is running.
+    fn frame_loaders(&self) -> impl Iterator<Item = LoaderId> + '_ {
+        self.frames.iter().map(|frame| frame.class.loader)
+    }
+}
+
+// Threads still running are stopped first, since they use the VM.
+impl Drop for Vm {
+    fn drop(&mut self) {
+        threads::stop_others(self);
+    }
+}
+
+impl Default for Vm {
+    fn default() -> Vm {
+        Vm::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Vm {
+        let safepoint = Safepoint::new();
+        let threads = Threads::new(safepoint.poll_flag());
+        let mut vm = Vm {
+            loaders: ClassLoaders::new(),
+            method_area: MethodArea::new(),
+            defining: HashSet::new(),
+            strings: StringPool::new(),
+            symbols: SymbolTable::new(),
+            descriptors: DescriptorCache::new(),
+            mirrors: HashMap::new(),
+            primitive_mirrors: HashMap::new(),
+            packages: HashMap::new(),
+            frames: vec![],
+            error_stack: None,
+            panic: Arc::new(Mutex::new(None)),
+            suspended: vec![],
+            nesting: Nesting::default(),
+            handles: Handles::new(),
+            max_stack_depth: DEFAULT_MAX_STACK_DEPTH,
+            throwing_stack_overflow: false,
+            heap: Heap::new(),
+            max_heap: None,
+            heap_measurement: (0, 0),
+            throwing_out_of_memory: false,
+            direct_memory: DirectMemory::new(),
+            zip_streams: ZipStreams::new(),
+            last_gc: None,
+            gc_totals: GcTotals::default(),
+            gc_listener: None,
+            class_load_listener: None,
+            safepoint,
+            threads,
+            hooks: None,
+            events: EventBus::new(),
+            allocation_sample_interval: events::DEFAULT_ALLOCATION_SAMPLE_INTERVAL,
+            allocated_since_sample: 0,
+            profiler: None,
+            quit_handler: None,
+            uncaught_handler: None,
+            debugging: Debugging::default(),
+            natives: NativeRegistry::new(),
+            console: Console::default(),
+            sandbox: Box::new(Unrestricted),
+            clock: Box::new(HostClock::new()),
+            entropy: Box::new(HostEntropy::default()),
+            library_path: vec![],
+            properties: default_properties(),
+            assertions_enabled: false,
+            #[cfg(feature = "native-libraries")]
+            libraries: Libraries::new(),
+            transformers: vec![],
+            boot_layer: ModuleLayer::empty(),
+            everything_open: false,
+            verify_mode: VerifyMode::default(),
+            budget: Budget::default(),
+            fuse_instructions: true,
+            #[cfg(feature = "jit")]
+            jit: None,
+            #[cfg(feature = "jit")]
+            jit_threshold: Some(jit::DEFAULT_THRESHOLD),
+        };
+        vm.loaders.get_mut(LoaderId::BOOTSTRAP).expect("The bootstrap loader always exists").classpath_mut().push(BootstrapSource);
+        vm
+    }
+
+    // Sets the maximum number of nested Java frames, beyond which invoking a method throws
+    // StackOverflowError. This plays the role of the JVM's -Xss option.
+    pub fn set_max_stack_depth(&mut self, depth: usize) {
+        self.max_stack_depth = depth;
+    }
+
+    pub fn max_stack_depth(&self) -> usize {
+        self.max_stack_depth
+    }
+
+    // The number of Java frames currently executing.
+    pub fn stack_depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    // Sets the modules that classes belong to. Until it's set, every class is in an unnamed
+    // module and modules don't restrict access.
+    pub fn set_boot_layer(&mut self, layer: ModuleLayer) {
+        self.boot_layer = layer;
+    }
+
+    pub fn boot_layer(&self) -> &ModuleLayer {
+        &self.boot_layer
+    }
+
+    // Lets every class access every other class whatever the modules say, like running the
+    // JDK with every package of every module exported and opened, for programs written before
+    // modules existed.
+    pub fn set_everything_open(&mut self, open: bool) {
+        self.everything_open = open;
+    }
+
+    pub fn is_everything_open(&self) -> bool {
+        self.everything_open
+    }
+
+    // Checks that a class may refer to another as far as modules are concerned, per JVM spec
+    // 5.4.4. An array class is accessible if its element class is.
+    pub fn check_access(&self, from: &RuntimeClass, to: &RuntimeClass) -> Result<(), VmError> {
+        if self.everything_open || self.boot_layer.is_empty() {
+            return Ok(());
+        }
+        let element = to.name.trim_start_matches('[');
+        let element = match element.strip_prefix('L') {
+            Some(name) if to.name.starts_with('[') => name.trim_end_matches(';'),
+            _ if to.name.starts_with('[') => return Ok(()), // Primitive arrays are in java.base.
+            _ => element,
+        };
+        self.boot_layer.check_access((from.loader, &from.name), (to.loader, element)).map_err(VmError::IllegalAccess)
+    }
+
+    // Sets which classes are verified from now on, like the JVM's -Xverify option. Loaders may
+    // override it for their own classes with ClassLoader::set_verify.
+    pub fn set_verify_mode(&mut self, mode: VerifyMode) {
+        self.verify_mode = mode;
+    }
+
+    pub fn verify_mode(&self) -> VerifyMode {
+        self.verify_mode
+    }
+
+    // Whether classes that the loader defines are verified.
+    pub fn verifies(&self, loader: LoaderId) -> bool {
+        match self.loaders.get(loader).and_then(ClassLoader::verify) {
+            Some(verify) => verify,
+            None => self.verify_mode.verifies(loader),
+        }
+    }
+
+    // Limits the resources that programs run from now on may use, and resets the counts of
+    // instructions executed and bytes allocated. Exceeding a limit stops the program with
+    // VmError::LimitExceeded.
+    pub fn set_limits(&mut self, limits: Limits) {
+        let timer = self.budget.timer().cloned();
+        self.budget = Budget::new(limits);
+        self.budget.set_timer(timer);
+    }
+
+    pub fn limits(&self) -> &Limits {
+        self.budget.limits()
+    }
+
+    // The number of instructions executed since the limits were last set.
+    pub fn executed_instructions(&self) -> u64 {
+        self.budget.instructions()
+    }
+
+    // The estimated number of bytes allocated since the limits were last set.
+    pub fn allocated_bytes(&self) -> u64 {
+        self.budget.allocated_bytes()
+    }
+
+    // The number of classes that have been loaded, including array classes.
+    pub fn loaded_class_count(&self) -> usize {
+        self.method_area.len()
+    }
+
+    // Every class that has been loaded, each once.
+    pub fn loaded_classes(&self) -> impl Iterator<Item = &Rc<RuntimeClass>> {
+        self.method_area.classes()
+    }
+
+    pub fn method_area(&self) -> &MethodArea {
+        &self.method_area
+    }
+
+    // Called by the interpreter before each instruction, which is also where it polls for
+    // safepoints.
+    #[inline]
+    pub fn count_instruction(&mut self) -> Result<(), VmError> {
+        self.budget.count_instruction()?;
+        if self.safepoint.is_requested() {
+            self.reach_safepoint()?;
+        }
+        Ok(())
+    }
+
+    // Runs the operation once every thread has stopped, before the next instruction. If no Java
+    // code is running, that's when the next method is invoked.
+    pub fn request_safepoint(&mut self, operation: VmOperation) {
+        self.safepoint.request(operation);
+    }
+
+    // The poll is also how a thread waiting to run asks for its turn, so this is where the
+    // running thread hands over.
+    #[cold]
+    fn reach_safepoint(&mut self) -> Result<(), VmError> {
+        self.safepoint.disarm();
+        profiler::poll(self);
+        if self.quit_handler.is_some() && signals::take_quit() {
+            self.handle_quit();
+        }
+        if self.safepoint.has_operations() {
+            if let Some(operations) = self.safepoint.arrive() {
+                for operation in operations {
+                    operation(self);
+                }
+            }
+        }
+        threads::tick(self)
+    }
+
+    pub fn safepoint(&self) -> &Safepoint {
+        &self.safepoint
+    }
+
+    // Runs the handler whenever the process gets SIGQUIT, at the next safepoint, instead of the
+    // process ending, as a JVM prints a thread dump. Nothing happens while no Java code is
+    // running. Returns false, and the handler never runs, where there are no signals. See the
+    // signals module.
+    pub fn set_quit_handler(&mut self, handler: QuitHandler) -> bool {
+        self.quit_handler = Some(handler);
+        signals::install_quit_handler(self.safepoint.poll_flag())
+    }
+
+    #[cold]
+    fn handle_quit(&mut self) {
+        if let Some(mut handler) = self.quit_handler.take() {
+            handler(self);
+            // Unless the handler set another.
+            self.quit_handler.get_or_insert(handler);
+        }
+    }
+
+    // Starts sampling every thread's Java stack at the interval, replacing any profiler that was
+    // running. See the profiler module.
+    pub fn start_profiler(&mut self, interval: Duration) {
+        self.profiler = Some(Profiler::start(interval, self.safepoint.poll_flag()));
+    }
+
+    // Stops the profiler, returning what it sampled, or None if it wasn't running.
+    pub fn stop_profiler(&mut self) -> Option<Profile> {
+        self.profiler.take().map(Profiler::finish)
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn profiler_mut(&mut self) -> Option<&mut Profiler> {
+        self.profiler.as_mut()
+    }
+
+    // Each thread's Java stack, innermost frame first, starting with the running thread's. Threads
+    // that haven't started or have finished have empty stacks.
+    pub fn thread_stacks(&self) -> Vec<(ThreadId, Vec<ActiveFrame>)> {
+        let current = self.threads.current();
+        let mut stacks = vec![(current, self.frames.iter().rev().cloned().collect())];
+        for (id, context) in self.threads.contexts() {
+            if id != current {
+                stacks.push((id, context.frames().iter().rev().cloned().collect()));
+            }
+        }
+        stacks
+    }
+
+    pub fn threads(&self) -> &Threads {
+        &self.threads
+    }
+
+    pub fn threads_mut(&mut self) -> &mut Threads {
+        &mut self.threads
+    }
+
+    // Chooses how Java threads run: each on a host thread of its own, which is the default, or
+    // all taking turns on the embedder's. See the threads module. This can't change once a
+    // thread has been started.
+    pub fn set_threading(&mut self, threading: Threading) {
+        assert_eq!(1, self.threads.count(), "Can't change the threading backend while threads are running");
+        // The cooperative scheduler switches threads every time the budget's slow path runs.
+        let timer = match threading {
+            Threading::Host => None,
+            Threading::Cooperative => Some(self.safepoint.poll_flag()),
+        };
+        self.budget.set_timer(timer);
+        self.threads.set_threading(threading);
+    }
+
+    pub fn nesting(&self) -> Nesting {
+        self.nesting
+    }
+
+    pub fn nesting_mut(&mut self) -> &mut Nesting {
+        &mut self.nesting
+    }
+
+    // Exchanges the running thread's frames, local handles and the like for another thread's.
+    pub fn swap_thread_context(&mut self, context: &mut ThreadContext) {
+        std::mem::swap(&mut self.frames, &mut context.frames);
+        std::mem::swap(&mut self.suspended, &mut context.suspended);
+        std::mem::swap(&mut self.nesting, &mut context.nesting);
+        self.handles.swap_locals(&mut context.locals);
+        std::mem::swap(&mut self.throwing_stack_overflow, &mut context.throwing_stack_overflow);
+        std::mem::swap(&mut self.defining, &mut context.defining);
+    }
+
+    // Sets whether common sequences of instructions, such as loading this and reading one of its
+    // fields, are run as single superinstructions. Turning it off makes the interpreter run
+    // exactly what the bytecode says, one instruction at a time, which helps when debugging the
+    // interpreter itself. Methods that have already run keep the instructions they were decoded
+    // with.
+    pub fn set_instruction_fusion(&mut self, fuse: bool) {
+        self.fuse_instructions = fuse;
+    }
+
+    pub fn fuses_instructions(&self) -> bool {
+        self.fuse_instructions
+    }
+
+    // Sets the number of invocations and loop iterations after which a method is compiled to
+    // native code, or disables compilation if it's None. Methods that have already been compiled
+    // stay compiled.
+    #[cfg(feature = "jit")]
+    pub fn set_jit_threshold(&mut self, threshold: Option<u32>) {
+        self.jit_threshold = threshold;
+        if let (Some(jit), Some(threshold)) = (self.jit.as_mut(), threshold) {
+            jit.set_threshold(threshold);
+        }
+    }
+
+    // The JIT compiler, unless it's disabled. Compiled code doesn't count the instructions it
+    // runs or check the clock, so it's also disabled while those are limited.
+    #[cfg(feature = "jit")]
+    pub fn jit(&mut self) -> Option<&mut Jit> {
+        let limits = self.budget.limits();
+        if limits.max_instructions.is_some() || limits.max_duration.is_some() {
+            return None;
+        }
+        let threshold = self.jit_threshold?;
+        if self.jit.is_none() {
+            self.jit = Jit::new(threshold);
+            if self.jit.is_none() {
+                self.jit_threshold = None; // The host isn't supported.
+            }
+        }
+        self.jit.as_mut()
+    }
+
+    // Installs hooks to be called as the interpreter runs, replacing any that were installed.
+    pub fn set_hooks(&mut self, hooks: Box<dyn ExecutionHooks>) {
+        self.hooks = Some(hooks);
+    }
+
+    // Uninstalls the current hooks, returning them.
+    pub fn take_hooks(&mut self) -> Option<Box<dyn ExecutionHooks>> {
+        self.hooks.take()
+    }
+
+    pub fn has_hooks(&self) -> bool {
+        self.hooks.is_some()
+    }
+
+    // Subscribes the listener to the given kinds of event; see the events module. Subscribers
+    // are told about each event in the order they subscribed.
+    pub fn subscribe(&mut self, kinds: EventKinds, listener: Box<dyn EventListener>) -> SubscriptionId {
+        self.events.subscribe(kinds, listener)
+    }
+
+    // Cancels the subscription, returning its listener if it hadn't been already.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> Option<Box<dyn EventListener>> {
+        self.events.unsubscribe(id)
+    }
+
+    // How many bytes are allocated between allocation samples, for subscribers to them.
+    pub fn set_allocation_sample_interval(&mut self, bytes: u64) {
+        self.allocation_sample_interval = bytes.max(1);
+        self.allocated_since_sample = 0;
+    }
+
+    // Whether the interpreter needs to run its hooked dispatch loop, for the execution hooks, the
+    // debugger or subscribers to method and exception events.
+    #[inline]
+    pub fn is_instrumented(&self) -> bool {
+        self.hooks.is_some() || self.debugging.is_active() || self.events.is_enabled(EventKinds::INTERPRETER)
+    }
+
+    // Tells the subscribers to its kind about the event, and the execution hooks too if it's one
+    // they have a callback for.
+    pub fn post_event(&mut self, event: &Event) {
+        if let Some(ref mut hooks) = self.hooks {
+            match *event {
+                Event::MethodEntry{class, method} => hooks.on_method_enter(class, method),
+                Event::MethodExit{class, method, result} => hooks.on_method_exit(class, method, result),
+                Event::Exception{class, method, pc, exception} => hooks.on_exception_thrown(class, method, pc, exception),
+                _ => (),
+            }
+        }
+        self.events.post(event);
+    }
+
+    // Installs a debugger to be called when a breakpoint is hit or a step finishes, replacing any
+    // that was installed. See the debugger module.
+    pub fn set_debugger(&mut self, debugger: Box<dyn Debugger>) {
+        self.debugging.set_debugger(debugger);
+    }
+
+    // Uninstalls the current debugger, returning it. Breakpoints are kept for the next one.
+    pub fn take_debugger(&mut self) -> Option<Box<dyn Debugger>> {
+        self.debugging.take_debugger()
+    }
+
+    pub fn is_debugging(&self) -> bool {
+        self.debugging.is_active()
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> BreakpointId {
+        self.debugging.add_breakpoint(breakpoint)
+    }
+
+    pub fn remove_breakpoint(&mut self, id: BreakpointId) -> Option<Breakpoint> {
+        self.debugging.remove_breakpoint(id)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = (BreakpointId, &Breakpoint)> {
+        self.debugging.breakpoints()
+    }
+
+    // Binds a native method to a Rust closure, which is passed an Env; see the natives module.
+    // The class is named in internal form.
+    pub fn register_native<Args>(&mut self, class: &str, name: &str, descriptor: &str, native: impl Native<Args> + 'static) {
+        self.natives.register(class, name, descriptor, native);
+    }
+
+    // Unbinds a native method, returning whether it was bound.
+    pub fn unregister_native(&mut self, class: &str, name: &str, descriptor: &str) -> bool {
+        self.natives.unregister(class, name, descriptor)
+    }
+
+    pub fn registered_native(&self, class: &str, name: &str, descriptor: &str) -> Option<NativeFn> {
+        self.natives.lookup(class, name, descriptor)
+    }
+
+    // Replaces the streams behind System.in, System.out and System.err, which are the host's by
+    // default. See the console module.
+    pub fn set_stdin(&mut self, stdin: impl Read + 'static) {
+        self.console.stdin = Box::new(stdin);
+    }
+
+    pub fn set_stdout(&mut self, stdout: impl Write + 'static) {
+        self.console.stdout = Box::new(stdout);
+    }
+
+    pub fn set_stderr(&mut self, stderr: impl Write + 'static) {
+        self.console.stderr = Box::new(stderr);
+    }
+
+    pub fn console_mut(&mut self) -> &mut Console {
+        &mut self.console
+    }
+
+    // Installs the policy that decides what Java code may do to the host, replacing the default,
+    // which allows everything. See the sandbox module.
+    pub fn set_sandbox_policy(&mut self, policy: Box<dyn SandboxPolicy>) {
+        self.sandbox = policy;
+    }
+
+    pub fn sandbox_mut(&mut self) -> &mut dyn SandboxPolicy {
+        &mut *self.sandbox
+    }
+
+    // Replaces the clock behind System.currentTimeMillis and System.nanoTime, which is the host's
+    // by default. See the clock module.
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    pub fn clock_mut(&mut self) -> &mut dyn Clock {
+        &mut *self.clock
+    }
+
+    // Replaces the source of seeds for java.util.Random. See the entropy module.
+    pub fn set_entropy_source(&mut self, entropy: Box<dyn EntropySource>) {
+        self.entropy = entropy;
+    }
+
+    pub fn entropy_source_mut(&mut self) -> &mut dyn EntropySource {
+        &mut *self.entropy
+    }
+
+    pub fn set_library_path(&mut self, path: Vec<PathBuf>) {
+        self.library_path = path;
+    }
+
+    pub fn library_path(&self) -> &[PathBuf] {
+        &self.library_path
+    }
+
+    // Sets a system property, as System.setProperty does, returning its previous value.
+    pub fn set_property(&mut self, key: &str, value: &str) -> Option<String> {
+        self.properties.insert(key.to_string(), value.to_string())
+    }
+
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(String::as_str)
+    }
+
+    // Whether assert statements are checked, like the JVM's -ea option, which as there doesn't
+    // cover the platform classes. This is decided when each class is initialized, so it should
+    // be set before running anything.
+    pub fn set_assertions_enabled(&mut self, enabled: bool) {
+        self.assertions_enabled = enabled;
+    }
+
+    // Whether the class's assert statements should be checked, as Class.desiredAssertionStatus
+    // reports.
+    pub fn assertions_enabled(&self, class: &RuntimeClass) -> bool {
+        self.assertions_enabled && class.loader != LoaderId::BOOTSTRAP
+    }
+
+    // Loads the shared library with the platform's name for the given library name, such as
+    // libfoo.so for foo on Linux, from the first directory on the library path that has it.
+    pub fn load_library(&mut self, name: &str) -> Result<(), VmError> {
+        let file_name = map_library_name(name);
+        match self.library_path.iter().map(|dir| dir.join(&file_name)).find(|path| path.is_file()) {
+            Some(path) => self.load_library_file(&path),
+            None => {
+                let paths: Vec<_> = self.library_path.iter().map(|dir| dir.display().to_string()).collect();
+                let message = format!("no {} in java.library.path: {}", name, paths.join(":"));
+                Err(self.throw_new_with_message("java/lang/UnsatisfiedLinkError", &message))
+            },
+        }
+    }
+
+    // Loads a shared library, whose functions then implement native methods; see the libraries
+    // module. Loading a library twice has no effect. The sandbox policy has the final say.
+    pub fn load_library_file(&mut self, path: &Path) -> Result<(), VmError> {
+        if !path.is_absolute() {
+            let message = format!("Expecting an absolute path of the library: {}", path.display());
+            return Err(self.throw_new_with_message("java/lang/UnsatisfiedLinkError", &message));
+        }
+        let path = &match self.sandbox.load_library(path) {
+            Decision::Allow => path.to_path_buf(),
+            Decision::Deny => return Err(sandbox::denied(self, &format!("load {}", path.display()))),
+            Decision::Virtualize(path) => path,
+        };
+        #[cfg(feature = "native-libraries")]
+        let result = self.libraries.load(path).map_err(|err| format!("Can't load library: {}: {}", path.display(), err));
+        #[cfg(not(feature = "native-libraries"))]
+        let result = Err(format!("Can't load library: {}: joyvm was built without the native-libraries feature", path.display()));
+        result.map_err(|message| self.throw_new_with_message("java/lang/UnsatisfiedLinkError", &message))
+    }
+
+    #[cfg(feature = "native-libraries")]
+    pub fn libraries(&self) -> &Libraries {
+        &self.libraries
+    }
+
+    #[cfg(feature = "native-libraries")]
+    pub fn libraries_mut(&mut self) -> &mut Libraries {
+        &mut self.libraries
+    }
+
+    pub fn hooks_mut(&mut self) -> Option<&mut (dyn ExecutionHooks + 'static)> {
+        self.hooks.as_deref_mut()
+    }
+
+    pub fn debugging(&mut self) -> &mut Debugging {
+        &mut self.debugging
+    }
+
+    // Records entry into a new Java frame, throwing StackOverflowError if that would exceed the
+    // stack depth limit. Every successful call must be paired with a call to exit_frame.
+    pub fn enter_frame(&mut self, class: &Rc<RuntimeClass>, method_index: usize) -> Result<(), VmError> {
+        let limit = if self.throwing_stack_overflow {
+            self.max_stack_depth + STACK_OVERFLOW_RESERVE
+        } else {
+            self.max_stack_depth
+        };
+
+        if self.frames.len() < limit {
+            self.frames.push(ActiveFrame {class: Rc::clone(class), method_index, pc: 0});
+            return Ok(());
+        }
+
+        if self.throwing_stack_overflow {
+            return Err(VmError::StackOverflow);
+        }
+
+        self.throwing_stack_overflow = true;
+        let error = self.throw_new("java/lang/StackOverflowError");
+        self.throwing_stack_overflow = false;
+        Err(error)
+    }
+
+    pub fn exit_frame(&mut self) {
+        self.frames.pop();
+    }
+
+    // Remembers the Java stack as an internal error is raised, for a crash report, since the
+    // frames are popped as the error propagates. Only the first is kept until it's taken, which
+    // is the innermost when errors are raised again further out.
+    #[cold]
+    pub fn record_error_stack(&mut self) {
+        if self.error_stack.is_none() {
+            self.error_stack = Some(self.frames.iter().rev().cloned().collect());
+        }
+    }
+
+    // The Java stack, innermost frame first, where the internal error last recorded was raised.
+    pub fn take_error_stack(&mut self) -> Option<Vec<ActiveFrame>> {
+        self.error_stack.take()
+    }
+
+    // Where the hook installed by crash::install_panic_hook records panics for this VM, which
+    // it shares with the threads the VM runs on.
+    pub fn panic_record(&self) -> Arc<Mutex<Option<Crash>>> {
+        Arc::clone(&self.panic)
+    }
+
+    // The first panic the hook installed by crash::install_panic_hook saw, on any thread, since
+    // one was last taken.
+    pub fn take_panic(&mut self) -> Option<Crash> {
+        self.panic.lock().unwrap_or_else(PoisonError::into_inner).take()
+    }
+
+    // The class whose method is running in the given frame, counting out from the innermost.
+    // Natives find the class that called them one frame out, since they have frames of their own.
+    pub fn frame_class(&self, depth: usize) -> Option<Rc<RuntimeClass>> {
+        self.frames.iter().rev().nth(depth).map(|frame| Rc::clone(&frame.class))
+    }
+
+    // Records the pc of the instruction the current frame is executing.
+    pub fn set_pc(&mut self, pc: usize) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.pc = pc;
+        }
+    }
+
+    // Records the current Java stack in the throwable's stackTrace field, innermost frame first,
+    // as Throwable.fillInStackTrace does. This is done as the throwable is allocated, so the
+    // frames of its constructors don't appear.
+    pub fn fill_in_stack_trace(&mut self, throwable: &ObjectRef) -> Result<(), VmError> {
+        let element_class = self.load_class("java/lang/StackTraceElement")?;
+        self.initialize(&element_class)?;
+        let frames: Vec<_> = self.frames.iter().rev().map(|frame| (Rc::clone(&frame.class), frame.method_index, frame.pc)).collect();
+
+        let array_class = self.load_class("[Ljava/lang/StackTraceElement;")?;
+        let stack_trace = self.new_array(&array_class, frames.len())?;
+        for (slot, (class, method_index, pc)) in frames.into_iter().enumerate() {
+            let method = &class.class.methods[method_index];
+            let line_number = if method.flags.contains(MethodFlags::NATIVE) {
+                outcome::NATIVE_LINE_NUMBER
+            } else {
+                method.code().and_then(|code| code.line_number(pc)).map_or(outcome::UNKNOWN_LINE_NUMBER, i32::from)
+            };
+            let file_name = match class.class.source_file() {
+                Some(file_name) => Value::Reference(Some(self.intern_string(file_name)?)),
+                None => Value::null(),
+            };
+
+            let element = self.new_object(&element_class);
+            set_field(&element, "declaringClass", Value::Reference(Some(self.intern_string(&class.java_name())?)))?;
+            set_field(&element, "methodName", Value::Reference(Some(self.intern_string(class.class.utf8(&method.name)?)?)))?;
+            set_field(&element, "fileName", file_name)?;
+            set_field(&element, "lineNumber", Value::Int(line_number))?;
+            stack_trace.fields.borrow_mut().set(slot, Value::Reference(Some(element)));
+        }
+
+        set_field(throwable, "stackTrace", Value::Reference(Some(stack_trace)))
+    }
+
+    // Makes a class available to the application class loader, returning its binary name. The
+    // class is not linked until it is first used.
+    pub fn add_class(&mut self, bytes: &[u8]) -> Result<String, VmError> {
+        self.add_class_to(LoaderId::APPLICATION, bytes)
+    }
+
+    // Makes a class available to the given loader. It's only defined by that loader if none of
+    // the loader's ancestors can find a class of the same name.
+    pub fn add_class_to(&mut self, loader: LoaderId, bytes: &[u8]) -> Result<String, VmError> {
+        self.class_loader_mut(loader)?.add_class(bytes)
+    }
+
+    // Creates a class loader that delegates to the given parent.
+    pub fn new_class_loader(&mut self, name: &str, parent: LoaderId) -> LoaderId {
+        self.loaders.define(name, parent)
+    }
+
+    pub fn class_loaders(&self) -> &ClassLoaders {
+        &self.loaders
+    }
+
+    pub fn class_loader_mut(&mut self, loader: LoaderId) -> Result<&mut ClassLoader, VmError> {
+        self.loaders.get_mut(loader).ok_or(VmError::UnknownClassLoader(loader))
+    }
+
+    // Sets where the application class loader looks for classes that haven't been added with
+    // add_class.
+    pub fn set_classpath(&mut self, classpath: Classpath) {
+        self.application_loader().set_classpath(classpath);
+    }
+
+    pub fn classpath(&self) -> &Classpath {
+        self.loaders.get(LoaderId::APPLICATION).expect("The application loader always exists").classpath()
+    }
+
+    pub fn classpath_mut(&mut self) -> &mut Classpath {
+        self.application_loader().classpath_mut()
+    }
+
+    fn application_loader(&mut self) -> &mut ClassLoader {
+        self.loaders.get_mut(LoaderId::APPLICATION).expect("The application loader always exists")
+    }
+
+    // The class of that name that the loader has loaded, whether it defined it or delegated.
+    pub fn find_loaded_class(&self, loader: LoaderId, name: &str) -> Option<Rc<RuntimeClass>> {
+        self.method_area.get(loader, name).cloned()
+    }
+
+    // Loads the named class through the application class loader, along with its superclasses
+    // and superinterfaces. Loading doesn't link or initialize the class; that's left until it's
+    // first used.
+    pub fn load_class(&mut self, name: &str) -> Result<Rc<RuntimeClass>, VmError> {
+        self.load_class_with(LoaderId::APPLICATION, name)
+    }
+
+    // Loads the named class as the given loader sees it. The loader delegates to its parent
+    // first, and only defines the class itself if the parent can't find it, per JVM spec 5.3.2.
+    // Classes the new class refers to are loaded through its defining loader.
+    pub fn load_class_with(&mut self, loader: LoaderId, name: &str) -> Result<Rc<RuntimeClass>, VmError> {
+        if let Some(class) = self.method_area.get(loader, name) {
+            return Ok(Rc::clone(class));
+        }
+        if name.starts_with('[') {
+            return self.load_array_class(loader, name);
+        }
+
+        let parent = self.loaders.get(loader).ok_or(VmError::UnknownClassLoader(loader))?.parent();
+        if let Some(parent) = parent {
+            match self.load_class_with(parent, name) {
+                Ok(class) => {
+                    self.method_area.record(loader, &class);
+                    return Ok(class);
+                },
+                Err(VmError::ClassNotFound(ref missing)) if missing == name => (),
+                Err(err) => return Err(err),
+            }
+        }
+
+        // A class that's already being defined by this loader has come round again while loading
+        // its own superclasses or superinterfaces.
+        if self.defining.contains(&(loader, name.to_string())) {
+            return Err(VmError::ClassCircularity(name.to_string()));
+        }
+        self.budget.check_class_load(self.loaded_class_count())?;
+        // Classes mapped from an archive are already parsed, but transformers need class files.
+        let archived = if self.transformers.is_empty() { self.class_loader_mut(loader)?.take_archived(name) } else { None };
+        let (class, source) = match archived {
+            Some((class, source)) => (class, Some(source)),
+            None => {
+                let found = self.class_loader_mut(loader)?.find_class(name)?.ok_or_else(|| VmError::ClassNotFound(name.to_string()))?;
+                let bytes = self.transform(loader, name, found.bytes);
+                (classloader::load_class(&bytes)?, found.source)
+            },
+        };
+        check_name(&class, name)?;
+        self.class_loader_mut(loader)?.define_package(name, source)?;
+        let class = self.define(loader, class)?;
+        if self.events.is_enabled(EventKinds::CLASS_LOAD) {
+            let loaders = &self.loaders;
+            let origin = source.and_then(|index| loaders.get(loader)?.classpath().source(index)?.origin());
+            self.events.post(&Event::ClassLoad{class: &class, origin});
+        }
+        Ok(class)
+    }
+
+    // Defines a class from its class file at runtime, as ClassLoader.defineClass does. Unlike
+    // loading, this doesn't delegate: the loader defines the class even if an ancestor could
+    // have loaded one of the same name. If a name is given, the class must have it.
+    pub fn define_class(&mut self, loader: LoaderId, name: Option<&str>, bytes: &[u8]) -> Result<Rc<RuntimeClass>, VmError> {
+        self.loaders.get(loader).ok_or(VmError::UnknownClassLoader(loader))?;
+        let mut class = classloader::load_class(bytes)?;
+        if let Some(name) = name {
+            check_name(&class, name)?;
+        }
+        let name = class.name()?.to_string();
+        if self.method_area.get(loader, &name).is_some() {
+            return Err(VmError::DuplicateClass{loader, name});
+        }
+        self.budget.check_class_load(self.loaded_class_count())?;
+        if !self.transformers.is_empty() {
+            class = classloader::load_class(&self.transform(loader, &name, bytes.to_vec()))?;
+            check_name(&class, &name)?;
+        }
+        self.class_loader_mut(loader)?.define_package(&name, None)?;
+        let class = self.define(loader, class)?;
+        self.events.post(&Event::ClassLoad{class: &class, origin: None});
+        Ok(class)
+    }
+
+    // Installs a callback that's told about each class as it's loaded or defined, along with
+    // where its class file came from if that's known. Array classes aren't reported.
+    pub fn set_class_load_listener(&mut self, listener: Option<ClassLoadListener>) {
+        if let Some(id) = self.class_load_listener.take() {
+            self.events.unsubscribe(id);
+        }
+        if let Some(mut listener) = listener {
+            self.class_load_listener = Some(self.subscribe(EventKinds::CLASS_LOAD, Box::new(move |event: &Event| {
+                if let Event::ClassLoad{class, origin} = *event {
+                    listener(class, origin);
+                }
+            })));
+        }
+    }
+
+    // Adds a transformer to be given the class file of each class before it's defined, after
+    // those already added.
+    pub fn add_transformer(&mut self, transformer: Box<dyn ClassFileTransformer>) {
+        self.transformers.push(transformer);
+    }
+
+    // Removes all the transformers, returning them.
+    pub fn take_transformers(&mut self) -> Vec<Box<dyn ClassFileTransformer>> {
+        std::mem::take(&mut self.transformers)
+    }
+
+    // Passes a class file through each transformer in turn.
+    fn transform(&mut self, loader: LoaderId, name: &str, mut bytes: Vec<u8>) -> Vec<u8> {
+        for transformer in &mut self.transformers {
+            if let Some(transformed) = transformer.transform(loader, name, &bytes) {
+                bytes = transformed;
+            }
+        }
+        bytes
+    }
+
+    // Creates the runtime class for a parsed class that the loader defines, loading its
+    // superclass and superinterfaces through the same loader. The class is marked as being
+    // defined meanwhile, so that it's caught if it turns out to be its own supertype.
+    fn define(&mut self, loader: LoaderId, class: Class) -> Result<Rc<RuntimeClass>, VmError> {
+        let key = (loader, class.name()?.to_string());
+        if !self.defining.insert(key.clone()) {
+            return Err(VmError::ClassCircularity(key.1));
+        }
+        let result = self.create_class(loader, class);
+        self.defining.remove(&key);
+        result
+    }
+
+    // Loads the superclass and superinterfaces of a class being defined, checking that they can
+    // be extended and implemented, per JVM spec 5.3.5, and then creates the class.
+    fn create_class(&mut self, loader: LoaderId, class: Class) -> Result<Rc<RuntimeClass>, VmError> {
+        if self.verifies(loader) {
+            verify::check_structure(&class)?;
+        }
+        let name = class.name()?;
+        let super_class = match class.super_class_name()? {
+            Some(super_name) => Some(self.load_class_with(loader, super_name)?),
+            None => None,
+        };
+        if let Some(ref super_class) = super_class {
+            if super_class.is_interface() {
+                return Err(VmError::IncompatibleClassChange(format!("class {} has interface {} as super class", name, super_class.name)));
+            }
+            // A final superclass is caught by verification in the JVM, so it's a VerifyError.
+            if super_class.class.flags.contains(ClassFlags::FINAL) {
+                return Err(VmError::Verification(format!("Cannot inherit from final class {}", super_class.name)));
+            }
+            verify::check_overrides(&class, super_class)?;
+        }
+
+        let mut interfaces = vec![];
+        for interface in &class.interfaces {
+            let interface = self.load_class_with(loader, class.class_name(interface)?)?;
+            if !interface.is_interface() {
+                return Err(VmError::IncompatibleClassChange(format!("class {} can not implement {}, because it is not an interface", name, interface.name)));
+            }
+            interfaces.push(interface);
+        }
+
+        let mut runtime_class = RuntimeClass::new(class, super_class, interfaces, &mut self.symbols)?;
+        runtime_class.loader = loader;
+        let runtime_class = Rc::new(runtime_class);
+        self.method_area.record(loader, &runtime_class);
+        Ok(runtime_class)
+    }
+
+    // Array classes are created on demand, after loading their component class if it is a
+    // reference type, per JVM spec 5.3.3. An array class belongs to its component class's
+    // defining loader, or to the bootstrap loader for arrays of primitives.
+    fn load_array_class(&mut self, loader: LoaderId, name: &str) -> Result<Rc<RuntimeClass>, VmError> {
+        let component_class = match *self.field_type(name)? {
+            FieldType::Array(ref component) => match **component {
+                FieldType::Object(ref component_name) => Some(self.load_class_with(loader, component_name)?),
+                FieldType::Array(_) => Some(self.load_class_with(loader, &name[1..])?),
+                _ => None,
+            },
+            _ => return Err(VmError::ClassNotFound(name.to_string())),
+        };
+
+        let defining_loader = component_class.as_ref().map_or(LoaderId::BOOTSTRAP, |component| component.loader);
+        let runtime_class = match self.find_loaded_class(defining_loader, name) {
+            Some(class) => class,
+            None => {
+                self.budget.check_class_load(self.loaded_class_count())?;
+                let object_class = self.load_class_with(LoaderId::BOOTSTRAP, "java/lang/Object")?;
+                let interfaces = vec![
+                    self.load_class_with(LoaderId::BOOTSTRAP, "java/lang/Cloneable")?,
+                    self.load_class_with(LoaderId::BOOTSTRAP, "java/io/Serializable")?,
+                ];
+                let mut runtime_class = RuntimeClass::new_array(name, object_class, interfaces, component_class, &mut self.symbols)?;
+                runtime_class.loader = defining_loader;
+                let runtime_class = Rc::new(runtime_class);
+                self.method_area.record(defining_loader, &runtime_class);
+                runtime_class
+            },
+        };
+        self.method_area.record(loader, &runtime_class);
+        Ok(runtime_class)
+    }
+
+    // Links the class, after linking its superclass and superinterfaces, per JVM spec 5.4:
+    // verifies it and then prepares it.
+    pub fn link(&mut self, class: &Rc<RuntimeClass>) -> Result<(), VmError> {
+        if class.is_linked() {
+            return Ok(());
+        }
+        if let Some(ref super_class) = class.super_class {
+            self.link(super_class)?;
+        }
+        for interface in &class.interfaces {
+            self.link(interface)?;
+        }
+        self.verify(class)?;
+        class.prepare();
+        self.events.post(&Event::ClassPrepare{class});
+        Ok(())
+    }
+
+    // Class files older than version 50 are verified by type inference. Version 50 class files
+    // may have StackMapTables, but the JVM falls back on inference when those don't check out,
+    // and they aren't checked here, so those are inferred too. Later class files aren't verified,
+    // nor are classes whose loader the verification mode trusts.
+    fn verify(&mut self, class: &Rc<RuntimeClass>) -> Result<(), VmError> {
+        if class.class.major_version > 50 || !self.verifies(class.loader) {
+            return Ok(());
+        }
+        let mut hierarchy = LoadingHierarchy {vm: self, loader: class.loader, error: None};
+        let result = inference::verify_class(&class.class, &mut hierarchy);
+        match (result, hierarchy.error) {
+            // The verifier couldn't load a class it needed to know about.
+            (Err(_), Some(err)) => Err(err),
+            (result, _) => Ok(result?),
+        }
+    }
+
+    // Discards every call site's inline cache. Caches are keyed by the receiver's class, so this
+    // is only needed when the methods of an already loaded class change, as when redefining it,
+    // or when classes are unloaded, so the caches don't keep them alive.
+    pub fn invalidate_inline_caches(&self) {
+        for class in self.loaded_classes() {
+            class.clear_inline_caches();
+        }
+    }
+
+    // Initializes the class for an instruction that hasn't changed anything yet. If another thread
+    // is initializing it, a green thread can unwind and run the instruction again once that
+    // finishes, rather than waiting with the threads beneath it held up.
+    pub fn initialize_or_suspend(&mut self, class: &Rc<RuntimeClass>) -> Result<(), VmError> {
+        if class.init_state.get() == InitState::Initializing {
+            threads::suspend_for_initializer(self, class)?;
+        }
+        self.initialize(class)
+    }
+
+    // Runs the class's static initializer if it hasn't already been run, per JVM spec 5.5,
+    // linking the class first if need be.
+    pub fn initialize(&mut self, class: &Rc<RuntimeClass>) -> Result<(), VmError> {
+        match class.init_state.get() {
+            InitState::Uninitialized => (),
+            InitState::Initializing => return threads::wait_for_initializer(self, class),
+            _ => return Ok(()),
+        }
+        self.link(class)?;
+
+        class.init_state.set(InitState::Initializing);
+        threads::run_initializer(self, class, |vm| {
+            if let Some(ref super_class) = class.super_class {
+                vm.initialize(super_class)?;
+            }
+
+            vm.assign_constant_values(class)?;
+            if let Some(clinit) = class.find_declared_method("<clinit>", "()V")? {
+                interpreter::invoke(vm, class, clinit, vec![])?;
+            }
+            Ok(())
+        })?;
+
+        class.init_state.set(InitState::Initialized);
+        Ok(())
+    }
+
+    fn assign_constant_values(&mut self, class: &Rc<RuntimeClass>) -> Result<(), VmError> {
+        for field in class.class.fields.iter().filter(|field| field.flags.contains(FieldFlags::STATIC)) {
+            for attribute in &field.attributes {
+                if let Attribute::ConstantValue{ref constant_value, ..} = *attribute {
+                    let value = match *constant_value.lookup(&class.class.constants)? {
+                        Constant::Integer(value) => Value::Int(value as i32),
+                        Constant::Long(value) => Value::Long(value as i64),
+                        Constant::Float(value) => Value::Float(value),
+                        Constant::Double(value) => Value::Double(value),
+                        _ => return Err(VmError::Unsupported(format!("ConstantValue at index {}", constant_value.0))),
+                    };
+                    let name = class.class.utf8(&field.name)?;
+                    let slot = class.static_slot(name).ok_or_else(|| VmError::FieldNotFound {class: class.name.to_string(), name: name.to_string()})?;
+                    class.put_static(slot, value);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Keeps the object alive for native code until the returned reference is deleted.
+    pub fn new_global_ref(&mut self, object: ObjectRef) -> GlobalRef {
+        self.handles.new_global(object)
+    }
+
+    pub fn global_ref(&self, global: GlobalRef) -> Option<&ObjectRef> {
+        self.handles.global(global)
+    }
+
+    pub fn delete_global_ref(&mut self, global: GlobalRef) -> Option<ObjectRef> {
+        self.handles.delete_global(global)
+    }
+
+    // Keeps the object alive for native code until the innermost local frame is popped.
+    pub fn new_local_ref(&mut self, object: ObjectRef) -> LocalRef {
+        self.handles.new_local(object)
+    }
+
+    pub fn local_ref(&self, local: LocalRef) -> Option<&ObjectRef> {
+        self.handles.local(local)
+    }
+
+    pub fn delete_local_ref(&mut self, local: LocalRef) -> Option<ObjectRef> {
+        self.handles.delete_local(local)
+    }
+
+    pub fn push_local_frame(&mut self) {
+        self.handles.push_frame();
+    }
+
+    // Deletes the locals created since the matching push_local_frame, apart from the result,
+    // which moves to the enclosing frame. See Handles::pop_frame.
+    pub fn pop_local_frame(&mut self, result: Option<LocalRef>) -> Option<LocalRef> {
+        self.handles.pop_frame(result)
+    }
+
+    // Runs the function in a local frame of its own, which is popped when it returns.
+    pub fn with_local_frame<T>(&mut self, f: impl FnOnce(&mut Vm) -> T) -> T {
+        self.push_local_frame();
+        let result = f(self);
+        self.pop_local_frame(None);
+        result
+    }
+
+    pub fn handles(&self) -> &Handles {
+        &self.handles
+    }
+
+    pub fn suspended_activations(&mut self) -> &mut Vec<Activation> {
+        &mut self.suspended
+    }
+
+    // Visits every reference held outside the heap: static fields, interned strings, class
+    // mirrors, package objects, native handles, and the locals and operand stacks of frames waiting for a callee.
+    // A frame that's in the middle of an instruction that runs Java code itself, such as a class
+    // initializer, holds its state on the Rust stack and isn't visited.
+    pub fn visit_roots(&self, visitor: &mut dyn RootVisitor) {
+        for class in self.loaded_classes() {
+            for (slot, value) in class.static_values().iter().enumerate() {
+                if let Value::Reference(Some(ref object)) = *value {
+                    visitor.visit_root(Root::StaticField{class, slot}, object);
+                }
+            }
+        }
+        for (text, string) in self.strings.strong_strings() {
+            visitor.visit_root(Root::InternedString(text), string);
+        }
+        for ((_, name), mirror) in &self.mirrors {
+            visitor.visit_root(Root::ClassMirror(name), mirror);
+        }
+        for (name, mirror) in &self.primitive_mirrors {
+            visitor.visit_root(Root::ClassMirror(name), mirror);
+        }
+        for ((_, name), package) in &self.packages {
+            visitor.visit_root(Root::Package(name), package);
+        }
+        for (index, object) in self.handles.globals() {
+            visitor.visit_root(Root::GlobalRef(index), object);
+        }
+        for (index, object) in self.handles.locals() {
+            visitor.visit_root(Root::LocalRef(index), object);
+        }
+        for activation in &self.suspended {
+            activation.visit_roots(visitor);
+        }
+        self.threads.visit_roots(visitor);
+    }
+
+    // Every object reachable from the roots. Live objects that aren't reachable are garbage that
+    // reference counting can't free, because they're part of a cycle.
+    pub fn reachable_objects(&self) -> HeapWalker {
+        let mut roots = vec![];
+        self.visit_roots(&mut |_: Root, object: &ObjectRef| roots.push(object.clone()));
+        HeapWalker::new(roots)
+    }
+
+    // Objects that are still allocated but can't be reached from the roots, such as leaked
+    // cycles, in the order they were allocated.
+    pub fn unreachable_objects(&self) -> Vec<ObjectRef> {
+        let reachable: HashSet<ObjectRef> = self.reachable_objects().collect();
+        self.heap.live_objects().filter(|object| !reachable.contains(object)).collect()
+    }
+
+    // Frees garbage that reference counting can't, i.e. cycles, and forgets weakly interned
+    // strings that have been freed. See gc::find_garbage. Classes defined by loaders that Java
+    // code can no longer reach are unloaded first, so that their instances can be freed too.
+    pub fn collect_garbage(&mut self, cause: GcCause) -> &GcStats {
+        self.events.post(&Event::GcStart{id: self.gc_totals.collections, cause});
+        let start = Instant::now();
+        let (objects_before, bytes_before) = self.heap_occupancy();
+        let unloaded_classes = self.unload_unreachable_classes();
+        for object in gc::find_garbage(&self.heap) {
+            object.clear_references();
+        }
+        self.strings.purge();
+        let reserved = self.direct_memory.reserved();
+        self.direct_memory.free_unowned();
+        self.charge_direct_memory(reserved);
+        self.zip_streams.free_unowned();
+        self.heap.prune();
+        let (objects_after, bytes_after) = self.heap_occupancy();
+        let (young_bytes, old_bytes) = self.heap.generation_sizes();
+        self.heap.tenure_survivors();
+        self.heap_measurement = (bytes_after, self.heap.allocated_bytes());
+
+        let stats = GcStats {
+            id: self.gc_totals.collections,
+            cause,
+            pause: start.elapsed(),
+            objects_before,
+            bytes_before,
+            objects_after,
+            bytes_after,
+            capacity: self.max_heap.unwrap_or(bytes_before).max(bytes_after),
+            young_bytes,
+            old_bytes,
+            unloaded_classes,
+        };
+        self.gc_totals.collections += 1;
+        self.gc_totals.pause += stats.pause;
+        self.gc_totals.reclaimed_bytes += stats.reclaimed_bytes();
+        self.events.post(&Event::GcFinish{stats: &stats});
+        self.last_gc.insert(stats)
+    }
+
+    // Unloads the classes of each loader created by Java code that's unreachable, returning how
+    // many there were. Per JVM spec 12.7, a loader is reachable as long as its ClassLoader
+    // object, any of its classes' mirrors, or any instance of its classes is, and its classes
+    // stay loaded until it's not. Classes held only by the embedder, as Rc<RuntimeClass>, don't
+    // count; they remain usable but are no longer found by name.
+    fn unload_unreachable_classes(&mut self) -> usize {
+        let mut unloaded = 0;
+        for loader in self.unreachable_loaders() {
+            let classes = self.method_area.remove_loader(loader);
+            self.mirrors.retain(|&(owner, _), _| owner != loader);
+            self.packages.retain(|&(owner, _), _| owner != loader);
+            for class in &classes {
+                class.unload();
+            }
+            unloaded += classes.len();
+            self.loaders.remove(loader);
+        }
+        if unloaded > 0 {
+            self.invalidate_inline_caches();
+            self.symbols.purge();
+        }
+        unloaded
+    }
+
+    // The loaders that could be unloaded, found the same way gc::find_garbage finds garbage.
+    // Static fields, resolved constants and mirrors of these loaders' classes don't count as
+    // references from outside the heap; the objects they refer to are only live if the loader
+    // turns out to be.
+    fn unreachable_loaders(&self) -> Vec<LoaderId> {
+        let candidates: HashSet<LoaderId> = self.loaders.iter()
+            .filter(|&(_, loader)| loader.is_unloadable())
+            .map(|(id, _)| id)
+            .collect();
+        if candidates.is_empty() {
+            return vec![];
+        }
+
+        let mut held: HashMap<LoaderId, Vec<ObjectRef>> = HashMap::new();
+        for class in self.loaded_classes().filter(|class| candidates.contains(&class.loader)) {
+            held.entry(class.loader).or_default().extend(class.held_objects());
+        }
+        for (&(loader, _), mirror) in self.mirrors.iter().filter(|&(&(loader, _), _)| candidates.contains(&loader)) {
+            held.entry(loader).or_default().push(mirror.clone());
+        }
+
+        // As in find_garbage, each object is referred to once by the objects list and once by
+        // its key in the map. Each held reference is counted twice: once where the class keeps
+        // it and once for the copy in held.
+        let objects: Vec<ObjectRef> = self.heap.live_objects().collect();
+        let mut borrowed = HashSet::new();
+        let mut internal: HashMap<ObjectRef, usize> = HashMap::new();
+        for object in &objects {
+            if object.fields.try_borrow_mut().is_err() {
+                borrowed.insert(object.clone());
+                continue;
+            }
+            for reference in object.references() {
+                *internal.entry(reference).or_insert(0) += 1;
+            }
+        }
+        for object in held.values().flatten() {
+            *internal.entry(object.clone()).or_insert(0) += 2;
+        }
+        let mut queue: Vec<ObjectRef> = objects.iter().filter(|&object| {
+            let accounted = 1 + internal.get(object).map_or(0, |count| count + 1);
+            object.strong_count() > accounted || borrowed.contains(object)
+        }).cloned().collect();
+        drop(internal);
+
+        // Reaching a loader's object or one of its mirrors makes it live, as does reaching an
+        // instance of one of its classes.
+        let mut owners: HashMap<ObjectRef, LoaderId> = HashMap::new();
+        for &loader in &candidates {
+            if let Some(object) = self.loaders.get(loader).and_then(ClassLoader::object).and_then(WeakObjectRef::upgrade) {
+                owners.insert(object, loader);
+            }
+        }
+        for (&(loader, _), mirror) in self.mirrors.iter().filter(|&(&(loader, _), _)| candidates.contains(&loader)) {
+            owners.insert(mirror.clone(), loader);
+        }
+
+        // The other loaders are always live, as are those whose classes are running. A live
+        // loader keeps its parent live, and the loaders of the classes its own classes refer to.
+        let mut pending: Vec<LoaderId> = self.loaders.iter().map(|(id, _)| id).filter(|id| !candidates.contains(id)).collect();
+        pending.extend(self.frames.iter().map(|frame| frame.class.loader));
+        pending.extend(self.threads.waiting_contexts().flat_map(ThreadContext::frame_loaders));
+        let mut live = HashSet::new();
+        let mut reached = HashSet::new();
+        loop {
+            while let Some(object) = queue.pop() {
+                if reached.insert(object.clone()) {
+                    pending.push(object.class().loader);
+                    pending.extend(owners.get(&object));
+                    queue.extend(object.references());
+                }
+            }
+            let loader = match pending.pop() {
+                Some(loader) => loader,
+                None => break,
+            };
+            if !live.insert(loader) {
+                continue;
+            }
+            queue.extend(held.remove(&loader).unwrap_or_default());
+            pending.extend(self.loaders.get(loader).and_then(ClassLoader::parent));
+            for class in self.method_area.classes_loaded_by(loader).filter(|class| class.loader == loader) {
+                pending.extend(class.referenced_classes().iter().map(|class| class.loader));
+            }
+        }
+        candidates.into_iter().filter(|loader| !live.contains(loader)).collect()
+    }
+
+    // Limits how much direct memory may be allocated at once, like the JVM's
+    // -XX:MaxDirectMemorySize option. There's no limit by default.
+    pub fn set_max_direct_memory(&mut self, limit: Option<u64>) {
+        self.direct_memory.set_limit(limit);
+    }
+
+    pub fn direct_memory(&self) -> &DirectMemory {
+        &self.direct_memory
+    }
+
+    pub fn direct_memory_mut(&mut self) -> &mut DirectMemory {
+        &mut self.direct_memory
+    }
+
+    pub fn zip_streams(&self) -> &ZipStreams {
+        &self.zip_streams
+    }
+
+    pub fn zip_streams_mut(&mut self) -> &mut ZipStreams {
+        &mut self.zip_streams
+    }
+
+    // Allocates a block of direct memory, freed along with the owner if there is one. As in the
+    // JDK, hitting the limit first triggers a collection in case unreachable buffers are holding
+    // memory, and throws OutOfMemoryError only if that doesn't free enough. So does a block the
+    // host can't allocate.
+    pub fn allocate_direct(&mut self, owner: Option<&ObjectRef>, size: u64) -> Result<u64, VmError> {
+        self.allocate_direct_with(|memory| match owner {
+            Some(owner) => memory.allocate_owned(owner, size),
+            None => memory.allocate(size),
+        })
+    }
+
+    // Frees a block of direct memory, crediting it back to the allocation limit.
+    pub fn free_direct(&mut self, address: u64) -> Result<(), VmError> {
+        let reserved = self.direct_memory.reserved();
+        self.direct_memory.free(address)?;
+        self.charge_direct_memory(reserved);
+        Ok(())
+    }
+
+    // Moves a block of direct memory to a new one of the given size, running out of memory as
+    // allocate_direct does.
+    pub fn reallocate_direct(&mut self, address: u64, size: u64) -> Result<u64, VmError> {
+        self.allocate_direct_with(|memory| memory.reallocate(address, size))
+    }
+
+    // Direct memory counts towards the allocation limit while it's allocated, and like a large
+    // array, fails at once if it takes us over.
+    fn allocate_direct_with(&mut self, mut allocate: impl FnMut(&mut DirectMemory) -> Result<u64, DirectMemoryError>) -> Result<u64, VmError> {
+        let mut result = self.try_allocate_direct(&mut allocate);
+        if let Err(DirectMemoryError::OutOfMemory{..} | DirectMemoryError::Unavailable{..}) = result {
+            self.collect_garbage(GcCause::DirectMemory);
+            result = self.try_allocate_direct(&mut allocate);
+        }
+        match result {
+            Err(err @ (DirectMemoryError::OutOfMemory{..} | DirectMemoryError::Unavailable{..})) => {
+                Err(self.throw_new_with_message("java/lang/OutOfMemoryError", &err.to_string()))
+            },
+            result => {
+                let address = result?;
+                self.budget.check_allocation()?;
+                Ok(address)
+            },
+        }
+    }
+
+    fn try_allocate_direct(&mut self, allocate: &mut impl FnMut(&mut DirectMemory) -> Result<u64, DirectMemoryError>) -> Result<u64, DirectMemoryError> {
+        let reserved = self.direct_memory.reserved();
+        let result = allocate(&mut self.direct_memory);
+        self.charge_direct_memory(reserved);
+        result
+    }
+
+    // Charges the change in direct memory since it was last the given size to the allocation
+    // limit, or credits it back if memory was freed.
+    fn charge_direct_memory(&mut self, reserved_before: u64) {
+        let reserved = self.direct_memory.reserved();
+        if reserved >= reserved_before {
+            self.budget.record_allocation(reserved - reserved_before);
+        } else {
+            self.budget.release_allocation(reserved_before - reserved);
+        }
+    }
+
+    // Limits the estimated size of the live objects, like the JVM's -Xmx option. Going over it
+    // triggers a collection, and if that doesn't free enough, OutOfMemoryError. There's no limit
+    // by default.
+    pub fn set_max_heap(&mut self, limit: Option<u64>) {
+        self.max_heap = limit;
+    }
+
+    pub fn max_heap(&self) -> Option<u64> {
+        self.max_heap
+    }
+
+    // Throws OutOfMemoryError if the heap is over its limit even after a collection. Between
+    // checks, the live size is taken to be what was live when it was last measured plus
+    // everything allocated since, so the heap is only walked once that passes the limit.
+    pub fn check_heap(&mut self) -> Result<(), VmError> {
+        let limit = match self.max_heap {
+            Some(limit) if !self.throwing_out_of_memory => limit,
+            _ => return Ok(()),
+        };
+        let (measured, allocated) = self.heap_measurement;
+        if measured + (self.heap.allocated_bytes() - allocated) <= limit {
+            return Ok(());
+        }
+        let (_, mut occupied) = self.heap_occupancy();
+        self.heap_measurement = (occupied, self.heap.allocated_bytes());
+        if occupied > limit {
+            occupied = self.collect_garbage(GcCause::AllocationFailure).bytes_after;
+        }
+        if occupied <= limit {
+            return Ok(());
+        }
+
+        self.throwing_out_of_memory = true;
+        let error = self.throw_new_with_message("java/lang/OutOfMemoryError", "Java heap space");
+        self.throwing_out_of_memory = false;
+        Err(error)
+    }
+
+    // The number and estimated total size of the objects that are still allocated.
+    pub fn heap_occupancy(&self) -> (usize, u64) {
+        self.heap.live_objects().fold((0, 0), |(count, bytes), object| (count + 1, bytes + limits::estimated_size(&object)))
+    }
+
+    pub fn last_gc(&self) -> Option<&GcStats> {
+        self.last_gc.as_ref()
+    }
+
+    pub fn gc_totals(&self) -> &GcTotals {
+        &self.gc_totals
+    }
+
+    // Installs a callback that's told about each collection as it finishes.
+    pub fn set_gc_listener(&mut self, listener: Option<GcListener>) {
+        if let Some(id) = self.gc_listener.take() {
+            self.events.unsubscribe(id);
+        }
+        if let Some(mut listener) = listener {
+            self.gc_listener = Some(self.subscribe(EventKinds::GC_FINISH, Box::new(move |event: &Event| {
+                if let Event::GcFinish{stats} = *event {
+                    listener(stats);
+                }
+            })));
+        }
+    }
+
+    pub fn identity_hash(&mut self, object: &ObjectRef) -> i32 {
+        self.heap.identity_hash(object)
+    }
+
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    // Every object is allocated through here, so that it counts towards the allocation limit.
+    fn record_allocation(&mut self, object: ObjectRef) -> ObjectRef {
+        let size = limits::estimated_size(&object);
+        self.budget.record_allocation(size);
+        if self.events.is_enabled(EventKinds::ALLOCATION_SAMPLE) {
+            self.sample_allocation(&object, size);
+        }
+        object
+    }
+
+    // Samples the allocation that takes the bytes allocated past the next multiple of the
+    // interval.
+    #[cold]
+    fn sample_allocation(&mut self, object: &ObjectRef, size: u64) {
+        self.allocated_since_sample += size;
+        if self.allocated_since_sample >= self.allocation_sample_interval {
+            self.allocated_since_sample %= self.allocation_sample_interval;
+            let thread = self.threads.current();
+            self.events.post(&Event::AllocationSample{thread, object, size});
+        }
+    }
+
+    pub fn new_object(&mut self, class: &Rc<RuntimeClass>) -> ObjectRef {
+        let object = self.heap.allocate_object(class);
+        self.record_allocation(object)
+    }
+
+    // Creates an array with every element set to the default value for the component type.
+    // Since arrays can be large, this fails at once if it takes us over the allocation limit.
+    pub fn new_array(&mut self, class: &Rc<RuntimeClass>, length: usize) -> Result<ObjectRef, VmError> {
+        let array = self.heap.allocate_array(class, length).ok_or_else(|| VmError::InvalidBytecode(format!("{} is not an array class", class.name)))?;
+        let array = self.record_allocation(array);
+        self.budget.check_allocation()?;
+        self.check_heap()?;
+        Ok(array)
+    }
+
+    // Creates a shallow copy of an object or array, as Object.clone does, with its own identity
+    // hash code. Fails at once if the copy takes us over the allocation limit, as for new_array.
+    pub fn clone_object(&mut self, object: &ObjectRef) -> Result<ObjectRef, VmError> {
+        let fields = object.fields.borrow().clone();
+        let copy = self.heap.allocate(object.class(), fields);
+        let copy = self.record_allocation(copy);
+        self.budget.check_allocation()?;
+        self.check_heap()?;
+        Ok(copy)
+    }
+
+    // Creates a new java.lang.String holding the UTF-16 encoding of the given text. The String
+    // constructor isn't run; the backing array is filled in directly, as HotSpot does.
+    pub fn new_string(&mut self, text: &str) -> Result<ObjectRef, VmError> {
+        let string_class = self.load_class("java/lang/String")?;
+        self.initialize(&string_class)?;
+        let char_array_class = self.load_class("[C")?;
+        let chars = self.heap.allocate(&char_array_class, Storage::Chars(text.encode_utf16().collect()));
+        let chars = self.record_allocation(chars);
+
+        let string = self.new_object(&string_class);
+        set_field(&string, "value", Value::Reference(Some(chars)))?;
+        Ok(string)
+    }
+
+    // Returns the canonical String object for the given text, as used for string literals.
+    pub fn intern_string(&mut self, text: &str) -> Result<ObjectRef, VmError> {
+        if let Some(string) = self.strings.get(text) {
+            return Ok(string);
+        }
+
+        let string = self.new_string(text)?;
+        self.strings.insert(text.to_string(), string.clone());
+        Ok(string)
+    }
+
+    // Implements String.intern: returns the pooled string with the same contents, adding this
+    // one to the pool if there isn't one yet.
+    pub fn intern(&mut self, string: &ObjectRef) -> Result<ObjectRef, VmError> {
+        let text = self.read_string(string)?;
+        if let Some(pooled) = self.strings.get(&text) {
+            return Ok(pooled);
+        }
+
+        self.strings.insert(text, string.clone());
+        Ok(string.clone())
+    }
+
+    pub fn string_pool(&self) -> &StringPool {
+        &self.strings
+    }
+
+    // The shared copy of a class name, member name or descriptor.
+    pub fn symbol(&mut self, text: &str) -> Symbol {
+        self.symbols.intern(text)
+    }
+
+    pub fn symbol_table(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    // Parses a method descriptor, or shares the result of parsing it before.
+    pub fn method_descriptor(&mut self, descriptor: &str) -> Result<Rc<MethodDescriptor>, VmError> {
+        Ok(self.descriptors.method(descriptor)?)
+    }
+
+    // Parses a field descriptor, or shares the result of parsing it before.
+    pub fn field_type(&mut self, descriptor: &str) -> Result<Rc<FieldType>, VmError> {
+        Ok(self.descriptors.field_type(descriptor)?)
+    }
+
+    pub fn descriptor_cache(&self) -> &DescriptorCache {
+        &self.descriptors
+    }
+
+    // Makes the string pool hold its strings weakly, so that they aren't GC roots, or strongly,
+    // which is the default.
+    pub fn set_weak_string_pool(&mut self, weak: bool) {
+        self.strings.set_weak(weak);
+    }
+
+    // Reads the contents of a java.lang.String object back into a Rust string.
+    pub fn read_string(&self, string: &ObjectRef) -> Result<String, VmError> {
+        let chars = match get_field(string, "value")? {
+            Value::Reference(Some(chars)) => chars,
+            other => return Err(VmError::InvalidBytecode(format!("String has invalid value {:?}", other))),
+        };
+
+        let fields = chars.fields.borrow();
+        let units = fields.chars().ok_or_else(|| VmError::InvalidBytecode(format!("String has value of type {}", chars.class().name)))?;
+        Ok(String::from_utf16_lossy(units))
+    }
+
+    // Returns the java.lang.Class object representing the given class, creating it on first use.
+    pub fn class_mirror(&mut self, class: &Rc<RuntimeClass>) -> Result<ObjectRef, VmError> {
+        let key = (class.loader, class.name.clone());
+        if let Some(mirror) = self.mirrors.get(&key) {
+            return Ok(mirror.clone());
+        }
+
+        let class_class = self.load_class("java/lang/Class")?;
+        self.initialize(&class_class)?;
+        let mirror = self.new_object(&class_class);
+        let name = self.intern_string(&class.java_name())?;
+        set_field(&mirror, "name", Value::Reference(Some(name)))?;
+        set_field(&mirror, "modifiers", Value::Int(class.modifiers() as i32))?;
+
+        // Interfaces report no superclass, even though their class files name Object.
+        let super_class = match class.super_class {
+            Some(ref super_class) if !class.is_interface() => Some(self.class_mirror(super_class)?),
+            _ => None,
+        };
+        set_field(&mirror, "superclass", Value::Reference(super_class))?;
+
+        let interfaces_class = self.load_class("[Ljava/lang/Class;")?;
+        let interfaces = self.new_array(&interfaces_class, class.interfaces.len())?;
+        for (i, interface) in class.interfaces.iter().enumerate() {
+            let interface = self.class_mirror(interface)?;
+            interfaces.fields.borrow_mut().set(i, Value::Reference(Some(interface)));
+        }
+        set_field(&mirror, "interfaces", Value::Reference(Some(interfaces)))?;
+
+        self.mirrors.insert(key, mirror.clone());
+        Ok(mirror)
+    }
+
+    // Returns the java.lang.Class object for a primitive type or void, such as Integer.TYPE, by
+    // its name in Java source. These have no RuntimeClass behind them; like the JDK's, they're
+    // public, final and abstract, and have no superclass or interfaces.
+    pub fn primitive_mirror(&mut self, name: &str) -> Result<ObjectRef, VmError> {
+        if let Some(mirror) = self.primitive_mirrors.get(name) {
+            return Ok(mirror.clone());
+        }
+        if !PRIMITIVE_NAMES.contains(&name) {
+            return Err(VmError::ClassNotFound(name.to_string()));
+        }
+
+        let class_class = self.load_class("java/lang/Class")?;
+        self.initialize(&class_class)?;
+        let mirror = self.new_object(&class_class);
+        let java_name = self.intern_string(name)?;
+        set_field(&mirror, "name", Value::Reference(Some(java_name)))?;
+        set_field(&mirror, "modifiers", Value::Int((ClassFlags::PUBLIC | ClassFlags::FINAL | ClassFlags::ABSTRACT).bits() as i32))?;
+        let interfaces_class = self.load_class("[Ljava/lang/Class;")?;
+        let interfaces = self.new_array(&interfaces_class, 0)?;
+        set_field(&mirror, "interfaces", Value::Reference(Some(interfaces)))?;
+        set_field(&mirror, "primitive", Value::Int(1))?;
+
+        self.primitive_mirrors.insert(name.to_string(), mirror.clone());
+        Ok(mirror)
+    }
+
+    // The class a java.lang.Class object represents, if it's a mirror the VM created.
+    pub fn mirror_class(&self, mirror: &ObjectRef) -> Option<Rc<RuntimeClass>> {
+        let &(loader, ref name) = self.mirrors.iter().find(|&(_, candidate)| candidate == mirror)?.0;
+        self.find_loaded_class(loader, name)
+    }
+
+    // Returns the java.lang.Package object for the package of the given class, creating it on
+    // first use, so that every class in a package sees the same one. Array classes aren't in a
+    // package.
+    pub fn package_object(&mut self, class: &Rc<RuntimeClass>) -> Result<Option<ObjectRef>, VmError> {
+        if class.name.starts_with('[') {
+            return Ok(None);
+        }
+        let name = loaders::package_name(&class.name);
+        let key = (class.loader, name.to_string());
+        if let Some(package) = self.packages.get(&key) {
+            return Ok(Some(package.clone()));
+        }
+        let package = match self.loaders.get(class.loader).and_then(|loader| loader.package(name)) {
+            Some(package) => package.clone(),
+            None => return Ok(None),
+        };
+
+        let package_class = self.load_class("java/lang/Package")?;
+        self.initialize(&package_class)?;
+        let object = self.new_object(&package_class);
+        let name = self.intern_string(&package.name.replace('/', "."))?;
+        set_field(&object, "name", Value::Reference(Some(name)))?;
+        let attributes = [
+            ("specificationTitle", &package.specification_title),
+            ("specificationVersion", &package.specification_version),
+            ("specificationVendor", &package.specification_vendor),
+            ("implementationTitle", &package.implementation_title),
+            ("implementationVersion", &package.implementation_version),
+            ("implementationVendor", &package.implementation_vendor),
+        ];
+        for &(field, value) in &attributes {
+            let value = match *value {
+                Some(ref value) => Some(self.intern_string(value)?),
+                None => None,
+            };
+            set_field(&object, field, Value::Reference(value))?;
+        }
+        set_field(&object, "sealed", Value::Int(package.sealed as i32))?;
+
+        self.packages.insert(key, object.clone());
+        Ok(Some(object))
+    }
+
+    // Constructs an exception of the given class using its no-argument constructor, and returns
+    // it as an error ready to be thrown. If the exception can't be constructed, the error that
+    // prevented it is returned instead.
+    pub fn throw_new(&mut self, class_name: &str) -> VmError {
+        match self.construct_exception(class_name, None) {
+            Ok(exception) => VmError::UncaughtException(exception),
+            Err(err) => err,
+        }
+    }
+
+    // As throw_new, but passes the given detail message to the exception's constructor.
+    pub fn throw_new_with_message(&mut self, class_name: &str, message: &str) -> VmError {
+        match self.construct_exception(class_name, Some(message)) {
+            Ok(exception) => VmError::UncaughtException(exception),
+            Err(err) => err,
+        }
+    }
+
+    fn construct_exception(&mut self, class_name: &str, message: Option<&str>) -> Result<ObjectRef, VmError> {
+        let class = self.load_class(class_name)?;
+        self.initialize(&class)?;
+        let exception = self.new_object(&class);
+        self.fill_in_stack_trace(&exception)?;
+        let mut args = vec![Value::Reference(Some(exception.clone()))];
+        if let Some(message) = message {
+            args.push(Value::Reference(Some(self.new_string(message)?)));
+        }
+
+        let descriptor = if message.is_some() { "(Ljava/lang/String;)V" } else { "()V" };
+        let init = class.find_declared_method("<init>", descriptor)?.ok_or_else(|| VmError::MethodNotFound {
+            class: class_name.to_string(),
+            name: "<init>".to_string(),
+            descriptor: descriptor.to_string(),
+        })?;
+        interpreter::invoke(self, &class, init, args)?;
+        Ok(exception)
+    }
+
+    // Runs a static method, initializing its class first if necessary. Exceptions that escape
+    // the method are returned as VmError::UncaughtException.
+    pub fn invoke_static(&mut self, class_name: &str, method_name: &str, descriptor: &str, args: Vec<Value>) -> Result<Option<Value>, VmError> {
+        let class = self.load_class(class_name)?;
+        self.initialize(&class)?;
+        let (declaring_class, method) = resolve_method(&class, method_name, descriptor)?.ok_or_else(|| VmError::MethodNotFound {
+            class: class_name.to_string(),
+            name: method_name.to_string(),
+            descriptor: descriptor.to_string(),
+        })?;
+
+        interpreter::invoke(self, &declaring_class, method, args)
+    }
+
+    // Invokes a static method named with its descriptor, as in "max(II)I", with arguments as
+    // they'd be written on a command line, which are parsed as the descriptor says. See
+    // call::parse_argument for how each type is written.
+    pub fn call_static(&mut self, class_name: &str, method: &str, args: &[&str]) -> Result<Option<Value>, VmError> {
+        let (name, descriptor, args) = call::parse_call(self, method, args)?;
+        self.invoke_static(class_name, name, descriptor, args)
+    }
+
+    // Runs the class's main method with the given command line arguments, and reports how the
+    // program finished. Exceptions escaping main, calls to System.exit and exceeded resource
+    // limits are outcomes rather than errors; errors are reserved for problems the program
+    // couldn't have caught, such as missing classes or invalid bytecode. As with the java
+    // launcher, the VM shuts down once main has returned or thrown. Threads still running when
+    // it halts, by exiting or otherwise, are stopped.
+    pub fn run_main(&mut self, class_name: &str, args: &[&str]) -> Result<Outcome, VmError> {
+        let finished = match self.start_main(class_name, args) {
+            Ok(()) => Ok(Outcome::Completed),
+            Err(VmError::UncaughtException(throwable)) => {
+                // Main's handlers see the exception too, though it's still reported.
+                let main = threads::current_thread(self)?;
+                threads::dispatch_uncaught(self, &main, &throwable)?;
+                threads::notify_embedder(self, &main, &throwable)?;
+                Ok(Outcome::Threw(UncaughtThrowable::from_object(self, &throwable)?))
+            },
+            Err(err) => Err(err),
+        };
+        let result = finished.and_then(|outcome| self.shutdown().map(|()| outcome));
+        threads::stop_others(self);
+        match result {
+            Ok(outcome) => Ok(outcome),
+            Err(VmError::SystemExit(status)) => Ok(Outcome::Exited(status)),
+            Err(VmError::LimitExceeded(violation)) => Ok(Outcome::LimitExceeded(violation)),
+            Err(VmError::UncaughtException(throwable)) => Ok(Outcome::Threw(UncaughtThrowable::from_object(self, &throwable)?)),
+            Err(err) => Err(err),
+        }
+    }
+
+    // Sets a handler to be told about every throwable that escapes a thread: main, threads it
+    // starts and shutdown hooks. Those escaping main are still returned as outcomes by run_main,
+    // and Java's UncaughtExceptionHandlers still see them first. Exceptions escaping other
+    // threads are no longer printed on stderr while there's a handler.
+    pub fn set_uncaught_handler(&mut self, handler: Option<UncaughtHandler>) {
+        self.uncaught_handler = handler;
+    }
+
+    pub fn has_uncaught_handler(&self) -> bool {
+        self.uncaught_handler.is_some()
+    }
+
+    // Passes the throwable to the handler, returning whether there was one.
+    pub fn notify_uncaught(&mut self, thread_name: &str, throwable: &UncaughtThrowable) -> bool {
+        match self.uncaught_handler {
+            Some(ref mut handler) => {
+                handler(thread_name, throwable);
+                true
+            },
+            None => false,
+        }
+    }
+
+    // Shuts down as the JVM does when its last non-daemon thread finishes: once the other
+    // non-daemon threads have finished, the hooks registered with Runtime.addShutdownHook run,
+    // unless they've already run, and then the daemon threads are stopped. run_main does this
+    // itself; embedders that run code with invoke_static can call it when they're done. Returns
+    // VmError::SystemExit if a thread or hook calls exit or halt, with the status it asked for.
+    pub fn shutdown(&mut self) -> Result<(), VmError> {
+        let result = threads::wait_for_non_daemons(self).and_then(|()| shutdown::run_hooks(self));
+        threads::stop_others(self);
+        result
+    }
+
+    fn start_main(&mut self, class_name: &str, args: &[&str]) -> Result<(), VmError> {
+        let class = self.load_class(class_name)?;
+        let descriptor = "([Ljava/lang/String;)V";
+        let (declaring_class, main) = resolve_method(&class, "main", descriptor)?
+            .filter(|&(ref declaring_class, main)| declaring_class.class.methods[main].flags.contains(MethodFlags::STATIC))
+            .ok_or_else(|| VmError::MethodNotFound {
+                class: class_name.to_string(),
+                name: "main".to_string(),
+                descriptor: descriptor.to_string(),
+            })?;
+        self.initialize(&class)?;
+
+        let args_class = self.load_class("[Ljava/lang/String;")?;
+        let java_args = self.new_array(&args_class, args.len())?;
+        for (slot, arg) in args.iter().enumerate() {
+            let arg = self.new_string(arg)?;
+            java_args.fields.borrow_mut().set(slot, Value::Reference(Some(arg)));
+        }
+
+        interpreter::invoke(self, &declaring_class, main, vec![Value::Reference(Some(java_args))])?;
+        Ok(())
+    }
+}
+
+// Checks that a class file found for a class is for that class.
+// Tells the verifier about classes by loading them through the loader of the class being
+// verified, keeping the first error if loading fails.
+struct LoadingHierarchy<'a> {
+    vm: &'a mut Vm,
+    loader: LoaderId,
+    error: Option<VmError>,
+}
+
+impl<'a> Hierarchy for LoadingHierarchy<'a> {
+    fn lookup(&mut self, name: &str) -> Option<ClassInfo> {
+        match self.vm.load_class_with(self.loader, name) {
+            Ok(class) => Some(ClassInfo {super_class: class.super_class.as_ref().map(|super_class| super_class.name.to_string()), is_interface: class.is_interface()}),
+            Err(err) => {
+                self.error.get_or_insert(err);
+                None
+            },
+        }
+    }
+}
+
+fn check_name(class: &Class, name: &str) -> Result<(), VmError> {
+    let found = class.name()?;
+    if found != name {
+        return Err(ClasspathError::WrongName{expected: name.to_string(), found: found.to_string()}.into());
+    }
+    Ok(())
+}
+
+// Reads an instance field by name, for use by the VM itself rather than by bytecode.
+pub fn get_field(object: &ObjectRef, name: &str) -> Result<Value, VmError> {
+    let slot = field_slot(object, name)?;
+    Ok(object.fields.borrow().get(slot))
+}
+
+// Writes an instance field by name, for use by the VM itself rather than by bytecode.
+pub fn set_field(object: &ObjectRef, name: &str, value: Value) -> Result<(), VmError> {
+    let slot = field_slot(object, name)?;
+    object.fields.borrow_mut().set(slot, value);
+    Ok(())
+}
+
+// The platform's file name for a shared library, as System.mapLibraryName returns.
+pub fn map_library_name(name: &str) -> String {
+    if cfg!(target_os = "windows") {
+        format!("{}.dll", name)
+    } else if cfg!(target_os = "macos") {
+        format!("lib{}.dylib", name)
+    } else {
+        format!("lib{}.so", name)
+    }
+}
+
+// The properties every program starts with, describing the host's conventions.
+fn default_properties() -> HashMap<String, String> {
+    let separators = if cfg!(target_os = "windows") { ["\\", ";", "\r\n"] } else { ["/", ":", "\n"] };
+    ["file.separator", "path.separator", "line.separator"].iter().zip(separators.iter())
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+fn field_slot(object: &ObjectRef, name: &str) -> Result<usize, VmError> {
+    object.class().field_slot(name).ok_or_else(|| VmError::FieldNotFound {
+        class: object.class().name.to_string(),
+        name: name.to_string(),
+    })
+}
+
+#[derive(Debug, PartialEq)]
+pub enum VmError {
+    ClassCircularity(String), // The named class is its own superclass or superinterface.
+    ClassLoad(ClassLoaderError),
+    ClassNotFound(String),
+    Classpath(ClasspathError),
+    ConstantLookup(ConstantLookupError),
+    Deadlock, // Every thread is waiting, with no deadline, in the cooperative scheduler.
+    Descriptor(DescriptorError),
+    DirectMemory(DirectMemoryError), // An access to direct memory outside any allocated block.
+    DuplicateClass{loader: LoaderId, name: String}, // The loader has already loaded a class of that name.
+    FieldNotFound{class: String, name: String},
+    IllegalAccess(String), // A class referred to another that it isn't allowed to.
+    IncompatibleClassChange(String),
+    Inference(InferenceError), // The type inference verifier rejected a class.
+    InvalidArgument(String), // An argument to Vm::call_static doesn't suit its parameter.
+    InvalidBytecode(String),
+    LimitExceeded(LimitViolation),
+    MethodNotFound{class: String, name: String, descriptor: String},
+    NativeSignature(String), // A registered native doesn't fit the descriptor of the method it's bound to.
+    SealingViolation(String), // A class was defined in a sealed package from outside its JAR.
+    StackOverflow, // The stack overflowed while constructing a StackOverflowError.
+    Structure(StructureError), // The class file is malformed in a way parsing doesn't catch.
+    Suspended, // Not an error; unwinds a green thread to the scheduler, leaving its frames to resume later.
+    SystemExit(i32), // Not really an error; unwinds the stack when the program calls System.exit.
+    ThreadStopped, // Not really an error either; unwinds a thread's stack when the VM halts.
+    UncaughtException(ObjectRef),
+    UnknownClassLoader(LoaderId),
+    Unsupported(String),
+    UnsupportedOpcode(u8),
+    Verification(String),
+}
+
+impl VmError {
+    // Whether this is a fault in the VM, or something it doesn't support, rather than a problem
+    // with the program or its classes. The launcher writes a crash report for these.
+    pub fn is_internal(&self) -> bool {
+        matches!(*self, VmError::ConstantLookup(_) | VmError::DirectMemory(_) | VmError::InvalidBytecode(_)
+            | VmError::Unsupported(_) | VmError::UnsupportedOpcode(_))
+    }
+
+    // The subclass of java.lang.LinkageError that Java code sees when this error happens while
+    // linking a class or resolving a reference, along with its message.
+    pub fn linkage_error(&self) -> Option<(&'static str, String)> {
+        match *self {
+            VmError::ClassCircularity(ref name) => Some(("java/lang/ClassCircularityError", name.clone())),
+            VmError::ClassLoad(ref cause) => Some(("java/lang/ClassFormatError", cause.to_string())),
+            VmError::ClassNotFound(ref name) => Some(("java/lang/NoClassDefFoundError", name.clone())),
+            VmError::Classpath(ClasspathError::WrongName{ref expected, ref found}) => {
+                Some(("java/lang/NoClassDefFoundError", format!("{} (wrong name: {})", expected, found)))
+            },
+            VmError::DuplicateClass{..} => Some(("java/lang/LinkageError", self.to_string())),
+            VmError::FieldNotFound{ref name, ..} => Some(("java/lang/NoSuchFieldError", name.clone())),
+            VmError::IllegalAccess(ref msg) => Some(("java/lang/IllegalAccessError", msg.clone())),
+            VmError::IncompatibleClassChange(ref msg) => Some(("java/lang/IncompatibleClassChangeError", msg.clone())),
+            VmError::Inference(ref cause) => Some(("java/lang/VerifyError", cause.to_string())),
+            VmError::MethodNotFound{ref class, ref name, ref descriptor} => {
+                Some(("java/lang/NoSuchMethodError", format!("{}.{}{}", class, name, descriptor)))
+            },
+            VmError::SealingViolation(ref msg) => Some(("java/lang/SecurityException", msg.clone())),
+            VmError::Structure(ref cause) => Some((cause.error_class(), cause.to_string())), // Not a LinkageError, but thrown the same way.
+            VmError::Verification(ref msg) => Some(("java/lang/VerifyError", msg.clone())),
+            _ => None,
+        }
+    }
+}
+
+impl std::convert::From<ClassLoaderError> for VmError {
+    fn from(cause: ClassLoaderError) -> VmError {
+        VmError::ClassLoad(cause)
+    }
+}
+
+impl std::convert::From<ClasspathError> for VmError {
+    fn from(cause: ClasspathError) -> VmError {
+        VmError::Classpath(cause)
+    }
+}
+
+impl std::convert::From<ConstantLookupError> for VmError {
+    fn from(cause: ConstantLookupError) -> VmError {
+        VmError::ConstantLookup(cause)
+    }
+}
+
+impl std::convert::From<StructureError> for VmError {
+    fn from(cause: StructureError) -> VmError {
+        VmError::Structure(cause)
+    }
+}
+
+impl std::convert::From<InferenceError> for VmError {
+    fn from(cause: InferenceError) -> VmError {
+        VmError::Inference(cause)
+    }
+}
+
+impl std::convert::From<LimitViolation> for VmError {
+    fn from(cause: LimitViolation) -> VmError {
+        VmError::LimitExceeded(cause)
+    }
+}
+
+impl std::convert::From<DescriptorError> for VmError {
+    fn from(cause: DescriptorError) -> VmError {
+        VmError::Descriptor(cause)
+    }
+}
+
+impl std::convert::From<DirectMemoryError> for VmError {
+    fn from(cause: DirectMemoryError) -> VmError {
+        VmError::DirectMemory(cause)
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VmError::ClassCircularity(ref name) => write!(f, "Class {} is its own superclass or superinterface", name),
+            VmError::ClassLoad(ref cause) => write!(f, "Failed to load class: {}", cause),
+            VmError::ClassNotFound(ref name) => write!(f, "Class {} not found", name),
+            VmError::Classpath(ref cause) => write!(f, "Failed to read class from classpath: {}", cause),
+            VmError::ConstantLookup(ref cause) => write!(f, "Invalid constant reference: {}", cause),
+            VmError::Deadlock => write!(f, "Deadlock: every thread is waiting for another"),
+            VmError::Descriptor(ref cause) => write!(f, "Invalid descriptor: {}", cause),
+            VmError::DirectMemory(ref cause) => write!(f, "Invalid direct memory access: {}", cause),
+            VmError::DuplicateClass{ref loader, ref name} => write!(f, "Class loader {} attempted duplicate class definition for {}", loader.index(), name),
+            VmError::FieldNotFound{ref class, ref name} => write!(f, "Field {}.{} not found", class, name),
+            VmError::IllegalAccess(ref msg) => write!(f, "Illegal access: {}", msg),
+            VmError::IncompatibleClassChange(ref msg) => write!(f, "Incompatible class change: {}", msg),
+            VmError::Inference(ref cause) => write!(f, "Verification failed: {}", cause),
+            VmError::InvalidArgument(ref msg) => write!(f, "Invalid argument: {}", msg),
+            VmError::InvalidBytecode(ref msg) => write!(f, "Invalid bytecode: {}", msg),
+            VmError::LimitExceeded(ref cause) => write!(f, "Resource limit exceeded: {}", cause),
+            VmError::MethodNotFound{ref class, ref name, ref descriptor} => write!(f, "Method {}.{}{} not found", class, name, descriptor),
+            VmError::NativeSignature(ref msg) => write!(f, "Native method mismatch: {}", msg),
+            VmError::SealingViolation(ref msg) => write!(f, "Security violation: {}", msg),
+            VmError::StackOverflow => write!(f, "Stack overflow while throwing StackOverflowError"),
+            VmError::Structure(ref cause) => write!(f, "Malformed class: {}", cause),
+            VmError::Suspended => write!(f, "Thread suspended by the scheduler"),
+            VmError::SystemExit(ref status) => write!(f, "System.exit called with status {}", status),
+            VmError::ThreadStopped => write!(f, "Thread stopped as the VM halted"),
+            VmError::UncaughtException(ref exception) => write!(f, "Uncaught exception {:?}", exception),
+            VmError::UnknownClassLoader(ref loader) => write!(f, "No class loader with id {}", loader.index()),
+            VmError::Unsupported(ref feature) => write!(f, "Unsupported feature: {}", feature),
+            VmError::UnsupportedOpcode(ref opcode) => write!(f, "Unsupported opcode {:#04x}", opcode),
+            VmError::Verification(ref msg) => write!(f, "Verification failed: {}", msg),
+        }
+    }
+}
+
+impl error::Error for VmError {
+    fn description(&self) -> &str {
+        match *self {
+            VmError::ClassCircularity(_) => "Class circularity",
+            VmError::ClassLoad(_) => "Failed to load class",
+            VmError::ClassNotFound(_) => "Class not found",
+            VmError::Classpath(_) => "Failed to read class from classpath",
+            VmError::ConstantLookup(_) => "Invalid constant reference",
+            VmError::Deadlock => "Deadlock",
+            VmError::Descriptor(_) => "Invalid descriptor",
+            VmError::DirectMemory(_) => "Invalid direct memory access",
+            VmError::DuplicateClass{..} => "Duplicate class definition",
+            VmError::FieldNotFound{..} => "Field not found",
+            VmError::IllegalAccess(ref msg) => msg,
+            VmError::IncompatibleClassChange(ref msg) => msg,
+            VmError::Inference(_) => "Verification failed",
+            VmError::InvalidArgument(ref msg) => msg,
+            VmError::InvalidBytecode(ref msg) => msg,
+            VmError::LimitExceeded(_) => "Resource limit exceeded",
+            VmError::MethodNotFound{..} => "Method not found",
+            VmError::NativeSignature(ref msg) => msg,
+            VmError::SealingViolation(ref msg) => msg,
+            VmError::StackOverflow => "Stack overflow while throwing StackOverflowError",
+            VmError::Structure(_) => "Malformed class",
+            VmError::Suspended => "Thread suspended",
+            VmError::SystemExit(_) => "System.exit called",
+            VmError::ThreadStopped => "Thread stopped",
+            VmError::UncaughtException(_) => "Uncaught exception",
+            VmError::UnknownClassLoader(_) => "Unknown class loader",
+            VmError::Unsupported(ref feature) => feature,
+            VmError::UnsupportedOpcode(_) => "Unsupported opcode",
+            VmError::Verification(ref msg) => msg,
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            VmError::ClassLoad(ref cause) => Some(cause),
+            VmError::Classpath(ref cause) => Some(cause),
+            VmError::ConstantLookup(ref cause) => Some(cause),
+            VmError::Descriptor(ref cause) => Some(cause),
+            VmError::DirectMemory(ref cause) => Some(cause),
+            VmError::LimitExceeded(ref cause) => Some(cause),
+            VmError::Structure(ref cause) => Some(cause),
+            VmError::ClassCircularity(_) => None,
+            VmError::ClassNotFound(_) => None,
+            VmError::Deadlock => None,
+            VmError::DuplicateClass{..} => None,
+            VmError::FieldNotFound{..} => None,
+            VmError::IllegalAccess(_) => None,
+            VmError::IncompatibleClassChange(_) => None,
+            VmError::Inference(ref cause) => Some(cause),
+            VmError::InvalidArgument(_) => None,
+            VmError::InvalidBytecode(_) => None,
+            VmError::MethodNotFound{..} => None,
+            VmError::NativeSignature(_) => None,
+            VmError::SealingViolation(_) => None,
+            VmError::StackOverflow => None,
+            VmError::Suspended => None,
+            VmError::SystemExit(_) => None,
+            VmError::ThreadStopped => None,
+            VmError::UncaughtException(_) => None,
+            VmError::UnknownClassLoader(_) => None,
+            VmError::Unsupported(_) => None,
+            VmError::UnsupportedOpcode(_) => None,
+            VmError::Verification(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, vm_with};
+    use crate::classpath::ClassSource;
+    use crate::jar::JarSource;
+    use crate::modules::ModuleDescriptor;
+    use std::cell::RefCell;
+    use std::time::Duration;
+
+    #[test]
+    fn test_load_bootstrap_class() {
+        let mut vm = Vm::new();
+        let class = vm.load_class("java/lang/NullPointerException").unwrap();
+        assert_eq!("java/lang/NullPointerException", class.name);
+        assert_eq!("java/lang/RuntimeException", class.super_class.as_ref().unwrap().name);
+    }
+
+    #[test]
+    fn test_bootstrap_classes_are_loaded_on_demand() {
+        let mut vm = Vm::new();
+        assert_eq!(0, vm.loaded_class_count());
+        vm.load_class("java/lang/Integer").unwrap();
+        let loaded: HashSet<&str> = vm.loaded_classes().map(|class| class.name.as_str()).collect();
+        assert_eq!(["java/lang/Integer", "java/lang/Number", "java/lang/Object", "java/io/Serializable"].iter().cloned().collect::<HashSet<_>>(), loaded);
+    }
+
+    #[test]
+    fn test_classes_are_linked_before_initialization() {
+        let mut vm = Vm::new();
+        let class = vm.load_class("java/lang/Integer").unwrap();
+        let super_class = class.super_class.clone().unwrap();
+        assert!(!class.is_linked());
+        assert_eq!(None, class.static_value("MIN_VALUE"));
+
+        vm.link(&class).unwrap();
+        assert!(class.is_linked() && super_class.is_linked());
+        assert_eq!(InitState::Uninitialized, class.init_state.get());
+        assert_eq!(Some(Value::Int(0)), class.static_value("MIN_VALUE"));
+
+        vm.initialize(&class).unwrap();
+        assert_eq!(InitState::Initialized, class.init_state.get());
+        assert_eq!(Some(Value::Int(i32::MIN)), class.static_value("MIN_VALUE"));
+    }
+
+    #[test]
+    fn test_load_class_returns_the_same_class_each_time() {
+        let mut vm = Vm::new();
+        let first = vm.load_class("java/lang/Object").unwrap();
+        let second = vm.load_class("java/lang/Object").unwrap();
+        assert!(Rc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn test_load_missing_class() {
+        let mut vm = Vm::new();
+        assert_eq!(Some(VmError::ClassNotFound("Missing".to_string())), vm.load_class("Missing").err());
+    }
+
+    #[test]
+    fn test_load_array_class() {
+        let mut vm = Vm::new();
+        let class = vm.load_class("[[I").unwrap();
+        assert!(class.is_array());
+        assert_eq!(Some(FieldType::Array(Box::new(FieldType::Int))), class.component_type());
+        assert_eq!("java/lang/Object", class.super_class.as_ref().unwrap().name);
+    }
+
+    #[test]
+    fn test_load_array_class_with_missing_element_class() {
+        let mut vm = Vm::new();
+        assert_eq!(Some(VmError::ClassNotFound("Missing".to_string())), vm.load_class("[LMissing;").err());
+    }
+
+    #[test]
+    fn test_new_array_has_default_elements() {
+        let mut vm = Vm::new();
+        let class = vm.load_class("[J").unwrap();
+        let array = vm.new_array(&class, 3).unwrap();
+        assert_eq!(Storage::Longs(vec![0; 3]), *array.fields.borrow());
+    }
+
+    #[test]
+    fn test_string_round_trip() {
+        let mut vm = Vm::new();
+        let string = vm.new_string("caf\u{e9}").unwrap();
+        assert_eq!("caf\u{e9}", vm.read_string(&string).unwrap());
+    }
+
+    #[test]
+    fn test_interned_strings_are_identical() {
+        let mut vm = Vm::new();
+        let first = vm.intern_string("hello").unwrap();
+        let second = vm.intern_string("hello").unwrap();
+        assert_eq!(first, second);
+        assert!(first != vm.new_string("hello").unwrap());
+    }
+
+    #[test]
+    fn test_class_names_are_shared_symbols() {
+        let mut vm = Vm::new();
+        let object = vm.load_class("java/lang/Object").unwrap();
+        let string = vm.load_class("java/lang/String").unwrap();
+        assert_eq!(object.name, vm.symbol("java/lang/Object"));
+        assert_eq!(string.super_class.as_ref().unwrap().name, object.name);
+        assert!(vm.symbol_table().get("<init>").is_some());
+    }
+
+    #[test]
+    fn test_descriptors_are_parsed_once() {
+        let mut vm = Vm::new();
+        let first = vm.method_descriptor("(Ljava/lang/String;)I").unwrap();
+        assert!(Rc::ptr_eq(&first, &vm.method_descriptor("(Ljava/lang/String;)I").unwrap()));
+        assert!(matches!(vm.method_descriptor("(I"), Err(VmError::Descriptor(_))));
+        assert!(!vm.descriptor_cache().is_empty());
+    }
+
+    #[test]
+    fn test_native_references_are_roots() {
+        let mut vm = Vm::new();
+        let object = vm.new_string("held").unwrap();
+        let global = vm.new_global_ref(object.clone());
+        let local = vm.new_local_ref(object.clone());
+        let mut held = vec![];
+        vm.visit_roots(&mut |root: Root, root_object: &ObjectRef| match root {
+            Root::GlobalRef(index) => held.push(format!("global {} {}", index, root_object == &object)),
+            Root::LocalRef(index) => held.push(format!("local {} {}", index, root_object == &object)),
+            _ => (),
+        });
+        assert_eq!(vec!["global 0 true", "local 0 true"], held);
+
+        assert_eq!(Some(object.clone()), vm.delete_global_ref(global));
+        assert_eq!(None, vm.global_ref(global));
+        assert_eq!(Some(&object), vm.local_ref(local));
+    }
+
+    #[test]
+    fn test_with_local_frame_deletes_locals() {
+        let mut vm = Vm::new();
+        let object = vm.new_string("temporary").unwrap();
+        let local = vm.with_local_frame(|vm| vm.new_local_ref(object.clone()));
+        assert_eq!(None, vm.local_ref(local));
+        assert_eq!(0, vm.handles().locals().count());
+    }
+
+    #[test]
+    fn test_map_library_name() {
+        let mut vm = Vm::new();
+        let name = vm.new_string("zip").unwrap();
+        match vm.invoke_static("java/lang/System", "mapLibraryName", "(Ljava/lang/String;)Ljava/lang/String;", vec![Value::Reference(Some(name))]) {
+            Ok(Some(Value::Reference(Some(mapped)))) => assert_eq!(map_library_name("zip"), vm.read_string(&mapped).unwrap()),
+            other => panic!("Expected a file name; got {:?}", other),
+        }
+        if cfg!(target_os = "linux") {
+            assert_eq!("libzip.so", map_library_name("zip"));
+        }
+    }
+
+    #[test]
+    fn test_missing_libraries_are_unsatisfied_links() {
+        let mut vm = Vm::new();
+        vm.set_library_path(vec![PathBuf::from("/nonexistent/a"), PathBuf::from("/nonexistent/b")]);
+        let expected = [
+            (vm.load_library("missing"), "no missing in java.library.path: /nonexistent/a:/nonexistent/b"),
+            (vm.load_library_file(Path::new("relative.so")), "Expecting an absolute path of the library: relative.so"),
+        ];
+        for (result, expected) in expected {
+            match result {
+                Err(VmError::UncaughtException(exception)) => {
+                    assert_eq!("java/lang/UnsatisfiedLinkError", exception.class().name);
+                    let message = match get_field(&exception, "detailMessage").unwrap() {
+                        Value::Reference(Some(message)) => message,
+                        other => panic!("Expected a message; got {:?}", other),
+                    };
+                    assert_eq!(expected, vm.read_string(&message).unwrap());
+                },
+                other => panic!("Expected UnsatisfiedLinkError; got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_throw_new_with_message() {
+        let mut vm = Vm::new();
+        match vm.throw_new_with_message("java/lang/ArithmeticException", "/ by zero") {
+            VmError::UncaughtException(exception) => {
+                let message = match get_field(&exception, "detailMessage").unwrap() {
+                    Value::Reference(Some(message)) => message,
+                    other => panic!("Expected a message; got {:?}", other),
+                };
+                assert_eq!("/ by zero", vm.read_string(&message).unwrap());
+            },
+            other => panic!("Expected an exception; got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_add_class_with_invalid_magic() {
+        let mut vm = Vm::new();
+        assert_eq!(Err(VmError::ClassLoad(ClassLoaderError::InvalidMagic(0xdeadbeef))), vm.add_class(b"\xde\xad\xbe\xef"));
+    }
+
+    #[test]
+    fn test_throw_new_constructs_exception() {
+        let mut vm = Vm::new();
+        match vm.throw_new("java/lang/NullPointerException") {
+            VmError::UncaughtException(exception) => assert_eq!("java/lang/NullPointerException", exception.class().name),
+            other => panic!("Expected an exception; got {:?}", other),
+        }
+    }
+
+    const MAINS: &[&[u8]] = &[fixture!("Completes"), fixture!("Exits"), fixture!("Throws"), fixture!("InheritsMain"), fixture!("InstanceMain")];
+
+    fn uncaught(outcome: Result<Outcome, VmError>) -> UncaughtThrowable {
+        match outcome {
+            Ok(Outcome::Threw(throwable)) => throwable,
+            other => panic!("Expected an uncaught throwable; got {:#?}", other),
+        }
+    }
+
+    #[test]
+    fn test_run_main_completes() {
+        let mut vm = vm_with(MAINS);
+        assert_eq!(Ok(Outcome::Completed), vm.run_main("Completes", &["one", "two"]));
+
+        let class = vm.load_class("Completes").unwrap();
+        let args = match class.static_value("receivedArgs").unwrap() {
+            Value::Reference(Some(ref args)) => args.clone(),
+            ref other => panic!("Expected the arguments array; got {:?}", other),
+        };
+        let args: Vec<String> = args.fields.borrow().values().unwrap().iter().map(|arg| match *arg {
+            Value::Reference(Some(ref arg)) => vm.read_string(arg).unwrap(),
+            ref other => panic!("Expected a string argument; got {:?}", other),
+        }).collect();
+        assert_eq!(vec!["one", "two"], args);
+    }
+
+    #[test]
+    fn test_run_inherited_main() {
+        assert_eq!(Ok(Outcome::Completed), vm_with(MAINS).run_main("InheritsMain", &[]));
+    }
+
+    #[test]
+    fn test_run_main_requires_static_main() {
+        match vm_with(MAINS).run_main("InstanceMain", &[]) {
+            Err(VmError::MethodNotFound{ref name, ..}) if name == "main" => (),
+            other => panic!("Expected main not to be found; got {:#?}", other),
+        }
+    }
+
+    #[test]
+    fn test_run_main_of_missing_class() {
+        assert_eq!(Err(VmError::ClassNotFound("Missing".to_string())), vm_with(MAINS).run_main("Missing", &[]));
+    }
+
+    #[test]
+    fn test_run_main_from_classpath() {
+        let mut vm = Vm::new();
+        vm.set_classpath(Classpath::parse(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata")).unwrap());
+        assert_eq!(Ok(Outcome::Completed), vm.run_main("Completes", &[]));
+        assert_eq!(Err(VmError::ClassNotFound("Missing".to_string())), vm.run_main("Missing", &[]));
+    }
+
+    #[test]
+    fn test_run_main_from_jar() {
+        let mut vm = Vm::new();
+        vm.set_classpath(Classpath::parse(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/jars/app.jar")).unwrap());
+        assert_eq!(Ok(Outcome::Completed), vm.run_main("com/example/App", &[]));
+        assert_eq!(Ok(Some(Value::Int(43))), vm.invoke_static("com/example/App", "answer", "()I", vec![]));
+    }
+
+    #[test]
+    fn test_system_properties() {
+        let mut vm = vm_with(&[fixture!("SystemProperties")]);
+        vm.set_property("app.name", "demo");
+        let call = |vm: &mut Vm, method: &str, args: &[&str]| {
+            let descriptor = format!("({})Ljava/lang/String;", "Ljava/lang/String;".repeat(args.len()));
+            let args = args.iter().map(|arg| Value::Reference(Some(vm.new_string(arg).unwrap()))).collect();
+            match vm.invoke_static("SystemProperties", method, &descriptor, args) {
+                Ok(Some(Value::Reference(value))) => value.map(|value| vm.read_string(&value).unwrap()),
+                other => panic!("Expected a string; got {:?}", other),
+            }
+        };
+
+        assert_eq!(Some("demo".to_string()), call(&mut vm, "get", &["app.name"]));
+        assert_eq!(Some("/".to_string()), call(&mut vm, "get", &["file.separator"]));
+        assert_eq!(None, call(&mut vm, "get", &["app.missing"]));
+        assert_eq!(Some("fallback".to_string()), call(&mut vm, "getOrDefault", &["app.missing", "fallback"]));
+        assert_eq!(Some("demo".to_string()), call(&mut vm, "set", &["app.name", "renamed"]));
+        assert_eq!(Some("renamed"), vm.property("app.name"));
+        let key = Value::Reference(Some(vm.new_string("").unwrap()));
+        match vm.invoke_static("SystemProperties", "get", "(Ljava/lang/String;)Ljava/lang/String;", vec![key]) {
+            Err(VmError::UncaughtException(exception)) => assert_eq!("java/lang/IllegalArgumentException", exception.class().name),
+            other => panic!("Expected an exception; got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_multi_release_jar_classes() {
+        let jar = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/jars/multi-release.jar");
+        let mut vm = Vm::new();
+        vm.set_classpath(Classpath::parse(jar).unwrap());
+        assert_eq!(Ok(Some(Value::Int(8))), vm.invoke_static("com/example/Release", "version", "()I", vec![]));
+
+        let mut vm = Vm::new();
+        vm.classpath_mut().set_runtime_version(17);
+        vm.classpath_mut().push_path(jar).unwrap();
+        assert_eq!(Ok(Some(Value::Int(11))), vm.invoke_static("com/example/Release", "version", "()I", vec![]));
+    }
+
+    fn string_field(vm: &Vm, object: &ObjectRef, name: &str) -> Option<String> {
+        match get_field(object, name).unwrap() {
+            Value::Reference(Some(ref string)) => Some(vm.read_string(string).unwrap()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_packages_are_described_by_manifests() {
+        let mut vm = Vm::new();
+        vm.set_classpath(Classpath::parse(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/jars/sealed.jar")).unwrap());
+        let package = match vm.invoke_static("com/example/sealed/Sealed", "getPackage", "()Ljava/lang/Package;", vec![]) {
+            Ok(Some(Value::Reference(Some(package)))) => package,
+            other => panic!("Expected a package; got {:?}", other),
+        };
+        assert_eq!(Some("com.example.sealed".to_string()), string_field(&vm, &package, "name"));
+        assert_eq!(Some("Sealed Example".to_string()), string_field(&vm, &package, "specificationTitle"));
+        assert_eq!(Some("sealed-example".to_string()), string_field(&vm, &package, "implementationTitle"));
+        assert_eq!(Some("1.0.3".to_string()), string_field(&vm, &package, "implementationVersion"));
+        assert_eq!(None, string_field(&vm, &package, "implementationVendor"));
+        assert_eq!(Value::Int(1), get_field(&package, "sealed").unwrap());
+        let class = vm.load_class("com/example/sealed/Sealed").unwrap();
+        assert_eq!(Some(package), vm.package_object(&class).unwrap());
+
+        // Classes that don't come from a JAR are in packages without attributes.
+        let string = vm.load_class("java/lang/String").unwrap();
+        let package = vm.package_object(&string).unwrap().unwrap();
+        assert_eq!(Some("java.lang".to_string()), string_field(&vm, &package, "name"));
+        assert_eq!(None, string_field(&vm, &package, "specificationTitle"));
+        assert_eq!(Value::Int(0), get_field(&package, "sealed").unwrap());
+        let array = vm.load_class("[Ljava/lang/String;").unwrap();
+        assert_eq!(None, vm.package_object(&array).unwrap());
+    }
+
+    #[test]
+    fn test_sealed_packages() {
+        let jars = |first: &str, second: &str| {
+            let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/jars/");
+            let mut vm = Vm::new();
+            vm.classpath_mut().push_path(format!("{}{}", dir, first)).unwrap();
+            vm.classpath_mut().push_path(format!("{}{}", dir, second)).unwrap();
+            vm
+        };
+
+        let mut vm = jars("sealed.jar", "split.jar");
+        vm.load_class("com/example/sealed/Sealed").unwrap();
+        let err = vm.load_class("com/example/sealed/Intruder").map(|class| class.name.clone()).unwrap_err();
+        assert_eq!(VmError::SealingViolation("sealing violation: package com.example.sealed is sealed".to_string()), err);
+        assert_eq!(Some("java/lang/SecurityException"), err.linkage_error().map(|(class, _)| class));
+
+        let mut vm = jars("split.jar", "sealed.jar");
+        vm.load_class("com/example/sealed/Intruder").unwrap();
+        assert_eq!(
+            Err(VmError::SealingViolation("sealing violation: can't seal package com.example.sealed: already loaded".to_string())),
+            vm.load_class("com/example/sealed/Sealed").map(|class| class.name.clone()));
+    }
+
+    // Puts the modular JARs under testdata/modules in the boot layer, with the outsider directory on
+    // the classpath too.
+    fn boot_modules(vm: &mut Vm) {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/modules/");
+        let mut modules = vec![];
+        for entry in ["app.jar", "api.jar", "outsider"] {
+            vm.classpath_mut().push_path(format!("{}{}", dir, entry)).unwrap();
+        }
+        for jar in ["app.jar", "api.jar"] {
+            let jar = JarSource::open(format!("{}{}", dir, jar)).unwrap();
+            modules.push((ModuleDescriptor::read(&jar).unwrap().unwrap(), LoaderId::APPLICATION));
+        }
+        vm.set_boot_layer(ModuleLayer::boot(modules).unwrap());
+    }
+
+    fn illegal_access(vm: &Vm, result: Result<Option<Value>, VmError>) -> String {
+        match result {
+            Err(VmError::UncaughtException(ref exception)) if exception.class().name == "java/lang/IllegalAccessError" => {
+                string_field(vm, exception, "detailMessage").unwrap()
+            },
+            other => panic!("Expected an IllegalAccessError; got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_modules_restrict_access_to_exported_packages() {
+        let mut vm = Vm::new();
+        boot_modules(&mut vm);
+        assert_eq!(Some("com.example.app"), vm.boot_layer().module_of(LoaderId::APPLICATION, "com/example/app/Main"));
+        assert_eq!(Ok(Some(Value::Int(42))), vm.invoke_static("com/example/app/Main", "useApi", "()I", vec![]));
+        assert_eq!(Ok(Some(Value::Int(42))), vm.invoke_static("Outsider", "useApi", "()I", vec![]));
+
+        let result = vm.invoke_static("com/example/app/Main", "useInternals", "()I", vec![]);
+        assert_eq!(
+            "class com.example.app.Main (in module com.example.app) cannot access class com.example.api.internal.Hidden (in module com.example.api) \
+             because module com.example.api does not export com.example.api.internal to module com.example.app",
+            illegal_access(&vm, result));
+        let result = vm.invoke_static("Outsider", "useInternals", "()I", vec![]);
+        assert!(illegal_access(&vm, result).ends_with("does not export com.example.api.internal to the unnamed module"));
+    }
+
+    #[test]
+    fn test_everything_open_skips_module_checks() {
+        let mut vm = Vm::new();
+        boot_modules(&mut vm);
+        vm.set_everything_open(true);
+        assert_eq!(Ok(Some(Value::Int(7))), vm.invoke_static("com/example/app/Main", "useInternals", "()I", vec![]));
+        assert_eq!(Ok(Some(Value::Int(7))), vm.invoke_static("Outsider", "useInternals", "()I", vec![]));
+    }
+
+    struct RenamingSource;
+
+    impl ClassSource for RenamingSource {
+        fn find_resource(&self, _: &str) -> Result<Option<Vec<u8>>, ClasspathError> {
+            Ok(Some(fixture!("Completes").to_vec()))
+        }
+    }
+
+    #[test]
+    fn test_classpath_class_must_have_requested_name() {
+        let mut vm = Vm::new();
+        vm.classpath_mut().push(RenamingSource);
+        let expected = ClasspathError::WrongName{expected: "Other".to_string(), found: "Completes".to_string()};
+        assert_eq!(Err(VmError::Classpath(expected)), vm.load_class("Other").map(|class| class.name.clone()));
+    }
+
+    // Two sibling loaders under the application loader, each given its own copy of the classes.
+    fn sibling_loaders(vm: &mut Vm, classes: &[&[u8]]) -> (LoaderId, LoaderId) {
+        let first = vm.new_class_loader("first", LoaderId::APPLICATION);
+        let second = vm.new_class_loader("second", LoaderId::APPLICATION);
+        for class in classes {
+            vm.add_class_to(first, class).unwrap();
+            vm.add_class_to(second, class).unwrap();
+        }
+        (first, second)
+    }
+
+    #[test]
+    fn test_loaders_have_separate_namespaces() {
+        let mut vm = Vm::new();
+        let (first, second) = sibling_loaders(&mut vm, &[fixture!("Vehicle"), fixture!("Car"), fixture!("Hatchback")]);
+        let first_car = vm.load_class_with(first, "Hatchback").unwrap();
+        let second_car = vm.load_class_with(second, "Hatchback").unwrap();
+        assert!(!Rc::ptr_eq(&first_car, &second_car));
+        assert_eq!(first, first_car.loader);
+        assert_eq!(first, first_car.super_class.as_ref().unwrap().loader);
+        assert!(!first_car.is_subclass_of(&vm.load_class_with(second, "Vehicle").unwrap()));
+        assert!(first_car.is_subclass_of(&vm.load_class_with(first, "Vehicle").unwrap()));
+
+        // Arrays belong to their component's loader.
+        let array = vm.load_class_with(second, "[LHatchback;").unwrap();
+        assert_eq!(second, array.loader);
+        assert_eq!(LoaderId::BOOTSTRAP, vm.load_class_with(first, "[I").unwrap().loader);
+        assert_eq!(Err(VmError::ClassNotFound("Hatchback".to_string())), vm.load_class("Hatchback").map(|_| ()));
+    }
+
+    #[test]
+    fn test_loaders_delegate_to_parent_first() {
+        let mut vm = Vm::new();
+        let child = vm.new_class_loader("child", LoaderId::APPLICATION);
+        vm.add_class_to(child, fixture!("Completes")).unwrap();
+        vm.add_class(fixture!("Completes")).unwrap();
+        let class = vm.load_class_with(child, "Completes").unwrap();
+        assert_eq!(LoaderId::APPLICATION, class.loader);
+        assert!(Rc::ptr_eq(&class, &vm.find_loaded_class(child, "Completes").unwrap()));
+        assert_eq!(LoaderId::BOOTSTRAP, vm.load_class_with(child, "java/lang/String").unwrap().loader);
+        assert!(vm.find_loaded_class(LoaderId::PLATFORM, "Completes").is_none());
+        assert_eq!(Err(VmError::ClassNotFound("Missing".to_string())), vm.load_class_with(child, "Missing").map(|_| ()));
+    }
+
+    #[test]
+    fn test_class_circularity() {
+        let mut vm = vm_with(&[fixture!("linkage/Circle1"), fixture!("linkage/changed/Circle2")]);
+        assert_eq!(Err(VmError::ClassCircularity("Circle1".to_string())), vm.load_class("Circle1").map(|_| ()));
+
+        // Circularity through a classpath, where the class can be found again.
+        let mut vm = Vm::new();
+        vm.set_classpath(Classpath::parse(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/linkage/changed")).unwrap());
+        vm.add_class(fixture!("linkage/Circle1")).unwrap();
+        assert_eq!(Err(VmError::ClassCircularity("Circle2".to_string())), vm.load_class("Circle2").map(|_| ()));
+    }
+
+    #[test]
+    fn test_invalid_supertypes() {
+        let mut vm = vm_with(&[fixture!("linkage/Derived"), fixture!("linkage/changed/Base")]);
+        assert_eq!(Err(VmError::IncompatibleClassChange("class Derived has interface Base as super class".to_string())), vm.load_class("Derived").map(|_| ()));
+
+        let mut vm = vm_with(&[fixture!("linkage/Party"), fixture!("linkage/changed/Contract")]);
+        assert_eq!(
+            Err(VmError::IncompatibleClassChange("class Party can not implement Contract, because it is not an interface".to_string())),
+            vm.load_class("Party").map(|_| ()));
+
+        let mut vm = vm_with(&[fixture!("linkage/Child"), fixture!("linkage/changed/Sealed")]);
+        assert_eq!(Err(VmError::Verification("Cannot inherit from final class Sealed".to_string())), vm.load_class("Child").map(|_| ()));
+    }
+
+    #[test]
+    fn test_legacy_classes_are_verified_when_linked() {
+        let mut vm = vm_with(&[fixture!("Legacy"), fixture!("Unverifiable")]);
+        assert_eq!(Some(Value::Int(10)), vm.invoke_static("Legacy", "finallyIncrements", "(I)I", vec![Value::Int(5)]).unwrap());
+        assert_eq!(Some(Value::Int(3)), vm.invoke_static("Legacy", "twoCallers", "()I", vec![]).unwrap());
+        assert_eq!(Some(Value::Int(2)), vm.invoke_static("Legacy", "nestedSubroutines", "()I", vec![]).unwrap());
+
+        let class = vm.load_class("Unverifiable").unwrap();
+        let error = vm.link(&class).unwrap_err();
+        let (exception, message) = error.linkage_error().unwrap();
+        assert_eq!("java/lang/VerifyError", exception);
+        assert!(message.starts_with("Bad local variable type\nException Details:\n  Location:\n    Unverifiable.loadsFloatAsInt()I @2: iload_0\n"), "{}", message);
+        assert!(!class.is_linked());
+    }
+
+    #[test]
+    fn test_verification_can_be_turned_off() {
+        let mut vm = vm_with(&[fixture!("Unverifiable")]);
+        vm.set_verify_mode(VerifyMode::None);
+        let class = vm.load_class("Unverifiable").unwrap();
+        assert_eq!(Ok(()), vm.link(&class));
+
+        // Loaders can override the mode for their own classes.
+        let mut vm = vm_with(&[fixture!("Unverifiable")]);
+        vm.set_verify_mode(VerifyMode::None);
+        vm.class_loader_mut(LoaderId::APPLICATION).unwrap().set_verify(Some(true));
+        let class = vm.load_class("Unverifiable").unwrap();
+        assert!(matches!(vm.link(&class), Err(VmError::Inference(_))));
+
+        let mut vm = Vm::new();
+        let trusted = vm.new_class_loader("trusted", LoaderId::APPLICATION);
+        vm.class_loader_mut(trusted).unwrap().set_verify(Some(false));
+        vm.add_class_to(trusted, fixture!("Unverifiable")).unwrap();
+        let class = vm.load_class_with(trusted, "Unverifiable").unwrap();
+        assert_eq!(Ok(()), vm.link(&class));
+        assert!(vm.verifies(LoaderId::APPLICATION));
+        assert!(!vm.verifies(LoaderId::BOOTSTRAP));
+    }
+
+    #[test]
+    fn test_final_methods_cannot_be_overridden() {
+        let mut vm = vm_with(&[fixture!("linkage/Polite"), fixture!("linkage/Greeter")]);
+        assert!(vm.load_class("Polite").is_ok());
+
+        let mut vm = vm_with(&[fixture!("linkage/Polite"), fixture!("linkage/changed/Greeter")]);
+        let error = vm.load_class("Polite").map(|_| ()).unwrap_err();
+        assert_eq!(
+            VmError::Structure(StructureError::FinalMethodOverridden{class: "Greeter".to_string(), method: "greet()Ljava/lang/String;".to_string()}),
+            error);
+        assert_eq!(Some(("java/lang/VerifyError", "Overrides final method Greeter.greet()Ljava/lang/String;".to_string())), error.linkage_error());
+    }
+
+    #[test]
+    fn test_instructions_are_checked_when_first_run() {
+        // Replace square's imul with an opcode that doesn't exist.
+        let mut bytes = fixture!("Debuggee").to_vec();
+        let square = [0x1a, 0x1a, 0x68, 0x3c, 0x1b, 0xac];
+        let at = bytes.windows(square.len()).position(|window| window == square).unwrap();
+        bytes[at + 2] = 0xcb;
+
+        let mut vm = vm_with(&[&bytes]);
+        let class = vm.load_class("Debuggee").unwrap();
+        assert_eq!(Ok(()), vm.link(&class));
+        let error = vm.invoke_static("Debuggee", "square", "(I)I", vec![Value::Int(3)]).unwrap_err();
+        assert!(matches!(error, VmError::Structure(StructureError::Bytecode{ref method, ..}) if method == "square(I)I"), "{:?}", error);
+        assert_eq!("java/lang/ClassFormatError", error.linkage_error().unwrap().0);
+        assert_eq!(0, vm.executed_instructions());
+
+        // Later calls fail the same way without checking the method again.
+        let square = class.class.methods.iter().position(|method| class.class.utf8(&method.name) == Ok("square")).unwrap();
+        let VmError::Structure(cause) = error else { unreachable!() };
+        assert_eq!(Err(cause.clone()), class.verified_method(square, || panic!("The method was checked again")));
+        assert_eq!(Err(VmError::Structure(cause)), vm.invoke_static("Debuggee", "square", "(I)I", vec![Value::Int(3)]));
+
+        // Trusted classes aren't checked.
+        let mut vm = vm_with(&[&bytes]);
+        vm.set_verify_mode(VerifyMode::None);
+        let result = vm.invoke_static("Debuggee", "square", "(I)I", vec![Value::Int(3)]);
+        assert!(!matches!(result, Err(VmError::Structure(_))), "{:?}", result);
+        assert!(vm.executed_instructions() > 0);
+    }
+
+    #[test]
+    fn test_define_class_does_not_delegate() {
+        let mut vm = vm_with(&[fixture!("Completes")]);
+        let loaded = vm.load_class("Completes").unwrap();
+        let child = vm.new_class_loader("child", LoaderId::APPLICATION);
+        let defined = vm.define_class(child, Some("Completes"), fixture!("Completes")).unwrap();
+        assert_eq!(child, defined.loader);
+        assert!(!loaded.is_same_class(&defined));
+        assert!(Rc::ptr_eq(&defined, &vm.load_class_with(child, "Completes").unwrap()));
+
+        assert_eq!(Err(VmError::DuplicateClass{loader: child, name: "Completes".to_string()}), vm.define_class(child, None, fixture!("Completes")).map(|_| ()));
+        let other = vm.new_class_loader("other", LoaderId::APPLICATION);
+        assert_eq!(
+            Err(VmError::Classpath(ClasspathError::WrongName{expected: "Other".to_string(), found: "Completes".to_string()})),
+            vm.define_class(other, Some("Other"), fixture!("Completes")).map(|_| ()));
+    }
+
+    #[test]
+    fn test_run_main_exits() {
+        let mut vm = vm_with(MAINS);
+        let outcome = vm.run_main("Exits", &["a", "b"]);
+        assert_eq!(Ok(Outcome::Exited(42)), outcome);
+        assert_eq!(42, outcome.unwrap().exit_code());
+        assert_eq!(0, vm.stack_depth());
+
+        // Exiting doesn't run finally blocks.
+        let class = vm.load_class("Exits").unwrap();
+        assert_eq!(Value::Int(0), class.static_value("finallyRan").unwrap());
+    }
+
+    #[test]
+    fn test_run_main_reports_uncaught_throwable() {
+        let throwable = uncaught(vm_with(MAINS).run_main("Throws", &[]));
+        assert_eq!("java.lang.RuntimeException", throwable.class_name);
+        assert_eq!(Some("wrapped".to_string()), throwable.message);
+        assert_eq!("java/lang/RuntimeException", throwable.throwable.class().name);
+
+        let cause = throwable.cause.expect("Expected a cause");
+        assert_eq!("java.lang.ArithmeticException", cause.class_name);
+        assert_eq!(Some("/ by zero".to_string()), cause.message);
+        assert_eq!(None, cause.cause);
+    }
+
+    #[test]
+    fn test_uncaught_throwable_stack_trace() {
+        let throwable = uncaught(vm_with(MAINS).run_main("Throws", &[]));
+        let expected = "java.lang.RuntimeException: wrapped\n\
+                        \tat Throws.main(Mains.java:26)\n\
+                        Caused by: java.lang.ArithmeticException: / by zero\n\
+                        \tat Throws.divide(Mains.java:31)\n\
+                        \tat Throws.main(Mains.java:24)";
+        assert_eq!(expected, throwable.to_string());
+    }
+
+    #[test]
+    fn test_uncaught_handler_sees_exceptions_escaping_main() {
+        let mut vm = vm_with(MAINS);
+        let seen = Rc::new(RefCell::new(vec![]));
+        let recorded = Rc::clone(&seen);
+        vm.set_uncaught_handler(Some(Box::new(move |thread: &str, throwable: &UncaughtThrowable| {
+            recorded.borrow_mut().push((thread.to_string(), throwable.to_string()));
+        })));
+        let throwable = uncaught(vm.run_main("Throws", &[]));
+        assert_eq!(vec![("main".to_string(), throwable.to_string())], *seen.borrow());
+
+        vm.set_uncaught_handler(None);
+        assert!(!vm.has_uncaught_handler());
+        uncaught(vm.run_main("Throws", &[]));
+        assert_eq!(1, seen.borrow().len());
+    }
+
+    #[test]
+    fn test_stack_trace_excludes_exception_constructor() {
+        let mut vm = Vm::new();
+        let exception = match vm.throw_new("java/lang/NullPointerException") {
+            VmError::UncaughtException(exception) => exception,
+            other => panic!("Expected an exception; got {:?}", other),
+        };
+        assert_eq!(Ok(vec![]), UncaughtThrowable::from_object(&vm, &exception).map(|throwable| throwable.stack_trace));
+    }
+
+    const LIMITED: &[&[u8]] = &[
+        fixture!("Spins"), fixture!("Allocates"), fixture!("AllocatesHugeArray"), fixture!("LoadsClasses"),
+        fixture!("First"), fixture!("Second"), fixture!("Third"), fixture!("CatchesEverything"),
+    ];
+
+    #[test]
+    fn test_instruction_limit_stops_infinite_loop() {
+        let mut vm = vm_with(LIMITED);
+        vm.set_limits(Limits {max_instructions: Some(10_000), ..Limits::default()});
+        assert_eq!(Ok(Outcome::LimitExceeded(LimitViolation::Instructions(10_000))), vm.run_main("Spins", &[]));
+        assert_eq!(10_001, vm.executed_instructions());
+        assert_eq!(0, vm.stack_depth());
+    }
+
+    #[test]
+    fn test_limit_violations_cannot_be_caught() {
+        let mut vm = vm_with(LIMITED);
+        vm.set_limits(Limits {max_instructions: Some(10_000), ..Limits::default()});
+        assert_eq!(Ok(Outcome::LimitExceeded(LimitViolation::Instructions(10_000))), vm.run_main("CatchesEverything", &[]));
+    }
+
+    #[test]
+    fn test_duration_limit_stops_infinite_loop() {
+        let limit = Duration::from_millis(20);
+        let mut vm = vm_with(LIMITED);
+        vm.set_limits(Limits {max_duration: Some(limit), ..Limits::default()});
+        assert_eq!(Ok(Outcome::LimitExceeded(LimitViolation::Duration(limit))), vm.run_main("Spins", &[]));
+    }
+
+    #[test]
+    fn test_allocation_limit_stops_allocation_loop() {
+        let mut vm = vm_with(LIMITED);
+        vm.set_limits(Limits {max_allocated_bytes: Some(1_000_000), ..Limits::default()});
+        assert_eq!(Ok(Outcome::LimitExceeded(LimitViolation::AllocatedBytes(1_000_000))), vm.run_main("Allocates", &[]));
+        assert!(vm.allocated_bytes() > 1_000_000);
+        assert!(vm.allocated_bytes() < 1_000_100);
+    }
+
+    #[test]
+    fn test_allocation_limit_stops_large_array() {
+        let mut vm = vm_with(LIMITED);
+        vm.set_limits(Limits {max_allocated_bytes: Some(1_000_000), ..Limits::default()});
+        let outcome = vm.run_main("AllocatesHugeArray", &[]);
+        assert_eq!(Ok(Outcome::LimitExceeded(LimitViolation::AllocatedBytes(1_000_000))), outcome);
+        assert_eq!(1, outcome.unwrap().exit_code());
+    }
+
+    #[test]
+    fn test_loaded_class_limit() {
+        let mut vm = vm_with(LIMITED);
+        assert_eq!(Ok(Outcome::Completed), vm.run_main("LoadsClasses", &[]));
+        let needed = vm.loaded_class_count();
+
+        let mut vm = vm_with(LIMITED);
+        vm.set_limits(Limits {max_loaded_classes: Some(needed - 1), ..Limits::default()});
+        assert_eq!(Ok(Outcome::LimitExceeded(LimitViolation::LoadedClasses(needed - 1))), vm.run_main("LoadsClasses", &[]));
+        let mut vm = vm_with(LIMITED);
+        vm.set_limits(Limits {max_loaded_classes: Some(needed), ..Limits::default()});
+        assert_eq!(Ok(Outcome::Completed), vm.run_main("LoadsClasses", &[]));
+    }
+
+    #[test]
+    fn test_set_limits_resets_usage() {
+        let mut vm = vm_with(LIMITED);
+        vm.run_main("LoadsClasses", &[]).unwrap();
+        assert!(vm.executed_instructions() > 0);
+        assert!(vm.allocated_bytes() > 0);
+
+        vm.set_limits(Limits {max_instructions: Some(5), ..Limits::default()});
+        assert_eq!(0, vm.executed_instructions());
+        assert_eq!(0, vm.allocated_bytes());
+        assert_eq!(Some(5), vm.limits().max_instructions);
+    }
+
+
+    fn label(vm: &Vm, object: &ObjectRef) -> String {
+        match object.field("label") {
+            Some(Value::Reference(Some(label))) => vm.read_string(&label).unwrap(),
+            other => panic!("Expected a label; got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reachable_objects() {
+        let mut vm = vm_with(&[fixture!("Graph")]);
+        vm.invoke_static("Graph", "keepChain", "()V", vec![]).unwrap();
+        let graphs: Vec<ObjectRef> = vm.reachable_objects().filter(|object| object.class().name == "Graph").collect();
+        assert_eq!(vec!["first", "second"], graphs.iter().map(|graph| label(&vm, graph)).collect::<Vec<_>>());
+
+        let fields = graphs[0].named_fields();
+        assert_eq!(vec!["next", "label"], fields.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>());
+        assert_eq!(Value::Reference(Some(graphs[1].clone())), fields[0].1);
+        assert!(graphs[0].references().contains(&graphs[1]));
+    }
+
+    #[test]
+    fn test_unreachable_objects_are_leaked_cycles() {
+        let mut vm = vm_with(&[fixture!("Graph")]);
+        vm.invoke_static("Graph", "keepChain", "()V", vec![]).unwrap();
+        assert!(vm.unreachable_objects().is_empty());
+        vm.invoke_static("Graph", "leakCycle", "()V", vec![]).unwrap();
+        let leaked = vm.unreachable_objects();
+        assert_eq!(vec!["a", "b"], leaked.iter().map(|graph| label(&vm, graph)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_collect_garbage_frees_cycles() {
+        let mut vm = vm_with(&[fixture!("Graph")]);
+        vm.invoke_static("Graph", "leakCycle", "()V", vec![]).unwrap();
+        let stats = vm.collect_garbage(GcCause::Requested).clone();
+        assert!(vm.unreachable_objects().is_empty());
+        assert_eq!(0, stats.id);
+        assert!(stats.reclaimed_bytes() > 0);
+        assert_eq!(stats.objects_before - 2, stats.objects_after);
+        assert_eq!(stats.bytes_after, stats.young_bytes + stats.old_bytes);
+        assert_eq!(1, vm.gc_totals().collections);
+    }
+
+    #[test]
+    fn test_survivors_are_tenured() {
+        let mut vm = vm_with(&[fixture!("Graph")]);
+        vm.invoke_static("Graph", "keepChain", "()V", vec![]).unwrap();
+        let first = vm.collect_garbage(GcCause::Requested).clone();
+        assert!(first.young_bytes > 0);
+        let second = vm.collect_garbage(GcCause::Requested);
+        assert_eq!((0, first.bytes_after), (second.young_bytes, second.old_bytes));
+    }
+
+    #[test]
+    fn test_system_gc_notifies_listener() {
+        let mut vm = vm_with(&[fixture!("Graph")]);
+        let collections = Rc::new(RefCell::new(vec![]));
+        let seen = Rc::clone(&collections);
+        vm.set_gc_listener(Some(Box::new(move |stats: &GcStats| seen.borrow_mut().push(stats.clone()))));
+        vm.invoke_static("Graph", "leakAndCollect", "()V", vec![]).unwrap();
+        assert!(vm.unreachable_objects().is_empty());
+        let collections = collections.borrow();
+        assert_eq!(GcCause::SystemGc, collections[0].cause);
+        assert!(collections[0].to_string().starts_with("GC(0) Pause Full (System.gc())"));
+    }
+
+    #[test]
+    fn test_safepoint_operations_run_inside_java_code() {
+        let mut vm = vm_with(&[fixture!("Graph")]);
+        let depths = Rc::new(RefCell::new(vec![]));
+        let seen = Rc::clone(&depths);
+        vm.request_safepoint(Box::new(move |vm: &mut Vm| {
+            seen.borrow_mut().push(vm.stack_depth());
+            vm.collect_garbage(GcCause::Requested);
+        }));
+        vm.invoke_static("Graph", "keepChain", "()V", vec![]).unwrap();
+        assert_eq!(vec![1], *depths.borrow());
+        assert_eq!(1, vm.safepoint().completed());
+        assert_eq!(1, vm.gc_totals().collections);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::heap::ObjectRef;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+// Walks the object graph breadth first from a set of starting objects, yielding each reachable
+// object once, starting objects included. For embedders inspecting the heap, e.g. to check what
+// a test leaves behind; see also Vm::reachable_objects and Vm::unreachable_objects.
+pub struct HeapWalker {
+    queue: VecDeque<ObjectRef>,
+    seen: HashSet<ObjectRef>,
+}
+
+impl HeapWalker {
+    pub fn new<I: IntoIterator<Item = ObjectRef>>(roots: I) -> HeapWalker {
+        let mut walker = HeapWalker {queue: VecDeque::new(), seen: HashSet::new()};
+        for root in roots {
+            walker.enqueue(root);
+        }
+        walker
+    }
+
+    fn enqueue(&mut self, object: ObjectRef) {
+        if self.seen.insert(object.clone()) {
+            self.queue.push_back(object);
+        }
+    }
+}
+
+impl Iterator for HeapWalker {
+    type Item = ObjectRef;
+
+    fn next(&mut self) -> Option<ObjectRef> {
+        let object = self.queue.pop_front()?;
+        for reference in object.references() {
+            self.enqueue(reference);
+        }
+        Some(object)
+    }
+}
+
+// The shortest chain of references leading from one object to another, including both ends, or
+// None if the target isn't reachable. Useful for finding out what's keeping an object alive.
+pub fn path_between(from: &ObjectRef, to: &ObjectRef) -> Option<Vec<ObjectRef>> {
+    let mut parents: HashMap<ObjectRef, Option<ObjectRef>> = HashMap::new();
+    let mut queue = VecDeque::new();
+    parents.insert(from.clone(), None);
+    queue.push_back(from.clone());
+
+    while let Some(object) = queue.pop_front() {
+        if object == *to {
+            let mut path = vec![object];
+            while let Some(Some(parent)) = parents.get(path.last().unwrap()) {
+                path.push(parent.clone());
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for reference in object.references() {
+            if !parents.contains_key(&reference) {
+                parents.insert(reference.clone(), Some(object.clone()));
+                queue.push_back(reference);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heap::{Heap, Storage};
+    use crate::runtime::Value;
+    use crate::vm::Vm;
+
+    // Allocates objects whose fields point at the given objects.
+    fn node(vm: &mut Vm, heap: &mut Heap, references: &[&ObjectRef]) -> ObjectRef {
+        let class = vm.load_class("java/lang/Object").unwrap();
+        let fields = references.iter().map(|&reference| Value::Reference(Some(reference.clone()))).collect();
+        heap.allocate(&class, Storage::Values(fields))
+    }
+
+    #[test]
+    fn test_walk_visits_each_object_once() {
+        let (mut vm, mut heap) = (Vm::new(), Heap::new());
+        let leaf = node(&mut vm, &mut heap, &[]);
+        let left = node(&mut vm, &mut heap, &[&leaf]);
+        let right = node(&mut vm, &mut heap, &[&leaf]);
+        let root = node(&mut vm, &mut heap, &[&left, &right]);
+        assert_eq!(vec![root.clone(), left, right, leaf], HeapWalker::new(vec![root]).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_path_between() {
+        let (mut vm, mut heap) = (Vm::new(), Heap::new());
+        let leaf = node(&mut vm, &mut heap, &[]);
+        let middle = node(&mut vm, &mut heap, &[&leaf]);
+        let root = node(&mut vm, &mut heap, &[&leaf, &middle]);
+        assert_eq!(Some(vec![root.clone(), leaf.clone()]), path_between(&root, &leaf));
+        assert_eq!(Some(vec![root.clone()]), path_between(&root, &root));
+        assert_eq!(None, path_between(&leaf, &root));
+    }
+}
@@ -0,0 +1,275 @@
+use crate::inflate::{self, InflateError};
+use std::collections::HashMap;
+use std::{error, fmt};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_SIGNATURE: u32 = 0x06054b50;
+const END_SIZE: usize = 22;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+// A ZIP archive held in memory, such as a JAR. Entries are found through the central directory at
+// the end of the archive, and decompressed when they're read.
+pub struct ZipArchive {
+    data: Vec<u8>,
+    entries: Vec<ZipEntry>,
+    by_name: HashMap<String, usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZipEntry {
+    pub name: String,
+    pub method: u16,
+    pub crc32: u32,
+    pub compressed_size: u32,
+    pub size: u32,
+    header_offset: u32,
+}
+
+impl ZipEntry {
+    pub fn is_directory(&self) -> bool {
+        self.name.ends_with('/')
+    }
+}
+
+impl ZipArchive {
+    pub fn new(data: Vec<u8>) -> Result<ZipArchive, ZipError> {
+        // The end record is followed only by a comment of up to 64KiB, so it's found by searching
+        // backwards from the end.
+        let search_start = data.len().saturating_sub(END_SIZE + 0xffff);
+        let end = (search_start..=data.len().saturating_sub(END_SIZE)).rev()
+            .find(|&offset| u32_at(&data, offset) == Some(END_SIGNATURE))
+            .ok_or(ZipError::NotAnArchive)?;
+        let count = u16_at(&data, end + 10).ok_or(ZipError::Truncated)? as usize;
+        let directory_offset = u32_at(&data, end + 16).ok_or(ZipError::Truncated)?;
+        if count == 0xffff || directory_offset == 0xffffffff {
+            return Err(ZipError::Unsupported("ZIP64 archives".to_string()));
+        }
+
+        // Each header takes at least 46 bytes, which bounds how many entries there can really be.
+        let mut entries = Vec::with_capacity(count.min(data.len().saturating_sub(directory_offset as usize) / 46));
+        let mut offset = directory_offset as usize;
+        for _ in 0..count {
+            if u32_at(&data, offset) != Some(CENTRAL_HEADER_SIGNATURE) {
+                return Err(ZipError::InvalidHeader(offset));
+            }
+            let field = |at| u16_at(&data, offset + at).ok_or(ZipError::Truncated);
+            let name_length = field(28)? as usize;
+            let extra_length = field(30)? as usize;
+            let comment_length = field(32)? as usize;
+            let name = data.get(offset + 46..offset + 46 + name_length).ok_or(ZipError::Truncated)?;
+            entries.push(ZipEntry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                method: field(10)?,
+                crc32: u32_at(&data, offset + 16).ok_or(ZipError::Truncated)?,
+                compressed_size: u32_at(&data, offset + 20).ok_or(ZipError::Truncated)?,
+                size: u32_at(&data, offset + 24).ok_or(ZipError::Truncated)?,
+                header_offset: u32_at(&data, offset + 42).ok_or(ZipError::Truncated)?,
+            });
+            offset += 46 + name_length + extra_length + comment_length;
+        }
+
+        // Should a name appear twice, the first entry wins, as with java.util.zip.
+        let mut by_name = HashMap::with_capacity(entries.len());
+        for (index, entry) in entries.iter().enumerate() {
+            by_name.entry(entry.name.clone()).or_insert(index);
+        }
+        Ok(ZipArchive {data, entries, by_name})
+    }
+
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    pub fn entry(&self, name: &str) -> Option<&ZipEntry> {
+        self.by_name.get(name).map(|&index| &self.entries[index])
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.by_name.contains_key(name)
+    }
+
+    // Reads and decompresses the named entry, or returns None if there's no such entry.
+    pub fn read(&self, name: &str) -> Result<Option<Vec<u8>>, ZipError> {
+        match self.entry(name) {
+            Some(entry) => self.read_entry(entry).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn read_entry(&self, entry: &ZipEntry) -> Result<Vec<u8>, ZipError> {
+        // The local header repeats the name but may have a different extra field, so the data's
+        // offset comes from the local header's own lengths.
+        let offset = entry.header_offset as usize;
+        if u32_at(&self.data, offset) != Some(LOCAL_HEADER_SIGNATURE) {
+            return Err(ZipError::InvalidHeader(offset));
+        }
+        let flags = u16_at(&self.data, offset + 6).ok_or(ZipError::Truncated)?;
+        if flags & 1 != 0 {
+            return Err(ZipError::Unsupported(format!("encrypted entry {}", entry.name)));
+        }
+        let name_length = u16_at(&self.data, offset + 26).ok_or(ZipError::Truncated)? as usize;
+        let extra_length = u16_at(&self.data, offset + 28).ok_or(ZipError::Truncated)? as usize;
+        let start = offset + 30 + name_length + extra_length;
+        let compressed = self.data.get(start..start + entry.compressed_size as usize).ok_or(ZipError::Truncated)?;
+
+        let contents = match entry.method {
+            STORED => compressed.to_vec(),
+            DEFLATED => inflate::inflate(compressed, entry.size as usize)?,
+            method => return Err(ZipError::Unsupported(format!("compression method {}", method))),
+        };
+        if contents.len() != entry.size as usize || crc32(&contents) != entry.crc32 {
+            return Err(ZipError::Corrupt(entry.name.clone()));
+        }
+        Ok(contents)
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    update_crc32(0, data)
+}
+
+// Updates a CRC-32 with more data. A new CRC starts at 0.
+pub fn update_crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb88320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ZipError {
+    NotAnArchive,
+    Truncated,
+    InvalidHeader(usize), // The offset of a header without the expected signature.
+    Corrupt(String), // The named entry's size or checksum is wrong.
+    Inflate(InflateError),
+    Unsupported(String),
+}
+
+impl std::convert::From<InflateError> for ZipError {
+    fn from(cause: InflateError) -> ZipError {
+        ZipError::Inflate(cause)
+    }
+}
+
+impl fmt::Display for ZipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ZipError::NotAnArchive => write!(f, "Not a ZIP archive"),
+            ZipError::Truncated => write!(f, "Archive is truncated"),
+            ZipError::InvalidHeader(offset) => write!(f, "Invalid header at offset {}", offset),
+            ZipError::Corrupt(ref name) => write!(f, "Entry {} is corrupt", name),
+            ZipError::Inflate(ref cause) => write!(f, "Failed to decompress entry: {}", cause),
+            ZipError::Unsupported(ref feature) => write!(f, "Unsupported feature: {}", feature),
+        }
+    }
+}
+
+impl error::Error for ZipError {
+    fn description(&self) -> &str {
+        match *self {
+            ZipError::NotAnArchive => "Not a ZIP archive",
+            ZipError::Truncated => "Archive is truncated",
+            ZipError::InvalidHeader(_) => "Invalid header",
+            ZipError::Corrupt(_) => "Corrupt entry",
+            ZipError::Inflate(_) => "Failed to decompress entry",
+            ZipError::Unsupported(ref feature) => feature,
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            ZipError::Inflate(ref cause) => Some(cause),
+            ZipError::NotAnArchive => None,
+            ZipError::Truncated => None,
+            ZipError::InvalidHeader(_) => None,
+            ZipError::Corrupt(_) => None,
+            ZipError::Unsupported(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds an archive of stored entries.
+    fn archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let (mut data, mut directory) = (vec![], vec![]);
+        for &(name, contents) in entries {
+            // The fields that the local and central headers share, from the flags to the name's length.
+            let mut common = vec![0; 8];
+            common.extend_from_slice(&crc32(contents).to_le_bytes());
+            common.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            common.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+
+            directory.extend_from_slice(&CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            directory.extend_from_slice(&[20, 0, 20, 0]);
+            directory.extend_from_slice(&common);
+            directory.extend_from_slice(&[0; 12]);
+            directory.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+
+            data.extend_from_slice(&LOCAL_HEADER_SIGNATURE.to_le_bytes());
+            data.extend_from_slice(&[20, 0]);
+            data.extend_from_slice(&common);
+            data.extend_from_slice(&[0, 0]);
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(contents);
+        }
+        let directory_offset = data.len() as u32;
+        data.extend_from_slice(&directory);
+        data.extend_from_slice(&END_SIGNATURE.to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        data.extend_from_slice(&directory_offset.to_le_bytes());
+        data.extend_from_slice(&[0, 0]);
+        data
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(0xcbf43926, crc32(b"123456789"));
+        assert_eq!(0, crc32(b""));
+    }
+
+    #[test]
+    fn test_read_stored_entries() {
+        let zip = ZipArchive::new(archive(&[("a/", b""), ("a/b.txt", b"hello"), ("c", b"world")])).unwrap();
+        assert_eq!(3, zip.entries().len());
+        assert!(zip.entry("a/").unwrap().is_directory());
+        assert_eq!(Ok(Some(b"hello".to_vec())), zip.read("a/b.txt"));
+        assert_eq!(Ok(Some(b"world".to_vec())), zip.read("c"));
+        assert_eq!(Ok(None), zip.read("d"));
+    }
+
+    #[test]
+    fn test_corrupt_entries_are_detected() {
+        let mut data = archive(&[("a", b"hello")]);
+        data[31] = b'j'; // The first byte of the contents.
+        assert_eq!(Err(ZipError::Corrupt("a".to_string())), ZipArchive::new(data).unwrap().read("a"));
+    }
+
+    #[test]
+    fn test_not_an_archive() {
+        assert_eq!(Err(ZipError::NotAnArchive), ZipArchive::new(b"hello".to_vec()).map(|_| ()));
+    }
+}
@@ -0,0 +1,50 @@
+public class Arithmetic {
+    static int divide(int a, int b) {
+        return a / b;
+    }
+
+    static int remainder(int a, int b) {
+        return a % b;
+    }
+
+    static long divideLong(long a, long b) {
+        return a / b;
+    }
+
+    static long remainderLong(long a, long b) {
+        return a % b;
+    }
+
+    static float divideFloat(float a, float b) {
+        return a / b;
+    }
+
+    static String catchDivideByZero() {
+        try {
+            divide(1, 0);
+        } catch (ArithmeticException e) {
+            return e.getMessage();
+        }
+        return null;
+    }
+
+    static String catchLongRemainderByZero() {
+        try {
+            remainderLong(1, 0);
+        } catch (RuntimeException e) {
+            return e.getMessage();
+        }
+        return null;
+    }
+
+    static int handlerSeesStateBeforeDivision(int divisor) {
+        int progress = 1;
+        try {
+            progress = 2;
+            progress = 10 / divisor;
+        } catch (ArithmeticException e) {
+            return progress;
+        }
+        return progress;
+    }
+}
@@ -0,0 +1,113 @@
+public class Arrays {
+    static int sumInts(int length) {
+        int[] values = new int[length];
+        for (int i = 0; i < values.length; i++) {
+            values[i] = i;
+        }
+
+        int sum = 0;
+        for (int i = 0; i < values.length; i++) {
+            sum += values[i];
+        }
+        return sum;
+    }
+
+    static long sumLongs() {
+        long[] values = new long[] {1L << 40, 2, 3};
+        return values[0] + values[1] + values[2];
+    }
+
+    static double sumDoubles() {
+        double[] values = new double[] {0.5, 0.25};
+        float[] floats = new float[] {0.125f};
+        return values[0] + values[1] + floats[0];
+    }
+
+    static int smallTypes() {
+        byte[] bytes = new byte[] {-1};
+        short[] shorts = new short[] {-2};
+        char[] chars = new char[] {'a'};
+        boolean[] booleans = new boolean[] {true};
+        return bytes[0] + shorts[0] + chars[0] + (booleans[0] ? 100 : 0);
+    }
+
+    static Object defaultElement() {
+        Object[] values = new Object[2];
+        return values[1];
+    }
+
+    static int grid() {
+        int[][] grid = new int[3][4];
+        grid[2][3] = 7;
+        return grid.length * 100 + grid[0].length * 10 + grid[2][3];
+    }
+
+    static boolean partialGrid() {
+        int[][] grid = new int[2][];
+        return grid[0] == null && grid[1] == null;
+    }
+
+    static int readPastEnd() {
+        int[] values = new int[3];
+        return values[3];
+    }
+
+    static void writeBeforeStart() {
+        String[] values = new String[3];
+        values[-1] = "";
+    }
+
+    static int negativeSize(int length) {
+        return new int[length].length;
+    }
+
+    static int negativeInnerSize(int length) {
+        return new int[0][length].length;
+    }
+
+    static int lengthOfNull() {
+        int[] values = null;
+        return values.length;
+    }
+
+    static void storeWrongType() {
+        Object[] values = new String[1];
+        values[0] = new Object();
+    }
+
+    static String readPastEndMessage() {
+        try {
+            readPastEnd();
+        } catch (ArrayIndexOutOfBoundsException e) {
+            return e.getMessage();
+        }
+        return null;
+    }
+
+    static String writeBeforeStartMessage() {
+        try {
+            writeBeforeStart();
+        } catch (IndexOutOfBoundsException e) {
+            return e.getMessage();
+        }
+        return null;
+    }
+
+    static String negativeSizeMessage() {
+        try {
+            negativeInnerSize(-3);
+        } catch (NegativeArraySizeException e) {
+            return e.getMessage();
+        }
+        return null;
+    }
+
+    static String storeWrongTypeMessage() {
+        try {
+            storeWrongType();
+        } catch (ArrayStoreException e) {
+            return e.getMessage();
+        }
+        return null;
+    }
+}
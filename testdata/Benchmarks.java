@@ -0,0 +1,77 @@
+// Mixes field types, so that its estimated size depends on how the fields are laid out.
+class Particle {
+    boolean alive;
+    byte kind;
+    long id;
+    int charge;
+    double mass;
+    Particle next;
+}
+
+// Workloads for benches/interpreter.rs.
+public class Benchmarks {
+    static int sumOfSquares(int n) {
+        int total = 0;
+        for (int i = 0; i < n; i++) {
+            total += i * i;
+        }
+        return total;
+    }
+
+    static long collatzSteps(int limit) {
+        long steps = 0;
+        for (int start = 1; start < limit; start++) {
+            long value = start;
+            while (value != 1) {
+                value = (value & 1) == 0 ? value >> 1 : 3 * value + 1;
+                steps++;
+            }
+        }
+        return steps;
+    }
+
+    static double leibnizPi(int terms) {
+        double sum = 0;
+        double sign = 1;
+        for (int i = 0; i < terms; i++) {
+            sum += sign / (2 * i + 1);
+            sign = -sign;
+        }
+        return 4 * sum;
+    }
+
+    static int sieve(int n) {
+        boolean[] composite = new boolean[n + 1];
+        int primes = 0;
+        for (int i = 2; i <= n; i++) {
+            if (!composite[i]) {
+                primes++;
+                for (int j = i * 2; j <= n; j += i) {
+                    composite[j] = true;
+                }
+            }
+        }
+        return primes;
+    }
+
+    static int fib(int n) {
+        return n < 2 ? n : fib(n - 1) + fib(n - 2);
+    }
+
+    static long particles(int n) {
+        Particle head = null;
+        for (int i = 0; i < n; i++) {
+            Particle particle = new Particle();
+            particle.id = i;
+            particle.charge = i % 3 - 1;
+            particle.next = head;
+            head = particle;
+        }
+
+        long total = 0;
+        for (Particle particle = head; particle != null; particle = particle.next) {
+            total += particle.id * particle.charge;
+        }
+        return total;
+    }
+}
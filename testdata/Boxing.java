@@ -0,0 +1,38 @@
+public class Boxing {
+    static boolean smallIntegersAreShared() {
+        Integer a = 127;
+        Integer b = 127;
+        Integer c = -128;
+        return a == b && c == Integer.valueOf(-128);
+    }
+
+    static boolean largeIntegersAreNot() {
+        Integer a = 128;
+        Integer b = 128;
+        return a != b && a.equals(b);
+    }
+
+    static boolean otherTypesAreCached() {
+        Long l = 5L;
+        Short s = (short) -7;
+        Byte b = (byte) 100;
+        Character c = 'x';
+        Boolean t = true;
+        return l == Long.valueOf(5) && s == Short.valueOf((short) -7) && b == Byte.valueOf((byte) 100)
+            && c == Character.valueOf('x') && t == Boolean.TRUE;
+    }
+
+    static boolean wideCharactersAreNot() {
+        Character a = '\u00e9';
+        Character b = '\u00e9';
+        return a != b && a.equals(b);
+    }
+
+    static int unbox(Integer boxed, Long other) {
+        return boxed + (int) (long) other;
+    }
+
+    static int sum() {
+        return unbox(40, 2L);
+    }
+}
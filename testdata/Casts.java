@@ -0,0 +1,58 @@
+interface Shape {}
+
+interface Polygon extends Shape {}
+
+class Square implements Polygon {}
+
+class Circle implements Shape {}
+
+public class Casts {
+    static boolean isShape(Object o) {
+        return o instanceof Shape;
+    }
+
+    static boolean isPolygon(Object o) {
+        return o instanceof Polygon;
+    }
+
+    static boolean isSquare(Object o) {
+        return o instanceof Square;
+    }
+
+    static boolean isShapeArray(Object o) {
+        return o instanceof Shape[];
+    }
+
+    static boolean isObjectArray(Object o) {
+        return o instanceof Object[];
+    }
+
+    static boolean isIntArray(Object o) {
+        return o instanceof int[];
+    }
+
+    static boolean isCloneable(Object o) {
+        return o instanceof Cloneable;
+    }
+
+    static boolean isSerializable(Object o) {
+        return o instanceof java.io.Serializable;
+    }
+
+    static Object castToPolygon(Object o) {
+        return (Polygon) o;
+    }
+
+    static Object castToShapeArray(Object o) {
+        return (Shape[]) o;
+    }
+
+    static String castFailureMessage(Object o) {
+        try {
+            castToPolygon(o);
+        } catch (ClassCastException e) {
+            return e.getMessage();
+        }
+        return null;
+    }
+}
@@ -0,0 +1,75 @@
+import java.util.zip.Adler32;
+import java.util.zip.CRC32;
+import java.util.zip.Checksum;
+import java.util.zip.DataFormatException;
+import java.util.zip.Deflater;
+import java.util.zip.Inflater;
+
+// Exercises the java.util.zip checksums, Inflater and Deflater.
+public class Compression {
+    static long checksum(Checksum checksum, byte[] data) {
+        checksum.update(data, 0, 2);
+        checksum.update(data[2]);
+        checksum.update(data, 3, data.length - 3);
+        return checksum.getValue();
+    }
+
+    static long crc32(byte[] data) {
+        return checksum(new CRC32(), data);
+    }
+
+    static long adler32(byte[] data) {
+        return checksum(new Adler32(), data);
+    }
+
+    // Compresses the data, then inflates it again from a few bytes at a time.
+    static byte[] roundTrip(byte[] data, int level, boolean nowrap) throws DataFormatException {
+        Deflater deflater = new Deflater(level, nowrap);
+        deflater.setInput(data);
+        deflater.finish();
+        byte[] compressed = new byte[data.length * 2 + 64];
+        int length = 0;
+        while (!deflater.finished()) {
+            length += deflater.deflate(compressed, length, 7);
+        }
+        if (deflater.getBytesRead() != data.length || deflater.getBytesWritten() != length) {
+            return null;
+        }
+        int adler = deflater.getAdler();
+        deflater.end();
+
+        Inflater inflater = new Inflater(nowrap);
+        byte[] output = new byte[data.length];
+        int produced = 0;
+        int offset = 0;
+        while (!inflater.finished()) {
+            if (inflater.needsInput()) {
+                if (offset == length) {
+                    return null;
+                }
+                int chunk = length - offset < 5 ? length - offset : 5;
+                inflater.setInput(compressed, offset, chunk);
+                offset += chunk;
+            }
+            produced += inflater.inflate(output, produced, output.length - produced);
+        }
+        if (inflater.getBytesRead() != length || inflater.getAdler() != adler) {
+            return null;
+        }
+        inflater.end();
+        return output;
+    }
+
+    // Inflates a whole zlib stream, returning how many bytes come after it.
+    static int remaining(byte[] compressed, byte[] output) throws DataFormatException {
+        Inflater inflater = new Inflater();
+        inflater.setInput(compressed);
+        inflater.inflate(output);
+        return inflater.finished() ? inflater.getRemaining() : -1;
+    }
+
+    static void abandon() {
+        new Inflater();
+        new Deflater();
+    }
+}
@@ -0,0 +1,38 @@
+import java.io.IOException;
+
+// Reads and writes System.in, System.out and System.err.
+public class ConsoleIo {
+    public static void main(String[] args) {
+        System.out.println("Hello, World!");
+    }
+
+    static void printAll() {
+        System.out.print("a");
+        System.out.print(1);
+        System.out.print(-42L);
+        System.out.print(true);
+        System.out.print('c');
+        System.out.print(new char[] {'d', 'e'});
+        System.out.println();
+        System.out.println((String) null);
+        System.out.println(Integer.MIN_VALUE);
+        System.out.println("caf\u00e9");
+        System.err.println("to stderr");
+    }
+
+    // Copies stdin to stdout, returning the number of bytes copied.
+    static int echo() throws IOException {
+        byte[] buffer = new byte[4];
+        int total = 0;
+        int read;
+        while ((read = System.in.read(buffer)) != -1) {
+            System.out.write(buffer, 0, read);
+            total += read;
+        }
+        return total;
+    }
+
+    static int readByte() throws IOException {
+        return System.in.read();
+    }
+}
@@ -0,0 +1,18 @@
+public class Crashes {
+    public static int locked(Object lock) {
+        synchronized (lock) {
+            return 1;
+        }
+    }
+
+    public static int callsLocked() {
+        return locked(new Object()) + 1;
+    }
+
+    // Nothing binds it, so calling it is an internal error.
+    public static native int missing();
+
+    public static int callsMissing() {
+        return missing() + 1;
+    }
+}
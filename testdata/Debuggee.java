@@ -0,0 +1,14 @@
+public class Debuggee {
+    static int square(int x) {
+        int result = x * x;
+        return result;
+    }
+
+    static int sumOfSquares(int n) {
+        int total = 0;
+        for (int i = 1; i <= n; i++) {
+            total += square(i);
+        }
+        return total;
+    }
+}
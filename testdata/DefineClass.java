@@ -0,0 +1,64 @@
+// Defines classes from bytes passed in by the test, through ClassLoader.defineClass.
+public class DefineClass {
+    static class BytesLoader extends ClassLoader {
+        Class<?> define(String name, byte[] b, int off, int len) {
+            return defineClass(name, b, off, len);
+        }
+
+        Class<?> define(byte[] b) {
+            return define(null, b, 0, b.length);
+        }
+    }
+
+    public static String defineAndName(byte[] b) {
+        return new BytesLoader().define(b).getName();
+    }
+
+    // Each loader defines a class of its own.
+    public static boolean separateCopies(byte[] b) {
+        return new BytesLoader().define(b) != new BytesLoader().define(b);
+    }
+
+    public static void defineTwice(byte[] b) {
+        BytesLoader loader = new BytesLoader();
+        loader.define(b);
+        loader.define(b);
+    }
+
+    public static void defineWrongName(byte[] b) {
+        new BytesLoader().define("Wrong", b, 0, b.length);
+    }
+
+    public static void defineOutOfBounds(byte[] b) {
+        new BytesLoader().define(null, b, 1, b.length);
+    }
+
+    public static void defineGarbage(byte[] b) {
+        new BytesLoader().define(null, b, 0, 4);
+    }
+
+    static ClassLoader keptLoader;
+    static Class<?> keptClass;
+
+    // Defines a class with a new loader each time, and drops both.
+    public static void defineAndDrop(byte[] b, int times) {
+        for (int i = 0; i < times; i++) {
+            new BytesLoader().define(b);
+        }
+    }
+
+    public static void defineAndKeepLoader(byte[] b) {
+        BytesLoader loader = new BytesLoader();
+        loader.define(b);
+        keptLoader = loader;
+    }
+
+    public static void defineAndKeepClass(byte[] b) {
+        keptClass = new BytesLoader().define(b);
+    }
+
+    public static void release() {
+        keptLoader = null;
+        keptClass = null;
+    }
+}
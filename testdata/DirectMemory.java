@@ -0,0 +1,63 @@
+import java.nio.ByteBuffer;
+import sun.misc.Unsafe;
+
+public class DirectMemory {
+    static boolean unsafeRoundTrip() {
+        Unsafe unsafe = Unsafe.getUnsafe();
+        long address = unsafe.allocateMemory(16);
+        unsafe.setMemory(address, 16, (byte) 0);
+        unsafe.putLong(address, 0x0102030405060708L);
+        unsafe.putDouble(address + 8, 2.5);
+        address = unsafe.reallocateMemory(address, 32);
+        unsafe.copyMemory(address, address + 16, 16);
+        boolean matches = unsafe.getLong(address + 16) == 0x0102030405060708L && unsafe.getDouble(address + 24) == 2.5;
+        unsafe.freeMemory(address);
+        return matches;
+    }
+
+    static int bufferRoundTrip() {
+        ByteBuffer buffer = ByteBuffer.allocateDirect(8);
+        buffer.putInt(4, 0x01020304);
+        return buffer.get(4) + buffer.get(7) + buffer.getInt(4);
+    }
+
+    static void bufferOutOfBounds() {
+        ByteBuffer.allocateDirect(8).getInt(5);
+    }
+
+    static void useAfterFree() {
+        Unsafe unsafe = Unsafe.getUnsafe();
+        long address = unsafe.allocateMemory(8);
+        unsafe.freeMemory(address);
+        unsafe.getByte(address);
+    }
+
+    // Each buffer is dropped before the next is allocated.
+    static void dropBuffers() {
+        for (int i = 0; i < 100; i++) {
+            ByteBuffer.allocateDirect(1024).put(0, (byte) i);
+        }
+    }
+
+    static void holdBuffers() {
+        ByteBuffer[] buffers = new ByteBuffer[100];
+        for (int i = 0; i < buffers.length; i++) {
+            buffers[i] = ByteBuffer.allocateDirect(1024);
+        }
+    }
+
+    static void allocateTooMuch() {
+        Unsafe.getUnsafe().allocateMemory(1L << 50);
+    }
+
+    static void reallocateTooMuch() {
+        Unsafe unsafe = Unsafe.getUnsafe();
+        unsafe.reallocateMemory(unsafe.allocateMemory(8), Long.MAX_VALUE);
+    }
+
+    static void copyTooMuch() {
+        Unsafe unsafe = Unsafe.getUnsafe();
+        long address = unsafe.allocateMemory(8);
+        unsafe.copyMemory(address, address, Long.MAX_VALUE);
+    }
+}
@@ -0,0 +1,61 @@
+interface Named {
+    int id();
+}
+
+class Animal implements Named {
+    int legs() {
+        return 4;
+    }
+
+    public int id() {
+        return 1;
+    }
+}
+
+class Bird extends Animal {
+    int legs() {
+        return 2;
+    }
+}
+
+class Snake extends Animal {
+    int legs() {
+        return 0;
+    }
+
+    public int id() {
+        return 3;
+    }
+}
+
+public class Dispatch {
+    static int calls;
+
+    static int legs(Animal animal) {
+        calls++;
+        return animal.legs();
+    }
+
+    static int id(Named named) {
+        return named.id();
+    }
+
+    // Every call goes through the same call sites with receivers of different classes.
+    static int totalLegs() {
+        Animal[] animals = {new Animal(), new Bird(), new Snake(), new Bird(), new Animal()};
+        int total = 0;
+        for (Animal animal : animals) {
+            total += legs(animal);
+        }
+        return total;
+    }
+
+    static int sumOfIds() {
+        Named[] named = {new Snake(), new Animal(), new Bird(), new Snake()};
+        int total = 0;
+        for (Named n : named) {
+            total += id(n);
+        }
+        return total;
+    }
+}
@@ -0,0 +1,38 @@
+// Expressions that javac compiles to the dup family of instructions.
+public class Dups {
+    int intValue;
+    long longValue;
+    static long longCounter;
+    static double doubleCounter;
+
+    static int assignIntField(int value) {
+        Dups dups = new Dups();
+        int result = dups.intValue = value;
+        return result + dups.intValue;
+    }
+
+    static long assignLongField(long value) {
+        Dups dups = new Dups();
+        long result = dups.longValue = value;
+        return result + dups.longValue;
+    }
+
+    static long incrementLongStatic() {
+        longCounter = 40;
+        long first = ++longCounter;
+        return first + ++longCounter;
+    }
+
+    static double postIncrementDoubleStatic() {
+        doubleCounter = 1.5;
+        double before = doubleCounter++;
+        return before + doubleCounter;
+    }
+
+    static long incrementLongField() {
+        Dups dups = new Dups();
+        dups.longValue = 10;
+        long before = dups.longValue++;
+        return before * 100 + dups.longValue;
+    }
+}
@@ -0,0 +1,175 @@
+public class Exceptions {
+    static int counter;
+
+    static int catchExactType() {
+        try {
+            throw new Boom();
+        } catch (Boom e) {
+            return 1;
+        }
+    }
+
+    static int catchSuperclass() {
+        try {
+            throw new Boom();
+        } catch (RuntimeException e) {
+            return 2;
+        }
+    }
+
+    static int catchFromCallee() {
+        try {
+            throwBoom();
+            return 0;
+        } catch (Boom e) {
+            return 3;
+        }
+    }
+
+    static int catchFromDeepCallee() {
+        try {
+            recurseThenThrow(10);
+            return 0;
+        } catch (Boom e) {
+            return 4;
+        }
+    }
+
+    static int skipsNonMatchingHandler() {
+        try {
+            try {
+                throwBoom();
+            } catch (Bang e) {
+                return 0;
+            }
+        } catch (Boom e) {
+            return 5;
+        }
+        return -1;
+    }
+
+    static int firstMatchingHandlerWins() {
+        try {
+            throwBoom();
+        } catch (Boom e) {
+            return 6;
+        } catch (RuntimeException e) {
+            return 0;
+        }
+        return -1;
+    }
+
+    static int finallyRunsBeforePropagating() {
+        counter = 0;
+        try {
+            finallyThenThrow();
+        } catch (Boom e) {
+            return counter;
+        }
+        return -1;
+    }
+
+    static int rethrowFromHandler() {
+        try {
+            try {
+                throwBoom();
+            } catch (Boom e) {
+                throw new Bang();
+            }
+        } catch (Bang e) {
+            return 8;
+        }
+        return -1;
+    }
+
+    static int caughtExceptionIsTheThrownObject() {
+        Boom boom = new Boom();
+        try {
+            throw boom;
+        } catch (Boom e) {
+            if (e == boom) {
+                return 9;
+            }
+            return 0;
+        }
+    }
+
+    static int handlerClearsOperandStack() {
+        int result = 10;
+        try {
+            result = result + addThrowing(1, 2);
+        } catch (Boom e) {
+            return result;
+        }
+        return -1;
+    }
+
+    static int catchNullPointerFromFieldAccess() {
+        Holder holder = null;
+        try {
+            return holder.value;
+        } catch (NullPointerException e) {
+            return 11;
+        }
+    }
+
+    static int catchNullPointerFromInvocation() {
+        Holder holder = null;
+        try {
+            return holder.get();
+        } catch (NullPointerException e) {
+            return 12;
+        }
+    }
+
+    static int catchNullPointerFromThrowingNull() {
+        try {
+            throw null;
+        } catch (NullPointerException e) {
+            return 13;
+        }
+    }
+
+    static void uncaught() {
+        throwBoom();
+    }
+
+    static void uncaughtError() {
+        throw new Error();
+    }
+
+    static void throwBoom() {
+        throw new Boom();
+    }
+
+    static void recurseThenThrow(int depth) {
+        if (depth == 0) {
+            throw new Boom();
+        }
+        recurseThenThrow(depth - 1);
+    }
+
+    static void finallyThenThrow() {
+        try {
+            throwBoom();
+        } finally {
+            counter = 7;
+        }
+    }
+
+    static int addThrowing(int a, int b) {
+        throw new Boom();
+    }
+}
+
+class Boom extends RuntimeException {}
+
+class Bang extends RuntimeException {}
+
+class Holder {
+    int value;
+
+    int get() {
+        return value;
+    }
+}
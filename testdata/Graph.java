@@ -0,0 +1,26 @@
+public class Graph {
+    static Graph kept;
+
+    Graph next;
+    String label;
+
+    Graph(String label, Graph next) {
+        this.label = label;
+        this.next = next;
+    }
+
+    static void keepChain() {
+        kept = new Graph("first", new Graph("second", null));
+    }
+
+    static void leakCycle() {
+        Graph a = new Graph("a", null);
+        Graph b = new Graph("b", a);
+        a.next = b;
+    }
+
+    static void leakAndCollect() {
+        leakCycle();
+        System.gc();
+    }
+}
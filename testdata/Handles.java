@@ -0,0 +1,130 @@
+import java.lang.invoke.MethodHandle;
+import java.lang.invoke.MethodHandles;
+import java.lang.invoke.MethodType;
+
+// Exercises MethodHandles.Lookup, MethodType and calls through method handles.
+public class Handles {
+    private int count;
+    static long total;
+    final int fixed = 1;
+
+    public Handles() {}
+
+    Handles(int count) {
+        this.count = count;
+    }
+
+    static int add(int a, int b) {
+        return a + b;
+    }
+
+    private int secret() {
+        return 7;
+    }
+
+    public String describe() {
+        return "base";
+    }
+
+    static class Derived extends Handles {
+        public String describe() {
+            return "derived";
+        }
+
+        static String describeSuper() throws Throwable {
+            MethodType type = MethodType.methodType(String.class);
+            MethodHandle describe = MethodHandles.lookup().findSpecial(Handles.class, "describe", type, Derived.class);
+            return (String) describe.invokeExact(new Derived());
+        }
+    }
+
+    // Compiled for Java 8, so nested classes aren't nestmates, and this can't use the private
+    // members of Handles.
+    static class Outsider {
+        static Object findSecret() throws Throwable {
+            return MethodHandles.lookup().findVirtual(Handles.class, "secret", MethodType.methodType(int.class));
+        }
+
+        static Object findFinalSetter() throws Throwable {
+            return MethodHandles.lookup().findSetter(Handles.class, "fixed", int.class);
+        }
+    }
+
+    static MethodHandle add() throws Throwable {
+        return MethodHandles.lookup().findStatic(Handles.class, "add", MethodType.methodType(int.class, int.class, int.class));
+    }
+
+    static MethodHandle describeHandle() throws Throwable {
+        return MethodHandles.lookup().findVirtual(Handles.class, "describe", MethodType.methodType(String.class));
+    }
+
+    static boolean methodTypes() {
+        MethodType type = MethodType.methodType(int.class, int.class, int.class);
+        return type.toMethodDescriptorString().equals("(II)I") && type.parameterCount() == 2 && type.returnType() == int.class
+            && type.parameterType(1) == int.class && type.equals(MethodType.methodType(int.class, new Class<?>[] {int.class, int.class}))
+            && !type.equals(MethodType.methodType(long.class, int.class, int.class))
+            && MethodType.methodType(void.class, String[].class).toMethodDescriptorString().equals("([Ljava/lang/String;)V");
+    }
+
+    static int invokeExactly() throws Throwable {
+        return (int) add().invokeExact(2, 3);
+    }
+
+    static Object invokeWithWrongType() throws Throwable {
+        return (Object) add().invokeExact(2, 3);
+    }
+
+    static long invokeConverting() throws Throwable {
+        Object boxed = add().invoke(Integer.valueOf(2), (byte) 3);
+        long widened = (long) add().invoke(4, 5);
+        return (Integer) boxed + widened;
+    }
+
+    static Object invokeCastFailure() throws Throwable {
+        return describeHandle().invoke((Object) "not a Handles");
+    }
+
+    static String invokeVirtual(Handles receiver) throws Throwable {
+        return (String) describeHandle().invokeExact(receiver);
+    }
+
+    static int invokePrivate() throws Throwable {
+        MethodHandle secret = MethodHandles.lookup().findVirtual(Handles.class, "secret", MethodType.methodType(int.class));
+        return (int) secret.invokeExact(new Handles());
+    }
+
+    static int construct() throws Throwable {
+        MethodHandle constructor = MethodHandles.lookup().findConstructor(Handles.class, MethodType.methodType(void.class, int.class));
+        Handles handles = (Handles) constructor.invokeExact(6);
+        return handles.count;
+    }
+
+    static long fields() throws Throwable {
+        MethodHandles.Lookup lookup = MethodHandles.lookup();
+        MethodHandle getter = lookup.findGetter(Handles.class, "count", int.class);
+        MethodHandle setter = lookup.findSetter(Handles.class, "count", int.class);
+        MethodHandle staticGetter = lookup.findStaticGetter(Handles.class, "total", long.class);
+        MethodHandle staticSetter = lookup.findStaticSetter(Handles.class, "total", long.class);
+        Handles handles = new Handles();
+        setter.invokeExact(handles, 5);
+        staticSetter.invokeExact(100L);
+        return (int) getter.invokeExact(handles) + (long) staticGetter.invokeExact();
+    }
+
+    static Object invokeWithArguments() throws Throwable {
+        return add().invokeWithArguments(20, 22);
+    }
+
+    static Object findStatic(String name, Class<?> returnType) throws Throwable {
+        return MethodHandles.lookup().findStatic(Handles.class, name, MethodType.methodType(returnType));
+    }
+
+    static Object findGetter(String name, Class<?> type) throws Throwable {
+        return MethodHandles.lookup().findGetter(Handles.class, name, type);
+    }
+
+    static void invokeNull() throws Throwable {
+        MethodHandle handle = null;
+        handle.invokeExact();
+    }
+}
@@ -0,0 +1,20 @@
+public class Hashing {
+    static boolean hashIsStable() {
+        Object object = new Object();
+        int hash = object.hashCode();
+        return hash == object.hashCode() && hash == System.identityHashCode(object);
+    }
+
+    static boolean distinctObjectsHaveDistinctHashes() {
+        return new Object().hashCode() != new Object().hashCode();
+    }
+
+    static int nullHash() {
+        return System.identityHashCode(null);
+    }
+
+    static boolean stringsHashTheirContents() {
+        String string = "text";
+        return string.hashCode() == 3556653 && string.hashCode() == new String(new char[] {'t', 'e', 'x', 't'}).hashCode();
+    }
+}
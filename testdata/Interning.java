@@ -0,0 +1,12 @@
+public class Interning {
+    static boolean internReturnsLiteral() {
+        String built = new String(new char[] {'h', 'i'});
+        return built != "hi" && built.intern() == "hi";
+    }
+
+    static boolean internAddsNewStrings() {
+        String built = new String(new char[] {'n', 'e', 'w'});
+        String copy = new String(new char[] {'n', 'e', 'w'});
+        return built.intern() == built && copy.intern() == built;
+    }
+}
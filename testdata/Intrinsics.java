@@ -0,0 +1,110 @@
+// Exercises System.arraycopy, Object.clone and the String intrinsics.
+public class Intrinsics implements Cloneable {
+    int value;
+    int[] shared;
+
+    static int[] shiftRight() {
+        int[] values = {1, 2, 3, 4, 5};
+        System.arraycopy(values, 0, values, 1, 4);
+        return values;
+    }
+
+    static int[] shiftLeft() {
+        int[] values = {1, 2, 3, 4, 5};
+        System.arraycopy(values, 1, values, 0, 4);
+        return values;
+    }
+
+    static Object[] shiftObjectsRight() {
+        Object[] values = {"a", "b", "c"};
+        System.arraycopy(values, 0, values, 1, 2);
+        return values;
+    }
+
+    static Object[] widen() {
+        String[] strings = {"a", "b"};
+        Object[] objects = new Object[3];
+        System.arraycopy(strings, 0, objects, 1, 2);
+        return objects;
+    }
+
+    // Copies until reaching an element that isn't a String, returning how many were copied.
+    static int partialCopy() {
+        Object[] objects = {"a", "b", new Object(), "d"};
+        String[] strings = new String[4];
+        try {
+            System.arraycopy(objects, 0, strings, 0, 4);
+            return -1;
+        } catch (ArrayStoreException e) {
+            int copied = 0;
+            for (String string : strings) {
+                if (string != null) {
+                    copied++;
+                }
+            }
+            return copied;
+        }
+    }
+
+    static void copy(Object src, int srcPos, Object dest, int destPos, int length) {
+        System.arraycopy(src, srcPos, dest, destPos, length);
+    }
+
+    static boolean cloneArray() {
+        int[] values = {1, 2, 3};
+        int[] copy = values.clone();
+        copy[0] = 9;
+        return copy != values && values[0] == 1 && copy[0] == 9 && copy[2] == 3 && copy.length == 3;
+    }
+
+    static boolean cloneObject() throws CloneNotSupportedException {
+        Intrinsics original = new Intrinsics();
+        original.value = 7;
+        original.shared = new int[1];
+        Intrinsics copy = (Intrinsics) original.clone();
+        return copy != original && copy.value == 7 && copy.shared == original.shared && copy.getClass() == Intrinsics.class;
+    }
+
+    static boolean cloneUncloneable() {
+        try {
+            new Uncloneable().copy();
+            return false;
+        } catch (CloneNotSupportedException e) {
+            return true;
+        }
+    }
+
+    static class Uncloneable {
+        Object copy() throws CloneNotSupportedException {
+            return clone();
+        }
+    }
+
+    static int length(String string) {
+        return string.length();
+    }
+
+    static char charAt(String string, int index) {
+        return string.charAt(index);
+    }
+
+    static boolean stringsEqual(String string, Object other) {
+        return string.equals(other);
+    }
+
+    static int hash(String string) {
+        return string.hashCode();
+    }
+
+    static int indexOf(String string, int c) {
+        return string.indexOf(c);
+    }
+
+    static int compare(String string, String other) {
+        return string.compareTo(other);
+    }
+
+    static byte[] bytes(String string) {
+        return string.getBytes();
+    }
+}
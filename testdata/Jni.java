@@ -0,0 +1,51 @@
+// Natives implemented in C, by native/jni.c.
+public class Jni {
+    int count;
+
+    static native int add(int a, int b);
+    static native double mix(int a, double b, float c, long d, boolean negate);
+    static native String greet(String name);
+    static native int sum(int[] values);
+    static native int[] squares(int length);
+    native int bump(int by);
+    static native int callBack(Jni jni, int value);
+    static native void fail(String message);
+    static native int overloaded(int value);
+    static native int overloaded(long value);
+    static native int missing();
+
+    static void load(String name) {
+        System.loadLibrary(name);
+    }
+
+    static int sumOfSquares(int length) {
+        return sum(squares(length));
+    }
+
+    static int bumpTwice() {
+        Jni jni = new Jni();
+        jni.bump(2);
+        return jni.bump(3);
+    }
+
+    static String catchFailure() {
+        try {
+            fail("thrown from C");
+            return null;
+        } catch (IllegalArgumentException e) {
+            return e.getMessage();
+        }
+    }
+
+    static int callBackWith(int value) {
+        return callBack(new Jni(), value);
+    }
+
+    int twice(int value) {
+        return 2 * value + count;
+    }
+
+    static int thrice(int value) {
+        return 3 * value;
+    }
+}
@@ -0,0 +1,44 @@
+class Spins {
+    public static void main(String[] args) {
+        while (true) {}
+    }
+}
+
+class Allocates {
+    static Object last;
+
+    public static void main(String[] args) {
+        while (true) {
+            last = new Object();
+        }
+    }
+}
+
+class AllocatesHugeArray {
+    public static void main(String[] args) {
+        long[] values = new long[1 << 20];
+    }
+}
+
+class LoadsClasses {
+    public static void main(String[] args) {
+        new First();
+        new Second();
+        new Third();
+    }
+}
+
+class First {}
+
+class Second {}
+
+class Third {}
+
+class CatchesEverything {
+    public static void main(String[] args) {
+        try {
+            while (true) {}
+        } catch (Throwable t) {}
+        System.exit(0);
+    }
+}
@@ -0,0 +1,51 @@
+public class Literals {
+    static int bigInt() {
+        return 123456789;
+    }
+
+    static float aFloat() {
+        return 3.5f;
+    }
+
+    static long aLong() {
+        return 1234567890123L;
+    }
+
+    static double aDouble() {
+        return 2.5e100;
+    }
+
+    static String aString() {
+        return "hello, world";
+    }
+
+    static String nonAscii() {
+        return "h\u00e9llo \u2603";
+    }
+
+    static boolean stringsAreInterned() {
+        return aString() == OtherLiterals.greeting();
+    }
+
+    static Class<?> stringClass() {
+        return String.class;
+    }
+
+    static Class<?> arrayClass() {
+        return Literals[][].class;
+    }
+
+    static boolean classLiteralsAreUnique() {
+        return stringClass() == OtherLiterals.stringClass();
+    }
+}
+
+class OtherLiterals {
+    static String greeting() {
+        return "hello, world";
+    }
+
+    static Class<?> stringClass() {
+        return String.class;
+    }
+}
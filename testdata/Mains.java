@@ -0,0 +1,39 @@
+class Completes {
+    static String[] receivedArgs;
+
+    public static void main(String[] args) {
+        receivedArgs = args;
+    }
+}
+
+class Exits {
+    static boolean finallyRan;
+
+    public static void main(String[] args) {
+        try {
+            System.exit(args.length + 40);
+        } finally {
+            finallyRan = true;
+        }
+    }
+}
+
+class Throws {
+    public static void main(String[] args) {
+        try {
+            divide(1, 0);
+        } catch (ArithmeticException e) {
+            throw new RuntimeException("wrapped", e);
+        }
+    }
+
+    static int divide(int a, int b) {
+        return a / b;
+    }
+}
+
+class InheritsMain extends Completes {}
+
+class InstanceMain {
+    public void main(String[] args) {}
+}
@@ -0,0 +1,42 @@
+interface Vehicle {}
+
+abstract class Car implements Vehicle {}
+
+final class Hatchback extends Car {}
+
+public class Mirrors {
+    static boolean getClassReturnsLiteral() {
+        return new Hatchback().getClass() == Hatchback.class && "text".getClass() == String.class;
+    }
+
+    static Class<?> superclass() {
+        return Hatchback.class.getSuperclass();
+    }
+
+    static boolean interfacesAreListed() {
+        Class<?>[] interfaces = Car.class.getInterfaces();
+        return interfaces.length == 1 && interfaces[0] == Vehicle.class && Hatchback.class.getInterfaces().length == 0;
+    }
+
+    static boolean rootsHaveNoSuperclass() {
+        return Object.class.getSuperclass() == null && Vehicle.class.getSuperclass() == null;
+    }
+
+    static boolean arraysExtendObject() {
+        return int[].class.getSuperclass() == Object.class && int[].class.getInterfaces().length == 2;
+    }
+
+    static int modifiers(int which) {
+        switch (which) {
+            case 0: return Mirrors.class.getModifiers();
+            case 1: return Hatchback.class.getModifiers();
+            case 2: return Vehicle.class.getModifiers();
+            case 3: return String[].class.getModifiers();
+            default: return Hatchback[].class.getModifiers();
+        }
+    }
+
+    static boolean isInterface() {
+        return Vehicle.class.isInterface() && !Car.class.isInterface();
+    }
+}
@@ -0,0 +1,74 @@
+public class Natives {
+    static String greeting = "hello";
+    int count;
+
+    static native int add(int a, int b);
+    static native long scale(long value, double factor);
+    static native boolean isEven(int value);
+    static native char upper(char c);
+    static native Object echo(Object value);
+    static native void record(Object value);
+    static native int unbound();
+    native Natives self();
+
+    // Natives that use the Env.
+    native int bump(int by);
+    static native String greet(String name);
+    static native int[] range(int length);
+    static native int callBack(Natives natives, int value);
+    static native Object make(int count);
+    static native void fail(String message);
+
+    static int sumOfSquares(int a, int b) {
+        return add(a * a, b * b);
+    }
+
+    static boolean selfIsSame() {
+        Natives natives = new Natives();
+        return natives.self() == natives;
+    }
+
+    static int bumpTwice() {
+        Natives natives = new Natives();
+        natives.bump(2);
+        return natives.bump(3);
+    }
+
+    static int sumRange(int length) {
+        int sum = 0;
+        for (int value : range(length)) {
+            sum += value;
+        }
+        return sum;
+    }
+
+    static String catchFailure() {
+        try {
+            fail("native failure");
+            return null;
+        } catch (IllegalArgumentException e) {
+            return e.getMessage();
+        }
+    }
+
+    Natives() {
+    }
+
+    Natives(int count) {
+        this.count = count;
+    }
+
+    int twice(int value) {
+        return 2 * value;
+    }
+
+    static int thrice(int value) {
+        return 3 * value;
+    }
+}
+
+class LoudNatives extends Natives {
+    int twice(int value) {
+        return 20 * value;
+    }
+}
@@ -0,0 +1,40 @@
+class Asserts {
+    static boolean enabled() {
+        boolean enabled = false;
+        assert enabled = true;
+        return enabled;
+    }
+
+    static void check(int value) {
+        assert value > 0 : "not positive";
+    }
+
+    static void fail() {
+        assert false;
+    }
+}
+
+class FillsHeap {
+    static int churn(int count) {
+        for (int i = 0; i < count; i++) {
+            byte[] chunk = new byte[1024];
+        }
+        return count;
+    }
+
+    static int retain(int count) {
+        Object[] chunks = new Object[count];
+        for (int i = 0; i < count; i++) {
+            chunks[i] = new byte[1024];
+        }
+        return count;
+    }
+
+    static int retainOrGiveUp(int count) {
+        try {
+            return retain(count);
+        } catch (OutOfMemoryError e) {
+            return -1;
+        }
+    }
+}
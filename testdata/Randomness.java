@@ -0,0 +1,52 @@
+import java.util.Random;
+
+// Draws from java.util.Random and reads the clock.
+public class Randomness {
+    static Random random;
+
+    static void seed(long seed) {
+        random = new Random(seed);
+    }
+
+    static void unseeded() {
+        random = new Random();
+    }
+
+    static int nextInt() {
+        return random.nextInt();
+    }
+
+    static int nextIntBelow(int bound) {
+        return random.nextInt(bound);
+    }
+
+    static long nextLong() {
+        return random.nextLong();
+    }
+
+    static boolean nextBoolean() {
+        return random.nextBoolean();
+    }
+
+    static double nextDouble() {
+        return random.nextDouble();
+    }
+
+    static float nextFloat() {
+        return random.nextFloat();
+    }
+
+    static byte[] nextBytes(int length) {
+        byte[] bytes = new byte[length];
+        random.nextBytes(bytes);
+        return bytes;
+    }
+
+    static long currentTimeMillis() {
+        return System.currentTimeMillis();
+    }
+
+    static long nanoTime() {
+        return System.nanoTime();
+    }
+}
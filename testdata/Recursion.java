@@ -0,0 +1,41 @@
+public class Recursion {
+    static int depth;
+
+    static void recurse() {
+        depth++;
+        recurse();
+    }
+
+    static int depthReached() {
+        depth = 0;
+        try {
+            recurse();
+        } catch (StackOverflowError e) {
+            return depth;
+        }
+        return -1;
+    }
+
+    static int overflowTwice() {
+        int first = depthReached();
+        int second = depthReached();
+        return first == second ? second : -1;
+    }
+
+    static int catchAsError() {
+        try {
+            recurse();
+        } catch (Error e) {
+            return 1;
+        }
+        return 0;
+    }
+
+    static int sum(int n) {
+        return n == 0 ? 0 : n + sum(n - 1);
+    }
+
+    static void uncaughtOverflow() {
+        recurse();
+    }
+}
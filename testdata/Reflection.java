@@ -0,0 +1,173 @@
+import java.lang.reflect.Constructor;
+import java.lang.reflect.Field;
+import java.lang.reflect.Method;
+
+// Exercises Class.forName and the core reflection natives.
+public class Reflection {
+    private int count = 1;
+    public long total;
+    final String label = "fixed";
+    static int instances;
+    static final Object SHARED = new Object();
+
+    public Reflection() {
+        instances++;
+    }
+
+    private Reflection(int count) {
+        this.count = count;
+        instances++;
+    }
+
+    public int add(int amount) {
+        count += amount;
+        return count;
+    }
+
+    public String describe() {
+        return "base";
+    }
+
+    static double half(double value) {
+        return value / 2;
+    }
+
+    static int divide(int dividend, int divisor) {
+        return dividend / divisor;
+    }
+
+    static void nothing() {}
+
+    static class Derived extends Reflection {
+        public String describe() {
+            return "derived";
+        }
+    }
+
+    abstract static class Abstract {}
+
+    // Compiled for Java 8, so nested classes aren't nestmates, and this can't use the private
+    // members of Reflection.
+    static class Outsider {
+        static Object readCount(boolean setAccessible) throws Exception {
+            Field count = field("count");
+            count.setAccessible(setAccessible);
+            return count.get(new Reflection());
+        }
+    }
+
+    static Field field(String name) {
+        for (Field field : Reflection.class.getDeclaredFields()) {
+            if (field.getName().equals(name)) {
+                return field;
+            }
+        }
+        return null;
+    }
+
+    static Method method(Class<?> owner, String name) {
+        for (Method method : owner.getDeclaredMethods()) {
+            if (method.getName().equals(name)) {
+                return method;
+            }
+        }
+        return null;
+    }
+
+    static Constructor<?> constructor(Class<?> owner, int parameterCount) {
+        for (Constructor<?> constructor : owner.getDeclaredConstructors()) {
+            if (constructor.getParameterCount() == parameterCount) {
+                return constructor;
+            }
+        }
+        return null;
+    }
+
+    static String forName(String name) throws Exception {
+        return Class.forName(name).getName();
+    }
+
+    static boolean bootstrapLoaderOnlyFindsPlatformClasses() throws Exception {
+        try {
+            Class.forName("Reflection", false, null);
+            return false;
+        } catch (ClassNotFoundException e) {
+            return Class.forName("java.lang.String", false, null) == String.class;
+        }
+    }
+
+    static boolean primitiveMirrors() {
+        return int.class == Integer.TYPE && int.class.isPrimitive() && !Integer.class.isPrimitive()
+            && int.class.getName().equals("int") && void.class.getName().equals("void") && (Object) double.class != long.class;
+    }
+
+    static String[] fieldNames() {
+        Field[] fields = Reflection.class.getDeclaredFields();
+        String[] names = new String[fields.length];
+        for (int i = 0; i < fields.length; i++) {
+            names[i] = fields[i].getName();
+        }
+        return names;
+    }
+
+    static String[] methodNames(Class<?> owner) {
+        Method[] methods = owner.getDeclaredMethods();
+        String[] names = new String[methods.length];
+        for (int i = 0; i < methods.length; i++) {
+            names[i] = methods[i].getName();
+        }
+        return names;
+    }
+
+    static boolean describesMembers() {
+        Field count = field("count");
+        Method add = method(Reflection.class, "add");
+        Method nothing = method(Reflection.class, "nothing");
+        return count.getType() == int.class && count.getModifiers() == 2 && count.getDeclaringClass() == Reflection.class
+            && field("label").getType() == String.class && add.getReturnType() == int.class
+            && add.getParameterTypes()[0] == int.class && add.getModifiers() == 1 && nothing.getReturnType() == void.class
+            && nothing.getModifiers() == 8 && Reflection.class.getDeclaredConstructors().length == 2;
+    }
+
+    static Object getCount() throws Exception {
+        return field("count").get(new Reflection());
+    }
+
+    static Object getStatic() throws Exception {
+        instances = 0;
+        return field("instances").get(null);
+    }
+
+    static long setFields() throws Exception {
+        Reflection reflection = new Reflection();
+        field("count").set(reflection, 41);
+        field("total").set(reflection, 'a'); // Widened from char to long.
+        field("instances").set(null, 3);
+        return reflection.count + reflection.total + instances;
+    }
+
+    static void set(String name, Object target, Object value, boolean setAccessible) throws Exception {
+        Field field = field(name);
+        field.setAccessible(setAccessible);
+        field.set(target, value);
+    }
+
+    static Object invoke(Class<?> owner, String name, Object receiver, Object[] args) throws Exception {
+        return method(owner, name).invoke(receiver, args);
+    }
+
+    static int invokeAdd() throws Exception {
+        Reflection reflection = new Reflection();
+        method(Reflection.class, "add").invoke(reflection, 10);
+        return (Integer) method(Reflection.class, "add").invoke(reflection, (byte) 5);
+    }
+
+    static Object construct(Class<?> owner, int parameterCount, Object[] args) throws Exception {
+        return constructor(owner, parameterCount).newInstance(args);
+    }
+
+    static int constructPrivately() throws Exception {
+        Reflection reflection = (Reflection) constructor(Reflection.class, 1).newInstance(7);
+        return reflection.count;
+    }
+}
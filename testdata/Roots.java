@@ -0,0 +1,33 @@
+// Fixtures for the GC root tests in src/roots.rs.
+public class Roots {
+    static Object kept;
+    static String literal;
+
+    static void setUp() {
+        kept = new Object();
+        literal = "interned";
+    }
+
+    static Object pick(Object first, Object second) {
+        Object unused = first;
+        return second;
+    }
+
+    static int countDown(int n, Object held) {
+        while (n > 0) {
+            if (held == null) {
+                return -1;
+            }
+            n--;
+        }
+        return n;
+    }
+
+    static Object fallback(Object value, Object otherwise) {
+        try {
+            return (String) value;
+        } catch (RuntimeException e) {
+            return otherwise;
+        }
+    }
+}
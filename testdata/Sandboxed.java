@@ -0,0 +1,37 @@
+import java.io.FileInputStream;
+import java.io.FileOutputStream;
+import java.io.IOException;
+
+// Reaches out to the host through the natives that the sandbox policy governs.
+public class Sandboxed {
+    static String getenv(String name) {
+        return System.getenv(name);
+    }
+
+    // Reads up to 100 bytes of the file.
+    static byte[] read(String name) throws IOException {
+        FileInputStream in = new FileInputStream(name);
+        byte[] buffer = new byte[100];
+        int length = 0;
+        int read;
+        while (length < buffer.length && (read = in.read(buffer, length, buffer.length - length)) != -1) {
+            length += read;
+        }
+        in.close();
+        byte[] contents = new byte[length];
+        System.arraycopy(buffer, 0, contents, 0, length);
+        return contents;
+    }
+
+    // Writes the text and returns whether closing the stream invalidated its file descriptor.
+    static boolean write(String name, boolean append, String text) throws IOException {
+        FileOutputStream out = new FileOutputStream(name, append);
+        out.write(text.getBytes());
+        out.close();
+        return !out.getFD().valid();
+    }
+
+    static void load(String path) {
+        System.load(path);
+    }
+}
@@ -0,0 +1,72 @@
+// Registers shutdown hooks that print their names, then finishes in the way its argument asks.
+public class ShutdownHooks {
+    static class Printer implements Runnable {
+        private final String text;
+
+        Printer(String text) {
+            this.text = text;
+        }
+
+        public void run() {
+            System.out.println(text);
+        }
+    }
+
+    static class Exiting extends Thread {
+        public void run() {
+            System.out.println("exiting from a hook");
+            System.exit(7);
+        }
+    }
+
+    static class Failing extends Thread {
+        Failing() {
+            super("failing hook");
+        }
+
+        public void run() {
+            throw new RuntimeException("boom");
+        }
+    }
+
+    static class Adding extends Thread {
+        public void run() {
+            try {
+                Runtime.getRuntime().addShutdownHook(new Thread(new Printer("never")));
+            } catch (IllegalStateException e) {
+                System.out.println(e.getMessage());
+            }
+        }
+    }
+
+    public static void main(String[] args) {
+        String how = args[0];
+        Runtime runtime = Runtime.getRuntime();
+        runtime.addShutdownHook(new Thread(new Printer("first")));
+        Thread removed = new Thread(new Printer("removed"));
+        runtime.addShutdownHook(removed);
+        runtime.addShutdownHook(new Thread(new Printer("second")));
+        try {
+            runtime.addShutdownHook(removed);
+        } catch (IllegalArgumentException e) {
+            System.out.println(e.getMessage());
+        }
+        System.out.println(runtime.removeShutdownHook(removed));
+        System.out.println(runtime.removeShutdownHook(removed));
+
+        if (how.equals("exit")) {
+            System.exit(3);
+        } else if (how.equals("halt")) {
+            runtime.halt(4);
+        } else if (how.equals("throw")) {
+            throw new IllegalStateException("main failed");
+        } else if (how.equals("exitFromHook")) {
+            runtime.addShutdownHook(new Exiting());
+            runtime.addShutdownHook(new Thread(new Printer("after exiting")));
+        } else if (how.equals("failingHook")) {
+            runtime.addShutdownHook(new Failing());
+            runtime.addShutdownHook(new Adding());
+        }
+        System.out.println("main returned");
+    }
+}
@@ -0,0 +1,54 @@
+public class Switches {
+    static int dense(int value) {
+        switch (value) {
+            case 1: return 10;
+            case 2: return 20;
+            case 3: return 30;
+            case 5: return 50;
+            default: return -1;
+        }
+    }
+
+    static int denseWithNegativeKeys(int value) {
+        switch (value) {
+            case -2: return 1;
+            case -1: return 2;
+            case 0: return 3;
+            case 1: return 4;
+            default: return 0;
+        }
+    }
+
+    static int sparse(int value) {
+        switch (value) {
+            case -1000000: return 1;
+            case -5: return 2;
+            case 100: return 3;
+            case 4096: return 4;
+            case 1000000: return 5;
+            case 2147483647: return 6;
+            default: return 0;
+        }
+    }
+
+    static int total;
+
+    static int fallThrough(int value) {
+        switch (value) {
+            case 1: total += 1;
+            case 2: total += 10;
+            case 3: total += 100;
+                break;
+            case 4: total += 1000;
+        }
+        return total;
+    }
+
+    static int noDefault(int value) {
+        switch (value) {
+            case 7: return 1;
+            case 700: return 2;
+        }
+        return 3;
+    }
+}
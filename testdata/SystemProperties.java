@@ -0,0 +1,13 @@
+class SystemProperties {
+    static String get(String key) {
+        return System.getProperty(key);
+    }
+
+    static String getOrDefault(String key, String def) {
+        return System.getProperty(key, def);
+    }
+
+    static String set(String key, String value) {
+        return System.setProperty(key, value);
+    }
+}
@@ -0,0 +1,559 @@
+import java.util.concurrent.locks.LockSupport;
+
+// Starts threads in the ways its argument asks, to check that they run alongside main.
+public class Threads {
+    static final int WAIT_FOR_MAIN = 0;
+    static final int FAIL = 1;
+    static final int EXIT = 2;
+    static final int PRINT = 3;
+    static final int INIT = 4;
+    static final int SPIN = 5;
+    static final int SLEEP = 6;
+    static final int DAEMON = 7;
+    static final int RACE_A = 8;
+    static final int RACE_B = 9;
+    static final int JOIN_MAIN = 10;
+    static final int PARK = 11;
+    static final int RECORD_HOST = 12;
+
+    static volatile boolean firstStarted;
+    static volatile boolean secondStarted;
+    static volatile boolean mainDone;
+    static volatile boolean reported;
+    static volatile boolean released;
+    static volatile boolean initWaiting;
+    static volatile boolean spinning;
+    static volatile boolean sleeping;
+    static volatile boolean daemonStarted;
+    static Thread mainThread;
+    static char[] raceLog = new char[40];
+    static int raceLength;
+    static volatile boolean unparked;
+
+    // Tells the embedder which host thread it was called on.
+    static native void recordHost();
+
+    // Each waits for the other to start without yielding, so neither finishes unless the other
+    // gets a turn part way through.
+    static class Counter extends Thread {
+        private final boolean first;
+
+        Counter(String name, boolean first) {
+            super(name);
+            this.first = first;
+        }
+
+        public void run() {
+            if (first) {
+                firstStarted = true;
+                while (!secondStarted) {}
+                System.out.println("first saw second");
+            } else {
+                secondStarted = true;
+                while (!firstStarted) {}
+                System.out.println("second saw first");
+            }
+        }
+    }
+
+    static class Identity extends Thread {
+        Identity() {
+            super("worker");
+        }
+
+        public void run() {
+            System.out.print(Thread.currentThread().getName());
+            System.out.print(" ");
+            System.out.println(Thread.currentThread() == this);
+            reported = true;
+            while (!released) {
+                Thread.yield();
+            }
+        }
+    }
+
+    static class Task implements Runnable {
+        private final int kind;
+
+        Task(int kind) {
+            this.kind = kind;
+        }
+
+        public void run() {
+            if (kind == WAIT_FOR_MAIN) {
+                while (!mainDone) {
+                    Thread.yield();
+                }
+                System.out.println("slow done");
+            } else if (kind == FAIL) {
+                throw new IllegalStateException("failed");
+            } else if (kind == EXIT) {
+                System.exit(3);
+            } else if (kind == PRINT) {
+                System.out.println("hook");
+            } else if (kind == INIT) {
+                initWaiting = true;
+                System.out.println(SlowInit.VALUE);
+            } else if (kind == SLEEP) {
+                sleeping = true;
+                try {
+                    Thread.sleep(60000);
+                    System.out.println("slept");
+                } catch (InterruptedException e) {
+                    System.out.println(e.getMessage());
+                    System.out.println(Thread.currentThread().isInterrupted());
+                }
+            } else if (kind == DAEMON) {
+                System.out.println(new Thread(new Task(PRINT)).isDaemon());
+                daemonStarted = true;
+                try {
+                    while (true) {
+                        Thread.sleep(1000);
+                    }
+                } catch (InterruptedException e) {
+                    System.out.println("interrupted");
+                }
+            } else if (kind == RACE_A || kind == RACE_B) {
+                for (int i = 0; i < 20; i++) {
+                    for (int j = 0; j < 300; j++) {}
+                    raceLog[raceLength++] = kind == RACE_A ? 'a' : 'b';
+                }
+            } else if (kind == PARK) {
+                while (!unparked) {
+                    LockSupport.park("blocker");
+                }
+                System.out.println("unparked");
+            } else if (kind == RECORD_HOST) {
+                recordHost();
+            } else if (kind == JOIN_MAIN) {
+                try {
+                    mainThread.join();
+                } catch (InterruptedException e) {
+                    System.out.println(e.getMessage());
+                }
+            } else {
+                spinning = true;
+                while (true) {}
+            }
+        }
+    }
+
+    static class Handler implements Thread.UncaughtExceptionHandler {
+        private final String name;
+
+        Handler(String name) {
+            this.name = name;
+        }
+
+        public void uncaughtException(Thread t, Throwable e) {
+            System.out.print(name);
+            System.out.print(" caught ");
+            System.out.print(e.getMessage());
+            System.out.print(" from ");
+            System.out.println(t.getName());
+        }
+    }
+
+    // Its initializer runs on main and yields until another thread needs the class.
+    static class SlowInit {
+        static final int VALUE;
+
+        static {
+            while (!initWaiting) {
+                Thread.yield();
+            }
+            for (int i = 0; i < 10; i++) {
+                Thread.yield();
+            }
+            VALUE = 42;
+        }
+    }
+
+    public static void main(String[] args) throws InterruptedException {
+        String how = args[0];
+        if (how.equals("interleave")) {
+            new Counter("first", true).start();
+            new Counter("second", false).start();
+        } else if (how.equals("identity")) {
+            identity();
+        } else if (how.equals("outlive")) {
+            Runtime.getRuntime().addShutdownHook(new Thread(new Task(PRINT)));
+            new Thread(new Task(WAIT_FOR_MAIN)).start();
+            System.out.println("main done");
+            mainDone = true;
+        } else if (how.equals("fail")) {
+            Thread failing = new Thread(new Task(FAIL), "failing");
+            failing.start();
+            while (failing.isAlive()) {
+                Thread.yield();
+            }
+            System.out.println("after");
+        } else if (how.equals("exit")) {
+            new Thread(new Task(EXIT)).start();
+            while (true) {
+                Thread.yield();
+            }
+        } else if (how.equals("init")) {
+            new Thread(new Task(INIT)).start();
+            System.out.println(SlowInit.VALUE);
+        } else if (how.equals("sleep")) {
+            sleep();
+        } else if (how.equals("interrupt")) {
+            Thread sleeper = new Thread(new Task(SLEEP));
+            sleeper.start();
+            while (!sleeping) {
+                Thread.yield();
+            }
+            sleeper.interrupt();
+            sleeper.join();
+            System.out.println("joined");
+        } else if (how.equals("daemon")) {
+            daemon();
+        } else if (how.equals("status")) {
+            interruptStatus();
+        } else if (how.equals("timeout")) {
+            Thread waiter = new Thread(new Task(WAIT_FOR_MAIN));
+            waiter.start();
+            long start = System.nanoTime();
+            waiter.join(20);
+            System.out.println(waiter.isAlive() && System.nanoTime() - start >= 20000000L);
+            mainDone = true;
+            waiter.join();
+            System.out.println(waiter.isAlive());
+        } else if (how.equals("race")) {
+            Thread a = new Thread(new Task(RACE_A));
+            Thread b = new Thread(new Task(RACE_B));
+            a.start();
+            b.start();
+            a.join();
+            b.join();
+            System.out.println(new String(raceLog));
+        } else if (how.equals("park")) {
+            park();
+        } else if (how.equals("handler")) {
+            handlers();
+        } else if (how.equals("priority")) {
+            priority();
+        } else if (how.equals("named")) {
+            Thread named = new Thread(new Task(RECORD_HOST), "worker-7");
+            named.start();
+            named.join();
+        } else if (how.equals("deadlock")) {
+            mainThread = Thread.currentThread();
+            Thread joiner = new Thread(new Task(JOIN_MAIN));
+            joiner.start();
+            joiner.join();
+        } else if (how.equals("synchronize")) {
+            synchronize();
+        } else if (how.equals("lock")) {
+            lock();
+        }
+    }
+
+    // Leaves a daemon thread sleeping as main finishes, after another thread that isn't one.
+    static void daemon() {
+        Thread daemon = new Thread(new Task(DAEMON));
+        System.out.println(daemon.isDaemon());
+        daemon.setDaemon(true);
+        daemon.start();
+        while (!daemonStarted) {
+            Thread.yield();
+        }
+        try {
+            daemon.setDaemon(false);
+        } catch (IllegalThreadStateException e) {
+            System.out.println("IllegalThreadStateException");
+        }
+        Runtime.getRuntime().addShutdownHook(new Thread(new Task(PRINT)));
+        new Thread(new Task(WAIT_FOR_MAIN)).start();
+        System.out.println("main done");
+        mainDone = true;
+    }
+
+    static void handlers() throws InterruptedException {
+        Thread.setDefaultUncaughtExceptionHandler(new Handler("default"));
+        Thread own = new Thread(new Task(FAIL), "own");
+        own.setUncaughtExceptionHandler(new Handler("own"));
+        own.start();
+        own.join();
+        Thread other = new Thread(new Task(FAIL), "other");
+        other.start();
+        other.join();
+        throw new IllegalStateException("main failed");
+    }
+
+    // Prints the log of a race between threads of the highest and lowest priorities.
+    static void priority() throws InterruptedException {
+        Thread main = Thread.currentThread();
+        System.out.println(main.getPriority());
+        main.setPriority(Thread.MAX_PRIORITY);
+        System.out.println(new Thread(new Task(PRINT)).getPriority());
+        try {
+            main.setPriority(Thread.MAX_PRIORITY + 1);
+        } catch (IllegalArgumentException e) {
+            System.out.println("IllegalArgumentException");
+        }
+        main.setPriority(Thread.NORM_PRIORITY);
+
+        Thread a = new Thread(new Task(RACE_A));
+        a.setPriority(Thread.MAX_PRIORITY);
+        Thread b = new Thread(new Task(RACE_B));
+        b.setPriority(Thread.MIN_PRIORITY);
+        a.start();
+        b.start();
+        a.join();
+        b.join();
+        System.out.println(new String(raceLog));
+    }
+
+    static void park() throws InterruptedException {
+        Thread current = Thread.currentThread();
+        LockSupport.unpark(current);
+        LockSupport.park();
+        System.out.println("took permit");
+        LockSupport.parkNanos(1000000L);
+        System.out.println("timed out");
+        LockSupport.parkUntil(0L);
+        System.out.println("deadline passed");
+        current.interrupt();
+        LockSupport.park();
+        System.out.println(Thread.interrupted());
+
+        Thread parker = new Thread(new Task(PARK));
+        LockSupport.unpark(parker);
+        parker.start();
+        while (LockSupport.getBlocker(parker) == null) {
+            Thread.yield();
+        }
+        System.out.println((String) LockSupport.getBlocker(parker));
+        unparked = true;
+        LockSupport.unpark(parker);
+        parker.join();
+        System.out.println(LockSupport.getBlocker(parker) == null);
+    }
+
+    static void sleep() throws InterruptedException {
+        long start = System.nanoTime();
+        Thread.sleep(20);
+        System.out.println(System.nanoTime() - start >= 20000000L);
+        try {
+            Thread.sleep(-1);
+        } catch (IllegalArgumentException e) {
+            System.out.println(e.getMessage());
+        }
+    }
+
+    static void interruptStatus() throws InterruptedException {
+        Thread current = Thread.currentThread();
+        current.interrupt();
+        System.out.println(current.isInterrupted());
+        System.out.println(Thread.interrupted());
+        System.out.println(Thread.interrupted());
+
+        current.interrupt();
+        try {
+            Thread.sleep(60000);
+        } catch (InterruptedException e) {
+            System.out.println(e.getMessage());
+        }
+        System.out.println(current.isInterrupted());
+
+        Thread waiter = new Thread(new Task(WAIT_FOR_MAIN));
+        waiter.start();
+        current.interrupt();
+        try {
+            waiter.join();
+        } catch (InterruptedException e) {
+            System.out.println(e.getMessage());
+        }
+        mainDone = true;
+        waiter.join();
+        System.out.println(waiter.isAlive());
+    }
+
+    static void identity() {
+        Thread main = Thread.currentThread();
+        System.out.print(main.getName());
+        System.out.print(" ");
+        System.out.println(main.isAlive() && main == Thread.currentThread());
+        Identity worker = new Identity();
+        System.out.println(worker.isAlive());
+        worker.start();
+        while (!reported) {
+            Thread.yield();
+        }
+        System.out.println(worker.isAlive());
+        released = true;
+        while (worker.isAlive()) {
+            Thread.yield();
+        }
+        System.out.println(worker.isAlive());
+        try {
+            worker.start();
+        } catch (IllegalThreadStateException e) {
+            System.out.println("IllegalThreadStateException");
+        }
+    }
+
+    // Leaves a thread spinning, for the embedder to stop.
+    static void startSpinning() {
+        new Thread(new Task(SPIN)).start();
+        while (!spinning) {
+            Thread.yield();
+        }
+    }
+
+    // Adds to counters in steps that yield part way through, so the totals are right only if
+    // each lock keeps the other adder out meanwhile.
+    static class Adder extends Thread {
+        static final Object lock = new Object();
+        static int inBlocks;
+        static int inMethods;
+
+        public void run() {
+            for (int i = 0; i < 50; i++) {
+                synchronized (lock) {
+                    int seen = inBlocks;
+                    Thread.yield();
+                    inBlocks = seen + 1;
+                }
+                addInMethod();
+            }
+        }
+
+        static synchronized void addInMethod() {
+            int seen = inMethods;
+            Thread.yield();
+            inMethods = seen + 1;
+        }
+    }
+
+    // Hands items from one thread to another through a single slot, waiting on its monitor for
+    // the slot to empty or fill.
+    static class Mailbox {
+        private int item;
+        private boolean full;
+
+        synchronized void put(int value) throws InterruptedException {
+            while (full) {
+                wait();
+            }
+            item = value;
+            full = true;
+            notifyAll();
+        }
+
+        synchronized int take() throws InterruptedException {
+            while (!full) {
+                wait();
+            }
+            full = false;
+            notifyAll();
+            return item;
+        }
+    }
+
+    static class Consumer extends Thread {
+        private final Mailbox mailbox;
+        int total;
+
+        Consumer(Mailbox mailbox) {
+            this.mailbox = mailbox;
+        }
+
+        public void run() {
+            try {
+                for (int i = 0; i < 5; i++) {
+                    total += mailbox.take();
+                }
+            } catch (InterruptedException e) {
+                System.out.println(e.getMessage());
+            }
+        }
+    }
+
+    static void synchronize() throws InterruptedException {
+        Adder first = new Adder();
+        Adder second = new Adder();
+        first.start();
+        second.start();
+        first.join();
+        second.join();
+        System.out.println(Adder.inBlocks);
+        System.out.println(Adder.inMethods);
+
+        Mailbox mailbox = new Mailbox();
+        Consumer consumer = new Consumer(mailbox);
+        consumer.start();
+        for (int i = 1; i <= 5; i++) {
+            mailbox.put(i);
+        }
+        consumer.join();
+        System.out.println(consumer.total);
+
+        synchronized (mailbox) {
+            mailbox.wait(1);
+        }
+        System.out.println("timed out");
+        try {
+            mailbox.notify();
+        } catch (IllegalMonitorStateException e) {
+            System.out.println("IllegalMonitorStateException");
+        }
+    }
+
+    // Adds to a counter as Adder does, holding a ReentrantLock instead, or else just tries to take
+    // the lock once.
+    static class Locker extends Thread {
+        static final java.util.concurrent.locks.ReentrantLock lock = new java.util.concurrent.locks.ReentrantLock();
+        static int count;
+        private final boolean trying;
+
+        Locker(boolean trying) {
+            this.trying = trying;
+        }
+
+        public void run() {
+            if (trying) {
+                System.out.println(lock.tryLock());
+                return;
+            }
+            for (int i = 0; i < 50; i++) {
+                lock.lock();
+                try {
+                    int seen = count;
+                    Thread.yield();
+                    count = seen + 1;
+                } finally {
+                    lock.unlock();
+                }
+            }
+        }
+    }
+
+    static void lock() throws InterruptedException {
+        Locker first = new Locker(false);
+        Locker second = new Locker(false);
+        first.start();
+        second.start();
+        first.join();
+        second.join();
+        System.out.println(Locker.count);
+
+        java.util.concurrent.locks.ReentrantLock lock = Locker.lock;
+        lock.lock();
+        lock.lock();
+        System.out.println(lock.getHoldCount());
+        Locker trier = new Locker(true);
+        trier.start();
+        trier.join();
+        lock.unlock();
+        lock.unlock();
+        System.out.println(lock.isLocked());
+        try {
+            lock.unlock();
+        } catch (IllegalMonitorStateException e) {
+            System.out.println("IllegalMonitorStateException");
+        }
+    }
+}
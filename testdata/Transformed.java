@@ -0,0 +1,6 @@
+// Returns a string constant that the transformer tests rewrite.
+public class Transformed {
+    public static String message() {
+        return "before";
+    }
+}
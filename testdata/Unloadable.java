@@ -0,0 +1,4 @@
+// Keeps an instance of itself in a static field, so the instance lives as long as the class.
+public class Unloadable {
+    static Unloadable instance = new Unloadable();
+}
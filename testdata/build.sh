@@ -0,0 +1,56 @@
+#!/bin/sh
+# Rebuilds the test fixture class files against joyvm's bootstrap classes. The compiled classes
+# are checked in so that running the tests doesn't require a JDK.
+set -e
+cd "$(dirname "$0")"
+javac -source 8 -target 8 -Xlint:-options -bootclasspath ../bootstrap -d . *.java
+python3 gen_constants.py
+python3 gen_subroutines.py
+python3 gen_legacy.py
+python3 gen_nests.py
+python3 gen_stack_ops.py
+python3 gen_field_misuse.py
+python3 gen_jimage.py
+
+# Classes for the linkage error tests. Those under linkage/changed replace the classes of the same
+# name after linkage/Linkage has been compiled against the originals.
+javac -source 8 -target 8 -Xlint:-options -bootclasspath ../bootstrap -d linkage linkage/*.java linkage/other/*.java
+javac -source 8 -target 8 -Xlint:-options -bootclasspath ../bootstrap -d linkage/changed linkage/changed/*.java linkage/changed/other/*.java
+
+# JARs for the classpath tests. The manifest of app.jar puts lib.jar on the classpath; lib.jar is
+# stored uncompressed. multi-release.jar has versions of its entries for Java 9 and 11. The manifest
+# of sealed.jar describes and seals com.example.sealed, which split.jar also has a class in.
+cd jars
+rm -rf build && mkdir -p build/app build/lib
+javac -source 8 -target 8 -Xlint:-options -bootclasspath ../../bootstrap -d build/lib lib/com/example/lib/*.java
+javac -source 8 -target 8 -Xlint:-options -bootclasspath ../../bootstrap -cp build/lib -d build/app app/com/example/*.java
+cp app/com/example/message.txt build/app/com/example/
+jar --create --file app.jar --manifest app/MANIFEST.MF -C build/app .
+jar --create --no-compress --file lib.jar -C build/lib .
+mkdir -p build/multi-release/META-INF/versions/9/com/example build/multi-release/META-INF/versions/11
+javac -source 8 -target 8 -Xlint:-options -bootclasspath ../../bootstrap -d build/multi-release multi-release/base/com/example/*.java
+javac -source 8 -target 8 -Xlint:-options -bootclasspath ../../bootstrap -d build/multi-release/META-INF/versions/11 multi-release/11/com/example/*.java
+cp multi-release/base/com/example/release.txt build/multi-release/com/example/
+cp multi-release/9/com/example/release.txt build/multi-release/META-INF/versions/9/com/example/
+jar --create --file multi-release.jar --manifest multi-release/MANIFEST.MF -C build/multi-release .
+mkdir -p build/sealed build/split
+javac -source 8 -target 8 -Xlint:-options -bootclasspath ../../bootstrap -d build/sealed sealed/com/example/sealed/*.java
+javac -source 8 -target 8 -Xlint:-options -bootclasspath ../../bootstrap -d build/split split/com/example/sealed/*.java
+jar --create --file sealed.jar --manifest sealed/MANIFEST.MF -C build/sealed .
+jar --create --file split.jar -C build/split .
+# verify.jar has a class that the verifier rejects along with classes that it accepts.
+jar --create --file verify.jar -C .. Boom.class -C .. Legacy.class -C .. Unverifiable.class
+rm -rf build
+cd ..
+
+# Modular JARs for the module tests, built with the JDK's own java.base since modules can't be
+# compiled against joyvm's bootstrap classes. com.example.app is compiled with access to an
+# internal package of com.example.api that isn't exported to it at runtime, as is the Outsider
+# class, which goes on the classpath.
+cd modules
+rm -rf build
+javac --release 9 --module-source-path . --add-exports com.example.api/com.example.api.internal=com.example.app -d build --module com.example.api,com.example.app
+jar --create --file api.jar -C build/com.example.api .
+jar --create --file app.jar -C build/com.example.app .
+javac --release 9 -cp build/com.example.api -d outsider outsider/Outsider.java
+rm -rf build
@@ -0,0 +1,102 @@
+# A tiny class file assembler for test fixtures that javac can't produce, such as methods using
+# jsr/ret or ldc of method handles. Methods are given as raw bytecode.
+import os
+import struct
+
+ACC_PUBLIC, ACC_PRIVATE, ACC_STATIC, ACC_SUPER = 0x0001, 0x0002, 0x0008, 0x0020
+
+
+class Pool:
+    def __init__(self):
+        self.entries = []
+        self.indexes = {}
+
+    def add(self, key, data):
+        if key not in self.indexes:
+            self.entries.append(data)
+            self.indexes[key] = len(self.entries)
+        return self.indexes[key]
+
+    def utf8(self, text):
+        encoded = text.encode('utf-8')
+        return self.add(('utf8', text), struct.pack('>BH', 1, len(encoded)) + encoded)
+
+    def cls(self, name):
+        return self.add(('class', name), struct.pack('>BH', 7, self.utf8(name)))
+
+    def string(self, text):
+        return self.add(('string', text), struct.pack('>BH', 8, self.utf8(text)))
+
+    def name_and_type(self, name, descriptor):
+        return self.add(('nat', name, descriptor), struct.pack('>BHH', 12, self.utf8(name), self.utf8(descriptor)))
+
+    def field(self, owner, name, descriptor):
+        return self.add(('field', owner, name, descriptor),
+                        struct.pack('>BHH', 9, self.cls(owner), self.name_and_type(name, descriptor)))
+
+    def method(self, owner, name, descriptor):
+        return self.add(('method', owner, name, descriptor),
+                        struct.pack('>BHH', 10, self.cls(owner), self.name_and_type(name, descriptor)))
+
+    def method_handle(self, kind, reference):
+        return self.add(('handle', kind, reference), struct.pack('>BBH', 15, kind, reference))
+
+    def method_type(self, descriptor):
+        return self.add(('method_type', descriptor), struct.pack('>BH', 16, self.utf8(descriptor)))
+
+    def dynamic(self, bootstrap_index, name, descriptor):
+        return self.add(('dynamic', bootstrap_index, name, descriptor),
+                        struct.pack('>BHH', 17, bootstrap_index, self.name_and_type(name, descriptor)))
+
+    def serialize(self):
+        return struct.pack('>H', len(self.entries) + 1) + b''.join(self.entries)
+
+
+class ClassFile:
+    def __init__(self, name, major_version, super_name='java/lang/Object'):
+        self.pool = Pool()
+        self.major_version = major_version
+        self.this_class = self.pool.cls(name)
+        self.super_class = self.pool.cls(super_name)
+        self.fields = []
+        self.methods = []
+        self.attributes = []
+
+    def add_static_field(self, name, descriptor, flags=ACC_STATIC):
+        self.fields.append(struct.pack('>HHHH', flags, self.pool.utf8(name), self.pool.utf8(descriptor), 0))
+
+    # Handlers are (start_pc, end_pc, handler_pc, catch_type) tuples, with a catch_type of 0
+    # catching everything.
+    def add_static_method(self, name, descriptor, max_stack, max_locals, bytecode, handlers=(), flags=ACC_STATIC):
+        code = (struct.pack('>HHI', max_stack, max_locals, len(bytecode)) + bytecode
+                + struct.pack('>H', len(handlers)) + b''.join(struct.pack('>HHHH', *handler) for handler in handlers)
+                + struct.pack('>H', 0))
+        self.methods.append(struct.pack('>HHHH', flags, self.pool.utf8(name), self.pool.utf8(descriptor), 1)
+                            + self.attribute('Code', code))
+
+    def add_bootstrap_methods(self, bootstrap_methods):
+        data = struct.pack('>H', len(bootstrap_methods))
+        for handle, arguments in bootstrap_methods:
+            data += struct.pack('>HH', handle, len(arguments))
+            data += b''.join(struct.pack('>H', argument) for argument in arguments)
+        self.attributes.append(self.attribute('BootstrapMethods', data))
+
+    def add_nest_host(self, host):
+        self.attributes.append(self.attribute('NestHost', struct.pack('>H', self.pool.cls(host))))
+
+    def add_nest_members(self, members):
+        data = struct.pack('>H', len(members)) + b''.join(struct.pack('>H', self.pool.cls(member)) for member in members)
+        self.attributes.append(self.attribute('NestMembers', data))
+
+    def attribute(self, name, data):
+        return struct.pack('>HI', self.pool.utf8(name), len(data)) + data
+
+    def write(self, filename):
+        # Every constant has been added by now, so the pool can be written out ahead of the members.
+        class_file = (struct.pack('>IHH', 0xcafebabe, 0, self.major_version) + self.pool.serialize()
+                      + struct.pack('>HHHH', ACC_PUBLIC | ACC_SUPER, self.this_class, self.super_class, 0)
+                      + struct.pack('>H', len(self.fields)) + b''.join(self.fields)
+                      + struct.pack('>H', len(self.methods)) + b''.join(self.methods)
+                      + struct.pack('>H', len(self.attributes)) + b''.join(self.attributes))
+        with open(os.path.join(os.path.dirname(os.path.abspath(__file__)), filename), 'wb') as out:
+            out.write(class_file)
@@ -0,0 +1,14 @@
+// Compared with changed/Edited, which edits its constants, throws clauses and code.
+public class Edited {
+    static final int LIMIT = 10;
+
+    int clamp(int value) {
+        if (value > LIMIT) {
+            return LIMIT;
+        }
+        return value;
+    }
+
+    void check() {
+    }
+}
@@ -0,0 +1,15 @@
+// Declares the same members as changed/Reordered, in another order, so the two have constant
+// pools in different orders but mean the same.
+public class Reordered {
+    static final String GREETING = "hello";
+    int count;
+
+    int add(int amount) {
+        count += amount;
+        return count;
+    }
+
+    String greet(String name) {
+        return name.length() == 0 ? GREETING : name;
+    }
+}
@@ -0,0 +1,19 @@
+public class Edited implements Runnable {
+    static final int LIMIT = 20;
+
+    int clamp(int value) {
+        if (value < 0) {
+            return 0;
+        }
+        if (value > LIMIT) {
+            return LIMIT;
+        }
+        return value;
+    }
+
+    void check() throws java.io.IOException {
+    }
+
+    public void run() {
+    }
+}
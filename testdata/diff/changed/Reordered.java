@@ -0,0 +1,13 @@
+public class Reordered {
+    int count;
+    static final String GREETING = "hello";
+
+    String greet(String name) {
+        return name.length() == 0 ? GREETING : name;
+    }
+
+    int add(int amount) {
+        count += amount;
+        return count;
+    }
+}
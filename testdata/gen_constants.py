@@ -0,0 +1,75 @@
+#!/usr/bin/env python3
+# Assembles Constants.class, which exercises constant pool entries that javac never loads with
+# ldc directly: method types, method handles and dynamically-computed constants. It also uses
+# ldc_w throughout, which javac only emits once the constant pool has more than 256 entries.
+#
+# The class is equivalent to the following, where CONDY stands for a dynamic constant named
+# "answer" of type String, bootstrapped by bootstrap() with the static argument "forty-two":
+#
+#   public class Constants {
+#       static int bootstrapCalls;
+#
+#       static String bootstrap(MethodHandles.Lookup lookup, String name, Class<?> type, String arg) {
+#           bootstrapCalls++;
+#           return arg;
+#       }
+#
+#       static Object dynamicConstant() { return CONDY; }
+#       static int dynamicConstantIsCached() { return CONDY == CONDY ? bootstrapCalls : -1; }
+#       static Object methodType() { return (I[Ljava/lang/String;)J; }
+#       static Object staticMethodHandle() { return Constants::bootstrap; }
+#       static Object virtualMethodHandle() { return Holder::get; }
+#       static Object fieldGetterHandle() { return <getter for Holder.value>; }
+#       static Object constructorHandle() { return Holder::new; }
+#       static int methodHandleIsCached() { return (Constants::bootstrap == Constants::bootstrap) ? 1 : 0; }
+#       static Object missingMethodHandle() { return Constants::missing; }
+#       static String wideString() { return "loaded with ldc_w"; }
+#   }
+import struct
+
+from classfile import ClassFile
+
+LDC_W, ICONST_M1, ICONST_0, ICONST_1 = 0x13, 0x02, 0x03, 0x04
+ALOAD_3, IADD, IF_ACMPNE, IRETURN, ARETURN = 0x2d, 0x60, 0xa6, 0xac, 0xb0
+GETSTATIC, PUTSTATIC = 0xb2, 0xb3
+
+constants = ClassFile('Constants', 55)
+pool = constants.pool
+
+bootstrap_descriptor = ('(Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;'
+                        'Ljava/lang/Class;Ljava/lang/String;)Ljava/lang/String;')
+bootstrap_handle = pool.method_handle(6, pool.method('Constants', 'bootstrap', bootstrap_descriptor))
+condy = pool.dynamic(0, 'answer', 'Ljava/lang/String;')
+calls = pool.field('Constants', 'bootstrapCalls', 'I')
+
+
+def ldc_w(index):
+    return struct.pack('>BH', LDC_W, index)
+
+
+constants.add_static_field('bootstrapCalls', 'I')
+constants.add_static_method('bootstrap', bootstrap_descriptor, 2, 4,
+    struct.pack('>BH', GETSTATIC, calls) + bytes([ICONST_1, IADD]) + struct.pack('>BH', PUTSTATIC, calls)
+    + bytes([ALOAD_3, ARETURN]))
+constants.add_static_method('dynamicConstant', '()Ljava/lang/Object;', 1, 0, ldc_w(condy) + bytes([ARETURN]))
+constants.add_static_method('dynamicConstantIsCached', '()I', 2, 0,
+    ldc_w(condy) + ldc_w(condy) + struct.pack('>Bh', IF_ACMPNE, 7)
+    + struct.pack('>BH', GETSTATIC, calls) + bytes([IRETURN, ICONST_M1, IRETURN]))
+constants.add_static_method('methodType', '()Ljava/lang/Object;', 1, 0,
+    ldc_w(pool.method_type('(I[Ljava/lang/String;)J')) + bytes([ARETURN]))
+constants.add_static_method('staticMethodHandle', '()Ljava/lang/Object;', 1, 0, ldc_w(bootstrap_handle) + bytes([ARETURN]))
+constants.add_static_method('virtualMethodHandle', '()Ljava/lang/Object;', 1, 0,
+    ldc_w(pool.method_handle(5, pool.method('Holder', 'get', '()I'))) + bytes([ARETURN]))
+constants.add_static_method('fieldGetterHandle', '()Ljava/lang/Object;', 1, 0,
+    ldc_w(pool.method_handle(1, pool.field('Holder', 'value', 'I'))) + bytes([ARETURN]))
+constants.add_static_method('constructorHandle', '()Ljava/lang/Object;', 1, 0,
+    ldc_w(pool.method_handle(8, pool.method('Holder', '<init>', '()V'))) + bytes([ARETURN]))
+constants.add_static_method('methodHandleIsCached', '()I', 2, 0,
+    ldc_w(bootstrap_handle) + ldc_w(bootstrap_handle) + struct.pack('>Bh', IF_ACMPNE, 5)
+    + bytes([ICONST_1, IRETURN, ICONST_0, IRETURN]))
+constants.add_static_method('missingMethodHandle', '()Ljava/lang/Object;', 1, 0,
+    ldc_w(pool.method_handle(6, pool.method('Constants', 'missing', '()V'))) + bytes([ARETURN]))
+constants.add_static_method('wideString', '()Ljava/lang/String;', 1, 0,
+    ldc_w(pool.string('loaded with ldc_w')) + bytes([ARETURN]))
+constants.add_bootstrap_methods([(bootstrap_handle, [pool.string('forty-two')])])
+constants.write('Constants.class')
@@ -0,0 +1,26 @@
+#!/usr/bin/env python3
+# Assembles FieldMisuse.class, a version 52 class whose methods use its instance field on objects
+# that don't have it. Classes from Java 7 on are only verified on request, so the interpreter
+# must reject these itself.
+import struct
+
+from classfile import ClassFile, ACC_PUBLIC
+
+ICONST_1, ICONST_5, DUP, IRETURN, RETURN = 0x04, 0x08, 0x59, 0xac, 0xb1
+GETFIELD, PUTFIELD, INVOKESPECIAL, NEW, NEWARRAY, T_INT = 0xb4, 0xb5, 0xb7, 0xbb, 0xbc, 10
+
+field_misuse = ClassFile('FieldMisuse', 52)
+value = field_misuse.pool.field('FieldMisuse', 'value', 'I')
+new_object = (struct.pack('>BH', NEW, field_misuse.pool.cls('java/lang/Object')) + bytes([DUP])
+              + struct.pack('>BH', INVOKESPECIAL, field_misuse.pool.method('java/lang/Object', '<init>', '()V')))
+
+field_misuse.add_static_field('value', 'I', flags=ACC_PUBLIC)
+field_misuse.add_static_method('getFieldOfObject', '()I', 2, 0, new_object + struct.pack('>BH', GETFIELD, value) + bytes([IRETURN]))
+field_misuse.add_static_method('putFieldOfObject', '()V', 3, 0,
+    new_object + bytes([ICONST_1]) + struct.pack('>BH', PUTFIELD, value) + bytes([RETURN]))
+field_misuse.add_static_method('getFieldOfArray', '()I', 1, 0,
+    bytes([ICONST_5, NEWARRAY, T_INT]) + struct.pack('>BH', GETFIELD, value) + bytes([IRETURN]))
+field_misuse.add_static_method('putFieldOfArray', '()V', 2, 0,
+    bytes([ICONST_5, NEWARRAY, T_INT, ICONST_1]) + struct.pack('>BH', PUTFIELD, value) + bytes([RETURN]))
+
+field_misuse.write('FieldMisuse.class')
@@ -0,0 +1,111 @@
+#!/usr/bin/env python3
+# Assembles jimage/modules, a small container in the jimage format of a JDK's lib/modules, for the
+# jimage tests. It holds two of joyvm's bootstrap classes in java.base, the second compressed as
+# jlink's zip plugin does, and a text resource in a second module.
+import struct
+import zlib
+
+HASH_MULTIPLIER = 0x01000193
+
+
+def hash_code(name, seed=HASH_MULTIPLIER):
+    for byte in name.encode('utf-8'):
+        seed = ((seed * HASH_MULTIPLIER) ^ byte) & 0xffffffff
+    return seed & 0x7fffffff
+
+
+class Strings:
+    def __init__(self):
+        self.data = bytearray(b'\0')
+        self.offsets = {'': 0}
+
+    def add(self, string):
+        if string not in self.offsets:
+            self.offsets[string] = len(self.data)
+            self.data += string.encode('utf-8') + b'\0'
+        return self.offsets[string]
+
+
+def attribute(kind, value):
+    length = max(1, (value.bit_length() + 7) // 8)
+    return bytes([(kind << 3) | (length - 1)]) + value.to_bytes(length, 'big')
+
+
+def compress(strings, content):
+    compressed = zlib.compress(content)
+    header = struct.pack('<IQQIIB', 0xcafefafa, len(compressed), len(content), strings.add('zip'), 0, 1)
+    return header + compressed
+
+
+def main():
+    with open('../bootstrap/java/lang/Object.class', 'rb') as f:
+        object_class = f.read()
+    with open('../bootstrap/java/lang/String.class', 'rb') as f:
+        string_class = f.read()
+
+    # (module, parent, base, extension, content, compressed)
+    resources = [
+        ('java.base', 'java/lang', 'Object', 'class', object_class, False),
+        ('java.base', 'java/lang', 'String', 'class', string_class, True),
+        ('jdk.example', 'jdk/example', 'message', 'txt', b'Hello from a jimage\n', False),
+    ]
+
+    strings = Strings()
+    names = []
+    locations = bytearray()
+    location_offsets = []
+    contents = bytearray()
+    for module, parent, base, extension, content, compressed in resources:
+        names.append('/%s/%s/%s.%s' % (module, parent, base, extension))
+        stored = compress(strings, content) if compressed else content
+        location_offsets.append(len(locations))
+        locations += attribute(1, strings.add(module))
+        locations += attribute(2, strings.add(parent))
+        locations += attribute(3, strings.add(base))
+        locations += attribute(4, strings.add(extension))
+        if contents:
+            locations += attribute(5, len(contents))
+        if compressed:
+            locations += attribute(6, len(stored))
+        locations += attribute(7, len(content))
+        locations += b'\0'
+        contents += stored
+
+    # A perfect hash table, built as jlink's PerfectHashBuilder does: names that share a bucket
+    # get a seed that spreads them over free slots; lone names are placed directly.
+    length = len(names)
+    buckets = [[] for _ in range(length)]
+    for index, name in enumerate(names):
+        buckets[hash_code(name) % length].append(index)
+    redirect = [0] * length
+    slots = [None] * length
+    for bucket, members in sorted(enumerate(buckets), key=lambda entry: -len(entry[1])):
+        if len(members) > 1:
+            seed = 1
+            while True:
+                chosen = [hash_code(names[member], seed) % length for member in members]
+                if len(set(chosen)) == len(chosen) and all(slots[slot] is None for slot in chosen):
+                    break
+                seed += 1
+            for member, slot in zip(members, chosen):
+                slots[slot] = member
+            redirect[bucket] = seed
+    free = [slot for slot in range(length) if slots[slot] is None]
+    for bucket, members in enumerate(buckets):
+        if len(members) == 1:
+            slot = free.pop()
+            slots[slot] = members[0]
+            redirect[bucket] = -1 - slot
+
+    header = struct.pack('<IIIIIII', 0xcafedada, 1 << 16, 0, len(names), length, len(locations), len(strings.data))
+    with open('jimage/modules', 'wb') as f:
+        f.write(header)
+        f.write(struct.pack('<%di' % length, *redirect))
+        f.write(struct.pack('<%dI' % length, *[location_offsets[slots[slot]] for slot in range(length)]))
+        f.write(locations)
+        f.write(strings.data)
+        f.write(contents)
+
+
+if __name__ == '__main__':
+    main()
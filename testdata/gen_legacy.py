@@ -0,0 +1,67 @@
+#!/usr/bin/env python3
+# Assembles Legacy.class and Unverifiable.class, version 49 classes that are checked by the type
+# inference verifier. Legacy's methods use subroutines the way javac did for finally blocks before
+# Java 6, and must verify. Each of Unverifiable's methods is invalid in a different way.
+import struct
+
+from classfile import ClassFile
+
+NOP, ICONST_0, ICONST_1, ICONST_2, LCONST_0, FCONST_1, FCONST_2 = 0x00, 0x03, 0x04, 0x05, 0x09, 0x0c, 0x0d
+ILOAD_0, ILOAD_1, ILOAD_2, FLOAD_1, ALOAD_2 = 0x1a, 0x1b, 0x1c, 0x23, 0x2c
+ISTORE_0, ISTORE_1, ISTORE_2, FSTORE_0, FSTORE_1, ASTORE_0, ASTORE_1, ASTORE_2, ASTORE_3 = (
+    0x3b, 0x3c, 0x3d, 0x43, 0x44, 0x4b, 0x4c, 0x4d, 0x4e)
+POP, IADD, IMUL, F2I, IFEQ, GOTO, JSR, RET = 0x57, 0x60, 0x68, 0x8b, 0x99, 0xa7, 0xa8, 0xa9
+IRETURN, RETURN, GETSTATIC, PUTSTATIC, INVOKEVIRTUAL, NEW, ATHROW = 0xac, 0xb1, 0xb2, 0xb3, 0xb6, 0xbb, 0xbf
+
+
+def branch(opcode, offset):
+    return struct.pack('>Bh', opcode, offset)
+
+
+legacy = ClassFile('Legacy', 49)
+counter = legacy.pool.field('Legacy', 'counter', 'I')
+increment_counter = (struct.pack('>BH', GETSTATIC, counter) + bytes([ICONST_1, IADD])
+                     + struct.pack('>BH', PUTSTATIC, counter))
+
+legacy.add_static_field('counter', 'I')
+
+# try { return x * 2; } finally { counter++; }
+legacy.add_static_method('finallyIncrements', '(I)I', 2, 4,
+    bytes([ILOAD_0, ICONST_2, IMUL, ISTORE_1]) + branch(JSR, 11) + bytes([ILOAD_1, IRETURN])
+    + bytes([ASTORE_2]) + branch(JSR, 5) + bytes([ALOAD_2, ATHROW])
+    + bytes([ASTORE_3]) + increment_counter + bytes([RET, 3]),
+    handlers=[(0, 4, 9, 0)])
+
+# Calls a subroutine while local 1 holds an int, and again while it holds a float. The subroutine
+# doesn't touch local 1, so each caller still sees its own type there afterwards. Returns 3.
+legacy.add_static_method('twoCallers', '()I', 2, 3,
+    bytes([ICONST_1, ISTORE_1]) + branch(JSR, 15) + bytes([ILOAD_1, ISTORE_2, FCONST_2, FSTORE_1])
+    + branch(JSR, 8) + bytes([FLOAD_1, F2I, ILOAD_2, IADD, IRETURN])
+    + bytes([ASTORE_0, RET, 0]))
+
+# A subroutine that calls another, as nested finally blocks do. Returns the incremented counter.
+legacy.add_static_method('nestedSubroutines', '()I', 2, 2,
+    branch(JSR, 7) + struct.pack('>BH', GETSTATIC, counter) + bytes([IRETURN])
+    + bytes([ASTORE_0]) + branch(JSR, 5) + bytes([RET, 0])
+    + bytes([ASTORE_1]) + increment_counter + bytes([RET, 1]))
+
+legacy.write('Legacy.class')
+
+
+unverifiable = ClassFile('Unverifiable', 49)
+hash_code = unverifiable.pool.method('java/lang/Object', 'hashCode', '()I')
+object_class = unverifiable.pool.cls('java/lang/Object')
+
+unverifiable.add_static_method('loadsFloatAsInt', '()I', 1, 1, bytes([FCONST_1, FSTORE_0, ILOAD_0, IRETURN]))
+unverifiable.add_static_method('retWithoutJsr', '()V', 1, 1, bytes([ICONST_0, ISTORE_0, RET, 0]))
+unverifiable.add_static_method('recursiveSubroutine', '()V', 1, 1,
+    branch(JSR, 4) + bytes([RETURN, ASTORE_0]) + branch(JSR, -1) + bytes([RET, 0]))
+unverifiable.add_static_method('mergesIntAndFloat', '(Z)I', 1, 1,
+    bytes([ILOAD_0]) + branch(IFEQ, 7) + bytes([ICONST_1]) + branch(GOTO, 4) + bytes([FCONST_1, IRETURN]))
+unverifiable.add_static_method('fallsOffEnd', '()V', 0, 0, bytes([NOP]))
+unverifiable.add_static_method('splitsLong', '()V', 2, 0, bytes([LCONST_0, POP, RETURN]))
+unverifiable.add_static_method('usesUninitialized', '()I', 1, 0,
+    struct.pack('>BH', NEW, object_class) + struct.pack('>BH', INVOKEVIRTUAL, hash_code) + bytes([IRETURN]))
+unverifiable.add_static_method('overflowsStack', '()V', 0, 0, bytes([ICONST_0, POP, RETURN]))
+
+unverifiable.write('Unverifiable.class')
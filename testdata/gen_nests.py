@@ -0,0 +1,46 @@
+#!/usr/bin/env python3
+# Assembles Nest.class and Nest$Member.class, nestmates that use each other's private members
+# directly, as javac compiles nested classes from Java 11 on, and Impostor.class, which names
+# Nest as its nest host although Nest doesn't list it as a member. Equivalent to:
+#
+#   public class Nest {
+#       private static int secret;
+#       private static int reveal() { return 42; }
+#       static int callMember() { return Member.hidden(); }
+#
+#       public static class Member {
+#           static int peek() { return reveal() + secret; }
+#           private static int hidden() { return 7; }
+#       }
+#   }
+#
+#   public class Impostor {
+#       static int peek() { return Nest.reveal(); }
+#   }
+import struct
+
+from classfile import ClassFile, ACC_PRIVATE, ACC_STATIC
+
+BIPUSH, IADD, IRETURN, GETSTATIC, INVOKESTATIC = 0x10, 0x60, 0xac, 0xb2, 0xb8
+
+nest = ClassFile('Nest', 55)
+nest.add_static_field('secret', 'I', flags=ACC_PRIVATE | ACC_STATIC)
+nest.add_static_method('reveal', '()I', 1, 0, bytes([BIPUSH, 42, IRETURN]), flags=ACC_PRIVATE | ACC_STATIC)
+nest.add_static_method('callMember', '()I', 1, 0,
+    struct.pack('>BH', INVOKESTATIC, nest.pool.method('Nest$Member', 'hidden', '()I')) + bytes([IRETURN]))
+nest.add_nest_members(['Nest$Member'])
+nest.write('Nest.class')
+
+member = ClassFile('Nest$Member', 55)
+member.add_static_method('peek', '()I', 2, 0,
+    struct.pack('>BH', INVOKESTATIC, member.pool.method('Nest', 'reveal', '()I'))
+    + struct.pack('>BH', GETSTATIC, member.pool.field('Nest', 'secret', 'I')) + bytes([IADD, IRETURN]))
+member.add_static_method('hidden', '()I', 1, 0, bytes([BIPUSH, 7, IRETURN]), flags=ACC_PRIVATE | ACC_STATIC)
+member.add_nest_host('Nest')
+member.write('Nest$Member.class')
+
+impostor = ClassFile('Impostor', 55)
+impostor.add_static_method('peek', '()I', 1, 0,
+    struct.pack('>BH', INVOKESTATIC, impostor.pool.method('Nest', 'reveal', '()I')) + bytes([IRETURN]))
+impostor.add_nest_host('Nest')
+impostor.write('Impostor.class')
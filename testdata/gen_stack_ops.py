@@ -0,0 +1,60 @@
+#!/usr/bin/env python3
+# Assembles StackOps.class, which exercises every form of the pop, dup and swap instructions.
+# Each method pushes some values, applies one instruction, and then folds whatever is left on the
+# operand stack into a decimal number, one digit per value starting from the top of the stack.
+# Ints and longs are both folded as single digits, so a method pushing int 1 and long 5 and then
+# applying dup2_x1 (giving 5, 1, 5) returns 515.
+import struct
+
+from classfile import ClassFile
+
+BIPUSH, I2L, L2I, ISTORE_0, ILOAD_0, ICONST_0, IMUL, IADD, IRETURN = 0x10, 0x85, 0x88, 0x3b, 0x1a, 0x03, 0x68, 0x60, 0xac
+OPCODES = {'pop': 0x57, 'pop2': 0x58, 'dup': 0x59, 'dup_x1': 0x5a, 'dup_x2': 0x5b,
+           'dup2': 0x5c, 'dup2_x1': 0x5d, 'dup2_x2': 0x5e, 'swap': 0x5f}
+
+
+def push(value):
+    # Ints are written as 'I3', longs as 'J7'.
+    code = struct.pack('>Bb', BIPUSH, int(value[1]))
+    return code + bytes([I2L]) if value[0] == 'J' else code
+
+
+def fold(value_types):
+    code = bytes([ICONST_0, ISTORE_0])
+    for value_type in reversed(value_types):
+        if value_type == 'J':
+            code += bytes([L2I])
+        code += bytes([ILOAD_0]) + struct.pack('>Bb', BIPUSH, 10) + bytes([IMUL, IADD, ISTORE_0])
+    return code + bytes([ILOAD_0, IRETURN])
+
+
+stack_ops = ClassFile('StackOps', 52)
+# Method name, values pushed (bottom first), instruction, types left on the stack (bottom first).
+cases = [
+    ('pop', ['I1', 'I2'], 'pop', 'I'),
+    ('pop2OfTwoInts', ['I1', 'I2', 'I3'], 'pop2', 'I'),
+    ('pop2OfLong', ['I1', 'J5'], 'pop2', 'I'),
+    ('dup', ['I1', 'I2'], 'dup', 'III'),
+    ('dupX1', ['I1', 'I2', 'I3'], 'dup_x1', 'IIII'),
+    ('dupX2OfThreeInts', ['I1', 'I2', 'I3', 'I4'], 'dup_x2', 'IIIII'),
+    ('dupX2OverLong', ['I1', 'J5', 'I2'], 'dup_x2', 'IIJI'),
+    ('dup2OfTwoInts', ['I1', 'I2', 'I3'], 'dup2', 'IIIII'),
+    ('dup2OfLong', ['I1', 'J5'], 'dup2', 'IJJ'),
+    ('dup2X1OfTwoInts', ['I1', 'I2', 'I3', 'I4'], 'dup2_x1', 'IIIIII'),
+    ('dup2X1OfLong', ['I1', 'I2', 'J5'], 'dup2_x1', 'IJIJ'),
+    ('dup2X2OfFourInts', ['I1', 'I2', 'I3', 'I4'], 'dup2_x2', 'IIIIII'),
+    ('dup2X2OfLongOverTwoInts', ['I1', 'I2', 'J5'], 'dup2_x2', 'JIIJ'),
+    ('dup2X2OfTwoIntsOverLong', ['J5', 'I1', 'I2'], 'dup2_x2', 'IIJII'),
+    ('dup2X2OfLongOverLong', ['I1', 'J5', 'J6'], 'dup2_x2', 'IJJJ'),
+    ('swap', ['I1', 'I2', 'I3'], 'swap', 'III'),
+    # These are invalid, since the instruction would split a long in two.
+    ('popOfLong', ['J5'], 'pop', ''),
+    ('dupX1OverLong', ['J5', 'I1'], 'dup_x1', 'IJI'),
+    ('dup2OfIntAndLong', ['J5', 'I1'], 'dup2', 'JIJI'),
+    ('swapWithLong', ['J5', 'I1'], 'swap', 'IJ'),
+]
+for name, values, instruction, remaining in cases:
+    code = b''.join(push(value) for value in values) + bytes([OPCODES[instruction]]) + fold(remaining)
+    stack_ops.add_static_method(name, '()I', 12, 1, code)
+
+stack_ops.write('StackOps.class')
@@ -0,0 +1,29 @@
+#!/usr/bin/env python3
+# Assembles Subroutines.class, which uses the jsr and ret instructions that javac stopped emitting
+# in Java 6. Class files from version 51 onwards may not contain them, so this is version 49.
+import struct
+
+from classfile import ClassFile
+
+ICONST_1, ASTORE_0, IADD, IRETURN = 0x04, 0x4b, 0x60, 0xac
+GETSTATIC, PUTSTATIC, JSR, RET, JSR_W, WIDE, ASTORE = 0xb2, 0xb3, 0xa8, 0xa9, 0xc9, 0xc4, 0x3a
+
+subroutines = ClassFile('Subroutines', 49)
+counter = subroutines.pool.field('Subroutines', 'counter', 'I')
+increment_counter = (struct.pack('>BH', GETSTATIC, counter) + bytes([ICONST_1, IADD])
+                     + struct.pack('>BH', PUTSTATIC, counter))
+return_counter = struct.pack('>BH', GETSTATIC, counter) + bytes([IRETURN])
+
+subroutines.add_static_field('counter', 'I')
+
+# Calls a subroutine that increments the counter twice, then returns the counter.
+subroutines.add_static_method('callTwice', '()I', 2, 1,
+    struct.pack('>Bh', JSR, 10) + struct.pack('>Bh', JSR, 7) + return_counter
+    + bytes([ASTORE_0]) + increment_counter + bytes([RET, 0]))
+
+# As above, but with jsr_w, and with the return address held in local 300.
+subroutines.add_static_method('callTwiceWide', '()I', 2, 301,
+    struct.pack('>Bi', JSR_W, 14) + struct.pack('>Bi', JSR_W, 9) + return_counter
+    + struct.pack('>BBH', WIDE, ASTORE, 300) + increment_counter + struct.pack('>BBH', WIDE, RET, 300))
+
+subroutines.write('Subroutines.class')
@@ -0,0 +1,13 @@
+package com.example;
+
+import com.example.lib.Lib;
+
+public class App {
+    public static int answer() {
+        return Lib.answer() + 1;
+    }
+
+    public static void main(String[] args) {
+        answer();
+    }
+}
@@ -0,0 +1,7 @@
+package com.example;
+
+public class Release {
+    public static int version() {
+        return 11;
+    }
+}
@@ -0,0 +1,8 @@
+package com.example.sealed;
+
+// In sealed.jar, whose manifest seals this package.
+public class Sealed {
+    public static Package getPackage() {
+        return Sealed.class.getPackage();
+    }
+}
@@ -0,0 +1,8 @@
+package com.example.sealed;
+
+// In split.jar, which doesn't seal the package that sealed.jar does.
+public class Intruder {
+    public static Package getPackage() {
+        return Intruder.class.getPackage();
+    }
+}
@@ -0,0 +1,17 @@
+public class Changed {
+    public static int removedField = 1;
+    public static int staticField = 2;
+    public int instanceField = 3;
+
+    public static int removedMethod() {
+        return 4;
+    }
+
+    public static int staticMethod() {
+        return 5;
+    }
+
+    public int instanceMethod() {
+        return 6;
+    }
+}
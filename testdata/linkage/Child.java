@@ -0,0 +1 @@
+public class Child extends Sealed {}
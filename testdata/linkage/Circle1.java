@@ -0,0 +1 @@
+public class Circle1 extends Circle2 {}
@@ -0,0 +1 @@
+public class Derived extends Base {}
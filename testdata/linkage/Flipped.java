@@ -0,0 +1,5 @@
+public class Flipped {
+    public static int run() {
+        return 7;
+    }
+}
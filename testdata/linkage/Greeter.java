@@ -0,0 +1,5 @@
+public class Greeter {
+    public String greet() {
+        return "Hello";
+    }
+}
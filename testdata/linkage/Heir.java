@@ -0,0 +1,23 @@
+// Subclasses may still call Exposed.protectedMethod once it's protected. They may use its
+// protected instance members on instances of Heir, but not on other instances of Exposed.
+public class Heir extends other.Exposed {
+    public static int callProtected() {
+        return protectedMethod();
+    }
+
+    public static int ownProtectedField() {
+        return new Heir().protectedField;
+    }
+
+    public static int ownProtectedInstanceMethod() {
+        return new Heir().protectedInstanceMethod();
+    }
+
+    public static int othersProtectedField() {
+        return new other.Exposed().protectedField;
+    }
+
+    public static int othersProtectedInstanceMethod() {
+        return new other.Exposed().protectedInstanceMethod();
+    }
+}
@@ -0,0 +1,64 @@
+// Uses classes that are changed incompatibly after this class is compiled; the changed versions
+// are under changed/.
+public class Linkage {
+    public static int missingClass() {
+        return new Missing().hashCode();
+    }
+
+    public static int removedField() {
+        return Changed.removedField;
+    }
+
+    public static int removedMethod() {
+        return Changed.removedMethod();
+    }
+
+    public static int staticFieldMadeInstance() {
+        return Changed.staticField;
+    }
+
+    public static int instanceFieldMadeStatic() {
+        return new Changed().instanceField;
+    }
+
+    public static int staticMethodMadeInstance() {
+        return Changed.staticMethod();
+    }
+
+    public static int instanceMethodMadeStatic() {
+        return new Changed().instanceMethod();
+    }
+
+    public static int classMadeInterface() {
+        return Flipped.run();
+    }
+
+    public static int unimplementedMethod() {
+        Shape shape = new Square();
+        return shape.corners();
+    }
+
+    public static int circularHierarchy() {
+        return new Circle1().hashCode();
+    }
+
+    public static int privateField() {
+        return other.Exposed.privateField;
+    }
+
+    public static int protectedMethod() {
+        return other.Exposed.protectedMethod();
+    }
+
+    public static int packageMethod() {
+        return other.Exposed.packageMethod();
+    }
+
+    public static int protectedMethodFromSubclass() {
+        return Heir.callProtected();
+    }
+
+    public static int classMadePackagePrivate() {
+        return other.Hidden.run();
+    }
+}
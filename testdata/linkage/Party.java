@@ -0,0 +1 @@
+public class Party implements Contract {}
@@ -0,0 +1,5 @@
+public class Polite extends Greeter {
+    public String greet() {
+        return "Good morning";
+    }
+}
@@ -0,0 +1,5 @@
+public interface Shape {
+    int sides();
+
+    int corners();
+}
@@ -0,0 +1,9 @@
+public class Square implements Shape {
+    public int sides() {
+        return 4;
+    }
+
+    public int corners() {
+        return 4;
+    }
+}
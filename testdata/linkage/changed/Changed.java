@@ -0,0 +1,12 @@
+public class Changed {
+    public int staticField = 2;
+    public static int instanceField = 3;
+
+    public int staticMethod() {
+        return 5;
+    }
+
+    public static int instanceMethod() {
+        return 6;
+    }
+}
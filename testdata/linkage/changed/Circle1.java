@@ -0,0 +1,2 @@
+// Compiled without the original Circle1, which extends this class.
+public class Circle1 {}
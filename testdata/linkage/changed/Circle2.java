@@ -0,0 +1 @@
+public class Circle2 extends Circle1 {}
@@ -0,0 +1,5 @@
+public interface Flipped {
+    static int run() {
+        return 7;
+    }
+}
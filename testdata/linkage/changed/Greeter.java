@@ -0,0 +1,5 @@
+public class Greeter {
+    public final String greet() {
+        return "Hello";
+    }
+}
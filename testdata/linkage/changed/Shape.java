@@ -0,0 +1,4 @@
+// The interface that changed/Square was compiled against, before corners() was added.
+public interface Shape {
+    int sides();
+}
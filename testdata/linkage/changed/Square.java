@@ -0,0 +1,5 @@
+public class Square implements Shape {
+    public int sides() {
+        return 4;
+    }
+}
@@ -0,0 +1,19 @@
+package other;
+
+public class Exposed {
+    private static int privateField = 1;
+
+    protected static int protectedMethod() {
+        return 2;
+    }
+
+    static int packageMethod() {
+        return 3;
+    }
+
+    protected int protectedField = 4;
+
+    protected int protectedInstanceMethod() {
+        return 5;
+    }
+}
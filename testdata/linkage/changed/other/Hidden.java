@@ -0,0 +1,7 @@
+package other;
+
+class Hidden {
+    public static int run() {
+        return 4;
+    }
+}
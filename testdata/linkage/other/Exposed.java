@@ -0,0 +1,20 @@
+package other;
+
+// Its members are made less accessible under changed/.
+public class Exposed {
+    public static int privateField = 1;
+
+    public static int protectedMethod() {
+        return 2;
+    }
+
+    public static int packageMethod() {
+        return 3;
+    }
+
+    public int protectedField = 4;
+
+    public int protectedInstanceMethod() {
+        return 5;
+    }
+}
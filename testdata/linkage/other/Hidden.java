@@ -0,0 +1,7 @@
+package other;
+
+public class Hidden {
+    public static int run() {
+        return 4;
+    }
+}
@@ -0,0 +1,7 @@
+package com.example.api;
+
+public class Api {
+    public static int answer() {
+        return 42;
+    }
+}
@@ -0,0 +1,7 @@
+package com.example.api.internal;
+
+public class Hidden {
+    public static int secret() {
+        return 7;
+    }
+}
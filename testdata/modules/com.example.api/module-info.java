@@ -0,0 +1,4 @@
+// Exports its API but not its internals.
+module com.example.api {
+    exports com.example.api;
+}
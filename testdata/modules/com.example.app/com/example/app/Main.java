@@ -0,0 +1,16 @@
+package com.example.app;
+
+import com.example.api.Api;
+import com.example.api.internal.Hidden;
+
+public class Main {
+    public static int useApi() {
+        return Api.answer();
+    }
+
+    // Compiles because the internal package is exported to this module at compile time, but it
+    // isn't at runtime.
+    public static int useInternals() {
+        return Hidden.secret();
+    }
+}
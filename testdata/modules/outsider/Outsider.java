@@ -0,0 +1,14 @@
+import com.example.api.Api;
+import com.example.api.internal.Hidden;
+
+// On the classpath, so in the unnamed module, which reads every module but can still only use
+// the packages they export.
+public class Outsider {
+    public static int useApi() {
+        return Api.answer();
+    }
+
+    public static int useInternals() {
+        return Hidden.secret();
+    }
+}
@@ -0,0 +1,120 @@
+// The natives of Jni.java, built into a shared library by the native library tests. To keep the
+// tests from needing a JDK, this declares just enough of jni.h itself, and calls the JNI
+// functions through their indexes in the function table, as listed in the JNI specification.
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+typedef int32_t jint;
+typedef int64_t jlong;
+typedef uint8_t jboolean;
+typedef float jfloat;
+typedef double jdouble;
+typedef void *jobject;
+typedef jobject jclass;
+typedef jobject jstring;
+typedef jobject jintArray;
+typedef void *jmethodID;
+typedef void *jfieldID;
+typedef union {jboolean z; int8_t b; uint16_t c; int16_t s; jint i; jlong j; jfloat f; jdouble d; jobject l;} jvalue;
+typedef void *const *JNIEnv;
+
+#define JNI(env, index, type) ((type) (*(env))[index])
+#define FindClass(env, name) JNI(env, 6, jclass (*)(JNIEnv *, const char *))(env, name)
+#define ThrowNew(env, class, message) JNI(env, 14, jint (*)(JNIEnv *, jclass, const char *))(env, class, message)
+#define NewGlobalRef(env, object) JNI(env, 21, jobject (*)(JNIEnv *, jobject))(env, object)
+#define DeleteGlobalRef(env, object) JNI(env, 22, void (*)(JNIEnv *, jobject))(env, object)
+#define IsSameObject(env, left, right) JNI(env, 24, jboolean (*)(JNIEnv *, jobject, jobject))(env, left, right)
+#define GetObjectClass(env, object) JNI(env, 31, jclass (*)(JNIEnv *, jobject))(env, object)
+#define GetMethodID(env, class, name, sig) JNI(env, 33, jmethodID (*)(JNIEnv *, jclass, const char *, const char *))(env, class, name, sig)
+#define CallIntMethodA(env, object, method, args) JNI(env, 51, jint (*)(JNIEnv *, jobject, jmethodID, const jvalue *))(env, object, method, args)
+#define GetFieldID(env, class, name, sig) JNI(env, 94, jfieldID (*)(JNIEnv *, jclass, const char *, const char *))(env, class, name, sig)
+#define GetIntField(env, object, field) JNI(env, 100, jint (*)(JNIEnv *, jobject, jfieldID))(env, object, field)
+#define SetIntField(env, object, field, value) JNI(env, 109, void (*)(JNIEnv *, jobject, jfieldID, jint))(env, object, field, value)
+#define GetStaticMethodID(env, class, name, sig) JNI(env, 113, jmethodID (*)(JNIEnv *, jclass, const char *, const char *))(env, class, name, sig)
+#define CallStaticIntMethodA(env, class, method, args) JNI(env, 131, jint (*)(JNIEnv *, jclass, jmethodID, const jvalue *))(env, class, method, args)
+#define NewStringUTF(env, chars) JNI(env, 167, jstring (*)(JNIEnv *, const char *))(env, chars)
+#define GetStringUTFLength(env, string) JNI(env, 168, jint (*)(JNIEnv *, jstring))(env, string)
+#define GetStringUTFChars(env, string, is_copy) JNI(env, 169, const char *(*)(JNIEnv *, jstring, jboolean *))(env, string, is_copy)
+#define ReleaseStringUTFChars(env, string, chars) JNI(env, 170, void (*)(JNIEnv *, jstring, const char *))(env, string, chars)
+#define GetArrayLength(env, array) JNI(env, 171, jint (*)(JNIEnv *, jobject))(env, array)
+#define NewIntArray(env, length) JNI(env, 179, jintArray (*)(JNIEnv *, jint))(env, length)
+#define GetIntArrayRegion(env, array, start, length, buffer) JNI(env, 203, void (*)(JNIEnv *, jintArray, jint, jint, jint *))(env, array, start, length, buffer)
+#define SetIntArrayRegion(env, array, start, length, buffer) JNI(env, 211, void (*)(JNIEnv *, jintArray, jint, jint, const jint *))(env, array, start, length, buffer)
+#define ExceptionCheck(env) JNI(env, 228, jboolean (*)(JNIEnv *))(env)
+
+jint Java_Jni_add(JNIEnv *env, jclass class, jint a, jint b) {
+    return a + b;
+}
+
+jdouble Java_Jni_mix(JNIEnv *env, jclass class, jint a, jdouble b, jfloat c, jlong d, jboolean negate) {
+    jdouble mixed = a + b + c + d;
+    return negate ? -mixed : mixed;
+}
+
+jstring Java_Jni_greet(JNIEnv *env, jclass class, jstring name) {
+    const char *chars = GetStringUTFChars(env, name, NULL);
+    char greeting[64];
+    snprintf(greeting, sizeof greeting, "hello, %s (%d bytes)", chars, GetStringUTFLength(env, name));
+    ReleaseStringUTFChars(env, name, chars);
+    return NewStringUTF(env, greeting);
+}
+
+jint Java_Jni_sum(JNIEnv *env, jclass class, jintArray values) {
+    jint length = GetArrayLength(env, values);
+    jint *buffer = malloc(length * sizeof(jint));
+    GetIntArrayRegion(env, values, 0, length, buffer);
+    jint sum = 0;
+    for (jint i = 0; i < length; i++) {
+        sum += buffer[i];
+    }
+    free(buffer);
+    return sum;
+}
+
+jintArray Java_Jni_squares(JNIEnv *env, jclass class, jint length) {
+    jintArray array = NewIntArray(env, length);
+    for (jint i = 0; i < length; i++) {
+        jint square = i * i;
+        SetIntArrayRegion(env, array, i, 1, &square);
+    }
+    return array;
+}
+
+jint Java_Jni_bump(JNIEnv *env, jobject this, jint by) {
+    jfieldID count = GetFieldID(env, GetObjectClass(env, this), "count", "I");
+    jint bumped = GetIntField(env, this, count) + by;
+    SetIntField(env, this, count, bumped);
+    return bumped;
+}
+
+jint Java_Jni_callBack(JNIEnv *env, jclass class, jobject jni, jint value) {
+    jobject global = NewGlobalRef(env, jni);
+    if (!IsSameObject(env, global, jni)) {
+        return -1;
+    }
+    jvalue args[1];
+    args[0].i = value;
+    jint twice = CallIntMethodA(env, global, GetMethodID(env, GetObjectClass(env, jni), "twice", "(I)I"), args);
+    DeleteGlobalRef(env, global);
+    jclass found = FindClass(env, "Jni");
+    jint thrice = CallStaticIntMethodA(env, found, GetStaticMethodID(env, found, "thrice", "(I)I"), args);
+    return twice + thrice;
+}
+
+void Java_Jni_fail(JNIEnv *env, jclass class, jstring message) {
+    const char *chars = GetStringUTFChars(env, message, NULL);
+    ThrowNew(env, FindClass(env, "java/lang/IllegalArgumentException"), chars);
+    ReleaseStringUTFChars(env, message, chars);
+    if (!ExceptionCheck(env)) {
+        abort();
+    }
+}
+
+jint Java_Jni_overloaded__I(JNIEnv *env, jclass class, jint value) {
+    return 1;
+}
+
+jint Java_Jni_overloaded__J(JNIEnv *env, jclass class, jlong value) {
+    return 2;
+}